@@ -0,0 +1,77 @@
+//! Benchmarks for the ingestion path and the dashboard's heaviest query, over data from
+//! `agenttop::bench`. Run with `cargo bench`; pass a name to run only matching ones
+//! (`cargo bench -- parse`).
+
+use std::hint::black_box;
+use std::sync::OnceLock;
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+
+use agenttop::bench::synthetic_payload;
+use agenttop::otlp::parse_logs;
+use agenttop::storage::StorageHandle;
+
+/// Store `events` synthetic events, returning once they are queryable
+fn fill(storage: &StorageHandle, events: usize) {
+    const BATCH: usize = 1000;
+    for first in (0..events).step_by(BATCH) {
+        let batch = parse_logs(&synthetic_payload(first, BATCH.min(events - first))).unwrap();
+        storage.record_log_events(batch);
+    }
+    // Queries wait behind the writes queued before them
+    storage.is_empty().unwrap();
+}
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse_logs");
+    for count in [100, 1000] {
+        let payload = synthetic_payload(0, count);
+        group.bench_with_input(
+            BenchmarkId::from_parameter(count),
+            &payload,
+            |b, payload| {
+                b.iter(|| parse_logs(black_box(payload)).unwrap());
+            },
+        );
+    }
+    group.finish();
+}
+
+fn insert(c: &mut Criterion) {
+    let mut group = c.benchmark_group("insert_batch");
+    for count in [1, 100, 1000] {
+        let storage = StorageHandle::new_in_memory().unwrap();
+        let payload = synthetic_payload(0, count);
+        group.bench_with_input(
+            BenchmarkId::from_parameter(count),
+            &payload,
+            |b, payload| {
+                b.iter(|| {
+                    storage.record_log_events(parse_logs(payload).unwrap());
+                    storage.is_empty().unwrap();
+                });
+            },
+        );
+    }
+    group.finish();
+}
+
+fn query(c: &mut Criterion) {
+    let mut group = c.benchmark_group("get_tool_metrics");
+    for rows in [10_000, 100_000] {
+        // Filled only if the benchmark isn't filtered out, which takes a while
+        let storage = OnceLock::new();
+        group.bench_function(BenchmarkId::from_parameter(rows), |b| {
+            let storage = storage.get_or_init(|| {
+                let storage = StorageHandle::new_in_memory().unwrap();
+                fill(&storage, rows);
+                storage
+            });
+            b.iter(|| storage.get_tool_metrics(None, None).unwrap());
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parse, insert, query);
+criterion_main!(benches);
@@ -0,0 +1,77 @@
+//! Slack and Discord message payloads for alert webhooks.
+
+use serde_json::{Value, json};
+
+use super::Firing;
+
+/// Discord embed color (red)
+const DISCORD_COLOR: u32 = 0xE0_1E_5A;
+
+fn headline(firing: &Firing) -> String {
+    format!("🚨 {}", firing.rule)
+}
+
+fn footer(firing: &Firing) -> String {
+    format!(
+        "agenttop · {} · {}",
+        firing.kind,
+        firing.fired_at.format("%Y-%m-%d %H:%M UTC")
+    )
+}
+
+/// Slack incoming-webhook message with Block Kit blocks
+pub fn slack_payload(firing: &Firing) -> Value {
+    let mut blocks = vec![
+        json!({
+            "type": "header",
+            "text": { "type": "plain_text", "text": headline(firing) }
+        }),
+        json!({
+            "type": "section",
+            "text": { "type": "mrkdwn", "text": firing.message }
+        }),
+    ];
+    if !firing.context.is_empty() {
+        let fields: Vec<Value> = firing
+            .context
+            .iter()
+            .map(|field| {
+                json!({
+                    "type": "mrkdwn",
+                    "text": format!("*{}*\n{}", field.label, field.value)
+                })
+            })
+            .collect();
+        blocks.push(json!({ "type": "section", "fields": fields }));
+    }
+    blocks.push(json!({
+        "type": "context",
+        "elements": [{ "type": "mrkdwn", "text": footer(firing) }]
+    }));
+
+    json!({
+        // Fallback for notifications and clients without blocks
+        "text": format!("{}: {}", headline(firing), firing.message),
+        "blocks": blocks
+    })
+}
+
+/// Discord webhook message with one embed
+pub fn discord_payload(firing: &Firing) -> Value {
+    let fields: Vec<Value> = firing
+        .context
+        .iter()
+        .map(|field| json!({ "name": field.label, "value": field.value, "inline": true }))
+        .collect();
+    json!({
+        "username": "agenttop",
+        "embeds": [{
+            "title": headline(firing),
+            "description": firing.message,
+            "color": DISCORD_COLOR,
+            "fields": fields,
+            "footer": { "text": footer(firing) },
+            "timestamp": firing.fired_at.to_rfc3339()
+        }]
+    })
+}
@@ -0,0 +1,626 @@
+//! Alert rules from the config file, evaluated on a refresh loop in the background.
+//!
+//! Threshold rules (daily cost, error rate, budget) fire when they cross their threshold and
+//! again only after recovering; occurrence rules (tool rejected, rate limited, anomaly)
+//! fire for every evaluation that sees new occurrences. Each firing posts a JSON payload to the
+//! rule's webhook: a Slack or Discord message, or generic JSON built from an optional
+//! template.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, Utc};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+use crate::anomaly::AnomalyKind;
+use crate::storage::{StorageHandle, get_tool_display_name};
+use crate::tui::app::local_day_start;
+
+mod chat;
+
+/// How often rules are evaluated
+const EVALUATION_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A named condition and where to send it when it fires
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AlertRule {
+    pub name: String,
+    pub condition: Condition,
+    pub webhook: Webhook,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Condition {
+    /// Cost since local midnight above a dollar amount
+    DailyCost { above_usd: f64 },
+    /// Tool error rate over the look-back window above a percentage
+    ErrorRate {
+        above_pct: f64,
+        /// Only this tool (all tools combined when omitted)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tool: Option<String>,
+        #[serde(default = "default_window_mins")]
+        window_mins: i64,
+        /// Minimum calls in the window before the rate is considered
+        #[serde(default = "default_min_calls")]
+        min_calls: u64,
+    },
+    /// A tool call was rejected by the user
+    ToolRejected {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tool: Option<String>,
+    },
+    /// A budget's current period spend above a percentage of its limit, as last
+    /// recorded by the budget monitor
+    Budget {
+        #[serde(default = "default_budget_pct")]
+        above_pct: f64,
+        /// Only this budget, by key ("monthly/global"); the one closest to its limit
+        /// when omitted
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        budget: Option<String>,
+    },
+    /// An API request failed because of rate limiting
+    RateLimited,
+    /// The anomaly detector flagged an error-rate spike or latency regression
+    Anomaly {
+        /// Only this kind (both when omitted)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        kind: Option<AnomalyKind>,
+    },
+}
+
+fn default_window_mins() -> i64 {
+    10
+}
+
+fn default_min_calls() -> u64 {
+    4
+}
+
+fn default_budget_pct() -> f64 {
+    100.0
+}
+
+/// JSON webhook target
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Webhook {
+    pub url: String,
+    #[serde(default)]
+    pub format: WebhookFormat,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub headers: HashMap<String, String>,
+    /// Payload with `{{rule}}`, `{{kind}}`, `{{message}}`, `{{value}}`, `{{threshold}}` and
+    /// `{{fired_at}}` placeholders; the default payload is the firing itself as JSON
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+}
+
+/// Payload shape expected by the webhook receiver
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookFormat {
+    /// The firing as JSON, or the template
+    #[default]
+    Json,
+    /// Slack incoming webhook (Block Kit message)
+    Slack,
+    /// Discord webhook (embed)
+    Discord,
+}
+
+impl Condition {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Condition::DailyCost { .. } => "daily_cost",
+            Condition::ErrorRate { .. } => "error_rate",
+            Condition::Budget { .. } => "budget",
+            Condition::ToolRejected { .. } => "tool_rejected",
+            Condition::RateLimited => "rate_limited",
+            Condition::Anomaly { .. } => "anomaly",
+        }
+    }
+
+    fn threshold(&self) -> Option<f64> {
+        match self {
+            Condition::DailyCost { above_usd } => Some(*above_usd),
+            Condition::ErrorRate { above_pct, .. } | Condition::Budget { above_pct, .. } => {
+                Some(*above_pct)
+            }
+            Condition::ToolRejected { .. } | Condition::RateLimited | Condition::Anomaly { .. } => {
+                None
+            }
+        }
+    }
+}
+
+/// Firings as they happen, for notifiers other than the rule's webhook (MQTT)
+pub static ALERT_FEED: Lazy<broadcast::Sender<Firing>> = Lazy::new(|| broadcast::channel(64).0);
+
+/// A rule that fired, and the default webhook payload
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Firing {
+    pub rule: String,
+    pub kind: &'static str,
+    pub message: String,
+    pub value: f64,
+    pub threshold: Option<f64>,
+    pub fired_at: DateTime<Utc>,
+    /// Recent activity at the time the rule fired
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub context: Vec<ContextField>,
+}
+
+/// One labelled value of recent activity attached to a firing
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ContextField {
+    pub label: String,
+    pub value: String,
+}
+
+/// What a condition currently measures
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Observation {
+    /// A level compared against the threshold
+    Level(f64),
+    /// A running total of occurrences; any increase fires
+    Count(u64),
+}
+
+/// Measure a condition against storage
+pub fn observe(
+    storage: &StorageHandle,
+    condition: &Condition,
+    now: DateTime<Utc>,
+) -> Result<Observation> {
+    Ok(match condition {
+        Condition::DailyCost { .. } => Observation::Level(
+            storage.get_total_cost(local_day_start(now.with_timezone(&Local)), None)?,
+        ),
+        Condition::ErrorRate {
+            tool,
+            window_mins,
+            min_calls,
+            ..
+        } => {
+            let since = now - chrono::Duration::minutes(*window_mins);
+            let (calls, errors) = storage
+                .get_tool_metrics(Some(since), None)?
+                .iter()
+                .filter(|t| tool.as_ref().is_none_or(|name| &t.tool_name == name))
+                .fold((0, 0), |(calls, errors), t| {
+                    (calls + t.call_count, errors + t.error_count)
+                });
+            let rate = if calls >= (*min_calls).max(1) {
+                errors as f64 / calls as f64 * 100.0
+            } else {
+                0.0
+            };
+            Observation::Level(rate)
+        }
+        Condition::Budget { budget, .. } => Observation::Level(
+            storage
+                .get_budget_usage(budget.as_deref(), true, usize::MAX)?
+                .iter()
+                .filter(|usage| usage.limit_usd > 0.0)
+                .map(|usage| usage.spent_usd / usage.limit_usd * 100.0)
+                .fold(0.0, f64::max),
+        ),
+        Condition::ToolRejected { tool } => Observation::Count(
+            storage
+                .get_tool_metrics(None, None)?
+                .iter()
+                .filter(|t| tool.as_ref().is_none_or(|name| &t.tool_name == name))
+                .map(|t| t.rejected_count)
+                .sum(),
+        ),
+        Condition::RateLimited => Observation::Count(storage.count_rate_limit_errors(None, None)?),
+        Condition::Anomaly { kind } => Observation::Count(storage.count_anomalies(*kind)?),
+    })
+}
+
+/// Rule state carried between evaluations
+#[derive(Debug, Clone, Default)]
+struct RuleState {
+    firing: bool,
+    last_count: Option<u64>,
+}
+
+/// Tracks each rule's state so rules fire on transitions rather than on every refresh
+#[derive(Debug, Clone)]
+pub struct Evaluator {
+    rules: Vec<AlertRule>,
+    states: Vec<RuleState>,
+}
+
+impl Evaluator {
+    pub fn new(rules: Vec<AlertRule>) -> Self {
+        let states = vec![RuleState::default(); rules.len()];
+        Self { rules, states }
+    }
+
+    pub fn rules(&self) -> &[AlertRule] {
+        &self.rules
+    }
+
+    /// Feed one observation per rule (same order as the rules); returns the rules
+    /// that fire, by index. Counts seen for the first time only set the baseline.
+    pub fn update(
+        &mut self,
+        observations: &[Observation],
+        now: DateTime<Utc>,
+    ) -> Vec<(usize, Firing)> {
+        let mut fired = Vec::new();
+        for (index, (rule, observation)) in self.rules.iter().zip(observations).enumerate() {
+            let state = &mut self.states[index];
+            let firing = match (*observation, rule.condition.threshold()) {
+                (Observation::Level(value), Some(threshold)) => {
+                    let over = value > threshold;
+                    let starts = over && !state.firing;
+                    state.firing = over;
+                    starts.then(|| (value, level_message(&rule.condition, value, threshold)))
+                }
+                (Observation::Count(count), _) => {
+                    let new = state
+                        .last_count
+                        .map_or(0, |last| count.saturating_sub(last));
+                    state.last_count = Some(count);
+                    (new > 0).then(|| (new as f64, count_message(&rule.condition, new)))
+                }
+                (Observation::Level(_), None) => None,
+            };
+            if let Some((value, message)) = firing {
+                fired.push((
+                    index,
+                    Firing {
+                        rule: rule.name.clone(),
+                        kind: rule.condition.kind(),
+                        message,
+                        value,
+                        threshold: rule.condition.threshold(),
+                        fired_at: now,
+                        context: Vec::new(),
+                    },
+                ));
+            }
+        }
+        fired
+    }
+}
+
+fn level_message(condition: &Condition, value: f64, threshold: f64) -> String {
+    match condition {
+        Condition::DailyCost { .. } => {
+            format!("Spend today is ${:.2} (limit ${:.2})", value, threshold)
+        }
+        Condition::ErrorRate {
+            tool, window_mins, ..
+        } => format!(
+            "{} error rate is {:.0}% over the last {} min (limit {:.0}%)",
+            tool.as_deref().unwrap_or("Tool"),
+            value,
+            window_mins,
+            threshold
+        ),
+        Condition::Budget { budget, .. } => format!(
+            "{} is at {:.0}% of its limit (alert at {:.0}%)",
+            budget
+                .as_deref()
+                .map_or("A budget".to_string(), |key| format!("Budget {}", key)),
+            value,
+            threshold
+        ),
+        _ => String::new(),
+    }
+}
+
+fn count_message(condition: &Condition, new: u64) -> String {
+    match condition {
+        Condition::ToolRejected { tool: Some(tool) } => {
+            format!("{} call(s) to {} rejected", new, tool)
+        }
+        Condition::ToolRejected { tool: None } => format!("{} tool call(s) rejected", new),
+        Condition::RateLimited => format!("{} API request(s) rate limited", new),
+        Condition::Anomaly {
+            kind: Some(AnomalyKind::ErrorRate),
+        } => format!("{} error-rate spike(s) detected", new),
+        Condition::Anomaly {
+            kind: Some(AnomalyKind::Latency),
+        } => format!("{} latency regression(s) detected", new),
+        Condition::Anomaly { kind: None } => format!("{} anomaly(ies) detected", new),
+        _ => String::new(),
+    }
+}
+
+/// Spend today plus tool and API activity over the last hour
+pub fn recent_context(storage: &StorageHandle, now: DateTime<Utc>) -> Result<Vec<ContextField>> {
+    let hour_ago = Some(now - chrono::Duration::hours(1));
+    let spend = storage.get_total_cost(local_day_start(now.with_timezone(&Local)), None)?;
+    let tools = storage.get_tool_metrics(hour_ago, None)?;
+    let api = storage.get_api_metrics(hour_ago, None)?;
+    let calls: u64 = tools.iter().map(|t| t.call_count).sum();
+    let errors: u64 = tools.iter().map(|t| t.error_count).sum();
+
+    let field = |label: &str, value: String| ContextField {
+        label: label.to_string(),
+        value,
+    };
+    let mut context = vec![
+        field("Spend today", format!("${:.2}", spend)),
+        field("Tool calls (1h)", format!("{} ({} failed)", calls, errors)),
+        field("API errors (1h)", api.total_errors.to_string()),
+    ];
+    // Tools are ordered by call count
+    if let Some(top) = tools.first() {
+        context.push(field(
+            "Top tool (1h)",
+            format!(
+                "{} ({} calls)",
+                get_tool_display_name(&top.tool_name),
+                top.call_count
+            ),
+        ));
+    }
+    Ok(context)
+}
+
+/// Escape text for use inside a JSON string literal (without the surrounding quotes)
+fn json_escape(text: &str) -> String {
+    let quoted = serde_json::to_string(text).unwrap_or_default();
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+/// The webhook body: the template with placeholders filled in, a Slack or Discord
+/// message, or the firing as JSON. Text placeholders are JSON-escaped, so put them
+/// inside quotes in the template.
+pub fn render_payload(webhook: &Webhook, firing: &Firing) -> String {
+    let Some(template) = &webhook.template else {
+        let payload = match webhook.format {
+            WebhookFormat::Json => serde_json::to_value(firing).unwrap_or_default(),
+            WebhookFormat::Slack => chat::slack_payload(firing),
+            WebhookFormat::Discord => chat::discord_payload(firing),
+        };
+        return payload.to_string();
+    };
+    template
+        .replace("{{rule}}", &json_escape(&firing.rule))
+        .replace("{{kind}}", firing.kind)
+        .replace("{{message}}", &json_escape(&firing.message))
+        .replace("{{value}}", &firing.value.to_string())
+        .replace(
+            "{{threshold}}",
+            &firing
+                .threshold
+                .map_or("null".to_string(), |t| t.to_string()),
+        )
+        .replace("{{fired_at}}", &firing.fired_at.to_rfc3339())
+}
+
+/// POST the payload to the webhook
+pub fn deliver(webhook: &Webhook, payload: &str) -> Result<()> {
+    let mut request = ureq::post(&webhook.url)
+        .timeout(Duration::from_secs(10))
+        .set("Content-Type", "application/json");
+    for (name, value) in &webhook.headers {
+        request = request.set(name, value);
+    }
+    request
+        .send_string(payload)
+        .with_context(|| format!("Webhook {} failed", webhook.url))?;
+    Ok(())
+}
+
+/// Evaluate the rules every few seconds on a background thread
+pub fn spawn(storage: StorageHandle, rules: Vec<AlertRule>) {
+    if rules.is_empty() {
+        return;
+    }
+    tracing::info!("Evaluating {} alert rule(s)", rules.len());
+    let mut evaluator = Evaluator::new(rules);
+    std::thread::spawn(move || {
+        loop {
+            let now = Utc::now();
+            let observations: Result<Vec<_>> = evaluator
+                .rules()
+                .iter()
+                .map(|rule| observe(&storage, &rule.condition, now))
+                .collect();
+            match observations {
+                Ok(observations) => {
+                    for (index, mut firing) in evaluator.update(&observations, now) {
+                        let webhook = &evaluator.rules()[index].webhook;
+                        tracing::info!("Alert {}: {}", firing.rule, firing.message);
+                        firing.context = recent_context(&storage, now).unwrap_or_default();
+                        if ALERT_FEED.receiver_count() > 0 {
+                            let _ = ALERT_FEED.send(firing.clone());
+                        }
+                        let payload = render_payload(webhook, &firing);
+                        if let Err(e) = deliver(webhook, &payload) {
+                            tracing::warn!("{:#}", e);
+                        }
+                    }
+                }
+                Err(e) => tracing::warn!("Could not evaluate alert rules: {:#}", e),
+            }
+            std::thread::sleep(EVALUATION_INTERVAL);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn webhook(format: WebhookFormat, template: Option<&str>) -> Webhook {
+        Webhook {
+            url: "http://localhost/hook".to_string(),
+            format,
+            headers: HashMap::new(),
+            template: template.map(str::to_string),
+        }
+    }
+
+    fn rule(name: &str, condition: Condition) -> AlertRule {
+        AlertRule {
+            name: name.to_string(),
+            condition,
+            webhook: webhook(WebhookFormat::Json, None),
+        }
+    }
+
+    #[test]
+    fn test_threshold_fires_once_until_recovered() {
+        let mut evaluator = Evaluator::new(vec![rule(
+            "spend",
+            Condition::DailyCost { above_usd: 10.0 },
+        )]);
+        let now = Utc::now();
+        assert!(evaluator.update(&[Observation::Level(5.0)], now).is_empty());
+
+        let fired = evaluator.update(&[Observation::Level(12.5)], now);
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].1.kind, "daily_cost");
+        assert_eq!(fired[0].1.value, 12.5);
+        assert_eq!(fired[0].1.message, "Spend today is $12.50 (limit $10.00)");
+
+        assert!(
+            evaluator
+                .update(&[Observation::Level(13.0)], now)
+                .is_empty()
+        );
+        assert!(evaluator.update(&[Observation::Level(1.0)], now).is_empty());
+        assert_eq!(evaluator.update(&[Observation::Level(11.0)], now).len(), 1);
+    }
+
+    #[test]
+    fn test_count_fires_on_new_occurrences() {
+        let mut evaluator = Evaluator::new(vec![rule("429", Condition::RateLimited)]);
+        let now = Utc::now();
+        // History before the first evaluation does not fire
+        assert!(evaluator.update(&[Observation::Count(7)], now).is_empty());
+        assert!(evaluator.update(&[Observation::Count(7)], now).is_empty());
+
+        let fired = evaluator.update(&[Observation::Count(9)], now);
+        assert_eq!(fired[0].1.value, 2.0);
+        assert_eq!(fired[0].1.message, "2 API request(s) rate limited");
+    }
+
+    #[test]
+    fn test_budget_condition() {
+        let condition: Condition = serde_json::from_str(r#"{"type": "budget"}"#).unwrap();
+        assert_eq!(
+            condition,
+            Condition::Budget {
+                above_pct: 100.0,
+                budget: None
+            }
+        );
+        let mut evaluator = Evaluator::new(vec![rule("over", condition)]);
+        let now = Utc::now();
+        assert!(
+            evaluator
+                .update(&[Observation::Level(80.0)], now)
+                .is_empty()
+        );
+        let fired = evaluator.update(&[Observation::Level(104.0)], now);
+        assert_eq!(fired[0].1.kind, "budget");
+        assert_eq!(
+            fired[0].1.message,
+            "A budget is at 104% of its limit (alert at 100%)"
+        );
+    }
+
+    #[test]
+    fn test_anomaly_condition() {
+        let condition: Condition =
+            serde_json::from_str(r#"{"type": "anomaly", "kind": "latency"}"#).unwrap();
+        assert_eq!(
+            condition,
+            Condition::Anomaly {
+                kind: Some(AnomalyKind::Latency)
+            }
+        );
+        let mut evaluator = Evaluator::new(vec![rule("slow", condition)]);
+        let now = Utc::now();
+        assert!(evaluator.update(&[Observation::Count(0)], now).is_empty());
+        let fired = evaluator.update(&[Observation::Count(1)], now);
+        assert_eq!(fired[0].1.kind, "anomaly");
+        assert_eq!(fired[0].1.message, "1 latency regression(s) detected");
+    }
+
+    #[test]
+    fn test_render_payload() {
+        let firing = Firing {
+            rule: "Bash \"flaky\"".to_string(),
+            kind: "error_rate",
+            message: "Bash error rate is 50%".to_string(),
+            value: 50.0,
+            threshold: Some(30.0),
+            fired_at: Utc::now(),
+            context: vec![ContextField {
+                label: "Spend today".to_string(),
+                value: "$4.20".to_string(),
+            }],
+        };
+        let template =
+            r#"{"text": "{{rule}}: {{message}}", "value": {{value}}, "limit": {{threshold}}}"#;
+        let payload = render_payload(&webhook(WebhookFormat::Slack, Some(template)), &firing);
+        let json: serde_json::Value = serde_json::from_str(&payload).unwrap();
+        assert_eq!(json["text"], "Bash \"flaky\": Bash error rate is 50%");
+        assert_eq!(json["value"], 50.0);
+        assert_eq!(json["limit"], 30.0);
+
+        let json: serde_json::Value = serde_json::from_str(&render_payload(
+            &webhook(WebhookFormat::Json, None),
+            &firing,
+        ))
+        .unwrap();
+        assert_eq!(json["rule"], "Bash \"flaky\"");
+        assert_eq!(json["kind"], "error_rate");
+        assert_eq!(json["context"][0]["value"], "$4.20");
+
+        let slack: serde_json::Value = serde_json::from_str(&render_payload(
+            &webhook(WebhookFormat::Slack, None),
+            &firing,
+        ))
+        .unwrap();
+        assert_eq!(slack["blocks"][0]["type"], "header");
+        assert_eq!(slack["blocks"][1]["text"]["text"], "Bash error rate is 50%");
+        assert_eq!(
+            slack["blocks"][2]["fields"][0]["text"],
+            "*Spend today*\n$4.20"
+        );
+
+        let discord: serde_json::Value = serde_json::from_str(&render_payload(
+            &webhook(WebhookFormat::Discord, None),
+            &firing,
+        ))
+        .unwrap();
+        assert_eq!(
+            discord["embeds"][0]["description"],
+            "Bash error rate is 50%"
+        );
+        assert_eq!(discord["embeds"][0]["fields"][0]["name"], "Spend today");
+    }
+
+    #[test]
+    fn test_rule_from_config() {
+        let rule: AlertRule = serde_json::from_str(
+            r#"{"name": "Bash", "condition": {"type": "error_rate", "above_pct": 30, "tool": "Bash"},
+                "webhook": {"url": "https://example.com"}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            rule.condition,
+            Condition::ErrorRate {
+                above_pct: 30.0,
+                tool: Some("Bash".to_string()),
+                window_mins: 10,
+                min_calls: 4,
+            }
+        );
+    }
+}
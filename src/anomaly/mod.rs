@@ -0,0 +1,344 @@
+//! Anomaly detection on tool and model error rates and latencies.
+//!
+//! Every [`DETECTION_INTERVAL`] the error rate and average latency of each tool and
+//! model over that interval are compared with an exponentially weighted moving average
+//! (and deviation) of the intervals before. A value far above its baseline is recorded
+//! as an [`Anomaly`] in storage, where the TUI alert banner and `anomaly` alert rules
+//! pick it up. A series is flagged once per excursion: it has to return to normal
+//! before it can be flagged again.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::storage::{ApiMetrics, StorageHandle, ToolMetrics, get_tool_display_name};
+
+/// Length of the windows compared with the baseline, and how often they are checked
+pub const DETECTION_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Weight of the newest window in the moving average
+const EWMA_ALPHA: f64 = 0.2;
+
+/// Standard deviations above the baseline at which a window is anomalous
+const SIGMAS: f64 = 3.0;
+
+/// Windows a series needs before it can be flagged
+const WARMUP_WINDOWS: u32 = 5;
+
+/// Calls a window needs to count; quieter windows neither flag nor move the baseline
+const MIN_CALLS: u64 = 5;
+
+/// Smallest rise over the baseline that is flagged, so that a flat baseline (with no
+/// deviation) doesn't turn every wobble into an anomaly: percentage points of error
+/// rate, and a multiple of the latency
+const MIN_ERROR_RATE_RISE_PCT: f64 = 20.0;
+const MIN_LATENCY_RATIO: f64 = 1.5;
+
+/// What went up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnomalyKind {
+    ErrorRate,
+    Latency,
+}
+
+impl AnomalyKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AnomalyKind::ErrorRate => "error_rate",
+            AnomalyKind::Latency => "latency",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "error_rate" => Some(AnomalyKind::ErrorRate),
+            "latency" => Some(AnomalyKind::Latency),
+            _ => None,
+        }
+    }
+}
+
+/// Whether the anomaly is about a tool or a model
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnomalyScope {
+    Tool,
+    Model,
+}
+
+impl AnomalyScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AnomalyScope::Tool => "tool",
+            AnomalyScope::Model => "model",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "tool" => Some(AnomalyScope::Tool),
+            "model" => Some(AnomalyScope::Model),
+            _ => None,
+        }
+    }
+}
+
+/// A window whose error rate or latency was far above its baseline
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Anomaly {
+    pub detected_at: DateTime<Utc>,
+    pub kind: AnomalyKind,
+    pub scope: AnomalyScope,
+    /// Tool or model name
+    pub subject: String,
+    /// Error rate (percent) or average latency (ms) in the window
+    pub value: f64,
+    /// Moving average of the windows before
+    pub baseline: f64,
+}
+
+impl Anomaly {
+    pub fn message(&self) -> String {
+        let subject = match self.scope {
+            AnomalyScope::Tool => get_tool_display_name(&self.subject),
+            AnomalyScope::Model => self.subject.clone(),
+        };
+        match self.kind {
+            AnomalyKind::ErrorRate => format!(
+                "{} error rate spiked to {:.0}% (usually {:.0}%)",
+                subject, self.value, self.baseline
+            ),
+            AnomalyKind::Latency => format!(
+                "{} latency rose to {} (usually {})",
+                subject,
+                crate::report::format_duration(self.value),
+                crate::report::format_duration(self.baseline)
+            ),
+        }
+    }
+}
+
+/// Moving average and deviation of one tool's or model's windows
+#[derive(Debug, Clone, Default)]
+struct Series {
+    mean: f64,
+    variance: f64,
+    windows: u32,
+    /// The last window was flagged (or still above the threshold)
+    anomalous: bool,
+}
+
+impl Series {
+    /// Add a window's value; returns the baseline it was compared with when it starts
+    /// an anomaly. Only values at least `min_rise` above and `min_ratio` times the
+    /// baseline count.
+    fn update(&mut self, value: f64, min_rise: f64, min_ratio: f64) -> Option<f64> {
+        let baseline = self.mean;
+        let over = self.windows >= WARMUP_WINDOWS
+            && value >= (baseline + min_rise).max(baseline * min_ratio)
+            && value > baseline + SIGMAS * self.variance.sqrt();
+        let starts = over && !self.anomalous;
+        self.anomalous = over;
+
+        if self.windows == 0 {
+            self.mean = value;
+        } else {
+            let diff = value - self.mean;
+            let step = EWMA_ALPHA * diff;
+            self.mean += step;
+            self.variance = (1.0 - EWMA_ALPHA) * (self.variance + diff * step);
+        }
+        self.windows += 1;
+        starts.then_some(baseline)
+    }
+}
+
+/// Baselines of every tool and model seen, fed one window at a time
+#[derive(Debug, Default)]
+pub struct Detector {
+    series: HashMap<(AnomalyKind, AnomalyScope, String), Series>,
+}
+
+impl Detector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed one window's metrics; returns the anomalies starting in it
+    pub fn evaluate(
+        &mut self,
+        tools: &[ToolMetrics],
+        api: &ApiMetrics,
+        now: DateTime<Utc>,
+    ) -> Vec<Anomaly> {
+        let mut windows = Vec::new();
+        for tool in tools {
+            windows.push((
+                AnomalyScope::Tool,
+                tool.tool_name.clone(),
+                tool.call_count,
+                tool.error_count,
+                tool.avg_duration_ms,
+            ));
+        }
+        for (model, calls) in &api.models {
+            let errors = api.errors_by_model.get(model).copied().unwrap_or(0);
+            let latency = api.latency_by_model.get(model).copied().unwrap_or(0.0);
+            windows.push((
+                AnomalyScope::Model,
+                model.clone(),
+                calls + errors,
+                errors,
+                latency,
+            ));
+        }
+
+        let mut anomalies = Vec::new();
+        for (scope, subject, calls, errors, latency_ms) in windows {
+            if calls < MIN_CALLS {
+                continue;
+            }
+            let error_rate = errors as f64 / calls as f64 * 100.0;
+            let mut check = |kind, value: f64, min_rise: f64, min_ratio: f64| {
+                let series = self
+                    .series
+                    .entry((kind, scope, subject.clone()))
+                    .or_default();
+                if let Some(baseline) = series.update(value, min_rise, min_ratio) {
+                    anomalies.push(Anomaly {
+                        detected_at: now,
+                        kind,
+                        scope,
+                        subject: subject.clone(),
+                        value,
+                        baseline,
+                    });
+                }
+            };
+            check(
+                AnomalyKind::ErrorRate,
+                error_rate,
+                MIN_ERROR_RATE_RISE_PCT,
+                1.0,
+            );
+            if latency_ms > 0.0 {
+                check(AnomalyKind::Latency, latency_ms, 0.0, MIN_LATENCY_RATIO);
+            }
+        }
+        anomalies
+    }
+}
+
+/// Check the last window every [`DETECTION_INTERVAL`] on a background thread and
+/// record the anomalies found
+pub fn spawn(storage: StorageHandle) {
+    std::thread::spawn(move || {
+        let mut detector = Detector::new();
+        loop {
+            std::thread::sleep(DETECTION_INTERVAL);
+            let now = Utc::now();
+            let since = Some(now - chrono::Duration::from_std(DETECTION_INTERVAL).unwrap());
+            let metrics = storage
+                .get_tool_metrics(since, Some(now))
+                .and_then(|tools| Ok((tools, storage.get_api_metrics(since, Some(now))?)));
+            match metrics {
+                Ok((tools, api)) => {
+                    for anomaly in detector.evaluate(&tools, &api, now) {
+                        tracing::warn!("Anomaly: {}", anomaly.message());
+                        storage.record_anomaly(anomaly);
+                    }
+                }
+                Err(e) => tracing::warn!("Could not check for anomalies: {:#}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tool(calls: u64, errors: u64, avg_duration_ms: f64) -> ToolMetrics {
+        ToolMetrics {
+            tool_name: "Bash".to_string(),
+            call_count: calls,
+            last_call: None,
+            avg_duration_ms,
+            min_duration_ms: avg_duration_ms,
+            max_duration_ms: avg_duration_ms,
+            success_count: calls - errors,
+            error_count: errors,
+            approved_count: 0,
+            rejected_count: 0,
+        }
+    }
+
+    #[test]
+    fn test_error_spike_flagged_once() {
+        let mut detector = Detector::new();
+        let api = ApiMetrics::default();
+        let now = Utc::now();
+        for errors in [0, 1, 0, 1, 0, 1] {
+            assert!(
+                detector
+                    .evaluate(&[tool(10, errors, 100.0)], &api, now)
+                    .is_empty()
+            );
+        }
+
+        let anomalies = detector.evaluate(&[tool(10, 8, 100.0)], &api, now);
+        assert_eq!(anomalies.len(), 1);
+        assert_eq!(anomalies[0].kind, AnomalyKind::ErrorRate);
+        assert_eq!(anomalies[0].value, 80.0);
+        assert!(anomalies[0].baseline < 10.0);
+        assert!(
+            anomalies[0]
+                .message()
+                .starts_with("Bash error rate spiked to 80%")
+        );
+
+        // Still failing: not flagged again until it recovers
+        assert!(
+            detector
+                .evaluate(&[tool(10, 9, 100.0)], &api, now)
+                .is_empty()
+        );
+        // Too few calls to count
+        assert!(
+            detector
+                .evaluate(&[tool(2, 2, 100.0)], &api, now)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_model_latency_regression() {
+        let mut detector = Detector::new();
+        let now = Utc::now();
+        let api = |latency: f64| {
+            let mut api = ApiMetrics::default();
+            api.models.insert("claude-sonnet-4-5".to_string(), 20);
+            api.latency_by_model
+                .insert("claude-sonnet-4-5".to_string(), latency);
+            api
+        };
+        // Small wobbles stay within the baseline even before warmup ends
+        for latency in [1000.0, 1100.0, 950.0, 1050.0, 1000.0, 1300.0] {
+            assert!(detector.evaluate(&[], &api(latency), now).is_empty());
+        }
+
+        let anomalies = detector.evaluate(&[], &api(4000.0), now);
+        assert_eq!(anomalies.len(), 1);
+        assert_eq!(anomalies[0].kind, AnomalyKind::Latency);
+        assert_eq!(anomalies[0].scope, AnomalyScope::Model);
+        assert_eq!(
+            anomalies[0].message(),
+            format!(
+                "claude-sonnet-4-5 latency rose to 4.0s (usually {})",
+                crate::report::format_duration(anomalies[0].baseline)
+            )
+        );
+    }
+}
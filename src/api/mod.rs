@@ -0,0 +1,318 @@
+//! REST API served under `/api` on the receiver port (enabled with `--api`), plus the
+//! `/ws` WebSocket stream of live updates and a browser dashboard at `/` built on both.
+//!
+//! Every endpoint takes the TUI's time range syntax as `?range=` ("24h", "since 09:00",
+//! "2025-01-10..2025-01-12"; default all-time) and answers with JSON.
+
+use crate::error::Result;
+use axum::{
+    Json, Router,
+    extract::{Query, State},
+    http::{HeaderMap, HeaderValue, Method, StatusCode, header},
+    response::{Html, IntoResponse, Response},
+    routing::{get, post},
+};
+use chrono::{DateTime, Local, Utc};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+use crate::cache::{self, LruCache};
+use crate::export::ToolExportRow;
+use crate::forecast::{self, Forecast};
+use crate::storage::remote::RemoteQuery;
+use crate::storage::{DataVersion, LogRecord, SessionMetrics, StorageHandle, TokenMetrics};
+use crate::tui::app::TimeFilter;
+
+mod ws;
+
+/// Events returned by /api/events unless `?limit=` asks for another number
+const DEFAULT_EVENT_LIMIT: usize = 100;
+
+/// Most events a single /api/events request can return
+const MAX_EVENT_LIMIT: usize = 1000;
+
+/// Query parameters shared by all endpoints
+#[derive(Debug, Default, Deserialize)]
+pub struct WindowParams {
+    pub range: Option<String>,
+    pub limit: Option<usize>,
+}
+
+/// Response body: the resolved time window and the endpoint's data
+#[derive(Debug, Serialize)]
+pub struct Windowed<T> {
+    pub range: String,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub data: T,
+}
+
+/// Answers to `/api/query` by query and the data version they were computed at, so
+/// remote dashboards asking the same thing of unchanged data share one answer
+static QUERY_RESULTS: Lazy<Mutex<LruCache<(String, DataVersion), serde_json::Value>>> =
+    Lazy::new(|| {
+        Mutex::new(LruCache::new(
+            "query_results",
+            cache::capacities().query_results,
+        ))
+    });
+
+/// Single-page dashboard, bundled into the binary
+const DASHBOARD_HTML: &str = include_str!("dashboard.html");
+
+/// Who may use the API besides pages served by agenttop itself and local programs
+#[derive(Debug, Clone, Default)]
+pub struct Access {
+    /// Browser origins (e.g. "https://grafana.example.com") allowed to read `/api` and
+    /// open `/ws`
+    pub allowed_origins: Vec<String>,
+    /// Bearer token `/api/query` requires (None: none)
+    pub token: Option<String>,
+    /// Bearer token raw SQL on `/api/query` requires even when `token` is None (None:
+    /// no raw SQL); agenttop generates one per run and leaves it in the lock file
+    pub sql_token: Option<String>,
+}
+
+/// Routes for the receiver's router
+pub fn router(access: Access) -> Router<StorageHandle> {
+    let required = Tokens {
+        query: access.token.map(Arc::from),
+        sql: access.sql_token.map(Arc::from),
+    };
+    let ws_origins: Arc<[String]> = access.allowed_origins.clone().into();
+    let mut api = Router::new()
+        .route("/api/tools", get(tools))
+        .route("/api/tokens", get(tokens))
+        .route("/api/sessions", get(sessions))
+        .route("/api/events", get(events))
+        .route("/api/forecast", get(forecast))
+        .route(
+            "/api/query",
+            post(move |state, headers, body| query(state, headers, body, required.clone())),
+        );
+    if !access.allowed_origins.is_empty() {
+        api = api.layer(cors(&access.allowed_origins));
+    }
+    api.route("/", get(|| async { Html(DASHBOARD_HTML) }))
+        .route(
+            "/ws",
+            get(move |ws, headers, state, query| {
+                ws::handler(ws, headers, state, query, ws_origins.clone())
+            }),
+        )
+}
+
+/// Only `/api/query`, behind `token`, for the receiver without `--api`: other agenttop
+/// processes on the machine read the database through it while it is recording
+pub fn query_router(token: String) -> Router<StorageHandle> {
+    let token: Arc<str> = Arc::from(token);
+    let required = Tokens {
+        query: Some(token.clone()),
+        sql: Some(token),
+    };
+    Router::new().route(
+        "/api/query",
+        post(move |state, headers, body| query(state, headers, body, required.clone())),
+    )
+}
+
+/// The tokens `/api/query` asks for (see [`Access`])
+#[derive(Clone)]
+struct Tokens {
+    query: Option<Arc<str>>,
+    sql: Option<Arc<str>>,
+}
+
+/// A random token for [`query_router`]
+pub fn generate_token() -> anyhow::Result<String> {
+    let mut bytes = [0u8; 16];
+    getrandom::fill(&mut bytes).map_err(|e| anyhow::anyhow!("No randomness for a token: {}", e))?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Cross-origin access to the API for the configured origins only
+fn cors(origins: &[String]) -> CorsLayer {
+    let origins = origins.iter().filter_map(|origin| {
+        HeaderValue::from_str(origin.trim_end_matches('/'))
+            .inspect_err(|_| tracing::warn!("Ignoring invalid allowed origin {:?}", origin))
+            .ok()
+    });
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::list(origins))
+        .allow_methods([Method::GET, Method::POST])
+        .allow_headers([header::CONTENT_TYPE, header::AUTHORIZATION])
+}
+
+/// Whether the request carries `Authorization: Bearer <token>` (always, without a token)
+fn authorized(headers: &HeaderMap, token: Option<&str>) -> bool {
+    let Some(token) = token else {
+        return true;
+    };
+    let given = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    // Compared in full whatever the first difference, so timing doesn't reveal the token
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Error answered as `{"error": "..."}`
+struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(serde_json::json!({ "error": self.1 }))).into_response()
+    }
+}
+
+impl WindowParams {
+    /// The requested time window (all-time when no range is given)
+    fn time_filter(&self) -> Result<TimeFilter, ApiError> {
+        match &self.range {
+            Some(range) => TimeFilter::parse(range, Local::now())
+                .map_err(|e| ApiError(StatusCode::BAD_REQUEST, e.to_string())),
+            None => Ok(TimeFilter::AllTime),
+        }
+    }
+}
+
+/// Run a storage query for the requested window off the async runtime
+async fn windowed<T, F>(
+    storage: StorageHandle,
+    params: WindowParams,
+    query: F,
+) -> Result<Json<Windowed<T>>, ApiError>
+where
+    T: Serialize + Send + 'static,
+    F: FnOnce(&StorageHandle, Option<DateTime<Utc>>, Option<DateTime<Utc>>) -> Result<T>
+        + Send
+        + 'static,
+{
+    let filter = params.time_filter()?;
+    let (since, until) = (filter.since(), filter.until());
+    let internal = |e: String| ApiError(StatusCode::INTERNAL_SERVER_ERROR, e);
+    let data = tokio::task::spawn_blocking(move || query(&storage, since, until))
+        .await
+        .map_err(|e| internal(e.to_string()))?
+        .map_err(|e| internal(format!("{:#}", e)))?;
+    Ok(Json(Windowed {
+        range: filter.label(),
+        since,
+        until,
+        data,
+    }))
+}
+
+/// Per-tool metrics, most called first
+async fn tools(
+    State(storage): State<StorageHandle>,
+    Query(params): Query<WindowParams>,
+) -> Result<Json<Windowed<Vec<ToolExportRow>>>, ApiError> {
+    windowed(storage, params, |storage, since, until| {
+        Ok(storage
+            .get_tool_metrics(since, until)?
+            .iter()
+            .map(ToolExportRow::from)
+            .collect())
+    })
+    .await
+}
+
+/// Token counts by type and total cost
+async fn tokens(
+    State(storage): State<StorageHandle>,
+    Query(params): Query<WindowParams>,
+) -> Result<Json<Windowed<TokenMetrics>>, ApiError> {
+    windowed(storage, params, |storage, since, until| {
+        storage.get_token_metrics(since, until)
+    })
+    .await
+}
+
+/// Lines of code, commits and active time
+async fn sessions(
+    State(storage): State<StorageHandle>,
+    Query(params): Query<WindowParams>,
+) -> Result<Json<Windowed<SessionMetrics>>, ApiError> {
+    windowed(storage, params, |storage, since, until| {
+        storage.get_session_metrics(since, until)
+    })
+    .await
+}
+
+/// The latest raw log events (`?limit=`, default 100), oldest first
+async fn events(
+    State(storage): State<StorageHandle>,
+    Query(params): Query<WindowParams>,
+) -> Result<Json<Windowed<Vec<LogRecord>>>, ApiError> {
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_EVENT_LIMIT)
+        .min(MAX_EVENT_LIMIT);
+    windowed(storage, params, move |storage, since, until| {
+        storage.get_log_events(since, until, limit)
+    })
+    .await
+}
+
+/// Projected spend for the current local month; takes no time range
+async fn forecast(State(storage): State<StorageHandle>) -> Result<Json<Forecast>, ApiError> {
+    let internal = |e: String| ApiError(StatusCode::INTERNAL_SERVER_ERROR, e);
+    let forecast = tokio::task::spawn_blocking(move || forecast::month_end(&storage, Local::now()))
+        .await
+        .map_err(|e| internal(e.to_string()))?
+        .map_err(|e| internal(format!("{:#}", e)))?;
+    Ok(Json(forecast))
+}
+
+/// One of the dashboard's storage queries, for `agenttop view --remote`; answers `{"data": ...}`
+async fn query(
+    State(storage): State<StorageHandle>,
+    headers: HeaderMap,
+    Json(query): Json<RemoteQuery>,
+    required: Tokens,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let allowed = match &query {
+        RemoteQuery::Sql { .. } => required
+            .sql
+            .is_some_and(|token| authorized(&headers, Some(&token))),
+        _ => authorized(&headers, required.query.as_deref()),
+    };
+    if !allowed {
+        return Err(ApiError(
+            StatusCode::UNAUTHORIZED,
+            "missing or wrong API token".to_string(),
+        ));
+    }
+    let internal = |e: String| ApiError(StatusCode::INTERNAL_SERVER_ERROR, e);
+    let data = tokio::task::spawn_blocking(move || execute_cached(&storage, query))
+        .await
+        .map_err(|e| internal(e.to_string()))?
+        .map_err(|e| internal(format!("{:#}", e)))?;
+    Ok(Json(serde_json::json!({ "data": data })))
+}
+
+/// Run `query`, or answer from [`QUERY_RESULTS`] if the data hasn't changed since it last ran
+fn execute_cached(
+    storage: &StorageHandle,
+    query: RemoteQuery,
+) -> anyhow::Result<serde_json::Value> {
+    if query == RemoteQuery::DataVersion || cache::capacities().query_results == 0 {
+        return query.execute(storage);
+    }
+    let key = (serde_json::to_string(&query)?, storage.get_data_version()?);
+    if let Some(data) = QUERY_RESULTS.lock().unwrap().get(&key) {
+        return Ok(data.clone());
+    }
+    let data = query.execute(storage)?;
+    QUERY_RESULTS.lock().unwrap().put(key, data.clone());
+    Ok(data)
+}
@@ -0,0 +1,176 @@
+//! `/ws`: WebSocket stream of new events and refreshed aggregates.
+//!
+//! Each message is a JSON object tagged by `type`:
+//! - `event`: a log event as it is received
+//! - `lagged`: the client fell behind and `skipped` events were dropped
+//! - `aggregates`: tools, tokens and session totals for the requested `?range=`,
+//!   sent on connect and again whenever they change
+
+use axum::{
+    extract::{
+        Query, State,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    http::{HeaderMap, StatusCode, header},
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+
+use super::{ApiError, WindowParams};
+use crate::export::ToolExportRow;
+use crate::otlp::EVENT_FEED;
+use crate::storage::{LogEvent, SessionMetrics, StorageHandle, TokenMetrics};
+use crate::tui::app::TimeFilter;
+
+/// How often aggregates are recomputed (and pushed if they changed)
+const AGGREGATE_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Update {
+    Event { event: LogEvent },
+    Lagged { skipped: u64 },
+    Aggregates(Aggregates),
+}
+
+#[derive(Debug, Serialize)]
+struct Aggregates {
+    range: String,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+    #[serde(flatten)]
+    data: AggregateData,
+}
+
+#[derive(Debug, Serialize)]
+struct AggregateData {
+    tools: Vec<ToolExportRow>,
+    tokens: TokenMetrics,
+    sessions: SessionMetrics,
+}
+
+/// Hosts whose pages may open the socket without being configured
+const LOCAL_HOSTS: &[&str] = &["localhost", "127.0.0.1", "[::1]"];
+
+pub(super) async fn handler(
+    ws: WebSocketUpgrade,
+    headers: HeaderMap,
+    State(storage): State<StorageHandle>,
+    Query(params): Query<WindowParams>,
+    allowed_origins: Arc<[String]>,
+) -> Result<Response, ApiError> {
+    let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+    if !origin_allowed(
+        header(header::ORIGIN),
+        header(header::HOST),
+        &allowed_origins,
+    ) {
+        return Err(ApiError(
+            StatusCode::FORBIDDEN,
+            "origin not allowed (see allowed_origins in the config file)".to_string(),
+        ));
+    }
+    let filter = params.time_filter()?;
+    Ok(ws.on_upgrade(move |socket| stream_updates(socket, storage, filter)))
+}
+
+/// Browsers send the page's origin with the upgrade: only agenttop's own dashboard,
+/// pages served from this machine and the configured origins get through. Other
+/// clients send no Origin and are let in.
+fn origin_allowed(origin: Option<&str>, host: Option<&str>, allowed: &[String]) -> bool {
+    let Some(origin) = origin else {
+        return true;
+    };
+    let origin = origin.trim_end_matches('/');
+    if allowed
+        .iter()
+        .any(|allowed| allowed.trim_end_matches('/').eq_ignore_ascii_case(origin))
+    {
+        return true;
+    }
+    let Some((_, authority)) = origin.split_once("://") else {
+        return false;
+    };
+    let hostname = match authority.rfind(':') {
+        Some(colon) if !authority[colon..].contains(']') => &authority[..colon],
+        _ => authority,
+    };
+    host.is_some_and(|host| host.eq_ignore_ascii_case(authority))
+        || LOCAL_HOSTS
+            .iter()
+            .any(|local| local.eq_ignore_ascii_case(hostname))
+}
+
+async fn stream_updates(mut socket: WebSocket, storage: StorageHandle, filter: TimeFilter) {
+    let mut events = EVENT_FEED.subscribe();
+    let mut ticker = tokio::time::interval(AGGREGATE_INTERVAL);
+    // Serialized data of the last aggregates sent, to skip unchanged ones
+    let mut last_sent: Option<String> = None;
+
+    loop {
+        let update = tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => Update::Event { event },
+                Err(RecvError::Lagged(skipped)) => Update::Lagged { skipped },
+                Err(RecvError::Closed) => break,
+            },
+            _ = ticker.tick() => {
+                let aggregates = match load_aggregates(&storage, filter).await {
+                    Ok(aggregates) => aggregates,
+                    Err(e) => {
+                        tracing::warn!("WebSocket aggregates failed: {:#}", e);
+                        continue;
+                    }
+                };
+                let data = serde_json::to_string(&aggregates.data).ok();
+                if data == last_sent {
+                    continue;
+                }
+                last_sent = data;
+                Update::Aggregates(aggregates)
+            }
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                // Client messages are ignored
+                Some(Ok(_)) => continue,
+            },
+        };
+
+        let Ok(text) = serde_json::to_string(&update) else {
+            continue;
+        };
+        if socket.send(Message::Text(text.into())).await.is_err() {
+            break;
+        }
+    }
+}
+
+async fn load_aggregates(
+    storage: &StorageHandle,
+    filter: TimeFilter,
+) -> anyhow::Result<Aggregates> {
+    let storage = storage.clone();
+    let (since, until) = (filter.since(), filter.until());
+    let data = tokio::task::spawn_blocking(move || {
+        anyhow::Ok(AggregateData {
+            tools: storage
+                .get_tool_metrics(since, until)?
+                .iter()
+                .map(ToolExportRow::from)
+                .collect(),
+            tokens: storage.get_token_metrics(since, until)?,
+            sessions: storage.get_session_metrics(since, until)?,
+        })
+    })
+    .await??;
+    Ok(Aggregates {
+        range: filter.label(),
+        since,
+        until,
+        data,
+    })
+}
@@ -0,0 +1,244 @@
+//! Synthetic load for measuring ingestion and queries: `agenttop bench` pushes generated
+//! OTLP logs through a receiver into a scratch database and reports throughput, and the
+//! benchmarks under `benches/` use the same data.
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use opentelemetry_proto::tonic::collector::logs::v1::ExportLogsServiceRequest;
+use opentelemetry_proto::tonic::common::v1::any_value::Value as AnyValueKind;
+use opentelemetry_proto::tonic::common::v1::{AnyValue, KeyValue};
+use opentelemetry_proto::tonic::logs::v1::{LogRecord, ResourceLogs, ScopeLogs};
+use prost::Message;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::otlp::{self, ReceiverOptions};
+use crate::self_metrics::SELF_METRICS;
+use crate::storage::StorageHandle;
+
+/// Tools the generated events rotate through
+const TOOLS: &[&str] = &[
+    "Read",
+    "Edit",
+    "Bash",
+    "Grep",
+    "Write",
+    "mcp__github__get_issue",
+    "mcp__context7__query-docs",
+];
+
+/// How long to wait for the receiver to start and for the data to be stored
+const WAIT: Duration = Duration::from_secs(600);
+
+/// `count` tool_result events numbered from `first`, spread over the last hour; every
+/// tenth one failed
+pub fn synthetic_logs(first: usize, count: usize) -> ExportLogsServiceRequest {
+    let now = Utc::now().timestamp_nanos_opt().unwrap_or_default() as u64;
+    let attribute = |key: &str, value: String| KeyValue {
+        key: key.to_string(),
+        value: Some(AnyValue {
+            value: Some(AnyValueKind::StringValue(value)),
+        }),
+    };
+    let log_records = (first..first + count)
+        .map(|n| LogRecord {
+            time_unix_nano: now - (n % 3600) as u64 * 1_000_000_000,
+            attributes: vec![
+                attribute("event.name", "claude_code.tool_result".to_string()),
+                attribute("tool_name", TOOLS[n % TOOLS.len()].to_string()),
+                attribute("success", (n % 10 != 0).to_string()),
+                attribute("duration_ms", (20 + n % 500).to_string()),
+                attribute("session.id", format!("bench-{}", n / 1000)),
+            ],
+            ..Default::default()
+        })
+        .collect();
+    ExportLogsServiceRequest {
+        resource_logs: vec![ResourceLogs {
+            scope_logs: vec![ScopeLogs {
+                log_records,
+                ..Default::default()
+            }],
+            ..Default::default()
+        }],
+    }
+}
+
+/// [`synthetic_logs`] as an OTLP/HTTP protobuf body
+pub fn synthetic_payload(first: usize, count: usize) -> Vec<u8> {
+    synthetic_logs(first, count).encode_to_vec()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchReport {
+    pub events: usize,
+    pub batch_size: usize,
+    /// Until the receiver accepted every batch
+    pub send: Duration,
+    /// Until every event was in the database
+    pub stored: Duration,
+    /// Write batches the storage could not keep up with
+    pub batches_dropped: u64,
+    /// One all-time `get_tool_metrics` over the result
+    pub tool_metrics_query: Duration,
+}
+
+/// Send `events` synthetic events in batches of `batch_size` to a receiver on a free
+/// port, backed by a scratch database that is deleted afterwards
+pub async fn run(events: usize, batch_size: usize) -> Result<BenchReport> {
+    let dir = std::env::temp_dir().join(format!("agenttop-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let result = run_in(dir.join("bench.duckdb"), events, batch_size.max(1)).await;
+    let _ = std::fs::remove_dir_all(&dir);
+    result
+}
+
+async fn run_in(path: PathBuf, events: usize, batch_size: usize) -> Result<BenchReport> {
+    let storage = StorageHandle::open(&path)?;
+    let addr = std::net::TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0)))?.local_addr()?;
+    let options = ReceiverOptions {
+        bind: Some(addr),
+        ..Default::default()
+    };
+    let receiver = tokio::spawn(otlp::start_receiver(storage.clone(), options));
+    let started = Instant::now();
+    while SELF_METRICS.receiver.snapshot().listen_addr != Some(addr.to_string()) {
+        if receiver.is_finished() || started.elapsed() > WAIT {
+            anyhow::bail!("The OTLP receiver did not start on {}", addr);
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let dropped_before = SELF_METRICS.receiver.snapshot().batches_dropped;
+
+    let url = format!("http://{}/v1/logs", addr);
+    let started = Instant::now();
+    let sent = tokio::task::spawn_blocking(move || -> Result<()> {
+        let agent = ureq::AgentBuilder::new().timeout(WAIT).build();
+        for first in (0..events).step_by(batch_size) {
+            let body = synthetic_payload(first, batch_size.min(events - first));
+            agent
+                .post(&url)
+                .set("Content-Type", "application/x-protobuf")
+                .send_bytes(&body)
+                .with_context(|| format!("Sending batch at event {} failed", first))?;
+        }
+        Ok(())
+    })
+    .await;
+    let send = started.elapsed();
+    receiver.abort();
+    sent??;
+
+    // Queries wait behind the writes queued before them
+    let batches_dropped = SELF_METRICS.receiver.snapshot().batches_dropped - dropped_before;
+    let count = storage.run_query("SELECT count(*) FROM log_events", 1)?;
+    let stored = started.elapsed();
+    let rows: usize = count.rows[0][0]
+        .as_deref()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or_default();
+    if rows < events && batches_dropped == 0 {
+        anyhow::bail!("Only {} of {} events were stored", rows, events);
+    }
+
+    let query_started = Instant::now();
+    storage.get_tool_metrics(None, None)?;
+    let tool_metrics_query = query_started.elapsed();
+
+    Ok(BenchReport {
+        events,
+        batch_size,
+        send,
+        stored,
+        batches_dropped,
+        tool_metrics_query,
+    })
+}
+
+/// Parse an event count with an optional `k` or `M` suffix ("250k", "1M")
+pub fn parse_count(value: &str) -> Result<usize, String> {
+    let value = value.trim();
+    let (digits, factor) = match value.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&value[..i], 1_000),
+        Some((i, 'm' | 'M')) => (&value[..i], 1_000_000),
+        _ => (value, 1),
+    };
+    digits
+        .parse::<usize>()
+        .map(|n| n * factor)
+        .map_err(|_| format!("invalid event count \"{}\" (e.g. 5000, 250k, 1M)", value))
+}
+
+/// Events per second over `elapsed`
+fn rate(events: usize, elapsed: Duration) -> f64 {
+    events as f64 / elapsed.as_secs_f64().max(f64::EPSILON)
+}
+
+pub fn render(report: &BenchReport) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "{} events in batches of {}",
+        report.events, report.batch_size
+    );
+    let _ = writeln!(
+        out,
+        "  received  {:>10.2?}  {:>12.0} events/s",
+        report.send,
+        rate(report.events, report.send)
+    );
+    let _ = writeln!(
+        out,
+        "  stored    {:>10.2?}  {:>12.0} events/s",
+        report.stored,
+        rate(report.events, report.stored)
+    );
+    if report.batches_dropped > 0 {
+        let _ = writeln!(
+            out,
+            "  dropped   {} batches (storage fell behind)",
+            report.batches_dropped
+        );
+    }
+    let _ = writeln!(
+        out,
+        "  get_tool_metrics (all time)  {:.2?}",
+        report.tool_metrics_query
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_synthetic_payload_parses() {
+        let events = otlp::parse_logs(&synthetic_payload(5, 20)).unwrap();
+        assert_eq!(events.len(), 20);
+        assert_eq!(
+            events[0].event_name.as_deref(),
+            Some("claude_code.tool_result")
+        );
+        assert_eq!(events[0].attributes["tool_name"], TOOLS[5 % TOOLS.len()]);
+        assert_eq!(events[5].attributes["success"], "false");
+    }
+
+    #[test]
+    fn test_parse_count() {
+        assert_eq!(parse_count("5000"), Ok(5000));
+        assert_eq!(parse_count("250k"), Ok(250_000));
+        assert_eq!(parse_count("1M"), Ok(1_000_000));
+        assert!(parse_count("lots").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_run() {
+        let report = run(250, 100).await.unwrap();
+        assert_eq!(report.events, 250);
+        assert!(report.stored >= report.send);
+        assert!(render(&report).contains("250 events in batches of 100"));
+    }
+}
@@ -0,0 +1,427 @@
+//! Spend budgets per local day, week or month, on all spend or one provider's or
+//! project's.
+//!
+//! Each budget has warn and critical thresholds (percent of its limit) shown in the TUI
+//! and logged in headless mode, and an optional hook command run when spend goes over
+//! the limit itself. The monitor keeps each period's spend in storage: the current
+//! period is updated on every check, and a period is closed with its final spend once
+//! the next one starts, so past periods can be reported and alerted on.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Datelike, Local, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::hooks::shell;
+use crate::storage::{BudgetUsage, StorageHandle};
+use crate::tui::app::{format_usd, local_day_start};
+
+/// How often the background monitor checks budgets
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetPeriod {
+    Daily,
+    /// Monday to Sunday
+    Weekly,
+    Monthly,
+}
+
+impl BudgetPeriod {
+    /// Start of the current period, in UTC
+    pub fn start(self, now: DateTime<Local>) -> Option<DateTime<Utc>> {
+        let days_back = match self {
+            BudgetPeriod::Daily => 0,
+            BudgetPeriod::Weekly => now.weekday().num_days_from_monday(),
+            BudgetPeriod::Monthly => now.day0(),
+        };
+        let day = now.date_naive() - chrono::Days::new(days_back as u64);
+        local_day_start(
+            day.and_hms_opt(12, 0, 0)?
+                .and_local_timezone(Local)
+                .earliest()?,
+        )
+    }
+
+    /// Start of the period after the one starting at `start`
+    pub fn next_start(self, start: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let days = match self {
+            BudgetPeriod::Daily => 1,
+            BudgetPeriod::Weekly => 7,
+            // Any day of the next month
+            BudgetPeriod::Monthly => 32,
+        };
+        let day = start.with_timezone(&Local).date_naive() + chrono::Days::new(days);
+        self.start(
+            day.and_hms_opt(12, 0, 0)?
+                .and_local_timezone(Local)
+                .earliest()?,
+        )
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BudgetPeriod::Daily => "daily",
+            BudgetPeriod::Weekly => "weekly",
+            BudgetPeriod::Monthly => "monthly",
+        }
+    }
+
+    /// Short label used after an amount ("$3.20/$10 today")
+    pub fn label(self) -> &'static str {
+        match self {
+            BudgetPeriod::Daily => "today",
+            BudgetPeriod::Weekly => "this week",
+            BudgetPeriod::Monthly => "this month",
+        }
+    }
+}
+
+/// Spend a budget counts: all of it (the cost metrics), or the api_request costs of
+/// one provider or project
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetScope {
+    #[default]
+    Global,
+    /// A provider id, e.g. "claude_code"
+    Provider(String),
+    /// The `project` attribute agents send
+    Project(String),
+}
+
+impl BudgetScope {
+    pub fn is_global(&self) -> bool {
+        *self == BudgetScope::Global
+    }
+
+    /// "global", "provider:claude_code" or "project:agenttop"
+    pub fn key(&self) -> String {
+        match self {
+            BudgetScope::Global => "global".to_string(),
+            BudgetScope::Provider(id) => format!("provider:{}", id),
+            BudgetScope::Project(name) => format!("project:{}", name),
+        }
+    }
+}
+
+/// A spend limit for one period
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Budget {
+    pub period: BudgetPeriod,
+    pub limit_usd: f64,
+    #[serde(default, skip_serializing_if = "BudgetScope::is_global")]
+    pub scope: BudgetScope,
+    /// Percent of the limit at which the budget turns yellow
+    #[serde(default = "default_warn_pct")]
+    pub warn_pct: f64,
+    /// Percent of the limit at which the budget turns red
+    #[serde(default = "default_critical_pct")]
+    pub critical_pct: f64,
+    /// Shell command run once when spend exceeds the limit (per period)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hook: Option<String>,
+}
+
+impl Budget {
+    /// Identifies the budget's periods in storage, e.g. "daily/provider:claude_code"
+    pub fn key(&self) -> String {
+        format!("{}/{}", self.period.as_str(), self.scope.key())
+    }
+}
+
+fn default_warn_pct() -> f64 {
+    75.0
+}
+
+fn default_critical_pct() -> f64 {
+    90.0
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetLevel {
+    Ok,
+    Warn,
+    Critical,
+    /// Spend is over the limit
+    Exceeded,
+}
+
+/// Spend against one budget
+#[derive(Debug, Clone, PartialEq)]
+pub struct BudgetStatus {
+    pub period: BudgetPeriod,
+    pub scope: BudgetScope,
+    pub spent_usd: f64,
+    pub limit_usd: f64,
+    pub level: BudgetLevel,
+}
+
+impl BudgetStatus {
+    pub fn new(budget: &Budget, spent_usd: f64) -> Self {
+        let pct = if budget.limit_usd > 0.0 {
+            spent_usd / budget.limit_usd * 100.0
+        } else {
+            0.0
+        };
+        let level = if budget.limit_usd > 0.0 && spent_usd > budget.limit_usd {
+            BudgetLevel::Exceeded
+        } else if pct >= budget.critical_pct {
+            BudgetLevel::Critical
+        } else if pct >= budget.warn_pct {
+            BudgetLevel::Warn
+        } else {
+            BudgetLevel::Ok
+        };
+        Self {
+            period: budget.period,
+            scope: budget.scope.clone(),
+            spent_usd,
+            limit_usd: budget.limit_usd,
+            level,
+        }
+    }
+
+    pub fn percent(&self) -> f64 {
+        if self.limit_usd > 0.0 {
+            self.spent_usd / self.limit_usd * 100.0
+        } else {
+            0.0
+        }
+    }
+
+    /// e.g. "$8.10/$10.00 today (81%)", after "claude_code: " for a scoped budget
+    pub fn describe(&self) -> String {
+        let scope = match &self.scope {
+            BudgetScope::Global => String::new(),
+            BudgetScope::Provider(name) | BudgetScope::Project(name) => format!("{}: ", name),
+        };
+        format!(
+            "{}{}/{} {} ({:.0}%)",
+            scope,
+            format_usd(self.spent_usd),
+            format_usd(self.limit_usd),
+            self.period.label(),
+            self.percent()
+        )
+    }
+}
+
+/// Current spend against every budget
+pub fn check(
+    storage: &StorageHandle,
+    budgets: &[Budget],
+    now: DateTime<Local>,
+) -> Result<Vec<BudgetStatus>> {
+    budgets
+        .iter()
+        .map(|budget| {
+            let spent = storage.get_budget_spend(&budget.scope, budget.period.start(now), None)?;
+            Ok(BudgetStatus::new(budget, spent))
+        })
+        .collect()
+}
+
+/// Store the current period's spend and close the budget's earlier periods still open,
+/// with their spend up to the end of the period
+pub fn record_usage(
+    storage: &StorageHandle,
+    budget: &Budget,
+    status: &BudgetStatus,
+    now: DateTime<Local>,
+) -> Result<()> {
+    let key = budget.key();
+    let start = budget
+        .period
+        .start(now)
+        .context("Could not find the start of the budget period")?;
+    for usage in storage.get_budget_usage(Some(&key), true, usize::MAX)? {
+        if usage.period_start >= start {
+            continue;
+        }
+        let spent_usd = storage.get_budget_spend(
+            &budget.scope,
+            Some(usage.period_start),
+            Some(usage.period_end),
+        )?;
+        tracing::info!(
+            "Budget {} closed at {} of {}",
+            key,
+            format_usd(spent_usd),
+            format_usd(usage.limit_usd)
+        );
+        storage.save_budget_usage(BudgetUsage {
+            spent_usd,
+            closed: true,
+            updated_at: now.with_timezone(&Utc),
+            ..usage
+        })?;
+    }
+    storage.save_budget_usage(BudgetUsage {
+        budget: key,
+        period_start: start,
+        period_end: budget.period.next_start(start).unwrap_or(start),
+        limit_usd: budget.limit_usd,
+        spent_usd: status.spent_usd,
+        closed: false,
+        updated_at: now.with_timezone(&Utc),
+    })?;
+    Ok(())
+}
+
+/// The status closest to (or furthest over) its limit
+pub fn most_severe(statuses: &[BudgetStatus]) -> Option<&BudgetStatus> {
+    statuses
+        .iter()
+        .max_by(|a, b| a.percent().total_cmp(&b.percent()))
+}
+
+/// Run a budget's hook with the spend in `AGENTTOP_BUDGET_*` environment variables
+fn run_hook(command: &str, status: &BudgetStatus) -> Result<()> {
+    let exit = shell(command)
+        .env("AGENTTOP_BUDGET_PERIOD", status.period.as_str())
+        .env("AGENTTOP_BUDGET_SCOPE", status.scope.key())
+        .env(
+            "AGENTTOP_BUDGET_LIMIT_USD",
+            format!("{:.2}", status.limit_usd),
+        )
+        .env(
+            "AGENTTOP_BUDGET_SPENT_USD",
+            format!("{:.2}", status.spent_usd),
+        )
+        .status()
+        .with_context(|| format!("Could not run budget hook `{}`", command))?;
+    if !exit.success() {
+        anyhow::bail!("Budget hook `{}` exited with {}", command, exit);
+    }
+    Ok(())
+}
+
+/// Log budget level changes and run hooks on a background thread
+pub fn spawn_monitor(storage: StorageHandle, budgets: Vec<Budget>) {
+    if budgets.is_empty() {
+        return;
+    }
+    std::thread::spawn(move || {
+        let mut levels = vec![BudgetLevel::Ok; budgets.len()];
+        loop {
+            let now = Local::now();
+            match check(&storage, &budgets, now) {
+                Ok(statuses) => {
+                    for ((budget, status), level) in budgets.iter().zip(&statuses).zip(&mut levels)
+                    {
+                        if let Err(e) = record_usage(&storage, budget, status, now) {
+                            tracing::warn!("Could not record budget usage: {:#}", e);
+                        }
+                        if status.level == *level {
+                            continue;
+                        }
+                        match status.level {
+                            BudgetLevel::Ok => {}
+                            BudgetLevel::Warn => {
+                                tracing::warn!("Budget warning: {}", status.describe())
+                            }
+                            BudgetLevel::Critical => {
+                                tracing::error!("Budget critical: {}", status.describe())
+                            }
+                            BudgetLevel::Exceeded => {
+                                tracing::error!("Budget exceeded: {}", status.describe())
+                            }
+                        }
+                        // A new period resets spend, which re-arms the hook
+                        if status.level == BudgetLevel::Exceeded
+                            && let Some(hook) = &budget.hook
+                            && let Err(e) = run_hook(hook, status)
+                        {
+                            tracing::error!("{:#}", e);
+                        }
+                        *level = status.level;
+                    }
+                }
+                Err(e) => tracing::warn!("Could not check budgets: {:#}", e),
+            }
+            std::thread::sleep(CHECK_INTERVAL);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn budget(limit_usd: f64) -> Budget {
+        Budget {
+            period: BudgetPeriod::Daily,
+            limit_usd,
+            scope: BudgetScope::Global,
+            warn_pct: default_warn_pct(),
+            critical_pct: default_critical_pct(),
+            hook: None,
+        }
+    }
+
+    #[test]
+    fn test_budget_levels() {
+        let b = budget(10.0);
+        assert_eq!(BudgetStatus::new(&b, 2.0).level, BudgetLevel::Ok);
+        assert_eq!(BudgetStatus::new(&b, 7.5).level, BudgetLevel::Warn);
+        assert_eq!(BudgetStatus::new(&b, 9.5).level, BudgetLevel::Critical);
+        assert_eq!(BudgetStatus::new(&b, 10.0).level, BudgetLevel::Critical);
+        assert_eq!(BudgetStatus::new(&b, 10.5).level, BudgetLevel::Exceeded);
+        assert_eq!(
+            BudgetStatus::new(&b, 8.1).describe(),
+            "$8.10/$10.00 today (81%)"
+        );
+    }
+
+    #[test]
+    fn test_period_start() {
+        // Thursday 2025-01-16
+        let now = Local.with_ymd_and_hms(2025, 1, 16, 15, 30, 0).unwrap();
+        let local = |start: Option<DateTime<Utc>>| {
+            start
+                .unwrap()
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        };
+        assert_eq!(local(BudgetPeriod::Daily.start(now)), "2025-01-16 00:00");
+        assert_eq!(local(BudgetPeriod::Weekly.start(now)), "2025-01-13 00:00");
+        assert_eq!(local(BudgetPeriod::Monthly.start(now)), "2025-01-01 00:00");
+
+        let next = |period: BudgetPeriod| local(period.next_start(period.start(now).unwrap()));
+        assert_eq!(next(BudgetPeriod::Daily), "2025-01-17 00:00");
+        assert_eq!(next(BudgetPeriod::Weekly), "2025-01-20 00:00");
+        assert_eq!(next(BudgetPeriod::Monthly), "2025-02-01 00:00");
+    }
+
+    #[test]
+    fn test_budget_scope() {
+        let scoped: Budget = serde_json::from_str(
+            r#"{"period": "weekly", "limit_usd": 50, "scope": {"provider": "claude_code"}}"#,
+        )
+        .unwrap();
+        assert_eq!(scoped.key(), "weekly/provider:claude_code");
+        assert_eq!(
+            BudgetStatus::new(&scoped, 10.0).describe(),
+            "claude_code: $10.00/$50.00 this week (20%)"
+        );
+        let global: Budget =
+            serde_json::from_str(r#"{"period": "daily", "limit_usd": 5}"#).unwrap();
+        assert_eq!(global.key(), "daily/global");
+        assert!(!serde_json::to_string(&global).unwrap().contains("scope"));
+    }
+
+    #[test]
+    fn test_most_severe() {
+        let statuses = vec![
+            BudgetStatus::new(&budget(10.0), 5.0),
+            BudgetStatus::new(&budget(4.0), 3.0),
+        ];
+        assert_eq!(most_severe(&statuses).unwrap().limit_usd, 4.0);
+        assert!(most_severe(&[]).is_none());
+    }
+}
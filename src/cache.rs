@@ -0,0 +1,116 @@
+//! Size-capped in-process caches, so a long-running receiver uses a predictable amount
+//! of memory. Each cache holds at most its configured number of entries (`[cache]` in
+//! the config file), evicting the least recently used one, and counts its hits, misses
+//! and evictions in the self-metrics under its name.
+
+use once_cell::sync::OnceCell;
+use std::hash::Hash;
+use std::num::NonZeroUsize;
+
+use crate::config::CacheSettings;
+use crate::self_metrics::SELF_METRICS;
+
+/// Answers to `/api/query` kept while the data is unchanged
+pub const DEFAULT_QUERY_RESULTS: usize = 256;
+
+/// Events kept for the dashboard's event log
+pub const DEFAULT_RECENT_EVENTS: usize = 1000;
+
+/// Recent token_usage rows that new token records can be added to
+pub const DEFAULT_TOKEN_ROWS: usize = 1024;
+
+/// Capacities set from the config file, if any
+static CAPACITIES: OnceCell<CacheSettings> = OnceCell::new();
+
+/// Use `settings` as the cache capacities for the rest of the process
+pub fn set_capacities(settings: CacheSettings) {
+    let _ = CAPACITIES.set(settings);
+}
+
+/// The configured capacities, or the defaults
+pub fn capacities() -> CacheSettings {
+    CAPACITIES.get().cloned().unwrap_or_default()
+}
+
+/// A least-recently-used cache of at most `capacity` entries; a capacity of 0 keeps
+/// nothing. Not synchronized: share it behind a mutex.
+pub struct LruCache<K, V> {
+    name: &'static str,
+    capacity: usize,
+    entries: Option<lru::LruCache<K, V>>,
+}
+
+impl<K: Hash + Eq, V> LruCache<K, V> {
+    pub fn new(name: &'static str, capacity: usize) -> Self {
+        SELF_METRICS.caches.set_size(name, 0, capacity);
+        Self {
+            name,
+            capacity,
+            entries: NonZeroUsize::new(capacity).map(lru::LruCache::new),
+        }
+    }
+
+    /// The entry for `key`, marking it as recently used
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let found = self.entries.as_mut().and_then(|entries| entries.get(key));
+        match found {
+            Some(_) => SELF_METRICS.caches.record_hit(self.name),
+            None => SELF_METRICS.caches.record_miss(self.name),
+        }
+        found
+    }
+
+    /// Store `value` under `key`, evicting the least recently used entry when full
+    pub fn put(&mut self, key: K, value: V) {
+        let Some(entries) = self.entries.as_mut() else {
+            return;
+        };
+        let evicts = entries.len() == self.capacity && !entries.contains(&key);
+        entries.put(key, value);
+        if evicts {
+            SELF_METRICS.caches.record_eviction(self.name);
+        }
+        SELF_METRICS
+            .caches
+            .set_size(self.name, entries.len(), self.capacity);
+    }
+
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.entries.as_ref().map_or(0, lru::LruCache::len)
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let mut cache = LruCache::new("test_lru", 2);
+        cache.put("a", 1);
+        cache.put("b", 2);
+        assert_eq!(cache.get(&"a"), Some(&1));
+        cache.put("c", 3);
+        assert_eq!(cache.get(&"b"), None);
+        assert_eq!(cache.get(&"a"), Some(&1));
+        assert_eq!(cache.len(), 2);
+
+        let stats = SELF_METRICS.caches.snapshot()["test_lru"];
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (2, 1, 1));
+        assert_eq!((stats.entries, stats.capacity), (2, 2));
+    }
+
+    #[test]
+    fn test_zero_capacity_keeps_nothing() {
+        let mut cache = LruCache::new("test_lru_off", 0);
+        cache.put("a", 1);
+        assert_eq!(cache.get(&"a"), None);
+        assert!(cache.is_empty());
+    }
+}
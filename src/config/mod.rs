@@ -1,9 +1,159 @@
 use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+use crate::alerting::AlertRule;
+use crate::budget::Budget;
+use crate::digest::DigestConfig;
+use crate::hooks::EventHook;
+use crate::mqtt::MqttConfig;
+use crate::quota::Quota;
+use crate::taxonomy::ErrorRule;
+
+pub mod settings;
+
+pub use settings::{CacheSettings, Settings, Theme};
 
 const OTLP_ENDPOINT: &str = "http://localhost:4318";
 
+/// Directory where agenttop keeps its database and exported files
+pub fn data_dir() -> Result<PathBuf> {
+    dirs::data_dir()
+        .map(|dir| dir.join("agenttop"))
+        .ok_or_else(|| anyhow::anyhow!("Could not determine data directory"))
+}
+
+/// User settings for agenttop itself: the top-level keys of `config.toml` (see
+/// [`Settings`]), or all of the older `config.json`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AppConfig {
+    /// Key binding overrides: key sequence (e.g. "gg", "<C-n>") to action name
+    /// (e.g. "top", "down"); the action "none" unbinds a default key
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub keys: HashMap<String, String>,
+    /// Tools kept at the top of their table regardless of sort order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pinned_tools: Vec<String>,
+    /// Latency targets: tool name to the highest acceptable average duration in milliseconds
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub latency_slos_ms: HashMap<String, u64>,
+    /// Push aggregated metrics to a remote OTLP collector
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub otlp_export: Option<OtlpExportConfig>,
+    /// Push aggregated metrics to InfluxDB (or Telegraf) as line protocol
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub influx_export: Option<InfluxExportConfig>,
+    /// Push aggregated metrics to Graphite (Carbon) in the plaintext protocol
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub graphite_export: Option<GraphiteExportConfig>,
+    /// Publish tool results, spend and alerts to an MQTT broker
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mqtt: Option<MqttConfig>,
+    /// Conditions that post to a webhook when they fire
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub alert_rules: Vec<AlertRule>,
+    /// Spend limits per day, week or month
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub budgets: Vec<Budget>,
+    /// Usage allowed per window of a subscription plan (e.g. Claude's 5-hour windows)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub quotas: Vec<Quota>,
+    /// Email summary of the previous day or week
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<DigestConfig>,
+    /// Shell commands run for received events that match a filter
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hooks: Vec<EventHook>,
+    /// Rules sorting error messages into categories, tried before the built-in ones
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub error_categories: Vec<ErrorRule>,
+    /// Context window in tokens per model name fragment, tried before the built-in ones
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub context_windows: BTreeMap<String, u64>,
+}
+
+/// Remote OTLP/HTTP collector that receives agenttop's aggregates
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OtlpExportConfig {
+    /// Collector base URL (e.g. "https://otel.example.com:4318"); `/v1/metrics` is appended
+    pub endpoint: String,
+    /// Seconds between pushes
+    #[serde(default = "default_export_interval")]
+    pub interval_secs: u64,
+    /// Extra HTTP headers, e.g. for authentication
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub headers: HashMap<String, String>,
+    /// Resource attributes added to (or overriding) service.name, user.name and host.name
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub resource_attributes: HashMap<String, String>,
+}
+
+/// InfluxDB or Telegraf endpoint that receives agenttop's aggregates as line protocol
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InfluxExportConfig {
+    /// Full write URL, e.g. "http://nas:8086/api/v2/write?org=home&bucket=agenttop" or
+    /// "http://nas:8086/write?db=agenttop"
+    pub url: String,
+    /// API token, sent as `Authorization: Token <token>`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    /// Seconds between pushes
+    #[serde(default = "default_export_interval")]
+    pub interval_secs: u64,
+    /// Tags added to every point (or overriding `host` and `user`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+}
+
+/// Carbon plaintext receiver that gets agenttop's aggregates
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GraphiteExportConfig {
+    /// host:port of the plaintext listener, e.g. "graphite:2003"
+    pub address: String,
+    /// Path every metric is put under, e.g. "dev.alice.agenttop"
+    #[serde(default = "default_graphite_prefix")]
+    pub prefix: String,
+    /// Seconds between pushes
+    #[serde(default = "default_export_interval")]
+    pub interval_secs: u64,
+}
+
+fn default_graphite_prefix() -> String {
+    "agenttop".to_string()
+}
+
+fn default_export_interval() -> u64 {
+    60
+}
+
+impl AppConfig {
+    /// Location used before `config.toml`: `<config dir>/agenttop/config.json`
+    pub fn path() -> Result<PathBuf> {
+        dirs::config_dir()
+            .map(|dir| dir.join("agenttop").join("config.json"))
+            .ok_or_else(|| anyhow::anyhow!("Could not determine config directory"))
+    }
+
+    pub fn load_from(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&content).with_context(|| format!("Invalid config {}", path.display()))
+    }
+
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
 #[allow(dead_code)]
 pub fn claude_settings_path() -> Option<PathBuf> {
     dirs::home_dir().map(|home| home.join(".claude").join("settings.json"))
@@ -0,0 +1,334 @@
+//! `config.toml`: the receiver's bind address, database location and retention, TUI
+//! options, and the [`AppConfig`] keys (key bindings, pins, budgets, alert rules, ...)
+//! at the top level.
+//!
+//! Without a `config.toml`, a `config.json` from earlier versions is still read.
+//! `AGENTTOP_*` environment variables override the file (see [`Settings::apply_env`]).
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::AppConfig;
+use crate::otlp::limits::DEFAULT_MAX_VALUE_BYTES;
+use crate::otlp::{IngestLimits, RECEIVER_ADDR};
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct Settings {
+    #[serde(default)]
+    pub receiver: ReceiverSettings,
+    #[serde(default)]
+    pub storage: StorageSettings,
+    #[serde(default)]
+    pub tui: TuiSettings,
+    #[serde(default)]
+    pub cache: CacheSettings,
+    #[serde(flatten)]
+    pub app: AppConfig,
+    /// File the settings were read from (None: defaults, nothing on disk)
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ReceiverSettings {
+    /// Address the OTLP receiver listens on
+    #[serde(default = "default_bind")]
+    pub bind: SocketAddr,
+    /// Longest attribute value stored, in bytes; longer ones are cut and their
+    /// original length kept (0: no cap)
+    #[serde(default = "default_max_value_bytes")]
+    pub max_attribute_bytes: usize,
+    /// Longest log body stored, in bytes (0: no cap)
+    #[serde(default = "default_max_value_bytes")]
+    pub max_body_bytes: usize,
+    /// Browser origins allowed to read the REST API from their pages
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    /// Token `/api/query` requires as `Authorization: Bearer <token>`, and that
+    /// `view --remote` sends
+    #[serde(default)]
+    pub api_token: Option<String>,
+}
+
+impl Default for ReceiverSettings {
+    fn default() -> Self {
+        Self {
+            bind: default_bind(),
+            max_attribute_bytes: default_max_value_bytes(),
+            max_body_bytes: default_max_value_bytes(),
+            allowed_origins: Vec::new(),
+            api_token: None,
+        }
+    }
+}
+
+impl ReceiverSettings {
+    pub fn limits(&self) -> IngestLimits {
+        IngestLimits {
+            max_attribute_bytes: self.max_attribute_bytes,
+            max_body_bytes: self.max_body_bytes,
+        }
+    }
+
+    pub fn api_access(&self) -> crate::api::Access {
+        crate::api::Access {
+            allowed_origins: self.allowed_origins.clone(),
+            token: self.api_token.clone(),
+            sql_token: self.api_token.clone(),
+        }
+    }
+}
+
+fn default_max_value_bytes() -> usize {
+    DEFAULT_MAX_VALUE_BYTES
+}
+
+fn default_bind() -> SocketAddr {
+    RECEIVER_ADDR
+        .parse()
+        .expect("valid default receiver address")
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct StorageSettings {
+    /// Database file (default: `metrics.duckdb` in the data directory); `~/` is expanded
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+    /// Delete rows older than this many days (default: keep everything)
+    #[serde(default)]
+    pub retention_days: Option<u32>,
+    /// Interrupt dashboard and API queries running longer than this (default: 30s; 0:
+    /// never)
+    #[serde(default)]
+    pub query_timeout_secs: Option<u64>,
+    /// Journal received writes so those lost in a crash are stored on the next start
+    #[serde(default)]
+    pub journal: bool,
+}
+
+impl StorageSettings {
+    pub fn query_timeout(&self) -> Option<Duration> {
+        match self.query_timeout_secs {
+            None => Some(crate::storage::DEFAULT_QUERY_TIMEOUT),
+            Some(0) => None,
+            Some(secs) => Some(Duration::from_secs(secs)),
+        }
+    }
+}
+
+/// Most entries kept by each in-process cache (see [`crate::cache`]); 0 turns a cache off
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct CacheSettings {
+    /// Answers to `/api/query` (remote dashboards) kept while the data is unchanged
+    #[serde(default = "default_query_results")]
+    pub query_results: usize,
+    /// Events kept for the dashboard's event log
+    #[serde(default = "default_recent_events")]
+    pub recent_events: usize,
+    /// Recent token_usage rows that new token records of the same kind are added to
+    #[serde(default = "default_token_rows")]
+    pub token_rows: usize,
+}
+
+impl Default for CacheSettings {
+    fn default() -> Self {
+        Self {
+            query_results: default_query_results(),
+            recent_events: default_recent_events(),
+            token_rows: default_token_rows(),
+        }
+    }
+}
+
+fn default_query_results() -> usize {
+    crate::cache::DEFAULT_QUERY_RESULTS
+}
+
+fn default_recent_events() -> usize {
+    crate::cache::DEFAULT_RECENT_EVENTS
+}
+
+fn default_token_rows() -> usize {
+    crate::cache::DEFAULT_TOKEN_ROWS
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TuiSettings {
+    /// Milliseconds between dashboard data refreshes
+    #[serde(default = "default_refresh_ms")]
+    pub refresh_ms: u64,
+    #[serde(default)]
+    pub theme: Theme,
+}
+
+impl Default for TuiSettings {
+    fn default() -> Self {
+        Self {
+            refresh_ms: default_refresh_ms(),
+            theme: Theme::default(),
+        }
+    }
+}
+
+fn default_refresh_ms() -> u64 {
+    1000
+}
+
+impl TuiSettings {
+    pub fn refresh_interval(&self) -> Duration {
+        Duration::from_millis(self.refresh_ms.max(100))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Theme {
+    #[default]
+    Default,
+    /// No colors, only bold/reversed text (for monochrome or low-contrast terminals)
+    Mono,
+}
+
+impl Settings {
+    /// Default location: `<config dir>/agenttop/config.toml`
+    pub fn path() -> Result<PathBuf> {
+        Ok(AppConfig::path()?.with_file_name("config.toml"))
+    }
+
+    /// Load `path` if given (it must exist), otherwise `config.toml` or the older
+    /// `config.json` from the config directory, otherwise the defaults
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        if let Some(path) = path {
+            if !path.exists() {
+                anyhow::bail!("Config file {} does not exist", path.display());
+            }
+            return Self::load_from(path);
+        }
+        let toml_path = Self::path()?;
+        if toml_path.exists() {
+            return Self::load_from(&toml_path);
+        }
+        let json_path = AppConfig::path()?;
+        if json_path.exists() {
+            return Self::load_from(&json_path);
+        }
+        Ok(Self::default())
+    }
+
+    /// Read a TOML file, or a `.json` file in the older [`AppConfig`]-only format
+    pub fn load_from(path: &Path) -> Result<Self> {
+        let mut settings = if is_json(path) {
+            Self {
+                app: AppConfig::load_from(path)?,
+                ..Self::default()
+            }
+        } else {
+            let content = fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            toml::from_str(&content)
+                .with_context(|| format!("Invalid config {}", path.display()))?
+        };
+        settings.path = Some(path.to_path_buf());
+        Ok(settings)
+    }
+
+    /// Override settings from `AGENTTOP_BIND`, `AGENTTOP_API_TOKEN`, `AGENTTOP_DB_PATH`,
+    /// `AGENTTOP_RETENTION_DAYS`, `AGENTTOP_REFRESH_MS` and `AGENTTOP_THEME`, read through
+    /// `var`; unset and empty variables are ignored
+    pub fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<()> {
+        let var = |name: &str| var(name).filter(|value| !value.trim().is_empty());
+        let invalid = |name: &str, value: &str| format!("Invalid {}: {}", name, value);
+
+        if let Some(value) = var("AGENTTOP_BIND") {
+            self.receiver.bind = value
+                .trim()
+                .parse()
+                .with_context(|| invalid("AGENTTOP_BIND", &value))?;
+        }
+        if let Some(value) = var("AGENTTOP_API_TOKEN") {
+            self.receiver.api_token = Some(value.trim().to_string());
+        }
+        if let Some(value) = var("AGENTTOP_DB_PATH") {
+            self.storage.path = Some(PathBuf::from(value));
+        }
+        if let Some(value) = var("AGENTTOP_RETENTION_DAYS") {
+            self.storage.retention_days = Some(
+                value
+                    .trim()
+                    .parse()
+                    .with_context(|| invalid("AGENTTOP_RETENTION_DAYS", &value))?,
+            );
+        }
+        if let Some(value) = var("AGENTTOP_REFRESH_MS") {
+            self.tui.refresh_ms = value
+                .trim()
+                .parse()
+                .with_context(|| invalid("AGENTTOP_REFRESH_MS", &value))?;
+        }
+        if let Some(value) = var("AGENTTOP_THEME") {
+            self.tui.theme =
+                serde_json::from_value(serde_json::Value::String(value.trim().to_lowercase()))
+                    .with_context(|| invalid("AGENTTOP_THEME", &value))?;
+        }
+        Ok(())
+    }
+
+    /// The database file: `[storage] path` or the default location
+    pub fn db_path(&self) -> Result<PathBuf> {
+        match &self.storage.path {
+            Some(path) => Ok(expand_home(path)),
+            None => Ok(crate::storage::default_db_path()?),
+        }
+    }
+
+    /// Write `app.pinned_tools` back to the file the settings came from (or a new
+    /// `config.toml`), leaving the rest of the file as it is
+    pub fn save_pinned_tools(&self) -> Result<()> {
+        let path = match &self.path {
+            Some(path) => path.clone(),
+            None => Self::path()?,
+        };
+        if is_json(&path) {
+            let mut config = AppConfig::load_from(&path)?;
+            config.pinned_tools = self.app.pinned_tools.clone();
+            return config.save_to(&path);
+        }
+
+        let content = if path.exists() {
+            fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?
+        } else {
+            String::new()
+        };
+        let mut doc: toml_edit::DocumentMut = content
+            .parse()
+            .with_context(|| format!("Invalid config {}", path.display()))?;
+        if self.app.pinned_tools.is_empty() {
+            doc.remove("pinned_tools");
+        } else {
+            let pins: toml_edit::Array = self.app.pinned_tools.iter().collect();
+            doc["pinned_tools"] = toml_edit::value(pins);
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, doc.to_string())
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "json")
+}
+
+/// Replace a leading `~/` with the home directory
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
@@ -0,0 +1,278 @@
+//! Context-window utilization: how much of the model's context each API request's
+//! prompt filled. Agents get worse as the context fills up (they lose track of earlier
+//! instructions, and compaction drops detail), so a session whose requests keep running
+//! near the ceiling is worth restarting or splitting.
+//!
+//! The prompt size is an api_request's input tokens, cached or not (see
+//! [`crate::storage::ContextSample`]). The model's limit is the event's `context_window`
+//! attribute when the agent sends one, else the config file's `context_windows` (model
+//! name fragment to tokens), else the built-in [`CONTEXT_WINDOWS`]:
+//!
+//! ```json
+//! "context_windows": { "sonnet-4-5": 1000000 }
+//! ```
+
+use anyhow::Result;
+use chrono::{Local, NaiveDate};
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::storage::ContextSample;
+
+/// Model name fragments and their context window in tokens, most specific first
+pub const CONTEXT_WINDOWS: &[(&str, u64)] = &[
+    // Anthropic (the 1M-token Sonnet beta is opt-in; configure it in `context_windows`)
+    ("opus", 200_000),
+    ("sonnet", 200_000),
+    ("haiku", 200_000),
+    ("claude", 200_000),
+    // OpenAI
+    ("gpt-5", 400_000),
+    ("gpt-4.1", 1_047_576),
+    ("gpt-4o", 128_000),
+    ("o4-mini", 200_000),
+    ("o3", 200_000),
+    // Google
+    ("gemini-2.5", 1_048_576),
+    ("gemini", 1_048_576),
+    // Qwen
+    ("qwen3-coder", 262_144),
+    ("qwen", 131_072),
+];
+
+/// Utilization (percent of the window) at or above which a request is near the ceiling
+pub const NEAR_CEILING_PCT: f64 = 80.0;
+
+/// Share of a session's requests near the ceiling from which the session is
+/// consistently near it
+pub const CONSISTENT_SHARE: f64 = 0.5;
+
+/// Requests with a known window a session needs before it is judged
+pub const MIN_REQUESTS: u64 = 5;
+
+/// Context windows set from the config file, tried before the built-in ones
+static CONFIGURED: OnceCell<Vec<(String, u64)>> = OnceCell::new();
+
+/// Use `windows` (model name fragment to tokens) before the built-in ones for the rest
+/// of the process
+pub fn set_windows(windows: &BTreeMap<String, u64>) -> Result<()> {
+    if let Some((fragment, _)) = windows.iter().find(|(_, tokens)| **tokens == 0) {
+        anyhow::bail!("Context window of \"{}\" must be more than 0", fragment);
+    }
+    // Longer fragments are more specific
+    let mut windows: Vec<(String, u64)> = windows
+        .iter()
+        .map(|(fragment, tokens)| (fragment.to_lowercase(), *tokens))
+        .collect();
+    windows.sort_by(|a, b| b.0.len().cmp(&a.0.len()).then(a.0.cmp(&b.0)));
+    let _ = CONFIGURED.set(windows);
+    Ok(())
+}
+
+/// Context window of a model (case-insensitive fragment match); None if unknown
+pub fn window_of(model: &str) -> Option<u64> {
+    let model = model.to_lowercase();
+    let configured = CONFIGURED.get().into_iter().flatten();
+    configured
+        .map(|(fragment, tokens)| (fragment.as_str(), *tokens))
+        .chain(CONTEXT_WINDOWS.iter().copied())
+        .find(|(fragment, _)| model.contains(fragment))
+        .map(|(_, tokens)| tokens)
+}
+
+/// Percent of its model's context window `sample` filled; None when the window is
+/// unknown
+pub fn utilization(sample: &ContextSample) -> Option<f64> {
+    let window = sample
+        .context_window
+        .or_else(|| sample.model.as_deref().and_then(window_of))?;
+    Some(sample.context_tokens as f64 / window as f64 * 100.0)
+}
+
+/// Utilization over a set of requests
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Utilization {
+    /// Requests with a known context window
+    pub requests: u64,
+    /// Requests at or above [`NEAR_CEILING_PCT`]
+    pub near_ceiling: u64,
+    pub avg_pct: f64,
+    pub peak_pct: f64,
+}
+
+impl Utilization {
+    fn add(&mut self, pct: f64) {
+        self.avg_pct = (self.avg_pct * self.requests as f64 + pct) / (self.requests + 1) as f64;
+        self.requests += 1;
+        self.peak_pct = self.peak_pct.max(pct);
+        if pct >= NEAR_CEILING_PCT {
+            self.near_ceiling += 1;
+        }
+    }
+
+    /// Share of the requests near the ceiling; None without requests
+    pub fn near_share(&self) -> Option<f64> {
+        (self.requests > 0).then(|| self.near_ceiling as f64 / self.requests as f64)
+    }
+
+    /// At least [`MIN_REQUESTS`] requests, and [`CONSISTENT_SHARE`] of them near the
+    /// ceiling
+    pub fn is_consistently_near(&self) -> bool {
+        self.requests >= MIN_REQUESTS
+            && self
+                .near_share()
+                .is_some_and(|share| share >= CONSISTENT_SHARE)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DayContext {
+    /// Local date
+    pub day: NaiveDate,
+    #[serde(flatten)]
+    pub utilization: Utilization,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SessionContext {
+    pub session_id: String,
+    /// Model of the session's latest request
+    pub model: Option<String>,
+    #[serde(flatten)]
+    pub utilization: Utilization,
+    /// Utilization of the session's latest request
+    pub last_pct: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ContextUsage {
+    #[serde(flatten)]
+    pub totals: Utilization,
+    /// Requests whose model's context window is unknown
+    pub unknown_window: u64,
+    /// Oldest first
+    pub days: Vec<DayContext>,
+    /// Most requests near the ceiling first
+    pub sessions: Vec<SessionContext>,
+}
+
+impl ContextUsage {
+    pub fn session(&self, session_id: &str) -> Option<&SessionContext> {
+        self.sessions.iter().find(|s| s.session_id == session_id)
+    }
+
+    /// Sessions [consistently near](Utilization::is_consistently_near) the ceiling
+    pub fn sessions_near_ceiling(&self) -> impl Iterator<Item = &SessionContext> {
+        self.sessions
+            .iter()
+            .filter(|s| s.utilization.is_consistently_near())
+    }
+}
+
+/// Utilization over `samples` (oldest first): in total, per local day and per session
+pub fn analyze(samples: &[ContextSample]) -> ContextUsage {
+    let mut usage = ContextUsage::default();
+    let mut days: BTreeMap<NaiveDate, Utilization> = BTreeMap::new();
+    let mut sessions: HashMap<&str, SessionContext> = HashMap::new();
+    for sample in samples {
+        let Some(pct) = utilization(sample) else {
+            usage.unknown_window += 1;
+            continue;
+        };
+        usage.totals.add(pct);
+        days.entry(sample.timestamp.with_timezone(&Local).date_naive())
+            .or_default()
+            .add(pct);
+        if let Some(id) = sample.session_id.as_deref() {
+            let session = sessions.entry(id).or_insert_with(|| SessionContext {
+                session_id: id.to_string(),
+                model: None,
+                utilization: Utilization::default(),
+                last_pct: 0.0,
+            });
+            session.utilization.add(pct);
+            session.last_pct = pct;
+            if sample.model.is_some() {
+                session.model = sample.model.clone();
+            }
+        }
+    }
+    usage.days = days
+        .into_iter()
+        .map(|(day, utilization)| DayContext { day, utilization })
+        .collect();
+    usage.sessions = sessions.into_values().collect();
+    usage.sessions.sort_by(|a, b| {
+        b.utilization
+            .near_ceiling
+            .cmp(&a.utilization.near_ceiling)
+            .then(b.utilization.avg_pct.total_cmp(&a.utilization.avg_pct))
+            .then(a.session_id.cmp(&b.session_id))
+    });
+    usage
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, TimeZone, Utc};
+
+    fn sample(at: DateTime<Utc>, session: &str, model: &str, tokens: u64) -> ContextSample {
+        ContextSample {
+            timestamp: at,
+            session_id: Some(session.to_string()),
+            model: Some(model.to_string()),
+            context_tokens: tokens,
+            context_window: None,
+        }
+    }
+
+    #[test]
+    fn test_window_of() {
+        assert_eq!(window_of("claude-sonnet-4-5-20250929"), Some(200_000));
+        assert_eq!(window_of("GPT-5-Codex"), Some(400_000));
+        assert_eq!(window_of("gpt-4o-mini"), Some(128_000));
+        assert_eq!(window_of("gemini-2.5-pro"), Some(1_048_576));
+        assert_eq!(window_of("mystery-model"), None);
+
+        let mut with_window = sample(Utc::now(), "a", "mystery-model", 50_000);
+        assert_eq!(utilization(&with_window), None);
+        with_window.context_window = Some(100_000);
+        assert_eq!(utilization(&with_window), Some(50.0));
+    }
+
+    #[test]
+    fn test_analyze() {
+        let at = |hour: u32| Utc.with_ymd_and_hms(2025, 6, 1, hour, 0, 0).unwrap();
+        let model = "claude-sonnet-4-5";
+        let mut samples: Vec<ContextSample> = [20_000, 170_000, 180_000, 190_000, 160_000, 100_000]
+            .into_iter()
+            .map(|tokens| sample(at(10), "full", model, tokens))
+            .collect();
+        samples.extend((0..6).map(|_| sample(at(11), "light", model, 40_000)));
+        samples.push(sample(at(12), "light", "mystery-model", 40_000));
+
+        let usage = analyze(&samples);
+        assert_eq!(usage.totals.requests, 12);
+        assert_eq!(usage.totals.near_ceiling, 4);
+        assert_eq!(usage.unknown_window, 1);
+        assert!((usage.totals.peak_pct - 95.0).abs() < 1e-9);
+
+        let full = &usage.sessions[0];
+        assert_eq!(full.session_id, "full");
+        assert_eq!(full.utilization.requests, 6);
+        assert!((full.utilization.avg_pct - 68.333_333).abs() < 1e-3);
+        assert_eq!(full.last_pct, 50.0);
+        assert!(full.utilization.is_consistently_near());
+
+        let light = usage.session("light").unwrap();
+        assert_eq!(light.utilization.near_share(), Some(0.0));
+        assert!(!light.utilization.is_consistently_near());
+        assert_eq!(usage.sessions_near_ceiling().count(), 1);
+
+        // Too few requests to judge
+        let short = analyze(&samples[1..4]);
+        assert!(!short.sessions[0].utilization.is_consistently_near());
+    }
+}
@@ -0,0 +1,343 @@
+//! `agenttop daemon` (a detached headless receiver tracked by a pid file) and
+//! `agenttop service` (a systemd user unit or launchd agent that starts it at login).
+
+use anyhow::{Context, Result};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::config;
+use crate::storage;
+
+/// systemd unit / launchd label
+const SERVICE_NAME: &str = "agenttop";
+const LAUNCHD_LABEL: &str = "dev.agenttop";
+
+pub fn pid_path() -> Result<PathBuf> {
+    Ok(config::data_dir()?.join("agenttop.pid"))
+}
+
+pub fn log_path() -> Result<PathBuf> {
+    Ok(config::data_dir()?.join("agenttop-daemon.log"))
+}
+
+/// Whether a process with this pid is running
+fn is_running(pid: u32) -> bool {
+    if cfg!(windows) {
+        Command::new("tasklist")
+            .args(["/FI", &format!("PID eq {}", pid), "/NH"])
+            .output()
+            .is_ok_and(|out| String::from_utf8_lossy(&out.stdout).contains(&pid.to_string()))
+    } else {
+        Command::new("kill")
+            .args(["-0", &pid.to_string()])
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+    }
+}
+
+/// The daemon's pid, if the pid file names a running process
+pub fn running_pid() -> Result<Option<u32>> {
+    let path = pid_path()?;
+    let Ok(content) = std::fs::read_to_string(&path) else {
+        return Ok(None);
+    };
+    Ok(content.trim().parse().ok().filter(|pid| is_running(*pid)))
+}
+
+/// Start `agenttop --headless <args>` receiving on `bind` in the background and record
+/// its pid
+pub fn start(headless_args: &[String], bind: SocketAddr) -> Result<u32> {
+    if let Some(pid) = running_pid()? {
+        anyhow::bail!("agenttop daemon is already running (pid {})", pid);
+    }
+    let exe = std::env::current_exe().context("Could not locate the agenttop binary")?;
+    let log = log_path()?;
+    if let Some(dir) = log.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let log_file = std::fs::File::create(&log)
+        .with_context(|| format!("Could not create {}", log.display()))?;
+
+    let args = headless_command(&exe, headless_args, &storage::db_path()?, bind);
+    let mut command = Command::new(&args[0]);
+    command
+        .args(&args[1..])
+        .stdin(Stdio::null())
+        .stdout(log_file.try_clone()?)
+        .stderr(log_file);
+    // Own process group, so the terminal's hangup does not reach it
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    let child = command.spawn().context("Could not start the daemon")?;
+
+    std::fs::write(pid_path()?, child.id().to_string())?;
+    Ok(child.id())
+}
+
+/// Stop the running daemon; false if none was running
+pub fn stop() -> Result<bool> {
+    let Some(pid) = running_pid()? else {
+        let _ = std::fs::remove_file(pid_path()?);
+        return Ok(false);
+    };
+    let status = if cfg!(windows) {
+        Command::new("taskkill")
+            .args(["/PID", &pid.to_string(), "/F"])
+            .status()?
+    } else {
+        Command::new("kill").arg(pid.to_string()).status()?
+    };
+    if !status.success() {
+        anyhow::bail!("Could not stop pid {}", pid);
+    }
+    std::fs::remove_file(pid_path()?)?;
+    Ok(true)
+}
+
+/// `exe --headless`, the database and the listen address, then `headless_args`: the
+/// receiver keeps using them whatever the environment it is started in
+fn headless_command(
+    exe: &Path,
+    headless_args: &[String],
+    db_path: &Path,
+    bind: SocketAddr,
+) -> Vec<String> {
+    let mut args = vec![
+        exe.display().to_string(),
+        "--headless".to_string(),
+        "--db".to_string(),
+        db_path.display().to_string(),
+        "--bind".to_string(),
+        bind.to_string(),
+    ];
+    args.extend(headless_args.iter().cloned());
+    args
+}
+
+/// systemd user unit running the headless receiver
+pub fn systemd_unit(
+    exe: &Path,
+    headless_args: &[String],
+    db_path: &Path,
+    bind: SocketAddr,
+) -> String {
+    let exec: Vec<String> = headless_command(exe, headless_args, db_path, bind)
+        .iter()
+        .map(|arg| systemd_quote(arg))
+        .collect();
+    format!(
+        "[Unit]\n\
+         Description=agenttop OTLP receiver for AI coding agents\n\
+         After=network.target\n\
+         \n\
+         [Service]\n\
+         # Receives OTLP on {addr}; metrics are stored in {db}\n\
+         ExecStart={exec}\n\
+         Restart=on-failure\n\
+         RestartSec=5\n\
+         \n\
+         [Install]\n\
+         WantedBy=default.target\n",
+        addr = bind,
+        db = db_path.display().to_string().replace('\n', " "),
+        exec = exec.join(" "),
+    )
+}
+
+/// An `ExecStart` argument: `%` (specifiers) and `$` (variables) doubled, and
+/// double-quoted with `\` and `"` escaped when it has spaces or quotes
+fn systemd_quote(arg: &str) -> String {
+    let arg = arg.replace('%', "%%").replace('$', "$$");
+    if !arg.is_empty()
+        && !arg
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, '"' | '\'' | '\\' | ';'))
+    {
+        return arg;
+    }
+    let mut quoted = String::from('"');
+    for c in arg.chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// launchd agent running the headless receiver at login
+pub fn launchd_plist(
+    exe: &Path,
+    headless_args: &[String],
+    db_path: &Path,
+    bind: SocketAddr,
+    log: &Path,
+) -> String {
+    let args: String = headless_command(exe, headless_args, db_path, bind)
+        .iter()
+        .map(|arg| format!("        <string>{}</string>\n", xml_escape(arg)))
+        .collect();
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<!-- agenttop receives OTLP on {addr}; metrics are stored in {db} -->
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{label}</string>
+    <key>ProgramArguments</key>
+    <array>
+{args}    </array>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <true/>
+    <key>StandardOutPath</key>
+    <string>{log}</string>
+    <key>StandardErrorPath</key>
+    <string>{log}</string>
+</dict>
+</plist>
+"#,
+        addr = bind,
+        db = xml_escape(&db_path.display().to_string()),
+        label = LAUNCHD_LABEL,
+        log = xml_escape(&log.display().to_string()),
+    )
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Where the service definition for this platform lives
+pub fn service_path() -> Result<PathBuf> {
+    let home = dirs::home_dir().context("Could not determine home directory")?;
+    if cfg!(target_os = "macos") {
+        Ok(home
+            .join("Library/LaunchAgents")
+            .join(format!("{}.plist", LAUNCHD_LABEL)))
+    } else if cfg!(target_os = "linux") {
+        Ok(dirs::config_dir()
+            .unwrap_or_else(|| home.join(".config"))
+            .join("systemd/user")
+            .join(format!("{}.service", SERVICE_NAME)))
+    } else {
+        anyhow::bail!("Services are supported on Linux (systemd) and macOS (launchd)")
+    }
+}
+
+/// The service definition for this platform, receiving on `bind`
+pub fn service_definition(headless_args: &[String], bind: SocketAddr) -> Result<String> {
+    let exe = std::env::current_exe().context("Could not locate the agenttop binary")?;
+    let db = storage::db_path()?;
+    let db = std::path::absolute(&db).unwrap_or(db);
+    if cfg!(target_os = "macos") {
+        Ok(launchd_plist(&exe, headless_args, &db, bind, &log_path()?))
+    } else {
+        Ok(systemd_unit(&exe, headless_args, &db, bind))
+    }
+}
+
+/// Write the service definition; returns its path and the commands that enable it
+pub fn install_service(headless_args: &[String], bind: SocketAddr) -> Result<(PathBuf, String)> {
+    let path = service_path()?;
+    let definition = service_definition(headless_args, bind)?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(&path, definition)
+        .with_context(|| format!("Could not write {}", path.display()))?;
+    let enable = if cfg!(target_os = "macos") {
+        format!("launchctl load -w {}", path.display())
+    } else {
+        format!(
+            "systemctl --user daemon-reload && systemctl --user enable --now {}",
+            SERVICE_NAME
+        )
+    };
+    Ok((path, enable))
+}
+
+/// Remove the service definition; returns its path and the commands that stop it first
+pub fn uninstall_service() -> Result<(PathBuf, String)> {
+    let path = service_path()?;
+    let disable = if cfg!(target_os = "macos") {
+        format!("launchctl unload -w {}", path.display())
+    } else {
+        format!("systemctl --user disable --now {}", SERVICE_NAME)
+    };
+    if path.exists() {
+        std::fs::remove_file(&path)?;
+    }
+    Ok((path, disable))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_systemd_unit() {
+        let bind: SocketAddr = "127.0.0.1:4318".parse().unwrap();
+        let unit = systemd_unit(
+            Path::new("/usr/local/bin/agenttop"),
+            &["--api".to_string()],
+            Path::new("/home/me/.local/share/agenttop/metrics.duckdb"),
+            bind,
+        );
+        assert!(unit.contains(
+            "ExecStart=/usr/local/bin/agenttop --headless \
+             --db /home/me/.local/share/agenttop/metrics.duckdb --bind 127.0.0.1:4318 --api\n"
+        ));
+        assert!(unit.contains("Receives OTLP on 127.0.0.1:4318"));
+        assert!(unit.contains("WantedBy=default.target"));
+
+        let unit = systemd_unit(
+            Path::new("/home/me/my tools/agenttop"),
+            &[],
+            Path::new("/home/me/100% \"real\" data/$HOME\\metrics.duckdb"),
+            "0.0.0.0:4319".parse().unwrap(),
+        );
+        assert!(unit.contains(
+            "ExecStart=\"/home/me/my tools/agenttop\" --headless \
+             --db \"/home/me/100%% \\\"real\\\" data/$$HOME\\\\metrics.duckdb\" --bind 0.0.0.0:4319\n"
+        ));
+    }
+
+    #[test]
+    fn test_systemd_quote() {
+        assert_eq!(systemd_quote("--api"), "--api");
+        assert_eq!(systemd_quote(""), "\"\"");
+        assert_eq!(systemd_quote("a b"), "\"a b\"");
+        assert_eq!(systemd_quote("it's"), "\"it's\"");
+        assert_eq!(systemd_quote("50%"), "50%%");
+        assert_eq!(systemd_quote("a\\b"), "\"a\\\\b\"");
+    }
+
+    #[test]
+    fn test_launchd_plist() {
+        let plist = launchd_plist(
+            Path::new("/opt/homebrew/bin/agenttop"),
+            &[],
+            Path::new("/Users/me/db & co/metrics.duckdb"),
+            "127.0.0.1:4318".parse().unwrap(),
+            Path::new("/tmp/agenttop.log"),
+        );
+        assert!(plist.contains("<string>/opt/homebrew/bin/agenttop</string>"));
+        assert!(plist.contains("<string>--headless</string>"));
+        assert!(plist.contains("<string>/Users/me/db &amp; co/metrics.duckdb</string>"));
+        assert!(plist.contains("<string>127.0.0.1:4318</string>"));
+        assert!(plist.contains("<string>dev.agenttop</string>"));
+    }
+}
@@ -0,0 +1,190 @@
+//! Email digest of the previous day or week (spend, tokens, top tools, top errors),
+//! configured under `digest` in the config file, for people who want a passive summary
+//! instead of watching the dashboard.
+//!
+//! The body is the `agenttop report` for the period; `agenttop digest` prints it, and
+//! `agenttop digest --send` mails it now (e.g. from cron).
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Local, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::budget::BudgetPeriod;
+use crate::storage::StorageHandle;
+use crate::tui::app::{TimeFilter, format_usd};
+
+pub mod smtp;
+
+use smtp::{Email, SmtpConfig};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DigestConfig {
+    /// Covered period, sent once it is over: daily, weekly (Monday to Sunday) or monthly
+    #[serde(default = "default_period")]
+    pub period: BudgetPeriod,
+    /// Local hour of the day the digest is sent
+    #[serde(default = "default_hour")]
+    pub hour: u32,
+    pub from: String,
+    pub to: Vec<String>,
+    pub smtp: SmtpConfig,
+}
+
+fn default_period() -> BudgetPeriod {
+    BudgetPeriod::Daily
+}
+
+fn default_hour() -> u32 {
+    8
+}
+
+/// The last complete period before `now`, as `(since, until)`
+pub fn previous_period(
+    period: BudgetPeriod,
+    now: DateTime<Local>,
+) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let until = period.start(now)?;
+    let since = period.start((until - Duration::hours(1)).with_timezone(&Local))?;
+    Some((since, until))
+}
+
+/// The first send time after `now`: `hour` o'clock on the first day of a period
+pub fn next_send(period: BudgetPeriod, hour: u32, now: DateTime<Local>) -> Option<DateTime<Utc>> {
+    let at_hour = |start: DateTime<Utc>| {
+        start
+            .with_timezone(&Local)
+            .date_naive()
+            .and_hms_opt(hour.min(23), 0, 0)?
+            .and_local_timezone(Local)
+            .earliest()
+            .map(|time| time.with_timezone(&Utc))
+    };
+    let current = at_hour(period.start(now)?)?;
+    if current > now {
+        return Some(current);
+    }
+    // Far enough past the start of this period to be inside the next one
+    let step = match period {
+        BudgetPeriod::Daily => Duration::hours(36),
+        BudgetPeriod::Weekly => Duration::days(8),
+        BudgetPeriod::Monthly => Duration::days(32),
+    };
+    let next = period.start((period.start(now)? + step).with_timezone(&Local))?;
+    at_hour(next)
+}
+
+/// Subject and body of the digest for the period before `now`
+pub fn render(
+    storage: &StorageHandle,
+    period: BudgetPeriod,
+    now: DateTime<Local>,
+) -> Result<(String, String)> {
+    let (since, until) = previous_period(period, now)
+        .ok_or_else(|| anyhow::anyhow!("Could not compute the previous period"))?;
+    let filter = TimeFilter::Range {
+        since: Some(since),
+        until: Some(until),
+    };
+    let cost = storage.get_total_cost(Some(since), Some(until))?;
+    let name = match period {
+        BudgetPeriod::Daily => "daily",
+        BudgetPeriod::Weekly => "weekly",
+        BudgetPeriod::Monthly => "monthly",
+    };
+    let subject = format!(
+        "agenttop {} digest · {}: {}",
+        name,
+        filter.label(),
+        format_usd(cost)
+    );
+    Ok((subject, crate::report::render(storage, &filter)?))
+}
+
+/// Mail the digest for the period before `now`
+pub fn send(storage: &StorageHandle, config: &DigestConfig, now: DateTime<Local>) -> Result<()> {
+    let (subject, body) = render(storage, config.period, now)?;
+    smtp::send(
+        &config.smtp,
+        &Email {
+            from: config.from.clone(),
+            to: config.to.clone(),
+            subject,
+            body,
+        },
+    )
+}
+
+/// Mail the digest at each configured time on a background thread
+pub fn spawn(storage: StorageHandle, config: DigestConfig) {
+    std::thread::spawn(move || {
+        loop {
+            let Some(due) = next_send(config.period, config.hour, Local::now()) else {
+                tracing::warn!("Could not schedule the email digest");
+                return;
+            };
+            tracing::info!(
+                "Next email digest to {} at {}",
+                config.to.join(", "),
+                due.with_timezone(&Local).format("%Y-%m-%d %H:%M")
+            );
+            // Sleep in short steps so a suspended laptop sends soon after waking
+            while Utc::now() < due {
+                std::thread::sleep(std::time::Duration::from_secs(60));
+            }
+            match send(&storage, &config, Local::now()) {
+                Ok(()) => tracing::info!("Sent email digest to {}", config.to.join(", ")),
+                Err(e) => tracing::warn!("Could not send email digest: {:#}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn local(y: i32, m: u32, d: u32, h: u32, min: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(y, m, d, h, min, 0).unwrap()
+    }
+
+    #[test]
+    fn test_previous_period() {
+        // Thursday
+        let now = local(2025, 1, 16, 10, 30);
+        let (since, until) = previous_period(BudgetPeriod::Daily, now).unwrap();
+        assert_eq!(since, local(2025, 1, 15, 0, 0).with_timezone(&Utc));
+        assert_eq!(until, local(2025, 1, 16, 0, 0).with_timezone(&Utc));
+
+        let (since, until) = previous_period(BudgetPeriod::Weekly, now).unwrap();
+        assert_eq!(since, local(2025, 1, 6, 0, 0).with_timezone(&Utc));
+        assert_eq!(until, local(2025, 1, 13, 0, 0).with_timezone(&Utc));
+
+        let (since, until) = previous_period(BudgetPeriod::Monthly, now).unwrap();
+        assert_eq!(since, local(2024, 12, 1, 0, 0).with_timezone(&Utc));
+        assert_eq!(until, local(2025, 1, 1, 0, 0).with_timezone(&Utc));
+    }
+
+    #[test]
+    fn test_next_send() {
+        let utc = |time: DateTime<Local>| time.with_timezone(&Utc);
+        // Before the hour: today; after it: tomorrow
+        assert_eq!(
+            next_send(BudgetPeriod::Daily, 8, local(2025, 1, 16, 7, 0)),
+            Some(utc(local(2025, 1, 16, 8, 0)))
+        );
+        assert_eq!(
+            next_send(BudgetPeriod::Daily, 8, local(2025, 1, 16, 8, 0)),
+            Some(utc(local(2025, 1, 17, 8, 0)))
+        );
+        // Weekly digests go out on Mondays
+        assert_eq!(
+            next_send(BudgetPeriod::Weekly, 9, local(2025, 1, 16, 10, 0)),
+            Some(utc(local(2025, 1, 20, 9, 0)))
+        );
+        assert_eq!(
+            next_send(BudgetPeriod::Monthly, 8, local(2025, 1, 31, 23, 0)),
+            Some(utc(local(2025, 2, 1, 8, 0)))
+        );
+    }
+}
@@ -0,0 +1,362 @@
+//! Just enough SMTP to hand one plain-text message to a submission server: implicit TLS
+//! or STARTTLS (through the rustls that ureq already links), AUTH PLAIN, one message.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::Duration;
+use ureq::rustls;
+use ureq::rustls::pki_types::CertificateDer;
+use ureq::rustls::pki_types::pem::PemObject;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// CA bundles of common Linux distributions and macOS, after `SSL_CERT_FILE`
+const CA_BUNDLES: &[&str] = &[
+    "/etc/ssl/certs/ca-certificates.crt",
+    "/etc/pki/tls/certs/ca-bundle.crt",
+    "/etc/ssl/ca-bundle.pem",
+    "/etc/ssl/cert.pem",
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SmtpSecurity {
+    /// Plain connection upgraded with STARTTLS (port 587)
+    #[default]
+    Starttls,
+    /// TLS from the start (port 465)
+    Tls,
+    /// No encryption, e.g. a relay on localhost (port 25)
+    None,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SmtpConfig {
+    pub host: String,
+    /// Default depends on `security`: 587, 465 or 25
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub security: SmtpSecurity,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+}
+
+impl SmtpConfig {
+    fn port(&self) -> u16 {
+        self.port.unwrap_or(match self.security {
+            SmtpSecurity::Starttls => 587,
+            SmtpSecurity::Tls => 465,
+            SmtpSecurity::None => 25,
+        })
+    }
+}
+
+/// A plain-text email
+#[derive(Debug, Clone, PartialEq)]
+pub struct Email {
+    pub from: String,
+    pub to: Vec<String>,
+    pub subject: String,
+    pub body: String,
+}
+
+impl Email {
+    /// The message as sent after DATA: headers, CRLF line endings, dot-stuffed lines
+    /// and the terminating `.` line
+    fn to_data(&self, date: DateTime<Local>) -> String {
+        let mut data = format!(
+            "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nMIME-Version: 1.0\r\n\
+             Content-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: 8bit\r\n\r\n",
+            self.from,
+            self.to.join(", "),
+            encode_header(&self.subject),
+            date.to_rfc2822()
+        );
+        for line in self.body.lines() {
+            if line.starts_with('.') {
+                data.push('.');
+            }
+            data.push_str(line);
+            data.push_str("\r\n");
+        }
+        data.push_str(".\r\n");
+        data
+    }
+}
+
+/// RFC 2047 encoded-word for header values that are not plain ASCII
+fn encode_header(value: &str) -> String {
+    if value.is_ascii() {
+        value.to_string()
+    } else {
+        format!("=?UTF-8?B?{}?=", base64(value.as_bytes()))
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | (u32::from(*b) << (16 - 8 * i)));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i)) as usize & 63] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+trait Stream: Read + Write + Send {}
+impl<T: Read + Write + Send> Stream for T {}
+
+/// Trusted roots from the system CA bundle
+fn root_store() -> Result<rustls::RootCertStore> {
+    let path = std::env::var("SSL_CERT_FILE")
+        .ok()
+        .or_else(|| {
+            CA_BUNDLES
+                .iter()
+                .find(|path| std::path::Path::new(path).exists())
+                .map(|path| path.to_string())
+        })
+        .context("No CA certificate bundle found; set SSL_CERT_FILE")?;
+    let pem = std::fs::read(&path).with_context(|| format!("Could not read {}", path))?;
+    let mut roots = rustls::RootCertStore::empty();
+    let (added, _) = roots.add_parsable_certificates(
+        CertificateDer::pem_slice_iter(&pem).filter_map(|cert| cert.ok()),
+    );
+    if added == 0 {
+        anyhow::bail!("No certificates in {}", path);
+    }
+    Ok(roots)
+}
+
+fn start_tls(host: &str, stream: TcpStream) -> Result<Box<dyn Stream>> {
+    let config = rustls::ClientConfig::builder_with_provider(
+        rustls::crypto::ring::default_provider().into(),
+    )
+    .with_safe_default_protocol_versions()?
+    .with_root_certificates(root_store()?)
+    .with_no_client_auth();
+    let name = rustls::pki_types::ServerName::try_from(host.to_string())
+        .with_context(|| format!("Invalid SMTP host name {}", host))?;
+    let connection = rustls::ClientConnection::new(Arc::new(config), name)?;
+    Ok(Box::new(rustls::StreamOwned::new(connection, stream)))
+}
+
+/// One SMTP conversation
+struct Session {
+    stream: Box<dyn Stream>,
+}
+
+impl Session {
+    /// Read a (possibly multi-line) reply and check its code is `expected`
+    fn expect(&mut self, expected: &[u16]) -> Result<String> {
+        let mut reply = String::new();
+        loop {
+            let mut line = Vec::new();
+            let mut byte = [0u8];
+            while !line.ends_with(b"\r\n") {
+                if self.stream.read(&mut byte)? == 0 {
+                    anyhow::bail!("SMTP server closed the connection");
+                }
+                line.push(byte[0]);
+            }
+            let line = String::from_utf8_lossy(&line);
+            reply.push_str(&line);
+            // "250-..." continues, "250 ..." is the last line
+            if line.as_bytes().get(3) != Some(&b'-') {
+                break;
+            }
+        }
+        let code: u16 = reply.get(..3).and_then(|c| c.parse().ok()).unwrap_or(0);
+        if !expected.contains(&code) {
+            anyhow::bail!("SMTP server replied: {}", reply.trim_end());
+        }
+        Ok(reply)
+    }
+
+    fn command(&mut self, command: &str, expected: &[u16]) -> Result<String> {
+        self.stream.write_all(command.as_bytes())?;
+        self.stream.write_all(b"\r\n")?;
+        self.stream.flush()?;
+        self.expect(expected)
+    }
+}
+
+/// Deliver `email` through the server in `config`
+pub fn send(config: &SmtpConfig, email: &Email) -> Result<()> {
+    let address = format!("{}:{}", config.host, config.port());
+    let addr = address
+        .to_socket_addrs()
+        .with_context(|| format!("Invalid SMTP server {}", address))?
+        .next()
+        .with_context(|| format!("{} did not resolve", address))?;
+    let tcp = TcpStream::connect_timeout(&addr, TIMEOUT)
+        .with_context(|| format!("Could not connect to SMTP server {}", address))?;
+    tcp.set_read_timeout(Some(TIMEOUT))?;
+    tcp.set_write_timeout(Some(TIMEOUT))?;
+
+    let hello = format!(
+        "EHLO {}",
+        std::env::var("HOSTNAME").unwrap_or_else(|_| "localhost".to_string())
+    );
+    let mut session = match config.security {
+        SmtpSecurity::Tls => Session {
+            stream: start_tls(&config.host, tcp)?,
+        },
+        SmtpSecurity::Starttls => {
+            let mut plain = Session {
+                stream: Box::new(tcp.try_clone()?),
+            };
+            plain.expect(&[220])?;
+            plain.command(&hello, &[250])?;
+            plain.command("STARTTLS", &[220])?;
+            let mut session = Session {
+                stream: start_tls(&config.host, tcp)?,
+            };
+            session.command(&hello, &[250])?;
+            session
+        }
+        SmtpSecurity::None => Session {
+            stream: Box::new(tcp),
+        },
+    };
+    if config.security != SmtpSecurity::Starttls {
+        session.expect(&[220])?;
+        session.command(&hello, &[250])?;
+    }
+
+    if let Some(username) = &config.username {
+        let password = config.password.as_deref().unwrap_or_default();
+        let credentials = base64(format!("\0{}\0{}", username, password).as_bytes());
+        session
+            .command(&format!("AUTH PLAIN {}", credentials), &[235])
+            .context("SMTP authentication failed")?;
+    }
+    session.command(&format!("MAIL FROM:<{}>", email.from), &[250])?;
+    for to in &email.to {
+        session.command(&format!("RCPT TO:<{}>", to), &[250, 251])?;
+    }
+    session.command("DATA", &[354])?;
+    session
+        .stream
+        .write_all(email.to_data(Local::now()).as_bytes())?;
+    session.expect(&[250])?;
+    let _ = session.command("QUIT", &[221]);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"\0user\0pass"), "AHVzZXIAcGFzcw==");
+    }
+
+    #[test]
+    fn test_to_data() {
+        let email = Email {
+            from: "agenttop@example.com".to_string(),
+            to: vec!["a@example.com".to_string(), "b@example.com".to_string()],
+            subject: "Digest · $1.00".to_string(),
+            body: "Totals\n.hidden\nend".to_string(),
+        };
+        let data = email.to_data(Local::now());
+        assert!(data.contains("To: a@example.com, b@example.com\r\n"));
+        assert!(data.contains("Subject: =?UTF-8?B?RGlnZXN0IMK3ICQxLjAw?=\r\n"));
+        assert!(data.ends_with("\r\n\r\nTotals\r\n..hidden\r\nend\r\n.\r\n"));
+    }
+
+    /// A whole conversation with a fake server that accepts everything
+    #[test]
+    fn test_send_plain() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            let mut received = Vec::new();
+            writer.write_all(b"220 test ESMTP\r\n").unwrap();
+            let mut in_data = false;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+                let line = line.trim_end().to_string();
+                let reply: &[u8] = if in_data {
+                    if line == "." {
+                        in_data = false;
+                        b"250 queued\r\n"
+                    } else {
+                        b""
+                    }
+                } else if line.starts_with("EHLO") {
+                    b"250-test\r\n250 AUTH PLAIN\r\n"
+                } else if line.starts_with("AUTH") {
+                    b"235 ok\r\n"
+                } else if line == "DATA" {
+                    in_data = true;
+                    b"354 go ahead\r\n"
+                } else if line == "QUIT" {
+                    writer.write_all(b"221 bye\r\n").unwrap();
+                    received.push(line);
+                    break;
+                } else {
+                    b"250 ok\r\n"
+                };
+                writer.write_all(reply).unwrap();
+                received.push(line);
+            }
+            received
+        });
+
+        let config = SmtpConfig {
+            host: "127.0.0.1".to_string(),
+            port: Some(port),
+            security: SmtpSecurity::None,
+            username: Some("user".to_string()),
+            password: Some("pass".to_string()),
+        };
+        let email = Email {
+            from: "agenttop@example.com".to_string(),
+            to: vec!["lead@example.com".to_string()],
+            subject: "agenttop digest".to_string(),
+            body: "Totals".to_string(),
+        };
+        send(&config, &email).unwrap();
+
+        let received = server.join().unwrap();
+        assert!(received[0].starts_with("EHLO "));
+        assert_eq!(received[1], "AUTH PLAIN AHVzZXIAcGFzcw==");
+        assert_eq!(received[2], "MAIL FROM:<agenttop@example.com>");
+        assert_eq!(received[3], "RCPT TO:<lead@example.com>");
+        assert_eq!(received[4], "DATA");
+        assert!(received.contains(&"Subject: agenttop digest".to_string()));
+        assert!(received.contains(&"Totals".to_string()));
+        assert_eq!(received.last().unwrap(), "QUIT");
+    }
+}
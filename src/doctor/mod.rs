@@ -0,0 +1,371 @@
+//! End-to-end diagnostics printed by `agenttop doctor`.
+//!
+//! Each check reports pass / warn / fail with a suggested fix; the last one sends a
+//! synthetic OTLP log event through the local receiver and looks for it in storage.
+
+use anyhow::Result;
+use chrono::Utc;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::otlp::{self, ReceiverOptions};
+use crate::port::connect_addr;
+use crate::providers::PROVIDER_REGISTRY;
+use crate::self_metrics::SELF_METRICS;
+use crate::storage::{self, StorageHandle};
+
+/// Event name of the synthetic event sent through the receiver
+pub const DOCTOR_EVENT: &str = "agenttop.doctor";
+
+/// How long to wait for the receiver to start and for the event to be stored
+const WAIT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// Result of one diagnostic check
+#[derive(Debug, Clone, PartialEq)]
+pub struct Check {
+    pub name: String,
+    pub status: Status,
+    pub detail: String,
+    /// What to do about a warning or failure
+    pub fix: Option<String>,
+}
+
+impl Check {
+    fn pass(name: &str, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            status: Status::Pass,
+            detail: detail.into(),
+            fix: None,
+        }
+    }
+
+    fn warn(name: &str, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            status: Status::Warn,
+            detail: detail.into(),
+            fix: Some(fix.into()),
+        }
+    }
+
+    fn fail(name: &str, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            status: Status::Fail,
+            detail: detail.into(),
+            fix: Some(fix.into()),
+        }
+    }
+}
+
+/// Run all checks in order, with the receiver address from the settings
+pub async fn run(bind: SocketAddr) -> Vec<Check> {
+    let mut checks = provider_checks();
+    checks.extend(pipeline_checks(bind).await);
+    checks
+}
+
+/// Print the checks, one per line with the fix underneath
+pub fn render(checks: &[Check]) -> String {
+    let mut out = String::new();
+    for check in checks {
+        let mark = match check.status {
+            Status::Pass => "✓",
+            Status::Warn => "!",
+            Status::Fail => "✗",
+        };
+        out.push_str(&format!("{} {}: {}\n", mark, check.name, check.detail));
+        if let Some(fix) = &check.fix {
+            out.push_str(&format!("    → {}\n", fix));
+        }
+    }
+    let failed = checks.iter().filter(|c| c.status == Status::Fail).count();
+    let warned = checks.iter().filter(|c| c.status == Status::Warn).count();
+    if failed == 0 && warned == 0 {
+        out.push_str("\nAll checks passed.\n");
+    } else {
+        out.push_str(&format!("\n{} failed, {} warnings.\n", failed, warned));
+    }
+    out
+}
+
+/// `--setup` argument for a provider id
+fn setup_name(provider_id: &str) -> &str {
+    match provider_id {
+        "claude_code" => "claude",
+        "gemini_cli" => "gemini",
+        "qwen_code" => "qwen",
+        other => other,
+    }
+}
+
+/// Whether each provider's settings file sends telemetry to agenttop
+fn provider_checks() -> Vec<Check> {
+    let mut checks = Vec::new();
+    for provider in PROVIDER_REGISTRY.providers() {
+        let name = format!("{} settings", provider.name());
+        let path = provider
+            .settings_path()
+            .map(|p| p.display().to_string())
+            .unwrap_or_default();
+        let setup = if provider.supports_setup() {
+            format!("agenttop --setup {}", setup_name(provider.id()))
+        } else {
+            format!("add the OTLP exporter to {} by hand (see README)", path)
+        };
+        let check = match provider.is_configured() {
+            None => continue,
+            Some(true) => Check::pass(&name, format!("telemetry enabled in {}", path)),
+            Some(false) if !provider.settings_path().is_some_and(|p| p.exists()) => {
+                Check::warn(&name, format!("{} not found (not installed?)", path), setup)
+            }
+            Some(false) => Check::fail(
+                &name,
+                format!("{} does not send telemetry to agenttop", path),
+                format!("{}, then restart {}", setup, provider.name()),
+            ),
+        };
+        checks.push(check);
+    }
+    checks
+}
+
+/// Port, database and receiver → storage round trip
+async fn pipeline_checks(bind: SocketAddr) -> Vec<Check> {
+    let marker = format!("{}-{}", std::process::id(), Utc::now().timestamp_millis());
+
+    // If the port is taken, the event can only go to whoever holds it
+    if tokio::net::TcpListener::bind(bind).await.is_err() {
+        return match post_json(connect_addr(bind), "/v1/logs", &synthetic_event(&marker)).await {
+            Ok(200) => vec![
+                Check::pass(
+                    "Receiver port",
+                    format!("{} is served by a running agenttop", bind),
+                ),
+                Check::warn(
+                    "Storage round trip",
+                    "event accepted, but the database is held by the running instance",
+                    format!("look for a {} event in its Events view", DOCTOR_EVENT),
+                ),
+            ],
+            Ok(status) => vec![Check::fail(
+                "Receiver port",
+                format!("{} is in use by another program (HTTP {})", bind, status),
+                format!("stop the other OTLP collector or free port {}", bind.port()),
+            )],
+            Err(e) => vec![Check::fail(
+                "Receiver port",
+                format!("{} is in use but does not answer OTLP: {}", bind, e),
+                format!("stop the program holding port {}", bind.port()),
+            )],
+        };
+    }
+    let mut checks = vec![Check::pass("Receiver port", format!("{} is free", bind))];
+
+    let db_path = storage::db_path()
+        .map(|p| p.display().to_string())
+        .unwrap_or_default();
+    let storage = match StorageHandle::new() {
+        Ok(storage) => storage,
+        Err(e) => {
+            checks.push(Check::fail(
+                "Database",
+                format!("could not open {}: {:#}", db_path, e),
+                "check the data directory permissions, or stop the process using the database",
+            ));
+            return checks;
+        }
+    };
+    checks.push(match storage.get_log_events_after(None, 1) {
+        Ok(events) => match events.last() {
+            Some(event) => Check::pass(
+                "Database",
+                format!(
+                    "{} (last event {})",
+                    db_path,
+                    event
+                        .timestamp
+                        .with_timezone(&chrono::Local)
+                        .format("%Y-%m-%d %H:%M")
+                ),
+            ),
+            None => Check::warn(
+                "Database",
+                format!("{} has no events yet", db_path),
+                "run agenttop while your agent is working (restart the agent after setup)",
+            ),
+        },
+        Err(e) => Check::fail(
+            "Database",
+            format!("{} is unreadable: {:#}", db_path, e),
+            "move the database aside and let agenttop create a new one",
+        ),
+    });
+
+    checks.push(round_trip(storage, &marker, bind).await);
+    checks
+}
+
+/// Start a receiver, send the synthetic event and wait for it to be stored
+async fn round_trip(storage: StorageHandle, marker: &str, bind: SocketAddr) -> Check {
+    const NAME: &str = "Storage round trip";
+
+    let options = ReceiverOptions {
+        bind: Some(bind),
+        ..Default::default()
+    };
+    let receiver = tokio::spawn(otlp::start_receiver(storage.clone(), options));
+    let started = tokio::time::Instant::now();
+    while SELF_METRICS.receiver.snapshot().listen_addr.is_none() {
+        if receiver.is_finished() || started.elapsed() > WAIT {
+            receiver.abort();
+            return Check::fail(
+                NAME,
+                "the OTLP receiver did not start",
+                "run with RUST_LOG=agenttop=debug for details",
+            );
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    let check = match post_json(connect_addr(bind), "/v1/logs", &synthetic_event(marker)).await {
+        Ok(200) => {
+            let started = tokio::time::Instant::now();
+            loop {
+                let stored = storage.get_log_events_after(None, 20).is_ok_and(|events| {
+                    events
+                        .iter()
+                        .any(|e| e.attributes.get("doctor.id").map(String::as_str) == Some(marker))
+                });
+                if stored {
+                    break Check::pass(NAME, "synthetic event was received and stored");
+                }
+                if started.elapsed() > WAIT {
+                    break Check::fail(
+                        NAME,
+                        "the event was accepted but never stored",
+                        "check agenttop.log in the data directory for storage errors",
+                    );
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        }
+        Ok(status) => Check::fail(
+            NAME,
+            format!("the receiver rejected the event (HTTP {})", status),
+            "run with RUST_LOG=agenttop=debug for details",
+        ),
+        Err(e) => Check::fail(
+            NAME,
+            format!("could not reach the receiver: {}", e),
+            format!("check firewall rules for {}", bind),
+        ),
+    };
+    receiver.abort();
+    check
+}
+
+/// OTLP/JSON logs payload with a single `agenttop.doctor` event tagged with `marker`
+pub fn synthetic_event(marker: &str) -> String {
+    serde_json::json!({
+        "resourceLogs": [{
+            "scopeLogs": [{
+                "logRecords": [{
+                    "timeUnixNano": Utc::now().timestamp_nanos_opt().unwrap_or_default().to_string(),
+                    "attributes": [
+                        {"key": "event.name", "value": {"stringValue": DOCTOR_EVENT}},
+                        {"key": "doctor.id", "value": {"stringValue": marker}}
+                    ]
+                }]
+            }]
+        }]
+    })
+    .to_string()
+}
+
+/// POST a JSON body over plain HTTP/1.1, returning the response status code
+async fn post_json(addr: SocketAddr, path: &str, body: &str) -> Result<u16> {
+    let exchange = async {
+        let mut stream = tokio::net::TcpStream::connect(addr).await?;
+        let request = format!(
+            "POST {path} HTTP/1.1\r\nHost: {addr}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(request.as_bytes()).await?;
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await?;
+        anyhow::Ok(response)
+    };
+    let response = tokio::time::timeout(WAIT, exchange)
+        .await
+        .map_err(|_| anyhow::anyhow!("timed out"))??;
+
+    // "HTTP/1.1 200 OK"
+    String::from_utf8_lossy(&response)
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| anyhow::anyhow!("not an HTTP response"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_synthetic_event_parses() {
+        let events = otlp::parse_logs(synthetic_event("abc").as_bytes()).unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event_name.as_deref(), Some(DOCTOR_EVENT));
+        assert_eq!(
+            events[0].attributes.get("doctor.id").map(String::as_str),
+            Some("abc")
+        );
+    }
+
+    #[test]
+    fn test_render_summary() {
+        let checks = vec![
+            Check::pass("Receiver port", "127.0.0.1:4318 is free"),
+            Check::fail("Database", "locked", "stop the other process"),
+        ];
+        let out = render(&checks);
+        assert!(out.contains("✓ Receiver port: 127.0.0.1:4318 is free"));
+        assert!(out.contains("✗ Database: locked\n    → stop the other process"));
+        assert!(out.ends_with("1 failed, 0 warnings.\n"));
+    }
+
+    #[tokio::test]
+    async fn test_checks_configured_port() {
+        // Holds the port without answering HTTP
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let bind = listener.local_addr().unwrap();
+        let accept = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                drop(stream);
+            }
+        });
+
+        let checks = pipeline_checks(bind).await;
+        accept.abort();
+        assert_eq!(checks.len(), 1);
+        assert_eq!(checks[0].status, Status::Fail);
+        assert!(checks[0].detail.starts_with(&bind.to_string()));
+        assert!(
+            checks[0]
+                .fix
+                .as_ref()
+                .unwrap()
+                .ends_with(&bind.port().to_string())
+        );
+    }
+}
@@ -0,0 +1,166 @@
+//! Prompt-cache efficiency per model and per day, and the dollars it saved: each cache
+//! read priced at its model's input price minus its cache-read price (see
+//! [`crate::pricing::cache_savings_usd`]).
+
+use chrono::{Local, NaiveDate};
+
+use crate::pricing;
+use crate::storage::CacheUsage;
+
+/// Prompt tokens by how the cache served them
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CacheTotals {
+    pub input_tokens: u64,
+    pub cache_read_tokens: u64,
+    pub cache_creation_tokens: u64,
+    /// Estimated dollars saved; None when none of the tokens had a priced model
+    pub saved_usd: Option<f64>,
+}
+
+impl CacheTotals {
+    /// Percent of the prompt tokens read from the cache; None without prompt tokens
+    pub fn hit_rate(&self) -> Option<f64> {
+        let prompt = self.input_tokens + self.cache_read_tokens + self.cache_creation_tokens;
+        (prompt > 0).then(|| self.cache_read_tokens as f64 / prompt as f64 * 100.0)
+    }
+
+    fn add(&mut self, usage: &CacheUsage, saved_usd: Option<f64>) {
+        self.input_tokens += usage.input_tokens;
+        self.cache_read_tokens += usage.cache_read_tokens;
+        self.cache_creation_tokens += usage.cache_creation_tokens;
+        if let Some(saved) = saved_usd {
+            self.saved_usd = Some(self.saved_usd.unwrap_or(0.0) + saved);
+        }
+    }
+}
+
+/// One model's cache use over the whole range
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelCache {
+    /// "unknown" for tokens recorded without a model
+    pub model: String,
+    pub totals: CacheTotals,
+}
+
+/// All models' cache use on one local day
+#[derive(Debug, Clone, PartialEq)]
+pub struct DayCache {
+    pub day: NaiveDate,
+    pub totals: CacheTotals,
+}
+
+/// Cache use in a range, per model and per day
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CacheEfficiency {
+    /// Most cache reads first
+    pub models: Vec<ModelCache>,
+    /// Oldest first; days without prompt tokens are left out
+    pub days: Vec<DayCache>,
+    pub total: CacheTotals,
+}
+
+impl CacheEfficiency {
+    /// Sum hourly usage (see [`crate::storage::StorageHandle::get_cache_usage`]) per
+    /// model and per local day
+    pub fn from_usage(usage: &[CacheUsage]) -> Self {
+        let mut efficiency = Self::default();
+        for row in usage {
+            let model = row.model.as_deref().unwrap_or("unknown");
+            let saved_usd = pricing::lookup(model)
+                .map(|pricing| pricing::cache_savings_usd(row.cache_read_tokens, &pricing));
+            efficiency.total.add(row, saved_usd);
+
+            match efficiency.models.iter_mut().find(|m| m.model == model) {
+                Some(entry) => entry.totals.add(row, saved_usd),
+                None => {
+                    let mut totals = CacheTotals::default();
+                    totals.add(row, saved_usd);
+                    efficiency.models.push(ModelCache {
+                        model: model.to_string(),
+                        totals,
+                    });
+                }
+            }
+
+            let day = row.hour.with_timezone(&Local).date_naive();
+            match efficiency.days.iter_mut().find(|d| d.day == day) {
+                Some(entry) => entry.totals.add(row, saved_usd),
+                None => {
+                    let mut totals = CacheTotals::default();
+                    totals.add(row, saved_usd);
+                    efficiency.days.push(DayCache { day, totals });
+                }
+            }
+        }
+        efficiency.models.sort_by(|a, b| {
+            b.totals
+                .cache_read_tokens
+                .cmp(&a.totals.cache_read_tokens)
+                .then_with(|| a.model.cmp(&b.model))
+        });
+        efficiency.days.sort_by_key(|d| d.day);
+        efficiency
+    }
+
+    /// Cache reads of models with known pricing, i.e. the ones in `total.saved_usd`
+    pub fn priced_cache_read_tokens(&self) -> u64 {
+        self.models
+            .iter()
+            .filter(|m| m.totals.saved_usd.is_some())
+            .map(|m| m.totals.cache_read_tokens)
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn usage(hour: u32, model: Option<&str>, input: u64, read: u64) -> CacheUsage {
+        CacheUsage {
+            hour: Local
+                .with_ymd_and_hms(2025, 6, 1 + hour / 24, hour % 24, 0, 0)
+                .unwrap()
+                .with_timezone(&Utc),
+            model: model.map(str::to_string),
+            input_tokens: input,
+            cache_read_tokens: read,
+            cache_creation_tokens: 0,
+        }
+    }
+
+    #[test]
+    fn test_from_usage() {
+        let efficiency = CacheEfficiency::from_usage(&[
+            usage(9, Some("claude-sonnet-4-5"), 1_000_000, 1_000_000),
+            usage(10, Some("claude-opus-4-1"), 0, 100_000),
+            usage(10, None, 500_000, 2_000_000),
+            usage(33, Some("claude-sonnet-4-5"), 0, 3_000_000),
+        ]);
+
+        // Sonnet saves $2.70/M and Opus $13.50/M; tokens without a model save nothing
+        let saved = efficiency.total.saved_usd.unwrap();
+        assert!((saved - (4.0 * 2.70 + 0.1 * 13.50)).abs() < 1e-9, "{saved}");
+        assert_eq!(efficiency.priced_cache_read_tokens(), 4_100_000);
+
+        let models: Vec<&str> = efficiency.models.iter().map(|m| m.model.as_str()).collect();
+        assert_eq!(models, ["claude-sonnet-4-5", "unknown", "claude-opus-4-1"]);
+        assert_eq!(efficiency.models[0].totals.hit_rate(), Some(80.0));
+        assert_eq!(efficiency.models[1].totals.saved_usd, None);
+
+        assert_eq!(efficiency.days.len(), 2);
+        assert_eq!(
+            efficiency.days[0].day,
+            NaiveDate::from_ymd_opt(2025, 6, 1).unwrap()
+        );
+        assert_eq!(efficiency.days[0].totals.cache_read_tokens, 3_100_000);
+        assert_eq!(efficiency.days[1].totals.hit_rate(), Some(100.0));
+    }
+
+    #[test]
+    fn test_hit_rate_without_prompt_tokens() {
+        assert_eq!(CacheTotals::default().hit_rate(), None);
+        assert_eq!(CacheEfficiency::from_usage(&[]).total.saved_usd, None);
+    }
+}
@@ -0,0 +1,176 @@
+//! Per-session efficiency score, for comparing agent configurations and models.
+//!
+//! Four parts, each from 0 (bad) to 1 (good), are averaged with [`WEIGHTS`] into a
+//! score from 0 to 100:
+//!
+//! - tool success: share of tool calls that succeeded
+//! - cache reuse: share of the input tokens read from the prompt cache
+//! - retries: share of API requests that did not fail (and get retried)
+//! - cost per outcome: spend per accepted edit or commit, scored
+//!   `REFERENCE_COST / (REFERENCE_COST + cost)` so [`REFERENCE_COST_PER_OUTCOME`] scores 0.5
+//!
+//! A part without data (e.g. no tool calls) is left out and the other weights scaled
+//! up. Every [`SCORE_INTERVAL`] the sessions with events newer than their score are
+//! scored again and the result stored with the session (see
+//! [`StorageHandle::score_sessions`]).
+//!
+//! Prompt-cache efficiency per model and day is in [`cache`].
+
+pub mod cache;
+
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::storage::StorageHandle;
+
+/// How often sessions with new events are scored
+pub const SCORE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Sessions scored per interval, so a large backlog is worked through gradually
+pub const SCORE_BATCH: usize = 100;
+
+/// Spend per accepted edit or commit that scores half of the cost part
+pub const REFERENCE_COST_PER_OUTCOME: f64 = 0.5;
+
+/// Weights of tool success, cache reuse, retries and cost per outcome
+pub const WEIGHTS: [f64; 4] = [0.3, 0.2, 0.2, 0.3];
+
+/// Tools whose accepted calls count as edits
+pub const EDIT_TOOLS: &[&str] = &["Edit", "MultiEdit", "Write", "NotebookEdit"];
+
+/// What a session did, as far as the score is concerned
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct EfficiencyInputs {
+    pub tool_calls: u64,
+    pub tool_errors: u64,
+    pub api_calls: u64,
+    pub api_errors: u64,
+    pub input_tokens: u64,
+    pub cache_read_tokens: u64,
+    pub cache_creation_tokens: u64,
+    pub cost_usd: f64,
+    /// Calls of [`EDIT_TOOLS`] that were accepted
+    pub accepted_edits: u64,
+    pub commits: u64,
+}
+
+/// A session's score and the parts it was made from
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Efficiency {
+    /// 0 to 100, higher is better
+    pub score: f64,
+    /// Percent of tool calls that succeeded
+    pub success_rate: Option<f64>,
+    /// Percent of input tokens read from the prompt cache
+    pub cache_reuse: Option<f64>,
+    /// Percent of API requests that failed
+    pub retry_rate: Option<f64>,
+    /// Spend per accepted edit or commit
+    pub cost_per_outcome: Option<f64>,
+    /// Accepted edits plus commits
+    pub outcomes: u64,
+}
+
+impl Efficiency {
+    /// Score a session; None when it has no data for any part
+    pub fn score(inputs: &EfficiencyInputs) -> Option<Self> {
+        let ratio = |part: u64, whole: u64| (whole > 0).then(|| part as f64 / whole as f64);
+        let success = ratio(
+            inputs.tool_calls.saturating_sub(inputs.tool_errors),
+            inputs.tool_calls,
+        );
+        let cache = ratio(
+            inputs.cache_read_tokens,
+            inputs.input_tokens + inputs.cache_read_tokens + inputs.cache_creation_tokens,
+        );
+        let retries = ratio(inputs.api_errors, inputs.api_calls + inputs.api_errors);
+        let outcomes = inputs.accepted_edits + inputs.commits;
+        let cost_per_outcome = (outcomes > 0).then(|| inputs.cost_usd / outcomes as f64);
+        // Spending without getting anything accepted scores nothing
+        let cost = match cost_per_outcome {
+            Some(cost) => Some(REFERENCE_COST_PER_OUTCOME / (REFERENCE_COST_PER_OUTCOME + cost)),
+            None if inputs.cost_usd > 0.0 => Some(0.0),
+            None => None,
+        };
+
+        let parts = [success, cache, retries.map(|r| 1.0 - r), cost];
+        let (total, weight) = parts
+            .iter()
+            .zip(WEIGHTS)
+            .filter_map(|(part, weight)| part.map(|part| (part * weight, weight)))
+            .fold((0.0, 0.0), |(total, sum), (part, weight)| {
+                (total + part, sum + weight)
+            });
+        (weight > 0.0).then(|| Self {
+            score: total / weight * 100.0,
+            success_rate: success.map(|r| r * 100.0),
+            cache_reuse: cache.map(|r| r * 100.0),
+            retry_rate: retries.map(|r| r * 100.0),
+            cost_per_outcome,
+            outcomes,
+        })
+    }
+}
+
+/// Score sessions with new events every [`SCORE_INTERVAL`] on a background thread
+pub fn spawn(storage: StorageHandle) {
+    std::thread::spawn(move || {
+        loop {
+            std::thread::sleep(SCORE_INTERVAL);
+            if let Err(e) = storage.score_sessions(SCORE_BATCH) {
+                tracing::warn!("Could not score sessions: {:#}", e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score_parts() {
+        let inputs = EfficiencyInputs {
+            tool_calls: 10,
+            tool_errors: 2,
+            api_calls: 9,
+            api_errors: 1,
+            input_tokens: 100,
+            cache_read_tokens: 300,
+            cache_creation_tokens: 0,
+            cost_usd: 2.0,
+            accepted_edits: 3,
+            commits: 1,
+        };
+        let efficiency = Efficiency::score(&inputs).unwrap();
+        assert_eq!(efficiency.success_rate, Some(80.0));
+        assert_eq!(efficiency.cache_reuse, Some(75.0));
+        assert_eq!(efficiency.retry_rate, Some(10.0));
+        assert_eq!(efficiency.cost_per_outcome, Some(0.5));
+        assert_eq!(efficiency.outcomes, 4);
+        // 0.3 * 0.8 + 0.2 * 0.75 + 0.2 * 0.9 + 0.3 * 0.5
+        assert!((efficiency.score - 72.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_missing_parts_left_out() {
+        assert_eq!(Efficiency::score(&EfficiencyInputs::default()), None);
+
+        // Only tool calls: the score is their success rate
+        let tools_only = EfficiencyInputs {
+            tool_calls: 4,
+            tool_errors: 1,
+            ..Default::default()
+        };
+        assert_eq!(Efficiency::score(&tools_only).unwrap().score, 75.0);
+
+        // Spend with nothing accepted scores nothing for cost
+        let unproductive = EfficiencyInputs {
+            cost_usd: 1.0,
+            ..Default::default()
+        };
+        let efficiency = Efficiency::score(&unproductive).unwrap();
+        assert_eq!(efficiency.score, 0.0);
+        assert_eq!(efficiency.cost_per_outcome, None);
+    }
+}
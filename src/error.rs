@@ -0,0 +1,157 @@
+//! Error type of the library API: [`StorageHandle`](crate::storage::StorageHandle) and
+//! the OTLP receiver and parsers. Each variant keeps the underlying error with its
+//! context, so consumers can match on the kind of failure instead of the message and
+//! still downcast to the cause.
+
+use std::sync::mpsc;
+
+pub type Result<T, E = AgenttopError> = std::result::Result<T, E>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum AgenttopError {
+    /// A database query or write failed, or storage is not running
+    #[error("{0:#}")]
+    Storage(anyhow::Error),
+    /// Received or stored data could not be decoded
+    #[error("{0:#}")]
+    Parse(anyhow::Error),
+    /// Reading or writing a file or socket failed
+    #[error("{0:#}")]
+    Io(anyhow::Error),
+    /// The configuration is unusable, e.g. there is no data directory for the database
+    #[error("{0:#}")]
+    Config(anyhow::Error),
+}
+
+/// Cause of an [`AgenttopError::Storage`] when the storage actor stopped answering
+#[derive(Debug, thiserror::Error)]
+#[error("storage is not running")]
+pub struct StorageUnavailable;
+
+/// Cause of an [`AgenttopError::Storage`] when a query ran longer than the configured
+/// `[storage] query_timeout_secs` and was interrupted
+#[derive(Debug, thiserror::Error)]
+#[error("query timed out after {}s", .0.as_secs())]
+pub struct QueryTimeout(pub std::time::Duration);
+
+/// Cause of an [`AgenttopError::Storage`] when a newer refresh superseded a query
+/// (see [`StorageHandle::supersede`](crate::storage::StorageHandle::supersede))
+#[derive(Debug, thiserror::Error)]
+#[error("query superseded by a newer one")]
+pub struct QueryCancelled;
+
+/// Cause of an [`AgenttopError::Storage`] when the database was written by a newer
+/// agenttop with a schema this version doesn't know; opening it is refused rather than
+/// risking its data
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "{path} was written by agenttop {written_by} (database schema {schema}), which \
+     agenttop {} cannot use; run agenttop >= {min_version}, or export the data with it \
+     (`agenttop export`) and use a new database here",
+    env!("CARGO_PKG_VERSION")
+)]
+pub struct IncompatibleDatabase {
+    pub path: String,
+    pub written_by: String,
+    pub schema: i64,
+    /// Oldest agenttop that can use the database
+    pub min_version: String,
+}
+
+impl AgenttopError {
+    /// The storage actor stopped answering (it panicked and is restarting, or shut down)
+    /// rather than the operation itself failing
+    pub fn is_unavailable(&self) -> bool {
+        matches!(self, Self::Storage(cause) if cause.is::<StorageUnavailable>())
+    }
+
+    /// The query was interrupted for taking too long, or skipped for being superseded,
+    /// rather than failing; retrying later may succeed
+    pub fn is_interrupted(&self) -> bool {
+        matches!(self, Self::Storage(cause)
+            if cause.is::<QueryTimeout>() || cause.is::<QueryCancelled>())
+    }
+}
+
+/// Classify by the first I/O or decoding error in the chain; anything else is a
+/// storage error
+impl From<anyhow::Error> for AgenttopError {
+    fn from(error: anyhow::Error) -> Self {
+        for cause in error.chain() {
+            if cause.is::<std::io::Error>() {
+                return Self::Io(error);
+            }
+            if cause.is::<serde_json::Error>() || cause.is::<prost::DecodeError>() {
+                return Self::Parse(error);
+            }
+        }
+        Self::Storage(error)
+    }
+}
+
+impl From<duckdb::Error> for AgenttopError {
+    fn from(error: duckdb::Error) -> Self {
+        Self::Storage(error.into())
+    }
+}
+
+impl From<std::io::Error> for AgenttopError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error.into())
+    }
+}
+
+impl From<serde_json::Error> for AgenttopError {
+    fn from(error: serde_json::Error) -> Self {
+        Self::Parse(error.into())
+    }
+}
+
+impl From<mpsc::RecvError> for AgenttopError {
+    fn from(_: mpsc::RecvError) -> Self {
+        Self::Storage(StorageUnavailable.into())
+    }
+}
+
+impl<T> From<mpsc::SendError<T>> for AgenttopError {
+    fn from(_: mpsc::SendError<T>) -> Self {
+        Self::Storage(StorageUnavailable.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_from_anyhow_keeps_context() {
+        let io = std::io::Error::new(std::io::ErrorKind::PermissionDenied, "denied");
+        let error: AgenttopError = Err::<(), _>(io)
+            .context("Failed to write export.csv")
+            .unwrap_err()
+            .into();
+        assert!(matches!(error, AgenttopError::Io(_)));
+        assert_eq!(error.to_string(), "Failed to write export.csv: denied");
+
+        let error: AgenttopError = anyhow::anyhow!("Unknown table").into();
+        assert!(matches!(error, AgenttopError::Storage(_)));
+        assert!(!error.is_unavailable());
+    }
+
+    #[test]
+    fn test_unavailable() {
+        let error: AgenttopError = mpsc::RecvError.into();
+        assert!(error.is_unavailable());
+        assert_eq!(error.to_string(), "storage is not running");
+    }
+
+    #[test]
+    fn test_interrupted() {
+        let error = AgenttopError::Storage(QueryTimeout(std::time::Duration::from_secs(30)).into());
+        assert!(error.is_interrupted());
+        assert!(!error.is_unavailable());
+        assert_eq!(error.to_string(), "query timed out after 30s");
+        assert!(AgenttopError::Storage(QueryCancelled.into()).is_interrupted());
+    }
+}
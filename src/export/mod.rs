@@ -0,0 +1,365 @@
+//! Export of agenttop metrics to files (CSV / JSON), and the loop that pushes them to
+//! other systems periodically
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::storage::{QueryResult, StorageHandle, TokenUsageRow, ToolMetrics};
+
+/// Output format for exported data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+        }
+    }
+}
+
+/// Output format for raw table exports (`agenttop export`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum TableFormat {
+    Csv,
+    /// Newline-delimited JSON, one object per row
+    Json,
+    Parquet,
+}
+
+impl TableFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            TableFormat::Csv => "csv",
+            TableFormat::Json => "ndjson",
+            TableFormat::Parquet => "parquet",
+        }
+    }
+}
+
+/// Output format for ad-hoc query results (`agenttop query`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum QueryFormat {
+    /// Aligned columns for reading in a terminal
+    Table,
+    Csv,
+    /// An array with one object per row
+    Json,
+}
+
+/// One exported tool row, in the order the table displays it
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolExportRow {
+    pub tool_name: String,
+    pub display_name: String,
+    pub kind: &'static str,
+    pub call_count: u64,
+    pub error_count: u64,
+    pub success_count: u64,
+    pub approval_rate: f64,
+    pub avg_duration_ms: f64,
+    pub min_duration_ms: f64,
+    pub max_duration_ms: f64,
+    pub last_call: Option<DateTime<Utc>>,
+}
+
+impl From<&ToolMetrics> for ToolExportRow {
+    fn from(tool: &ToolMetrics) -> Self {
+        Self {
+            tool_name: tool.tool_name.clone(),
+            display_name: tool.display_name(),
+            kind: if tool.is_builtin() { "builtin" } else { "mcp" },
+            call_count: tool.call_count,
+            error_count: tool.error_count,
+            success_count: tool.success_count,
+            approval_rate: tool.approval_rate(),
+            avg_duration_ms: tool.avg_duration_ms,
+            min_duration_ms: tool.min_duration_ms,
+            max_duration_ms: tool.max_duration_ms,
+            last_call: tool.last_call,
+        }
+    }
+}
+
+/// JSON document written for a tool metrics export
+#[derive(Debug, Serialize)]
+struct ToolExport<'a> {
+    exported_at: DateTime<Utc>,
+    time_range: &'a str,
+    tools: &'a [ToolExportRow],
+}
+
+/// Quote a CSV field if it contains a delimiter, quote or newline
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Write tool rows in the given format
+pub fn write_tool_metrics<W: Write>(
+    writer: &mut W,
+    format: ExportFormat,
+    rows: &[ToolExportRow],
+    time_range: &str,
+) -> Result<()> {
+    match format {
+        ExportFormat::Json => {
+            let export = ToolExport {
+                exported_at: Utc::now(),
+                time_range,
+                tools: rows,
+            };
+            serde_json::to_writer_pretty(&mut *writer, &export)?;
+            writeln!(writer)?;
+        }
+        ExportFormat::Csv => {
+            writeln!(
+                writer,
+                "tool_name,display_name,kind,call_count,error_count,success_count,approval_rate,avg_duration_ms,min_duration_ms,max_duration_ms,last_call"
+            )?;
+            for row in rows {
+                writeln!(
+                    writer,
+                    "{},{},{},{},{},{},{:.1},{:.1},{:.1},{:.1},{}",
+                    csv_field(&row.tool_name),
+                    csv_field(&row.display_name),
+                    row.kind,
+                    row.call_count,
+                    row.error_count,
+                    row.success_count,
+                    row.approval_rate,
+                    row.avg_duration_ms,
+                    row.min_duration_ms,
+                    row.max_duration_ms,
+                    row.last_call.map(|t| t.to_rfc3339()).unwrap_or_default(),
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Write tool rows to a timestamped file in `dir`, returning the file path
+pub fn export_tool_metrics(
+    dir: &Path,
+    format: ExportFormat,
+    rows: &[ToolExportRow],
+    time_range: &str,
+) -> Result<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+    let file_name = format!(
+        "agenttop-tools-{}.{}",
+        Utc::now().format("%Y%m%d-%H%M%S"),
+        format.extension()
+    );
+    let path = dir.join(file_name);
+    let mut file = std::io::BufWriter::new(std::fs::File::create(&path)?);
+    write_tool_metrics(&mut file, format, rows, time_range)?;
+    file.flush()?;
+    Ok(path)
+}
+
+/// Export each table's rows in the time range to `<dir>/agenttop-<table>-<timestamp>.<ext>`,
+/// returning (table, path, rows) for each file written
+pub fn export_tables(
+    storage: &StorageHandle,
+    tables: &[&str],
+    format: TableFormat,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+    dir: &Path,
+) -> Result<Vec<(String, PathBuf, u64)>> {
+    std::fs::create_dir_all(dir)?;
+    let stamp = Utc::now().format("%Y%m%d-%H%M%S");
+    let mut written = Vec::new();
+    for table in tables {
+        let path = dir.join(format!(
+            "agenttop-{}-{}.{}",
+            table,
+            stamp,
+            format.extension()
+        ));
+        let rows = storage.export_table(table, since, until, format, &path)?;
+        written.push((table.to_string(), path, rows));
+    }
+    Ok(written)
+}
+
+/// Write a query result in the given format; values stay text and NULLs become
+/// empty CSV fields / JSON nulls
+pub fn write_query_result<W: Write>(
+    writer: &mut W,
+    format: QueryFormat,
+    result: &QueryResult,
+) -> Result<()> {
+    match format {
+        QueryFormat::Table => {
+            let cell =
+                |value: &Option<String>| value.as_deref().unwrap_or("NULL").replace('\n', " ");
+            let widths: Vec<usize> = result
+                .columns
+                .iter()
+                .enumerate()
+                .map(|(i, column)| {
+                    result
+                        .rows
+                        .iter()
+                        .map(|row| cell(&row[i]).chars().count())
+                        .max()
+                        .unwrap_or(0)
+                        .max(column.chars().count())
+                })
+                .collect();
+            let line = |values: Vec<String>| {
+                values
+                    .iter()
+                    .zip(&widths)
+                    .map(|(value, width)| format!("{:<width$}", value, width = width))
+                    .collect::<Vec<_>>()
+                    .join("  ")
+                    .trim_end()
+                    .to_string()
+            };
+            writeln!(writer, "{}", line(result.columns.clone()))?;
+            writeln!(
+                writer,
+                "{}",
+                line(widths.iter().map(|w| "─".repeat(*w)).collect())
+            )?;
+            for row in &result.rows {
+                writeln!(writer, "{}", line(row.iter().map(cell).collect()))?;
+            }
+            let count = result.rows.len();
+            writeln!(
+                writer,
+                "({} row{}{})",
+                count,
+                if count == 1 { "" } else { "s" },
+                if result.truncated { ", truncated" } else { "" }
+            )?;
+        }
+        QueryFormat::Csv => {
+            let header: Vec<String> = result.columns.iter().map(|c| csv_field(c)).collect();
+            writeln!(writer, "{}", header.join(","))?;
+            for row in &result.rows {
+                let fields: Vec<String> = row
+                    .iter()
+                    .map(|value| value.as_deref().map(csv_field).unwrap_or_default())
+                    .collect();
+                writeln!(writer, "{}", fields.join(","))?;
+            }
+        }
+        QueryFormat::Json => {
+            let rows: Vec<serde_json::Map<String, serde_json::Value>> = result
+                .rows
+                .iter()
+                .map(|row| {
+                    result
+                        .columns
+                        .iter()
+                        .cloned()
+                        .zip(row.iter().map(|value| match value {
+                            Some(v) => serde_json::Value::String(v.clone()),
+                            None => serde_json::Value::Null,
+                        }))
+                        .collect()
+                })
+                .collect();
+            serde_json::to_writer_pretty(&mut *writer, &rows)?;
+            writeln!(writer)?;
+        }
+    }
+    Ok(())
+}
+
+/// Write token usage rows as CSV batch by batch as they arrive, returning the number of
+/// rows written
+pub fn write_token_usage_csv<W: Write>(
+    writer: &mut W,
+    batches: impl IntoIterator<Item = crate::error::Result<Vec<TokenUsageRow>>>,
+) -> Result<u64> {
+    writeln!(writer, "timestamp,token_type,count,model,provider")?;
+    let mut written = 0;
+    for batch in batches {
+        for row in batch? {
+            writeln!(
+                writer,
+                "{},{},{},{},{}",
+                row.timestamp.to_rfc3339(),
+                csv_field(&row.token_type),
+                row.count,
+                row.model.as_deref().map(csv_field).unwrap_or_default(),
+                row.provider.as_deref().map(csv_field).unwrap_or_default()
+            )?;
+            written += 1;
+        }
+    }
+    writer.flush()?;
+    Ok(written)
+}
+
+/// Run `export` every `interval` on a background thread, logging what fails; the
+/// InfluxDB, Graphite and OTLP exporters push their aggregates this way
+pub fn spawn_periodic(interval: Duration, mut export: impl FnMut() -> Result<()> + Send + 'static) {
+    std::thread::spawn(move || {
+        loop {
+            std::thread::sleep(interval);
+            if let Err(e) = export() {
+                tracing::warn!("{:#}", e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_field_quoting() {
+        assert_eq!(csv_field("Read"), "Read");
+        assert_eq!(csv_field("server:a,b"), "\"server:a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn test_write_query_result() {
+        let result = QueryResult {
+            columns: vec!["tool".to_string(), "n".to_string()],
+            rows: vec![
+                vec![Some("Read".to_string()), Some("12".to_string())],
+                vec![Some("a,b".to_string()), None],
+            ],
+            truncated: true,
+        };
+        let render = |format| {
+            let mut out = Vec::new();
+            write_query_result(&mut out, format, &result).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let table = render(QueryFormat::Table);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines[0], "tool  n");
+        assert_eq!(lines[2], "Read  12");
+        assert_eq!(lines[3], "a,b   NULL");
+        assert_eq!(lines[4], "(2 rows, truncated)");
+
+        assert_eq!(render(QueryFormat::Csv), "tool,n\nRead,12\n\"a,b\",\n");
+
+        let json: serde_json::Value = serde_json::from_str(&render(QueryFormat::Json)).unwrap();
+        assert_eq!(json[0]["n"], "12");
+        assert!(json[1]["n"].is_null());
+    }
+}
@@ -0,0 +1,197 @@
+//! Month-end spend forecast from recent daily spend.
+//!
+//! A line is fitted by least squares to the spend of the last [`HISTORY_DAYS`] complete
+//! local days (from the first day with any spend), and extended over the rest of the
+//! month. The bounds assume days scatter around the line as they did in the fitted
+//! days, independently: ±1.96 residual deviations per remaining day, summed in
+//! quadrature. The burn-rate projection in the TUI metrics bar answers "at this pace";
+//! this answers "at the usual pace".
+
+use anyhow::Result;
+use chrono::{DateTime, Datelike, Local, Months, NaiveDate, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::storage::StorageHandle;
+use crate::tui::app::format_usd;
+
+/// Complete days of spend the line is fitted to
+pub const HISTORY_DAYS: u64 = 28;
+
+/// Standard normal quantile for the 95% bounds
+const Z_95: f64 = 1.96;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct Forecast {
+    /// Spend since the start of the local calendar month
+    pub month_to_date_usd: f64,
+    /// Expected spend for the whole month
+    pub projected_usd: f64,
+    /// 95% bounds of the month's spend
+    pub low_usd: f64,
+    pub high_usd: f64,
+    /// Fitted spend for today
+    pub daily_usd: f64,
+    /// Change in daily spend per day
+    pub trend_usd_per_day: f64,
+    /// Complete days the fit is based on (0: only this month's spend so far is known)
+    pub days_fitted: usize,
+}
+
+impl Forecast {
+    /// Fit `history` (spend per complete day, oldest first, ending yesterday) and
+    /// project the month containing `now`
+    pub fn project(history: &[f64], month_to_date_usd: f64, now: DateTime<Local>) -> Self {
+        let first = history.iter().position(|usd| *usd > 0.0);
+        let days = first.map_or(&[][..], |first| &history[first..]);
+        let n = days.len() as f64;
+
+        let (intercept, slope) = match days.len() {
+            0 => (0.0, 0.0),
+            1 => (days[0], 0.0),
+            _ => {
+                let mean_x = (n - 1.0) / 2.0;
+                let mean_y = days.iter().sum::<f64>() / n;
+                let (mut sxy, mut sxx) = (0.0, 0.0);
+                for (x, y) in days.iter().enumerate() {
+                    let dx = x as f64 - mean_x;
+                    sxy += dx * (y - mean_y);
+                    sxx += dx * dx;
+                }
+                let slope = sxy / sxx;
+                (mean_y - slope * mean_x, slope)
+            }
+        };
+        let fitted = |x: f64| (intercept + slope * x).max(0.0);
+        // Residual deviation, with a degree of freedom per fitted parameter
+        let deviation = if days.len() > 2 {
+            let squares: f64 = days
+                .iter()
+                .enumerate()
+                .map(|(x, y)| (y - (intercept + slope * x as f64)).powi(2))
+                .sum();
+            (squares / (n - 2.0)).sqrt()
+        } else {
+            0.0
+        };
+
+        let (today_left, days_left) = remaining(now);
+        let today = fitted(n);
+        let mut expected = today * today_left;
+        for day in 1..=days_left {
+            expected += fitted(n + day as f64);
+        }
+        let margin = Z_95 * deviation * (today_left + days_left as f64).sqrt();
+        let projected_usd = month_to_date_usd + expected;
+        Self {
+            month_to_date_usd,
+            projected_usd,
+            low_usd: (projected_usd - margin).max(month_to_date_usd),
+            high_usd: projected_usd + margin,
+            daily_usd: today,
+            trend_usd_per_day: slope,
+            days_fitted: days.len(),
+        }
+    }
+
+    /// e.g. "~$412.00 by month end ($380.10–$445.20, 95%)"
+    pub fn describe(&self) -> String {
+        format!(
+            "~{} by month end ({}–{}, 95%)",
+            format_usd(self.projected_usd),
+            format_usd(self.low_usd),
+            format_usd(self.high_usd)
+        )
+    }
+}
+
+/// Fraction of today still ahead, and the whole days left in the month after today
+fn remaining(now: DateTime<Local>) -> (f64, u32) {
+    let today = now.date_naive();
+    let seconds = now.num_seconds_from_midnight() as f64;
+    let today_left = (1.0 - seconds / 86_400.0).clamp(0.0, 1.0);
+    let last = today
+        .with_day(1)
+        .and_then(|d| d.checked_add_months(Months::new(1)))
+        .and_then(|d| d.pred_opt())
+        .unwrap_or(today);
+    (today_left, (last - today).num_days().max(0) as u32)
+}
+
+/// Forecast the month containing `now` from the stored cost metrics
+pub fn month_end(storage: &StorageHandle, now: DateTime<Local>) -> Result<Forecast> {
+    let today = now.date_naive();
+    let month_start = today.with_day(1).unwrap_or(today);
+    let first_day = (today - chrono::Days::new(HISTORY_DAYS)).min(month_start);
+    let since = local_midnight(first_day).unwrap_or_else(|| now.with_timezone(&Utc));
+
+    let mut by_day: HashMap<NaiveDate, f64> = HashMap::new();
+    for hour in storage.get_hourly_activity(since, now.with_timezone(&Utc))? {
+        *by_day
+            .entry(hour.hour.with_timezone(&Local).date_naive())
+            .or_default() += hour.cost_usd;
+    }
+    let history: Vec<f64> = (1..=HISTORY_DAYS)
+        .rev()
+        .map(|back| {
+            let day = today - chrono::Days::new(back);
+            by_day.get(&day).copied().unwrap_or(0.0)
+        })
+        .collect();
+    let month_to_date = by_day
+        .iter()
+        .filter(|(day, _)| **day >= month_start)
+        .map(|(_, usd)| usd)
+        .sum();
+    Ok(Forecast::project(&history, month_to_date, now))
+}
+
+fn local_midnight(day: NaiveDate) -> Option<DateTime<Utc>> {
+    day.and_hms_opt(0, 0, 0)?
+        .and_local_timezone(Local)
+        .earliest()
+        .map(|dt| dt.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_flat_spend() {
+        // Noon on the 29th of a 30-day month: half of today and one more day left
+        let now = Local.with_ymd_and_hms(2025, 6, 29, 12, 0, 0).unwrap();
+        let forecast = Forecast::project(&[10.0; 7], 200.0, now);
+        assert_eq!(forecast.days_fitted, 7);
+        assert!((forecast.projected_usd - 215.0).abs() < 1e-9);
+        assert_eq!(forecast.low_usd, forecast.high_usd);
+        assert_eq!(
+            forecast.describe(),
+            "~$215.00 by month end ($215.00–$215.00, 95%)"
+        );
+    }
+
+    #[test]
+    fn test_trend_and_bounds() {
+        let now = Local.with_ymd_and_hms(2025, 6, 28, 0, 0, 0).unwrap();
+        // Days before the first spend are left out of the fit
+        let history = [0.0, 0.0, 1.0, 3.0, 2.0, 4.0, 3.0, 5.0];
+        let forecast = Forecast::project(&history, 50.0, now);
+        assert_eq!(forecast.days_fitted, 6);
+        assert!(forecast.trend_usd_per_day > 0.0);
+        // Today and two more days, each above the last fitted day's 5.0
+        assert!(forecast.projected_usd > 50.0 + 3.0 * 4.5);
+        assert!(forecast.low_usd < forecast.projected_usd);
+        assert!(forecast.high_usd > forecast.projected_usd);
+        assert!(forecast.low_usd >= 50.0);
+    }
+
+    #[test]
+    fn test_no_history() {
+        let now = Local.with_ymd_and_hms(2025, 6, 10, 12, 0, 0).unwrap();
+        let forecast = Forecast::project(&[0.0; 28], 0.0, now);
+        assert_eq!(forecast.projected_usd, 0.0);
+        assert_eq!(forecast.days_fitted, 0);
+    }
+}
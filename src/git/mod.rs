@@ -0,0 +1,322 @@
+//! Git correlation: the commits made in a repository while an agent session was running,
+//! and what the session spent on each.
+//!
+//! Commits come from `git log` in the repository. A commit is counted as AI-assisted when
+//! it was made between a session's first API call and [`COMMIT_GRACE`] after its last,
+//! and the session worked in the repository: its `cwd` attribute (see
+//! [`crate::otlp::parser::CWD_ATTRIBUTE`]) is inside it, or it reports no working
+//! directory at all. The commit's cost is what the session spent since its previous
+//! commit (or its start).
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, TimeZone, Utc};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::storage::ApiSpend;
+
+/// Time after a session's last API call in which a commit still counts as made during it,
+/// e.g. when the user commits the agent's work by hand
+pub const COMMIT_GRACE: chrono::Duration = chrono::Duration::minutes(5);
+
+/// Separates commits and fields in the `git log` output
+const RECORD_SEPARATOR: char = '\u{1e}';
+const FIELD_SEPARATOR: char = '\u{1f}';
+
+/// One non-merge commit with the lines it changed
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Commit {
+    pub hash: String,
+    /// Author date
+    pub time: DateTime<Utc>,
+    pub author: String,
+    /// First line of the message
+    pub summary: String,
+    pub insertions: u64,
+    pub deletions: u64,
+}
+
+impl Commit {
+    /// Lines added and removed
+    pub fn lines(&self) -> u64 {
+        self.insertions + self.deletions
+    }
+}
+
+/// Top level of the git work tree holding `dir`, if any
+pub fn repo_root(dir: &Path) -> Option<PathBuf> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["rev-parse", "--show-toplevel"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let root = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!root.is_empty()).then(|| PathBuf::from(root))
+}
+
+/// The repositories the sessions in `spend` worked in, found from their working
+/// directories on this machine; sorted
+pub fn session_repos(spend: &[ApiSpend]) -> Vec<PathBuf> {
+    let dirs: BTreeSet<&str> = spend.iter().filter_map(|s| s.cwd.as_deref()).collect();
+    let repos: BTreeSet<PathBuf> = dirs
+        .into_iter()
+        .filter_map(|dir| repo_root(Path::new(dir)))
+        .collect();
+    repos.into_iter().collect()
+}
+
+/// Non-merge commits in `repo` authored in the range, oldest first
+pub fn log(
+    repo: &Path,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+) -> Result<Vec<Commit>> {
+    let mut command = Command::new("git");
+    command.arg("-C").arg(repo).args([
+        "log",
+        "--no-merges",
+        "--reverse",
+        "--numstat",
+        "--format=\u{1e}%H\u{1f}%at\u{1f}%an\u{1f}%s",
+    ]);
+    if let Some(since) = since {
+        command.arg(format!("--since=@{}", since.timestamp()));
+    }
+    if let Some(until) = until {
+        command.arg(format!("--until=@{}", until.timestamp()));
+    }
+    let output = command
+        .output()
+        .context("Could not run git; is it installed?")?;
+    if !output.status.success() {
+        bail!(
+            "git log failed in {}: {}",
+            repo.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let mut commits = parse_log(&String::from_utf8_lossy(&output.stdout));
+    // --since/--until filter on the committer date; keep to the author date
+    commits.retain(|c| since.is_none_or(|s| c.time >= s) && until.is_none_or(|u| c.time < u));
+    Ok(commits)
+}
+
+/// Parse `git log --numstat` output in the format [`log`] asks for
+fn parse_log(output: &str) -> Vec<Commit> {
+    output
+        .split(RECORD_SEPARATOR)
+        .filter_map(|record| {
+            let mut lines = record.lines();
+            let mut fields = lines.next()?.splitn(4, FIELD_SEPARATOR);
+            let hash = fields.next()?.to_string();
+            let time = Utc
+                .timestamp_opt(fields.next()?.parse().ok()?, 0)
+                .single()?;
+            let author = fields.next()?.to_string();
+            let summary = fields.next().unwrap_or("").to_string();
+            let (mut insertions, mut deletions) = (0, 0);
+            for line in lines {
+                // "<added>\t<removed>\t<path>"; binary files show "-"
+                let mut counts = line.split('\t');
+                insertions += counts.next().and_then(|n| n.parse().ok()).unwrap_or(0);
+                deletions += counts.next().and_then(|n| n.parse().ok()).unwrap_or(0);
+            }
+            Some(Commit {
+                hash,
+                time,
+                author,
+                summary,
+                insertions,
+                deletions,
+            })
+        })
+        .collect()
+}
+
+/// A commit and the session it was made in, if any
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CorrelatedCommit {
+    #[serde(flatten)]
+    pub commit: Commit,
+    /// The session's `session.id`; None for a commit made outside any session
+    pub session_id: Option<String>,
+    /// What the session spent since its previous commit (or its start); None outside a
+    /// session
+    pub cost_usd: Option<f64>,
+}
+
+impl CorrelatedCommit {
+    pub fn assisted(&self) -> bool {
+        self.session_id.is_some()
+    }
+}
+
+/// A session's API calls that may have worked in the repository, oldest first
+struct SessionSpend<'a> {
+    id: Option<&'a str>,
+    calls: Vec<&'a ApiSpend>,
+    /// Calls up to here are paid for by earlier commits
+    attributed: usize,
+}
+
+/// Whether a session working in `cwd` may have made commits in `repo` (as git reports
+/// it: with symlinks resolved)
+fn works_in(cwd: Option<&str>, repo: &Path) -> bool {
+    cwd.is_none_or(|cwd| {
+        let cwd = Path::new(cwd);
+        cwd.starts_with(repo) || cwd.canonicalize().is_ok_and(|cwd| cwd.starts_with(repo))
+    })
+}
+
+/// Match `commits` (oldest first) in `repo` to the sessions in `spend` (oldest first).
+/// When sessions overlap, a commit goes to the one with the latest API call before it.
+pub fn correlate(repo: &Path, commits: Vec<Commit>, spend: &[ApiSpend]) -> Vec<CorrelatedCommit> {
+    let mut sessions: Vec<SessionSpend> = Vec::new();
+    let mut index: HashMap<Option<&str>, usize> = HashMap::new();
+    let mut in_repo: HashMap<Option<&str>, bool> = HashMap::new();
+    for call in spend {
+        let cwd = call.cwd.as_deref();
+        if !*in_repo.entry(cwd).or_insert_with(|| works_in(cwd, repo)) {
+            continue;
+        }
+        let id = call.session_id.as_deref();
+        let i = *index.entry(id).or_insert_with(|| {
+            sessions.push(SessionSpend {
+                id,
+                calls: Vec::new(),
+                attributed: 0,
+            });
+            sessions.len() - 1
+        });
+        sessions[i].calls.push(call);
+    }
+
+    commits
+        .into_iter()
+        .map(|commit| {
+            let session = sessions
+                .iter_mut()
+                .filter(|s| {
+                    s.calls.first().is_some_and(|c| c.timestamp <= commit.time)
+                        && s.calls
+                            .last()
+                            .is_some_and(|c| commit.time <= c.timestamp + COMMIT_GRACE)
+                })
+                .max_by_key(|s| {
+                    s.calls
+                        .iter()
+                        .rev()
+                        .find(|c| c.timestamp <= commit.time)
+                        .map(|c| c.timestamp)
+                });
+            let Some(session) = session else {
+                return CorrelatedCommit {
+                    commit,
+                    session_id: None,
+                    cost_usd: None,
+                };
+            };
+            let end = session
+                .calls
+                .partition_point(|c| c.timestamp <= commit.time);
+            let cost_usd = session.calls[session.attributed.min(end)..end]
+                .iter()
+                .map(|c| c.cost_usd)
+                .sum();
+            session.attributed = session.attributed.max(end);
+            CorrelatedCommit {
+                session_id: Some(session.id.unwrap_or("(none)").to_string()),
+                cost_usd: Some(cost_usd),
+                commit,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(1_750_000_000 + secs, 0).unwrap()
+    }
+
+    fn commit(hash: &str, secs: i64, lines: u64) -> Commit {
+        Commit {
+            hash: hash.to_string(),
+            time: at(secs),
+            author: "dev".to_string(),
+            summary: String::new(),
+            insertions: lines,
+            deletions: 0,
+        }
+    }
+
+    fn call(secs: i64, session: &str, cwd: Option<&str>, cost_usd: f64) -> ApiSpend {
+        ApiSpend {
+            timestamp: at(secs),
+            session_id: Some(session.to_string()),
+            cwd: cwd.map(str::to_string),
+            cost_usd,
+        }
+    }
+
+    #[test]
+    fn test_parse_log() {
+        let output = "\u{1e}abc123\u{1f}1750000000\u{1f}Dev One\u{1f}Add parser\n\n\
+                      10\t2\tsrc/parser.rs\n-\t-\tlogo.png\n3\t0\tREADME.md\n\
+                      \u{1e}def456\u{1f}1750000600\u{1f}Dev Two\u{1f}Empty: with \u{1f} in it\n";
+        let commits = parse_log(output);
+        assert_eq!(commits.len(), 2);
+        assert_eq!(commits[0].hash, "abc123");
+        assert_eq!(commits[0].time, at(0));
+        assert_eq!(commits[0].author, "Dev One");
+        assert_eq!(commits[0].summary, "Add parser");
+        assert_eq!((commits[0].insertions, commits[0].deletions), (13, 2));
+        assert_eq!(commits[1].summary, "Empty: with \u{1f} in it");
+        assert_eq!(commits[1].lines(), 0);
+    }
+
+    #[test]
+    fn test_correlate() {
+        let repo = Path::new("/work/app");
+        let spend = vec![
+            call(0, "a", Some("/work/app/src"), 1.0),
+            call(100, "a", Some("/work/app"), 2.0),
+            call(200, "other", Some("/work/lib"), 5.0),
+            call(300, "a", Some("/work/app"), 4.0),
+            call(400, "b", None, 0.5),
+        ];
+        let commits = vec![
+            commit("before", -10, 1),
+            commit("first", 150, 10),
+            commit("second", 320, 20),
+            // Within the grace after a's last call, but b called more recently
+            commit("third", 420, 5),
+            commit("later", 400 + COMMIT_GRACE.num_seconds() + 1, 7),
+        ];
+        let correlated = correlate(repo, commits, &spend);
+        let sessions: Vec<Option<&str>> =
+            correlated.iter().map(|c| c.session_id.as_deref()).collect();
+        assert_eq!(sessions, [None, Some("a"), Some("a"), Some("b"), None]);
+        let costs: Vec<Option<f64>> = correlated.iter().map(|c| c.cost_usd).collect();
+        // "other" works in another repository and is never counted
+        assert_eq!(costs, [None, Some(3.0), Some(4.0), Some(0.5), None]);
+        assert!(correlated[1].assisted());
+    }
+
+    #[test]
+    fn test_works_in() {
+        let repo = Path::new("/work/app");
+        assert!(works_in(Some("/work/app"), repo));
+        assert!(works_in(Some("/work/app/src"), repo));
+        assert!(works_in(None, repo));
+        assert!(!works_in(Some("/work/application"), repo));
+    }
+}
@@ -0,0 +1,136 @@
+//! Periodic push of agenttop's aggregates (per-tool calls and errors, tokens, cost) in
+//! Graphite's plaintext protocol over TCP, configured under `graphite_export` in the
+//! config file.
+//!
+//! As with the other exports, values are all-time cumulative counts.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::config::GraphiteExportConfig;
+use crate::storage::{StorageHandle, TokenMetrics, ToolMetrics};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Make `name` one path component: anything but letters, digits, `-` and `_` (dots
+/// included) becomes `_`
+fn path_component(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Build the plaintext body for the current aggregates, one `<path> <value> <time>`
+/// line per metric
+pub fn build_lines(
+    prefix: &str,
+    tools: &[ToolMetrics],
+    tokens: &TokenMetrics,
+    now: DateTime<Utc>,
+) -> String {
+    let prefix = prefix.trim_end_matches('.');
+    let timestamp = now.timestamp();
+    let mut lines = String::new();
+    let mut line = |path: &str, value: &dyn std::fmt::Display| {
+        let _ = writeln!(lines, "{}.{} {} {}", prefix, path, value, timestamp);
+    };
+
+    for tool in tools {
+        let tool_path = path_component(&tool.tool_name);
+        line(&format!("tools.{}.calls", tool_path), &tool.call_count);
+        line(&format!("tools.{}.errors", tool_path), &tool.error_count);
+    }
+    for (kind, count) in [
+        ("input", tokens.input_tokens),
+        ("output", tokens.output_tokens),
+        ("cache_read", tokens.cache_read_tokens),
+        ("cache_creation", tokens.cache_creation_tokens),
+    ] {
+        line(&format!("tokens.{}", kind), &count);
+    }
+    line("cost_usd", &tokens.total_cost_usd);
+    lines
+}
+
+/// Query the aggregates and send them once
+fn export_once(storage: &StorageHandle, config: &GraphiteExportConfig) -> Result<()> {
+    let tools = storage.get_tool_metrics(None, None)?;
+    let tokens = storage.get_token_metrics(None, None)?;
+    let body = build_lines(&config.prefix, &tools, &tokens, Utc::now());
+
+    let addr = config
+        .address
+        .to_socket_addrs()
+        .with_context(|| format!("Invalid Graphite address {}", config.address))?
+        .next()
+        .with_context(|| format!("{} did not resolve", config.address))?;
+    let mut stream = TcpStream::connect_timeout(&addr, TIMEOUT)
+        .with_context(|| format!("Could not connect to Graphite at {}", config.address))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    stream
+        .write_all(body.as_bytes())
+        .with_context(|| format!("Graphite export to {} failed", config.address))?;
+    Ok(())
+}
+
+/// Push aggregates every `interval_secs` on a background thread
+pub fn spawn(storage: StorageHandle, config: GraphiteExportConfig) {
+    let interval = Duration::from_secs(config.interval_secs.max(1));
+    tracing::info!(
+        "Sending metrics to Graphite at {} every {}s",
+        config.address,
+        interval.as_secs()
+    );
+    crate::export::spawn_periodic(interval, move || export_once(&storage, &config));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_lines() {
+        let tools = vec![ToolMetrics {
+            tool_name: "mcp__github__get.issue".to_string(),
+            call_count: 4,
+            last_call: None,
+            avg_duration_ms: 0.0,
+            min_duration_ms: 0.0,
+            max_duration_ms: 0.0,
+            success_count: 3,
+            error_count: 1,
+            approved_count: 4,
+            rejected_count: 0,
+        }];
+        let tokens = TokenMetrics {
+            output_tokens: 7,
+            total_cost_usd: 1.25,
+            ..Default::default()
+        };
+        let now = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+
+        let body = build_lines("dev.alice.agenttop.", &tools, &tokens, now);
+        let lines: Vec<&str> = body.lines().collect();
+        assert_eq!(lines.len(), 7);
+        assert_eq!(
+            lines[0],
+            "dev.alice.agenttop.tools.mcp__github__get_issue.calls 4 1700000000"
+        );
+        assert_eq!(
+            lines[1],
+            "dev.alice.agenttop.tools.mcp__github__get_issue.errors 1 1700000000"
+        );
+        assert_eq!(lines[3], "dev.alice.agenttop.tokens.output 7 1700000000");
+        assert_eq!(lines[6], "dev.alice.agenttop.cost_usd 1.25 1700000000");
+    }
+}
@@ -0,0 +1,371 @@
+//! Event hooks from the config file: a shell command run for every received log event
+//! that matches the hook's filter, with the event as JSON on stdin.
+//!
+//! ```json
+//! "hooks": [{
+//!   "name": "git push",
+//!   "event": "*tool_result",
+//!   "tool": "Bash",
+//!   "attributes": { "tool_parameters": "git\\s+push" },
+//!   "command": "notify-send 'agent pushed'",
+//!   "timeout_secs": 10
+//! }]
+//! ```
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::otlp::EVENT_FEED;
+use crate::self_metrics::SELF_METRICS;
+use crate::storage::{LogEvent, get_tool_display_name};
+use crate::tail::glob_match;
+
+/// Hook commands run at once; further matches wait for one to finish
+const HOOK_WORKERS: usize = 4;
+
+/// Matches waiting for a worker; past this, new ones are dropped and counted
+const HOOK_QUEUE_CAPACITY: usize = 256;
+
+/// How long a hook's command may run when the hook sets no `timeout_secs`
+const DEFAULT_HOOK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A hook to run for an event
+type HookRun = (EventHook, LogEvent);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventHook {
+    pub name: String,
+    /// Glob on the event name, e.g. "*tool_result" (any event when omitted)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event: Option<String>,
+    /// The event's `tool_name`, raw ("mcp__github__create_issue") or as displayed
+    /// ("github:create_issue")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool: Option<String>,
+    /// Attribute name to a regex its value must match; all must match
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub attributes: HashMap<String, String>,
+    /// Shell command run with the event JSON on stdin
+    pub command: String,
+    /// Kill the command once it has run this long (default: 30s)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+}
+
+/// A hook with its attribute regexes compiled
+#[derive(Debug)]
+pub struct CompiledHook {
+    pub hook: EventHook,
+    patterns: Vec<(String, Regex)>,
+}
+
+impl CompiledHook {
+    pub fn new(hook: EventHook) -> Result<Self> {
+        let patterns = hook
+            .attributes
+            .iter()
+            .map(|(key, pattern)| {
+                Regex::new(pattern)
+                    .map(|regex| (key.clone(), regex))
+                    .with_context(|| format!("Hook `{}`: invalid regex for {}", hook.name, key))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { hook, patterns })
+    }
+
+    pub fn matches(&self, event: &LogEvent) -> bool {
+        if let Some(glob) = &self.hook.event
+            && !glob_match(glob, event.event_name.as_deref().unwrap_or(""))
+        {
+            return false;
+        }
+        if let Some(tool) = &self.hook.tool {
+            let Some(name) = event.attributes.get("tool_name") else {
+                return false;
+            };
+            if name != tool && get_tool_display_name(name) != *tool {
+                return false;
+            }
+        }
+        self.patterns.iter().all(|(key, regex)| {
+            event
+                .attributes
+                .get(key)
+                .is_some_and(|value| regex.is_match(value))
+        })
+    }
+}
+
+/// `sh -c <command>` (`cmd /C` on Windows)
+pub(crate) fn shell(command: &str) -> Command {
+    let mut shell = if cfg!(windows) {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C");
+        cmd
+    } else {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c");
+        cmd
+    };
+    shell.arg(command);
+    shell
+}
+
+/// Run a hook's command with the event JSON on stdin and wait for it, killing it once
+/// it runs past the hook's timeout
+fn run_hook(hook: &EventHook, event: &LogEvent) -> Result<()> {
+    let timeout = hook
+        .timeout_secs
+        .map_or(DEFAULT_HOOK_TIMEOUT, Duration::from_secs);
+    let input = serde_json::to_string(event)?;
+    let mut child = shell(&hook.command)
+        .env("AGENTTOP_HOOK", &hook.name)
+        .env(
+            "AGENTTOP_EVENT_NAME",
+            event.event_name.as_deref().unwrap_or(""),
+        )
+        .stdin(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Could not run hook `{}`", hook.name))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // On its own thread, so a command that doesn't read stdin can't block past the
+        // timeout; one that exits without reading closes the pipe early, which is fine
+        std::thread::spawn(move || {
+            let _ = stdin.write_all(input.as_bytes());
+        });
+    }
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            anyhow::bail!(
+                "Hook `{}` was killed after running for {}s",
+                hook.name,
+                timeout.as_secs()
+            );
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    };
+    if !status.success() {
+        anyhow::bail!("Hook `{}` exited with {}", hook.name, status);
+    }
+    Ok(())
+}
+
+/// Queue a hook run for the workers; false once they are gone
+fn enqueue(queue: &SyncSender<HookRun>, run: HookRun) -> bool {
+    match queue.try_send(run) {
+        Ok(()) => true,
+        Err(TrySendError::Full((hook, _))) => {
+            SELF_METRICS.receiver.record_dropped_hook_run();
+            tracing::debug!("Hook queue full, dropped a run of `{}`", hook.name);
+            true
+        }
+        Err(TrySendError::Disconnected(_)) => false,
+    }
+}
+
+/// Worker: run queued hooks one after another
+fn run_queued(queue: &Mutex<Receiver<HookRun>>) {
+    loop {
+        let Ok((hook, event)) = queue.lock().unwrap().recv() else {
+            break;
+        };
+        if let Err(e) = run_hook(&hook, &event) {
+            tracing::warn!("{:#}", e);
+        }
+    }
+}
+
+/// Run matching hooks for every received event on a pool of background threads
+pub fn spawn(hooks: Vec<EventHook>) {
+    let hooks: Vec<CompiledHook> = hooks
+        .into_iter()
+        .filter_map(|hook| {
+            CompiledHook::new(hook)
+                .map_err(|e| tracing::warn!("Skipping hook: {:#}", e))
+                .ok()
+        })
+        .collect();
+    if hooks.is_empty() {
+        return;
+    }
+    tracing::info!("Running {} event hook(s)", hooks.len());
+
+    // A few workers, so slow hooks don't hold up events or each other without one
+    // process per event piling up
+    let (queue, pending) = mpsc::sync_channel(HOOK_QUEUE_CAPACITY);
+    let pending = Arc::new(Mutex::new(pending));
+    for _ in 0..HOOK_WORKERS {
+        let pending = pending.clone();
+        std::thread::spawn(move || run_queued(&pending));
+    }
+
+    let mut events = EVENT_FEED.subscribe();
+    std::thread::spawn(move || {
+        loop {
+            let event = match events.blocking_recv() {
+                Ok(event) => event,
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!("Event hooks skipped {} events", skipped);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            for compiled in hooks.iter().filter(|compiled| compiled.matches(&event)) {
+                if !enqueue(&queue, (compiled.hook.clone(), event.clone())) {
+                    return;
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn event(name: &str, attrs: &[(&str, &str)]) -> LogEvent {
+        LogEvent {
+            timestamp: Utc::now(),
+            event_name: Some(name.to_string()),
+            body: None,
+            attributes: attrs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    fn hook(event: Option<&str>, tool: Option<&str>, attributes: &[(&str, &str)]) -> CompiledHook {
+        CompiledHook::new(EventHook {
+            name: "test".to_string(),
+            event: event.map(str::to_string),
+            tool: tool.map(str::to_string),
+            attributes: attributes
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            command: "true".to_string(),
+            timeout_secs: None,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_hook_matches() {
+        let push = event(
+            "claude_code.tool_result",
+            &[
+                ("tool_name", "Bash"),
+                (
+                    "tool_parameters",
+                    r#"{"bash_command":"git push origin main"}"#,
+                ),
+            ],
+        );
+        let status = event(
+            "claude_code.tool_result",
+            &[
+                ("tool_name", "Bash"),
+                ("tool_parameters", r#"{"bash_command":"git status"}"#),
+            ],
+        );
+
+        let git_push = hook(
+            Some("*tool_result"),
+            Some("Bash"),
+            &[("tool_parameters", r"git\s+push")],
+        );
+        assert!(git_push.matches(&push));
+        assert!(!git_push.matches(&status));
+
+        assert!(hook(None, None, &[]).matches(&status));
+        assert!(!hook(Some("*api_error"), None, &[]).matches(&status));
+        assert!(!hook(None, Some("Read"), &[]).matches(&status));
+        assert!(!hook(None, None, &[("missing", ".*")]).matches(&status));
+
+        let mcp = event("tool_result", &[("tool_name", "mcp__github__create_issue")]);
+        assert!(hook(None, Some("github:create_issue"), &[]).matches(&mcp));
+        assert!(hook(None, Some("mcp__github__create_issue"), &[]).matches(&mcp));
+    }
+
+    #[test]
+    fn test_invalid_regex() {
+        let result = CompiledHook::new(EventHook {
+            name: "bad".to_string(),
+            event: None,
+            tool: None,
+            attributes: HashMap::from([("error".to_string(), "(".to_string())]),
+            command: "true".to_string(),
+            timeout_secs: None,
+        });
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_full_queue_drops_runs() {
+        let (queue, pending) = mpsc::sync_channel(1);
+        let run = || (hook(None, None, &[]).hook, event("tool_result", &[]));
+        let dropped = SELF_METRICS.receiver.snapshot().hook_runs_dropped;
+
+        assert!(enqueue(&queue, run()));
+        assert!(enqueue(&queue, run()));
+        assert!(SELF_METRICS.receiver.snapshot().hook_runs_dropped > dropped);
+        assert!(pending.try_recv().is_ok());
+        assert!(pending.try_recv().is_err());
+
+        drop(pending);
+        assert!(!enqueue(&queue, run()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_hook_passes_event_on_stdin() {
+        let out = std::env::temp_dir().join(format!("agenttop-hook-{}.json", std::process::id()));
+        let hook = EventHook {
+            name: "capture".to_string(),
+            event: None,
+            tool: None,
+            attributes: HashMap::new(),
+            command: format!("cat > {}", out.display()),
+            timeout_secs: None,
+        };
+        run_hook(&hook, &event("tool_result", &[("tool_name", "Bash")])).unwrap();
+        let written: LogEvent =
+            serde_json::from_str(&std::fs::read_to_string(&out).unwrap()).unwrap();
+        let _ = std::fs::remove_file(&out);
+        assert_eq!(written.event_name.as_deref(), Some("tool_result"));
+        assert_eq!(written.attributes["tool_name"], "Bash");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_hook_killed_after_timeout() {
+        let hook = EventHook {
+            name: "slow".to_string(),
+            event: None,
+            tool: None,
+            attributes: HashMap::new(),
+            command: "sleep 30".to_string(),
+            timeout_secs: Some(1),
+        };
+        let started = Instant::now();
+        let err = run_hook(&hook, &event("tool_result", &[])).unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(err.to_string().contains("killed after running for 1s"));
+    }
+}
@@ -0,0 +1,170 @@
+//! Periodic push of agenttop's aggregates (per-tool calls and errors, tokens, cost) as
+//! InfluxDB line protocol, configured under `influx_export` in the config file. Any
+//! endpoint that accepts line protocol over HTTP works: InfluxDB 1.x (`/write`), 2.x
+//! (`/api/v2/write`) or a Telegraf HTTP listener.
+//!
+//! As with the OTLP export, values are all-time cumulative counts.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+use crate::config::InfluxExportConfig;
+use crate::storage::{StorageHandle, TokenMetrics, ToolMetrics};
+
+/// Escape a tag key or value: commas, equals signs and spaces are significant
+fn escape_tag(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, ',' | '=' | ' ') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Tags identifying this instance; `extra` entries override the defaults
+fn instance_tags(extra: &BTreeMap<String, String>) -> BTreeMap<String, String> {
+    let mut tags = BTreeMap::new();
+    if let Ok(user) = std::env::var("USER").or_else(|_| std::env::var("USERNAME")) {
+        tags.insert("user".to_string(), user);
+    }
+    if let Ok(host) = std::env::var("HOSTNAME").or_else(|_| std::env::var("COMPUTERNAME")) {
+        tags.insert("host".to_string(), host);
+    }
+    tags.extend(extra.clone());
+    tags
+}
+
+/// Build the line protocol body for the current aggregates, one line per point
+pub fn build_lines(
+    tools: &[ToolMetrics],
+    tokens: &TokenMetrics,
+    tags: &BTreeMap<String, String>,
+    now: DateTime<Utc>,
+) -> String {
+    let timestamp = now.timestamp_nanos_opt().unwrap_or_default();
+    let common: String = tags
+        .iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(key, value)| format!(",{}={}", escape_tag(key), escape_tag(value)))
+        .collect();
+
+    let mut lines = String::new();
+    for tool in tools {
+        let _ = writeln!(
+            lines,
+            "agenttop_tool{},tool={} calls={}i,errors={}i {}",
+            common,
+            escape_tag(&tool.tool_name),
+            tool.call_count,
+            tool.error_count,
+            timestamp
+        );
+    }
+    for (kind, count) in [
+        ("input", tokens.input_tokens),
+        ("output", tokens.output_tokens),
+        ("cache_read", tokens.cache_read_tokens),
+        ("cache_creation", tokens.cache_creation_tokens),
+    ] {
+        let _ = writeln!(
+            lines,
+            "agenttop_tokens{},type={} count={}i {}",
+            common, kind, count, timestamp
+        );
+    }
+    let _ = writeln!(
+        lines,
+        "agenttop_cost{} usd={} {}",
+        common, tokens.total_cost_usd, timestamp
+    );
+    lines
+}
+
+/// Query the aggregates and send them once
+fn export_once(storage: &StorageHandle, config: &InfluxExportConfig) -> Result<()> {
+    let tools = storage.get_tool_metrics(None, None)?;
+    let tokens = storage.get_token_metrics(None, None)?;
+    let body = build_lines(&tools, &tokens, &instance_tags(&config.tags), Utc::now());
+
+    let mut http = ureq::post(&config.url)
+        .timeout(Duration::from_secs(10))
+        .set("Content-Type", "text/plain; charset=utf-8");
+    if let Some(token) = &config.token {
+        http = http.set("Authorization", &format!("Token {}", token));
+    }
+    http.send_string(&body)
+        .with_context(|| format!("InfluxDB export to {} failed", config.url))?;
+    Ok(())
+}
+
+/// Push aggregates every `interval_secs` on a background thread
+pub fn spawn(storage: StorageHandle, config: InfluxExportConfig) {
+    let interval = Duration::from_secs(config.interval_secs.max(1));
+    tracing::info!(
+        "Writing line protocol to {} every {}s",
+        config.url,
+        interval.as_secs()
+    );
+    crate::export::spawn_periodic(interval, move || export_once(&storage, &config));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_tag() {
+        assert_eq!(escape_tag("Bash"), "Bash");
+        assert_eq!(
+            escape_tag("mcp__a b,c=d__x"),
+            "mcp__a\\ b\\,c\\=d__x".to_string()
+        );
+    }
+
+    #[test]
+    fn test_build_lines() {
+        let tools = vec![ToolMetrics {
+            tool_name: "Bash".to_string(),
+            call_count: 4,
+            last_call: None,
+            avg_duration_ms: 0.0,
+            min_duration_ms: 0.0,
+            max_duration_ms: 0.0,
+            success_count: 3,
+            error_count: 1,
+            approved_count: 4,
+            rejected_count: 0,
+        }];
+        let tokens = TokenMetrics {
+            output_tokens: 7,
+            total_cost_usd: 1.25,
+            ..Default::default()
+        };
+        let tags = BTreeMap::from([
+            ("host".to_string(), "dev box".to_string()),
+            ("team".to_string(), "infra".to_string()),
+        ]);
+        let now = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+
+        let body = build_lines(&tools, &tokens, &tags, now);
+        let lines: Vec<&str> = body.lines().collect();
+        assert_eq!(lines.len(), 6);
+        assert_eq!(
+            lines[0],
+            "agenttop_tool,host=dev\\ box,team=infra,tool=Bash calls=4i,errors=1i 1700000000000000000"
+        );
+        assert_eq!(
+            lines[2],
+            "agenttop_tokens,host=dev\\ box,team=infra,type=output count=7i 1700000000000000000"
+        );
+        assert_eq!(
+            lines[5],
+            "agenttop_cost,host=dev\\ box,team=infra usd=1.25 1700000000000000000"
+        );
+    }
+}
@@ -2,8 +2,42 @@
 //!
 //! A terminal observability dashboard for monitoring Claude Code and other AI agents.
 
+pub mod alerting;
+pub mod anomaly;
+pub mod api;
+pub mod bench;
+pub mod budget;
+pub mod cache;
 pub mod config;
+pub mod context;
+pub mod daemon;
+pub mod digest;
+pub mod doctor;
+pub mod efficiency;
+pub mod error;
+pub mod export;
+pub mod forecast;
+pub mod git;
+pub mod graphite_export;
+pub mod hooks;
+pub mod influx_export;
+pub mod mqtt;
 pub mod otlp;
+pub mod otlp_export;
+pub mod port;
+pub mod pricing;
+pub mod productivity;
+pub mod prometheus;
+pub mod prompt_cost;
 pub mod providers;
+pub mod quota;
+pub mod repl;
+pub mod report;
+pub mod schema;
+pub mod self_metrics;
 pub mod storage;
+pub mod tail;
+pub mod taxonomy;
+pub mod text;
 pub mod tui;
+pub mod watch;
@@ -1,29 +1,994 @@
+mod alerting;
+mod anomaly;
+mod api;
+mod bench;
+mod budget;
+mod cache;
 mod config;
+mod context;
+mod daemon;
+mod digest;
+mod doctor;
+mod efficiency;
+mod error;
+mod export;
+mod forecast;
+mod git;
+mod graphite_export;
+mod hooks;
+mod influx_export;
+mod mqtt;
 mod otlp;
+mod otlp_export;
+mod port;
+mod pricing;
+mod productivity;
+mod prometheus;
+mod prompt_cost;
 mod providers;
+mod quota;
+mod repl;
+mod report;
+mod schema;
+mod self_metrics;
 mod storage;
+mod tail;
+mod taxonomy;
+mod text;
 mod tui;
+mod watch;
 
-use anyhow::Result;
-use clap::Parser;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use clap::{Parser, Subcommand};
 use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt};
 
-use crate::providers::PROVIDER_REGISTRY;
+use crate::export::{QueryFormat, TableFormat};
+use crate::providers::{DEFAULT_OTLP_ENDPOINT, PROVIDER_REGISTRY};
 use crate::storage::StorageHandle;
+use crate::tui::alerts::AlertThresholds;
+use crate::tui::snapshot::SnapshotFormat;
 
 #[derive(Parser)]
 #[command(name = "agenttop", about = "htop for AI coding agents")]
 struct Args {
+    /// Config file (default: config.toml in the agenttop config directory)
+    #[arg(long, global = true, value_name = "PATH", env = "AGENTTOP_CONFIG")]
+    config: Option<std::path::PathBuf>,
+
+    /// Database file (overrides `[storage] path` and AGENTTOP_DB_PATH)
+    #[arg(long, global = true, value_name = "PATH")]
+    db: Option<std::path::PathBuf>,
+
+    /// Address the OTLP receiver listens on (overrides `[receiver] bind` and AGENTTOP_BIND)
+    #[arg(long, global = true, value_name = "ADDR")]
+    bind: Option<std::net::SocketAddr>,
+
     /// Run in headless mode (no TUI, OTLP receiver only)
-    #[arg(short = 'H', long)]
+    #[arg(short = 'H', long, env = "AGENTTOP_HEADLESS")]
     headless: bool,
 
+    /// When the receiver port is taken: ask, view (the running agenttop's database), alternate (next free port), fail
+    #[arg(
+        long,
+        value_name = "ACTION",
+        value_enum,
+        default_value_t = port::Fallback::Ask,
+        env = "AGENTTOP_ON_PORT_CONFLICT"
+    )]
+    on_port_conflict: port::Fallback,
+
+    /// Show the dashboard read-only, even while another agenttop records into the database
+    #[arg(long, env = "AGENTTOP_FORCE_READ_ONLY")]
+    force_read_only: bool,
+
     /// Configure OTLP telemetry for a provider (claude, gemini, qwen, all)
     #[arg(long, value_name = "PROVIDER")]
     setup: Option<String>,
+
+    /// OTLP endpoint written by --setup, e.g. a central receiver
+    #[arg(long, value_name = "URL", requires = "setup", default_value = DEFAULT_OTLP_ENDPOINT, value_parser = providers::parse_endpoint)]
+    endpoint: String,
+
+    /// Tool error rate (percent) that triggers the alert banner
+    #[arg(long, value_name = "PCT", default_value_t = AlertThresholds::default().tool_error_rate_pct)]
+    alert_error_rate: f64,
+
+    /// API errors per model that trigger the alert banner
+    #[arg(long, value_name = "N", default_value_t = AlertThresholds::default().api_errors)]
+    alert_api_errors: u64,
+
+    /// Look-back window (minutes) for alert thresholds
+    #[arg(long, value_name = "MINS", default_value_t = AlertThresholds::default().window_mins)]
+    alert_window: i64,
+
+    /// Serve a JSON REST API under /api on the receiver port
+    #[arg(long, env = "AGENTTOP_API")]
+    api: bool,
+
+    /// Serve Prometheus metrics at /metrics on the receiver port
+    #[arg(long, env = "AGENTTOP_METRICS")]
+    metrics: bool,
+
+    /// Stream received events at /events/stream, for `agenttop tail` in another terminal
+    #[arg(long, env = "AGENTTOP_EVENT_STREAM")]
+    event_stream: bool,
+
+    /// In headless mode, log a one-line activity summary every SECS seconds
+    #[arg(
+        long,
+        value_name = "SECS",
+        requires = "headless",
+        env = "AGENTTOP_SUMMARY_INTERVAL"
+    )]
+    summary_interval: Option<u64>,
+
+    /// Render the dashboard to a snapshot file and exit (text, html)
+    #[arg(long, value_name = "FORMAT")]
+    snapshot: Option<SnapshotFormat>,
+
+    /// Snapshot output path (default: a timestamped file in the data directory)
+    #[arg(long, value_name = "PATH", requires = "snapshot")]
+    output: Option<std::path::PathBuf>,
+
+    /// Query the database once, print a summary and exit (1 if a --max-* limit is exceeded)
+    #[arg(long)]
+    once: bool,
+
+    /// Time range checked by --once, e.g. "1h", "24h", "since 09:00"
+    #[arg(long, value_name = "RANGE", default_value = "24h", requires = "once")]
+    range: String,
+
+    /// Print the --once summary as JSON
+    #[arg(long, requires = "once")]
+    json: bool,
+
+    /// --once fails if the tool error rate (percent) is above this
+    #[arg(long, value_name = "PCT", requires = "once")]
+    max_error_rate: Option<f64>,
+
+    /// --once fails if there are more API errors than this
+    #[arg(long, value_name = "N", requires = "once")]
+    max_api_errors: Option<u64>,
+
+    /// --once fails if the cost (USD) is above this
+    #[arg(long, value_name = "USD", requires = "once")]
+    max_cost: Option<f64>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print a summary report (totals, tools, cost per model, top errors) and exit
+    Report {
+        /// Time range, e.g. "24h", "last 7d", "since 09:00", "2025-01-10..2025-01-12", "all"
+        #[arg(long, value_name = "RANGE", default_value = "24h")]
+        range: String,
+
+        /// Report on one session instead (its id, or the start of it)
+        #[arg(long, value_name = "ID", conflicts_with = "range")]
+        session: Option<String>,
+
+        /// Output format of the --session report (text, md)
+        #[arg(long, value_enum, default_value_t, requires = "session")]
+        format: report::session::ReportFormat,
+    },
+    /// Print totals (tokens, cost, tools, errors, sessions) as text or versioned JSON
+    Stats {
+        /// Only activity at or after this time: a date/time ("2025-01-10", "09:00") or a span ("24h", "7d")
+        #[arg(long, value_name = "WHEN")]
+        since: Option<String>,
+
+        /// Only activity before this time ("2025-01-12", "2025-01-12 18:00"; a bare date is inclusive)
+        #[arg(long, value_name = "WHEN")]
+        until: Option<String>,
+
+        /// Print JSON (see "Stats JSON" in the README for the schema)
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print spend grouped by day, week, project or model
+    Cost {
+        /// Grouping (day, week, project, model); projects come from the agents'
+        /// `project` resource attribute
+        #[arg(long, value_enum, default_value_t)]
+        group_by: report::cost::CostGroup,
+
+        /// Only spend at or after this time: a date/time ("2025-01-01") or a span ("30d")
+        #[arg(long, value_name = "WHEN")]
+        since: Option<String>,
+
+        /// Only spend before this time ("2025-02-01"; a bare date is inclusive)
+        #[arg(long, value_name = "WHEN")]
+        until: Option<String>,
+
+        /// Print JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print lines of code per dollar, commits per hour and cost per commit per week or
+    /// session (from the code metrics Claude Code reports)
+    Productivity {
+        /// Grouping (week, session)
+        #[arg(long, value_enum, default_value_t)]
+        group_by: report::productivity::ProductivityGroup,
+
+        /// Only sessions active at or after this time: a date/time ("2025-01-01") or a span ("30d")
+        #[arg(long, value_name = "WHEN")]
+        since: Option<String>,
+
+        /// Only sessions started before this time ("2025-02-01"; a bare date is inclusive)
+        #[arg(long, value_name = "WHEN")]
+        until: Option<String>,
+
+        /// Print JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print turn cost by prompt size, with the correlation between prompt length and
+    /// cost
+    PromptCost {
+        /// Only turns started at or after this time: a date/time ("2025-01-01") or a span ("30d")
+        #[arg(long, value_name = "WHEN")]
+        since: Option<String>,
+
+        /// Only turns started before this time ("2025-02-01"; a bare date is inclusive)
+        #[arg(long, value_name = "WHEN")]
+        until: Option<String>,
+
+        /// Print JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print how much of the model's context window prompts filled, per day and for the
+    /// sessions that ran near the limit
+    Context {
+        /// Only requests at or after this time: a date/time ("2025-01-01") or a span ("30d")
+        #[arg(long, value_name = "WHEN")]
+        since: Option<String>,
+
+        /// Only requests before this time ("2025-02-01"; a bare date is inclusive)
+        #[arg(long, value_name = "WHEN")]
+        until: Option<String>,
+
+        /// Print JSON instead of tables
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print tool and API errors by category (permission denied, timeout, not found, ...)
+    /// and by tool or model
+    Errors {
+        /// Only errors at or after this time: a date/time ("2025-01-01") or a span ("30d")
+        #[arg(long, value_name = "WHEN")]
+        since: Option<String>,
+
+        /// Only errors before this time ("2025-02-01"; a bare date is inclusive)
+        #[arg(long, value_name = "WHEN")]
+        until: Option<String>,
+
+        /// Classify the stored errors again with the current `error_categories` rules first
+        #[arg(long)]
+        reclassify: bool,
+
+        /// Print JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print the detected providers side by side: spend, tokens, API latency, API
+    /// success and tool error rates
+    Compare {
+        /// Only events at or after this time: a date/time ("2025-01-01") or a span ("30d")
+        #[arg(long, value_name = "WHEN")]
+        since: Option<String>,
+
+        /// Only events before this time ("2025-02-01"; a bare date is inclusive)
+        #[arg(long, value_name = "WHEN")]
+        until: Option<String>,
+
+        /// Print JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print the commits made in each git repository the agents worked in, with the
+    /// session each was made in, its lines changed and its cost
+    Commits {
+        /// Only this repository (any directory inside it); default: the repositories of
+        /// the sessions' `cwd` attribute, else the current directory's
+        #[arg(long, value_name = "DIR")]
+        repo: Option<std::path::PathBuf>,
+
+        /// Only commits and spend at or after this time: a date/time ("2025-01-01") or a span ("7d")
+        #[arg(long, value_name = "WHEN")]
+        since: Option<String>,
+
+        /// Only commits and spend before this time ("2025-02-01"; a bare date is inclusive)
+        #[arg(long, value_name = "WHEN")]
+        until: Option<String>,
+
+        /// Print JSON instead of tables
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print the JSON Schema of an output format (event, tool-metrics, stats, cost)
+    Schema {
+        #[arg(value_enum, required_unless_present = "output_dir")]
+        kind: Option<schema::SchemaKind>,
+
+        /// Write every schema as <kind>.schema.json into this directory instead
+        #[arg(long, value_name = "DIR", conflicts_with = "kind")]
+        output_dir: Option<std::path::PathBuf>,
+    },
+    /// Export raw rows from the metrics database to files and exit
+    Export {
+        /// Output format
+        #[arg(long, value_enum, default_value_t = TableFormat::Csv)]
+        format: TableFormat,
+
+        /// Only rows at or after this time: a date/time ("2025-01-10", "09:00") or a span ("24h", "7d")
+        #[arg(long, value_name = "WHEN")]
+        since: Option<String>,
+
+        /// Only rows before this time ("2025-01-12", "2025-01-12 18:00"; a bare date is inclusive)
+        #[arg(long, value_name = "WHEN")]
+        until: Option<String>,
+
+        /// Table to export (repeatable; default: all tables)
+        #[arg(long = "table", value_name = "TABLE", value_parser = clap::builder::PossibleValuesParser::new(storage::TABLES))]
+        tables: Vec<String>,
+
+        /// Directory for the exported files (default: "exports" in the data directory)
+        #[arg(long, value_name = "DIR")]
+        output_dir: Option<std::path::PathBuf>,
+
+        /// Stream token usage (timestamp, token_type, count, model, provider) as CSV to
+        /// stdout instead, reading the database while it is recorded into
+        #[arg(long, conflicts_with_all = ["format", "tables", "output_dir"])]
+        stdout: bool,
+    },
+    /// Delete old rows from the metrics database and exit
+    Prune {
+        /// Delete rows older than this: a span ("30d", "12w") or a date/time ("2025-01-01")
+        #[arg(long, value_name = "WHEN")]
+        older_than: String,
+
+        /// Only report how many rows would be deleted
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Check provider settings, the receiver port and the receiver → storage pipeline
+    Doctor,
+    /// Print each newly received log event as a JSON line
+    Tail {
+        /// Only events whose name matches this glob, e.g. "*tool_result" (repeatable)
+        #[arg(long = "filter", value_name = "GLOB")]
+        filters: Vec<String>,
+    },
+    /// Receive telemetry and print a table of activity every interval (no TUI)
+    Watch {
+        /// Append plain-text tables instead of redrawing the screen (for CI logs)
+        #[arg(long)]
+        plain: bool,
+
+        /// Time between tables, e.g. "30s", "5m"
+        #[arg(long, value_name = "INTERVAL", default_value = "30s", value_parser = watch::parse_interval)]
+        interval: std::time::Duration,
+    },
+    /// Run a read-only SQL query against the metrics database and print the result
+    Query {
+        /// A single SELECT-like statement, e.g. "SELECT event_name, count(*) FROM log_events GROUP BY 1"
+        sql: String,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = QueryFormat::Table)]
+        format: QueryFormat,
+
+        /// Maximum number of rows to print
+        #[arg(long, value_name = "N", default_value_t = 1000)]
+        limit: usize,
+    },
+    /// Print the all-time aggregates served on /metrics, e.g. for a textfile collector
+    Metrics {
+        /// OpenMetrics 1.0 text instead of the Prometheus text format
+        #[arg(long)]
+        openmetrics: bool,
+    },
+    /// Print spend against each configured budget and the past periods recorded
+    Budget {
+        /// Recorded periods listed after the current ones
+        #[arg(long, value_name = "N", default_value_t = 20)]
+        history: usize,
+
+        /// Print JSON instead of tables
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print each configured quota's current usage window (capacity left and reset
+    /// time) and its recent windows
+    Quota {
+        /// Print JSON instead of tables
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print the email digest for the previous period, or mail it with --send
+    Digest {
+        /// Period covered (default: the configured one, else daily)
+        #[arg(long, value_enum)]
+        period: Option<budget::BudgetPeriod>,
+
+        /// Mail it through the configured SMTP server instead of printing it
+        #[arg(long)]
+        send: bool,
+    },
+    /// Push synthetic events through a receiver into a scratch database and report
+    /// throughput
+    Bench {
+        /// Number of events, e.g. 100000 or 1M
+        #[arg(long, value_name = "N", default_value = "100k", value_parser = bench::parse_count)]
+        events: usize,
+
+        /// Events per OTLP request
+        #[arg(long, value_name = "N", default_value_t = 1000)]
+        batch_size: usize,
+    },
+    /// Interactive read-only SQL prompt with table and column completion
+    Repl {
+        /// Maximum number of rows to print per query (change with .limit)
+        #[arg(long, value_name = "N", default_value_t = 1000)]
+        limit: usize,
+    },
+    /// Receive and store telemetry without the TUI (like --headless); pair with `view`
+    Serve {
+        #[command(flatten)]
+        flags: HeadlessFlags,
+    },
+    /// Open the TUI on the database `serve` writes, read-only and without receiving
+    View {
+        /// Query a remote agenttop running with --api instead, e.g. http://devbox:4318
+        #[arg(long, value_name = "URL", value_parser = providers::parse_endpoint)]
+        remote: Option<String>,
+    },
+    /// Run the headless receiver in the background
+    Daemon {
+        #[command(subcommand)]
+        action: Option<DaemonAction>,
+
+        #[command(flatten)]
+        flags: HeadlessFlags,
+    },
+    /// Start the headless receiver at login (systemd user unit or launchd agent)
+    Service {
+        #[command(subcommand)]
+        action: ServiceAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum DaemonAction {
+    /// Start the daemon (default)
+    Start,
+    /// Stop the running daemon
+    Stop,
+    /// Print whether the daemon is running
+    Status,
+}
+
+#[derive(Subcommand)]
+enum ServiceAction {
+    /// Write the service definition for this platform
+    Install {
+        /// Print the definition instead of writing it
+        #[arg(long)]
+        print: bool,
+
+        #[command(flatten)]
+        flags: HeadlessFlags,
+    },
+    /// Remove the service definition
+    Uninstall,
+}
+
+/// Flags passed on to a background `agenttop --headless`
+#[derive(clap::Args)]
+struct HeadlessFlags {
+    /// Serve the JSON REST API under /api
+    #[arg(long)]
+    api: bool,
+
+    /// Serve Prometheus metrics at /metrics
+    #[arg(long)]
+    metrics: bool,
+
+    /// Stream received events at /events/stream
+    #[arg(long)]
+    event_stream: bool,
+
+    /// Log a one-line activity summary every SECS seconds
+    #[arg(long, value_name = "SECS")]
+    summary_interval: Option<u64>,
+}
+
+impl HeadlessFlags {
+    /// The flags as arguments, plus `--config` (made absolute) when one was given
+    fn to_args(&self, config: Option<&std::path::Path>) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(path) = config {
+            let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+            args.extend(["--config".to_string(), path.display().to_string()]);
+        }
+        if self.api {
+            args.push("--api".to_string());
+        }
+        if self.metrics {
+            args.push("--metrics".to_string());
+        }
+        if self.event_stream {
+            args.push("--event-stream".to_string());
+        }
+        if let Some(secs) = self.summary_interval {
+            args.extend(["--summary-interval".to_string(), secs.to_string()]);
+        }
+        args
+    }
+}
+
+fn run_daemon(
+    action: Option<DaemonAction>,
+    headless_args: &[String],
+    bind: std::net::SocketAddr,
+) -> Result<()> {
+    match action.unwrap_or(DaemonAction::Start) {
+        DaemonAction::Start => {
+            let pid = daemon::start(headless_args, bind)?;
+            println!("agenttop daemon started (pid {})", pid);
+            println!("Log: {}", daemon::log_path()?.display());
+        }
+        DaemonAction::Stop => {
+            if daemon::stop()? {
+                println!("agenttop daemon stopped");
+            } else {
+                println!("agenttop daemon is not running");
+            }
+        }
+        DaemonAction::Status => match daemon::running_pid()? {
+            Some(pid) => println!("agenttop daemon is running (pid {})", pid),
+            None => {
+                println!("agenttop daemon is not running");
+                std::process::exit(1);
+            }
+        },
+    }
+    Ok(())
+}
+
+fn run_service(
+    action: ServiceAction,
+    config: Option<&std::path::Path>,
+    bind: std::net::SocketAddr,
+) -> Result<()> {
+    match action {
+        ServiceAction::Install { print: true, flags } => {
+            print!(
+                "{}",
+                daemon::service_definition(&flags.to_args(config), bind)?
+            );
+        }
+        ServiceAction::Install {
+            print: false,
+            flags,
+        } => {
+            let (path, enable) = daemon::install_service(&flags.to_args(config), bind)?;
+            println!("Wrote {}", path.display());
+            println!("Enable it with: {}", enable);
+        }
+        ServiceAction::Uninstall => {
+            let (path, disable) = daemon::uninstall_service()?;
+            println!("Removed {}", path.display());
+            println!("If it was running, stop it with: {}", disable);
+        }
+    }
+    Ok(())
+}
+
+/// Terminal size used for snapshots rendered from the command line
+const SNAPSHOT_WIDTH: u16 = 120;
+const SNAPSHOT_HEIGHT: u16 = 40;
+
+fn run_snapshot(format: SnapshotFormat, output: Option<std::path::PathBuf>) -> Result<()> {
+    let mut app = tui::app::App::new(StorageHandle::attach(&storage::db_path()?)?);
+    app.refresh()?;
+
+    let path = match output {
+        Some(path) => {
+            let content = tui::snapshot::render(&app, format, SNAPSHOT_WIDTH, SNAPSHOT_HEIGHT)?;
+            std::fs::write(&path, content)?;
+            path
+        }
+        None => tui::snapshot::save(
+            &app,
+            &config::data_dir()?.join("snapshots"),
+            format,
+            SNAPSHOT_WIDTH,
+            SNAPSHOT_HEIGHT,
+        )?,
+    };
+    println!("Snapshot saved to {}", path.display());
+    Ok(())
+}
+
+/// Run the --once check; Ok(false) if a limit was exceeded
+fn run_once(range: &str, json: bool, policy: &report::check::Policy) -> Result<bool> {
+    let filter = tui::app::TimeFilter::parse(range, chrono::Local::now())?;
+    let storage = StorageHandle::attach(&storage::db_path()?)?;
+    let result = report::check::run(&storage, &filter, policy)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&result)?);
+    } else {
+        print!("{}", result.render_text());
+    }
+    Ok(result.passed)
+}
+
+fn run_report(range: &str) -> Result<()> {
+    let filter = tui::app::TimeFilter::parse(range, chrono::Local::now())?;
+    print!(
+        "{}",
+        report::render(&StorageHandle::attach(&storage::db_path()?)?, &filter)?
+    );
+    Ok(())
+}
+
+fn run_cost(
+    group_by: report::cost::CostGroup,
+    since: Option<&str>,
+    until: Option<&str>,
+    json: bool,
+) -> Result<()> {
+    let (since, until) = parse_time_bounds(since, until)?;
+    let storage = StorageHandle::attach(&storage::db_path()?)?;
+    let cost = report::cost::collect(&storage, group_by, since, until)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&cost)?);
+    } else {
+        print!("{}", cost.render_text());
+    }
+    Ok(())
+}
+
+fn run_productivity(
+    group_by: report::productivity::ProductivityGroup,
+    since: Option<&str>,
+    until: Option<&str>,
+    json: bool,
+) -> Result<()> {
+    let (since, until) = parse_time_bounds(since, until)?;
+    let storage = StorageHandle::attach(&storage::db_path()?)?;
+    let productivity = report::productivity::collect(&storage, group_by, since, until)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&productivity)?);
+    } else {
+        print!("{}", productivity.render_text());
+    }
+    Ok(())
+}
+
+fn run_prompt_cost(since: Option<&str>, until: Option<&str>, json: bool) -> Result<()> {
+    let (since, until) = parse_time_bounds(since, until)?;
+    let storage = StorageHandle::attach(&storage::db_path()?)?;
+    let analysis = report::prompt_cost::collect(&storage, since, until)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&analysis)?);
+    } else {
+        print!("{}", report::prompt_cost::render_text(&analysis));
+    }
+    Ok(())
+}
+
+fn run_context(since: Option<&str>, until: Option<&str>, json: bool) -> Result<()> {
+    let (since, until) = parse_time_bounds(since, until)?;
+    let storage = StorageHandle::attach(&storage::db_path()?)?;
+    let usage = report::context::collect(&storage, since, until)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&usage)?);
+    } else {
+        print!("{}", report::context::render_text(&usage));
+    }
+    Ok(())
+}
+
+fn run_errors(
+    since: Option<&str>,
+    until: Option<&str>,
+    reclassify: bool,
+    json: bool,
+) -> Result<()> {
+    let (since, until) = parse_time_bounds(since, until)?;
+    let storage = if reclassify {
+        let storage = StorageHandle::new()?;
+        let errors = storage.reclassify_errors()?;
+        eprintln!("Reclassified {} errors", errors);
+        storage
+    } else {
+        StorageHandle::attach(&storage::db_path()?)?
+    };
+    let errors = report::errors::collect(&storage, since, until)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&errors)?);
+    } else {
+        print!("{}", errors.render_text());
+    }
+    Ok(())
+}
+
+fn run_compare(since: Option<&str>, until: Option<&str>, json: bool) -> Result<()> {
+    let (since, until) = parse_time_bounds(since, until)?;
+    let storage = StorageHandle::attach(&storage::db_path()?)?;
+    let comparison = report::providers::collect(&storage, since, until)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&comparison)?);
+    } else {
+        print!("{}", comparison.render_text());
+    }
+    Ok(())
+}
+
+fn run_commits(
+    repo: Option<&std::path::Path>,
+    since: Option<&str>,
+    until: Option<&str>,
+    json: bool,
+) -> Result<()> {
+    let (since, until) = parse_time_bounds(since, until)?;
+    let storage = StorageHandle::attach(&storage::db_path()?)?;
+    let reports = report::commits::collect(&storage, repo, since, until)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+    } else {
+        let tables: Vec<String> = reports.iter().map(|r| r.render_text()).collect();
+        print!("{}", tables.join("\n"));
+    }
+    Ok(())
+}
+
+fn run_schema(
+    kind: Option<schema::SchemaKind>,
+    output_dir: Option<std::path::PathBuf>,
+) -> Result<()> {
+    match (kind, output_dir) {
+        (_, Some(dir)) => {
+            for path in schema::write_all(&dir)? {
+                println!("{}", path.display());
+            }
+        }
+        (Some(kind), None) => {
+            println!("{}", serde_json::to_string_pretty(&schema::generate(kind))?);
+        }
+        (None, None) => unreachable!("clap requires a kind or --output-dir"),
+    }
+    Ok(())
+}
+
+fn run_session_report(session_id: &str, format: report::session::ReportFormat) -> Result<()> {
+    let storage = StorageHandle::attach(&storage::db_path()?)?;
+    print!("{}", report::session::render(&storage, session_id, format)?);
+    Ok(())
+}
+
+fn run_stats(since: Option<&str>, until: Option<&str>, json: bool) -> Result<()> {
+    let (since, until) = parse_time_bounds(since, until)?;
+    let storage = StorageHandle::attach(&storage::db_path()?)?;
+    let stats = report::stats::collect(&storage, since, until)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+    } else {
+        print!("{}", stats.render_text());
+    }
+    Ok(())
+}
+
+/// Optional start and end of a time range
+type TimeBounds = (Option<DateTime<Utc>>, Option<DateTime<Utc>>);
+
+/// Resolve --since/--until to timestamps. `since` may also be a span such as "24h".
+fn parse_time_bounds(since: Option<&str>, until: Option<&str>) -> Result<TimeBounds> {
+    use tui::app::TimeFilter;
+
+    let now = chrono::Local::now();
+    let since = match since {
+        Some(s) => TimeFilter::parse(&format!("{}..", s), now)
+            .or_else(|_| TimeFilter::parse(s, now))?
+            .since(),
+        None => None,
+    };
+    let until = match until {
+        Some(u) => TimeFilter::parse(&format!("..{}", u), now)?.until(),
+        None => None,
+    };
+    if let (Some(since), Some(until)) = (since, until)
+        && since >= until
+    {
+        anyhow::bail!("--since must be before --until");
+    }
+    Ok((since, until))
+}
+
+fn run_export(
+    format: TableFormat,
+    since: Option<&str>,
+    until: Option<&str>,
+    tables: &[String],
+    output_dir: Option<std::path::PathBuf>,
+) -> Result<()> {
+    let (since, until) = parse_time_bounds(since, until)?;
+    let tables: Vec<&str> = if tables.is_empty() {
+        storage::TABLES.to_vec()
+    } else {
+        tables.iter().map(String::as_str).collect()
+    };
+    let dir = match output_dir {
+        Some(dir) => dir,
+        None => config::data_dir()?.join("exports"),
+    };
+
+    let storage = StorageHandle::open_for_export(&storage::db_path()?)?;
+    for (table, path, rows) in export::export_tables(&storage, &tables, format, since, until, &dir)?
+    {
+        println!("{:<16} {:>8} rows  {}", table, rows, path.display());
+    }
+    Ok(())
 }
 
-fn run_setup(provider_name: &str) -> Result<()> {
+fn run_token_usage_csv(since: Option<&str>, until: Option<&str>) -> Result<()> {
+    let (since, until) = parse_time_bounds(since, until)?;
+    let storage = StorageHandle::attach(&storage::db_path()?)?;
+    let batches = storage.stream_token_usage(since, until)?;
+    let mut stdout = std::io::BufWriter::new(std::io::stdout().lock());
+    match export::write_token_usage_csv(&mut stdout, batches) {
+        Err(e)
+            if e.downcast_ref::<std::io::Error>()
+                .is_some_and(|e| e.kind() == std::io::ErrorKind::BrokenPipe) =>
+        {
+            // The reading end closed early, e.g. `| head`
+            Ok(())
+        }
+        result => result.map(|_| ()),
+    }
+}
+
+fn run_prune(older_than: &str, dry_run: bool) -> Result<()> {
+    let (Some(before), _) = parse_time_bounds(Some(older_than), None)? else {
+        anyhow::bail!(
+            "--older-than needs a span or a date, not \"{}\"",
+            older_than
+        );
+    };
+
+    let storage = StorageHandle::new()?;
+    let counts = storage.prune(before, dry_run)?;
+    let verb = if dry_run {
+        "would be deleted"
+    } else {
+        "deleted"
+    };
+    println!(
+        "Rows before {} {}:",
+        before
+            .with_timezone(&chrono::Local)
+            .format("%Y-%m-%d %H:%M"),
+        verb
+    );
+    for (table, rows) in &counts {
+        println!("  {:<16} {:>8}", table, rows);
+    }
+    println!(
+        "  {:<16} {:>8}",
+        "total",
+        counts.iter().map(|(_, rows)| rows).sum::<u64>()
+    );
+    Ok(())
+}
+
+fn run_query(sql: &str, format: QueryFormat, limit: usize) -> Result<()> {
+    let storage = StorageHandle::attach(&storage::db_path()?)?;
+    let result = storage.run_query(sql, limit)?;
+    let mut stdout = std::io::stdout().lock();
+    export::write_query_result(&mut stdout, format, &result)?;
+    if result.truncated && format != QueryFormat::Table {
+        eprintln!("Output truncated to {} rows (use --limit)", limit);
+    }
+    Ok(())
+}
+
+fn run_metrics(openmetrics: bool) -> Result<()> {
+    let storage = StorageHandle::attach(&storage::db_path()?)?;
+    let tools = storage.get_tool_metrics(None, None)?;
+    let tokens = storage.get_token_metrics(None, None)?;
+    let format = if openmetrics {
+        prometheus::TextFormat::OpenMetrics
+    } else {
+        prometheus::TextFormat::Prometheus
+    };
+    // This process receives nothing, so there are no receiver metrics to report
+    print!(
+        "{}",
+        prometheus::render(&tools, &tokens, None, None, None, format)
+    );
+    Ok(())
+}
+
+fn run_quota(quotas: &[quota::Quota], json: bool) -> Result<()> {
+    let storage = StorageHandle::attach(&storage::db_path()?)?;
+    let report = report::quota::collect(&storage, quotas, chrono::Utc::now())?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print!("{}", report.render_text());
+    }
+    Ok(())
+}
+
+fn run_budget(budgets: &[budget::Budget], history: usize, json: bool) -> Result<()> {
+    let storage = StorageHandle::attach(&storage::db_path()?)?;
+    let report = report::budget::collect(&storage, budgets, history, chrono::Local::now())?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print!("{}", report.render_text());
+    }
+    Ok(())
+}
+
+fn run_digest(
+    config: Option<&digest::DigestConfig>,
+    period: Option<budget::BudgetPeriod>,
+    send: bool,
+) -> Result<()> {
+    let storage = StorageHandle::attach(&storage::db_path()?)?;
+    let now = chrono::Local::now();
+    if send {
+        let mut config = config
+            .cloned()
+            .context("No `digest` section in the config file")?;
+        config.period = period.unwrap_or(config.period);
+        digest::send(&storage, &config, now)?;
+        println!("Sent digest to {}", config.to.join(", "));
+    } else {
+        let period = period
+            .or(config.map(|c| c.period))
+            .unwrap_or(budget::BudgetPeriod::Daily);
+        let (subject, body) = digest::render(&storage, period, now)?;
+        print!("{}\n\n{}", subject, body);
+    }
+    Ok(())
+}
+
+/// Log a summary of the activity received in each `interval`
+fn spawn_summary_logger(storage: StorageHandle, interval: std::time::Duration) {
+    std::thread::spawn(move || {
+        let mut since = Utc::now();
+        let mut events = self_metrics::SELF_METRICS
+            .receiver
+            .snapshot()
+            .events_received;
+        loop {
+            std::thread::sleep(interval);
+            let now = Utc::now();
+            let total = self_metrics::SELF_METRICS
+                .receiver
+                .snapshot()
+                .events_received;
+            match report::summary_line(&storage, since, total - events) {
+                Ok(line) => tracing::info!("Last {}s: {}", interval.as_secs(), line),
+                Err(e) => tracing::warn!("Could not build summary: {}", e),
+            }
+            since = now;
+            events = total;
+        }
+    });
+}
+
+fn run_setup(provider_name: &str, endpoint: &str) -> Result<()> {
     let providers_to_setup: Vec<&str> = if provider_name == "all" {
         vec!["claude", "gemini", "qwen"]
     } else {
@@ -42,7 +1007,7 @@ fn run_setup(provider_name: &str) -> Result<()> {
                 println!("[otel]");
                 println!("exporter = \"otlp-http\"");
                 println!("[otel.exporter.otlp-http]");
-                println!("endpoint = \"http://localhost:4318/v1/logs\"");
+                println!("endpoint = \"{}/v1/logs\"", endpoint);
                 println!();
                 continue;
             }
@@ -56,7 +1021,7 @@ fn run_setup(provider_name: &str) -> Result<()> {
         if let Some(provider) = PROVIDER_REGISTRY.get(provider_id) {
             println!("Configuring {} telemetry...", provider.name());
 
-            match provider.ensure_configured() {
+            match provider.ensure_configured(endpoint) {
                 Ok(true) => {
                     println!(
                         "  Configured {} settings at {:?}",
@@ -81,13 +1046,240 @@ fn run_setup(provider_name: &str) -> Result<()> {
     Ok(())
 }
 
+/// Where the local agents reach a receiver bound to `addr`
+fn local_endpoint(addr: std::net::SocketAddr) -> String {
+    format!("http://localhost:{}", addr.port())
+}
+
+/// Where to receive: `bind` if it is free, otherwise what `--on-port-conflict` picks.
+/// None means view-only, with no receiver.
+async fn choose_receiver_addr(
+    bind: std::net::SocketAddr,
+    fallback: port::Fallback,
+    headless: bool,
+) -> Result<Option<std::net::SocketAddr>> {
+    let Some(owner) = port::probe(bind).await else {
+        return Ok(Some(bind));
+    };
+    let alternate = port::alternate_port(bind);
+    let choice = match fallback {
+        port::Fallback::Ask if port::interactive() => {
+            port::ask(bind, &owner, alternate, !headless)?
+        }
+        port::Fallback::Ask => port::Fallback::Fail,
+        other => other,
+    };
+    match (choice, alternate) {
+        (port::Fallback::View, _) if !headless => Ok(None),
+        (port::Fallback::Alternate, Some(alternate)) => {
+            point_agents_at(&local_endpoint(alternate));
+            Ok(Some(alternate))
+        }
+        _ => anyhow::bail!(
+            "{} is already in use by {} (see --on-port-conflict)",
+            bind,
+            owner.describe()
+        ),
+    }
+}
+
+/// Rewrite the settings of agents already set up for agenttop to send to `endpoint`
+fn point_agents_at(endpoint: &str) {
+    for provider in PROVIDER_REGISTRY.providers() {
+        if !provider.supports_setup() || provider.is_configured() != Some(true) {
+            continue;
+        }
+        match provider.ensure_configured(endpoint) {
+            Ok(_) => eprintln!(
+                "Pointed {} at {}; restart it for this to take effect",
+                provider.name(),
+                endpoint
+            ),
+            Err(e) => eprintln!("Could not reconfigure {}: {}", provider.name(), e),
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    let args = Args::parse();
+    let mut args = Args::parse();
+
+    // An explicit --config must load; a broken default config falls back to the defaults
+    let mut settings = match &args.config {
+        Some(path) => config::Settings::load(Some(path))?,
+        None => config::Settings::load(None).unwrap_or_else(|e| {
+            eprintln!("Warning: ignoring config file: {:#}", e);
+            config::Settings::default()
+        }),
+    };
+    settings.apply_env(|name| std::env::var(name).ok())?;
+    if let Some(path) = args.db.take() {
+        settings.storage.path = Some(path);
+    }
+    if let Some(bind) = args.bind {
+        settings.receiver.bind = bind;
+    }
+    storage::set_db_path(settings.db_path()?);
+    storage::set_query_timeout(settings.storage.query_timeout());
+    cache::set_capacities(settings.cache.clone());
+    taxonomy::set_rules(&settings.app.error_categories)?;
+    context::set_windows(&settings.app.context_windows)?;
+
+    // Handle subcommands
+    let mut view_only = false;
+    let mut remote = None;
+    match args.command {
+        Some(Command::Report {
+            range,
+            session,
+            format,
+        }) => {
+            return match session {
+                Some(id) => run_session_report(&id, format),
+                None => run_report(&range),
+            };
+        }
+        Some(Command::Stats { since, until, json }) => {
+            return run_stats(since.as_deref(), until.as_deref(), json);
+        }
+        Some(Command::Cost {
+            group_by,
+            since,
+            until,
+            json,
+        }) => return run_cost(group_by, since.as_deref(), until.as_deref(), json),
+        Some(Command::Productivity {
+            group_by,
+            since,
+            until,
+            json,
+        }) => return run_productivity(group_by, since.as_deref(), until.as_deref(), json),
+        Some(Command::PromptCost { since, until, json }) => {
+            return run_prompt_cost(since.as_deref(), until.as_deref(), json);
+        }
+        Some(Command::Context { since, until, json }) => {
+            return run_context(since.as_deref(), until.as_deref(), json);
+        }
+        Some(Command::Errors {
+            since,
+            until,
+            reclassify,
+            json,
+        }) => return run_errors(since.as_deref(), until.as_deref(), reclassify, json),
+        Some(Command::Compare { since, until, json }) => {
+            return run_compare(since.as_deref(), until.as_deref(), json);
+        }
+        Some(Command::Commits {
+            repo,
+            since,
+            until,
+            json,
+        }) => return run_commits(repo.as_deref(), since.as_deref(), until.as_deref(), json),
+        Some(Command::Schema { kind, output_dir }) => return run_schema(kind, output_dir),
+        Some(Command::Export {
+            since,
+            until,
+            stdout: true,
+            ..
+        }) => return run_token_usage_csv(since.as_deref(), until.as_deref()),
+        Some(Command::Export {
+            format,
+            since,
+            until,
+            tables,
+            output_dir,
+            stdout: false,
+        }) => {
+            return run_export(
+                format,
+                since.as_deref(),
+                until.as_deref(),
+                &tables,
+                output_dir,
+            );
+        }
+        Some(Command::Prune {
+            older_than,
+            dry_run,
+        }) => return run_prune(&older_than, dry_run),
+        Some(Command::Doctor) => {
+            let checks = doctor::run(settings.receiver.bind).await;
+            print!("{}", doctor::render(&checks));
+            if checks.iter().any(|c| c.status == doctor::Status::Fail) {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(Command::Tail { filters }) => {
+            return tail::run(&filters, settings.receiver.bind).await;
+        }
+        Some(Command::Watch { plain, interval }) => {
+            return watch::run(interval, plain, settings.receiver.bind).await;
+        }
+        Some(Command::Query { sql, format, limit }) => return run_query(&sql, format, limit),
+        Some(Command::Repl { limit }) => return repl::run(limit),
+        Some(Command::Bench { events, batch_size }) => {
+            print!("{}", bench::render(&bench::run(events, batch_size).await?));
+            return Ok(());
+        }
+        Some(Command::Metrics { openmetrics }) => return run_metrics(openmetrics),
+        Some(Command::Budget { history, json }) => {
+            return run_budget(&settings.app.budgets, history, json);
+        }
+        Some(Command::Quota { json }) => return run_quota(&settings.app.quotas, json),
+        Some(Command::Digest { period, send }) => {
+            return run_digest(settings.app.digest.as_ref(), period, send);
+        }
+        Some(Command::Daemon { action, flags }) => {
+            return run_daemon(
+                action,
+                &flags.to_args(args.config.as_deref()),
+                settings.receiver.bind,
+            );
+        }
+        Some(Command::Service { action }) => {
+            return run_service(action, args.config.as_deref(), settings.receiver.bind);
+        }
+        Some(Command::Serve { flags }) => {
+            args.headless = true;
+            args.api |= flags.api;
+            args.metrics |= flags.metrics;
+            args.event_stream |= flags.event_stream;
+            args.summary_interval = flags.summary_interval.or(args.summary_interval);
+        }
+        Some(Command::View { remote: url }) => {
+            view_only = true;
+            remote = url;
+        }
+        None => {}
+    }
 
     // Handle --setup flag
     if let Some(provider_name) = args.setup {
-        return run_setup(&provider_name);
+        return run_setup(&provider_name, &args.endpoint);
+    }
+
+    // Handle --snapshot flag
+    if let Some(format) = args.snapshot {
+        return run_snapshot(format, args.output);
+    }
+
+    // Handle --once flag: exit 0 if healthy, 1 if a limit is exceeded, 2 if the check failed
+    if args.once {
+        let policy = report::check::Policy {
+            max_error_rate_pct: args.max_error_rate,
+            max_api_errors: args.max_api_errors,
+            max_cost_usd: args.max_cost,
+        };
+        let code = match run_once(&args.range, args.json, &policy) {
+            Ok(true) => 0,
+            Ok(false) => 1,
+            Err(e) => {
+                eprintln!("Error: {:#}", e);
+                2
+            }
+        };
+        std::process::exit(code);
     }
 
     // Initialize tracing
@@ -115,36 +1307,167 @@ async fn main() -> Result<()> {
             .init();
     }
 
+    if args.force_read_only {
+        if args.headless {
+            anyhow::bail!("--force-read-only shows the dashboard; it cannot run headless");
+        }
+        view_only = true;
+    }
+
+    // Claim the database, then decide where to receive, before touching agent settings
+    let lock = if view_only {
+        None
+    } else {
+        match storage::lock::InstanceLock::acquire(&storage::db_path()?, args.headless) {
+            Ok(lock) => Some(lock),
+            Err(e)
+                if e.is::<storage::lock::DatabaseLocked>()
+                    && args.on_port_conflict == port::Fallback::View
+                    && !args.headless =>
+            {
+                tracing::info!("{}", e);
+                view_only = true;
+                None
+            }
+            Err(e) => return Err(e),
+        }
+    };
+    let receive_on = if view_only {
+        None
+    } else {
+        choose_receiver_addr(settings.receiver.bind, args.on_port_conflict, args.headless).await?
+    };
+    // Without --api, /api/query still answers other local agenttop processes (`stats`,
+    // `query`, a view-only dashboard), which find the token in the lock file. Raw SQL
+    // always needs a token, even when the API is open.
+    let mut access = settings.receiver.api_access();
+    if access.sql_token.is_none() {
+        access.sql_token = Some(api::generate_token()?);
+    }
+    // Held until agenttop exits; dropped when viewing after all
+    let _lock = match (lock, receive_on) {
+        (Some(mut lock), Some(bind)) => {
+            lock.set_receiver(bind, access.sql_token.as_deref())?;
+            Some(lock)
+        }
+        _ => None,
+    };
+    let endpoint = local_endpoint(receive_on.unwrap_or(settings.receiver.bind));
+
     // Check and auto-configure Claude Code OTEL if needed (backwards compatibility)
-    if let Some(claude_provider) = PROVIDER_REGISTRY.get("claude_code")
-        && let Err(e) = claude_provider.ensure_configured()
+    if remote.is_none()
+        && let Some(claude_provider) = PROVIDER_REGISTRY.get("claude_code")
+        && let Err(e) = claude_provider.ensure_configured(&endpoint)
     {
         eprintln!("Warning: Could not auto-configure Claude Code OTEL: {}", e);
         eprintln!("Please manually enable OTEL in ~/.claude/settings.json");
         eprintln!("Or run: agenttop --setup claude");
     }
 
-    // Initialize storage handle (spawns storage actor thread)
-    let storage = StorageHandle::new()?;
+    if let Some(path) = &settings.path {
+        tracing::info!("Using config {}", path.display());
+    }
+    let alert_thresholds = AlertThresholds {
+        tool_error_rate_pct: args.alert_error_rate,
+        api_errors: args.alert_api_errors,
+        window_mins: args.alert_window,
+        ..AlertThresholds::default()
+    };
+    let Some(bind) = receive_on else {
+        // View-only: another agenttop (`serve`, or the one holding the port) writes the
+        // database and runs the exporters, alerts and hooks
+        let storage = match &remote {
+            Some(url) => {
+                tracing::info!("Viewing {} remotely", url);
+                StorageHandle::connect(url, settings.receiver.api_token.as_deref())?
+            }
+            None => {
+                tracing::info!("Viewing the database read-only");
+                StorageHandle::attach(&storage::db_path()?)
+                    .context("Nothing to view yet; start `agenttop serve` first")?
+            }
+        };
+        return tui::run(storage, alert_thresholds, settings, true).await;
+    };
+
+    // Initialize storage handle (spawns storage actor thread). The dashboard opens the
+    // database in the background so it shows up at once; receiving queues writes meanwhile.
+    let storage = if args.headless {
+        StorageHandle::new()?
+    } else {
+        StorageHandle::new_lazy()?
+    };
+    let storage = match settings.storage.journal {
+        true => storage.with_journal(&storage::journal_path(&storage::db_path()?))?,
+        false => storage,
+    };
+    let config = &settings.app;
+    if let Some(export) = config.otlp_export.clone() {
+        otlp_export::spawn(storage.clone(), export);
+    }
+    if let Some(export) = config.influx_export.clone() {
+        influx_export::spawn(storage.clone(), export);
+    }
+    if let Some(export) = config.graphite_export.clone() {
+        graphite_export::spawn(storage.clone(), export);
+    }
+    if let Some(mqtt) = config.mqtt.clone() {
+        mqtt::spawn(storage.clone(), mqtt);
+    }
+    alerting::spawn(storage.clone(), config.alert_rules.clone());
+    anomaly::spawn(storage.clone());
+    efficiency::spawn(storage.clone());
+    budget::spawn_monitor(storage.clone(), config.budgets.clone());
+    quota::spawn_monitor(storage.clone(), config.quotas.clone());
+    if let Some(digest) = config.digest.clone() {
+        digest::spawn(storage.clone(), digest);
+    }
+    hooks::spawn(config.hooks.clone());
+    if let Some(days) = settings.storage.retention_days {
+        storage::spawn_retention(storage.clone(), days);
+    }
+    let receiver_options = otlp::ReceiverOptions {
+        bind: Some(bind),
+        api: args.api,
+        access,
+        metrics: args.metrics,
+        event_stream: args.event_stream,
+        limits: settings.receiver.limits(),
+    };
 
     if args.headless {
         // Headless mode: just run the OTLP receiver
         tracing::info!("Running in headless mode (no TUI)");
-        tracing::info!("OTLP endpoint: http://127.0.0.1:4318");
+        tracing::info!("OTLP endpoint: http://{}", bind);
+        if args.api {
+            tracing::info!("REST API: http://{}/api", bind);
+        }
+        if args.metrics {
+            tracing::info!("Prometheus metrics: http://{}/metrics", bind);
+        }
+        if args.event_stream {
+            tracing::info!("Event stream: http://{}/events/stream", bind);
+        }
         tracing::info!("Press Ctrl+C to stop");
 
-        otlp::start_receiver(storage).await?;
+        if let Some(secs) = args.summary_interval {
+            spawn_summary_logger(storage.clone(), std::time::Duration::from_secs(secs.max(1)));
+        }
+
+        otlp::start_receiver(storage, receiver_options).await?;
     } else {
         // Start OTLP receiver in background
         let otlp_storage = storage.clone();
         tokio::spawn(async move {
-            if let Err(e) = otlp::start_receiver(otlp_storage).await {
+            if let Err(e) = otlp::start_receiver(otlp_storage, receiver_options).await {
                 tracing::error!("OTLP receiver error: {}", e);
             }
         });
 
-        // Run TUI (this blocks until quit)
-        tui::run(storage).await?;
+        // Run TUI (this blocks until quit), then store what arrived before quitting
+        let recorder = storage.clone();
+        tui::run(storage, alert_thresholds, settings, false).await?;
+        tokio::task::spawn_blocking(move || recorder.close()).await?;
     }
 
     Ok(())
@@ -0,0 +1,565 @@
+//! Publishing of summarized activity to an MQTT broker, configured under `mqtt` in the
+//! config file, so home-automation setups can react to agents: finished tool calls,
+//! spend (retained, so a new subscriber sees the current value) and alert firings.
+//!
+//! Only what this needs of MQTT 3.1.1 is implemented: connect, QoS 0 publish and ping.
+//! A lost connection is re-established, and messages that could not be sent meanwhile
+//! are sent once it is.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::mpsc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::alerting::ALERT_FEED;
+use crate::otlp::EVENT_FEED;
+use crate::storage::{LogEvent, StorageHandle, get_tool_display_name};
+use crate::tui::app::local_day_start;
+
+/// Seconds the broker waits for a packet before dropping the connection
+const KEEP_ALIVE_SECS: u16 = 60;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Pause between attempts to reach the broker while messages wait for it
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Messages kept while the broker is unreachable; past this the oldest are dropped
+const PENDING_CAPACITY: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MqttConfig {
+    /// host:port of the broker, e.g. "homeassistant.local:1883"
+    pub broker: String,
+    /// Client id (default: "agenttop-<host name>")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// Topic for each finished tool call ("" to publish none)
+    #[serde(default = "default_tool_result_topic")]
+    pub tool_result_topic: String,
+    /// Retained topic for spend and tokens, updated when they change ("" to publish none)
+    #[serde(default = "default_cost_topic")]
+    pub cost_topic: String,
+    /// Topic for alert rule firings ("" to publish none)
+    #[serde(default = "default_alert_topic")]
+    pub alert_topic: String,
+    /// Seconds between checks whether spend changed
+    #[serde(default = "default_cost_interval")]
+    pub cost_interval_secs: u64,
+}
+
+fn default_tool_result_topic() -> String {
+    "agenttop/tool_result".to_string()
+}
+
+fn default_cost_topic() -> String {
+    "agenttop/cost".to_string()
+}
+
+fn default_alert_topic() -> String {
+    "agenttop/alert".to_string()
+}
+
+fn default_cost_interval() -> u64 {
+    30
+}
+
+/// A message for the publisher thread
+#[derive(Debug, Clone, PartialEq)]
+struct Message {
+    topic: String,
+    payload: String,
+    retain: bool,
+}
+
+/// Payload published for a finished tool call
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ToolResultMessage {
+    pub tool: String,
+    pub success: bool,
+    pub duration_ms: Option<u64>,
+    pub error: Option<String>,
+    pub session_id: Option<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl ToolResultMessage {
+    /// Summarize a tool_result event (None for any other event)
+    pub fn from_event(event: &LogEvent) -> Option<Self> {
+        if !event.event_name.as_deref()?.ends_with("tool_result") {
+            return None;
+        }
+        let attribute = |key: &str| event.attributes.get(key).cloned();
+        Some(Self {
+            tool: get_tool_display_name(event.attributes.get("tool_name")?),
+            success: matches!(attribute("success").as_deref(), Some("true" | "1")),
+            duration_ms: attribute("duration_ms").and_then(|d| d.parse().ok()),
+            error: attribute("error"),
+            session_id: attribute("session.id"),
+            timestamp: event.timestamp,
+        })
+    }
+}
+
+/// Payload published on the cost topic
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CostMessage {
+    pub today_usd: f64,
+    pub total_usd: f64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// MQTT's variable-length "remaining length": 7 bits per byte, high bit = more follow
+fn encode_remaining_length(mut length: usize, out: &mut Vec<u8>) {
+    loop {
+        let mut byte = (length % 128) as u8;
+        length /= 128;
+        if length > 0 {
+            byte |= 0x80;
+        }
+        out.push(byte);
+        if length == 0 {
+            break;
+        }
+    }
+}
+
+/// A length-prefixed UTF-8 string or binary field
+fn encode_field(field: &[u8], out: &mut Vec<u8>) {
+    out.extend_from_slice(&(field.len() as u16).to_be_bytes());
+    out.extend_from_slice(field);
+}
+
+fn packet(kind: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![kind];
+    encode_remaining_length(body.len(), &mut packet);
+    packet.extend_from_slice(body);
+    packet
+}
+
+fn connect_packet(client_id: &str, username: Option<&str>, password: Option<&str>) -> Vec<u8> {
+    let mut flags = 0x02; // clean session
+    if username.is_some() {
+        flags |= 0x80;
+    }
+    if password.is_some() {
+        flags |= 0x40;
+    }
+    let mut body = Vec::new();
+    encode_field(b"MQTT", &mut body);
+    body.push(4); // protocol level 3.1.1
+    body.push(flags);
+    body.extend_from_slice(&KEEP_ALIVE_SECS.to_be_bytes());
+    encode_field(client_id.as_bytes(), &mut body);
+    for field in [username, password].into_iter().flatten() {
+        encode_field(field.as_bytes(), &mut body);
+    }
+    packet(0x10, &body)
+}
+
+fn publish_packet(message: &Message) -> Vec<u8> {
+    let mut body = Vec::new();
+    encode_field(message.topic.as_bytes(), &mut body);
+    body.extend_from_slice(message.payload.as_bytes());
+    packet(0x30 | u8::from(message.retain), &body)
+}
+
+const PINGREQ: [u8; 2] = [0xC0, 0x00];
+const PINGRESP: [u8; 2] = [0xD0, 0x00];
+
+/// Ping the broker and wait for its answer, which is the only packet it sends a
+/// publish-only client after CONNACK
+fn ping(stream: &mut TcpStream) -> Result<()> {
+    stream.write_all(&PINGREQ)?;
+    let mut answer = [0u8; 2];
+    stream.read_exact(&mut answer).context("no PINGRESP")?;
+    if answer != PINGRESP {
+        anyhow::bail!("expected PINGRESP, got {:02x?}", answer);
+    }
+    Ok(())
+}
+
+fn connect(config: &MqttConfig) -> Result<TcpStream> {
+    let mut stream = TcpStream::connect(&config.broker)
+        .with_context(|| format!("Could not connect to MQTT broker {}", config.broker))?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let client_id = config.client_id.clone().unwrap_or_else(|| {
+        let host = std::env::var("HOSTNAME").unwrap_or_else(|_| std::process::id().to_string());
+        format!("agenttop-{}", host)
+    });
+    stream.write_all(&connect_packet(
+        &client_id,
+        config.username.as_deref(),
+        config.password.as_deref(),
+    ))?;
+
+    let mut connack = [0u8; 4];
+    stream
+        .read_exact(&mut connack)
+        .with_context(|| format!("No CONNACK from MQTT broker {}", config.broker))?;
+    match connack {
+        [0x20, 0x02, _, 0] => Ok(stream),
+        [0x20, 0x02, _, code] => anyhow::bail!(
+            "MQTT broker {} refused the connection (code {})",
+            config.broker,
+            code
+        ),
+        _ => anyhow::bail!("{} did not answer like an MQTT broker", config.broker),
+    }
+}
+
+/// Keep `message` until it is sent. A retained message replaces the one waiting for
+/// the same topic, as only the latest value counts.
+fn hold(pending: &mut VecDeque<Message>, message: Message) {
+    if message.retain {
+        pending.retain(|held| !(held.retain && held.topic == message.topic));
+    }
+    if pending.len() >= PENDING_CAPACITY {
+        pending.pop_front();
+    }
+    pending.push_back(message);
+}
+
+/// Send messages as they come, pinging while idle. After a failure, messages wait
+/// until the broker can be reached again.
+fn run_publisher(config: MqttConfig, messages: mpsc::Receiver<Message>) {
+    let idle = Duration::from_secs(u64::from(KEEP_ALIVE_SECS) / 2);
+    let mut connection: Option<TcpStream> = None;
+    let mut pending = VecDeque::new();
+    loop {
+        let wait = if pending.is_empty() {
+            idle
+        } else {
+            RECONNECT_DELAY
+        };
+        let closed = match messages.recv_timeout(wait) {
+            Ok(message) => {
+                hold(&mut pending, message);
+                false
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => false,
+            Err(mpsc::RecvTimeoutError::Disconnected) => true,
+        };
+        if pending.is_empty() {
+            if closed {
+                break;
+            }
+            if let Some(stream) = &mut connection
+                && let Err(e) = ping(stream)
+            {
+                tracing::warn!("MQTT ping to {} failed: {:#}", config.broker, e);
+                connection = None;
+            }
+            continue;
+        }
+
+        if connection.is_none() {
+            match connect(&config) {
+                Ok(stream) => connection = Some(stream),
+                Err(e) => tracing::warn!("{:#}", e),
+            }
+        }
+        if let Some(stream) = &mut connection {
+            while let Some(message) = pending.front() {
+                if let Err(e) = stream.write_all(&publish_packet(message)) {
+                    tracing::warn!("MQTT publish to {} failed: {}", message.topic, e);
+                    connection = None;
+                    break;
+                }
+                pending.pop_front();
+            }
+        }
+        if closed {
+            break;
+        }
+    }
+}
+
+/// Forward every item of a broadcast feed that `convert` turns into a message
+fn forward<T: Clone + Send + 'static>(
+    mut feed: broadcast::Receiver<T>,
+    tx: mpsc::Sender<Message>,
+    convert: impl Fn(&T) -> Option<Message> + Send + 'static,
+) {
+    std::thread::spawn(move || {
+        loop {
+            let item = match feed.blocking_recv() {
+                Ok(item) => item,
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!("MQTT publishing skipped {} items", skipped);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            if let Some(message) = convert(&item)
+                && tx.send(message).is_err()
+            {
+                break;
+            }
+        }
+    });
+}
+
+fn cost_message(storage: &StorageHandle, now: DateTime<Utc>) -> Result<CostMessage> {
+    let tokens = storage.get_token_metrics(None, None)?;
+    Ok(CostMessage {
+        today_usd: storage.get_total_cost(local_day_start(now.with_timezone(&Local)), None)?,
+        total_usd: tokens.total_cost_usd,
+        input_tokens: tokens.input_tokens,
+        output_tokens: tokens.output_tokens,
+        updated_at: now,
+    })
+}
+
+/// Publish to the configured topics from background threads
+pub fn spawn(storage: StorageHandle, config: MqttConfig) {
+    tracing::info!("Publishing activity to MQTT broker {}", config.broker);
+    let (tx, rx) = mpsc::channel();
+
+    if !config.tool_result_topic.is_empty() {
+        let topic = config.tool_result_topic.clone();
+        forward(EVENT_FEED.subscribe(), tx.clone(), move |event| {
+            Some(Message {
+                topic: topic.clone(),
+                payload: serde_json::to_string(&ToolResultMessage::from_event(event)?).ok()?,
+                retain: false,
+            })
+        });
+    }
+    if !config.alert_topic.is_empty() {
+        let topic = config.alert_topic.clone();
+        forward(ALERT_FEED.subscribe(), tx.clone(), move |firing| {
+            Some(Message {
+                topic: topic.clone(),
+                payload: serde_json::to_string(firing).ok()?,
+                retain: false,
+            })
+        });
+    }
+    if !config.cost_topic.is_empty() {
+        let topic = config.cost_topic.clone();
+        let interval = Duration::from_secs(config.cost_interval_secs.max(1));
+        let tx = tx.clone();
+        std::thread::spawn(move || {
+            let mut published: Option<(f64, f64)> = None;
+            loop {
+                match cost_message(&storage, Utc::now()) {
+                    Ok(cost) => {
+                        let current = Some((cost.today_usd, cost.total_usd));
+                        if current != published {
+                            published = current;
+                            let message = Message {
+                                topic: topic.clone(),
+                                payload: serde_json::to_string(&cost).unwrap_or_default(),
+                                retain: true,
+                            };
+                            if tx.send(message).is_err() {
+                                break;
+                            }
+                        }
+                    }
+                    Err(e) => tracing::warn!("Could not read spend for MQTT: {:#}", e),
+                }
+                std::thread::sleep(interval);
+            }
+        });
+    }
+    drop(tx);
+
+    std::thread::spawn(move || run_publisher(config, rx));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_encode_remaining_length() {
+        for (length, expected) in [
+            (0, vec![0x00]),
+            (127, vec![0x7F]),
+            (128, vec![0x80, 0x01]),
+            (16_383, vec![0xFF, 0x7F]),
+            (16_384, vec![0x80, 0x80, 0x01]),
+        ] {
+            let mut out = Vec::new();
+            encode_remaining_length(length, &mut out);
+            assert_eq!(out, expected, "length {}", length);
+        }
+    }
+
+    #[test]
+    fn test_tool_result_message() {
+        let event = LogEvent {
+            timestamp: Utc::now(),
+            event_name: Some("claude_code.tool_result".to_string()),
+            body: None,
+            attributes: [
+                ("tool_name", "mcp__github__create_issue"),
+                ("success", "true"),
+                ("duration_ms", "95000"),
+            ]
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+        };
+        let message = ToolResultMessage::from_event(&event).unwrap();
+        assert_eq!(message.tool, "github:create_issue");
+        assert!(message.success);
+        assert_eq!(message.duration_ms, Some(95_000));
+
+        let prompt = LogEvent {
+            event_name: Some("claude_code.user_prompt".to_string()),
+            ..event
+        };
+        assert_eq!(ToolResultMessage::from_event(&prompt), None);
+    }
+
+    /// Connect and publish against a fake broker that accepts one connection
+    #[test]
+    fn test_publish_to_broker() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let config = MqttConfig {
+            broker: listener.local_addr().unwrap().to_string(),
+            client_id: Some("test".to_string()),
+            username: Some("user".to_string()),
+            password: Some("secret".to_string()),
+            tool_result_topic: default_tool_result_topic(),
+            cost_topic: default_cost_topic(),
+            alert_topic: default_alert_topic(),
+            cost_interval_secs: 30,
+        };
+        let broker = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut header = [0u8; 2];
+            stream.read_exact(&mut header).unwrap();
+            let mut connect = vec![0u8; header[1] as usize];
+            stream.read_exact(&mut connect).unwrap();
+            stream.write_all(&[0x20, 0x02, 0x00, 0x00]).unwrap();
+            let mut publish = [0u8; 64];
+            let n = stream.read(&mut publish).unwrap();
+            (header[0], connect, publish[..n].to_vec())
+        });
+
+        let (tx, rx) = mpsc::channel();
+        tx.send(Message {
+            topic: "agenttop/cost".to_string(),
+            payload: "{}".to_string(),
+            retain: true,
+        })
+        .unwrap();
+        drop(tx);
+        run_publisher(config, rx);
+
+        let (kind, connect, publish) = broker.join().unwrap();
+        assert_eq!(kind, 0x10);
+        assert_eq!(&connect[..7], b"\x00\x04MQTT\x04");
+        assert_eq!(connect[7], 0xC2);
+        assert!(connect.ends_with(b"\x00\x04user\x00\x06secret"));
+        assert_eq!(publish, b"\x31\x11\x00\x0dagenttop/cost{}");
+    }
+
+    /// Accept a connection on `listener` and answer its CONNECT with `code`
+    fn accept(listener: &TcpListener, code: u8) -> TcpStream {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut header = [0u8; 2];
+        stream.read_exact(&mut header).unwrap();
+        let mut connect = vec![0u8; header[1] as usize];
+        stream.read_exact(&mut connect).unwrap();
+        stream.write_all(&[0x20, 0x02, 0x00, code]).unwrap();
+        stream
+    }
+
+    #[test]
+    fn test_ping_waits_for_pingresp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let broker = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut ping = [0u8; 2];
+            stream.read_exact(&mut ping).unwrap();
+            stream.write_all(&PINGRESP).unwrap();
+            stream.read_exact(&mut ping).unwrap();
+            // No answer to the second one
+            ping
+        });
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        ping(&mut stream).unwrap();
+        assert!(ping(&mut stream).is_err());
+        assert_eq!(broker.join().unwrap(), PINGREQ);
+    }
+
+    /// Messages that could not be sent go out once the broker takes a connection
+    #[test]
+    fn test_messages_kept_until_reconnected() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let config = MqttConfig {
+            broker: listener.local_addr().unwrap().to_string(),
+            client_id: Some("test".to_string()),
+            username: None,
+            password: None,
+            tool_result_topic: default_tool_result_topic(),
+            cost_topic: default_cost_topic(),
+            alert_topic: default_alert_topic(),
+            cost_interval_secs: 30,
+        };
+        let message = |topic: &str, payload: &str, retain: bool| Message {
+            topic: topic.to_string(),
+            payload: payload.to_string(),
+            retain,
+        };
+        let (tx, rx) = mpsc::channel();
+        let publisher = std::thread::spawn(move || run_publisher(config, rx));
+
+        // Refused, so the spend waits; a newer value replaces it
+        tx.send(message("agenttop/cost", "1", true)).unwrap();
+        drop(accept(&listener, 5));
+        tx.send(message("agenttop/cost", "2", true)).unwrap();
+        drop(accept(&listener, 5));
+        tx.send(message("agenttop/tool", "t", false)).unwrap();
+        let mut stream = accept(&listener, 0);
+        drop(tx);
+        publisher.join().unwrap();
+
+        let mut published = Vec::new();
+        stream.read_to_end(&mut published).unwrap();
+        let mut expected = publish_packet(&message("agenttop/cost", "2", true));
+        expected.extend(publish_packet(&message("agenttop/tool", "t", false)));
+        assert_eq!(published, expected);
+    }
+
+    #[test]
+    fn test_hold_bounded() {
+        let mut pending = VecDeque::new();
+        for i in 0..PENDING_CAPACITY + 10 {
+            hold(
+                &mut pending,
+                Message {
+                    topic: "agenttop/tool".to_string(),
+                    payload: i.to_string(),
+                    retain: false,
+                },
+            );
+        }
+        assert_eq!(pending.len(), PENDING_CAPACITY);
+        assert_eq!(pending[0].payload, "10");
+    }
+}
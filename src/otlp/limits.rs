@@ -0,0 +1,110 @@
+//! Size caps for received log events. Some agents put whole file contents into
+//! tool_result attributes; values past the cap are cut before they reach the database,
+//! and the original length is kept next to them.
+
+use crate::storage::LogEvent;
+
+/// Cap applied when the config file sets none
+pub const DEFAULT_MAX_VALUE_BYTES: usize = 64 * 1024;
+
+/// Prefix of the attribute holding a truncated value's original length in bytes,
+/// followed by the attribute key (or `body`)
+pub const ORIGINAL_LENGTH_PREFIX: &str = "agenttop.original_length.";
+
+/// Longest attribute value and body kept, in bytes (0: no cap)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IngestLimits {
+    pub max_attribute_bytes: usize,
+    pub max_body_bytes: usize,
+}
+
+impl Default for IngestLimits {
+    fn default() -> Self {
+        Self {
+            max_attribute_bytes: DEFAULT_MAX_VALUE_BYTES,
+            max_body_bytes: DEFAULT_MAX_VALUE_BYTES,
+        }
+    }
+}
+
+impl IngestLimits {
+    /// Truncate the body and attribute values of `event` that are over the caps,
+    /// recording each original length; returns how many values were cut
+    pub fn apply(&self, event: &mut LogEvent) -> u64 {
+        let mut truncated = Vec::new();
+        if let Some(body) = &mut event.body
+            && let Some(length) = truncate(body, self.max_body_bytes)
+        {
+            truncated.push(("body".to_string(), length));
+        }
+        for (key, value) in &mut event.attributes {
+            if let Some(length) = truncate(value, self.max_attribute_bytes) {
+                truncated.push((key.clone(), length));
+            }
+        }
+        for (key, length) in &truncated {
+            event.attributes.insert(
+                format!("{}{}", ORIGINAL_LENGTH_PREFIX, key),
+                length.to_string(),
+            );
+        }
+        truncated.len() as u64
+    }
+}
+
+/// Cut `value` to at most `max` bytes on a character boundary, returning its original
+/// length if it was longer
+fn truncate(value: &mut String, max: usize) -> Option<usize> {
+    if max == 0 || value.len() <= max {
+        return None;
+    }
+    let length = value.len();
+    let mut end = max;
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    value.truncate(end);
+    Some(length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_truncate() {
+        let mut value = "héllo".to_string();
+        assert_eq!(truncate(&mut value, 0), None);
+        assert_eq!(truncate(&mut value, 6), None);
+        // "é" is two bytes and doesn't fit in the first 2
+        assert_eq!(truncate(&mut value, 2), Some(6));
+        assert_eq!(value, "h");
+    }
+
+    #[test]
+    fn test_apply() {
+        let limits = IngestLimits {
+            max_attribute_bytes: 4,
+            max_body_bytes: 0,
+        };
+        let mut event = LogEvent {
+            timestamp: chrono::Utc::now(),
+            event_name: Some("tool_result".to_string()),
+            body: Some("a long body, but uncapped".to_string()),
+            attributes: HashMap::from([
+                ("tool_name".to_string(), "Read".to_string()),
+                ("tool_output".to_string(), "file contents".to_string()),
+            ]),
+        };
+
+        assert_eq!(limits.apply(&mut event), 1);
+        assert_eq!(event.attributes["tool_name"], "Read");
+        assert_eq!(event.attributes["tool_output"], "file");
+        assert_eq!(
+            event.attributes["agenttop.original_length.tool_output"],
+            "13"
+        );
+        assert_eq!(event.body.as_deref(), Some("a long body, but uncapped"));
+    }
+}
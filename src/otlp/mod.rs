@@ -1,23 +1,99 @@
-use anyhow::Result;
-use axum::{Router, body::Bytes, extract::State, http::StatusCode, routing::post};
+use crate::error::{AgenttopError, Result};
+use axum::{
+    Router,
+    body::{Body, Bytes},
+    extract::State,
+    http::{HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use once_cell::sync::Lazy;
+use tokio::sync::{Semaphore, broadcast};
+use tokio_stream::{StreamExt, wrappers::BroadcastStream};
 use tower_http::cors::CorsLayer;
 
-use crate::storage::StorageHandle;
+use crate::self_metrics::SELF_METRICS;
+use crate::storage::{LogEvent, StorageHandle};
 
+pub mod limits;
 pub mod parser;
 
+pub use limits::IngestLimits;
 pub use parser::*;
 
-pub async fn start_receiver(storage: StorageHandle) -> Result<()> {
-    let app = Router::new()
+/// Address the OTLP receiver listens on
+pub const RECEIVER_ADDR: &str = "127.0.0.1:4318";
+
+/// `Server` header on every receiver response, so another program can tell an agenttop
+/// on the port apart (see [`crate::port`])
+pub const SERVER_HEADER: &str = concat!("agenttop/", env!("CARGO_PKG_VERSION"));
+
+/// Events buffered per feed subscriber; a subscriber that falls further behind skips events
+const EVENT_FEED_CAPACITY: usize = 1024;
+
+/// Live feed of received log events, published before they are stored
+pub static EVENT_FEED: Lazy<broadcast::Sender<LogEvent>> =
+    Lazy::new(|| broadcast::channel(EVENT_FEED_CAPACITY).0);
+
+/// Payloads up to this size are decoded on the async worker that received them; larger
+/// ones on the blocking thread pool, so decoding them doesn't delay other requests
+const INLINE_PARSE_BYTES: usize = 64 * 1024;
+
+/// Payloads decoded on the blocking pool at once, one per core; further ones wait
+static PARSE_PERMITS: Lazy<Semaphore> = Lazy::new(|| {
+    Semaphore::new(std::thread::available_parallelism().map_or(2, |cores| cores.get()))
+});
+
+/// Listen address and optional features served next to the OTLP endpoints
+#[derive(Debug, Clone, Default)]
+pub struct ReceiverOptions {
+    /// Address to listen on (default: [`RECEIVER_ADDR`])
+    pub bind: Option<std::net::SocketAddr>,
+    /// Serve the REST API under /api
+    pub api: bool,
+    /// Origins and tokens for the REST API; without `api`, the SQL token still serves
+    /// `/api/query` (see [`query_router`](crate::api::query_router))
+    pub access: crate::api::Access,
+    /// Serve Prometheus metrics at /metrics
+    pub metrics: bool,
+    /// Stream received log events at /events/stream (read by `agenttop tail`)
+    pub event_stream: bool,
+    /// Caps on received attribute values and bodies
+    pub limits: IngestLimits,
+}
+
+pub async fn start_receiver(storage: StorageHandle, options: ReceiverOptions) -> Result<()> {
+    // Browser OTLP exporters post from other origins; nothing else is shared with them
+    let mut app = Router::new()
         .route("/v1/metrics", post(handle_metrics))
-        .route("/v1/logs", post(handle_logs))
+        .route(
+            "/v1/logs",
+            post(move |state, body| handle_logs(state, body, options.limits)),
+        )
         .route("/v1/traces", post(handle_traces))
-        .layer(CorsLayer::permissive())
+        .layer(CorsLayer::permissive());
+    if options.event_stream {
+        app = app.route("/events/stream", get(handle_event_stream));
+    }
+    if options.api {
+        app = app.merge(crate::api::router(options.access));
+    } else if let Some(token) = options.access.sql_token {
+        app = app.merge(crate::api::query_router(token));
+    }
+    if options.metrics {
+        app = app.merge(crate::prometheus::router());
+    }
+    let app = app
+        .layer(axum::middleware::map_response(identify))
         .with_state(storage);
 
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:4318").await?;
-    tracing::info!("OTLP receiver listening on http://127.0.0.1:4318");
+    let listener = match options.bind {
+        Some(addr) => tokio::net::TcpListener::bind(addr).await?,
+        None => tokio::net::TcpListener::bind(RECEIVER_ADDR).await?,
+    };
+    let local_addr = listener.local_addr()?;
+    SELF_METRICS.receiver.set_listening(&local_addr.to_string());
+    tracing::info!("OTLP receiver listening on http://{}", local_addr);
 
     axum::serve(listener, app).await?;
     Ok(())
@@ -26,18 +102,43 @@ pub async fn start_receiver(storage: StorageHandle) -> Result<()> {
 async fn handle_metrics(State(storage): State<StorageHandle>, body: Bytes) -> StatusCode {
     tracing::debug!("Received metrics: {} bytes", body.len());
 
-    match parser::parse_metrics(&body) {
+    match parse_body(body, parser::parse_metrics).await {
         Ok(metrics) => {
+            SELF_METRICS.receiver.record_events(metrics.len() as u64);
             for metric in metrics {
                 match metric {
-                    ParsedMetric::TokenUsage { token_type, count } => {
-                        storage.record_token_usage(&token_type, count);
+                    ParsedMetric::TokenUsage {
+                        token_type,
+                        count,
+                        model,
+                        provider,
+                    } => {
+                        storage.record_model_token_usage(
+                            &token_type,
+                            count,
+                            model.as_deref(),
+                            provider.as_deref(),
+                        );
                     }
-                    ParsedMetric::CostUsage { cost_usd } => {
-                        storage.record_cost(cost_usd);
+                    ParsedMetric::CostUsage {
+                        cost_usd,
+                        session_id,
+                        model,
+                        cumulative,
+                    } => {
+                        storage.record_cost_for(
+                            session_id.as_deref(),
+                            model.as_deref(),
+                            cost_usd,
+                            cumulative,
+                        );
                     }
-                    ParsedMetric::SessionMetric { name, value } => {
-                        storage.record_session_metric(&name, value);
+                    ParsedMetric::SessionMetric {
+                        name,
+                        value,
+                        session_id,
+                    } => {
+                        storage.record_session_metric_for(session_id.as_deref(), &name, value);
                     }
                 }
             }
@@ -45,15 +146,26 @@ async fn handle_metrics(State(storage): State<StorageHandle>, body: Bytes) -> St
         }
         Err(e) => {
             tracing::error!("Failed to parse metrics: {}", e);
+            SELF_METRICS.receiver.record_dropped_payload();
             StatusCode::BAD_REQUEST
         }
     }
 }
 
-async fn handle_logs(State(storage): State<StorageHandle>, body: Bytes) -> StatusCode {
+async fn handle_logs(
+    State(storage): State<StorageHandle>,
+    body: Bytes,
+    limits: IngestLimits,
+) -> StatusCode {
     tracing::debug!("Received logs: {} bytes", body.len());
 
-    match parser::parse_logs(&body) {
+    let parsed = parse_body(body, move |body| {
+        let mut events = parser::parse_logs(body)?;
+        let truncated = events.iter_mut().map(|event| limits.apply(event)).sum();
+        SELF_METRICS.receiver.record_truncated_values(truncated);
+        Ok(events)
+    });
+    match parsed.await {
         Ok(events) => {
             tracing::debug!("Parsed {} log events", events.len());
             for event in &events {
@@ -63,19 +175,83 @@ async fn handle_logs(State(storage): State<StorageHandle>, body: Bytes) -> Statu
                     event.attributes.keys().collect::<Vec<_>>()
                 );
             }
+            SELF_METRICS.receiver.record_events(events.len() as u64);
+            if EVENT_FEED.receiver_count() > 0 {
+                for event in &events {
+                    let _ = EVENT_FEED.send(event.clone());
+                }
+            }
             // Store all log events without filtering - filtering happens at query time
             storage.record_log_events(events);
             StatusCode::OK
         }
         Err(e) => {
             tracing::error!("Failed to parse logs: {}", e);
+            SELF_METRICS.receiver.record_dropped_payload();
             StatusCode::BAD_REQUEST
         }
     }
 }
 
+/// Decode `body` with `parse`, on the blocking thread pool if it is large (see
+/// [`INLINE_PARSE_BYTES`] and [`PARSE_PERMITS`])
+async fn parse_body<T, F>(body: Bytes, parse: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&[u8]) -> Result<T> + Send + 'static,
+{
+    if body.len() <= INLINE_PARSE_BYTES {
+        return parse(&body);
+    }
+    let _permit = PARSE_PERMITS.acquire().await.expect("never closed");
+    tokio::task::spawn_blocking(move || parse(&body))
+        .await
+        .map_err(|e| AgenttopError::Parse(anyhow::anyhow!("decoding the payload failed: {e}")))?
+}
+
 async fn handle_traces(State(_storage): State<StorageHandle>, body: Bytes) -> StatusCode {
     // Traces are not used currently, but we accept them
     tracing::debug!("Received traces: {} bytes", body.len());
     StatusCode::OK
 }
+
+async fn identify(mut response: Response) -> Response {
+    response
+        .headers_mut()
+        .insert(header::SERVER, HeaderValue::from_static(SERVER_HEADER));
+    response
+}
+
+/// Stream received log events as JSON lines until the client disconnects
+async fn handle_event_stream() -> impl IntoResponse {
+    let lines = BroadcastStream::new(EVENT_FEED.subscribe()).filter_map(|event| {
+        // Events a slow client missed are skipped
+        let mut line = serde_json::to_string(&event.ok()?).ok()?;
+        line.push('\n');
+        Some(Ok::<_, std::convert::Infallible>(line))
+    });
+    (
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(lines),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_parse_large_body_off_runtime() {
+        let payload = crate::bench::synthetic_payload(0, 5000);
+        assert!(payload.len() > INLINE_PARSE_BYTES);
+        let events = parse_body(Bytes::from(payload), parser::parse_logs)
+            .await
+            .unwrap();
+        assert_eq!(events.len(), 5000);
+
+        // A parser panicking on the blocking pool fails the request, not the receiver
+        let large = Bytes::from(vec![0; INLINE_PARSE_BYTES + 1]);
+        let failed = parse_body(large, |_| -> Result<()> { panic!("bad payload") }).await;
+        assert!(matches!(failed, Err(AgenttopError::Parse(_))));
+    }
+}
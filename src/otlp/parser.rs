@@ -1,4 +1,4 @@
-use anyhow::Result;
+use crate::error::Result;
 use chrono::{TimeZone, Utc};
 use opentelemetry_proto::tonic::collector::logs::v1::ExportLogsServiceRequest;
 use opentelemetry_proto::tonic::collector::metrics::v1::ExportMetricsServiceRequest;
@@ -8,13 +8,50 @@ use prost::Message;
 use serde::Deserialize;
 use std::collections::HashMap;
 
+use crate::providers::PROVIDER_REGISTRY;
+use crate::self_metrics::SELF_METRICS;
 use crate::storage::LogEvent;
 
+/// Resource attribute copied onto every log record of the resource (unless the record
+/// has its own), so spend can be grouped by project. Agents set it with
+/// `OTEL_RESOURCE_ATTRIBUTES=project=<name>`.
+pub const PROJECT_ATTRIBUTE: &str = "project";
+
+/// Resource attribute naming the agent's working directory, copied onto the log records
+/// like [`PROJECT_ATTRIBUTE`] so sessions can be matched to a git repository
+/// (`OTEL_RESOURCE_ATTRIBUTES=cwd=$PWD`)
+pub const CWD_ATTRIBUTE: &str = "cwd";
+
+/// Resource attributes copied onto every log record of the resource
+const INHERITED_ATTRIBUTES: &[&str] = &[PROJECT_ATTRIBUTE, CWD_ATTRIBUTE];
+
+/// `AggregationTemporality::Cumulative` in OTLP: each data point of a sum is the running
+/// total since the series started, rather than the change since the last export
+const AGGREGATION_TEMPORALITY_CUMULATIVE: i32 = 2;
+
 #[derive(Debug, Clone)]
 pub enum ParsedMetric {
-    TokenUsage { token_type: String, count: u64 },
-    CostUsage { cost_usd: f64 },
-    SessionMetric { name: String, value: i64 },
+    TokenUsage {
+        token_type: String,
+        count: u64,
+        model: Option<String>,
+        /// Id of the provider whose metric this is (see [`crate::providers::Provider::id`])
+        provider: Option<String>,
+    },
+    CostUsage {
+        cost_usd: f64,
+        /// `session.id` of the data point, if the agent sent one
+        session_id: Option<String>,
+        model: Option<String>,
+        /// Whether `cost_usd` is the running total of its series rather than an increment
+        cumulative: bool,
+    },
+    SessionMetric {
+        name: String,
+        value: i64,
+        /// `session.id` of the data point, if the agent sent one
+        session_id: Option<String>,
+    },
 }
 
 // OTLP JSON structures for metrics (fallback)
@@ -50,6 +87,8 @@ struct Metric {
 #[serde(rename_all = "camelCase")]
 struct MetricSum {
     data_points: Vec<DataPoint>,
+    #[serde(default)]
+    aggregation_temporality: i32,
 }
 
 #[derive(Debug, Deserialize)]
@@ -121,9 +160,18 @@ struct OtlpLogsRequest {
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct ResourceLogs {
+    #[serde(default)]
+    resource: Option<Resource>,
     scope_logs: Vec<ScopeLogs>,
 }
 
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Resource {
+    #[serde(default)]
+    attributes: Vec<Attribute>,
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct ScopeLogs {
@@ -211,9 +259,17 @@ pub fn parse_metrics(data: &[u8]) -> Result<Vec<ParsedMetric>> {
         "Failed to parse metrics data ({} bytes) as protobuf or JSON",
         data.len()
     );
+    SELF_METRICS.receiver.record_dropped_payload();
     Ok(vec![])
 }
 
+/// Id of the provider whose metric prefix `metric_name` has
+fn metric_provider(metric_name: &str) -> Option<String> {
+    PROVIDER_REGISTRY
+        .detect_from_metric(metric_name)
+        .map(|provider| provider.id().to_string())
+}
+
 fn parse_metrics_proto(request: ExportMetricsServiceRequest) -> Result<Vec<ParsedMetric>> {
     let mut metrics = Vec::new();
 
@@ -223,16 +279,17 @@ fn parse_metrics_proto(request: ExportMetricsServiceRequest) -> Result<Vec<Parse
                 let name = &metric.name;
 
                 // Get data points from sum or gauge
-                let data_points: Vec<_> = metric
+                let (data_points, cumulative) = metric
                     .data
                     .map(|d| match d {
-                        opentelemetry_proto::tonic::metrics::v1::metric::Data::Sum(sum) => {
-                            sum.data_points
-                        }
+                        opentelemetry_proto::tonic::metrics::v1::metric::Data::Sum(sum) => (
+                            sum.data_points,
+                            sum.aggregation_temporality == AGGREGATION_TEMPORALITY_CUMULATIVE,
+                        ),
                         opentelemetry_proto::tonic::metrics::v1::metric::Data::Gauge(gauge) => {
-                            gauge.data_points
+                            (gauge.data_points, false)
                         }
-                        _ => vec![],
+                        _ => (vec![], false),
                     })
                     .unwrap_or_default();
 
@@ -256,8 +313,19 @@ fn parse_metrics_proto(request: ExportMetricsServiceRequest) -> Result<Vec<Parse
                                 ) => d as u64,
                                 None => 0,
                             };
+                            let model = dp
+                                .attributes
+                                .iter()
+                                .find(|a| a.key == "model")
+                                .and_then(|a| a.value.as_ref())
+                                .and_then(get_string_value);
 
-                            Some(ParsedMetric::TokenUsage { token_type, count })
+                            Some(ParsedMetric::TokenUsage {
+                                token_type,
+                                count,
+                                model,
+                                provider: metric_provider(name),
+                            })
                         }
                         "claude_code.cost.usage" => {
                             let cost_usd = match dp.value {
@@ -269,7 +337,19 @@ fn parse_metrics_proto(request: ExportMetricsServiceRequest) -> Result<Vec<Parse
                                 ) => i as f64,
                                 None => 0.0,
                             };
-                            Some(ParsedMetric::CostUsage { cost_usd })
+                            let attribute = |key: &str| {
+                                dp.attributes
+                                    .iter()
+                                    .find(|a| a.key == key)
+                                    .and_then(|a| a.value.as_ref())
+                                    .and_then(get_string_value)
+                            };
+                            Some(ParsedMetric::CostUsage {
+                                cost_usd,
+                                session_id: attribute("session.id"),
+                                model: attribute("model"),
+                                cumulative,
+                            })
                         }
                         n if n.starts_with("claude_code.") => {
                             let metric_name = n
@@ -287,9 +367,17 @@ fn parse_metrics_proto(request: ExportMetricsServiceRequest) -> Result<Vec<Parse
                                 None => 0,
                             };
 
+                            let session_id = dp
+                                .attributes
+                                .iter()
+                                .find(|a| a.key == "session.id")
+                                .and_then(|a| a.value.as_ref())
+                                .and_then(get_string_value);
+
                             Some(ParsedMetric::SessionMetric {
                                 name: metric_name,
                                 value,
+                                session_id,
                             })
                         }
                         _ => None,
@@ -313,10 +401,14 @@ fn parse_metrics_json(request: OtlpMetricsRequest) -> Result<Vec<ParsedMetric>>
     for resource in request.resource_metrics {
         for scope in resource.scope_metrics {
             for metric in scope.metrics {
-                let data_points = metric
+                let (data_points, cumulative) = metric
                     .sum
-                    .map(|s| s.data_points)
-                    .or_else(|| metric.gauge.map(|g| g.data_points))
+                    .map(|s| {
+                        let cumulative =
+                            s.aggregation_temporality == AGGREGATION_TEMPORALITY_CUMULATIVE;
+                        (s.data_points, cumulative)
+                    })
+                    .or_else(|| metric.gauge.map(|g| (g.data_points, false)))
                     .unwrap_or_default();
 
                 for dp in data_points {
@@ -330,11 +422,32 @@ fn parse_metrics_json(request: OtlpMetricsRequest) -> Result<Vec<ParsedMetric>>
                                 .unwrap_or_else(|| "unknown".to_string());
 
                             let count = dp.as_int.unwrap_or(0) as u64;
-                            Some(ParsedMetric::TokenUsage { token_type, count })
+                            let model = dp
+                                .attributes
+                                .iter()
+                                .find(|a| a.key == "model")
+                                .and_then(|a| a.value.string_value.clone());
+                            Some(ParsedMetric::TokenUsage {
+                                token_type,
+                                count,
+                                model,
+                                provider: metric_provider(&metric.name),
+                            })
                         }
                         "claude_code.cost.usage" => {
                             let cost_usd = dp.as_double.unwrap_or(0.0);
-                            Some(ParsedMetric::CostUsage { cost_usd })
+                            let attribute = |key: &str| {
+                                dp.attributes
+                                    .iter()
+                                    .find(|a| a.key == key)
+                                    .and_then(|a| a.value.string_value.clone())
+                            };
+                            Some(ParsedMetric::CostUsage {
+                                cost_usd,
+                                session_id: attribute("session.id"),
+                                model: attribute("model"),
+                                cumulative,
+                            })
                         }
                         "claude_code.lines_of_code.count"
                         | "claude_code.commit.count"
@@ -346,7 +459,16 @@ fn parse_metrics_json(request: OtlpMetricsRequest) -> Result<Vec<ParsedMetric>>
                                 .replace(".count", "")
                                 .replace(".total", "");
                             let value = dp.as_int.unwrap_or(0);
-                            Some(ParsedMetric::SessionMetric { name, value })
+                            let session_id = dp
+                                .attributes
+                                .iter()
+                                .find(|a| a.key == "session.id")
+                                .and_then(|a| a.value.string_value.clone());
+                            Some(ParsedMetric::SessionMetric {
+                                name,
+                                value,
+                                session_id,
+                            })
                         }
                         _ => None,
                     };
@@ -385,6 +507,7 @@ pub fn parse_logs(data: &[u8]) -> Result<Vec<LogEvent>> {
         "Failed to parse logs data ({} bytes) as protobuf or JSON",
         data.len()
     );
+    SELF_METRICS.receiver.record_dropped_payload();
     Ok(vec![])
 }
 
@@ -392,6 +515,22 @@ fn parse_logs_proto(request: ExportLogsServiceRequest) -> Result<Vec<LogEvent>>
     let mut events = Vec::new();
 
     for resource in request.resource_logs {
+        let inherited: Vec<(String, String)> = resource
+            .resource
+            .as_ref()
+            .map(|r| {
+                r.attributes
+                    .iter()
+                    .filter(|a| INHERITED_ATTRIBUTES.contains(&a.key.as_str()))
+                    .filter_map(|a| {
+                        a.value
+                            .as_ref()
+                            .and_then(get_any_value_as_string)
+                            .map(|value| (a.key.clone(), value))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
         for scope in resource.scope_logs {
             for record in scope.log_records {
                 // Extract event.name from attributes
@@ -424,7 +563,7 @@ fn parse_logs_proto(request: ExportLogsServiceRequest) -> Result<Vec<LogEvent>>
                 }
 
                 // Store ALL attributes as a HashMap for query-time filtering
-                let attributes: HashMap<String, String> = record
+                let mut attributes: HashMap<String, String> = record
                     .attributes
                     .iter()
                     .filter_map(|a| {
@@ -433,6 +572,11 @@ fn parse_logs_proto(request: ExportLogsServiceRequest) -> Result<Vec<LogEvent>>
                             .and_then(|v| get_any_value_as_string(v).map(|s| (a.key.clone(), s)))
                     })
                     .collect();
+                for (key, value) in &inherited {
+                    attributes
+                        .entry(key.clone())
+                        .or_insert_with(|| value.clone());
+                }
 
                 // Extract body if present
                 let body = record.body.as_ref().and_then(get_string_value);
@@ -462,6 +606,19 @@ fn parse_logs_json(request: OtlpLogsRequest) -> Result<Vec<LogEvent>> {
     let mut events = Vec::new();
 
     for resource in request.resource_logs {
+        let inherited: Vec<(String, String)> = resource
+            .resource
+            .as_ref()
+            .map(|r| {
+                r.attributes
+                    .iter()
+                    .filter(|a| INHERITED_ATTRIBUTES.contains(&a.key.as_str()))
+                    .filter_map(|a| {
+                        get_json_attribute_as_string(&a.value).map(|value| (a.key.clone(), value))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
         for scope in resource.scope_logs {
             for record in scope.log_records {
                 // Extract event.name from attributes
@@ -472,13 +629,18 @@ fn parse_logs_json(request: OtlpLogsRequest) -> Result<Vec<LogEvent>> {
                     .and_then(|a| a.value.string_value.clone());
 
                 // Store ALL attributes as a HashMap for query-time filtering
-                let attributes: HashMap<String, String> = record
+                let mut attributes: HashMap<String, String> = record
                     .attributes
                     .iter()
                     .filter_map(|a| {
                         get_json_attribute_as_string(&a.value).map(|s| (a.key.clone(), s))
                     })
                     .collect();
+                for (key, value) in &inherited {
+                    attributes
+                        .entry(key.clone())
+                        .or_insert_with(|| value.clone());
+                }
 
                 // Extract body if present
                 let body = record.body.as_ref().and_then(|b| b.string_value.clone());
@@ -544,7 +706,9 @@ mod tests {
         let metrics = parse_metrics(json.as_bytes()).unwrap();
         assert_eq!(metrics.len(), 1);
         match &metrics[0] {
-            ParsedMetric::TokenUsage { token_type, count } => {
+            ParsedMetric::TokenUsage {
+                token_type, count, ..
+            } => {
                 assert_eq!(token_type, "input");
                 assert_eq!(*count, 1000);
             }
@@ -701,4 +865,32 @@ mod tests {
             Some("claude_code.tool_result".to_string())
         );
     }
+
+    #[test]
+    fn test_parse_logs_copies_project_and_cwd_resource_attributes() {
+        let json = r#"{
+            "resourceLogs": [{
+                "resource": {
+                    "attributes": [
+                        {"key": "project", "value": {"stringValue": "agenttop"}},
+                        {"key": "cwd", "value": {"stringValue": "/home/dev/agenttop"}},
+                        {"key": "service.name", "value": {"stringValue": "claude-code"}}
+                    ]
+                },
+                "scopeLogs": [{
+                    "logRecords": [
+                        {"attributes": [{"key": "event.name", "value": {"stringValue": "api_request"}}]},
+                        {"attributes": [{"key": "project", "value": {"stringValue": "other"}}]}
+                    ]
+                }]
+            }]
+        }"#;
+
+        let events = parse_logs(json.as_bytes()).unwrap();
+        assert_eq!(events[0].attributes["project"], "agenttop");
+        assert_eq!(events[0].attributes["cwd"], "/home/dev/agenttop");
+        assert!(!events[0].attributes.contains_key("service.name"));
+        // A record's own attribute wins
+        assert_eq!(events[1].attributes["project"], "other");
+    }
 }
@@ -0,0 +1,273 @@
+//! Periodic push of agenttop's own aggregates (per-tool calls and errors, tokens, cost)
+//! as OTLP/HTTP protobuf metrics to a remote collector, configured under `otlp_export`
+//! in the config file.
+//!
+//! All values are all-time cumulative sums, so a collector can aggregate many
+//! developers' agenttop instances by their resource attributes.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use opentelemetry_proto::tonic::collector::metrics::v1::ExportMetricsServiceRequest;
+use opentelemetry_proto::tonic::common::v1::any_value::Value as AnyValueKind;
+use opentelemetry_proto::tonic::common::v1::{AnyValue, InstrumentationScope, KeyValue};
+use opentelemetry_proto::tonic::metrics::v1::{
+    AggregationTemporality, Metric, NumberDataPoint, ResourceMetrics, ScopeMetrics, Sum,
+    metric::Data, number_data_point::Value,
+};
+use opentelemetry_proto::tonic::resource::v1::Resource;
+use prost::Message;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::config::OtlpExportConfig;
+use crate::storage::{StorageHandle, TokenMetrics, ToolMetrics};
+
+fn string_attr(key: &str, value: &str) -> KeyValue {
+    KeyValue {
+        key: key.to_string(),
+        value: Some(AnyValue {
+            value: Some(AnyValueKind::StringValue(value.to_string())),
+        }),
+    }
+}
+
+fn unix_nanos(time: DateTime<Utc>) -> u64 {
+    time.timestamp_nanos_opt().unwrap_or_default() as u64
+}
+
+/// Cumulative monotonic sum with one data point per attribute set
+fn sum(name: &str, unit: &str, description: &str, points: Vec<NumberDataPoint>) -> Metric {
+    Metric {
+        name: name.to_string(),
+        description: description.to_string(),
+        unit: unit.to_string(),
+        data: Some(Data::Sum(Sum {
+            data_points: points,
+            aggregation_temporality: AggregationTemporality::Cumulative as i32,
+            is_monotonic: true,
+        })),
+        ..Default::default()
+    }
+}
+
+/// Resource attributes identifying this instance; `extra` entries override the defaults
+fn resource(extra: &HashMap<String, String>) -> Resource {
+    let mut attributes: HashMap<String, String> = HashMap::new();
+    attributes.insert("service.name".to_string(), "agenttop".to_string());
+    attributes.insert(
+        "service.version".to_string(),
+        env!("CARGO_PKG_VERSION").to_string(),
+    );
+    if let Ok(user) = std::env::var("USER").or_else(|_| std::env::var("USERNAME")) {
+        attributes.insert("user.name".to_string(), user);
+    }
+    if let Ok(host) = std::env::var("HOSTNAME").or_else(|_| std::env::var("COMPUTERNAME")) {
+        attributes.insert("host.name".to_string(), host);
+    }
+    attributes.extend(extra.clone());
+
+    let mut attributes: Vec<_> = attributes.into_iter().collect();
+    attributes.sort();
+    Resource {
+        attributes: attributes
+            .iter()
+            .map(|(key, value)| string_attr(key, value))
+            .collect(),
+        ..Default::default()
+    }
+}
+
+/// Build the export request for the current aggregates
+pub fn build_request(
+    tools: &[ToolMetrics],
+    tokens: &TokenMetrics,
+    resource_attributes: &HashMap<String, String>,
+    start: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> ExportMetricsServiceRequest {
+    let point = |attributes: Vec<KeyValue>, value: Value| NumberDataPoint {
+        attributes,
+        start_time_unix_nano: unix_nanos(start),
+        time_unix_nano: unix_nanos(now),
+        value: Some(value),
+        ..Default::default()
+    };
+    let per_tool = |count: fn(&ToolMetrics) -> u64| {
+        tools
+            .iter()
+            .map(|tool| {
+                point(
+                    vec![string_attr("tool", &tool.tool_name)],
+                    Value::AsInt(count(tool) as i64),
+                )
+            })
+            .collect()
+    };
+
+    let metrics = vec![
+        sum(
+            "agenttop.tool.calls",
+            "{call}",
+            "Tool calls recorded",
+            per_tool(|t| t.call_count),
+        ),
+        sum(
+            "agenttop.tool.errors",
+            "{call}",
+            "Tool calls that failed",
+            per_tool(|t| t.error_count),
+        ),
+        sum(
+            "agenttop.tokens",
+            "{token}",
+            "Tokens used by type",
+            [
+                ("input", tokens.input_tokens),
+                ("output", tokens.output_tokens),
+                ("cache_read", tokens.cache_read_tokens),
+                ("cache_creation", tokens.cache_creation_tokens),
+            ]
+            .into_iter()
+            .map(|(kind, count)| point(vec![string_attr("type", kind)], Value::AsInt(count as i64)))
+            .collect(),
+        ),
+        sum(
+            "agenttop.cost",
+            "USD",
+            "Total cost reported by agents",
+            vec![point(vec![], Value::AsDouble(tokens.total_cost_usd))],
+        ),
+    ];
+
+    ExportMetricsServiceRequest {
+        resource_metrics: vec![ResourceMetrics {
+            resource: Some(resource(resource_attributes)),
+            scope_metrics: vec![ScopeMetrics {
+                scope: Some(InstrumentationScope {
+                    name: "agenttop".to_string(),
+                    version: env!("CARGO_PKG_VERSION").to_string(),
+                    ..Default::default()
+                }),
+                metrics,
+                ..Default::default()
+            }],
+            ..Default::default()
+        }],
+    }
+}
+
+/// The collector's metrics URL: `endpoint` with `/v1/metrics` appended unless already present
+pub fn metrics_url(endpoint: &str) -> String {
+    let endpoint = endpoint.trim_end_matches('/');
+    if endpoint.ends_with("/v1/metrics") {
+        endpoint.to_string()
+    } else {
+        format!("{}/v1/metrics", endpoint)
+    }
+}
+
+/// Query the aggregates and send them once
+fn export_once(
+    storage: &StorageHandle,
+    config: &OtlpExportConfig,
+    start: DateTime<Utc>,
+) -> Result<()> {
+    let tools = storage.get_tool_metrics(None, None)?;
+    let tokens = storage.get_token_metrics(None, None)?;
+    let request = build_request(
+        &tools,
+        &tokens,
+        &config.resource_attributes,
+        start,
+        Utc::now(),
+    );
+
+    let url = metrics_url(&config.endpoint);
+    let mut http = ureq::post(&url)
+        .timeout(Duration::from_secs(10))
+        .set("Content-Type", "application/x-protobuf");
+    for (name, value) in &config.headers {
+        http = http.set(name, value);
+    }
+    http.send_bytes(&request.encode_to_vec())
+        .with_context(|| format!("OTLP export to {} failed", url))?;
+    Ok(())
+}
+
+/// Push aggregates every `interval_secs` on a background thread
+pub fn spawn(storage: StorageHandle, config: OtlpExportConfig) {
+    let start = Utc::now();
+    let interval = Duration::from_secs(config.interval_secs.max(1));
+    tracing::info!(
+        "Exporting metrics to {} every {}s",
+        metrics_url(&config.endpoint),
+        interval.as_secs()
+    );
+    crate::export::spawn_periodic(interval, move || export_once(&storage, &config, start));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics_url() {
+        assert_eq!(
+            metrics_url("https://otel.example.com:4318/"),
+            "https://otel.example.com:4318/v1/metrics"
+        );
+        assert_eq!(
+            metrics_url("http://collector/v1/metrics"),
+            "http://collector/v1/metrics"
+        );
+    }
+
+    #[test]
+    fn test_build_request_round_trip() {
+        let tools = vec![ToolMetrics {
+            tool_name: "Bash".to_string(),
+            call_count: 4,
+            last_call: None,
+            avg_duration_ms: 0.0,
+            min_duration_ms: 0.0,
+            max_duration_ms: 0.0,
+            success_count: 3,
+            error_count: 1,
+            approved_count: 4,
+            rejected_count: 0,
+        }];
+        let tokens = TokenMetrics {
+            output_tokens: 7,
+            total_cost_usd: 1.25,
+            ..Default::default()
+        };
+        let extra = HashMap::from([("team".to_string(), "infra".to_string())]);
+        let now = Utc::now();
+        let bytes = build_request(&tools, &tokens, &extra, now, now).encode_to_vec();
+
+        let request = ExportMetricsServiceRequest::decode(bytes.as_slice()).unwrap();
+        let resource_metrics = &request.resource_metrics[0];
+        let attributes = &resource_metrics.resource.as_ref().unwrap().attributes;
+        assert!(attributes.contains(&string_attr("service.name", "agenttop")));
+        assert!(attributes.contains(&string_attr("team", "infra")));
+
+        let metrics = &resource_metrics.scope_metrics[0].metrics;
+        let points = |name: &str| match &metrics.iter().find(|m| m.name == name).unwrap().data {
+            Some(Data::Sum(sum)) => sum.data_points.clone(),
+            other => panic!("unexpected data {:?}", other),
+        };
+        assert_eq!(
+            points("agenttop.tool.calls")[0].value,
+            Some(Value::AsInt(4))
+        );
+        assert_eq!(
+            points("agenttop.tool.errors")[0].value,
+            Some(Value::AsInt(1))
+        );
+        assert_eq!(points("agenttop.tokens")[1].value, Some(Value::AsInt(7)));
+        assert_eq!(
+            points("agenttop.cost")[0].value,
+            Some(Value::AsDouble(1.25))
+        );
+    }
+}
@@ -0,0 +1,266 @@
+//! What to do when the receiver port is already taken: find out who holds it, then
+//! either view the database the other agenttop is writing, or receive on another port
+//! and point the configured agents there.
+
+use anyhow::Result;
+use std::io::{BufRead, IsTerminal, Write};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::process::Command;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+/// How many ports after the configured one are tried for `--on-port-conflict alternate`
+const ALTERNATE_PORTS: u16 = 20;
+
+/// How long to wait for the port's holder to answer
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// `--on-port-conflict`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Fallback {
+    /// Prompt on the terminal (fail when not interactive)
+    #[default]
+    Ask,
+    /// Show the database the running agenttop writes, without receiving
+    View,
+    /// Receive on the next free port and reconfigure the agents set up for agenttop
+    Alternate,
+    /// Exit with an error
+    Fail,
+}
+
+/// Whoever is listening on the receiver port
+#[derive(Debug, Clone, PartialEq)]
+pub struct PortOwner {
+    /// It answers like an agenttop receiver
+    pub agenttop: bool,
+    /// From `lsof` or `ss`, when either is available
+    pub process: Option<ListenerProcess>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListenerProcess {
+    pub name: String,
+    pub pid: u32,
+}
+
+impl PortOwner {
+    /// "another agenttop (pid 123)", "otelcol (pid 77)", ...
+    pub fn describe(&self) -> String {
+        match (self.agenttop, &self.process) {
+            (true, Some(process)) => format!("another agenttop (pid {})", process.pid),
+            (true, None) => "another agenttop".to_string(),
+            (false, Some(process)) => format!("{} (pid {})", process.name, process.pid),
+            (false, None) => "another program".to_string(),
+        }
+    }
+}
+
+/// None if `addr` is free, otherwise who holds it
+pub async fn probe(addr: SocketAddr) -> Option<PortOwner> {
+    if tokio::net::TcpListener::bind(addr).await.is_ok() {
+        return None;
+    }
+    Some(PortOwner {
+        agenttop: answers_like_agenttop(connect_addr(addr)).await,
+        process: listener_process(addr.port()),
+    })
+}
+
+/// The first free port after `addr`'s, on the same interface
+pub fn alternate_port(addr: SocketAddr) -> Option<SocketAddr> {
+    (1..=ALTERNATE_PORTS)
+        .filter_map(|offset| addr.port().checked_add(offset))
+        .map(|port| SocketAddr::new(addr.ip(), port))
+        .find(|candidate| std::net::TcpListener::bind(candidate).is_ok())
+}
+
+/// Whether a prompt can be shown
+pub fn interactive() -> bool {
+    std::io::stdin().is_terminal() && std::io::stderr().is_terminal()
+}
+
+/// Ask on the terminal what to do; `can_view` is false without a TUI (headless)
+pub fn ask(
+    addr: SocketAddr,
+    owner: &PortOwner,
+    alternate: Option<SocketAddr>,
+    can_view: bool,
+) -> Result<Fallback> {
+    let mut stderr = std::io::stderr();
+    writeln!(
+        stderr,
+        "{} is already in use by {}.",
+        addr,
+        owner.describe()
+    )?;
+    let mut choices = Vec::new();
+    if can_view {
+        writeln!(
+            stderr,
+            "  [v] view the existing database read-only (no receiving)"
+        )?;
+        choices.push(('v', Fallback::View));
+    }
+    if let Some(alternate) = alternate {
+        writeln!(
+            stderr,
+            "  [a] receive on {} and point the configured agents there",
+            alternate
+        )?;
+        choices.push(('a', Fallback::Alternate));
+    }
+    writeln!(stderr, "  [q] quit")?;
+    choices.push(('q', Fallback::Fail));
+
+    // View suits a second agenttop; another collector is better avoided
+    let default = if owner.agenttop {
+        choices[0]
+    } else {
+        *choices
+            .iter()
+            .find(|(_, fallback)| *fallback == Fallback::Alternate)
+            .unwrap_or(&choices[0])
+    };
+    write!(stderr, "Choice [{}]: ", default.0)?;
+    stderr.flush()?;
+
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+    let key = answer.trim().chars().next().map(|c| c.to_ascii_lowercase());
+    Ok(match key {
+        None => default.1,
+        Some(key) => choices
+            .iter()
+            .find(|(choice, _)| *choice == key)
+            .map_or(Fallback::Fail, |(_, fallback)| *fallback),
+    })
+}
+
+/// Where to connect to reach a listener bound to `addr` (loopback for 0.0.0.0 / ::)
+pub fn connect_addr(addr: SocketAddr) -> SocketAddr {
+    if addr.ip().is_unspecified() {
+        SocketAddr::new(Ipv4Addr::LOCALHOST.into(), addr.port())
+    } else {
+        addr
+    }
+}
+
+/// An agenttop receiver names itself in the `Server` header of every response
+async fn answers_like_agenttop(addr: SocketAddr) -> bool {
+    let exchange = async {
+        let mut stream = tokio::net::TcpStream::connect(addr).await?;
+        stream
+            .write_all(format!("GET /v1/logs HTTP/1.0\r\nHost: {addr}\r\n\r\n").as_bytes())
+            .await?;
+        let mut lines = BufReader::new(stream).lines();
+        while let Some(line) = lines.next_line().await? {
+            if line.is_empty() {
+                break;
+            }
+            let server = line
+                .split_once(':')
+                .filter(|(name, _)| name.eq_ignore_ascii_case("server"));
+            if server.is_some_and(|(_, value)| value.trim().starts_with("agenttop/")) {
+                return anyhow::Ok(true);
+            }
+        }
+        anyhow::Ok(false)
+    };
+    matches!(
+        tokio::time::timeout(PROBE_TIMEOUT, exchange).await,
+        Ok(Ok(true))
+    )
+}
+
+/// The process listening on `port`, via `lsof` or else `ss`
+fn listener_process(port: u16) -> Option<ListenerProcess> {
+    let run = |program: &str, args: &[&str]| {
+        Command::new(program)
+            .args(args)
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).into_owned())
+    };
+    let tcp = format!("-iTCP:{}", port);
+    if let Some(process) = run("lsof", &["-nP", &tcp, "-sTCP:LISTEN", "-Fpc"])
+        .as_deref()
+        .and_then(parse_lsof)
+    {
+        return Some(process);
+    }
+    let filter = format!("sport = :{}", port);
+    run("ss", &["-ltnpH", &filter])
+        .as_deref()
+        .and_then(parse_ss)
+}
+
+/// `lsof -F pc` output: "p1234\ncagenttop\n"
+fn parse_lsof(output: &str) -> Option<ListenerProcess> {
+    let pid = output.lines().find_map(|line| line.strip_prefix('p'))?;
+    let name = output
+        .lines()
+        .find_map(|line| line.strip_prefix('c'))
+        .unwrap_or("?");
+    Some(ListenerProcess {
+        name: name.to_string(),
+        pid: pid.parse().ok()?,
+    })
+}
+
+/// `ss -p` output: `... users:(("agenttop",pid=1234,fd=9))`
+fn parse_ss(output: &str) -> Option<ListenerProcess> {
+    let users = &output[output.find("users:((")? + "users:((".len()..];
+    let name = users.split('"').nth(1)?;
+    let pid = users
+        .split(',')
+        .find_map(|field| field.strip_prefix("pid="))?;
+    Some(ListenerProcess {
+        name: name.to_string(),
+        pid: pid.parse().ok()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_listener_process() {
+        let lsof = parse_lsof("p4242\ncagenttop\nf9\n").unwrap();
+        assert_eq!((lsof.name.as_str(), lsof.pid), ("agenttop", 4242));
+        assert_eq!(parse_lsof(""), None);
+
+        let ss =
+            parse_ss("LISTEN 0 1024 127.0.0.1:4318 0.0.0.0:* users:((\"otelcol\",pid=77,fd=7))\n")
+                .unwrap();
+        assert_eq!((ss.name.as_str(), ss.pid), ("otelcol", 77));
+        assert_eq!(parse_ss("LISTEN 0 1024 127.0.0.1:4318 0.0.0.0:*\n"), None);
+
+        let owner = PortOwner {
+            agenttop: true,
+            process: Some(lsof),
+        };
+        assert_eq!(owner.describe(), "another agenttop (pid 4242)");
+        let owner = PortOwner {
+            agenttop: false,
+            process: Some(ss),
+        };
+        assert_eq!(owner.describe(), "otelcol (pid 77)");
+    }
+
+    #[tokio::test]
+    async fn test_probe_and_alternate_port() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let owner = probe(addr).await.expect("port is taken");
+        // Accepts connections but never answers HTTP
+        assert!(!owner.agenttop);
+
+        let alternate = alternate_port(addr).expect("a free port nearby");
+        assert_ne!(alternate.port(), addr.port());
+        assert!(probe(alternate).await.is_none());
+    }
+}
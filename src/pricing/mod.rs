@@ -0,0 +1,138 @@
+//! Model pricing tables used to estimate spend from token counts.
+//!
+//! Prices are public list prices in USD per million tokens. They only feed
+//! estimates (the "Cache saved" figure and the estimated cost of token usage, for
+//! agents that report no cost), never the reported session cost.
+
+use crate::providers::{TOKEN_CACHE_READ, TOKEN_CACHE_WRITE, TOKEN_INPUT, TOKEN_OUTPUT};
+
+/// Prices for a model, in USD per million tokens
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModelPricing {
+    /// Regular (uncached) input tokens
+    pub input_per_mtok: f64,
+    pub output_per_mtok: f64,
+    /// Input tokens served from the prompt cache
+    pub cache_read_per_mtok: f64,
+    /// Input tokens written to the prompt cache (the input price where writes cost extra
+    /// only for Anthropic models)
+    pub cache_write_per_mtok: f64,
+}
+
+impl ModelPricing {
+    /// Price of a normalized token type (see [`crate::providers`]); None if unknown
+    pub fn per_mtok(&self, token_type: &str) -> Option<f64> {
+        match token_type {
+            TOKEN_INPUT => Some(self.input_per_mtok),
+            TOKEN_OUTPUT => Some(self.output_per_mtok),
+            TOKEN_CACHE_READ => Some(self.cache_read_per_mtok),
+            TOKEN_CACHE_WRITE => Some(self.cache_write_per_mtok),
+            _ => None,
+        }
+    }
+}
+
+const fn pricing(
+    input_per_mtok: f64,
+    output_per_mtok: f64,
+    cache_read_per_mtok: f64,
+    cache_write_per_mtok: f64,
+) -> ModelPricing {
+    ModelPricing {
+        input_per_mtok,
+        output_per_mtok,
+        cache_read_per_mtok,
+        cache_write_per_mtok,
+    }
+}
+
+/// Model name fragments and their pricing, most specific first
+const PRICING_TABLE: &[(&str, ModelPricing)] = &[
+    // Anthropic (cache writes at 1.25x the input price)
+    ("opus-4-5", pricing(5.0, 25.0, 0.50, 6.25)),
+    ("opus", pricing(15.0, 75.0, 1.50, 18.75)),
+    ("sonnet", pricing(3.0, 15.0, 0.30, 3.75)),
+    ("haiku-4-5", pricing(1.0, 5.0, 0.10, 1.25)),
+    ("3-5-haiku", pricing(0.80, 4.0, 0.08, 1.0)),
+    ("haiku", pricing(0.25, 1.25, 0.03, 0.30)),
+    // OpenAI
+    ("gpt-5-mini", pricing(0.25, 2.0, 0.025, 0.25)),
+    ("gpt-5", pricing(1.25, 10.0, 0.125, 1.25)),
+    ("gpt-4.1-mini", pricing(0.40, 1.60, 0.10, 0.40)),
+    ("gpt-4.1", pricing(2.0, 8.0, 0.50, 2.0)),
+    ("gpt-4o-mini", pricing(0.15, 0.60, 0.075, 0.15)),
+    ("gpt-4o", pricing(2.50, 10.0, 1.25, 2.50)),
+    ("o4-mini", pricing(1.10, 4.40, 0.275, 1.10)),
+    ("o3", pricing(2.0, 8.0, 0.50, 2.0)),
+    // Google
+    ("gemini-2.5-pro", pricing(1.25, 10.0, 0.31, 1.25)),
+    ("gemini-2.5-flash-lite", pricing(0.10, 0.40, 0.025, 0.10)),
+    ("gemini-2.5-flash", pricing(0.30, 2.50, 0.075, 0.30)),
+];
+
+/// Look up pricing for a model name (case-insensitive fragment match)
+pub fn lookup(model: &str) -> Option<ModelPricing> {
+    let model = model.to_lowercase();
+    PRICING_TABLE
+        .iter()
+        .find(|(fragment, _)| model.contains(fragment))
+        .map(|(_, pricing)| *pricing)
+}
+
+/// Estimated dollars for `count` tokens of a normalized `token_type` used by `model`;
+/// None when the model's pricing or the token type is unknown
+pub fn estimate_cost_usd(model: &str, token_type: &str, count: u64) -> Option<f64> {
+    let per_mtok = lookup(model)?.per_mtok(token_type)?;
+    Some(count as f64 * per_mtok / 1_000_000.0)
+}
+
+/// Dollars saved by serving `cache_read_tokens` from the cache instead of as full-price input
+pub fn cache_savings_usd(cache_read_tokens: u64, pricing: &ModelPricing) -> f64 {
+    cache_read_tokens as f64 * (pricing.input_per_mtok - pricing.cache_read_per_mtok).max(0.0)
+        / 1_000_000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_prefers_specific_entries() {
+        assert_eq!(
+            lookup("claude-opus-4-5-20251101"),
+            Some(pricing(5.0, 25.0, 0.50, 6.25))
+        );
+        assert_eq!(
+            lookup("claude-opus-4-1-20250805"),
+            Some(pricing(15.0, 75.0, 1.50, 18.75))
+        );
+        assert_eq!(
+            lookup("gpt-4o-mini"),
+            Some(pricing(0.15, 0.60, 0.075, 0.15))
+        );
+        assert_eq!(
+            lookup("Gemini-2.5-Flash"),
+            Some(pricing(0.30, 2.50, 0.075, 0.30))
+        );
+        assert_eq!(lookup("unknown-model"), None);
+    }
+
+    #[test]
+    fn test_cache_savings_usd() {
+        let sonnet = lookup("claude-sonnet-4-5").unwrap();
+        // 1M cached tokens: $3.00 at full price vs $0.30 from cache
+        assert!((cache_savings_usd(1_000_000, &sonnet) - 2.70).abs() < 1e-9);
+        assert_eq!(cache_savings_usd(0, &sonnet), 0.0);
+    }
+
+    #[test]
+    fn test_estimate_cost_usd() {
+        let estimate = |token_type| estimate_cost_usd("claude-sonnet-4-5", token_type, 1_000_000);
+        assert_eq!(estimate(TOKEN_INPUT), Some(3.0));
+        assert_eq!(estimate(TOKEN_OUTPUT), Some(15.0));
+        assert_eq!(estimate(TOKEN_CACHE_READ), Some(0.30));
+        assert_eq!(estimate(TOKEN_CACHE_WRITE), Some(3.75));
+        assert_eq!(estimate("reasoning"), None);
+        assert_eq!(estimate_cost_usd("unknown-model", TOKEN_INPUT, 10), None);
+    }
+}
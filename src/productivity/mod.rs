@@ -0,0 +1,176 @@
+//! Productivity: what the sessions produced for what they cost. Lines of code, commits
+//! and active time come from the agent's session metrics (Claude Code's
+//! `lines_of_code.count`, `commit.count` and `active_time.total`), spend from the
+//! api_request events, and are combined into lines per dollar, commits per hour and cost
+//! per commit, per session and per local week.
+
+use chrono::{DateTime, Datelike, Local, NaiveDate, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::storage::SessionProductivity;
+use crate::tui::app::format_usd;
+
+/// Code output and spend of one or more sessions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Productivity {
+    pub sessions: u64,
+    /// Net lines added
+    pub lines_of_code: i64,
+    pub commits: u64,
+    /// Active time where the agent reports it, else the sessions' length
+    pub hours: f64,
+    pub cost_usd: f64,
+}
+
+impl Productivity {
+    pub fn add(&mut self, session: &SessionProductivity) {
+        self.sessions += 1;
+        self.lines_of_code += session.lines_of_code;
+        self.commits += session.commits;
+        self.hours += session_hours(session);
+        self.cost_usd += session.cost_usd;
+    }
+
+    /// Net lines of code per dollar; None without spend
+    pub fn lines_per_usd(&self) -> Option<f64> {
+        (self.cost_usd > 0.0).then(|| self.lines_of_code as f64 / self.cost_usd)
+    }
+
+    /// None without time
+    pub fn commits_per_hour(&self) -> Option<f64> {
+        (self.hours > 0.0).then(|| self.commits as f64 / self.hours)
+    }
+
+    /// None without commits
+    pub fn cost_per_commit(&self) -> Option<f64> {
+        (self.commits > 0).then(|| self.cost_usd / self.commits as f64)
+    }
+
+    /// "120 lines/$ · 0.8 commits/h · $1.20/commit", leaving out the ratios there is no
+    /// data for
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(lines) = self.lines_per_usd() {
+            parts.push(format!("{lines:.0} lines/$"));
+        }
+        if let Some(commits) = self.commits_per_hour() {
+            parts.push(format!("{commits:.1} commits/h"));
+        }
+        if let Some(cost) = self.cost_per_commit() {
+            parts.push(format!("{}/commit", format_usd(cost)));
+        }
+        parts.join(" · ")
+    }
+}
+
+/// Hours a session counts for: its reported active time, else first to last event
+fn session_hours(session: &SessionProductivity) -> f64 {
+    if session.active_time_secs > 0 {
+        session.active_time_secs as f64 / 3600.0
+    } else {
+        (session.ended_at - session.started_at).num_seconds().max(0) as f64 / 3600.0
+    }
+}
+
+/// Monday of the local week holding `at`
+pub fn week_start(at: DateTime<Utc>) -> NaiveDate {
+    let day = at.with_timezone(&Local).date_naive();
+    day - chrono::Days::new(day.weekday().num_days_from_monday() as u64)
+}
+
+/// Productivity of one session
+pub fn of_session(session: &SessionProductivity) -> Productivity {
+    let mut productivity = Productivity::default();
+    productivity.add(session);
+    productivity
+}
+
+/// Totals of `sessions` per local week they started in, oldest first
+pub fn by_week(sessions: &[SessionProductivity]) -> Vec<(NaiveDate, Productivity)> {
+    let mut weeks: BTreeMap<NaiveDate, Productivity> = BTreeMap::new();
+    for session in sessions {
+        weeks
+            .entry(week_start(session.started_at))
+            .or_default()
+            .add(session);
+    }
+    weeks.into_iter().collect()
+}
+
+/// Totals of all `sessions`
+pub fn total(sessions: &[SessionProductivity]) -> Productivity {
+    let mut productivity = Productivity::default();
+    for session in sessions {
+        productivity.add(session);
+    }
+    productivity
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn session(
+        start: DateTime<Utc>,
+        minutes: i64,
+        active_secs: u64,
+        lines: i64,
+        commits: u64,
+        cost: f64,
+    ) -> SessionProductivity {
+        SessionProductivity {
+            session_id: format!("s{}", start.timestamp()),
+            started_at: start,
+            ended_at: start + chrono::Duration::minutes(minutes),
+            cost_usd: cost,
+            lines_of_code: lines,
+            commits,
+            active_time_secs: active_secs,
+        }
+    }
+
+    #[test]
+    fn test_ratios() {
+        let start = Utc.with_ymd_and_hms(2025, 6, 4, 12, 0, 0).unwrap();
+        // 30 minutes active of a 2 hour session
+        let p = of_session(&session(start, 120, 1800, 300, 2, 1.5));
+        assert_eq!(p.hours, 0.5);
+        assert_eq!(p.lines_per_usd(), Some(200.0));
+        assert_eq!(p.commits_per_hour(), Some(4.0));
+        assert_eq!(p.cost_per_commit(), Some(0.75));
+        assert_eq!(p.describe(), "200 lines/$ · 4.0 commits/h · $0.75/commit");
+
+        // No active time reported: the session's length counts
+        let p = of_session(&session(start, 90, 0, 40, 0, 0.0));
+        assert_eq!(p.hours, 1.5);
+        assert_eq!(p.lines_per_usd(), None);
+        assert_eq!(p.cost_per_commit(), None);
+        assert_eq!(p.describe(), "0.0 commits/h");
+    }
+
+    #[test]
+    fn test_by_week() {
+        let local = |d: u32, h: u32| {
+            Local
+                .with_ymd_and_hms(2025, 6, d, h, 0, 0)
+                .unwrap()
+                .with_timezone(&Utc)
+        };
+        // Monday 2 June and Sunday 8 June are one week, Monday 9 June the next
+        let sessions = [
+            session(local(9, 9), 60, 0, 50, 1, 1.0),
+            session(local(2, 9), 60, 0, 100, 1, 2.0),
+            session(local(8, 23), 30, 0, 20, 0, 0.5),
+        ];
+        let weeks = by_week(&sessions);
+        assert_eq!(weeks.len(), 2);
+        assert_eq!(weeks[0].0, NaiveDate::from_ymd_opt(2025, 6, 2).unwrap());
+        assert_eq!(weeks[0].1.sessions, 2);
+        assert_eq!(weeks[0].1.lines_of_code, 120);
+        assert_eq!(weeks[0].1.cost_usd, 2.5);
+        assert_eq!(weeks[1].0, NaiveDate::from_ymd_opt(2025, 6, 9).unwrap());
+        assert_eq!(total(&sessions).commits, 2);
+    }
+}
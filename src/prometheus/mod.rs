@@ -0,0 +1,426 @@
+//! `/metrics`: all-time aggregates in the Prometheus text exposition format
+//! (served on the receiver port with `--metrics`). `agenttop metrics` prints the same
+//! aggregates once, optionally as OpenMetrics, for textfile collectors.
+
+use anyhow::Result;
+use axum::{
+    Router,
+    extract::State,
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::self_metrics::{
+    CacheSnapshot, LATENCY_BUCKETS, ReceiverSnapshot, SELF_METRICS, StorageSnapshot,
+};
+use crate::storage::{StorageHandle, TokenMetrics, ToolMetrics};
+
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Routes for the receiver's router
+pub fn router() -> Router<StorageHandle> {
+    Router::new().route("/metrics", get(metrics))
+}
+
+async fn metrics(State(storage): State<StorageHandle>) -> Response {
+    let rendered = tokio::task::spawn_blocking(move || -> Result<String> {
+        let tools = storage.get_tool_metrics(None, None)?;
+        let tokens = storage.get_token_metrics(None, None)?;
+        Ok(render(
+            &tools,
+            &tokens,
+            Some(&SELF_METRICS.receiver.snapshot()),
+            Some(&SELF_METRICS.storage.snapshot()),
+            Some(&SELF_METRICS.caches.snapshot()),
+            TextFormat::Prometheus,
+        ))
+    })
+    .await;
+    match rendered {
+        Ok(Ok(body)) => ([(header::CONTENT_TYPE, CONTENT_TYPE)], body).into_response(),
+        Ok(Err(e)) => (StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e)).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// Name, type, help text and per-tool value of a metric family labelled by tool
+type ToolFamily = (
+    &'static str,
+    &'static str,
+    &'static str,
+    fn(&ToolMetrics) -> f64,
+);
+
+const TOOL_FAMILIES: [ToolFamily; 3] = [
+    (
+        "agenttop_tool_calls_total",
+        "counter",
+        "Tool calls recorded",
+        |t| t.call_count as f64,
+    ),
+    (
+        "agenttop_tool_errors_total",
+        "counter",
+        "Tool calls that failed",
+        |t| t.error_count as f64,
+    ),
+    (
+        "agenttop_tool_duration_seconds_avg",
+        "gauge",
+        "Average tool call duration",
+        |t| t.avg_duration_ms / 1000.0,
+    ),
+];
+
+/// Exposition format of [`render`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextFormat {
+    /// Prometheus text format 0.0.4
+    Prometheus,
+    /// OpenMetrics 1.0: counter families are named without `_total`, and the output
+    /// ends with `# EOF`
+    OpenMetrics,
+}
+
+/// Escape a label value (backslash, double quote and newline)
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Write the `# HELP` and `# TYPE` lines of a metric family
+fn family(out: &mut String, format: TextFormat, name: &str, kind: &str, help: &str) {
+    let name = match format {
+        TextFormat::Prometheus => name,
+        TextFormat::OpenMetrics => name.strip_suffix("_total").unwrap_or(name),
+    };
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+/// Render tool and token metrics, and the receiver's, storage's and caches'
+/// self-metrics when given, as text
+pub fn render(
+    tools: &[ToolMetrics],
+    tokens: &TokenMetrics,
+    receiver: Option<&ReceiverSnapshot>,
+    storage: Option<&StorageSnapshot>,
+    caches: Option<&BTreeMap<&'static str, CacheSnapshot>>,
+    format: TextFormat,
+) -> String {
+    let mut out = String::new();
+
+    for (name, kind, help, value) in TOOL_FAMILIES {
+        family(&mut out, format, name, kind, help);
+        for tool in tools {
+            let _ = writeln!(
+                out,
+                "{name}{{tool=\"{}\"}} {}",
+                escape_label(&tool.tool_name),
+                value(tool)
+            );
+        }
+    }
+
+    family(
+        &mut out,
+        format,
+        "agenttop_tokens_total",
+        "counter",
+        "Tokens used by type",
+    );
+    for (kind, count) in [
+        ("input", tokens.input_tokens),
+        ("output", tokens.output_tokens),
+        ("cache_read", tokens.cache_read_tokens),
+        ("cache_creation", tokens.cache_creation_tokens),
+    ] {
+        let _ = writeln!(out, "agenttop_tokens_total{{type=\"{kind}\"}} {count}");
+    }
+
+    family(
+        &mut out,
+        format,
+        "agenttop_cost_usd",
+        "gauge",
+        "Total cost reported by agents in USD",
+    );
+    let _ = writeln!(out, "agenttop_cost_usd {}", tokens.total_cost_usd);
+
+    if let Some(receiver) = receiver {
+        render_receiver(&mut out, receiver, format);
+    }
+    if let Some(storage) = storage {
+        render_storage(&mut out, storage, format);
+    }
+    if let Some(caches) = caches {
+        render_caches(&mut out, caches, format);
+    }
+    if format == TextFormat::OpenMetrics {
+        out.push_str("# EOF\n");
+    }
+    out
+}
+
+fn render_receiver(out: &mut String, receiver: &ReceiverSnapshot, format: TextFormat) {
+    family(
+        out,
+        format,
+        "agenttop_receiver_events_received_total",
+        "counter",
+        "Log events and metric data points accepted since start",
+    );
+    let _ = writeln!(
+        out,
+        "agenttop_receiver_events_received_total {}",
+        receiver.events_received
+    );
+    family(
+        out,
+        format,
+        "agenttop_receiver_payloads_dropped_total",
+        "counter",
+        "OTLP payloads rejected because they could not be parsed",
+    );
+    let _ = writeln!(
+        out,
+        "agenttop_receiver_payloads_dropped_total {}",
+        receiver.payloads_dropped
+    );
+    family(
+        out,
+        format,
+        "agenttop_receiver_batches_dropped_total",
+        "counter",
+        "Accepted write batches dropped because storage fell behind",
+    );
+    let _ = writeln!(
+        out,
+        "agenttop_receiver_batches_dropped_total {}",
+        receiver.batches_dropped
+    );
+    family(
+        out,
+        format,
+        "agenttop_receiver_hook_runs_dropped_total",
+        "counter",
+        "Event hook runs dropped because the hook queue was full",
+    );
+    let _ = writeln!(
+        out,
+        "agenttop_receiver_hook_runs_dropped_total {}",
+        receiver.hook_runs_dropped
+    );
+    family(
+        out,
+        format,
+        "agenttop_receiver_values_truncated_total",
+        "counter",
+        "Attribute values and bodies cut to the configured size caps",
+    );
+    let _ = writeln!(
+        out,
+        "agenttop_receiver_values_truncated_total {}",
+        receiver.values_truncated
+    );
+    if let Some(last) = receiver.last_event_at {
+        family(
+            out,
+            format,
+            "agenttop_receiver_last_event_timestamp_seconds",
+            "gauge",
+            "Unix time of the last accepted event",
+        );
+        let _ = writeln!(
+            out,
+            "agenttop_receiver_last_event_timestamp_seconds {}",
+            last.timestamp_millis() as f64 / 1000.0
+        );
+    }
+}
+
+/// Name, type, help text and per-cache value of a metric family labelled by cache
+type CacheFamily = (
+    &'static str,
+    &'static str,
+    &'static str,
+    fn(&CacheSnapshot) -> u64,
+);
+
+const CACHE_FAMILIES: [CacheFamily; 5] = [
+    (
+        "agenttop_cache_hits_total",
+        "counter",
+        "Cache lookups that found an entry",
+        |c| c.hits,
+    ),
+    (
+        "agenttop_cache_misses_total",
+        "counter",
+        "Cache lookups that found nothing",
+        |c| c.misses,
+    ),
+    (
+        "agenttop_cache_evictions_total",
+        "counter",
+        "Entries dropped to keep a cache within its capacity",
+        |c| c.evictions,
+    ),
+    (
+        "agenttop_cache_entries",
+        "gauge",
+        "Entries held by a cache",
+        |c| c.entries as u64,
+    ),
+    (
+        "agenttop_cache_capacity",
+        "gauge",
+        "Most entries a cache holds",
+        |c| c.capacity as u64,
+    ),
+];
+
+fn render_caches(
+    out: &mut String,
+    caches: &BTreeMap<&'static str, CacheSnapshot>,
+    format: TextFormat,
+) {
+    for (name, kind, help, value) in CACHE_FAMILIES {
+        family(out, format, name, kind, help);
+        for (cache, snapshot) in caches {
+            let _ = writeln!(out, "{name}{{cache=\"{cache}\"}} {}", value(snapshot));
+        }
+    }
+}
+
+fn render_storage(out: &mut String, storage: &StorageSnapshot, format: TextFormat) {
+    family(
+        out,
+        format,
+        "agenttop_storage_restarts_total",
+        "counter",
+        "Storage restarts after a failure",
+    );
+    let _ = writeln!(out, "agenttop_storage_restarts_total {}", storage.restarts);
+
+    const NAME: &str = "agenttop_storage_query_duration_seconds";
+    family(
+        out,
+        format,
+        NAME,
+        "histogram",
+        "Time the storage took per command",
+    );
+    for (query, histogram) in &storage.latencies {
+        let mut cumulative = 0;
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "{NAME}_bucket{{query=\"{query}\",le=\"{bound}\"}} {cumulative}"
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{NAME}_bucket{{query=\"{query}\",le=\"+Inf\"}} {}",
+            histogram.count
+        );
+        let _ = writeln!(
+            out,
+            "{NAME}_sum{{query=\"{query}\"}} {}",
+            histogram.sum_secs
+        );
+        let _ = writeln!(out, "{NAME}_count{{query=\"{query}\"}} {}", histogram.count);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let tools = vec![ToolMetrics {
+            tool_name: "mcp \"x\"".to_string(),
+            call_count: 3,
+            last_call: None,
+            avg_duration_ms: 250.0,
+            min_duration_ms: 100.0,
+            max_duration_ms: 400.0,
+            success_count: 2,
+            error_count: 1,
+            approved_count: 3,
+            rejected_count: 0,
+        }];
+        let tokens = TokenMetrics {
+            input_tokens: 10,
+            total_cost_usd: 0.5,
+            ..Default::default()
+        };
+        let storage = crate::self_metrics::StorageStats::default();
+        storage.record_latency("is_empty", std::time::Duration::from_millis(3));
+        let storage = storage.snapshot();
+        let caches = BTreeMap::from([(
+            "query_results",
+            CacheSnapshot {
+                capacity: 256,
+                entries: 2,
+                hits: 5,
+                misses: 2,
+                evictions: 0,
+            },
+        )]);
+        let out = render(
+            &tools,
+            &tokens,
+            Some(&ReceiverSnapshot::default()),
+            Some(&storage),
+            Some(&caches),
+            TextFormat::Prometheus,
+        );
+        assert!(out.contains("# TYPE agenttop_tool_calls_total counter\n"));
+        assert!(out.contains("agenttop_tool_calls_total{tool=\"mcp \\\"x\\\"\"} 3\n"));
+        assert!(out.contains("agenttop_tool_errors_total{tool=\"mcp \\\"x\\\"\"} 1\n"));
+        assert!(out.contains("agenttop_tool_duration_seconds_avg{tool=\"mcp \\\"x\\\"\"} 0.25\n"));
+        assert!(out.contains("agenttop_tokens_total{type=\"input\"} 10\n"));
+        assert!(out.contains("agenttop_cost_usd 0.5\n"));
+        assert!(out.contains("agenttop_receiver_events_received_total 0\n"));
+        assert!(!out.contains("last_event_timestamp"));
+        assert!(out.contains("# TYPE agenttop_storage_query_duration_seconds histogram\n"));
+        assert!(out.contains(
+            "agenttop_storage_query_duration_seconds_bucket{query=\"is_empty\",le=\"0.001\"} 0\n"
+        ));
+        assert!(out.contains(
+            "agenttop_storage_query_duration_seconds_bucket{query=\"is_empty\",le=\"0.005\"} 1\n"
+        ));
+        assert!(out.contains(
+            "agenttop_storage_query_duration_seconds_bucket{query=\"is_empty\",le=\"+Inf\"} 1\n"
+        ));
+        assert!(
+            out.contains("agenttop_storage_query_duration_seconds_count{query=\"is_empty\"} 1\n")
+        );
+        assert!(out.contains("# TYPE agenttop_cache_hits_total counter\n"));
+        assert!(out.contains("agenttop_cache_hits_total{cache=\"query_results\"} 5\n"));
+        assert!(out.contains("agenttop_cache_capacity{cache=\"query_results\"} 256\n"));
+    }
+
+    #[test]
+    fn test_render_openmetrics() {
+        let tokens = TokenMetrics {
+            output_tokens: 7,
+            ..Default::default()
+        };
+        let out = render(&[], &tokens, None, None, None, TextFormat::OpenMetrics);
+        assert!(out.contains("# TYPE agenttop_tool_calls counter\n"));
+        assert!(out.contains("# HELP agenttop_tokens Tokens used by type\n"));
+        assert!(out.contains("agenttop_tokens_total{type=\"output\"} 7\n"));
+        assert!(out.contains("# TYPE agenttop_cost_usd gauge\n"));
+        assert!(!out.contains("agenttop_receiver"));
+        assert!(out.ends_with("agenttop_cost_usd 0\n# EOF\n"));
+    }
+}
@@ -0,0 +1,196 @@
+//! Prompt size against cost: how a turn's cost grows with the prompt that started it,
+//! for tuning how much context to paste into the agent. Prompt lengths come from the
+//! user_prompt events' `prompt_length` attribute and costs from the turn's api_request
+//! events (see [`crate::storage::Turn`]); turns without a length are left out.
+
+use serde::Serialize;
+
+use crate::storage::Turn;
+use crate::tui::app::format_usd;
+
+/// Upper bounds (exclusive, in characters) and labels of the prompt size buckets; the
+/// last takes every longer prompt
+pub const SIZE_BUCKETS: [(u64, &str); 5] = [
+    (500, "<500"),
+    (2_000, "500-2K"),
+    (10_000, "2K-10K"),
+    (50_000, "10K-50K"),
+    (u64::MAX, "50K+"),
+];
+
+/// Turns whose prompts fall in one size bucket
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SizeBucket {
+    pub label: &'static str,
+    pub turns: u64,
+    pub prompt_chars: u64,
+    pub cost_usd: f64,
+    pub median_cost_usd: f64,
+}
+
+impl SizeBucket {
+    /// None without turns
+    pub fn avg_cost_usd(&self) -> Option<f64> {
+        (self.turns > 0).then(|| self.cost_usd / self.turns as f64)
+    }
+
+    /// None without prompt text
+    pub fn cost_per_1k_chars(&self) -> Option<f64> {
+        (self.prompt_chars > 0).then(|| self.cost_usd / self.prompt_chars as f64 * 1000.0)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PromptCost {
+    /// Turns with a prompt length
+    pub turns: u64,
+    /// Every bucket of [`SIZE_BUCKETS`], smallest prompts first
+    pub buckets: Vec<SizeBucket>,
+    /// Pearson correlation of prompt length and turn cost; None with fewer than two
+    /// turns or when either doesn't vary
+    pub correlation: Option<f64>,
+    /// Least-squares slope: what 1K more prompt characters adds to a turn's cost
+    pub marginal_cost_per_1k_chars: Option<f64>,
+    /// All turns' cost over all their prompt characters, per 1K
+    pub cost_per_1k_chars: Option<f64>,
+}
+
+impl PromptCost {
+    /// "r = 0.62 · +$0.04 per 1K prompt chars", or "" without enough turns
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(r) = self.correlation {
+            parts.push(format!("r = {r:.2}"));
+        }
+        if let Some(slope) = self.marginal_cost_per_1k_chars {
+            let sign = if slope < 0.0 { "-" } else { "+" };
+            parts.push(format!(
+                "{sign}{} per 1K prompt chars",
+                format_usd(slope.abs())
+            ));
+        }
+        parts.join(" · ")
+    }
+}
+
+/// Index into [`SIZE_BUCKETS`] of a prompt of `chars` characters
+fn bucket_of(chars: u64) -> usize {
+    SIZE_BUCKETS
+        .iter()
+        .position(|(upper, _)| chars < *upper)
+        .unwrap_or(SIZE_BUCKETS.len() - 1)
+}
+
+fn median(mut values: Vec<f64>) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.sort_by(f64::total_cmp);
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    }
+}
+
+/// Prompt size against cost over `turns`
+pub fn analyze(turns: &[Turn]) -> PromptCost {
+    let points: Vec<(f64, f64)> = turns
+        .iter()
+        .filter_map(|t| t.prompt_length.map(|len| (len as f64, t.cost_usd)))
+        .collect();
+
+    let mut costs: Vec<Vec<f64>> = vec![Vec::new(); SIZE_BUCKETS.len()];
+    let mut buckets: Vec<SizeBucket> = SIZE_BUCKETS
+        .iter()
+        .map(|(_, label)| SizeBucket {
+            label,
+            ..Default::default()
+        })
+        .collect();
+    for &(chars, cost) in &points {
+        let i = bucket_of(chars as u64);
+        buckets[i].turns += 1;
+        buckets[i].prompt_chars += chars as u64;
+        buckets[i].cost_usd += cost;
+        costs[i].push(cost);
+    }
+    for (bucket, costs) in buckets.iter_mut().zip(costs) {
+        bucket.median_cost_usd = median(costs);
+    }
+
+    let n = points.len() as f64;
+    let total_chars: f64 = points.iter().map(|p| p.0).sum();
+    let total_cost: f64 = points.iter().map(|p| p.1).sum();
+    let (mut sxx, mut syy, mut sxy) = (0.0, 0.0, 0.0);
+    if n > 0.0 {
+        let (mx, my) = (total_chars / n, total_cost / n);
+        for &(x, y) in &points {
+            sxx += (x - mx) * (x - mx);
+            syy += (y - my) * (y - my);
+            sxy += (x - mx) * (y - my);
+        }
+    }
+    PromptCost {
+        turns: points.len() as u64,
+        buckets,
+        correlation: (sxx > 0.0 && syy > 0.0).then(|| sxy / (sxx * syy).sqrt()),
+        marginal_cost_per_1k_chars: (sxx > 0.0).then(|| sxy / sxx * 1000.0),
+        cost_per_1k_chars: (total_chars > 0.0).then(|| total_cost / total_chars * 1000.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn turn(prompt_length: Option<u64>, cost_usd: f64) -> Turn {
+        Turn {
+            session_id: None,
+            started_at: Utc::now(),
+            ended_at: Utc::now(),
+            prompt_length,
+            api_calls: 1,
+            tool_calls: 0,
+            input_tokens: 0,
+            output_tokens: 0,
+            cost_usd,
+        }
+    }
+
+    #[test]
+    fn test_analyze() {
+        // Cost is 0.1 + 0.02 per 1K characters
+        let turns = [
+            turn(Some(100), 0.102),
+            turn(Some(1_000), 0.12),
+            turn(Some(1_500), 0.13),
+            turn(Some(20_000), 0.5),
+            turn(None, 9.0),
+        ];
+        let analysis = analyze(&turns);
+        assert_eq!(analysis.turns, 4);
+        assert!((analysis.correlation.unwrap() - 1.0).abs() < 1e-9);
+        assert!((analysis.marginal_cost_per_1k_chars.unwrap() - 0.02).abs() < 1e-9);
+        assert_eq!(analysis.describe(), "r = 1.00 · +$0.02 per 1K prompt chars");
+
+        let counts: Vec<u64> = analysis.buckets.iter().map(|b| b.turns).collect();
+        assert_eq!(counts, [1, 2, 0, 1, 0]);
+        let mid = &analysis.buckets[1];
+        assert_eq!(mid.label, "500-2K");
+        assert!((mid.median_cost_usd - 0.125).abs() < 1e-9);
+        assert!((mid.cost_per_1k_chars().unwrap() - 0.1).abs() < 1e-9);
+        assert_eq!(analysis.buckets[2].avg_cost_usd(), None);
+    }
+
+    #[test]
+    fn test_analyze_without_variation() {
+        let analysis = analyze(&[turn(Some(800), 0.1), turn(Some(800), 0.3)]);
+        assert_eq!(analysis.correlation, None);
+        assert_eq!(analysis.marginal_cost_per_1k_chars, None);
+        assert!((analysis.cost_per_1k_chars.unwrap() - 0.25).abs() < 1e-9);
+        assert_eq!(analyze(&[]).describe(), "");
+    }
+}
@@ -1,12 +1,12 @@
 //! Claude Code provider implementation
 
-use super::{Provider, TOKEN_CACHE_READ, TOKEN_CACHE_WRITE, TOKEN_INPUT, TOKEN_OUTPUT};
+use super::{
+    DEFAULT_OTLP_ENDPOINT, Provider, TOKEN_CACHE_READ, TOKEN_CACHE_WRITE, TOKEN_INPUT, TOKEN_OUTPUT,
+};
 use anyhow::{Context, Result};
 use std::fs;
 use std::path::PathBuf;
 
-const OTLP_ENDPOINT: &str = "http://localhost:4318";
-
 /// Built-in Claude Code tools
 const BUILTIN_TOOLS: &[&str] = &[
     "Read",
@@ -99,7 +99,22 @@ impl Provider for ClaudeCodeProvider {
         dirs::home_dir().map(|home| home.join(".claude").join("settings.json"))
     }
 
-    fn ensure_configured(&self) -> Result<bool> {
+    fn supports_setup(&self) -> bool {
+        true
+    }
+
+    fn is_configured(&self) -> Option<bool> {
+        let Some(settings) = super::read_json_settings(&self.settings_path()?) else {
+            return Some(false);
+        };
+        let env = |key: &str| settings.get("env")?.get(key)?.as_str().map(str::to_string);
+        Some(
+            env("CLAUDE_CODE_ENABLE_TELEMETRY").as_deref() == Some("1")
+                && env("OTEL_EXPORTER_OTLP_ENDPOINT").as_deref() == Some(DEFAULT_OTLP_ENDPOINT),
+        )
+    }
+
+    fn ensure_configured(&self, endpoint: &str) -> Result<bool> {
         let settings_path = self
             .settings_path()
             .ok_or_else(|| anyhow::anyhow!("Could not determine home directory"))?;
@@ -118,7 +133,7 @@ impl Provider for ClaudeCodeProvider {
                     "OTEL_METRICS_EXPORTER": "otlp",
                     "OTEL_LOGS_EXPORTER": "otlp",
                     "OTEL_EXPORTER_OTLP_PROTOCOL": "http/protobuf",
-                    "OTEL_EXPORTER_OTLP_ENDPOINT": OTLP_ENDPOINT
+                    "OTEL_EXPORTER_OTLP_ENDPOINT": endpoint
                 }
             });
 
@@ -158,7 +173,7 @@ impl Provider for ClaudeCodeProvider {
                     || env
                         .get("OTEL_EXPORTER_OTLP_ENDPOINT")
                         .and_then(|v| v.as_str())
-                        != Some(OTLP_ENDPOINT)
+                        != Some(endpoint)
             }
         };
 
@@ -174,8 +189,7 @@ impl Provider for ClaudeCodeProvider {
             env["OTEL_LOGS_EXPORTER"] = serde_json::Value::String("otlp".to_string());
             env["OTEL_EXPORTER_OTLP_PROTOCOL"] =
                 serde_json::Value::String("http/protobuf".to_string());
-            env["OTEL_EXPORTER_OTLP_ENDPOINT"] =
-                serde_json::Value::String(OTLP_ENDPOINT.to_string());
+            env["OTEL_EXPORTER_OTLP_ENDPOINT"] = serde_json::Value::String(endpoint.to_string());
 
             modified = true;
         }
@@ -1,12 +1,10 @@
 //! Gemini CLI provider implementation
 
-use super::{Provider, TOKEN_INPUT, TOKEN_OUTPUT};
+use super::{DEFAULT_OTLP_ENDPOINT, Provider, TOKEN_INPUT, TOKEN_OUTPUT};
 use anyhow::{Context, Result};
 use std::fs;
 use std::path::PathBuf;
 
-const OTLP_ENDPOINT: &str = "http://localhost:4318";
-
 /// Built-in Gemini CLI tools
 /// Note: Gemini CLI tool names may vary; these are the known ones
 const BUILTIN_TOOLS: &[&str] = &[
@@ -98,7 +96,25 @@ impl Provider for GeminiCliProvider {
         dirs::home_dir().map(|home| home.join(".gemini").join("settings.json"))
     }
 
-    fn ensure_configured(&self) -> Result<bool> {
+    fn supports_setup(&self) -> bool {
+        true
+    }
+
+    fn is_configured(&self) -> Option<bool> {
+        let Some(settings) = super::read_json_settings(&self.settings_path()?) else {
+            return Some(false);
+        };
+        let telemetry = settings.get("telemetry");
+        Some(
+            telemetry.and_then(|t| t.get("enabled")) == Some(&serde_json::Value::Bool(true))
+                && telemetry
+                    .and_then(|t| t.get("otlpEndpoint"))
+                    .and_then(|v| v.as_str())
+                    == Some(DEFAULT_OTLP_ENDPOINT),
+        )
+    }
+
+    fn ensure_configured(&self, endpoint: &str) -> Result<bool> {
         let settings_path = self
             .settings_path()
             .ok_or_else(|| anyhow::anyhow!("Could not determine home directory"))?;
@@ -114,7 +130,7 @@ impl Provider for GeminiCliProvider {
                 "telemetry": {
                     "enabled": true,
                     "target": "local",
-                    "otlpEndpoint": OTLP_ENDPOINT,
+                    "otlpEndpoint": endpoint,
                     "otlpProtocol": "http"
                 }
             });
@@ -143,7 +159,7 @@ impl Provider for GeminiCliProvider {
             Some(t) => {
                 t.get("enabled") != Some(&serde_json::Value::Bool(true))
                     || t.get("target").and_then(|v| v.as_str()) != Some("local")
-                    || t.get("otlpEndpoint").and_then(|v| v.as_str()) != Some(OTLP_ENDPOINT)
+                    || t.get("otlpEndpoint").and_then(|v| v.as_str()) != Some(endpoint)
             }
         };
 
@@ -151,7 +167,7 @@ impl Provider for GeminiCliProvider {
             settings["telemetry"] = serde_json::json!({
                 "enabled": true,
                 "target": "local",
-                "otlpEndpoint": OTLP_ENDPOINT,
+                "otlpEndpoint": endpoint,
                 "otlpProtocol": "http"
             });
             modified = true;
@@ -21,6 +21,9 @@ pub const TOKEN_OUTPUT: &str = "output";
 pub const TOKEN_CACHE_READ: &str = "cache_read";
 pub const TOKEN_CACHE_WRITE: &str = "cache_write";
 
+/// Where providers send telemetry unless `--setup` is given `--endpoint`: the local receiver
+pub const DEFAULT_OTLP_ENDPOINT: &str = "http://localhost:4318";
+
 /// Trait for AI coding agent providers
 pub trait Provider: Send + Sync {
     /// Unique ID (e.g., "claude_code")
@@ -30,7 +33,6 @@ pub trait Provider: Send + Sync {
     fn name(&self) -> &'static str;
 
     /// OTLP metric/event prefix for auto-detection (e.g., "claude_code")
-    #[allow(dead_code)]
     fn metric_prefix(&self) -> &'static str;
 
     /// Built-in tools specific to this agent
@@ -42,17 +44,49 @@ pub trait Provider: Send + Sync {
     /// Normalize token type to internal format. Returns None if unknown.
     fn normalize_token_type(&self, token_type: &str) -> Option<&'static str>;
 
-    /// Configure this provider's OTLP settings. Returns Ok(true) if configured.
-    fn ensure_configured(&self) -> Result<bool> {
+    /// Configure this provider to send OTLP telemetry to `endpoint`.
+    /// Returns Ok(true) if the settings were changed.
+    fn ensure_configured(&self, _endpoint: &str) -> Result<bool> {
         Ok(false) // Default: no auto-config
     }
 
+    /// Whether ensure_configured() can set up telemetry for this provider
+    fn supports_setup(&self) -> bool {
+        false
+    }
+
+    /// Whether the settings file already sends telemetry to the local agenttop, without
+    /// modifying it.
+    /// None if this provider's settings can't be checked.
+    fn is_configured(&self) -> Option<bool> {
+        None
+    }
+
     /// Get the settings file path for this provider (if applicable)
     fn settings_path(&self) -> Option<std::path::PathBuf> {
         None
     }
 }
 
+/// Parse an `--endpoint` URL: http(s) with a host; a trailing `/` is dropped
+pub fn parse_endpoint(input: &str) -> Result<String, String> {
+    let url = input.trim().trim_end_matches('/');
+    let host = url
+        .strip_prefix("http://")
+        .or_else(|| url.strip_prefix("https://"))
+        .ok_or_else(|| format!("Endpoint must start with http:// or https://: {}", input))?;
+    if host.is_empty() || host.starts_with(':') {
+        return Err(format!("Endpoint has no host: {}", input));
+    }
+    Ok(url.to_string())
+}
+
+/// Read a provider's JSON settings file; None if it is missing or unparseable
+fn read_json_settings(path: &std::path::Path) -> Option<serde_json::Value> {
+    let content = std::fs::read_to_string(path).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
 /// Registry of all known providers
 pub struct ProviderRegistry {
     providers: Vec<Box<dyn Provider>>,
@@ -85,7 +119,6 @@ impl ProviderRegistry {
     }
 
     /// Detect provider from metric/event name prefix
-    #[allow(dead_code)]
     pub fn detect_from_metric(&self, metric_name: &str) -> Option<&dyn Provider> {
         self.providers
             .iter()
@@ -110,9 +143,9 @@ impl ProviderRegistry {
                 return short;
             }
         }
-        // Fallback: truncate to 12 chars
-        if model_name.len() > 12 {
-            format!("{}...", &model_name[..12])
+        // Fallback: truncate to 12 columns
+        if crate::text::width(model_name) > 12 {
+            format!("{}...", crate::text::prefix(model_name, 12))
         } else {
             model_name.to_string()
         }
@@ -163,6 +196,21 @@ mod tests {
         assert!(registry.get("unknown").is_none());
     }
 
+    #[test]
+    fn test_parse_endpoint() {
+        assert_eq!(
+            parse_endpoint("http://collector:4318/"),
+            Ok("http://collector:4318".to_string())
+        );
+        assert_eq!(
+            parse_endpoint("https://otel.example.com"),
+            Ok("https://otel.example.com".to_string())
+        );
+        assert!(parse_endpoint("collector:4318").is_err());
+        assert!(parse_endpoint("http://").is_err());
+        assert!(parse_endpoint("http://:4318").is_err());
+    }
+
     #[test]
     fn test_detect_from_metric() {
         let registry = ProviderRegistry::new();
@@ -261,5 +309,10 @@ mod tests {
             registry.shorten_model_name("some-very-long-unknown-model-name"),
             "some-very-lo..."
         );
+        // Multibyte names are cut between characters instead of panicking
+        assert_eq!(
+            registry.shorten_model_name("модель-для-кода"),
+            "модель-для-к..."
+        );
     }
 }
@@ -97,6 +97,12 @@ impl Provider for OpenAICodexProvider {
     }
 
     // No ensure_configured() - TOML format requires manual setup (documented in README)
+
+    fn is_configured(&self) -> Option<bool> {
+        // No TOML parser here; look for the agenttop endpoint anywhere in the file
+        let content = std::fs::read_to_string(self.settings_path()?).unwrap_or_default();
+        Some(content.contains("localhost:4318") || content.contains("127.0.0.1:4318"))
+    }
 }
 
 #[cfg(test)]
@@ -1,12 +1,10 @@
 //! Qwen Code provider implementation
 
-use super::{Provider, TOKEN_CACHE_READ, TOKEN_INPUT, TOKEN_OUTPUT};
+use super::{DEFAULT_OTLP_ENDPOINT, Provider, TOKEN_CACHE_READ, TOKEN_INPUT, TOKEN_OUTPUT};
 use anyhow::{Context, Result};
 use std::fs;
 use std::path::PathBuf;
 
-const OTLP_ENDPOINT: &str = "http://localhost:4318";
-
 /// Built-in Qwen Code tools
 /// Note: Qwen Code tool names may vary; these are estimated based on similar tools
 const BUILTIN_TOOLS: &[&str] = &[
@@ -88,7 +86,25 @@ impl Provider for QwenCodeProvider {
         dirs::home_dir().map(|home| home.join(".qwen").join("settings.json"))
     }
 
-    fn ensure_configured(&self) -> Result<bool> {
+    fn supports_setup(&self) -> bool {
+        true
+    }
+
+    fn is_configured(&self) -> Option<bool> {
+        let Some(settings) = super::read_json_settings(&self.settings_path()?) else {
+            return Some(false);
+        };
+        let telemetry = settings.get("telemetry");
+        Some(
+            telemetry.and_then(|t| t.get("enabled")) == Some(&serde_json::Value::Bool(true))
+                && telemetry
+                    .and_then(|t| t.get("otlpEndpoint"))
+                    .and_then(|v| v.as_str())
+                    == Some(DEFAULT_OTLP_ENDPOINT),
+        )
+    }
+
+    fn ensure_configured(&self, endpoint: &str) -> Result<bool> {
         let settings_path = self
             .settings_path()
             .ok_or_else(|| anyhow::anyhow!("Could not determine home directory"))?;
@@ -104,7 +120,7 @@ impl Provider for QwenCodeProvider {
                 "telemetry": {
                     "enabled": true,
                     "target": "local",
-                    "otlpEndpoint": OTLP_ENDPOINT,
+                    "otlpEndpoint": endpoint,
                     "otlpProtocol": "http"
                 }
             });
@@ -133,7 +149,7 @@ impl Provider for QwenCodeProvider {
             Some(t) => {
                 t.get("enabled") != Some(&serde_json::Value::Bool(true))
                     || t.get("target").and_then(|v| v.as_str()) != Some("local")
-                    || t.get("otlpEndpoint").and_then(|v| v.as_str()) != Some(OTLP_ENDPOINT)
+                    || t.get("otlpEndpoint").and_then(|v| v.as_str()) != Some(endpoint)
             }
         };
 
@@ -141,7 +157,7 @@ impl Provider for QwenCodeProvider {
             settings["telemetry"] = serde_json::json!({
                 "enabled": true,
                 "target": "local",
-                "otlpEndpoint": OTLP_ENDPOINT,
+                "otlpEndpoint": endpoint,
                 "otlpProtocol": "http"
             });
             modified = true;
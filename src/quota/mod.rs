@@ -0,0 +1,475 @@
+//! Usage-window quotas of subscription plans, e.g. Claude's 5-hour windows: a window
+//! opens with the first request after the previous one reset and lasts `window_hours`,
+//! and the plan allows so much usage per window.
+//!
+//! Windows are found from the stored api_request times, and the usage in the current one
+//! is compared with the quota's capacity: `limit_tokens` and/or `limit_usd` when
+//! configured, or else the usage at the last rate-limited request, the wall the plan
+//! actually hit. The TUI header shows the capacity left and when the window resets, and
+//! the alert banner warns once usage passes `warn_pct`, or sooner when the current rate
+//! would use the window up before it resets.
+//!
+//! ```json
+//! "quotas": [
+//!   { "provider": "claude_code", "window_hours": 5, "limit_tokens": 4000000 }
+//! ]
+//! ```
+
+use anyhow::Result;
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::budget::BudgetLevel;
+use crate::report::format_count;
+use crate::storage::{StorageHandle, UsagePoint};
+use crate::tui::app::format_usd;
+
+/// How often the background monitor checks quotas
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Windows of usage read back from now, to find where the current one opened and the
+/// last rate-limited one
+pub const LOOKBACK_WINDOWS: i32 = 10;
+
+/// Minutes into a window before its rate is projected to the reset (earlier, a burst
+/// of requests would project a wall that isn't coming)
+const MIN_PROJECTION_MINS: i64 = 15;
+
+/// A plan's usage limit per window, for one provider or all
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Quota {
+    /// Provider id (e.g. "claude_code"); all providers' usage when omitted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    #[serde(default = "default_window_hours")]
+    pub window_hours: f64,
+    /// Input and output tokens allowed per window
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit_tokens: Option<u64>,
+    /// Spend (at API prices) allowed per window
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit_usd: Option<f64>,
+    /// Percent of the capacity at which the quota turns yellow
+    #[serde(default = "default_warn_pct")]
+    pub warn_pct: f64,
+    /// Percent of the capacity at which the quota turns red
+    #[serde(default = "default_critical_pct")]
+    pub critical_pct: f64,
+}
+
+fn default_window_hours() -> f64 {
+    5.0
+}
+
+fn default_warn_pct() -> f64 {
+    75.0
+}
+
+fn default_critical_pct() -> f64 {
+    90.0
+}
+
+impl Quota {
+    pub fn window(&self) -> chrono::Duration {
+        chrono::Duration::milliseconds((self.window_hours.max(0.0) * 3_600_000.0) as i64)
+    }
+
+    /// The provider, or "all"
+    pub fn name(&self) -> &str {
+        self.provider.as_deref().unwrap_or("all")
+    }
+}
+
+/// Usage in one window
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UsageWindow {
+    pub started_at: DateTime<Utc>,
+    pub resets_at: DateTime<Utc>,
+    pub requests: u64,
+    pub tokens: u64,
+    pub cost_usd: f64,
+    /// First rate-limited request in the window
+    pub rate_limited_at: Option<DateTime<Utc>>,
+    /// Tokens and spend before the first rate-limited request: what the plan allowed
+    pub tokens_at_limit: Option<u64>,
+    pub cost_at_limit_usd: Option<f64>,
+}
+
+/// Split `points` (oldest first) into windows: each opens at the first point after the
+/// previous one reset
+pub fn windows(points: &[UsagePoint], window: chrono::Duration) -> Vec<UsageWindow> {
+    let mut windows: Vec<UsageWindow> = Vec::new();
+    for point in points {
+        let current = match windows.last_mut() {
+            Some(last) if point.timestamp < last.resets_at => last,
+            _ => {
+                windows.push(UsageWindow {
+                    started_at: point.timestamp,
+                    resets_at: point.timestamp + window,
+                    requests: 0,
+                    tokens: 0,
+                    cost_usd: 0.0,
+                    rate_limited_at: None,
+                    tokens_at_limit: None,
+                    cost_at_limit_usd: None,
+                });
+                windows.last_mut().expect("just pushed")
+            }
+        };
+        if point.rate_limited {
+            if current.rate_limited_at.is_none() {
+                current.rate_limited_at = Some(point.timestamp);
+                current.tokens_at_limit = Some(current.tokens);
+                current.cost_at_limit_usd = Some(current.cost_usd);
+            }
+        } else {
+            current.requests += 1;
+            current.tokens += point.tokens;
+            current.cost_usd += point.cost_usd;
+        }
+    }
+    windows
+}
+
+/// Usage allowed per window
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Capacity {
+    pub tokens: Option<u64>,
+    pub usd: Option<f64>,
+    /// Taken from the last rate-limited window rather than configured
+    pub learned: bool,
+}
+
+impl Capacity {
+    /// The configured limits, or the usage at which the latest of `windows` was rate
+    /// limited; None when neither is known
+    pub fn of(quota: &Quota, windows: &[UsageWindow]) -> Option<Self> {
+        if quota.limit_tokens.is_some() || quota.limit_usd.is_some() {
+            return Some(Self {
+                tokens: quota.limit_tokens,
+                usd: quota.limit_usd,
+                learned: false,
+            });
+        }
+        windows
+            .iter()
+            .rev()
+            .find_map(|w| w.tokens_at_limit.filter(|tokens| *tokens > 0))
+            .map(|tokens| Self {
+                tokens: Some(tokens),
+                usd: None,
+                learned: true,
+            })
+    }
+
+    /// Percent of the capacity `tokens` and `cost_usd` use, by whichever limit is closer
+    pub fn percent(&self, tokens: u64, cost_usd: f64) -> f64 {
+        let by_tokens = self
+            .tokens
+            .filter(|limit| *limit > 0)
+            .map(|limit| tokens as f64 / limit as f64 * 100.0);
+        let by_usd = self
+            .usd
+            .filter(|limit| *limit > 0.0)
+            .map(|limit| cost_usd / limit * 100.0);
+        by_tokens.into_iter().chain(by_usd).fold(0.0, f64::max)
+    }
+}
+
+/// Usage of the current window against a quota
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QuotaStatus {
+    /// The provider, or "all"
+    pub name: String,
+    /// None when no window is open (the next request opens one with full capacity)
+    pub window: Option<UsageWindow>,
+    pub capacity: Option<Capacity>,
+    pub level: BudgetLevel,
+    /// When the window's usage so far, continued at the same rate, would reach the
+    /// capacity; only set when that is before the reset
+    pub exhausts_at: Option<DateTime<Utc>>,
+}
+
+impl QuotaStatus {
+    /// Status at `now` from the windows of the last [`LOOKBACK_WINDOWS`]
+    pub fn new(quota: &Quota, windows: &[UsageWindow], now: DateTime<Utc>) -> Self {
+        let capacity = Capacity::of(quota, windows);
+        let window = windows
+            .iter()
+            .rev()
+            .find(|w| w.started_at <= now && now < w.resets_at)
+            .cloned();
+        let mut status = Self {
+            name: quota.name().to_string(),
+            window,
+            capacity,
+            level: BudgetLevel::Ok,
+            exhausts_at: None,
+        };
+        let Some(window) = &status.window else {
+            return status;
+        };
+        if window.rate_limited_at.is_some() {
+            status.level = BudgetLevel::Exceeded;
+            return status;
+        }
+        let Some(pct) = status.percent() else {
+            return status;
+        };
+        let elapsed = now - window.started_at;
+        if pct > 0.0 && elapsed >= chrono::Duration::minutes(MIN_PROJECTION_MINS) {
+            let full = elapsed.num_milliseconds() as f64 * 100.0 / pct;
+            let at = window.started_at + chrono::Duration::milliseconds(full as i64);
+            status.exhausts_at = (at < window.resets_at).then_some(at);
+        }
+        status.level = if pct >= 100.0 {
+            BudgetLevel::Exceeded
+        } else if pct >= quota.critical_pct {
+            BudgetLevel::Critical
+        } else if pct >= quota.warn_pct || status.exhausts_at.is_some() {
+            BudgetLevel::Warn
+        } else {
+            BudgetLevel::Ok
+        };
+        status
+    }
+
+    /// Percent of the capacity the current window has used; None without a known
+    /// capacity
+    pub fn percent(&self) -> Option<f64> {
+        let capacity = self.capacity?;
+        Some(
+            self.window
+                .as_ref()
+                .map_or(0.0, |w| capacity.percent(w.tokens, w.cost_usd)),
+        )
+    }
+
+    /// e.g. "claude_code: 38% left, resets 14:32", "claude_code: rate limited, resets
+    /// 14:32" or "claude_code: 1.2M tokens, resets 14:32" without a known capacity
+    pub fn describe(&self) -> String {
+        let Some(window) = &self.window else {
+            return format!("{}: no window open", self.name);
+        };
+        let resets = window.resets_at.with_timezone(&Local).format("%H:%M");
+        let usage = if window.rate_limited_at.is_some() {
+            "rate limited".to_string()
+        } else {
+            match (self.percent(), self.capacity) {
+                (Some(pct), Some(capacity)) => format!(
+                    "{:.0}% left{}",
+                    (100.0 - pct).max(0.0),
+                    if capacity.learned { " (learned)" } else { "" }
+                ),
+                _ => format!(
+                    "{} tokens, {}",
+                    format_count(window.tokens),
+                    format_usd(window.cost_usd)
+                ),
+            }
+        };
+        let mut text = format!("{}: {}, resets {}", self.name, usage, resets);
+        if let Some(at) = self.exhausts_at {
+            text.push_str(&format!(
+                ", out by {} at this rate",
+                at.with_timezone(&Local).format("%H:%M")
+            ));
+        }
+        text
+    }
+}
+
+/// Windows of `quota` over the last [`LOOKBACK_WINDOWS`]
+pub fn recent_windows(
+    storage: &StorageHandle,
+    quota: &Quota,
+    now: DateTime<Utc>,
+) -> Result<Vec<UsageWindow>> {
+    let window = quota.window();
+    let points =
+        storage.get_usage_points(quota.provider.as_deref(), now - window * LOOKBACK_WINDOWS)?;
+    Ok(windows(&points, window))
+}
+
+/// Current window of every quota
+pub fn check(
+    storage: &StorageHandle,
+    quotas: &[Quota],
+    now: DateTime<Utc>,
+) -> Result<Vec<QuotaStatus>> {
+    quotas
+        .iter()
+        .map(|quota| {
+            let windows = recent_windows(storage, quota, now)?;
+            Ok(QuotaStatus::new(quota, &windows, now))
+        })
+        .collect()
+}
+
+/// The status at the highest level, closest to its capacity among those
+pub fn most_severe(statuses: &[QuotaStatus]) -> Option<&QuotaStatus> {
+    statuses.iter().max_by(|a, b| {
+        a.level.cmp(&b.level).then(
+            a.percent()
+                .unwrap_or(0.0)
+                .total_cmp(&b.percent().unwrap_or(0.0)),
+        )
+    })
+}
+
+/// Log quota level changes on a background thread
+pub fn spawn_monitor(storage: StorageHandle, quotas: Vec<Quota>) {
+    if quotas.is_empty() {
+        return;
+    }
+    std::thread::spawn(move || {
+        let mut levels = vec![BudgetLevel::Ok; quotas.len()];
+        loop {
+            match check(&storage, &quotas, Utc::now()) {
+                Ok(statuses) => {
+                    for (status, level) in statuses.iter().zip(&mut levels) {
+                        if status.level == *level {
+                            continue;
+                        }
+                        match status.level {
+                            BudgetLevel::Ok => {}
+                            BudgetLevel::Warn => {
+                                tracing::warn!("Usage window warning: {}", status.describe())
+                            }
+                            BudgetLevel::Critical => {
+                                tracing::error!("Usage window critical: {}", status.describe())
+                            }
+                            BudgetLevel::Exceeded => {
+                                tracing::error!("Usage window exhausted: {}", status.describe())
+                            }
+                        }
+                        *level = status.level;
+                    }
+                }
+                Err(e) => tracing::warn!("Could not check usage windows: {:#}", e),
+            }
+            std::thread::sleep(CHECK_INTERVAL);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(mins: i64) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, 6, 1, 8, 0, 0).unwrap() + chrono::Duration::minutes(mins)
+    }
+
+    fn request(mins: i64, tokens: u64) -> UsagePoint {
+        UsagePoint {
+            timestamp: at(mins),
+            tokens,
+            cost_usd: tokens as f64 / 1_000_000.0,
+            rate_limited: false,
+        }
+    }
+
+    fn rate_limited(mins: i64) -> UsagePoint {
+        UsagePoint {
+            timestamp: at(mins),
+            tokens: 0,
+            cost_usd: 0.0,
+            rate_limited: true,
+        }
+    }
+
+    fn quota(limit_tokens: Option<u64>) -> Quota {
+        serde_json::from_value(serde_json::json!({ "limit_tokens": limit_tokens })).unwrap()
+    }
+
+    #[test]
+    fn test_windows() {
+        let points = [
+            request(0, 100),
+            request(60, 200),
+            rate_limited(120),
+            request(130, 50),
+            // The first window reset at 5:00; this opens the next
+            request(301, 10),
+            request(400, 20),
+        ];
+        let windows = windows(&points, chrono::Duration::hours(5));
+        assert_eq!(windows.len(), 2);
+        assert_eq!(
+            (windows[0].started_at, windows[0].resets_at),
+            (at(0), at(300))
+        );
+        assert_eq!((windows[0].requests, windows[0].tokens), (3, 350));
+        assert_eq!(windows[0].rate_limited_at, Some(at(120)));
+        assert_eq!(windows[0].tokens_at_limit, Some(300));
+        assert_eq!(windows[1].started_at, at(301));
+        assert_eq!(windows[1].tokens, 30);
+        assert_eq!(windows[1].tokens_at_limit, None);
+    }
+
+    #[test]
+    fn test_quota_status() {
+        let window = chrono::Duration::hours(5);
+        let windows = windows(&[request(0, 500), request(60, 300)], window);
+
+        // 80% used an hour into the window: past the warning, and on course to run out
+        let status = QuotaStatus::new(&quota(Some(1000)), &windows, at(60));
+        assert_eq!(status.percent(), Some(80.0));
+        assert_eq!(status.level, BudgetLevel::Warn);
+        assert_eq!(status.exhausts_at, Some(at(75)));
+        assert!(status.describe().starts_with("all: 20% left, resets "));
+        assert!(status.describe().contains(", out by "));
+
+        // A slow start isn't projected to the wall
+        let slow = QuotaStatus::new(&quota(Some(100_000)), &windows, at(60));
+        assert_eq!(slow.level, BudgetLevel::Ok);
+        assert_eq!(slow.exhausts_at, None);
+
+        // Without a capacity, only the usage is shown
+        let unknown = QuotaStatus::new(&quota(None), &windows, at(60));
+        assert_eq!(unknown.percent(), None);
+        assert!(unknown.describe().starts_with("all: 800 tokens, "));
+
+        // After the reset no window is open
+        let reset = QuotaStatus::new(&quota(Some(1000)), &windows, at(300));
+        assert_eq!(reset.window, None);
+        assert_eq!(reset.percent(), Some(0.0));
+        assert_eq!(reset.level, BudgetLevel::Ok);
+    }
+
+    #[test]
+    fn test_learned_capacity() {
+        let points = [
+            request(0, 600),
+            request(30, 400),
+            rate_limited(45),
+            request(400, 250),
+        ];
+        let windows = windows(&points, chrono::Duration::hours(5));
+        let status = QuotaStatus::new(&quota(None), &windows, at(410));
+        let capacity = status.capacity.unwrap();
+        assert_eq!((capacity.tokens, capacity.learned), (Some(1000), true));
+        assert_eq!(status.percent(), Some(25.0));
+        assert!(status.describe().contains("75% left (learned)"));
+
+        // Rate limited in the current window
+        let limited = QuotaStatus::new(&quota(None), &windows, at(50));
+        assert_eq!(limited.level, BudgetLevel::Exceeded);
+        assert!(limited.describe().starts_with("all: rate limited, resets "));
+    }
+
+    #[test]
+    fn test_most_severe() {
+        let windows = windows(&[request(0, 500)], chrono::Duration::hours(5));
+        let statuses = [
+            QuotaStatus::new(&quota(Some(10_000)), &windows, at(1)),
+            QuotaStatus::new(&quota(Some(600)), &windows, at(1)),
+        ];
+        let worst = most_severe(&statuses).unwrap().percent().unwrap();
+        assert!((worst - 500.0 / 6.0).abs() < 1e-9);
+        assert!(most_severe(&[]).is_none());
+    }
+}
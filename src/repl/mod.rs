@@ -0,0 +1,299 @@
+//! `agenttop repl`: an interactive read-only SQL prompt on the metrics database, with
+//! line editing, history and completion of table and column names.
+//!
+//! Statements end with `;` and may span lines; lines starting with `.` are commands
+//! (`.help` lists them).
+
+use anyhow::Result;
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use std::path::PathBuf;
+
+use crate::export::{self, QueryFormat};
+use crate::storage::{self, StorageHandle};
+
+/// Commands understood at the start of a line
+const COMMANDS: &[&str] = &[
+    ".help", ".tables", ".schema", ".format", ".limit", ".quit", ".exit",
+];
+
+const HELP: &str = "\
+Enter a SELECT-like statement ending with `;` (it may span lines).
+  .tables           List the tables
+  .schema TABLE     Show the columns of TABLE
+  .format FORMAT    Print results as table, csv or json
+  .limit N          Print at most N rows
+  .quit             Leave (or Ctrl-D)";
+
+/// Completes commands at the start of the line and table and column names elsewhere
+struct ReplHelper {
+    /// Tables, then their columns, without duplicates
+    names: Vec<String>,
+}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let (start, matches) = complete(&self.names, line, pos);
+        Ok((
+            start,
+            matches
+                .into_iter()
+                .map(|name| Pair {
+                    display: name.clone(),
+                    replacement: name,
+                })
+                .collect(),
+        ))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+/// Where the word at `pos` starts and the names it may complete to
+fn complete(names: &[String], line: &str, pos: usize) -> (usize, Vec<String>) {
+    let before = &line[..pos];
+    let start = before
+        .rfind(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+        .map_or(0, |i| i + 1);
+    let word = &before[start..];
+
+    if start == 0 && word.starts_with('.') {
+        let matches = COMMANDS
+            .iter()
+            .filter(|command| command.starts_with(word))
+            .map(|command| command.to_string())
+            .collect();
+        return (start, matches);
+    }
+
+    // Complete only the part after a qualifier, e.g. the column in "t.col"
+    let start = start + word.rfind('.').map_or(0, |i| i + 1);
+    let word = before[start..].to_lowercase();
+    if word.is_empty() {
+        return (start, Vec::new());
+    }
+    let matches = names
+        .iter()
+        .filter(|name| name.starts_with(&word))
+        .cloned()
+        .collect();
+    (start, matches)
+}
+
+/// The tables followed by their columns, for completion
+fn completion_names(storage: &StorageHandle) -> Vec<String> {
+    let mut names: Vec<String> = storage::TABLES.iter().map(|t| t.to_string()).collect();
+    let columns = storage.run_query(
+        "SELECT DISTINCT column_name FROM information_schema.columns \
+         WHERE table_schema = 'main' ORDER BY column_name",
+        10_000,
+    );
+    if let Ok(columns) = columns {
+        for column in columns
+            .rows
+            .into_iter()
+            .filter_map(|row| row.into_iter().next()?)
+        {
+            if !names.contains(&column) {
+                names.push(column);
+            }
+        }
+    }
+    names
+}
+
+fn history_path() -> Option<PathBuf> {
+    Some(crate::config::data_dir().ok()?.join("repl_history"))
+}
+
+/// Output settings changed with `.format` and `.limit`
+struct Settings {
+    format: QueryFormat,
+    limit: usize,
+}
+
+/// Run the prompt until `.quit` or end of input
+pub fn run(limit: usize) -> Result<()> {
+    let storage = StorageHandle::attach(&storage::db_path()?)?;
+    let mut editor: Editor<ReplHelper, DefaultHistory> = Editor::new()?;
+    editor.set_helper(Some(ReplHelper {
+        names: completion_names(&storage),
+    }));
+    let history = history_path();
+    if let Some(path) = &history {
+        let _ = editor.load_history(path);
+    }
+
+    println!(
+        "agenttop {} SQL prompt on {} (read-only). Type .help for help.",
+        env!("CARGO_PKG_VERSION"),
+        storage::db_path()?.display()
+    );
+    let mut settings = Settings {
+        format: QueryFormat::Table,
+        limit,
+    };
+    let mut statement = String::new();
+    loop {
+        let prompt = if statement.is_empty() {
+            "sql> "
+        } else {
+            "  -> "
+        };
+        let line = match editor.readline(prompt) {
+            Ok(line) => line,
+            // Ctrl-C drops the statement being typed
+            Err(ReadlineError::Interrupted) => {
+                statement.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(trimmed);
+
+        if statement.is_empty() && trimmed.starts_with('.') {
+            match run_command(&storage, &mut settings, trimmed) {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(e) => eprintln!("Error: {:#}", e),
+            }
+            continue;
+        }
+
+        statement.push_str(&line);
+        statement.push('\n');
+        if trimmed.ends_with(';') {
+            let sql = std::mem::take(&mut statement);
+            if let Err(e) = run_statement(&storage, &settings, &sql) {
+                eprintln!("Error: {:#}", e);
+            }
+        }
+    }
+
+    if let Some(path) = &history {
+        let _ = editor.save_history(path);
+    }
+    Ok(())
+}
+
+fn run_statement(storage: &StorageHandle, settings: &Settings, sql: &str) -> Result<()> {
+    let result = storage.run_query(sql, settings.limit)?;
+    let mut stdout = std::io::stdout().lock();
+    export::write_query_result(&mut stdout, settings.format, &result)?;
+    if result.truncated && settings.format != QueryFormat::Table {
+        eprintln!("Output truncated to {} rows (.limit N)", settings.limit);
+    }
+    Ok(())
+}
+
+/// Run a `.command`; false to leave the prompt
+fn run_command(storage: &StorageHandle, settings: &mut Settings, line: &str) -> Result<bool> {
+    let mut words = line.split_whitespace();
+    let command = words.next().unwrap_or_default();
+    let arg = words.next();
+    match (command, arg) {
+        (".quit" | ".exit", _) => return Ok(false),
+        (".help", _) => println!("{}", HELP),
+        (".tables", _) => {
+            for table in storage::TABLES {
+                println!("{}", table);
+            }
+        }
+        (".schema", Some(table)) => {
+            if !storage::TABLES.contains(&table) {
+                anyhow::bail!(
+                    "Unknown table `{}` (one of {})",
+                    table,
+                    storage::TABLES.join(", ")
+                );
+            }
+            let sql = format!(
+                "SELECT column_name, data_type, is_nullable FROM information_schema.columns \
+                 WHERE table_schema = 'main' AND table_name = '{}' ORDER BY ordinal_position",
+                table
+            );
+            let result = storage.run_query(&sql, 1000)?;
+            export::write_query_result(&mut std::io::stdout().lock(), QueryFormat::Table, &result)?;
+        }
+        (".format", Some(format)) => {
+            settings.format = <QueryFormat as clap::ValueEnum>::from_str(format, true)
+                .map_err(|_| anyhow::anyhow!("Unknown format `{}` (table, csv or json)", format))?;
+        }
+        (".limit", Some(limit)) => {
+            settings.limit = limit
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Not a row count: `{}`", limit))?;
+        }
+        (".schema" | ".format" | ".limit", None) => {
+            anyhow::bail!("{} needs an argument (see .help)", command)
+        }
+        _ => anyhow::bail!("Unknown command `{}` (see .help)", command),
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_complete() {
+        let names: Vec<String> = ["tool_events", "token_usage", "tool_name", "timestamp"]
+            .iter()
+            .map(|n| n.to_string())
+            .collect();
+
+        let line = "SELECT * FROM to";
+        assert_eq!(
+            complete(&names, line, line.len()),
+            (
+                14,
+                vec![
+                    "tool_events".to_string(),
+                    "token_usage".to_string(),
+                    "tool_name".to_string()
+                ]
+            )
+        );
+
+        let line = "SELECT e.TIM";
+        assert_eq!(
+            complete(&names, line, line.len()),
+            (9, vec!["timestamp".to_string()])
+        );
+
+        let line = ".sch";
+        assert_eq!(
+            complete(&names, line, line.len()),
+            (0, vec![".schema".to_string()])
+        );
+
+        let line = "SELECT ";
+        assert_eq!(complete(&names, line, line.len()), (7, Vec::new()));
+    }
+}
@@ -0,0 +1,116 @@
+//! `agenttop budget`: spend against each configured budget in its current period, and
+//! the past periods the budget monitor recorded.
+
+use anyhow::Result;
+use chrono::{DateTime, Local, Utc};
+use serde::Serialize;
+use std::fmt::Write;
+
+use crate::budget::{self, Budget, BudgetLevel};
+use crate::storage::{BudgetUsage, StorageHandle};
+use crate::tui::app::format_usd;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BudgetReport {
+    /// Configured budgets, in config order
+    pub current: Vec<BudgetRow>,
+    /// Recorded periods, latest first
+    pub history: Vec<BudgetUsage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BudgetRow {
+    /// e.g. "monthly/provider:claude_code"
+    pub budget: String,
+    pub period_start: Option<DateTime<Utc>>,
+    pub limit_usd: f64,
+    pub spent_usd: f64,
+    pub percent: f64,
+    pub level: BudgetLevel,
+}
+
+/// Current spend against `budgets`, and up to `history` recorded periods
+pub fn collect(
+    storage: &StorageHandle,
+    budgets: &[Budget],
+    history: usize,
+    now: DateTime<Local>,
+) -> Result<BudgetReport> {
+    let current = budgets
+        .iter()
+        .zip(budget::check(storage, budgets, now)?)
+        .map(|(budget, status)| BudgetRow {
+            budget: budget.key(),
+            period_start: budget.period.start(now),
+            limit_usd: status.limit_usd,
+            spent_usd: status.spent_usd,
+            percent: status.percent(),
+            level: status.level,
+        })
+        .collect();
+    Ok(BudgetReport {
+        current,
+        history: storage.get_budget_usage(None, false, history)?,
+    })
+}
+
+impl BudgetReport {
+    pub fn render_text(&self) -> String {
+        let width = self
+            .current
+            .iter()
+            .map(|row| row.budget.len())
+            .chain(self.history.iter().map(|usage| usage.budget.len()))
+            .chain(["BUDGET".len()])
+            .max()
+            .unwrap_or(0);
+        let mut out = String::new();
+        if self.current.is_empty() {
+            let _ = writeln!(out, "No budgets configured");
+        } else {
+            let _ = writeln!(
+                out,
+                "{:<width$}  {:>10}  {:>10}  {:>5}  LEVEL",
+                "BUDGET", "SPENT", "LIMIT", "USED"
+            );
+            for row in &self.current {
+                let _ = writeln!(
+                    out,
+                    "{:<width$}  {:>10}  {:>10}  {:>4.0}%  {:?}",
+                    row.budget,
+                    format_usd(row.spent_usd),
+                    format_usd(row.limit_usd),
+                    row.percent,
+                    row.level
+                );
+            }
+        }
+
+        if !self.history.is_empty() {
+            let _ = writeln!(
+                out,
+                "\n{:<width$}  {:<16}  {:>10}  {:>10}  STATUS",
+                "BUDGET", "PERIOD", "SPENT", "LIMIT"
+            );
+            for usage in &self.history {
+                let _ = writeln!(
+                    out,
+                    "{:<width$}  {:<16}  {:>10}  {:>10}  {}",
+                    usage.budget,
+                    usage
+                        .period_start
+                        .with_timezone(&Local)
+                        .format("%Y-%m-%d %H:%M"),
+                    format_usd(usage.spent_usd),
+                    format_usd(usage.limit_usd),
+                    match (usage.closed, usage.spent_usd > usage.limit_usd) {
+                        (false, _) => "current",
+                        (true, true) => "over",
+                        (true, false) => "within",
+                    }
+                );
+            }
+        }
+        out
+    }
+}
@@ -0,0 +1,140 @@
+//! `agenttop --once`: query the database once, print a summary and exit non-zero when
+//! a health policy is violated, so CI jobs and cron checks can gate on agent health.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::fmt::Write;
+
+use super::percent;
+use crate::storage::StorageHandle;
+use crate::tui::app::{TimeFilter, format_usd};
+
+/// Limits checked by `--once`; unset limits are not checked
+#[derive(Debug, Clone, Default)]
+pub struct Policy {
+    /// Tool calls that failed, as a percent of all tool calls
+    pub max_error_rate_pct: Option<f64>,
+    pub max_api_errors: Option<u64>,
+    pub max_cost_usd: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Violation {
+    /// Which limit: "error_rate", "api_errors" or "cost"
+    pub check: &'static str,
+    pub limit: f64,
+    pub actual: f64,
+    pub message: String,
+}
+
+/// Totals for the checked range plus any violations
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckResult {
+    pub range: String,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub tool_calls: u64,
+    pub tool_errors: u64,
+    pub error_rate_pct: f64,
+    pub api_calls: u64,
+    pub api_errors: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cost_usd: f64,
+    pub passed: bool,
+    pub violations: Vec<Violation>,
+}
+
+/// Query the totals for `filter` and compare them to `policy`
+pub fn run(storage: &StorageHandle, filter: &TimeFilter, policy: &Policy) -> Result<CheckResult> {
+    let (since, until) = (filter.since(), filter.until());
+    let tools = storage.get_tool_metrics(since, until)?;
+    let tokens = storage.get_token_metrics(since, until)?;
+    let api = storage.get_api_metrics(since, until)?;
+
+    let tool_calls: u64 = tools.iter().map(|t| t.call_count).sum();
+    let tool_errors: u64 = tools.iter().map(|t| t.error_count).sum();
+    let error_rate_pct = percent(tool_errors, tool_calls);
+
+    let mut violations = Vec::new();
+    if let Some(limit) = policy.max_error_rate_pct
+        && error_rate_pct > limit
+    {
+        violations.push(Violation {
+            check: "error_rate",
+            limit,
+            actual: error_rate_pct,
+            message: format!(
+                "Tool error rate {:.1}% is above {:.1}% ({} of {} calls failed)",
+                error_rate_pct, limit, tool_errors, tool_calls
+            ),
+        });
+    }
+    if let Some(limit) = policy.max_api_errors
+        && api.total_errors > limit
+    {
+        violations.push(Violation {
+            check: "api_errors",
+            limit: limit as f64,
+            actual: api.total_errors as f64,
+            message: format!("{} API errors, above {}", api.total_errors, limit),
+        });
+    }
+    if let Some(limit) = policy.max_cost_usd
+        && tokens.total_cost_usd > limit
+    {
+        violations.push(Violation {
+            check: "cost",
+            limit,
+            actual: tokens.total_cost_usd,
+            message: format!(
+                "Cost {} is above {}",
+                format_usd(tokens.total_cost_usd),
+                format_usd(limit)
+            ),
+        });
+    }
+
+    Ok(CheckResult {
+        range: filter.label(),
+        since,
+        until,
+        tool_calls,
+        tool_errors,
+        error_rate_pct,
+        api_calls: api.total_calls,
+        api_errors: api.total_errors,
+        input_tokens: tokens.input_tokens,
+        output_tokens: tokens.output_tokens,
+        cost_usd: tokens.total_cost_usd,
+        passed: violations.is_empty(),
+        violations,
+    })
+}
+
+impl CheckResult {
+    /// Short plain-text summary ending in PASS or one FAIL line per violation
+    pub fn render_text(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "agenttop check · {}", self.range);
+        let _ = writeln!(
+            out,
+            "  Tool calls  {} ({} errors, {:.1}%)",
+            self.tool_calls, self.tool_errors, self.error_rate_pct
+        );
+        let _ = writeln!(
+            out,
+            "  API calls   {} ({} errors)",
+            self.api_calls, self.api_errors
+        );
+        let _ = writeln!(out, "  Cost        {}", format_usd(self.cost_usd));
+        if self.passed {
+            let _ = writeln!(out, "PASS");
+        }
+        for violation in &self.violations {
+            let _ = writeln!(out, "FAIL {}", violation.message);
+        }
+        out
+    }
+}
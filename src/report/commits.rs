@@ -0,0 +1,146 @@
+//! `agenttop commits`: the commits in each git repository the agents worked in, which
+//! were made during a session, and their lines changed and cost (see [`crate::git`]).
+
+use anyhow::{Result, bail};
+use chrono::{DateTime, Local, Utc};
+use serde::Serialize;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use super::format_count;
+use crate::git::{self, CorrelatedCommit};
+use crate::storage::StorageHandle;
+use crate::text::truncate;
+use crate::tui::app::format_usd;
+
+/// Longest commit summary shown before it is cut off
+const SUMMARY_WIDTH: usize = 50;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CommitReport {
+    pub repo: PathBuf,
+    /// Oldest first
+    pub commits: Vec<CorrelatedCommit>,
+    /// Commits made during a session
+    pub assisted_commits: u64,
+    /// Lines added and removed by those commits
+    pub assisted_lines: u64,
+    /// Spend attributed to those commits
+    pub assisted_cost_usd: f64,
+}
+
+impl CommitReport {
+    fn new(repo: PathBuf, commits: Vec<CorrelatedCommit>) -> Self {
+        let assisted = || commits.iter().filter(|c| c.assisted());
+        Self {
+            assisted_commits: assisted().count() as u64,
+            assisted_lines: assisted().map(|c| c.commit.lines()).sum(),
+            assisted_cost_usd: assisted().filter_map(|c| c.cost_usd).sum(),
+            repo,
+            commits,
+        }
+    }
+
+    /// Spend per AI-assisted commit
+    pub fn cost_per_commit(&self) -> Option<f64> {
+        (self.assisted_commits > 0).then(|| self.assisted_cost_usd / self.assisted_commits as f64)
+    }
+
+    /// Spend per line changed by AI-assisted commits
+    pub fn cost_per_line(&self) -> Option<f64> {
+        (self.assisted_lines > 0).then(|| self.assisted_cost_usd / self.assisted_lines as f64)
+    }
+
+    /// Table of commits, oldest first, with a totals line
+    pub fn render_text(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "{}", self.repo.display());
+        if self.commits.is_empty() {
+            let _ = writeln!(out, "No commits in the range");
+            return out;
+        }
+        let _ = writeln!(
+            out,
+            "{:<8}  {:<16}  {:<10}  {:>7}  {:>7}  {:>8}  SUMMARY",
+            "COMMIT", "DATE", "SESSION", "ADDED", "REMOVED", "COST"
+        );
+        for c in &self.commits {
+            let _ = writeln!(
+                out,
+                "{:<8}  {:<16}  {:<10}  {:>7}  {:>7}  {:>8}  {}",
+                truncate(&c.commit.hash, 8),
+                c.commit.time.with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+                c.session_id
+                    .as_deref()
+                    .map_or("-".to_string(), |id| truncate(id, 10)),
+                format!("+{}", c.commit.insertions),
+                format!("-{}", c.commit.deletions),
+                c.cost_usd.map_or("-".to_string(), format_usd),
+                truncate(&c.commit.summary, SUMMARY_WIDTH)
+            );
+        }
+        let total = self.commits.len() as u64;
+        let _ = writeln!(
+            out,
+            "\n{} of {} commits AI-assisted ({:.0}%), {} lines, {}",
+            self.assisted_commits,
+            total,
+            self.assisted_commits as f64 / total as f64 * 100.0,
+            format_count(self.assisted_lines),
+            format_usd(self.assisted_cost_usd)
+        );
+        if let (Some(per_commit), Some(per_line)) = (self.cost_per_commit(), self.cost_per_line()) {
+            let _ = writeln!(
+                out,
+                "{}/commit, {}/100 lines",
+                format_usd(per_commit),
+                format_usd(per_line * 100.0)
+            );
+        }
+        out
+    }
+}
+
+/// Commits in the range, per repository: `repo` when given, else every repository the
+/// sessions' working directories are in, else the one holding the current directory.
+/// Without `since`, commits from before the first recorded API call are left out.
+pub fn collect(
+    storage: &StorageHandle,
+    repo: Option<&Path>,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+) -> Result<Vec<CommitReport>> {
+    let spend = storage.get_api_spend(since, until)?;
+    let repos = match repo {
+        Some(dir) => match git::repo_root(dir) {
+            Some(root) => vec![root],
+            None => bail!("{} is not in a git repository", dir.display()),
+        },
+        None => {
+            let repos = git::session_repos(&spend);
+            if repos.is_empty() {
+                let cwd = std::env::current_dir()?;
+                match git::repo_root(&cwd) {
+                    Some(root) => vec![root],
+                    None => bail!(
+                        "No session reported a working directory in a git repository and {} \
+                         is not in one; pass --repo",
+                        cwd.display()
+                    ),
+                }
+            } else {
+                repos
+            }
+        }
+    };
+
+    let since = since.or_else(|| spend.first().map(|s| s.timestamp));
+    repos
+        .into_iter()
+        .map(|repo| {
+            let commits = git::log(&repo, since, until)?;
+            let correlated = git::correlate(&repo, commits, &spend);
+            Ok(CommitReport::new(repo, correlated))
+        })
+        .collect()
+}
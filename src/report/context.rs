@@ -0,0 +1,100 @@
+//! `agenttop context`: how much of the model's context window the prompts filled, per
+//! day and for the sessions that ran near the ceiling, as a table or JSON (see
+//! [`crate::context`]).
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::fmt::Write;
+
+use crate::context::{self, ContextUsage, Utilization};
+use crate::storage::StorageHandle;
+
+/// Most api_requests read for the analysis
+const SAMPLE_LIMIT: usize = 100_000;
+
+/// Context utilization of the api_requests in the range
+pub fn collect(
+    storage: &StorageHandle,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+) -> Result<ContextUsage> {
+    Ok(context::analyze(&storage.get_context_samples(
+        since,
+        until,
+        SAMPLE_LIMIT,
+    )?))
+}
+
+/// "  12    3    41%    93%" columns of a utilization row
+fn columns(utilization: &Utilization) -> String {
+    format!(
+        "{:>8}  {:>5}  {:>5.0}%  {:>5.0}%",
+        utilization.requests, utilization.near_ceiling, utilization.avg_pct, utilization.peak_pct
+    )
+}
+
+/// A line per day, then the sessions consistently near the ceiling
+pub fn render_text(usage: &ContextUsage) -> String {
+    let mut out = String::new();
+    if usage.totals.requests == 0 {
+        let _ = writeln!(
+            out,
+            "No api_requests with a known context window in the range{}",
+            if usage.unknown_window > 0 {
+                " (set the models' sizes in \"context_windows\" in the config file)"
+            } else {
+                ""
+            }
+        );
+        return out;
+    }
+    let header = format!(
+        "{:>8}  {:>5}  {:>6}  {:>6}",
+        "REQUESTS", "NEAR", "AVG", "PEAK"
+    );
+    let _ = writeln!(out, "{:<10}  {}", "DAY", header);
+    for day in &usage.days {
+        let _ = writeln!(out, "{:<10}  {}", day.day, columns(&day.utilization));
+    }
+    let _ = writeln!(out, "{:<10}  {}", "Total", columns(&usage.totals));
+    if usage.unknown_window > 0 {
+        let _ = writeln!(
+            out,
+            "{} requests left out: unknown context window",
+            usage.unknown_window
+        );
+    }
+
+    let _ = writeln!(out);
+    let near: Vec<_> = usage.sessions_near_ceiling().collect();
+    if near.is_empty() {
+        let _ = writeln!(
+            out,
+            "No session had {:.0}% of its requests at {:.0}% of the context window or more",
+            context::CONSISTENT_SHARE * 100.0,
+            context::NEAR_CEILING_PCT
+        );
+        return out;
+    }
+    let _ = writeln!(
+        out,
+        "Sessions near the context limit ({:.0}%+ of the window):",
+        context::NEAR_CEILING_PCT
+    );
+    let _ = writeln!(
+        out,
+        "{:<8}  {:<24}  {}  {:>6}",
+        "SESSION", "MODEL", header, "LAST"
+    );
+    for session in near {
+        let _ = writeln!(
+            out,
+            "{:<8}  {:<24}  {}  {:>5.0}%",
+            session.session_id.chars().take(8).collect::<String>(),
+            session.model.as_deref().unwrap_or("-"),
+            columns(&session.utilization),
+            session.last_pct
+        );
+    }
+    out
+}
@@ -0,0 +1,180 @@
+//! `agenttop cost`: spend grouped by local day, week, project or model, as a table or
+//! JSON. Costs come from the api_request events, which carry the model and project.
+
+use anyhow::Result;
+use chrono::{DateTime, Datelike, Local, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use super::format_count;
+use crate::forecast::{self, Forecast};
+use crate::storage::{CostBreakdown, StorageHandle};
+use crate::tui::app::format_usd;
+
+/// Row label for api_request events without a `project` attribute
+const NO_PROJECT: &str = "(none)";
+
+/// `agenttop cost --group-by`
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, schemars::JsonSchema, clap::ValueEnum,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum CostGroup {
+    /// Local calendar day
+    #[default]
+    Day,
+    /// Monday to Sunday, labelled with the Monday
+    Week,
+    /// The `project` resource attribute agents send
+    Project,
+    Model,
+}
+
+impl CostGroup {
+    fn header(self) -> &'static str {
+        match self {
+            CostGroup::Day => "DAY",
+            CostGroup::Week => "WEEK OF",
+            CostGroup::Project => "PROJECT",
+            CostGroup::Model => "MODEL",
+        }
+    }
+
+    fn key(self, entry: &CostBreakdown) -> String {
+        let day = entry.bucket.with_timezone(&Local).date_naive();
+        match self {
+            CostGroup::Day => day.to_string(),
+            CostGroup::Week => {
+                (day - chrono::Days::new(day.weekday().num_days_from_monday() as u64)).to_string()
+            }
+            CostGroup::Project => entry
+                .project
+                .clone()
+                .unwrap_or_else(|| NO_PROJECT.to_string()),
+            CostGroup::Model => entry.model.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, schemars::JsonSchema)]
+pub struct CostReport {
+    pub group_by: CostGroup,
+    /// Start of the range (null: from the first recorded event)
+    pub since: Option<DateTime<Utc>>,
+    /// End of the range (null: now)
+    pub until: Option<DateTime<Utc>>,
+    /// Days and weeks oldest first; projects and models most expensive first
+    pub rows: Vec<CostRow>,
+    pub total: CostRow,
+    /// Projected spend for the current month; only when the range runs up to now
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub forecast: Option<Forecast>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, schemars::JsonSchema)]
+pub struct CostRow {
+    /// The day or week ("2025-01-13"), project or model
+    pub key: String,
+    pub calls: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cost_usd: f64,
+}
+
+impl CostRow {
+    fn add(&mut self, entry: &CostBreakdown) {
+        self.calls += entry.calls;
+        self.input_tokens += entry.input_tokens;
+        self.output_tokens += entry.output_tokens;
+        self.cost_usd += entry.cost_usd;
+    }
+}
+
+/// Spend between `since` and `until`, grouped by `group`
+pub fn collect(
+    storage: &StorageHandle,
+    group: CostGroup,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+) -> Result<CostReport> {
+    let mut groups: BTreeMap<String, CostRow> = BTreeMap::new();
+    let mut total = CostRow {
+        key: "TOTAL".to_string(),
+        ..CostRow::default()
+    };
+    for entry in storage.get_cost_breakdown(since, until)? {
+        let key = group.key(&entry);
+        groups
+            .entry(key.clone())
+            .or_insert_with(|| CostRow {
+                key,
+                ..CostRow::default()
+            })
+            .add(&entry);
+        total.add(&entry);
+    }
+
+    let mut rows: Vec<CostRow> = groups.into_values().collect();
+    if matches!(group, CostGroup::Project | CostGroup::Model) {
+        rows.sort_by(|a, b| {
+            b.cost_usd
+                .total_cmp(&a.cost_usd)
+                .then_with(|| a.key.cmp(&b.key))
+        });
+    }
+    let forecast = match until {
+        None => Some(forecast::month_end(storage, Local::now())?),
+        Some(_) => None,
+    };
+    Ok(CostReport {
+        group_by: group,
+        since,
+        until,
+        rows,
+        total,
+        forecast,
+    })
+}
+
+impl CostReport {
+    /// Aligned table with a total line
+    pub fn render_text(&self) -> String {
+        let key_width = self
+            .rows
+            .iter()
+            .map(|row| row.key.chars().count())
+            .chain([self.group_by.header().len(), self.total.key.len()])
+            .max()
+            .unwrap_or(0);
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "{:<key_width$}  {:>7}  {:>10}  {:>10}  {:>10}",
+            self.group_by.header(),
+            "CALLS",
+            "TOKENS IN",
+            "TOKENS OUT",
+            "COST"
+        );
+        let mut line = |row: &CostRow| {
+            let _ = writeln!(
+                out,
+                "{:<key_width$}  {:>7}  {:>10}  {:>10}  {:>10}",
+                row.key,
+                row.calls,
+                format_count(row.input_tokens),
+                format_count(row.output_tokens),
+                format_usd(row.cost_usd)
+            );
+        };
+        for row in &self.rows {
+            line(row);
+        }
+        line(&self.total);
+        if let Some(forecast) = self.forecast.filter(|f| f.projected_usd > 0.0) {
+            let _ = writeln!(out, "\nThis month: {}", forecast.describe());
+        }
+        out
+    }
+}
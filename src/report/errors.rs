@@ -0,0 +1,97 @@
+//! `agenttop errors`: tool and API errors by category (see [`crate::taxonomy`]), then by
+//! category and tool or model with the latest message, as a table or JSON. The errors
+//! view in the TUI shows the same breakdown.
+
+use anyhow::Result;
+use chrono::{DateTime, Local, Utc};
+use serde::Serialize;
+use std::fmt::Write;
+
+use crate::storage::{ErrorBreakdown, ErrorCategoryCount, StorageHandle};
+use crate::text::truncate;
+
+/// Most category and tool or model rows listed
+const BREAKDOWN_LIMIT: usize = 100;
+
+/// Width of the latest message column
+const MESSAGE_WIDTH: usize = 60;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorReport {
+    pub errors: u64,
+    /// Most frequent first
+    pub categories: Vec<ErrorCategoryCount>,
+    /// Most frequent first
+    pub breakdown: Vec<ErrorBreakdown>,
+}
+
+/// Errors in the range by category and by tool or model
+pub fn collect(
+    storage: &StorageHandle,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+) -> Result<ErrorReport> {
+    let categories = storage.get_error_categories(since, until)?;
+    Ok(ErrorReport {
+        errors: categories.iter().map(|c| c.errors).sum(),
+        breakdown: storage.get_error_breakdown(since, until, BREAKDOWN_LIMIT)?,
+        categories,
+    })
+}
+
+impl ErrorReport {
+    /// A line per category with its share, then a line per category and tool or model
+    pub fn render_text(&self) -> String {
+        let mut out = String::new();
+        if self.errors == 0 {
+            let _ = writeln!(out, "No tool or API errors in the range");
+            return out;
+        }
+        let _ = writeln!(
+            out,
+            "{:<18}  {:>6}  {:>5}  {:>5}  {:>5}  {:>8}  LAST",
+            "CATEGORY", "ERRORS", "SHARE", "TOOL", "API", "SESSIONS"
+        );
+        for category in &self.categories {
+            let _ = writeln!(
+                out,
+                "{:<18}  {:>6}  {:>4.0}%  {:>5}  {:>5}  {:>8}  {}",
+                truncate(&category.category, 18),
+                category.errors,
+                category.errors as f64 / self.errors as f64 * 100.0,
+                category.tool_errors,
+                category.api_errors,
+                category.sessions,
+                category
+                    .last_at
+                    .with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M")
+            );
+        }
+        let _ = writeln!(out);
+        let _ = writeln!(
+            out,
+            "{:<18}  {:<4}  {:<24}  {:>6}  LATEST MESSAGE",
+            "CATEGORY", "KIND", "TOOL/MODEL", "ERRORS"
+        );
+        for entry in &self.breakdown {
+            let line = format!(
+                "{:<18}  {:<4}  {:<24}  {:>6}  {}",
+                truncate(&entry.category, 18),
+                entry.kind,
+                truncate(&entry.source, 24),
+                entry.errors,
+                truncate(
+                    &entry
+                        .last_message
+                        .as_deref()
+                        .unwrap_or("-")
+                        .replace('\n', " "),
+                    MESSAGE_WIDTH
+                )
+            );
+            let _ = writeln!(out, "{}", line.trim_end());
+        }
+        out
+    }
+}
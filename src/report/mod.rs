@@ -0,0 +1,254 @@
+//! Plain-text summary report printed by `agenttop report`.
+//!
+//! The layout is fixed-width text so it pastes cleanly into chat or a standup doc
+//! (inside a code block).
+
+use anyhow::Result;
+use chrono::{DateTime, Local, Utc};
+use std::fmt::Write;
+
+use crate::productivity::Productivity;
+use crate::storage::StorageHandle;
+use crate::text::truncate;
+use crate::tui::app::{TimeFilter, format_usd};
+
+pub mod budget;
+pub mod check;
+pub mod commits;
+pub mod context;
+pub mod cost;
+pub mod errors;
+pub mod productivity;
+pub mod prompt_cost;
+pub mod providers;
+pub mod quota;
+pub mod session;
+pub mod stats;
+
+/// Tools listed in the per-tool table; the rest are summarized in one line
+const REPORT_TOOL_LIMIT: usize = 15;
+
+/// Distinct (tool, error) pairs listed under "Top errors"
+const REPORT_ERROR_LIMIT: usize = 5;
+
+/// Longest error message shown before it is cut off
+const REPORT_ERROR_WIDTH: usize = 60;
+
+/// Build the report for `filter`, read from `storage`
+pub fn render(storage: &StorageHandle, filter: &TimeFilter) -> Result<String> {
+    let (since, until) = (filter.since(), filter.until());
+    let tools = {
+        let mut tools = storage.get_tool_metrics(since, until)?;
+        tools.sort_by(|a, b| {
+            b.call_count
+                .cmp(&a.call_count)
+                .then_with(|| a.tool_name.cmp(&b.tool_name))
+        });
+        tools
+    };
+    let tokens = storage.get_token_metrics(since, until)?;
+    let session = storage.get_session_metrics(since, until)?;
+    let api = storage.get_api_metrics(since, until)?;
+    let models = storage.get_model_costs(since, until)?;
+    let errors = storage.get_top_tool_errors(since, until, REPORT_ERROR_LIMIT)?;
+
+    let mut out = String::new();
+    writeln!(out, "agenttop report · {}", filter.label())?;
+    writeln!(
+        out,
+        "{} → {}",
+        since.map_or("beginning".to_string(), format_time),
+        format_time(until.unwrap_or_else(Utc::now))
+    )?;
+
+    // Totals
+    let tool_calls: u64 = tools.iter().map(|t| t.call_count).sum();
+    let tool_errors: u64 = tools.iter().map(|t| t.error_count).sum();
+    writeln!(out, "\nTotals")?;
+    writeln!(
+        out,
+        "  Tool calls  {} ({} errors, {:.1}%)",
+        tool_calls,
+        tool_errors,
+        percent(tool_errors, tool_calls)
+    )?;
+    writeln!(
+        out,
+        "  API calls   {} ({} errors, avg {})",
+        api.total_calls,
+        api.total_errors,
+        format_duration(api.avg_latency_ms)
+    )?;
+    writeln!(
+        out,
+        "  Tokens      in {} · out {} · cache read {} ({:.0}% reuse)",
+        format_count(tokens.input_tokens),
+        format_count(tokens.output_tokens),
+        format_count(tokens.cache_read_tokens),
+        percent(
+            tokens.cache_read_tokens,
+            tokens.input_tokens + tokens.cache_read_tokens
+        )
+    )?;
+    writeln!(out, "  Cost        {}", format_usd(tokens.total_cost_usd))?;
+    if until.is_none() {
+        let forecast = crate::forecast::month_end(storage, Local::now())?;
+        if forecast.projected_usd > 0.0 {
+            writeln!(out, "  Forecast    {}", forecast.describe())?;
+        }
+    }
+    if session.lines_of_code != 0 || session.commit_count > 0 {
+        writeln!(
+            out,
+            "  Code        {:+} lines · {} commits",
+            session.lines_of_code, session.commit_count
+        )?;
+        let productivity = Productivity {
+            lines_of_code: session.lines_of_code,
+            commits: session.commit_count,
+            hours: session.active_time_secs as f64 / 3600.0,
+            cost_usd: tokens.total_cost_usd,
+            ..Productivity::default()
+        }
+        .describe();
+        if !productivity.is_empty() {
+            writeln!(out, "  Output      {}", productivity)?;
+        }
+    }
+
+    // Per-tool table
+    if !tools.is_empty() {
+        let name_width = tools
+            .iter()
+            .take(REPORT_TOOL_LIMIT)
+            .map(|t| t.display_name().chars().count())
+            .max()
+            .unwrap_or(0)
+            .max("TOOL".len());
+        writeln!(out, "\nTools")?;
+        writeln!(
+            out,
+            "  {:<name_width$}  {:>6}  {:>5}  {:>6}  {:>7}",
+            "TOOL", "CALLS", "ERR", "ERR%", "AVG"
+        )?;
+        for tool in tools.iter().take(REPORT_TOOL_LIMIT) {
+            writeln!(
+                out,
+                "  {:<name_width$}  {:>6}  {:>5}  {:>5.1}%  {:>7}",
+                tool.display_name(),
+                tool.call_count,
+                tool.error_count,
+                percent(tool.error_count, tool.call_count),
+                format_duration(tool.avg_duration_ms)
+            )?;
+        }
+        if tools.len() > REPORT_TOOL_LIMIT {
+            let rest = &tools[REPORT_TOOL_LIMIT..];
+            writeln!(
+                out,
+                "  … and {} more tools ({} calls)",
+                rest.len(),
+                rest.iter().map(|t| t.call_count).sum::<u64>()
+            )?;
+        }
+    }
+
+    // Per-model cost
+    if !models.is_empty() {
+        let name_width = models
+            .iter()
+            .map(|m| m.model.chars().count())
+            .max()
+            .unwrap_or(0)
+            .max("MODEL".len());
+        writeln!(out, "\nCost by model")?;
+        writeln!(
+            out,
+            "  {:<name_width$}  {:>6}  {:>9}",
+            "MODEL", "CALLS", "COST"
+        )?;
+        for model in &models {
+            writeln!(
+                out,
+                "  {:<name_width$}  {:>6}  {:>9}",
+                model.model,
+                model.calls,
+                format_usd(model.cost_usd)
+            )?;
+        }
+    }
+
+    // Most frequent errors
+    if !errors.is_empty() {
+        writeln!(out, "\nTop errors")?;
+        for error in &errors {
+            let message = error.error.as_deref().unwrap_or("(no message)");
+            writeln!(
+                out,
+                "  {:>4}× {}: {}",
+                error.count,
+                error.tool_name,
+                truncate(&message.replace('\n', " "), REPORT_ERROR_WIDTH)
+            )?;
+        }
+    }
+
+    Ok(out)
+}
+
+/// One-line activity summary for the window starting at `since`, logged periodically
+/// in headless mode. `events` is the number of events the receiver accepted in the window.
+pub fn summary_line(storage: &StorageHandle, since: DateTime<Utc>, events: u64) -> Result<String> {
+    let tokens = storage.get_token_metrics(Some(since), None)?;
+    let tools = storage.get_tool_metrics(Some(since), None)?;
+    let top_tool = tools
+        .iter()
+        .max_by(|a, b| {
+            a.call_count
+                .cmp(&b.call_count)
+                .then_with(|| b.tool_name.cmp(&a.tool_name))
+        })
+        .map_or("none".to_string(), |t| {
+            format!("{} ({} calls)", t.display_name(), t.call_count)
+        });
+    Ok(format!(
+        "{} events · tokens in {} / out {} · cost {} · top tool {}",
+        events,
+        format_count(tokens.input_tokens),
+        format_count(tokens.output_tokens),
+        format_usd(tokens.total_cost_usd),
+        top_tool
+    ))
+}
+
+fn format_time(time: DateTime<Utc>) -> String {
+    time.with_timezone(&Local)
+        .format("%Y-%m-%d %H:%M")
+        .to_string()
+}
+
+pub(crate) fn percent(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        part as f64 / total as f64 * 100.0
+    }
+}
+
+pub(crate) fn format_duration(ms: f64) -> String {
+    if ms < 1000.0 {
+        format!("{}ms", ms as u64)
+    } else {
+        format!("{:.1}s", ms / 1000.0)
+    }
+}
+
+pub(crate) fn format_count(n: u64) -> String {
+    if n < 1000 {
+        n.to_string()
+    } else if n < 1_000_000 {
+        format!("{:.1}K", n as f64 / 1000.0)
+    } else {
+        format!("{:.1}M", n as f64 / 1_000_000.0)
+    }
+}
@@ -0,0 +1,138 @@
+//! `agenttop productivity`: lines of code per dollar, commits per hour and cost per
+//! commit per local week or per session, as a table or JSON (see [`crate::productivity`]).
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::fmt::Write;
+
+use crate::productivity::{self, Productivity};
+use crate::storage::StorageHandle;
+use crate::text::truncate;
+use crate::tui::app::format_usd;
+
+/// Most sessions read for the report
+const SESSION_LIMIT: usize = 10_000;
+
+/// `agenttop productivity --group-by`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum ProductivityGroup {
+    /// Monday to Sunday, labelled with the Monday
+    #[default]
+    Week,
+    Session,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProductivityReport {
+    pub group_by: ProductivityGroup,
+    /// Weeks oldest first, sessions most recent first
+    pub rows: Vec<ProductivityRow>,
+    pub total: ProductivityRow,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProductivityRow {
+    /// The week ("2025-01-13") or session id
+    pub key: String,
+    #[serde(flatten)]
+    pub productivity: Productivity,
+    pub lines_per_usd: Option<f64>,
+    pub commits_per_hour: Option<f64>,
+    pub cost_per_commit: Option<f64>,
+}
+
+impl ProductivityRow {
+    fn new(key: String, productivity: Productivity) -> Self {
+        Self {
+            key,
+            lines_per_usd: productivity.lines_per_usd(),
+            commits_per_hour: productivity.commits_per_hour(),
+            cost_per_commit: productivity.cost_per_commit(),
+            productivity,
+        }
+    }
+}
+
+/// Productivity of the sessions overlapping the range that reported code metrics,
+/// grouped by `group`
+pub fn collect(
+    storage: &StorageHandle,
+    group: ProductivityGroup,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+) -> Result<ProductivityReport> {
+    let sessions = storage.get_session_productivity(since, until, SESSION_LIMIT)?;
+    let rows = match group {
+        ProductivityGroup::Week => productivity::by_week(&sessions)
+            .into_iter()
+            .map(|(week, p)| ProductivityRow::new(week.to_string(), p))
+            .collect(),
+        ProductivityGroup::Session => sessions
+            .iter()
+            .map(|s| ProductivityRow::new(s.session_id.clone(), productivity::of_session(s)))
+            .collect(),
+    };
+    Ok(ProductivityReport {
+        group_by: group,
+        rows,
+        total: ProductivityRow::new("TOTAL".to_string(), productivity::total(&sessions)),
+    })
+}
+
+impl ProductivityReport {
+    /// Aligned table with a total line
+    pub fn render_text(&self) -> String {
+        let header = match self.group_by {
+            ProductivityGroup::Week => "WEEK OF",
+            ProductivityGroup::Session => "SESSION",
+        };
+        let mut out = String::new();
+        if self.rows.is_empty() {
+            let _ = writeln!(
+                out,
+                "No sessions with code metrics in the range (Claude Code reports lines of \
+                 code, commits and active time)"
+            );
+            return out;
+        }
+        let _ = writeln!(
+            out,
+            "{:<12}  {:>8}  {:>7}  {:>7}  {:>6}  {:>9}  {:>7}  {:>9}  {:>8}",
+            header,
+            "SESSIONS",
+            "LINES",
+            "COMMITS",
+            "HOURS",
+            "COST",
+            "LINES/$",
+            "COMMITS/H",
+            "$/COMMIT"
+        );
+        let ratio = |value: Option<f64>, precision: usize| {
+            value.map_or("-".to_string(), |v| format!("{v:.precision$}"))
+        };
+        let mut line = |row: &ProductivityRow| {
+            let p = &row.productivity;
+            let _ = writeln!(
+                out,
+                "{:<12}  {:>8}  {:>7}  {:>7}  {:>6.1}  {:>9}  {:>7}  {:>9}  {:>8}",
+                truncate(&row.key, 12),
+                p.sessions,
+                format!("{:+}", p.lines_of_code),
+                p.commits,
+                p.hours,
+                format_usd(p.cost_usd),
+                ratio(row.lines_per_usd, 0),
+                ratio(row.commits_per_hour, 1),
+                row.cost_per_commit.map_or("-".to_string(), format_usd)
+            );
+        };
+        for row in &self.rows {
+            line(row);
+        }
+        line(&self.total);
+        out
+    }
+}
@@ -0,0 +1,73 @@
+//! `agenttop prompt-cost`: turn cost by prompt size, with the correlation between the
+//! two, as a table or JSON (see [`crate::prompt_cost`]).
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::fmt::Write;
+
+use crate::prompt_cost::{self, PromptCost};
+use crate::storage::StorageHandle;
+use crate::tui::app::format_usd;
+
+/// Most turns read for the analysis
+const TURN_LIMIT: usize = 100_000;
+
+/// Prompt size against cost for the turns started in the range
+pub fn collect(
+    storage: &StorageHandle,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+) -> Result<PromptCost> {
+    Ok(prompt_cost::analyze(
+        &storage.get_turns(since, until, TURN_LIMIT)?,
+    ))
+}
+
+/// A line per prompt size bucket, then the correlation and cost per 1K characters
+pub fn render_text(analysis: &PromptCost) -> String {
+    let mut out = String::new();
+    if analysis.turns == 0 {
+        let _ = writeln!(
+            out,
+            "No turns with a prompt length in the range (the agent's user_prompt events \
+             carry it as prompt_length)"
+        );
+        return out;
+    }
+    let usd = |value: Option<f64>| value.map_or("-".to_string(), format_usd);
+    let _ = writeln!(
+        out,
+        "{:<8}  {:>6}  {:>9}  {:>9}  {:>9}  {:>10}",
+        "PROMPT", "TURNS", "COST", "AVG", "MEDIAN", "$/1K CHARS"
+    );
+    for bucket in &analysis.buckets {
+        let _ = writeln!(
+            out,
+            "{:<8}  {:>6}  {:>9}  {:>9}  {:>9}  {:>10}",
+            bucket.label,
+            bucket.turns,
+            format_usd(bucket.cost_usd),
+            usd(bucket.avg_cost_usd()),
+            if bucket.turns > 0 {
+                format_usd(bucket.median_cost_usd)
+            } else {
+                "-".to_string()
+            },
+            usd(bucket.cost_per_1k_chars())
+        );
+    }
+    let _ = writeln!(out);
+    let _ = writeln!(out, "Turns              {}", analysis.turns);
+    let _ = writeln!(
+        out,
+        "Cost per 1K chars  {}",
+        usd(analysis.cost_per_1k_chars)
+    );
+    match analysis.describe() {
+        described if described.is_empty() => {}
+        described => {
+            let _ = writeln!(out, "Correlation        {}", described);
+        }
+    }
+    out
+}
@@ -0,0 +1,217 @@
+//! `agenttop compare`: the detected providers side by side over a range (spend, tokens,
+//! API latency, API success and tool error rates), as a table or JSON. The providers
+//! view in the TUI shows the same measures.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::fmt::Write;
+
+use super::{format_count, format_duration};
+use crate::providers::PROVIDER_REGISTRY;
+use crate::storage::{ProviderComparison, StorageHandle};
+use crate::tui::app::format_usd;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderReport {
+    /// Most spend first
+    pub providers: Vec<ProviderEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProviderEntry {
+    /// Display name (e.g. "Claude Code")
+    pub name: String,
+    #[serde(flatten)]
+    pub totals: ProviderComparison,
+    pub cost_per_session: Option<f64>,
+    pub cost_per_million_tokens: Option<f64>,
+    pub api_success_rate: Option<f64>,
+    pub tool_error_rate: Option<f64>,
+}
+
+impl ProviderEntry {
+    fn new(totals: ProviderComparison) -> Self {
+        Self {
+            name: provider_name(&totals.provider).to_string(),
+            cost_per_session: totals.cost_per_session(),
+            cost_per_million_tokens: totals.cost_per_million_tokens(),
+            api_success_rate: totals.api_success_rate(),
+            tool_error_rate: totals.tool_error_rate(),
+            totals,
+        }
+    }
+}
+
+/// Display name of a provider id, or the id for an unknown one
+pub fn provider_name(id: &str) -> &str {
+    PROVIDER_REGISTRY.get(id).map_or(id, |p| p.name())
+}
+
+/// One compared measure: a cell per provider, and which provider does best on it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Measure {
+    pub label: &'static str,
+    pub cells: Vec<String>,
+    /// Index of the best provider; None when fewer than two have a value or the measure
+    /// has no better direction (e.g. total spend, which follows how much each was used)
+    pub best: Option<usize>,
+}
+
+/// Whether lower or higher values of a measure are better
+#[derive(Clone, Copy)]
+enum Better {
+    Lower,
+    Higher,
+}
+
+fn measure(
+    label: &'static str,
+    values: Vec<Option<f64>>,
+    better: Option<Better>,
+    format: impl Fn(f64) -> String,
+) -> Measure {
+    let present: Vec<(usize, f64)> = values
+        .iter()
+        .enumerate()
+        .filter_map(|(i, v)| v.map(|v| (i, v)))
+        .collect();
+    let best = match better {
+        Some(better) if present.len() >= 2 => present
+            .iter()
+            .min_by(|a, b| match better {
+                Better::Lower => a.1.total_cmp(&b.1),
+                Better::Higher => b.1.total_cmp(&a.1),
+            })
+            .map(|(i, _)| *i),
+        _ => None,
+    };
+    Measure {
+        label,
+        cells: values
+            .into_iter()
+            .map(|v| v.map_or("-".to_string(), &format))
+            .collect(),
+        best,
+    }
+}
+
+/// The measures compared, in display order, with a cell per provider in `providers`
+pub fn measures(providers: &[ProviderComparison]) -> Vec<Measure> {
+    let each = |f: fn(&ProviderComparison) -> Option<f64>| providers.iter().map(f).collect();
+    let count = |n: f64| format_count(n as u64);
+    let rate = |r: f64| format!("{:.1}%", r * 100.0);
+    let ms = format_duration;
+    // Latency is only known where there were API calls
+    let latency = |f: fn(&ProviderComparison) -> f64| {
+        providers
+            .iter()
+            .map(|p| (p.api_calls > 0).then(|| f(p)))
+            .collect()
+    };
+    vec![
+        measure("Sessions", each(|p| Some(p.sessions as f64)), None, count),
+        measure("Cost", each(|p| Some(p.cost_usd)), None, format_usd),
+        measure(
+            "Cost/session",
+            each(ProviderComparison::cost_per_session),
+            Some(Better::Lower),
+            format_usd,
+        ),
+        measure(
+            "Cost/M tokens",
+            each(ProviderComparison::cost_per_million_tokens),
+            Some(Better::Lower),
+            format_usd,
+        ),
+        measure(
+            "Tokens in",
+            each(|p| Some(p.input_tokens as f64)),
+            None,
+            count,
+        ),
+        measure(
+            "Tokens out",
+            each(|p| Some(p.output_tokens as f64)),
+            None,
+            count,
+        ),
+        measure("API calls", each(|p| Some(p.api_calls as f64)), None, count),
+        measure(
+            "Avg latency",
+            latency(|p| p.avg_latency_ms),
+            Some(Better::Lower),
+            ms,
+        ),
+        measure(
+            "P95 latency",
+            latency(|p| p.p95_latency_ms as f64),
+            Some(Better::Lower),
+            ms,
+        ),
+        measure(
+            "API success",
+            each(ProviderComparison::api_success_rate),
+            Some(Better::Higher),
+            rate,
+        ),
+        measure(
+            "Tool calls",
+            each(|p| Some(p.tool_calls as f64)),
+            None,
+            count,
+        ),
+        measure(
+            "Tool errors",
+            each(ProviderComparison::tool_error_rate),
+            Some(Better::Lower),
+            rate,
+        ),
+    ]
+}
+
+/// Totals of each provider with events in the range
+pub fn collect(
+    storage: &StorageHandle,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+) -> Result<ProviderReport> {
+    Ok(ProviderReport {
+        providers: storage
+            .get_provider_comparison(since, until)?
+            .into_iter()
+            .map(ProviderEntry::new)
+            .collect(),
+    })
+}
+
+impl ProviderReport {
+    /// One column per provider, one line per measure; the best value on a measure is
+    /// marked with `*`
+    pub fn render_text(&self) -> String {
+        let mut out = String::new();
+        if self.providers.is_empty() {
+            let _ = writeln!(out, "No provider events in the range");
+            return out;
+        }
+        let totals: Vec<ProviderComparison> =
+            self.providers.iter().map(|p| p.totals.clone()).collect();
+        let _ = write!(out, "{:<14}", "");
+        for provider in &self.providers {
+            let _ = write!(out, "  {:>13}", provider.name);
+        }
+        out.push('\n');
+        for measure in measures(&totals) {
+            let mut line = format!("{:<14}", measure.label);
+            for (i, cell) in measure.cells.iter().enumerate() {
+                let mark = if measure.best == Some(i) { "*" } else { " " };
+                let _ = write!(line, "  {:>12}{}", cell, mark);
+            }
+            let _ = writeln!(out, "{}", line.trim_end());
+        }
+        if self.providers.len() > 1 {
+            let _ = writeln!(out, "\n* best of the providers on the measure");
+        }
+        out
+    }
+}
@@ -0,0 +1,95 @@
+//! `agenttop quota`: the current usage window of each configured quota, with the
+//! capacity left and when it resets, and the recent windows (see [`crate::quota`]).
+
+use anyhow::Result;
+use chrono::{DateTime, Local, Utc};
+use serde::Serialize;
+use std::fmt::Write;
+
+use super::format_count;
+use crate::quota::{self, Quota, QuotaStatus, UsageWindow};
+use crate::storage::StorageHandle;
+use crate::tui::app::format_usd;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QuotaReport {
+    /// Configured quotas, in config order
+    pub quotas: Vec<QuotaEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QuotaEntry {
+    #[serde(flatten)]
+    pub status: QuotaStatus,
+    /// Windows of the last [`quota::LOOKBACK_WINDOWS`], latest first
+    pub windows: Vec<UsageWindow>,
+}
+
+/// Current and recent windows of every quota
+pub fn collect(
+    storage: &StorageHandle,
+    quotas: &[Quota],
+    now: DateTime<Utc>,
+) -> Result<QuotaReport> {
+    let quotas = quotas
+        .iter()
+        .map(|quota| {
+            let mut windows = quota::recent_windows(storage, quota, now)?;
+            let status = QuotaStatus::new(quota, &windows, now);
+            windows.reverse();
+            Ok(QuotaEntry { status, windows })
+        })
+        .collect::<Result<_>>()?;
+    Ok(QuotaReport { quotas })
+}
+
+impl QuotaReport {
+    /// Per quota, its status line, then a line per recent window
+    pub fn render_text(&self) -> String {
+        let mut out = String::new();
+        if self.quotas.is_empty() {
+            let _ = writeln!(
+                out,
+                "No quotas configured (add \"quotas\" to the config file, e.g. \
+                 {{ \"provider\": \"claude_code\", \"window_hours\": 5 }})"
+            );
+            return out;
+        }
+        let time = |at: DateTime<Utc>| at.with_timezone(&Local).format("%Y-%m-%d %H:%M");
+        for (i, entry) in self.quotas.iter().enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            let _ = writeln!(out, "{}", entry.status.describe());
+            if entry.windows.is_empty() {
+                continue;
+            }
+            let _ = writeln!(
+                out,
+                "{:<16}  {:<16}  {:>8}  {:>8}  {:>9}  RATE LIMITED",
+                "OPENED", "RESETS", "REQUESTS", "TOKENS", "COST"
+            );
+            for window in &entry.windows {
+                let limited = match (window.rate_limited_at, window.tokens_at_limit) {
+                    (Some(at), Some(tokens)) => format!(
+                        "{} after {} tokens",
+                        at.with_timezone(&Local).format("%H:%M"),
+                        format_count(tokens)
+                    ),
+                    _ => String::new(),
+                };
+                let line = format!(
+                    "{:<16}  {:<16}  {:>8}  {:>8}  {:>9}  {}",
+                    time(window.started_at),
+                    time(window.resets_at),
+                    window.requests,
+                    format_count(window.tokens),
+                    format_usd(window.cost_usd),
+                    limited
+                );
+                let _ = writeln!(out, "{}", line.trim_end());
+            }
+        }
+        out
+    }
+}
@@ -0,0 +1,264 @@
+//! `agenttop report --session <id>`: what one agent session did (duration, cost, tools,
+//! errors, code), as text or as markdown for a pull request description.
+
+use anyhow::Result;
+use chrono::{DateTime, Local, Utc};
+use std::fmt::Write;
+
+use super::{REPORT_ERROR_LIMIT, REPORT_ERROR_WIDTH, REPORT_TOOL_LIMIT, percent};
+use super::{format_count, format_time};
+use crate::storage::{SessionSummary, StorageHandle, get_tool_display_name};
+use crate::text::truncate;
+use crate::tui::app::format_usd;
+
+/// Output format of `agenttop report --session`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReportFormat {
+    /// Fixed-width text for the terminal
+    #[default]
+    Text,
+    /// Markdown, e.g. for a pull request description
+    Md,
+}
+
+/// Build the report for the session `session_id` (or the one whose id starts with it)
+pub fn render(storage: &StorageHandle, session_id: &str, format: ReportFormat) -> Result<String> {
+    let Some(summary) = storage.get_session_summary(session_id)? else {
+        anyhow::bail!("No session with id `{}`", session_id);
+    };
+    Ok(match format {
+        ReportFormat::Text => render_text(&summary)?,
+        ReportFormat::Md => render_markdown(&summary)?,
+    })
+}
+
+fn render_text(summary: &SessionSummary) -> Result<String> {
+    let tool_calls: u64 = summary.tools.iter().map(|t| t.calls).sum();
+    let tool_errors: u64 = summary.tools.iter().map(|t| t.errors).sum();
+
+    let mut out = String::new();
+    writeln!(out, "agenttop session {}", summary.session_id)?;
+    writeln!(out, "{}", span(summary))?;
+    writeln!(out, "\nTotals")?;
+    writeln!(
+        out,
+        "  Tool calls  {} ({} errors, {:.1}%)",
+        tool_calls,
+        tool_errors,
+        percent(tool_errors, tool_calls)
+    )?;
+    writeln!(
+        out,
+        "  API calls   {} ({} errors)",
+        summary.api_calls, summary.api_errors
+    )?;
+    writeln!(out, "  Tokens      {}", tokens(summary))?;
+    writeln!(out, "  Cost        {}", format_usd(summary.cost_usd))?;
+    writeln!(out, "  Code        {}", code(summary))?;
+
+    if !summary.tools.is_empty() {
+        let name_width = summary
+            .tools
+            .iter()
+            .take(REPORT_TOOL_LIMIT)
+            .map(|t| get_tool_display_name(&t.tool_name).chars().count())
+            .max()
+            .unwrap_or(0)
+            .max("TOOL".len());
+        writeln!(out, "\nTools")?;
+        writeln!(
+            out,
+            "  {:<name_width$}  {:>6}  {:>5}",
+            "TOOL", "CALLS", "ERR"
+        )?;
+        for tool in summary.tools.iter().take(REPORT_TOOL_LIMIT) {
+            writeln!(
+                out,
+                "  {:<name_width$}  {:>6}  {:>5}",
+                get_tool_display_name(&tool.tool_name),
+                tool.calls,
+                tool.errors
+            )?;
+        }
+        if let Some(rest) = more_tools(summary) {
+            writeln!(out, "  {}", rest)?;
+        }
+    }
+
+    if !summary.errors.is_empty() {
+        writeln!(out, "\nTop errors")?;
+        for error in summary.errors.iter().take(REPORT_ERROR_LIMIT) {
+            writeln!(
+                out,
+                "  {:>4}× {}: {}",
+                error.count,
+                get_tool_display_name(&error.tool_name),
+                error_message(error.error.as_deref())
+            )?;
+        }
+    }
+    Ok(out)
+}
+
+fn render_markdown(summary: &SessionSummary) -> Result<String> {
+    let tool_calls: u64 = summary.tools.iter().map(|t| t.calls).sum();
+    let tool_errors: u64 = summary.tools.iter().map(|t| t.errors).sum();
+
+    let mut out = String::new();
+    writeln!(out, "### AI assistance")?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "Agent session `{}` · {}",
+        summary.session_id,
+        span(summary)
+    )?;
+    writeln!(out)?;
+    writeln!(out, "| | |")?;
+    writeln!(out, "|---|---|")?;
+    writeln!(out, "| Cost | {} |", format_usd(summary.cost_usd))?;
+    writeln!(
+        out,
+        "| Tool calls | {} ({} errors) |",
+        tool_calls, tool_errors
+    )?;
+    writeln!(
+        out,
+        "| API calls | {} ({} errors) |",
+        summary.api_calls, summary.api_errors
+    )?;
+    writeln!(out, "| Tokens | {} |", tokens(summary))?;
+    writeln!(out, "| Code | {} |", code(summary))?;
+    if !summary.models.is_empty() {
+        let models: Vec<String> = summary
+            .models
+            .iter()
+            .map(|m| format!("{} ({})", cell(&m.model), format_usd(m.cost_usd)))
+            .collect();
+        writeln!(out, "| Models | {} |", models.join(", "))?;
+    }
+
+    if !summary.tools.is_empty() {
+        writeln!(out)?;
+        writeln!(out, "| Tool | Calls | Errors |")?;
+        writeln!(out, "|---|---:|---:|")?;
+        for tool in summary.tools.iter().take(REPORT_TOOL_LIMIT) {
+            writeln!(
+                out,
+                "| {} | {} | {} |",
+                cell(&get_tool_display_name(&tool.tool_name)),
+                tool.calls,
+                tool.errors
+            )?;
+        }
+        if let Some(rest) = more_tools(summary) {
+            writeln!(out, "\n_{}_", rest)?;
+        }
+    }
+
+    if !summary.errors.is_empty() {
+        writeln!(out)?;
+        writeln!(out, "<details><summary>Errors</summary>")?;
+        writeln!(out)?;
+        for error in summary.errors.iter().take(REPORT_ERROR_LIMIT) {
+            writeln!(
+                out,
+                "- {}× {}: `{}`",
+                error.count,
+                get_tool_display_name(&error.tool_name),
+                error_message(error.error.as_deref()).replace('`', "'")
+            )?;
+        }
+        writeln!(out)?;
+        writeln!(out, "</details>")?;
+    }
+
+    writeln!(out)?;
+    writeln!(
+        out,
+        "<sub>Generated by agenttop {}</sub>",
+        env!("CARGO_PKG_VERSION")
+    )?;
+    Ok(out)
+}
+
+/// "2026-01-10 09:12 → 10:47 (1h 35m)"
+fn span(summary: &SessionSummary) -> String {
+    let end_format = if local_date(summary.started_at) == local_date(summary.ended_at) {
+        "%H:%M"
+    } else {
+        "%Y-%m-%d %H:%M"
+    };
+    let secs = (summary.ended_at - summary.started_at).num_seconds().max(0);
+    let mut duration = minutes(secs);
+    if summary.active_secs > 0 {
+        let _ = write!(duration, ", {} active", minutes(summary.active_secs as i64));
+        if secs > 0 {
+            let _ = write!(
+                duration,
+                " ({:.0}%)",
+                (summary.active_secs as f64 / secs as f64 * 100.0).min(100.0)
+            );
+        }
+    }
+    format!(
+        "{} → {} ({})",
+        format_time(summary.started_at),
+        summary.ended_at.with_timezone(&Local).format(end_format),
+        duration
+    )
+}
+
+/// "1h 5m" or "12m"
+fn minutes(secs: i64) -> String {
+    let mins = secs / 60;
+    if mins >= 60 {
+        format!("{}h {}m", mins / 60, mins % 60)
+    } else {
+        format!("{}m", mins)
+    }
+}
+
+fn local_date(time: DateTime<Utc>) -> chrono::NaiveDate {
+    time.with_timezone(&Local).date_naive()
+}
+
+fn tokens(summary: &SessionSummary) -> String {
+    format!(
+        "in {} · out {} · cache read {}",
+        format_count(summary.input_tokens),
+        format_count(summary.output_tokens),
+        format_count(summary.cache_read_tokens)
+    )
+}
+
+fn code(summary: &SessionSummary) -> String {
+    format!(
+        "{:+} lines · {} commits",
+        summary.lines_of_code, summary.commit_count
+    )
+}
+
+fn more_tools(summary: &SessionSummary) -> Option<String> {
+    let rest = summary
+        .tools
+        .get(REPORT_TOOL_LIMIT..)
+        .filter(|r| !r.is_empty())?;
+    Some(format!(
+        "… and {} more tools ({} calls)",
+        rest.len(),
+        rest.iter().map(|t| t.calls).sum::<u64>()
+    ))
+}
+
+fn error_message(error: Option<&str>) -> String {
+    truncate(
+        &error.unwrap_or("(no message)").replace('\n', " "),
+        REPORT_ERROR_WIDTH,
+    )
+}
+
+/// Escape text for a markdown table cell
+fn cell(text: &str) -> String {
+    text.replace('|', "\\|")
+}
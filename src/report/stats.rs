@@ -0,0 +1,143 @@
+//! `agenttop stats`: totals for a time range in a stable JSON schema for dashboards and
+//! scripts. Fields are only ever added; a removal or change in meaning bumps
+//! [`SCHEMA_VERSION`].
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::fmt::Write;
+
+use crate::storage::StorageHandle;
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, schemars::JsonSchema)]
+pub struct Stats {
+    pub schema_version: u32,
+    /// Start of the range (null: from the first recorded event)
+    pub since: Option<DateTime<Utc>>,
+    /// End of the range (null: now)
+    pub until: Option<DateTime<Utc>>,
+    pub generated_at: DateTime<Utc>,
+    pub tokens: TokenStats,
+    pub cost_usd: f64,
+    pub tools: ToolStats,
+    pub api: ApiStats,
+    /// Distinct session ids seen in the range
+    pub sessions: u64,
+}
+
+#[derive(Debug, Clone, Serialize, schemars::JsonSchema)]
+pub struct TokenStats {
+    pub input: u64,
+    pub output: u64,
+    pub cache_read: u64,
+    pub cache_creation: u64,
+    /// Sum of the four counts above
+    pub total: u64,
+}
+
+#[derive(Debug, Clone, Serialize, schemars::JsonSchema)]
+pub struct ToolStats {
+    pub calls: u64,
+    pub errors: u64,
+    /// Per tool, most calls first
+    pub by_tool: Vec<ToolCount>,
+}
+
+#[derive(Debug, Clone, Serialize, schemars::JsonSchema)]
+pub struct ToolCount {
+    pub name: String,
+    pub calls: u64,
+    pub errors: u64,
+    pub avg_duration_ms: f64,
+}
+
+#[derive(Debug, Clone, Serialize, schemars::JsonSchema)]
+pub struct ApiStats {
+    pub calls: u64,
+    pub errors: u64,
+    /// Errors caused by rate limiting (HTTP 429 or a "rate limit" message)
+    pub rate_limited: u64,
+    pub avg_latency_ms: f64,
+}
+
+/// Collect the totals between `since` and `until`
+pub fn collect(
+    storage: &StorageHandle,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+) -> Result<Stats> {
+    let mut tools = storage.get_tool_metrics(since, until)?;
+    tools.sort_by(|a, b| {
+        b.call_count
+            .cmp(&a.call_count)
+            .then_with(|| a.tool_name.cmp(&b.tool_name))
+    });
+    let tokens = storage.get_token_metrics(since, until)?;
+    let api = storage.get_api_metrics(since, until)?;
+
+    Ok(Stats {
+        schema_version: SCHEMA_VERSION,
+        since,
+        until,
+        generated_at: Utc::now(),
+        tokens: TokenStats {
+            input: tokens.input_tokens,
+            output: tokens.output_tokens,
+            cache_read: tokens.cache_read_tokens,
+            cache_creation: tokens.cache_creation_tokens,
+            total: tokens.input_tokens
+                + tokens.output_tokens
+                + tokens.cache_read_tokens
+                + tokens.cache_creation_tokens,
+        },
+        cost_usd: tokens.total_cost_usd,
+        tools: ToolStats {
+            calls: tools.iter().map(|t| t.call_count).sum(),
+            errors: tools.iter().map(|t| t.error_count).sum(),
+            by_tool: tools
+                .iter()
+                .map(|t| ToolCount {
+                    name: t.tool_name.clone(),
+                    calls: t.call_count,
+                    errors: t.error_count,
+                    avg_duration_ms: t.avg_duration_ms,
+                })
+                .collect(),
+        },
+        api: ApiStats {
+            calls: api.total_calls,
+            errors: api.total_errors,
+            rate_limited: storage.count_rate_limit_errors(since, until)?,
+            avg_latency_ms: api.avg_latency_ms,
+        },
+        sessions: storage.count_sessions(since, until)?,
+    })
+}
+
+impl Stats {
+    /// `key value` lines with the same names as the JSON fields
+    pub fn render_text(&self) -> String {
+        let mut out = String::new();
+        let mut line = |key: &str, value: String| {
+            let _ = writeln!(out, "{:<22} {}", key, value);
+        };
+        line("tokens.input", self.tokens.input.to_string());
+        line("tokens.output", self.tokens.output.to_string());
+        line("tokens.cache_read", self.tokens.cache_read.to_string());
+        line(
+            "tokens.cache_creation",
+            self.tokens.cache_creation.to_string(),
+        );
+        line("tokens.total", self.tokens.total.to_string());
+        line("cost_usd", format!("{:.2}", self.cost_usd));
+        line("tools.calls", self.tools.calls.to_string());
+        line("tools.errors", self.tools.errors.to_string());
+        line("api.calls", self.api.calls.to_string());
+        line("api.errors", self.api.errors.to_string());
+        line("api.rate_limited", self.api.rate_limited.to_string());
+        line("sessions", self.sessions.to_string());
+        out
+    }
+}
@@ -0,0 +1,119 @@
+//! `agenttop schema`: JSON Schemas for the JSON agenttop writes, generated from the
+//! types that are serialized so they always match.
+
+use anyhow::{Context, Result};
+use std::path::Path;
+
+use crate::report::cost::CostReport;
+use crate::report::stats::Stats;
+use crate::storage::{LogEvent, ToolMetrics};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SchemaKind {
+    /// One log event: `agenttop tail` lines, /api/events, /ws and event hooks' stdin
+    Event,
+    /// Per-tool metrics from /api/tools
+    ToolMetrics,
+    /// `agenttop stats --json`
+    Stats,
+    /// `agenttop cost --json`
+    Cost,
+}
+
+impl SchemaKind {
+    pub const ALL: [SchemaKind; 4] = [
+        SchemaKind::Event,
+        SchemaKind::ToolMetrics,
+        SchemaKind::Stats,
+        SchemaKind::Cost,
+    ];
+
+    /// File name used by [`write_all`], e.g. "tool-metrics.schema.json"
+    pub fn file_name(self) -> &'static str {
+        match self {
+            SchemaKind::Event => "event.schema.json",
+            SchemaKind::ToolMetrics => "tool-metrics.schema.json",
+            SchemaKind::Stats => "stats.schema.json",
+            SchemaKind::Cost => "cost.schema.json",
+        }
+    }
+}
+
+/// The schema for `kind`
+pub fn generate(kind: SchemaKind) -> serde_json::Value {
+    let schema = match kind {
+        SchemaKind::Event => schemars::schema_for!(LogEvent),
+        SchemaKind::ToolMetrics => schemars::schema_for!(ToolMetrics),
+        SchemaKind::Stats => schemars::schema_for!(Stats),
+        SchemaKind::Cost => schemars::schema_for!(CostReport),
+    };
+    schema.to_value()
+}
+
+/// Write every schema into `dir`, returning the files written
+pub fn write_all(dir: &Path) -> Result<Vec<std::path::PathBuf>> {
+    std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    SchemaKind::ALL
+        .iter()
+        .map(|&kind| {
+            let path = dir.join(kind.file_name());
+            let json = serde_json::to_string_pretty(&generate(kind))?;
+            std::fs::write(&path, json + "\n")
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            Ok(path)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schemas_describe_serialized_fields() {
+        let event = generate(SchemaKind::Event);
+        assert_eq!(event["title"], "LogEvent");
+        assert_eq!(event["properties"]["timestamp"]["format"], "date-time");
+        assert_eq!(
+            event["properties"]["attributes"]["additionalProperties"]["type"],
+            "string"
+        );
+
+        let stats = generate(SchemaKind::Stats);
+        let required: Vec<&str> = stats["required"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|v| v.as_str())
+            .collect();
+        assert!(required.contains(&"schema_version"));
+        assert!(stats["$defs"]["TokenStats"].is_object());
+
+        let cost = generate(SchemaKind::Cost);
+        // Documented variants become one `const` each, the rest are collected in an `enum`
+        let mut groups: Vec<&str> = cost["$defs"]["CostGroup"]["oneOf"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .flat_map(|variant| match variant["enum"].as_array() {
+                Some(values) => values.iter().filter_map(|v| v.as_str()).collect(),
+                None => variant["const"].as_str().into_iter().collect::<Vec<_>>(),
+            })
+            .collect();
+        groups.sort_unstable();
+        assert_eq!(groups, ["day", "model", "project", "week"]);
+    }
+
+    #[test]
+    fn test_write_all() {
+        let dir = std::env::temp_dir().join(format!("agenttop-schema-{}", std::process::id()));
+        let paths = write_all(&dir).unwrap();
+        assert_eq!(paths.len(), SchemaKind::ALL.len());
+        let written: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(dir.join("tool-metrics.schema.json")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(written["title"], "ToolMetrics");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,296 @@
+//! Self-metrics: counters describing agenttop's own health.
+//!
+//! A single global instance is shared by the OTLP receiver and the TUI,
+//! the same way the provider registry is.
+
+use chrono::{DateTime, TimeZone, Utc};
+use once_cell::sync::Lazy;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Upper bounds in seconds of the latency histogram buckets (the last bucket is +Inf)
+pub const LATENCY_BUCKETS: [f64; 12] = [
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+];
+
+/// OTLP receiver health counters
+#[derive(Debug, Default)]
+pub struct ReceiverStats {
+    listen_addr: Mutex<Option<String>>,
+    /// Log events and metric data points accepted
+    events_received: AtomicU64,
+    /// Payloads rejected because they could not be parsed
+    payloads_dropped: AtomicU64,
+    /// Write batches dropped because storage fell behind
+    batches_dropped: AtomicU64,
+    /// Attribute values and bodies cut to the configured caps
+    values_truncated: AtomicU64,
+    /// Hook runs dropped because the hook queue was full
+    hook_runs_dropped: AtomicU64,
+    /// Unix millis of the last accepted event (0 = never)
+    last_event_millis: AtomicI64,
+}
+
+/// Point-in-time copy of the receiver counters
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReceiverSnapshot {
+    pub listen_addr: Option<String>,
+    pub events_received: u64,
+    pub payloads_dropped: u64,
+    pub batches_dropped: u64,
+    pub values_truncated: u64,
+    pub hook_runs_dropped: u64,
+    pub last_event_at: Option<DateTime<Utc>>,
+}
+
+impl ReceiverStats {
+    pub fn set_listening(&self, addr: &str) {
+        *self.listen_addr.lock().unwrap() = Some(addr.to_string());
+    }
+
+    pub fn record_events(&self, count: u64) {
+        if count == 0 {
+            return;
+        }
+        self.events_received.fetch_add(count, Ordering::Relaxed);
+        self.last_event_millis
+            .store(Utc::now().timestamp_millis(), Ordering::Relaxed);
+    }
+
+    pub fn record_dropped_payload(&self) {
+        self.payloads_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_truncated_values(&self, count: u64) {
+        self.values_truncated.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn record_dropped_batch(&self) {
+        self.batches_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_dropped_hook_run(&self) {
+        self.hook_runs_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> ReceiverSnapshot {
+        let last = self.last_event_millis.load(Ordering::Relaxed);
+        ReceiverSnapshot {
+            listen_addr: self.listen_addr.lock().unwrap().clone(),
+            events_received: self.events_received.load(Ordering::Relaxed),
+            payloads_dropped: self.payloads_dropped.load(Ordering::Relaxed),
+            batches_dropped: self.batches_dropped.load(Ordering::Relaxed),
+            values_truncated: self.values_truncated.load(Ordering::Relaxed),
+            hook_runs_dropped: self.hook_runs_dropped.load(Ordering::Relaxed),
+            last_event_at: (last > 0)
+                .then(|| Utc.timestamp_millis_opt(last).single())
+                .flatten(),
+        }
+    }
+}
+
+/// Durations bucketed by [`LATENCY_BUCKETS`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LatencyHistogram {
+    /// Observations per bucket (not cumulative); one more than there are bounds
+    pub buckets: [u64; LATENCY_BUCKETS.len() + 1],
+    pub count: u64,
+    pub sum_secs: f64,
+}
+
+impl LatencyHistogram {
+    pub fn observe(&mut self, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| secs <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.sum_secs += secs;
+    }
+}
+
+/// Storage actor health: whether it is running, how often it was restarted, and how
+/// long each kind of command takes
+#[derive(Debug, Default)]
+pub struct StorageStats {
+    /// Why the actor is down, while it is
+    error: Mutex<Option<String>>,
+    restarts: AtomicU64,
+    latencies: Mutex<BTreeMap<&'static str, LatencyHistogram>>,
+}
+
+/// Point-in-time copy of the storage actor health
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StorageSnapshot {
+    /// Set while the actor is down (degraded: queries fail, writes are dropped)
+    pub error: Option<String>,
+    pub restarts: u64,
+    /// Latency per command (e.g. "get_tool_metrics")
+    pub latencies: BTreeMap<&'static str, LatencyHistogram>,
+}
+
+impl StorageStats {
+    pub fn set_down(&self, error: &str) {
+        *self.error.lock().unwrap() = Some(error.to_string());
+    }
+
+    /// Clear the error without counting a restart, e.g. once a lazily opened database is open
+    pub fn set_up(&self) {
+        *self.error.lock().unwrap() = None;
+    }
+
+    pub fn record_restart(&self) {
+        *self.error.lock().unwrap() = None;
+        self.restarts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_latency(&self, command: &'static str, elapsed: Duration) {
+        self.latencies
+            .lock()
+            .unwrap()
+            .entry(command)
+            .or_default()
+            .observe(elapsed);
+    }
+
+    pub fn snapshot(&self) -> StorageSnapshot {
+        StorageSnapshot {
+            error: self.error.lock().unwrap().clone(),
+            restarts: self.restarts.load(Ordering::Relaxed),
+            latencies: self.latencies.lock().unwrap().clone(),
+        }
+    }
+}
+
+/// Size and effectiveness of the in-process caches (see [`crate::cache`]), by name
+#[derive(Debug, Default)]
+pub struct CacheStats {
+    caches: Mutex<BTreeMap<&'static str, CacheSnapshot>>,
+}
+
+/// Point-in-time copy of one cache's counters
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CacheSnapshot {
+    pub capacity: usize,
+    pub entries: usize,
+    /// Lookups that found an entry
+    pub hits: u64,
+    pub misses: u64,
+    /// Entries dropped to stay within the capacity
+    pub evictions: u64,
+}
+
+impl CacheStats {
+    fn update(&self, name: &'static str, f: impl FnOnce(&mut CacheSnapshot)) {
+        f(self.caches.lock().unwrap().entry(name).or_default());
+    }
+
+    pub fn record_hit(&self, name: &'static str) {
+        self.update(name, |cache| cache.hits += 1);
+    }
+
+    pub fn record_miss(&self, name: &'static str) {
+        self.update(name, |cache| cache.misses += 1);
+    }
+
+    pub fn record_evictions(&self, name: &'static str, count: u64) {
+        self.update(name, |cache| cache.evictions += count);
+    }
+
+    pub fn record_eviction(&self, name: &'static str) {
+        self.record_evictions(name, 1);
+    }
+
+    pub fn set_size(&self, name: &'static str, entries: usize, capacity: usize) {
+        self.update(name, |cache| {
+            cache.entries = entries;
+            cache.capacity = capacity;
+        });
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<&'static str, CacheSnapshot> {
+        self.caches.lock().unwrap().clone()
+    }
+}
+
+/// All self-metrics exposed by agenttop
+#[derive(Debug, Default)]
+pub struct SelfMetrics {
+    pub receiver: ReceiverStats,
+    pub storage: StorageStats,
+    pub caches: CacheStats,
+}
+
+/// Global self-metrics instance
+pub static SELF_METRICS: Lazy<SelfMetrics> = Lazy::new(SelfMetrics::default);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_receiver_stats_snapshot() {
+        let stats = ReceiverStats::default();
+        assert_eq!(stats.snapshot(), ReceiverSnapshot::default());
+
+        stats.set_listening("127.0.0.1:4318");
+        stats.record_events(0);
+        assert!(stats.snapshot().last_event_at.is_none());
+
+        stats.record_events(3);
+        stats.record_events(2);
+        stats.record_dropped_payload();
+        stats.record_dropped_batch();
+        stats.record_truncated_values(2);
+        stats.record_dropped_hook_run();
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.listen_addr.as_deref(), Some("127.0.0.1:4318"));
+        assert_eq!(snapshot.events_received, 5);
+        assert_eq!(snapshot.payloads_dropped, 1);
+        assert_eq!(snapshot.batches_dropped, 1);
+        assert_eq!(snapshot.values_truncated, 2);
+        assert_eq!(snapshot.hook_runs_dropped, 1);
+        assert!(snapshot.last_event_at.is_some());
+    }
+
+    #[test]
+    fn test_storage_stats_snapshot() {
+        let stats = StorageStats::default();
+        assert_eq!(stats.snapshot(), StorageSnapshot::default());
+
+        stats.set_down("boom");
+        assert_eq!(stats.snapshot().error.as_deref(), Some("boom"));
+
+        stats.record_restart();
+        assert_eq!(
+            stats.snapshot(),
+            StorageSnapshot {
+                error: None,
+                restarts: 1,
+                latencies: BTreeMap::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_latency_histogram() {
+        let stats = StorageStats::default();
+        stats.record_latency("get_tool_metrics", Duration::from_micros(500));
+        stats.record_latency("get_tool_metrics", Duration::from_millis(30));
+        stats.record_latency("get_tool_metrics", Duration::from_secs(60));
+
+        let snapshot = stats.snapshot();
+        let histogram = &snapshot.latencies["get_tool_metrics"];
+        assert_eq!(histogram.count, 3);
+        assert_eq!(histogram.buckets[0], 1);
+        // 30ms lands in the (0.025, 0.05] bucket
+        assert_eq!(histogram.buckets[5], 1);
+        assert_eq!(histogram.buckets[LATENCY_BUCKETS.len()], 1);
+        assert!((histogram.sum_secs - 60.0305).abs() < 1e-9);
+    }
+}
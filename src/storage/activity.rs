@@ -0,0 +1,212 @@
+//! Active time from event density. A session's events less than [`ACTIVE_GAP`] apart
+//! make up one active segment; the gaps between segments are idle. The storage actor
+//! merges each inserted batch into the `session_activity` table, so a terminal left open
+//! overnight counts the hours something happened in, not the hours it was open (the
+//! agent's own `active_time` metric is not reported by every agent, and not always
+//! trustworthy when it is).
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use duckdb::params;
+use std::collections::HashMap;
+
+use super::{ACTIVE_GAP, SqlTimestamp, Storage};
+use crate::providers::PROVIDER_REGISTRY;
+
+/// Log events read at a time when filling the session_activity table from existing events
+const REBUILD_BATCH: usize = 10_000;
+
+/// First and last event of an active segment
+type Segment = (DateTime<Utc>, DateTime<Utc>);
+
+/// `segments` sorted and joined where less than `gap` apart
+fn merge(mut segments: Vec<Segment>, gap: chrono::Duration) -> Vec<Segment> {
+    segments.sort();
+    let mut merged: Vec<Segment> = Vec::with_capacity(segments.len());
+    for (start, end) in segments {
+        match merged.last_mut() {
+            Some(last) if start < last.1 + gap => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+impl Storage {
+    /// Merge the event times of each session into its active segments
+    pub(super) fn track_activity(&self, times: &HashMap<String, Vec<DateTime<Utc>>>) -> Result<()> {
+        let gap = chrono::Duration::from_std(ACTIVE_GAP)?;
+        let mut select = self.conn.prepare_cached(
+            "SELECT started_at, ended_at FROM session_activity \
+             WHERE session_id = ? AND ended_at >= ? AND started_at <= ?",
+        )?;
+        let mut delete = self.conn.prepare_cached(
+            "DELETE FROM session_activity \
+             WHERE session_id = ? AND ended_at >= ? AND started_at <= ?",
+        )?;
+        let mut insert = self
+            .conn
+            .prepare_cached("INSERT INTO session_activity VALUES (?, ?, ?)")?;
+        for (session, times) in times {
+            let (Some(first), Some(last)) = (times.iter().min(), times.iter().max()) else {
+                continue;
+            };
+            // Stored segments are more than a gap apart, so only those within a gap of
+            // the new events can change
+            let window = params![
+                session,
+                SqlTimestamp(*first - gap),
+                SqlTimestamp(*last + gap)
+            ];
+            let mut segments = select
+                .query_map(window, |row| {
+                    Ok((
+                        row.get::<_, SqlTimestamp>(0)?.0,
+                        row.get::<_, SqlTimestamp>(1)?.0,
+                    ))
+                })?
+                .collect::<duckdb::Result<Vec<_>>>()?;
+            segments.extend(times.iter().map(|t| (*t, *t)));
+            delete.execute(window)?;
+            for (started_at, ended_at) in merge(segments, gap) {
+                insert.execute(params![
+                    session,
+                    SqlTimestamp(started_at),
+                    SqlTimestamp(ended_at)
+                ])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Seconds of the spans in `table` (session_activity or sessions) within the range
+    pub(super) fn clipped_secs(
+        &self,
+        table: &str,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<u64> {
+        let mut bounds = Vec::new();
+        let mut start = "started_at".to_string();
+        let mut end = "ended_at".to_string();
+        if since.is_some() {
+            let n = bounds.len() + 1;
+            bounds.push(format!("ended_at >= ?{n}"));
+            start = format!("greatest(started_at, ?{n})");
+        }
+        if until.is_some() {
+            let n = bounds.len() + 1;
+            bounds.push(format!("started_at < ?{n}"));
+            end = format!("least(ended_at, ?{n})");
+        }
+        let filter = if bounds.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", bounds.join(" AND "))
+        };
+        let millis: i64 = self
+            .conn
+            .prepare_cached(&format!(
+                "SELECT CAST(COALESCE(SUM(date_diff('millisecond', {start}, {end})), 0) AS BIGINT) \
+             FROM {table} {filter}"
+            ))?
+            .query_row(super::time_params(since, until), |row| row.get(0))?;
+        Ok(millis.max(0) as u64 / 1000)
+    }
+
+    /// Fill the session_activity table from the stored log events, e.g. in a database
+    /// written before activity was tracked. Events without a `session.id` go to the
+    /// inferred session of their provider that spans them.
+    pub(super) fn rebuild_session_activity(&self) -> Result<()> {
+        let mut stmt = self.conn.prepare(
+            "SELECT session_id, provider, started_at, ended_at FROM sessions WHERE inferred",
+        )?;
+        let inferred = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                    row.get::<_, SqlTimestamp>(2)?.0,
+                    row.get::<_, SqlTimestamp>(3)?.0,
+                ))
+            })?
+            .collect::<duckdb::Result<Vec<_>>>()?;
+
+        let mut after = i64::MIN;
+        loop {
+            let mut stmt = self.conn.prepare(&format!(
+                r#"SELECT id, timestamp, event_name, json_extract_string(attributes, '$."session.id"')
+                   FROM log_events WHERE id > ? ORDER BY id LIMIT {REBUILD_BATCH}"#
+            ))?;
+            let rows = stmt
+                .query_map(params![after], |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, SqlTimestamp>(1)?.0,
+                        row.get::<_, Option<String>>(2)?,
+                        row.get::<_, Option<String>>(3)?,
+                    ))
+                })?
+                .collect::<duckdb::Result<Vec<_>>>()?;
+            let Some((last, ..)) = rows.last() else {
+                return Ok(());
+            };
+            after = *last;
+
+            let mut times: HashMap<String, Vec<DateTime<Utc>>> = HashMap::new();
+            for (_, timestamp, event_name, session_id) in rows {
+                let session = session_id.or_else(|| {
+                    let provider = event_name
+                        .as_deref()
+                        .and_then(|name| PROVIDER_REGISTRY.detect_from_metric(name))
+                        .map(|p| p.id());
+                    inferred
+                        .iter()
+                        .find(|(_, p, started, ended)| {
+                            p.as_deref() == provider && *started <= timestamp && timestamp <= *ended
+                        })
+                        .map(|(id, ..)| id.clone())
+                });
+                if let Some(session) = session {
+                    times.entry(session).or_default().push(timestamp);
+                }
+            }
+            self.track_activity(&times)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_merge() {
+        let at = |mins: i64| {
+            Utc.with_ymd_and_hms(2025, 6, 1, 12, 0, 0).unwrap() + chrono::Duration::minutes(mins)
+        };
+        let gap = chrono::Duration::minutes(5);
+        let merged = merge(
+            vec![
+                (at(30), at(30)),
+                (at(0), at(10)),
+                (at(14), at(14)),
+                (at(12), at(13)),
+                (at(20), at(20)),
+                (at(40), at(41)),
+            ],
+            gap,
+        );
+        assert_eq!(
+            merged,
+            [
+                (at(0), at(14)),
+                (at(20), at(20)),
+                (at(30), at(30)),
+                (at(40), at(41))
+            ]
+        );
+        assert_eq!(merge(merged.clone(), gap), merged);
+    }
+}
@@ -0,0 +1,75 @@
+//! The `anomalies` table, written by the detector in [`crate::anomaly`] and read by the
+//! TUI alert banner and `anomaly` alert rules.
+
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use duckdb::params;
+
+use super::{SqlTimestamp, Storage, time_clause, time_params};
+use crate::anomaly::{Anomaly, AnomalyKind, AnomalyScope};
+
+impl Storage {
+    pub(super) fn record_anomaly(&self, anomaly: &Anomaly) -> Result<()> {
+        self.conn
+            .prepare_cached(
+                "INSERT INTO anomalies (timestamp, kind, scope, subject, value, baseline) \
+                 VALUES (?, ?, ?, ?, ?, ?)",
+            )?
+            .execute(params![
+                SqlTimestamp(anomaly.detected_at),
+                anomaly.kind.as_str(),
+                anomaly.scope.as_str(),
+                anomaly.subject,
+                anomaly.value,
+                anomaly.baseline,
+            ])?;
+        Ok(())
+    }
+
+    pub(super) fn get_anomalies(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        limit: usize,
+    ) -> Result<Vec<Anomaly>> {
+        let query = format!(
+            "SELECT timestamp, kind, scope, subject, value, baseline FROM anomalies {} \
+             ORDER BY timestamp DESC, id DESC LIMIT {limit}",
+            time_clause("WHERE", since, until)
+        );
+        let mut stmt = self.conn.prepare_cached(&query)?;
+        let rows = stmt.query_map(time_params(since, until), |row| {
+            Ok((
+                row.get::<_, SqlTimestamp>(0)?.0,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, f64>(4)?,
+                row.get::<_, f64>(5)?,
+            ))
+        })?;
+        rows.map(|row| {
+            let (detected_at, kind, scope, subject, value, baseline) = row?;
+            Ok(Anomaly {
+                detected_at,
+                kind: AnomalyKind::from_name(&kind)
+                    .ok_or_else(|| anyhow!("Unknown anomaly kind \"{}\"", kind))?,
+                scope: AnomalyScope::from_name(&scope)
+                    .ok_or_else(|| anyhow!("Unknown anomaly scope \"{}\"", scope))?,
+                subject,
+                value,
+                baseline,
+            })
+        })
+        .collect()
+    }
+
+    pub(super) fn count_anomalies(&self, kind: Option<AnomalyKind>) -> Result<u64> {
+        let count: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM anomalies WHERE ?1 IS NULL OR kind = ?1",
+            params![kind.map(|kind| kind.as_str())],
+            |row| row.get(0),
+        )?;
+        Ok(count as u64)
+    }
+}
@@ -0,0 +1,94 @@
+//! Budget spend per scope, and the `budget_periods` table the budget monitor keeps (see
+//! [`crate::budget`]).
+
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use duckdb::params;
+use duckdb::types::Value;
+
+use super::{BudgetUsage, SqlTimestamp, Storage, time_clause};
+use crate::budget::BudgetScope;
+use crate::providers::PROVIDER_REGISTRY;
+
+impl Storage {
+    pub(super) fn get_budget_spend(
+        &self,
+        scope: &BudgetScope,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<f64> {
+        let (filter, name) = match scope {
+            BudgetScope::Global => return self.get_total_cost(since, until),
+            BudgetScope::Provider(id) => {
+                let provider = PROVIDER_REGISTRY
+                    .get(id)
+                    .ok_or_else(|| anyhow!("Unknown provider \"{}\" in budget scope", id))?;
+                ("starts_with(event_name, ?{})", provider.metric_prefix())
+            }
+            BudgetScope::Project(name) => (
+                "json_extract_string(attributes, '$.project') = ?{}",
+                name.as_str(),
+            ),
+        };
+        let mut values: Vec<Value> = since
+            .into_iter()
+            .chain(until)
+            .map(|t| SqlTimestamp(t).into())
+            .collect();
+        values.push(Value::Text(name.to_string()));
+        let query = format!(
+            "SELECT COALESCE(SUM(TRY_CAST(json_extract_string(attributes, '$.cost_usd') AS DOUBLE)), 0) \
+             FROM log_events WHERE event_name LIKE '%api_request' {} AND {}",
+            time_clause("AND", since, until),
+            filter.replace("{}", &values.len().to_string())
+        );
+        let spent: f64 = self
+            .conn
+            .prepare_cached(&query)?
+            .query_row(duckdb::params_from_iter(values), |row| row.get(0))?;
+        Ok(spent)
+    }
+
+    pub(super) fn get_budget_usage(
+        &self,
+        budget: Option<&str>,
+        open_only: bool,
+        limit: usize,
+    ) -> Result<Vec<BudgetUsage>> {
+        let query = format!(
+            "SELECT budget, period_start, period_end, limit_usd, spent_usd, closed, updated_at \
+             FROM budget_periods \
+             WHERE (?1 IS NULL OR budget = ?1) AND (NOT ?2 OR NOT closed) \
+             ORDER BY period_start DESC, budget LIMIT {}",
+            limit.min(i64::MAX as usize)
+        );
+        let mut stmt = self.conn.prepare_cached(&query)?;
+        let rows = stmt.query_map(params![budget, open_only], |row| {
+            Ok(BudgetUsage {
+                budget: row.get(0)?,
+                period_start: row.get::<_, SqlTimestamp>(1)?.0,
+                period_end: row.get::<_, SqlTimestamp>(2)?.0,
+                limit_usd: row.get(3)?,
+                spent_usd: row.get(4)?,
+                closed: row.get(5)?,
+                updated_at: row.get::<_, SqlTimestamp>(6)?.0,
+            })
+        })?;
+        Ok(rows.collect::<duckdb::Result<Vec<_>>>()?)
+    }
+
+    pub(super) fn save_budget_usage(&self, usage: &BudgetUsage) -> Result<()> {
+        self.conn
+            .prepare_cached("INSERT OR REPLACE INTO budget_periods VALUES (?, ?, ?, ?, ?, ?, ?)")?
+            .execute(params![
+                usage.budget,
+                SqlTimestamp(usage.period_start),
+                SqlTimestamp(usage.period_end),
+                usage.limit_usd,
+                usage.spent_usd,
+                usage.closed,
+                SqlTimestamp(usage.updated_at),
+            ])?;
+        Ok(())
+    }
+}
@@ -0,0 +1,117 @@
+//! Provider head-to-head: each detected provider's spend, tokens, API latency and error
+//! rates over a range, from the log events (the provider is found from the event name's
+//! prefix, as at ingest) and the sessions table.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+
+use super::efficiency::FAILED;
+use super::{ProviderComparison, Storage, time_clause};
+use crate::providers::PROVIDER_REGISTRY;
+
+impl Storage {
+    pub(super) fn get_provider_comparison(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<Vec<ProviderComparison>> {
+        // Prefixes and ids are the registry's own constants, not user input
+        let provider = PROVIDER_REGISTRY
+            .providers()
+            .iter()
+            .map(|p| {
+                format!(
+                    "WHEN starts_with(event_name, '{}') THEN '{}'",
+                    p.metric_prefix(),
+                    p.id()
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        let mut bounds = Vec::new();
+        if since.is_some() {
+            bounds.push(format!("ended_at >= ?{}", bounds.len() + 1));
+        }
+        if until.is_some() {
+            bounds.push(format!("started_at < ?{}", bounds.len() + 1));
+        }
+        let session_filter = if bounds.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", bounds.join(" AND "))
+        };
+        let attr_u64 = |name: &str| {
+            format!(
+                "CAST(COALESCE(SUM(TRY_CAST(json_extract_string(attributes, '$.{name}') AS BIGINT)) FILTER (WHERE event_name LIKE '%api_request'), 0) AS BIGINT)"
+            )
+        };
+        let query = format!(
+            r#"
+            WITH events AS (
+                SELECT
+                    CASE {provider} END AS provider,
+                    event_name,
+                    attributes,
+                    TRY_CAST(COALESCE(
+                        json_extract_string(attributes, '$.latency_ms'),
+                        json_extract_string(attributes, '$.duration_ms')
+                    ) AS DOUBLE) AS latency_ms
+                FROM log_events
+                WHERE event_name IS NOT NULL {time_filter}
+            ),
+            totals AS (
+                SELECT
+                    provider,
+                    COUNT(*) FILTER (WHERE event_name LIKE '%api_request') AS api_calls,
+                    COUNT(*) FILTER (WHERE event_name LIKE '%api_error') AS api_errors,
+                    COALESCE(SUM(TRY_CAST(json_extract_string(attributes, '$.cost_usd') AS DOUBLE)) FILTER (WHERE event_name LIKE '%api_request'), 0) AS cost_usd,
+                    {input} AS input_tokens,
+                    {output} AS output_tokens,
+                    {cache_read} AS cache_read_tokens,
+                    COALESCE(AVG(latency_ms) FILTER (WHERE event_name LIKE '%api_request'), 0) AS avg_latency_ms,
+                    CAST(COALESCE(quantile_disc(latency_ms, 0.95) FILTER (WHERE event_name LIKE '%api_request'), 0) AS BIGINT) AS p95_latency_ms,
+                    COUNT(*) FILTER (WHERE event_name LIKE '%tool_result') AS tool_calls,
+                    COUNT(*) FILTER (WHERE event_name LIKE '%tool_result' AND {FAILED}) AS tool_errors
+                FROM events
+                WHERE provider IS NOT NULL
+                GROUP BY provider
+            ),
+            session_counts AS (
+                SELECT provider, COUNT(*) AS sessions
+                FROM sessions
+                {session_filter}
+                GROUP BY provider
+            )
+            SELECT t.provider, COALESCE(s.sessions, 0), t.api_calls, t.api_errors, t.cost_usd,
+                   t.input_tokens, t.output_tokens, t.cache_read_tokens, t.avg_latency_ms,
+                   t.p95_latency_ms, t.tool_calls, t.tool_errors
+            FROM totals t
+            LEFT JOIN session_counts s USING (provider)
+            ORDER BY t.cost_usd DESC, t.provider
+            "#,
+            time_filter = time_clause("AND", since, until),
+            input = attr_u64("input_tokens"),
+            output = attr_u64("output_tokens"),
+            cache_read = attr_u64("cache_read_tokens"),
+        );
+        // Not cached, for the reason given in get_session_metrics
+        let mut stmt = self.conn.prepare(&query)?;
+        let rows = stmt.query_map(super::time_params(since, until), |row| {
+            Ok(ProviderComparison {
+                provider: row.get(0)?,
+                sessions: row.get::<_, i64>(1)?.max(0) as u64,
+                api_calls: row.get::<_, i64>(2)?.max(0) as u64,
+                api_errors: row.get::<_, i64>(3)?.max(0) as u64,
+                cost_usd: row.get(4)?,
+                input_tokens: row.get::<_, i64>(5)?.max(0) as u64,
+                output_tokens: row.get::<_, i64>(6)?.max(0) as u64,
+                cache_read_tokens: row.get::<_, i64>(7)?.max(0) as u64,
+                avg_latency_ms: row.get(8)?,
+                p95_latency_ms: row.get::<_, i64>(9)?.max(0) as u64,
+                tool_calls: row.get::<_, i64>(10)?.max(0) as u64,
+                tool_errors: row.get::<_, i64>(11)?.max(0) as u64,
+            })
+        })?;
+        Ok(rows.collect::<duckdb::Result<Vec<_>>>()?)
+    }
+}
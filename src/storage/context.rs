@@ -0,0 +1,50 @@
+//! Context size of each api_request (see [`crate::context`]): the prompt the model read,
+//! i.e. its uncached, cache-read and cache-written input tokens.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+
+use super::{ContextSample, SqlTimestamp, Storage, time_clause, time_params};
+
+impl Storage {
+    pub(super) fn get_context_samples(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        limit: usize,
+    ) -> Result<Vec<ContextSample>> {
+        let tokens = |name: &str| {
+            format!("COALESCE(TRY_CAST(json_extract_string(attributes, '$.{name}') AS BIGINT), 0)")
+        };
+        let query = format!(
+            r#"SELECT timestamp, json_extract_string(attributes, '$."session.id"'),
+                      json_extract_string(attributes, '$.model'), {} + {} + {},
+                      TRY_CAST(json_extract_string(attributes, '$.context_window') AS BIGINT)
+               FROM log_events WHERE event_name LIKE '%api_request' {}
+               ORDER BY timestamp DESC LIMIT {}"#,
+            tokens("input_tokens"),
+            tokens("cache_read_tokens"),
+            tokens("cache_creation_tokens"),
+            time_clause("AND", since, until),
+            limit.min(i64::MAX as usize)
+        );
+        // Not cached, for the reason given in get_session_metrics
+        let mut stmt = self.conn.prepare(&query)?;
+        let mut samples = stmt
+            .query_map(time_params(since, until), |row| {
+                Ok(ContextSample {
+                    timestamp: row.get::<_, SqlTimestamp>(0)?.0,
+                    session_id: row.get(1)?,
+                    model: row.get(2)?,
+                    context_tokens: row.get::<_, i64>(3)?.max(0) as u64,
+                    context_window: row
+                        .get::<_, Option<i64>>(4)?
+                        .filter(|tokens| *tokens > 0)
+                        .map(|tokens| tokens as u64),
+                })
+            })?
+            .collect::<duckdb::Result<Vec<_>>>()?;
+        samples.reverse();
+        Ok(samples)
+    }
+}
@@ -0,0 +1,125 @@
+//! The `session_scores` table: each session's efficiency score (see
+//! [`crate::efficiency`]), kept by the scorer and joined into the session list.
+//! Inferred sessions carry no `session.id` to select their events by and aren't scored.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use duckdb::params;
+
+use super::{SqlTimestamp, Storage};
+use crate::efficiency::{EDIT_TOOLS, Efficiency, EfficiencyInputs};
+
+/// SQL condition that a tool_result event failed
+pub(super) const FAILED: &str =
+    "COALESCE(json_extract_string(attributes, '$.success'), 'false') NOT IN ('true', '1')";
+
+impl Storage {
+    /// Score up to `limit` sessions with events newer than their score, most recently
+    /// active first; returns how many were scored
+    pub(super) fn score_sessions(&self, limit: usize) -> Result<usize> {
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT s.session_id, s.started_at, s.ended_at FROM sessions s \
+             LEFT JOIN session_scores sc USING (session_id) \
+             WHERE NOT s.inferred AND (sc.scored_through IS NULL OR sc.scored_through < s.ended_at) \
+             ORDER BY s.ended_at DESC LIMIT {limit}"
+        ))?;
+        let sessions = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, SqlTimestamp>(1)?.0,
+                    row.get::<_, SqlTimestamp>(2)?.0,
+                ))
+            })?
+            .collect::<duckdb::Result<Vec<_>>>()?;
+
+        for (session_id, started_at, ended_at) in &sessions {
+            let inputs = self.get_efficiency_inputs(session_id, *started_at, *ended_at)?;
+            let efficiency = Efficiency::score(&inputs);
+            self.conn
+                .prepare_cached(
+                    "INSERT OR REPLACE INTO session_scores VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+                )?
+                .execute(params![
+                    session_id,
+                    SqlTimestamp(*ended_at),
+                    efficiency.map(|e| e.score),
+                    efficiency.and_then(|e| e.success_rate),
+                    efficiency.and_then(|e| e.cache_reuse),
+                    efficiency.and_then(|e| e.retry_rate),
+                    efficiency.and_then(|e| e.cost_per_outcome),
+                    inputs.accepted_edits as i64 + inputs.commits as i64,
+                ])?;
+        }
+        Ok(sessions.len())
+    }
+
+    fn get_efficiency_inputs(
+        &self,
+        session_id: &str,
+        started_at: DateTime<Utc>,
+        ended_at: DateTime<Utc>,
+    ) -> Result<EfficiencyInputs> {
+        let attr_u64 = |name: &str| {
+            format!(
+                "COALESCE(SUM(TRY_CAST(json_extract_string(attributes, '$.{name}') AS BIGINT)) FILTER (WHERE event_name LIKE '%api_request'), 0)"
+            )
+        };
+        let edit_tools = EDIT_TOOLS
+            .iter()
+            .map(|tool| format!("'{tool}'"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let query = format!(
+            r#"
+            SELECT
+                COUNT(*) FILTER (WHERE event_name LIKE '%tool_result'),
+                COUNT(*) FILTER (WHERE event_name LIKE '%tool_result' AND {FAILED}),
+                COUNT(*) FILTER (WHERE event_name LIKE '%api_request'),
+                COUNT(*) FILTER (WHERE event_name LIKE '%api_error'),
+                {},
+                {},
+                {},
+                COALESCE(SUM(TRY_CAST(json_extract_string(attributes, '$.cost_usd') AS DOUBLE)) FILTER (WHERE event_name LIKE '%api_request'), 0),
+                COUNT(*) FILTER (
+                    WHERE event_name LIKE '%tool_result'
+                    AND NOT {FAILED}
+                    AND json_extract_string(attributes, '$.tool_name') IN ({edit_tools})
+                    AND COALESCE(json_extract_string(attributes, '$.decision'), '')
+                        NOT IN ('reject', 'rejected', 'deny', 'denied')
+                )
+            FROM log_events
+            WHERE json_extract_string(attributes, '$."session.id"') = ?
+            "#,
+            attr_u64("input_tokens"),
+            attr_u64("cache_read_tokens"),
+            attr_u64("cache_creation_tokens"),
+        );
+        let mut inputs =
+            self.conn
+                .prepare_cached(&query)?
+                .query_row(params![session_id], |row| {
+                    Ok(EfficiencyInputs {
+                        tool_calls: row.get::<_, i64>(0)? as u64,
+                        tool_errors: row.get::<_, i64>(1)? as u64,
+                        api_calls: row.get::<_, i64>(2)? as u64,
+                        api_errors: row.get::<_, i64>(3)? as u64,
+                        input_tokens: row.get::<_, i64>(4)? as u64,
+                        cache_read_tokens: row.get::<_, i64>(5)? as u64,
+                        cache_creation_tokens: row.get::<_, i64>(6)? as u64,
+                        cost_usd: row.get(7)?,
+                        accepted_edits: row.get::<_, i64>(8)? as u64,
+                        commits: 0,
+                    })
+                })?;
+        // Commits carry no session id; as in the session report, count the ones recorded
+        // between the session's first event and a minute after its last
+        inputs.commits = self
+            .get_session_metrics(
+                Some(started_at),
+                Some(ended_at + chrono::Duration::minutes(1)),
+            )?
+            .commit_count;
+        Ok(inputs)
+    }
+}
@@ -0,0 +1,239 @@
+//! Classified errors: each failed tool call and api_error with its category (see
+//! [`crate::taxonomy`]). The storage actor adds each inserted batch to the
+//! `error_events` table, so the errors view groups by a stored column instead of
+//! matching every message against the rules on each refresh.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use duckdb::params;
+
+use super::{
+    ErrorBreakdown, ErrorCategoryCount, LogEvent, SqlTimestamp, Storage, time_clause, time_params,
+};
+use crate::providers::PROVIDER_REGISTRY;
+use crate::taxonomy;
+
+/// Error events read at a time when filling the error_events table from existing events
+const REBUILD_BATCH: usize = 10_000;
+
+/// `error_events.kind` of a failed tool call
+pub const TOOL_ERROR: &str = "tool";
+/// `error_events.kind` of an api_error
+pub const API_ERROR: &str = "api";
+
+/// Kind, source (tool or model) and message of `event` if it is an error
+fn error_of(event: &LogEvent) -> Option<(&'static str, Option<&str>, String)> {
+    let name = event.event_name.as_deref()?;
+    let attr = |key: &str| event.attributes.get(key).map(String::as_str);
+    let (kind, source) = if name.ends_with("tool_result") {
+        if matches!(attr("success"), Some("true" | "1")) {
+            return None;
+        }
+        (TOOL_ERROR, attr("tool_name"))
+    } else if name.ends_with("api_error") {
+        (API_ERROR, attr("model"))
+    } else {
+        return None;
+    };
+    // An API error's status code classifies it where the message doesn't
+    let message = [attr("status_code"), attr("error")]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" ");
+    Some((kind, source, message))
+}
+
+impl Storage {
+    /// Add the errors among `events`, just inserted into log_events, to error_events
+    pub(super) fn track_errors(&self, events: &[LogEvent]) -> Result<()> {
+        let mut stmt = self.conn.prepare_cached(
+            "INSERT INTO error_events \
+             (timestamp, session_id, provider, kind, source, category, message) \
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )?;
+        for event in events {
+            let Some((kind, source, message)) = error_of(event) else {
+                continue;
+            };
+            let provider = event
+                .event_name
+                .as_deref()
+                .and_then(|name| PROVIDER_REGISTRY.detect_from_metric(name))
+                .map(|p| p.id());
+            stmt.execute(params![
+                SqlTimestamp(event.timestamp),
+                event.attributes.get("session.id"),
+                provider,
+                kind,
+                source,
+                taxonomy::classify(&message),
+                event.attributes.get("error"),
+            ])?;
+        }
+        Ok(())
+    }
+
+    /// Fill the error_events table from the stored events, e.g. in a database written
+    /// before errors were classified
+    pub(super) fn rebuild_error_events(&self) -> Result<()> {
+        let mut after = i64::MIN;
+        loop {
+            let mut stmt = self.conn.prepare(&format!(
+                "SELECT id, timestamp, event_name, CAST(attributes AS VARCHAR) FROM log_events \
+                 WHERE id > ? AND (event_name LIKE '%tool_result' OR event_name LIKE '%api_error') \
+                 ORDER BY id LIMIT {REBUILD_BATCH}"
+            ))?;
+            let rows = stmt
+                .query_map(params![after], |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, SqlTimestamp>(1)?.0,
+                        row.get::<_, Option<String>>(2)?,
+                        row.get::<_, Option<String>>(3)?,
+                    ))
+                })?
+                .collect::<duckdb::Result<Vec<_>>>()?;
+            let Some((last, ..)) = rows.last() else {
+                return Ok(());
+            };
+            after = *last;
+
+            let events: Vec<LogEvent> = rows
+                .into_iter()
+                .map(|(_, timestamp, event_name, attributes)| LogEvent {
+                    timestamp,
+                    event_name,
+                    body: None,
+                    attributes: attributes
+                        .and_then(|json| serde_json::from_str(&json).ok())
+                        .unwrap_or_default(),
+                })
+                .collect();
+            self.track_errors(&events)?;
+        }
+    }
+
+    /// Classify every stored error again with the current rules; returns how many there are
+    pub(super) fn reclassify_errors(&self) -> Result<u64> {
+        self.conn.execute_batch("BEGIN TRANSACTION")?;
+        let rebuilt = self
+            .conn
+            .execute_batch("DELETE FROM error_events")
+            .map_err(anyhow::Error::from)
+            .and_then(|_| self.rebuild_error_events());
+        if let Err(e) = rebuilt {
+            let _ = self.conn.execute_batch("ROLLBACK");
+            return Err(e);
+        }
+        self.conn.execute_batch("COMMIT")?;
+        let count: i64 = self
+            .conn
+            .query_row("SELECT COUNT(*) FROM error_events", [], |row| row.get(0))?;
+        Ok(count.max(0) as u64)
+    }
+
+    pub(super) fn get_error_categories(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<Vec<ErrorCategoryCount>> {
+        let query = format!(
+            "SELECT category, COUNT(*), \
+                    COUNT(*) FILTER (WHERE kind = '{TOOL_ERROR}'), \
+                    COUNT(*) FILTER (WHERE kind = '{API_ERROR}'), \
+                    COUNT(DISTINCT session_id), MAX(timestamp) \
+             FROM error_events {} \
+             GROUP BY category ORDER BY COUNT(*) DESC, category",
+            time_clause("WHERE", since, until)
+        );
+        // Not cached, for the reason given in get_session_metrics
+        let mut stmt = self.conn.prepare(&query)?;
+        let rows = stmt.query_map(time_params(since, until), |row| {
+            Ok(ErrorCategoryCount {
+                category: row.get(0)?,
+                errors: row.get::<_, i64>(1)?.max(0) as u64,
+                tool_errors: row.get::<_, i64>(2)?.max(0) as u64,
+                api_errors: row.get::<_, i64>(3)?.max(0) as u64,
+                sessions: row.get::<_, i64>(4)?.max(0) as u64,
+                last_at: row.get::<_, SqlTimestamp>(5)?.0,
+            })
+        })?;
+        Ok(rows.collect::<duckdb::Result<Vec<_>>>()?)
+    }
+
+    pub(super) fn get_error_breakdown(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        limit: usize,
+    ) -> Result<Vec<ErrorBreakdown>> {
+        let query = format!(
+            "SELECT category, kind, COALESCE(source, 'unknown') AS tool_or_model, COUNT(*), \
+                    MAX(timestamp), arg_max(message, timestamp) \
+             FROM error_events {} \
+             GROUP BY category, kind, tool_or_model \
+             ORDER BY COUNT(*) DESC, category, kind, tool_or_model \
+             LIMIT {}",
+            time_clause("WHERE", since, until),
+            limit.min(i64::MAX as usize)
+        );
+        // Not cached, for the reason given in get_session_metrics
+        let mut stmt = self.conn.prepare(&query)?;
+        let rows = stmt.query_map(time_params(since, until), |row| {
+            Ok(ErrorBreakdown {
+                category: row.get(0)?,
+                kind: row.get(1)?,
+                source: row.get(2)?,
+                errors: row.get::<_, i64>(3)?.max(0) as u64,
+                last_at: row.get::<_, SqlTimestamp>(4)?.0,
+                last_message: row.get(5)?,
+            })
+        })?;
+        Ok(rows.collect::<duckdb::Result<Vec<_>>>()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(name: &str, attrs: &[(&str, &str)]) -> LogEvent {
+        LogEvent {
+            timestamp: Utc::now(),
+            event_name: Some(name.to_string()),
+            body: None,
+            attributes: attrs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_error_of() {
+        let failed = event(
+            "claude_code.tool_result",
+            &[
+                ("tool_name", "Bash"),
+                ("success", "false"),
+                ("error", "Permission denied"),
+            ],
+        );
+        assert_eq!(
+            error_of(&failed),
+            Some((TOOL_ERROR, Some("Bash"), "Permission denied".to_string()))
+        );
+        let ok = event("claude_code.tool_result", &[("success", "true")]);
+        assert_eq!(error_of(&ok), None);
+        let api = event(
+            "claude_code.api_error",
+            &[("model", "claude-sonnet-4-5"), ("status_code", "429")],
+        );
+        assert_eq!(
+            error_of(&api),
+            Some((API_ERROR, Some("claude-sonnet-4-5"), "429".to_string()))
+        );
+        assert_eq!(error_of(&event("claude_code.api_request", &[])), None);
+    }
+}
@@ -0,0 +1,241 @@
+//! File activity: the file each tool call worked on, taken from the tool_result's
+//! attributes (`file_path`, `path`, ... directly or inside the JSON of
+//! `tool_parameters` / `tool_input`) and normalized. The storage actor adds each
+//! inserted batch to the `file_events` table, so "most edited files" reads one narrow
+//! row per call instead of parsing every event's attributes.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use duckdb::params;
+use std::collections::HashMap;
+
+use super::{FileActivity, LogEvent, SqlTimestamp, Storage, time_clause, time_params};
+use crate::efficiency::EDIT_TOOLS;
+
+/// Attributes holding the path a tool worked on, most specific first
+const PATH_KEYS: &[&str] = &["file_path", "notebook_path", "absolute_path", "path"];
+
+/// Attributes holding a tool's parameters as JSON, which may contain one of [`PATH_KEYS`]
+const PARAMETER_KEYS: &[&str] = &["tool_parameters", "tool_input"];
+
+/// Tool results read at a time when filling the file_events table from existing events
+const REBUILD_BATCH: usize = 10_000;
+
+/// The normalized path a tool call worked on, if its attributes name one
+fn file_path(attributes: &HashMap<String, String>) -> Option<String> {
+    let direct = PATH_KEYS
+        .iter()
+        .find_map(|key| attributes.get(*key).cloned());
+    let from_parameters = || {
+        PARAMETER_KEYS.iter().find_map(|key| {
+            let parameters: serde_json::Value = serde_json::from_str(attributes.get(*key)?).ok()?;
+            PATH_KEYS
+                .iter()
+                .find_map(|key| parameters.get(*key)?.as_str().map(str::to_string))
+        })
+    };
+    normalize_path(&direct.or_else(from_parameters)?)
+}
+
+/// `path` with `/` separators, `.` and `..` resolved and no trailing separator; None
+/// when nothing is left
+fn normalize_path(path: &str) -> Option<String> {
+    let path = path.trim().replace('\\', "/");
+    let absolute = path.starts_with('/');
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." if parts.last().is_some_and(|last| *last != "..") => {
+                parts.pop();
+            }
+            // Nothing above the root
+            ".." if absolute => {}
+            _ => parts.push(part),
+        }
+    }
+    match (absolute, parts.is_empty()) {
+        (true, true) => Some("/".to_string()),
+        (false, true) => None,
+        (true, false) => Some(format!("/{}", parts.join("/"))),
+        (false, false) => Some(parts.join("/")),
+    }
+}
+
+/// Directory holding the normalized `path`: "." for a bare relative name
+fn directory(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(0) => "/",
+        Some(i) => &path[..i],
+        None => ".",
+    }
+}
+
+impl Storage {
+    /// Add the tool results among `events`, just inserted into log_events, that name a
+    /// file to file_events
+    pub(super) fn track_files(&self, events: &[LogEvent]) -> Result<()> {
+        let mut stmt = self.conn.prepare_cached(
+            "INSERT INTO file_events (timestamp, session_id, tool_name, path, directory, edit) \
+             VALUES (?, ?, ?, ?, ?, ?)",
+        )?;
+        for event in events {
+            if !event
+                .event_name
+                .as_deref()
+                .is_some_and(|name| name.ends_with("tool_result"))
+            {
+                continue;
+            }
+            let Some(path) = file_path(&event.attributes) else {
+                continue;
+            };
+            let tool = event
+                .attributes
+                .get("tool_name")
+                .map_or("unknown", String::as_str);
+            stmt.execute(params![
+                SqlTimestamp(event.timestamp),
+                event.attributes.get("session.id"),
+                tool,
+                path,
+                directory(&path),
+                EDIT_TOOLS.contains(&tool),
+            ])?;
+        }
+        Ok(())
+    }
+
+    /// Fill the file_events table from the stored tool results, e.g. in a database
+    /// written before file activity was tracked
+    pub(super) fn rebuild_file_events(&self) -> Result<()> {
+        let mut after = i64::MIN;
+        loop {
+            let mut stmt = self.conn.prepare(&format!(
+                "SELECT id, timestamp, event_name, CAST(attributes AS VARCHAR) FROM log_events \
+                 WHERE id > ? AND event_name LIKE '%tool_result' ORDER BY id LIMIT {REBUILD_BATCH}"
+            ))?;
+            let rows = stmt
+                .query_map(params![after], |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, SqlTimestamp>(1)?.0,
+                        row.get::<_, Option<String>>(2)?,
+                        row.get::<_, Option<String>>(3)?,
+                    ))
+                })?
+                .collect::<duckdb::Result<Vec<_>>>()?;
+            let Some((last, ..)) = rows.last() else {
+                return Ok(());
+            };
+            after = *last;
+
+            let events: Vec<LogEvent> = rows
+                .into_iter()
+                .map(|(_, timestamp, event_name, attributes)| LogEvent {
+                    timestamp,
+                    event_name,
+                    body: None,
+                    attributes: attributes
+                        .and_then(|json| serde_json::from_str(&json).ok())
+                        .unwrap_or_default(),
+                })
+                .collect();
+            self.track_files(&events)?;
+        }
+    }
+
+    pub(super) fn get_file_activity(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        directories: bool,
+        limit: usize,
+    ) -> Result<Vec<FileActivity>> {
+        let column = if directories { "directory" } else { "path" };
+        let query = format!(
+            r#"
+            SELECT
+                {column},
+                COUNT(*) FILTER (WHERE edit),
+                COUNT(*),
+                COUNT(DISTINCT session_id),
+                MAX(timestamp)
+            FROM file_events
+            {}
+            GROUP BY {column}
+            ORDER BY 2 DESC, 3 DESC, 1
+            LIMIT {limit}
+            "#,
+            time_clause("WHERE", since, until)
+        );
+        let mut stmt = self.conn.prepare_cached(&query)?;
+        let rows = stmt.query_map(time_params(since, until), |row| {
+            Ok(FileActivity {
+                path: row.get(0)?,
+                edits: row.get::<_, i64>(1)? as u64,
+                calls: row.get::<_, i64>(2)? as u64,
+                sessions: row.get::<_, i64>(3)? as u64,
+                last_at: row.get::<_, SqlTimestamp>(4)?.0,
+            })
+        })?;
+        Ok(rows.collect::<duckdb::Result<Vec<_>>>()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_path() {
+        assert_eq!(
+            normalize_path("/repo/src/./lib.rs").unwrap(),
+            "/repo/src/lib.rs"
+        );
+        assert_eq!(
+            normalize_path("/repo/src/../README.md/").unwrap(),
+            "/repo/README.md"
+        );
+        assert_eq!(
+            normalize_path(r"C:\repo\src\main.rs").unwrap(),
+            "C:/repo/src/main.rs"
+        );
+        assert_eq!(normalize_path("../../up").unwrap(), "../../up");
+        assert_eq!(normalize_path("/../etc//hosts").unwrap(), "/etc/hosts");
+        assert_eq!(normalize_path(" ./ "), None);
+        assert_eq!(directory("/repo/src/lib.rs"), "/repo/src");
+        assert_eq!(directory("/lib.rs"), "/");
+        assert_eq!(directory("lib.rs"), ".");
+    }
+
+    #[test]
+    fn test_file_path_from_attributes() {
+        let attributes = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        assert_eq!(
+            file_path(&attributes(&[("file_path", "/repo/a.rs")])).unwrap(),
+            "/repo/a.rs"
+        );
+        assert_eq!(
+            file_path(&attributes(&[(
+                "tool_parameters",
+                r#"{"notebook_path": "/repo/nb.ipynb"}"#
+            )]))
+            .unwrap(),
+            "/repo/nb.ipynb"
+        );
+        assert_eq!(
+            file_path(&attributes(&[(
+                "tool_parameters",
+                r#"{"bash_command": "ls"}"#
+            )])),
+            None
+        );
+        assert_eq!(file_path(&attributes(&[("tool_input", "not json")])), None);
+    }
+}
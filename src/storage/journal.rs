@@ -0,0 +1,426 @@
+//! Write-ahead journal of received writes. Writes are queued for the storage actor and
+//! committed a moment later, so a crash (or storage being down) in between would lose
+//! them. With the journal enabled, each write is appended to a JSONL file first, under
+//! a number; whatever is left in it is replayed on the next start.
+//!
+//! Once a write was committed, or dropped because the queue was full, a `{"done": N}`
+//! line follows, and the write is not replayed; one that failed to commit stays for the
+//! next start. When most of the file is done with, it is rewritten with the writes still
+//! outstanding or failed, replacing it in one rename, so it stays small under steady
+//! ingest and every write is replayed at most once.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use super::{LogEvent, StorageCommand, ToolEvent};
+use crate::anomaly::Anomaly;
+
+/// A line of the file
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Line<'a> {
+    /// Entry `done` was committed or dropped
+    Done { done: u64 },
+    Write {
+        seq: u64,
+        #[serde(flatten)]
+        entry: Entry<'a>,
+    },
+}
+
+/// One journaled write
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Entry<'a> {
+    ToolEvent {
+        event: Cow<'a, ToolEvent>,
+    },
+    LogEvents {
+        events: Cow<'a, [LogEvent]>,
+    },
+    TokenUsage {
+        token_type: Cow<'a, str>,
+        count: u64,
+        model: Option<Cow<'a, str>>,
+        provider: Option<Cow<'a, str>>,
+    },
+    Cost {
+        cost_usd: f64,
+        session_id: Option<Cow<'a, str>>,
+        model: Option<Cow<'a, str>>,
+        cumulative: bool,
+    },
+    SessionMetric {
+        name: Cow<'a, str>,
+        value: i64,
+        session_id: Option<Cow<'a, str>>,
+    },
+    Anomaly {
+        anomaly: Cow<'a, Anomaly>,
+    },
+}
+
+impl<'a> Entry<'a> {
+    /// The entry for a write command; None for any other command
+    fn of(cmd: &'a StorageCommand) -> Option<Self> {
+        let borrow = |value: &'a Option<String>| value.as_deref().map(Cow::Borrowed);
+        Some(match cmd {
+            StorageCommand::RecordToolEvent(event) => Self::ToolEvent {
+                event: Cow::Borrowed(event),
+            },
+            StorageCommand::RecordLogEvents(events) => Self::LogEvents {
+                events: Cow::Borrowed(events),
+            },
+            StorageCommand::RecordTokenUsage {
+                token_type,
+                count,
+                model,
+                provider,
+            } => Self::TokenUsage {
+                token_type: Cow::Borrowed(token_type),
+                count: *count,
+                model: borrow(model),
+                provider: borrow(provider),
+            },
+            StorageCommand::RecordCost {
+                cost_usd,
+                session_id,
+                model,
+                cumulative,
+            } => Self::Cost {
+                cost_usd: *cost_usd,
+                session_id: borrow(session_id),
+                model: borrow(model),
+                cumulative: *cumulative,
+            },
+            StorageCommand::RecordSessionMetric {
+                name,
+                value,
+                session_id,
+            } => Self::SessionMetric {
+                name: Cow::Borrowed(name),
+                value: *value,
+                session_id: borrow(session_id),
+            },
+            StorageCommand::RecordAnomaly(anomaly) => Self::Anomaly {
+                anomaly: Cow::Borrowed(anomaly),
+            },
+            _ => return None,
+        })
+    }
+
+    fn into_command(self) -> StorageCommand {
+        let own = |value: Option<Cow<str>>| value.map(Cow::into_owned);
+        match self {
+            Self::ToolEvent { event } => StorageCommand::RecordToolEvent(event.into_owned()),
+            Self::LogEvents { events } => StorageCommand::RecordLogEvents(events.into_owned()),
+            Self::TokenUsage {
+                token_type,
+                count,
+                model,
+                provider,
+            } => StorageCommand::RecordTokenUsage {
+                token_type: token_type.into_owned(),
+                count,
+                model: own(model),
+                provider: own(provider),
+            },
+            Self::Cost {
+                cost_usd,
+                session_id,
+                model,
+                cumulative,
+            } => StorageCommand::RecordCost {
+                cost_usd,
+                session_id: own(session_id),
+                model: own(model),
+                cumulative,
+            },
+            Self::SessionMetric {
+                name,
+                value,
+                session_id,
+            } => StorageCommand::RecordSessionMetric {
+                name: name.into_owned(),
+                value,
+                session_id: own(session_id),
+            },
+            Self::Anomaly { anomaly } => StorageCommand::RecordAnomaly(anomaly.into_owned()),
+        }
+    }
+}
+
+/// Rewrite the file once it has this many lines, and mostly ones done with
+const COMPACT_LINES: usize = 1024;
+
+pub(super) struct Journal {
+    path: PathBuf,
+    state: Mutex<State>,
+}
+
+struct State {
+    file: File,
+    /// Lines in the file
+    lines: usize,
+    /// Number of the next entry
+    next: u64,
+    /// Entries not yet committed or dropped, by number, as their lines
+    outstanding: BTreeMap<u64, Vec<u8>>,
+    /// Lines of the entries that failed to commit, kept for the next start
+    failed: Vec<Vec<u8>>,
+}
+
+impl Journal {
+    /// Open the journal at `path`, creating it if needed, and return the writes left in
+    /// it by the last run with their entry numbers. They stay in the file until they
+    /// are committed again.
+    pub fn open(path: &Path) -> Result<(Self, Vec<(u64, StorageCommand)>)> {
+        let mut writes = BTreeMap::new();
+        let mut next = 0;
+        match File::open(path) {
+            Ok(file) => {
+                for (number, line) in BufReader::new(file).lines().enumerate() {
+                    let line = line.with_context(|| format!("reading {}", path.display()))?;
+                    // The last line is cut short if the crash hit while writing it
+                    match serde_json::from_str::<Line>(&line) {
+                        Ok(Line::Done { done }) => {
+                            writes.remove(&done);
+                        }
+                        Ok(Line::Write { seq, entry }) => {
+                            next = next.max(seq + 1);
+                            writes.insert(seq, (entry.into_command(), format!("{}\n", line)));
+                        }
+                        Err(e) => tracing::warn!(
+                            "Skipping line {} of {}: {}",
+                            number + 1,
+                            path.display(),
+                            e
+                        ),
+                    }
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+        }
+
+        let mut replay = Vec::new();
+        let mut outstanding = BTreeMap::new();
+        for (seq, (cmd, line)) in writes {
+            replay.push((seq, cmd));
+            outstanding.insert(seq, line.into_bytes());
+        }
+        // Start over with the writes left, without what was done with or cut short
+        let journal = Self {
+            path: path.to_path_buf(),
+            state: Mutex::new(State {
+                file: rewrite(path, outstanding.values())?,
+                lines: outstanding.len(),
+                next,
+                outstanding,
+                failed: Vec::new(),
+            }),
+        };
+        Ok((journal, replay))
+    }
+
+    /// Append `cmd` if it is a write, before it is queued, returning its entry number
+    /// for [`done`](Self::done)
+    pub fn append(&self, cmd: &StorageCommand) -> Option<u64> {
+        let entry = Entry::of(cmd)?;
+        let mut state = self.state.lock().unwrap();
+        let seq = state.next;
+        let mut line = match serde_json::to_vec(&Line::Write { seq, entry }) {
+            Ok(line) => line,
+            Err(e) => {
+                tracing::warn!("Could not journal a write: {}", e);
+                return None;
+            }
+        };
+        line.push(b'\n');
+
+        if let Err(e) = state.file.write_all(&line) {
+            tracing::warn!("Could not write to {}: {}", self.path.display(), e);
+            return None;
+        }
+        state.next += 1;
+        state.lines += 1;
+        state.outstanding.insert(seq, line);
+        Some(seq)
+    }
+
+    /// Entry `seq` was committed or dropped, or `failed` to commit and is kept for the
+    /// next start
+    pub fn done(&self, seq: u64, failed: bool) {
+        let mut state = self.state.lock().unwrap();
+        let Some(line) = state.outstanding.remove(&seq) else {
+            return;
+        };
+        if failed {
+            state.failed.push(line);
+            return;
+        }
+
+        let kept = state.outstanding.len() + state.failed.len();
+        if kept == 0 {
+            // Nothing to keep, so emptying the file loses nothing even if it is cut short
+            match state.file.set_len(0) {
+                Ok(()) => state.lines = 0,
+                Err(e) => tracing::warn!("Could not empty {}: {}", self.path.display(), e),
+            }
+            return;
+        }
+        if state.lines + 1 >= COMPACT_LINES && state.lines + 1 >= 2 * kept {
+            let State {
+                outstanding,
+                failed,
+                ..
+            } = &*state;
+            match rewrite(&self.path, failed.iter().chain(outstanding.values())) {
+                Ok(file) => {
+                    state.file = file;
+                    state.lines = kept;
+                    return;
+                }
+                Err(e) => tracing::warn!("Could not rewrite {}: {:#}", self.path.display(), e),
+            }
+        }
+
+        let mut line = serde_json::to_vec(&Line::Done { done: seq }).unwrap_or_default();
+        line.push(b'\n');
+        match state.file.write_all(&line) {
+            Ok(()) => state.lines += 1,
+            Err(e) => tracing::warn!("Could not write to {}: {}", self.path.display(), e),
+        }
+    }
+}
+
+/// Replace the file at `path` with `lines` in one rename, so a crash leaves either the
+/// old file or the new one, and open it for appending
+fn rewrite<'a>(path: &Path, lines: impl Iterator<Item = &'a Vec<u8>>) -> Result<File> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+    let mut file = File::create(&temp).with_context(|| format!("creating {}", temp.display()))?;
+    for line in lines {
+        file.write_all(line)
+            .with_context(|| format!("writing {}", temp.display()))?;
+    }
+    file.sync_all()
+        .with_context(|| format!("writing {}", temp.display()))?;
+    std::fs::rename(&temp, path).with_context(|| format!("replacing {}", path.display()))?;
+    OpenOptions::new()
+        .append(true)
+        .open(path)
+        .with_context(|| format!("opening {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cost(cost_usd: f64) -> StorageCommand {
+        StorageCommand::RecordCost {
+            cost_usd,
+            session_id: Some("s".to_string()),
+            model: None,
+            cumulative: true,
+        }
+    }
+
+    fn costs(replay: &[(u64, StorageCommand)]) -> Vec<f64> {
+        replay
+            .iter()
+            .map(|(_, cmd)| match cmd {
+                StorageCommand::RecordCost { cost_usd, .. } => *cost_usd,
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_uncommitted_writes_replayed() {
+        let path =
+            std::env::temp_dir().join(format!("agenttop-journal-unit-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let (journal, replay) = Journal::open(&path).unwrap();
+        assert!(replay.is_empty());
+        let first = journal.append(&cost(1.0)).unwrap();
+        journal.append(&cost(2.0)).unwrap();
+        // Committed only in part, e.g. a crash before the second
+        journal.done(first, false);
+        drop(journal);
+
+        let (journal, replay) = Journal::open(&path).unwrap();
+        assert_eq!(costs(&replay), vec![2.0]);
+
+        // Once nothing is outstanding, only the failed write is kept
+        let third = journal.append(&cost(3.0)).unwrap();
+        journal.done(replay[0].0, true);
+        journal.done(third, false);
+        drop(journal);
+
+        let (journal, replay) = Journal::open(&path).unwrap();
+        assert_eq!(costs(&replay), vec![2.0]);
+        journal.done(replay[0].0, false);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_crash_mid_stream() {
+        let path =
+            std::env::temp_dir().join(format!("agenttop-journal-crash-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        // Steady ingest: a few writes are always outstanding, so the file is never empty
+        let (journal, _) = Journal::open(&path).unwrap();
+        let mut outstanding = std::collections::VecDeque::new();
+        for i in 0..10 * COMPACT_LINES {
+            outstanding.push_back(journal.append(&cost(i as f64)).unwrap());
+            if outstanding.len() > 3 {
+                journal.done(outstanding.pop_front().unwrap(), false);
+            }
+        }
+        let lines = std::fs::read_to_string(&path).unwrap().lines().count();
+        assert!(lines < 2 * COMPACT_LINES, "{lines} lines");
+
+        // A failed write, and committed ones on both sides of uncommitted ones
+        let failed = journal.append(&cost(-1.0)).unwrap();
+        journal.done(failed, true);
+        let before = journal.append(&cost(-2.0)).unwrap();
+        journal.append(&cost(-3.0)).unwrap();
+        let after = journal.append(&cost(-4.0)).unwrap();
+        journal.done(after, false);
+        journal.done(before, false);
+        drop(journal);
+
+        // Each write not committed comes back once, in order
+        let last = 10 * COMPACT_LINES;
+        let (journal, replay) = Journal::open(&path).unwrap();
+        assert_eq!(
+            costs(&replay),
+            vec![
+                (last - 3) as f64,
+                (last - 2) as f64,
+                (last - 1) as f64,
+                -1.0,
+                -3.0
+            ]
+        );
+        // Committed now, so a crash right after replays nothing
+        for (seq, _) in &replay {
+            journal.done(*seq, false);
+        }
+        drop(journal);
+        let (_, replay) = Journal::open(&path).unwrap();
+        assert!(replay.is_empty());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
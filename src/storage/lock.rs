@@ -0,0 +1,225 @@
+//! One receiving agenttop per database: the process that records into a database holds
+//! an OS lock on `<database>.lock` and writes who it is into the file, so a second one
+//! can say which process it is up against instead of failing on DuckDB's file lock.
+//!
+//! The OS releases the lock when the process exits, so a crash never leaves it stale.
+//! The recording process keeps the database open, so other agenttop processes find its
+//! API in the lock file and query through it (see
+//! [`StorageHandle::attach`](super::StorageHandle::attach)).
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+/// Who holds the lock, as written into the lock file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LockOwner {
+    pub pid: u32,
+    pub version: String,
+    pub started_at: DateTime<Utc>,
+    pub headless: bool,
+    /// Where it receives OTLP, once decided
+    #[serde(default)]
+    pub receiver: Option<SocketAddr>,
+    /// Bearer token its `/api/query` takes; the lock file is only readable by its user
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_token: Option<String>,
+}
+
+impl LockOwner {
+    /// "agenttop 0.2.0 (pid 123, headless, receiving on 127.0.0.1:4318, since 09:12)"
+    pub fn describe(&self) -> String {
+        let mut details = vec![format!("pid {}", self.pid)];
+        if self.headless {
+            details.push("headless".to_string());
+        }
+        if let Some(receiver) = self.receiver {
+            details.push(format!("receiving on {}", receiver));
+        }
+        let started = self.started_at.with_timezone(&Local);
+        let format = if started.date_naive() == Local::now().date_naive() {
+            "%H:%M"
+        } else {
+            "%Y-%m-%d %H:%M"
+        };
+        details.push(format!("since {}", started.format(format)));
+        format!("agenttop {} ({})", self.version, details.join(", "))
+    }
+}
+
+/// The error when another process holds the lock
+#[derive(Debug)]
+pub struct DatabaseLocked {
+    pub db_path: PathBuf,
+    /// None if the lock file could not be read (an older agenttop, or Windows)
+    pub owner: Option<LockOwner>,
+}
+
+impl std::fmt::Display for DatabaseLocked {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let holder = self
+            .owner
+            .as_ref()
+            .map_or_else(|| "another agenttop".to_string(), LockOwner::describe);
+        write!(
+            f,
+            "{} is in use by {}.\nRun `agenttop view` to watch it, or pass --force-read-only.",
+            self.db_path.display(),
+            holder
+        )
+    }
+}
+
+impl std::error::Error for DatabaseLocked {}
+
+/// Held for as long as this process records into the database
+#[derive(Debug)]
+pub struct InstanceLock {
+    file: File,
+    path: PathBuf,
+    owner: LockOwner,
+}
+
+impl InstanceLock {
+    /// Lock the database at `db_path` for this process, or fail naming the process that
+    /// holds it
+    pub fn acquire(db_path: &Path, headless: bool) -> Result<Self> {
+        let path = lock_path(db_path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut options = OpenOptions::new();
+        options.read(true).write(true).create(true).truncate(false);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options
+            .open(&path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                return Err(DatabaseLocked {
+                    db_path: db_path.to_path_buf(),
+                    owner: read_owner(&mut file),
+                }
+                .into());
+            }
+            Err(TryLockError::Error(e)) => {
+                return Err(e).with_context(|| format!("Failed to lock {}", path.display()));
+            }
+        }
+        // Created by an older agenttop, before the file held a token
+        #[cfg(unix)]
+        std::fs::set_permissions(&path, std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+
+        let mut lock = Self {
+            file,
+            path,
+            owner: LockOwner {
+                pid: std::process::id(),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                started_at: Utc::now(),
+                headless,
+                receiver: None,
+                api_token: None,
+            },
+        };
+        lock.write_owner()?;
+        Ok(lock)
+    }
+
+    /// Record where this process receives OTLP, and the token its `/api/query` takes
+    pub fn set_receiver(&mut self, receiver: SocketAddr, api_token: Option<&str>) -> Result<()> {
+        self.owner.receiver = Some(receiver);
+        self.owner.api_token = api_token.map(str::to_string);
+        self.write_owner()
+    }
+
+    fn write_owner(&mut self) -> Result<()> {
+        let json = serde_json::to_string(&self.owner)?;
+        self.file.set_len(0)?;
+        self.file.rewind()?;
+        self.file
+            .write_all(json.as_bytes())
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+}
+
+/// The error [`InstanceLock::acquire`] would fail with: None while no other agenttop
+/// records into the database at `db_path`
+pub fn holder(db_path: &Path) -> Option<DatabaseLocked> {
+    let mut file = File::open(lock_path(db_path)).ok()?;
+    match file.try_lock_shared() {
+        Ok(()) => None,
+        Err(TryLockError::WouldBlock) => Some(DatabaseLocked {
+            db_path: db_path.to_path_buf(),
+            owner: read_owner(&mut file),
+        }),
+        Err(TryLockError::Error(_)) => None,
+    }
+}
+
+/// `metrics.duckdb` -> `metrics.duckdb.lock`
+fn lock_path(db_path: &Path) -> PathBuf {
+    let mut name = db_path.as_os_str().to_owned();
+    name.push(".lock");
+    PathBuf::from(name)
+}
+
+fn read_owner(file: &mut File) -> Option<LockOwner> {
+    let mut content = String::new();
+    file.rewind().ok()?;
+    file.read_to_string(&mut content).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recorded_owner(db: &Path) -> Option<LockOwner> {
+        read_owner(&mut File::open(lock_path(db)).ok()?)
+    }
+
+    #[test]
+    fn test_second_instance_is_refused() {
+        let dir = std::env::temp_dir().join(format!("agenttop-lock-{}", std::process::id()));
+        let db = dir.join("metrics.duckdb");
+
+        assert!(holder(&db).is_none());
+        let mut first = InstanceLock::acquire(&db, true).unwrap();
+        first
+            .set_receiver("127.0.0.1:4318".parse().unwrap(), Some("s3cret"))
+            .unwrap();
+        assert_eq!(recorded_owner(&db), Some(first.owner.clone()));
+        let owner = holder(&db).unwrap().owner.unwrap();
+        assert_eq!(owner.api_token.as_deref(), Some("s3cret"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(lock_path(&db))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let err = InstanceLock::acquire(&db, false).unwrap_err();
+        assert!(err.is::<DatabaseLocked>());
+        let err = err.to_string();
+        assert!(err.contains(&format!("pid {}", std::process::id())));
+        assert!(err.contains("headless, receiving on 127.0.0.1:4318"));
+        assert!(err.contains("--force-read-only"));
+
+        drop(first);
+        assert!(holder(&db).is_none());
+        let second = InstanceLock::acquire(&db, false).unwrap();
+        assert!(!recorded_owner(&db).unwrap().headless);
+        drop(second);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,17 +1,127 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
-use duckdb::{Connection, params};
-use once_cell::sync::Lazy;
+use duckdb::types::{FromSql, FromSqlError, FromSqlResult, TimeUnit, ToSql, ToSqlOutput};
+use duckdb::types::{Value, ValueRef};
+use duckdb::{Connection, ParamsFromIter, params, params_from_iter};
+use once_cell::sync::{Lazy, OnceCell};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
+use std::collections::VecDeque;
 use std::path::PathBuf;
-use std::sync::mpsc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, mpsc};
 use std::thread;
 
+use crate::anomaly::{Anomaly, AnomalyKind};
+use crate::budget::BudgetScope;
+use crate::cache::LruCache;
+use crate::efficiency::Efficiency;
+use crate::error::{self, AgenttopError};
+use crate::export::TableFormat;
 use crate::providers::{
     PROVIDER_REGISTRY, TOKEN_CACHE_READ, TOKEN_CACHE_WRITE, TOKEN_INPUT, TOKEN_OUTPUT,
 };
+use crate::self_metrics::SELF_METRICS;
+use journal::Journal;
+use watchdog::{Scope, Stamp, WATCHDOG};
+
+mod activity;
+mod anomalies;
+mod budgets;
+mod comparison;
+mod context;
+mod efficiency;
+mod errors;
+pub use errors::TOOL_ERROR;
+mod files;
+mod journal;
+pub mod lock;
+mod productivity;
+mod quota;
+pub mod remote;
+mod sequences;
+mod sessions;
+mod watchdog;
+
+/// How often the configured retention is applied
+const RETENTION_INTERVAL: std::time::Duration = std::time::Duration::from_secs(3600);
+
+/// A database attached read-only is closed after this long without a command, so an
+/// agenttop can start recording into it in between
+const ATTACHED_IDLE: std::time::Duration = std::time::Duration::from_millis(200);
+
+/// How long to keep retrying while another process has the database file open
+const LOCK_WAIT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Prepared statements kept per connection: one per query shape the dashboard polls
+/// (each time filter variant counts separately), with room to spare
+const STATEMENT_CACHE_CAPACITY: usize = 64;
+
+/// Shortest and longest pause before reopening the database after the actor panicked
+const RESTART_BACKOFF_MIN: std::time::Duration = std::time::Duration::from_secs(1);
+const RESTART_BACKOFF_MAX: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Longest a query may run when the config file sets no `[storage] query_timeout_secs`
+pub const DEFAULT_QUERY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Version of the database layout, recorded in the `agenttop_meta` table. Bump it when a
+/// change leaves databases unusable for older agenttop versions, and set
+/// [`SCHEMA_SINCE`] to the release making it.
+const SCHEMA_VERSION: i64 = 1;
+
+/// First agenttop release writing [`SCHEMA_VERSION`]
+const SCHEMA_SINCE: &str = "0.2.0";
+
+/// Session metrics the agent reports as a running total per session (Claude Code's
+/// `active_time.total`), rather than as increments
+pub const CUMULATIVE_SESSION_METRICS: &[&str] = &["active_time"];
+
+/// Session metric names lines of code are recorded under (Claude Code's
+/// `lines_of_code.count` is stored as "lines_of_code")
+pub const LINES_OF_CODE_METRICS: &[&str] = &["lines_of_code", "loc"];
+
+/// Session metric names commits are recorded under (Claude Code's `commit.count` is
+/// stored as "commit")
+pub const COMMIT_METRICS: &[&str] = &["commit", "commits", "commit_count"];
+
+/// Token records of the same type, model and provider arriving in the same window of
+/// this length (counted from the Unix epoch) are added to the window's first row
+/// instead of each getting their own. The row keeps the time of its first record, so a
+/// query from the start of a window counts exactly what arrived since.
+const TOKEN_COALESCE_WINDOW: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Log events without a `session.id` more than this far apart belong to different
+/// sessions (see [`Session`])
+pub const SESSION_IDLE_GAP: std::time::Duration = std::time::Duration::from_secs(30 * 60);
+
+/// A session's events more than this far apart are separated by idle time (see
+/// [`Session::active_secs`])
+pub const ACTIVE_GAP: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+
+/// SQL condition on an api_error's attributes: the request was rate limited
+const RATE_LIMITED: &str = "(json_extract_string(attributes, '$.status_code') = '429' \
+     OR regexp_matches(lower(json_extract_string(attributes, '$.error')), 'rate.?limit'))";
+
+/// Write batches waiting for the storage actor before the oldest is dropped
+const INGEST_QUEUE_CAPACITY: usize = 4096;
+
+/// Rows per batch sent by [`StorageHandle::stream_token_usage`]
+const STREAM_BATCH_ROWS: usize = 1000;
+
+/// Batches read ahead of the consumer of a stream
+const STREAM_BATCHES_AHEAD: usize = 4;
+
+/// Metric tables; each has an `id` and a `timestamp` column
+pub const TABLES: &[&str] = &[
+    "tool_events",
+    "log_events",
+    "token_usage",
+    "cost_usage",
+    "session_metrics",
+    "anomalies",
+];
 
 /// Regex to parse MCP tool names in format: mcp__<server>__<tool> or mcp__plugin_<plugin>_<server>__<tool>
 static MCP_TOOL_REGEX: Lazy<Regex> =
@@ -54,7 +164,7 @@ pub fn get_tool_display_name(name: &str) -> String {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
 pub struct ToolMetrics {
     pub tool_name: String,
     pub call_count: u64,
@@ -107,22 +217,80 @@ pub struct TokenMetrics {
     pub cache_read_tokens: u64,
     pub cache_creation_tokens: u64,
     pub total_cost_usd: f64,
+    /// The tokens priced at list prices (see [`crate::pricing`]), for agents that report
+    /// no cost; tokens of models without known pricing count nothing
+    #[serde(default)]
+    pub estimated_cost_usd: f64,
+}
+
+/// Prompt tokens of one model in one hour, by how the prompt cache served them (see
+/// [`StorageHandle::get_cache_usage`])
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CacheUsage {
+    /// Start of the hour
+    pub hour: DateTime<Utc>,
+    /// None for tokens recorded without a model
+    pub model: Option<String>,
+    pub input_tokens: u64,
+    pub cache_read_tokens: u64,
+    pub cache_creation_tokens: u64,
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct SessionMetrics {
     pub lines_of_code: i64,
     pub commit_count: u64,
+    /// As the agent reports it
     pub active_time_secs: u64,
+    /// Time in the sessions' active segments within the range, found from event density
+    /// (see [`ACTIVE_GAP`])
+    #[serde(default)]
+    pub detected_active_secs: u64,
+    /// Time from each session's first to last event within the range
+    #[serde(default)]
+    pub session_secs: u64,
+}
+
+impl SessionMetrics {
+    /// Share of the sessions' time that was active; None without sessions
+    pub fn utilization(&self) -> Option<f64> {
+        (self.session_secs > 0)
+            .then(|| (self.detected_active_secs as f64 / self.session_secs as f64).min(1.0))
+    }
 }
 
 /// API request metrics aggregated from api_request events
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ApiMetrics {
     pub total_calls: u64,
     pub total_errors: u64,
     pub avg_latency_ms: f64,
     pub models: HashMap<String, u64>,
+    /// API error count per model (errors without a model attribute are only in total_errors)
+    pub errors_by_model: HashMap<String, u64>,
+    /// Average latency per model
+    #[serde(default)]
+    pub latency_by_model: HashMap<String, f64>,
+}
+
+/// Highest row id and row count of each table, in [`TABLES`] order, and how many
+/// token_usage rows later records were added to. Rows are only ever appended or deleted
+/// otherwise, so an unchanged version means unchanged data.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct DataVersion {
+    pub max_ids: [i64; TABLES.len()],
+    pub row_counts: [u64; TABLES.len()],
+    /// Counted by the connection that recorded them, which is the only one that adds
+    /// to its rows
+    #[serde(default)]
+    pub token_updates: u64,
+}
+
+/// A tool that has started (pre-tool / accepted decision event) but not yet reported a result
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InFlightTool {
+    pub tool_name: String,
+    pub started_at: DateTime<Utc>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -134,491 +302,3922 @@ pub struct ToolEvent {
     pub error: Option<String>,
 }
 
-/// Raw log event that stores all OTLP log records without filtering
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct LogEvent {
+/// Kind of mark shown on the session timeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TimelineKind {
+    Tool,
+    Api,
+    Error,
+}
+
+/// A tool call, API request or error on the session timeline
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimelineEvent {
+    /// When the event was reported (end of the call for tools and API requests)
     pub timestamp: DateTime<Utc>,
-    pub event_name: Option<String>,
-    pub body: Option<String>,
-    pub attributes: HashMap<String, String>,
+    pub kind: TimelineKind,
+    /// Tool name or model
+    pub name: String,
+    pub duration_ms: u64,
 }
 
-// Commands that can be sent to the storage actor
-#[allow(dead_code)]
-enum StorageCommand {
-    RecordToolEvent(ToolEvent),
-    RecordLogEvents(Vec<LogEvent>),
-    RecordTokenUsage {
-        token_type: String,
-        count: u64,
-    },
-    RecordCost(f64),
-    RecordSessionMetric {
-        name: String,
-        value: i64,
-    },
-    GetToolMetrics {
-        since: Option<DateTime<Utc>>,
-        tx: mpsc::Sender<Result<Vec<ToolMetrics>>>,
-    },
-    GetTokenMetrics {
-        since: Option<DateTime<Utc>>,
-        tx: mpsc::Sender<Result<TokenMetrics>>,
-    },
-    GetLastToolError {
-        tool_name: String,
-        tx: mpsc::Sender<Result<Option<String>>>,
-    },
-    GetSessionMetrics {
-        since: Option<DateTime<Utc>>,
-        tx: mpsc::Sender<Result<SessionMetrics>>,
-    },
-    GetApiMetrics {
-        since: Option<DateTime<Utc>>,
-        tx: mpsc::Sender<Result<ApiMetrics>>,
-    },
-    Shutdown,
+/// Events of the most recent session, oldest first
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SessionTimeline {
+    /// None when events carry no session.id (timeline then covers the last hour)
+    pub session_id: Option<String>,
+    pub events: Vec<TimelineEvent>,
 }
 
-// Thread-safe handle to the storage actor
-#[derive(Clone)]
-pub struct StorageHandle {
-    sender: mpsc::Sender<StorageCommand>,
+/// Tool calls and cost within one clock hour (UTC)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HourlyActivity {
+    /// Start of the hour
+    pub hour: DateTime<Utc>,
+    pub tool_calls: u64,
+    pub cost_usd: f64,
 }
 
-impl StorageHandle {
-    pub fn new() -> Result<Self> {
-        Self::spawn_actor(Storage::new()?)
-    }
+/// Health of one MCP server, aggregated over all of its tools
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct McpServerHealth {
+    /// Server name from `mcp__<server>__<tool>`, or the tool name when it has no server part
+    pub server_name: String,
+    pub tool_count: usize,
+    pub call_count: u64,
+    pub error_count: u64,
+    /// 95th percentile call duration (nearest rank)
+    pub p95_duration_ms: u64,
+    pub last_seen: Option<DateTime<Utc>>,
+}
 
-    /// Create an in-memory storage handle for testing.
-    /// The database is isolated and won't persist or affect other tests.
-    #[allow(dead_code)]
-    pub fn new_in_memory() -> Result<Self> {
-        Self::spawn_actor(Storage::new_in_memory()?)
+impl McpServerHealth {
+    /// Error rate as a percentage (0-100)
+    pub fn error_rate(&self) -> f64 {
+        if self.call_count == 0 {
+            return 0.0;
+        }
+        self.error_count as f64 / self.call_count as f64 * 100.0
     }
+}
 
-    fn spawn_actor(storage: Storage) -> Result<Self> {
-        let (sender, receiver) = mpsc::channel();
+/// API usage and cost for one model, from api_request events
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelCost {
+    pub model: String,
+    pub calls: u64,
+    /// Sum of the events' cost_usd attribute (0 for agents that don't report it)
+    pub cost_usd: f64,
+}
 
-        // Spawn the storage actor thread
-        thread::spawn(move || {
-            if let Err(e) = run_storage_actor(storage, receiver) {
-                tracing::error!("Storage actor error: {}", e);
-            }
-        });
+/// API calls and cost for one model and project within 15 minutes, from api_request
+/// events (fine enough to regroup by local day or week in any time zone)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CostBreakdown {
+    /// Start of the 15 minutes
+    pub bucket: DateTime<Utc>,
+    pub model: String,
+    /// The events' `project` attribute (see [`crate::otlp::parser::PROJECT_ATTRIBUTE`])
+    pub project: Option<String>,
+    pub calls: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cost_usd: f64,
+}
 
-        Ok(Self { sender })
-    }
+/// One api_request's cost with the session and working directory it came from, for
+/// attributing spend to the commits made meanwhile (see [`crate::git`])
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApiSpend {
+    pub timestamp: DateTime<Utc>,
+    pub session_id: Option<String>,
+    /// The events' `cwd` attribute (see [`crate::otlp::parser::CWD_ATTRIBUTE`])
+    pub cwd: Option<String>,
+    pub cost_usd: f64,
+}
 
-    /// Record a tool event to the legacy tool_events table.
-    /// Note: This method is kept for backward compatibility. New code should use
-    /// record_log_events() which stores all OTLP logs without filtering.
-    #[allow(dead_code)]
-    pub fn record_tool_event(&self, event: ToolEvent) {
-        let _ = self.sender.send(StorageCommand::RecordToolEvent(event));
-    }
+/// One row of the token_usage table
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenUsageRow {
+    pub timestamp: DateTime<Utc>,
+    pub token_type: String,
+    pub count: u64,
+    /// None for rows recorded before the model was stored, or without a model attribute
+    pub model: Option<String>,
+    /// Id of the provider that sent the metric (e.g. "claude_code")
+    pub provider: Option<String>,
+}
 
-    pub fn record_log_events(&self, events: Vec<LogEvent>) {
-        let _ = self.sender.send(StorageCommand::RecordLogEvents(events));
+/// Number of failed calls for a tool with the same error message
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolErrorCount {
+    pub tool_name: String,
+    pub error: Option<String>,
+    pub count: u64,
+}
+
+/// Calls and failures of one tool within a session
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCallCount {
+    pub tool_name: String,
+    pub calls: u64,
+    pub errors: u64,
+}
+
+/// What one session (`session.id`) did, for `agenttop report --session`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionSummary {
+    pub session_id: String,
+    /// First and last event of the session
+    pub started_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+    pub api_calls: u64,
+    pub api_errors: u64,
+    /// From the api_request events' attributes
+    pub cost_usd: f64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cache_read_tokens: u64,
+    pub cache_creation_tokens: u64,
+    /// Most expensive first
+    pub models: Vec<ModelCost>,
+    /// Most calls first
+    pub tools: Vec<ToolCallCount>,
+    /// Most frequent first
+    pub errors: Vec<ToolErrorCount>,
+    /// Lines of code and commits carry no session id; these are the metrics recorded
+    /// between the session's first event and a minute after its last
+    pub lines_of_code: i64,
+    pub commit_count: u64,
+    /// Time in the session's active segments (see [`ACTIVE_GAP`])
+    #[serde(default)]
+    pub active_secs: u64,
+}
+
+/// One row of the sessions table: a session's span and totals, kept up to date as its
+/// events arrive (see [`SESSION_IDLE_GAP`])
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    /// The events' `session.id`, or `<provider>@<first event>` for an inferred session
+    pub session_id: String,
+    /// Found by inactivity gaps because the events carry no `session.id`
+    pub inferred: bool,
+    /// Id of the provider whose events make up the session (e.g. "claude_code")
+    pub provider: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+    pub events: u64,
+    pub api_calls: u64,
+    pub api_errors: u64,
+    pub tool_calls: u64,
+    /// From the api_request events' attributes
+    pub cost_usd: f64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// Efficiency score as of the session's last scored event; None until it has been
+    /// scored (inferred sessions never are)
+    #[serde(default)]
+    pub efficiency: Option<Efficiency>,
+    /// Time in the session's active segments (see [`ACTIVE_GAP`])
+    #[serde(default)]
+    pub active_secs: u64,
+}
+
+impl Session {
+    /// Share of the session's length that was active; None for a single instant
+    pub fn utilization(&self) -> Option<f64> {
+        let secs = (self.ended_at - self.started_at).num_milliseconds() as f64 / 1000.0;
+        (secs > 0.0).then(|| (self.active_secs as f64 / secs).min(1.0))
     }
+}
 
-    pub fn record_token_usage(&self, token_type: &str, count: u64) {
-        let _ = self.sender.send(StorageCommand::RecordTokenUsage {
-            token_type: token_type.to_string(),
-            count,
-        });
+/// A session's code output next to its spend, for productivity ratios (see
+/// [`crate::productivity`])
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionProductivity {
+    pub session_id: String,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+    /// From the api_request events' attributes
+    pub cost_usd: f64,
+    /// Net lines added
+    pub lines_of_code: i64,
+    pub commits: u64,
+    /// The agent's reported active time (0 when it reports none)
+    pub active_time_secs: u64,
+}
+
+/// One provider's totals over a range, for comparing agents side by side (see
+/// `agenttop compare` and the providers view)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProviderComparison {
+    /// Provider id (e.g. "claude_code")
+    pub provider: String,
+    /// Sessions of the provider overlapping the range
+    pub sessions: u64,
+    pub api_calls: u64,
+    pub api_errors: u64,
+    /// From the api_request events' attributes
+    pub cost_usd: f64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cache_read_tokens: u64,
+    pub avg_latency_ms: f64,
+    pub p95_latency_ms: u64,
+    pub tool_calls: u64,
+    pub tool_errors: u64,
+}
+
+impl ProviderComparison {
+    pub fn tokens(&self) -> u64 {
+        self.input_tokens + self.output_tokens
     }
 
-    pub fn record_cost(&self, cost_usd: f64) {
-        let _ = self.sender.send(StorageCommand::RecordCost(cost_usd));
+    /// Share of API requests that didn't fail; None without any
+    pub fn api_success_rate(&self) -> Option<f64> {
+        let attempts = self.api_calls + self.api_errors;
+        (attempts > 0).then(|| self.api_calls as f64 / attempts as f64)
     }
 
-    pub fn record_session_metric(&self, name: &str, value: i64) {
-        let _ = self.sender.send(StorageCommand::RecordSessionMetric {
-            name: name.to_string(),
-            value,
-        });
+    /// Share of tool calls that failed; None without any
+    pub fn tool_error_rate(&self) -> Option<f64> {
+        (self.tool_calls > 0).then(|| self.tool_errors as f64 / self.tool_calls as f64)
     }
 
-    pub fn get_tool_metrics(&self, since: Option<DateTime<Utc>>) -> Result<Vec<ToolMetrics>> {
-        let (tx, rx) = mpsc::channel();
-        self.sender
-            .send(StorageCommand::GetToolMetrics { since, tx })?;
-        rx.recv()?
+    /// None without sessions
+    pub fn cost_per_session(&self) -> Option<f64> {
+        (self.sessions > 0).then(|| self.cost_usd / self.sessions as f64)
     }
 
-    pub fn get_token_metrics(&self, since: Option<DateTime<Utc>>) -> Result<TokenMetrics> {
-        let (tx, rx) = mpsc::channel();
-        self.sender
-            .send(StorageCommand::GetTokenMetrics { since, tx })?;
-        rx.recv()?
+    /// Dollars per million tokens in and out; None without tokens
+    pub fn cost_per_million_tokens(&self) -> Option<f64> {
+        (self.tokens() > 0).then(|| self.cost_usd / self.tokens() as f64 * 1e6)
     }
+}
 
-    pub fn get_last_tool_error(&self, tool_name: &str) -> Result<Option<String>> {
-        let (tx, rx) = mpsc::channel();
-        self.sender.send(StorageCommand::GetLastToolError {
-            tool_name: tool_name.to_string(),
-            tx,
-        })?;
-        rx.recv()?
+/// Errors of one category (see [`crate::taxonomy`]) in a range
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErrorCategoryCount {
+    pub category: String,
+    pub errors: u64,
+    /// Failed tool calls
+    pub tool_errors: u64,
+    /// api_error events
+    pub api_errors: u64,
+    /// Sessions with an error of the category (events without a `session.id` count for
+    /// none)
+    pub sessions: u64,
+    pub last_at: DateTime<Utc>,
+}
+
+/// Errors of one category from one tool (tool errors) or model (API errors)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErrorBreakdown {
+    pub category: String,
+    /// "tool" or "api"
+    pub kind: String,
+    /// Tool name or model; "unknown" when the event names none
+    pub source: String,
+    pub errors: u64,
+    pub last_at: DateTime<Utc>,
+    /// Message of the latest error, if it had one
+    pub last_message: Option<String>,
+}
+
+/// How much of the model's context window one api_request filled (see
+/// [`crate::context`])
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ContextSample {
+    pub timestamp: DateTime<Utc>,
+    pub session_id: Option<String>,
+    pub model: Option<String>,
+    /// Input tokens, cached or not: the size of the prompt
+    pub context_tokens: u64,
+    /// The model's context limit, when the event's `context_window` attribute gives it
+    pub context_window: Option<u64>,
+}
+
+/// An api_request's usage, or a rate-limited api_error (see [`crate::quota`])
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsagePoint {
+    pub timestamp: DateTime<Utc>,
+    /// Input and output tokens
+    pub tokens: u64,
+    pub cost_usd: f64,
+    /// A rate-limited api_error rather than a request
+    pub rate_limited: bool,
+}
+
+/// One row of the budget_periods table: a budget's spend in one of its periods, kept by
+/// the budget monitor (see [`crate::budget`])
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BudgetUsage {
+    /// [`Budget::key`](crate::budget::Budget::key), e.g. "daily/global"
+    pub budget: String,
+    pub period_start: DateTime<Utc>,
+    pub period_end: DateTime<Utc>,
+    pub limit_usd: f64,
+    pub spent_usd: f64,
+    /// The period is over and `spent_usd` is final
+    pub closed: bool,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Result of an ad-hoc query from the SQL console; all values rendered as text
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct QueryResult {
+    pub columns: Vec<String>,
+    /// None for SQL NULL
+    pub rows: Vec<Vec<Option<String>>>,
+    /// More rows matched than the row limit allowed
+    pub truncated: bool,
+}
+
+/// A stored log event as shown in the event stream
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogRecord {
+    /// Row id; increases with insertion order
+    pub id: i64,
+    pub timestamp: DateTime<Utc>,
+    pub event_name: Option<String>,
+    pub attributes: HashMap<String, String>,
+}
+
+/// A user prompt sent to the agent
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PromptEntry {
+    pub timestamp: DateTime<Utc>,
+    /// Prompt text; only present when the agent is configured to log prompts
+    pub text: Option<String>,
+    /// Prompt length in characters
+    pub length: Option<u64>,
+}
+
+/// One instruction and the work it caused: a user prompt and the API requests and tool
+/// calls after it in the same session, up to the next prompt
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Turn {
+    pub session_id: Option<String>,
+    /// When the prompt was sent
+    pub started_at: DateTime<Utc>,
+    /// Last API request or tool result of the turn (the prompt itself if none yet)
+    pub ended_at: DateTime<Utc>,
+    pub prompt_length: Option<u64>,
+    pub api_calls: u64,
+    pub tool_calls: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cost_usd: f64,
+}
+
+impl Turn {
+    /// Time from the prompt to the turn's last event
+    pub fn latency_ms(&self) -> u64 {
+        (self.ended_at - self.started_at).num_milliseconds().max(0) as u64
     }
 
-    pub fn get_session_metrics(&self, since: Option<DateTime<Utc>>) -> Result<SessionMetrics> {
-        let (tx, rx) = mpsc::channel();
-        self.sender
-            .send(StorageCommand::GetSessionMetrics { since, tx })?;
-        rx.recv()?
+    pub fn tokens(&self) -> u64 {
+        self.input_tokens + self.output_tokens
     }
+}
 
-    pub fn get_api_metrics(&self, since: Option<DateTime<Utc>>) -> Result<ApiMetrics> {
-        let (tx, rx) = mpsc::channel();
-        self.sender
-            .send(StorageCommand::GetApiMetrics { since, tx })?;
-        rx.recv()?
+/// Tool calls on one file or directory (see [`StorageHandle::get_file_activity`])
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileActivity {
+    /// Normalized path of the file or directory
+    pub path: String,
+    /// Calls of [`crate::efficiency::EDIT_TOOLS`]
+    pub edits: u64,
+    /// All tool calls, edits included
+    pub calls: u64,
+    /// Sessions with a call on it
+    pub sessions: u64,
+    pub last_at: DateTime<Utc>,
+}
+
+/// A run of consecutive tool calls within a session, e.g. Grep → Read → Edit, and how
+/// often it occurred (see [`StorageHandle::get_tool_sequences`])
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolSequence {
+    pub tools: Vec<String>,
+    /// Occurrences, overlapping ones included
+    pub count: u64,
+    /// Sessions it occurred in
+    pub sessions: u64,
+    /// Occurrences starting right where an identical one ended: the agent going round
+    /// the same loop
+    pub repeats: u64,
+    /// Average time from the start of the first call to the end of the last
+    pub avg_cycle_ms: f64,
+}
+
+/// Raw log event that stores all OTLP log records without filtering
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct LogEvent {
+    pub timestamp: DateTime<Utc>,
+    pub event_name: Option<String>,
+    pub body: Option<String>,
+    pub attributes: HashMap<String, String>,
+}
+
+/// Database location set from the config file, if any
+static DB_PATH: OnceCell<PathBuf> = OnceCell::new();
+
+/// Use `path` as the database location for the rest of the process
+pub fn set_db_path(path: PathBuf) {
+    let _ = DB_PATH.set(path);
+}
+
+/// Query timeout set from the config file, if any (None inside: no timeout)
+static QUERY_TIMEOUT: OnceCell<Option<std::time::Duration>> = OnceCell::new();
+
+/// Interrupt queries running longer than `timeout` for the rest of the process
+pub fn set_query_timeout(timeout: Option<std::time::Duration>) {
+    let _ = QUERY_TIMEOUT.set(timeout);
+}
+
+fn query_timeout() -> Option<std::time::Duration> {
+    QUERY_TIMEOUT
+        .get()
+        .copied()
+        .unwrap_or(Some(DEFAULT_QUERY_TIMEOUT))
+}
+
+/// Database location: the configured path, or the default
+pub fn db_path() -> error::Result<PathBuf> {
+    match DB_PATH.get() {
+        Some(path) => Ok(path.clone()),
+        None => default_db_path(),
     }
 }
 
-fn run_storage_actor(storage: Storage, receiver: mpsc::Receiver<StorageCommand>) -> Result<()> {
-    for cmd in receiver {
-        match cmd {
-            StorageCommand::RecordToolEvent(event) => {
-                if let Err(e) = storage.record_tool_event(&event) {
-                    tracing::error!("Failed to record tool event: {}", e);
-                }
-            }
-            StorageCommand::RecordLogEvents(events) => {
-                if let Err(e) = storage.insert_log_events(&events) {
-                    tracing::error!("Failed to record log events: {}", e);
-                }
-            }
-            StorageCommand::RecordTokenUsage { token_type, count } => {
-                if let Err(e) = storage.record_token_usage(&token_type, count) {
-                    tracing::error!("Failed to record token usage: {}", e);
-                }
-            }
-            StorageCommand::RecordCost(cost) => {
-                if let Err(e) = storage.record_cost(cost) {
-                    tracing::error!("Failed to record cost: {}", e);
-                }
-            }
-            StorageCommand::RecordSessionMetric { name, value } => {
-                if let Err(e) = storage.record_session_metric(&name, value) {
-                    tracing::error!("Failed to record session metric: {}", e);
+/// Journal of the database at `db_path` (see [`StorageHandle::with_journal`]):
+/// `metrics.journal.jsonl` next to `metrics.duckdb`
+pub fn journal_path(db_path: &std::path::Path) -> PathBuf {
+    db_path.with_extension("journal.jsonl")
+}
+
+/// Default database location: `metrics.duckdb` in the data directory
+pub fn default_db_path() -> error::Result<PathBuf> {
+    let dir = crate::config::data_dir().map_err(AgenttopError::Config)?;
+    Ok(dir.join("metrics.duckdb"))
+}
+
+/// Whether `error` means the storage actor stopped answering (see
+/// [`AgenttopError::is_unavailable`]) rather than the query itself failing
+pub fn is_unavailable(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<AgenttopError>()
+        .is_some_and(AgenttopError::is_unavailable)
+}
+
+/// Whether `error` means a query timed out or was superseded (see
+/// [`AgenttopError::is_interrupted`])
+pub fn is_interrupted(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<AgenttopError>()
+        .is_some_and(AgenttopError::is_interrupted)
+}
+
+/// Whether a query failed because [`WATCHDOG`] interrupted it
+fn is_interrupt(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .any(|cause| cause.to_string().contains("INTERRUPT"))
+}
+
+/// Delete rows older than `retention_days` once an hour on a background thread
+pub fn spawn_retention(storage: StorageHandle, retention_days: u32) {
+    std::thread::spawn(move || {
+        loop {
+            let before = Utc::now() - chrono::Duration::days(retention_days as i64);
+            match storage.prune(before, false) {
+                Ok(counts) => {
+                    let deleted: u64 = counts.iter().map(|(_, count)| count).sum();
+                    if deleted > 0 {
+                        tracing::info!(
+                            "Deleted {} rows older than {} days",
+                            deleted,
+                            retention_days
+                        );
+                    }
                 }
+                Err(e) => tracing::warn!("Could not apply retention: {:#}", e),
             }
-            StorageCommand::GetToolMetrics { since, tx } => {
-                let _ = tx.send(storage.get_tool_metrics(since));
-            }
-            StorageCommand::GetTokenMetrics { since, tx } => {
-                let _ = tx.send(storage.get_token_metrics(since));
-            }
-            StorageCommand::GetLastToolError { tool_name, tx } => {
-                let _ = tx.send(storage.get_last_tool_error(&tool_name));
-            }
-            StorageCommand::GetSessionMetrics { since, tx } => {
-                let _ = tx.send(storage.get_session_metrics(since));
-            }
-            StorageCommand::GetApiMetrics { since, tx } => {
-                let _ = tx.send(storage.get_api_metrics(since));
-            }
-            StorageCommand::Shutdown => break,
+            std::thread::sleep(RETENTION_INTERVAL);
         }
-    }
+    });
+}
 
-    Ok(())
+/// Build a SQL filter on the `timestamp` column for the window `[since, until)`, with
+/// the bounds as numbered parameters (bind them with [`time_params`]) so the text,
+/// and with it the cached prepared statement, stays the same as the window moves.
+/// `keyword` is the leading keyword ("WHERE" or "AND"); unbounded windows yield "".
+fn time_clause(
+    keyword: &str,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+) -> String {
+    let mut bounds = Vec::new();
+    if since.is_some() {
+        bounds.push(format!("timestamp >= ?{}", bounds.len() + 1));
+    }
+    if until.is_some() {
+        bounds.push(format!("timestamp < ?{}", bounds.len() + 1));
+    }
+    if bounds.is_empty() {
+        String::new()
+    } else {
+        format!("{} {}", keyword, bounds.join(" AND "))
+    }
 }
 
-struct Storage {
-    conn: Connection,
+/// List-price estimate of `count` tokens of `token_type` (as the agent named it) used
+/// by `model`; None without a model or its pricing
+fn estimate_token_cost(token_type: &str, count: u64, model: Option<&str>) -> Option<f64> {
+    let token_type = PROVIDER_REGISTRY.normalize_token_type(token_type)?;
+    crate::pricing::estimate_cost_usd(model?, token_type, count)
 }
 
-impl Storage {
-    /// Create storage with the default database path
-    fn new() -> Result<Self> {
-        let db_path = Self::db_path()?;
+/// Parameters for a [`time_clause`] built from the same bounds
+fn time_params(
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+) -> ParamsFromIter<Vec<SqlTimestamp>> {
+    params_from_iter(since.into_iter().chain(until).map(SqlTimestamp).collect())
+}
 
-        // Ensure parent directory exists
-        if let Some(parent) = db_path.parent() {
-            std::fs::create_dir_all(parent)?;
-        }
+/// A `TIMESTAMP` (UTC) bound and read as its native value instead of text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SqlTimestamp(DateTime<Utc>);
 
-        let conn = Connection::open(&db_path)?;
-        let storage = Self { conn };
-        storage.init_schema()?;
-        Ok(storage)
+impl From<SqlTimestamp> for Value {
+    fn from(time: SqlTimestamp) -> Self {
+        Value::Timestamp(TimeUnit::Microsecond, time.0.timestamp_micros())
     }
+}
 
-    /// Create an in-memory storage instance (for testing)
-    #[allow(dead_code)]
-    fn new_in_memory() -> Result<Self> {
-        let conn = Connection::open_in_memory()?;
-        let storage = Self { conn };
-        storage.init_schema()?;
-        Ok(storage)
+impl ToSql for SqlTimestamp {
+    fn to_sql(&self) -> duckdb::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::Owned((*self).into()))
     }
+}
 
-    fn db_path() -> Result<PathBuf> {
-        let data_dir = dirs::data_dir()
-            .ok_or_else(|| anyhow::anyhow!("Could not determine data directory"))?;
-        Ok(data_dir.join("agenttop").join("metrics.duckdb"))
+impl FromSql for SqlTimestamp {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let ValueRef::Timestamp(unit, t) = value else {
+            return Err(FromSqlError::InvalidType);
+        };
+        let micros = match unit {
+            TimeUnit::Second => t.checked_mul(1_000_000),
+            TimeUnit::Millisecond => t.checked_mul(1_000),
+            TimeUnit::Microsecond => Some(t),
+            TimeUnit::Nanosecond => Some(t / 1_000),
+        };
+        micros
+            .and_then(DateTime::from_timestamp_micros)
+            .map(SqlTimestamp)
+            .ok_or(FromSqlError::OutOfRange(t as i128))
     }
+}
+
+/// `WITH combined_events AS (...)` over the legacy tool_events table and
+/// tool_result log events, filtered by `time_clause` (an "AND ..." clause).
+/// Columns: tool_name, timestamp, duration_ms, success, decision.
+fn combined_tool_events_cte(time_clause: &str) -> String {
+    format!(
+        r#"
+        WITH combined_events AS (
+            -- Legacy tool_events table (no decision tracking)
+            SELECT
+                tool_name,
+                timestamp,
+                duration_ms,
+                success,
+                NULL as decision
+            FROM tool_events
+            WHERE 1=1 {time_clause}
+
+            UNION ALL
+
+            -- New log_events table with query-time filtering
+            SELECT
+                COALESCE(json_extract_string(attributes, '$.tool_name'), 'unknown') as tool_name,
+                timestamp,
+                COALESCE(CAST(json_extract(attributes, '$.duration_ms') AS BIGINT), 0) as duration_ms,
+                CASE
+                    WHEN json_extract_string(attributes, '$.success') IN ('true', '1') THEN true
+                    WHEN json_extract(attributes, '$.success') = true THEN true
+                    ELSE false
+                END as success,
+                json_extract_string(attributes, '$.decision') as decision
+            FROM log_events
+            WHERE event_name LIKE '%tool_result' {time_clause}
+        )
+        "#
+    )
+}
+
+// Commands that can be sent to the storage actor
+#[allow(dead_code)]
+enum StorageCommand {
+    RecordToolEvent(ToolEvent),
+    RecordLogEvents(Vec<LogEvent>),
+    RecordTokenUsage {
+        token_type: String,
+        count: u64,
+        model: Option<String>,
+        provider: Option<String>,
+    },
+    RecordCost {
+        cost_usd: f64,
+        session_id: Option<String>,
+        model: Option<String>,
+        cumulative: bool,
+    },
+    RecordSessionMetric {
+        name: String,
+        value: i64,
+        session_id: Option<String>,
+    },
+    RecordAnomaly(Anomaly),
+    GetToolMetrics {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        tx: mpsc::Sender<Result<Vec<ToolMetrics>>>,
+    },
+    GetToolCallSeries {
+        since: DateTime<Utc>,
+        bucket_secs: i64,
+        buckets: usize,
+        tx: mpsc::Sender<Result<HashMap<String, Vec<u64>>>>,
+    },
+    GetInFlightTools {
+        since: DateTime<Utc>,
+        tx: mpsc::Sender<Result<Vec<InFlightTool>>>,
+    },
+    GetSessionTimeline {
+        tx: mpsc::Sender<Result<SessionTimeline>>,
+    },
+    GetHourlyActivity {
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+        tx: mpsc::Sender<Result<Vec<HourlyActivity>>>,
+    },
+    GetMcpServerHealth {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        tx: mpsc::Sender<Result<Vec<McpServerHealth>>>,
+    },
+    IsEmpty {
+        tx: mpsc::Sender<Result<bool>>,
+    },
+    GetDataVersion {
+        tx: mpsc::Sender<Result<DataVersion>>,
+    },
+    ExportTable {
+        table: String,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        format: TableFormat,
+        path: PathBuf,
+        tx: mpsc::Sender<Result<u64>>,
+    },
+    Prune {
+        before: DateTime<Utc>,
+        dry_run: bool,
+        tx: mpsc::Sender<Result<Vec<(String, u64)>>>,
+    },
+    GetModelCosts {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        tx: mpsc::Sender<Result<Vec<ModelCost>>>,
+    },
+    GetTopToolErrors {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        limit: usize,
+        tx: mpsc::Sender<Result<Vec<ToolErrorCount>>>,
+    },
+    RunQuery {
+        sql: String,
+        limit: usize,
+        tx: mpsc::Sender<Result<QueryResult>>,
+    },
+    GetLogEventsAfter {
+        after_id: Option<i64>,
+        limit: usize,
+        tx: mpsc::Sender<Result<Vec<LogRecord>>>,
+    },
+    GetLogEvents {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        limit: usize,
+        tx: mpsc::Sender<Result<Vec<LogRecord>>>,
+    },
+    GetRecentPrompts {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        limit: usize,
+        tx: mpsc::Sender<Result<Vec<PromptEntry>>>,
+    },
+    GetTurns {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        limit: usize,
+        tx: mpsc::Sender<Result<Vec<Turn>>>,
+    },
+    GetToolSequences {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        length: usize,
+        limit: usize,
+        tx: mpsc::Sender<Result<Vec<ToolSequence>>>,
+    },
+    GetFileActivity {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        directories: bool,
+        limit: usize,
+        tx: mpsc::Sender<Result<Vec<FileActivity>>>,
+    },
+    GetTokenMetrics {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        tx: mpsc::Sender<Result<TokenMetrics>>,
+    },
+    GetCacheUsage {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        tx: mpsc::Sender<Result<Vec<CacheUsage>>>,
+    },
+    GetTotalCost {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        tx: mpsc::Sender<Result<f64>>,
+    },
+    GetLastToolError {
+        tool_name: String,
+        tx: mpsc::Sender<Result<Option<String>>>,
+    },
+    GetSessionMetrics {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        tx: mpsc::Sender<Result<SessionMetrics>>,
+    },
+    GetApiMetrics {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        tx: mpsc::Sender<Result<ApiMetrics>>,
+    },
+    CountRateLimitErrors {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        tx: mpsc::Sender<Result<u64>>,
+    },
+    CountSessions {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        tx: mpsc::Sender<Result<u64>>,
+    },
+    GetSessionSummary {
+        session_id: String,
+        tx: mpsc::Sender<Result<Option<SessionSummary>>>,
+    },
+    GetSessions {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        limit: usize,
+        tx: mpsc::Sender<Result<Vec<Session>>>,
+    },
+    ScoreSessions {
+        limit: usize,
+        tx: mpsc::Sender<Result<usize>>,
+    },
+    GetAnomalies {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        limit: usize,
+        tx: mpsc::Sender<Result<Vec<Anomaly>>>,
+    },
+    CountAnomalies {
+        kind: Option<AnomalyKind>,
+        tx: mpsc::Sender<Result<u64>>,
+    },
+    GetBudgetSpend {
+        scope: BudgetScope,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        tx: mpsc::Sender<Result<f64>>,
+    },
+    GetBudgetUsage {
+        budget: Option<String>,
+        open_only: bool,
+        limit: usize,
+        tx: mpsc::Sender<Result<Vec<BudgetUsage>>>,
+    },
+    SaveBudgetUsage {
+        usage: BudgetUsage,
+        tx: mpsc::Sender<Result<()>>,
+    },
+    GetCostBreakdown {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        tx: mpsc::Sender<Result<Vec<CostBreakdown>>>,
+    },
+    GetApiSpend {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        tx: mpsc::Sender<Result<Vec<ApiSpend>>>,
+    },
+    GetSessionProductivity {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        limit: usize,
+        tx: mpsc::Sender<Result<Vec<SessionProductivity>>>,
+    },
+    GetProviderComparison {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        tx: mpsc::Sender<Result<Vec<ProviderComparison>>>,
+    },
+    GetContextSamples {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        limit: usize,
+        tx: mpsc::Sender<Result<Vec<ContextSample>>>,
+    },
+    GetUsagePoints {
+        provider: Option<String>,
+        since: DateTime<Utc>,
+        tx: mpsc::Sender<Result<Vec<UsagePoint>>>,
+    },
+    GetErrorCategories {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        tx: mpsc::Sender<Result<Vec<ErrorCategoryCount>>>,
+    },
+    GetErrorBreakdown {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        limit: usize,
+        tx: mpsc::Sender<Result<Vec<ErrorBreakdown>>>,
+    },
+    ReclassifyErrors {
+        tx: mpsc::Sender<Result<u64>>,
+    },
+    StreamTokenUsage {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        tx: mpsc::SyncSender<error::Result<Vec<TokenUsageRow>>>,
+    },
+    /// Wake-up for the actor: run the writes waiting in the queue
+    Ingest(Arc<IngestQueue>),
+    /// A query from a [`StorageHandle::scoped`] handle, skipped once superseded
+    Scoped {
+        cmd: Box<StorageCommand>,
+        stamp: Stamp,
+    },
+    Shutdown,
+}
+
+impl StorageCommand {
+    /// Label for the command's tracing span and latency histogram
+    fn name(&self) -> &'static str {
+        match self {
+            Self::RecordToolEvent(..) => "record_tool_event",
+            Self::RecordLogEvents(..) => "record_log_events",
+            Self::RecordTokenUsage { .. } => "record_token_usage",
+            Self::RecordCost { .. } => "record_cost",
+            Self::RecordSessionMetric { .. } => "record_session_metric",
+            Self::RecordAnomaly(..) => "record_anomaly",
+            Self::GetToolMetrics { .. } => "get_tool_metrics",
+            Self::GetToolCallSeries { .. } => "get_tool_call_series",
+            Self::GetInFlightTools { .. } => "get_in_flight_tools",
+            Self::GetSessionTimeline { .. } => "get_session_timeline",
+            Self::GetHourlyActivity { .. } => "get_hourly_activity",
+            Self::GetMcpServerHealth { .. } => "get_mcp_server_health",
+            Self::IsEmpty { .. } => "is_empty",
+            Self::GetDataVersion { .. } => "get_data_version",
+            Self::ExportTable { .. } => "export_table",
+            Self::Prune { .. } => "prune",
+            Self::GetModelCosts { .. } => "get_model_costs",
+            Self::GetTopToolErrors { .. } => "get_top_tool_errors",
+            Self::RunQuery { .. } => "run_query",
+            Self::GetLogEventsAfter { .. } => "get_log_events_after",
+            Self::GetLogEvents { .. } => "get_log_events",
+            Self::GetRecentPrompts { .. } => "get_recent_prompts",
+            Self::GetTurns { .. } => "get_turns",
+            Self::GetToolSequences { .. } => "get_tool_sequences",
+            Self::GetFileActivity { .. } => "get_file_activity",
+            Self::GetTokenMetrics { .. } => "get_token_metrics",
+            Self::GetCacheUsage { .. } => "get_cache_usage",
+            Self::GetTotalCost { .. } => "get_total_cost",
+            Self::GetLastToolError { .. } => "get_last_tool_error",
+            Self::GetSessionMetrics { .. } => "get_session_metrics",
+            Self::GetApiMetrics { .. } => "get_api_metrics",
+            Self::CountRateLimitErrors { .. } => "count_rate_limit_errors",
+            Self::CountSessions { .. } => "count_sessions",
+            Self::GetSessionSummary { .. } => "get_session_summary",
+            Self::GetSessions { .. } => "get_sessions",
+            Self::ScoreSessions { .. } => "score_sessions",
+            Self::GetAnomalies { .. } => "get_anomalies",
+            Self::CountAnomalies { .. } => "count_anomalies",
+            Self::GetBudgetSpend { .. } => "get_budget_spend",
+            Self::GetBudgetUsage { .. } => "get_budget_usage",
+            Self::SaveBudgetUsage { .. } => "save_budget_usage",
+            Self::GetCostBreakdown { .. } => "get_cost_breakdown",
+            Self::GetApiSpend { .. } => "get_api_spend",
+            Self::GetSessionProductivity { .. } => "get_session_productivity",
+            Self::GetProviderComparison { .. } => "get_provider_comparison",
+            Self::GetContextSamples { .. } => "get_context_samples",
+            Self::GetUsagePoints { .. } => "get_usage_points",
+            Self::GetErrorCategories { .. } => "get_error_categories",
+            Self::GetErrorBreakdown { .. } => "get_error_breakdown",
+            Self::ReclassifyErrors { .. } => "reclassify_errors",
+            Self::StreamTokenUsage { .. } => "stream_token_usage",
+            Self::Ingest(..) => "ingest",
+            Self::Scoped { cmd, .. } => cmd.name(),
+            Self::Shutdown => "shutdown",
+        }
+    }
+
+    /// The command, and the scope it was sent from
+    fn unscoped(self) -> (Self, Option<Stamp>) {
+        match self {
+            Self::Scoped { cmd, stamp } => (*cmd, Some(stamp)),
+            cmd => (cmd, None),
+        }
+    }
+
+    /// Whether the query timeout applies: not to writes, or to exports, pruning and
+    /// streams, which take as long as the data they go through
+    fn has_timeout(&self) -> bool {
+        !matches!(
+            self,
+            Self::RecordToolEvent(..)
+                | Self::RecordLogEvents(..)
+                | Self::RecordTokenUsage { .. }
+                | Self::RecordCost { .. }
+                | Self::RecordSessionMetric { .. }
+                | Self::RecordAnomaly(..)
+                | Self::ExportTable { .. }
+                | Self::Prune { .. }
+                | Self::ReclassifyErrors { .. }
+                | Self::StreamTokenUsage { .. }
+                | Self::Ingest(..)
+                | Self::Scoped { .. }
+                | Self::Shutdown
+        )
+    }
+}
+
+/// Write commands waiting for the storage actor. Writes don't wait for a reply, so
+/// when the actor falls behind (e.g. DuckDB stalls) they would pile up without bound;
+/// past the capacity the oldest batch is dropped and counted in the self-metrics.
+struct IngestQueue {
+    pending: Mutex<VecDeque<Queued>>,
+    capacity: usize,
+    /// Set by [`StorageHandle::with_journal`]
+    journal: OnceCell<Journal>,
+}
+
+/// A write in the [`IngestQueue`]
+struct Queued {
+    cmd: StorageCommand,
+    /// Its journal entry, if it was journaled
+    seq: Option<u64>,
+}
+
+impl IngestQueue {
+    fn new(capacity: usize) -> Self {
+        Self {
+            pending: Mutex::new(VecDeque::new()),
+            capacity,
+            journal: OnceCell::new(),
+        }
+    }
+
+    /// Queue `cmd`, returning true when the queue was empty, i.e. the actor has no
+    /// [`StorageCommand::Ingest`] wake-up pending for it
+    fn push(&self, cmd: StorageCommand, seq: Option<u64>) -> bool {
+        let mut pending = self.pending.lock().unwrap();
+        let was_empty = pending.is_empty();
+        if pending.len() >= self.capacity
+            && let Some(dropped) = pending.pop_front()
+        {
+            self.dropped(dropped);
+        }
+        pending.push_back(Queued { cmd, seq });
+        was_empty
+    }
+
+    fn take(&self) -> VecDeque<Queued> {
+        std::mem::take(&mut *self.pending.lock().unwrap())
+    }
+
+    /// Drop the queued writes while storage is unavailable, counting them as dropped
+    /// batches. The queue is empty afterwards, so the next write wakes the actor again.
+    fn discard(&self) {
+        for queued in self.take() {
+            self.dropped(queued);
+        }
+    }
+
+    /// Count a write that will never be committed; it leaves the journal like a
+    /// committed one, so a stall doesn't grow the file without bound
+    fn dropped(&self, queued: Queued) {
+        SELF_METRICS.receiver.record_dropped_batch();
+        self.written(queued.seq, false);
+    }
+
+    /// The actor is done with the write journaled as `seq`, which `failed` or not
+    fn written(&self, seq: Option<u64>, failed: bool) {
+        if let (Some(journal), Some(seq)) = (self.journal.get(), seq) {
+            journal.done(seq, failed);
+        }
+    }
+}
+
+// Thread-safe handle to the storage actor
+#[derive(Clone)]
+pub struct StorageHandle {
+    sender: mpsc::Sender<StorageCommand>,
+    ingest: Arc<IngestQueue>,
+    /// False until a database opened in the background is ready
+    ready: Arc<AtomicBool>,
+    /// Set on [`scoped`](Self::scoped) handles, whose queries can be superseded
+    scope: Option<Scope>,
+    /// The actor's thread, which [`close`](Self::close) waits for
+    actor: Arc<Mutex<Option<thread::JoinHandle<()>>>>,
+}
+
+impl StorageHandle {
+    /// Open the default database for reading and writing (see [`open`](Self::open))
+    pub fn new() -> error::Result<Self> {
+        Self::open(&db_path()?)
+    }
+
+    /// Open the default database in the background (see [`open_lazy`](Self::open_lazy))
+    pub fn new_lazy() -> error::Result<Self> {
+        Ok(Self::open_lazy(&db_path()?))
+    }
+
+    /// Open the database at `path` for reading and writing, creating it if needed. The
+    /// connection stays open until the handle is dropped; other processes read the
+    /// database through this one's API (see [`attach`](Self::attach)). Fails at once
+    /// while another agenttop records into it.
+    pub fn open(path: &std::path::Path) -> error::Result<Self> {
+        Self::open_with(path, Storage::open)
+    }
+
+    /// Like [`open`](Self::open), for `agenttop export`: statements on this handle may
+    /// read and write any file, which [`export_table`](Self::export_table) needs, so it
+    /// must not serve queries from elsewhere
+    pub fn open_for_export(path: &std::path::Path) -> error::Result<Self> {
+        Self::open_with(path, Storage::open_for_export)
+    }
+
+    fn open_with(
+        path: &std::path::Path,
+        open: fn(&std::path::Path) -> Result<Storage>,
+    ) -> error::Result<Self> {
+        if let Some(locked) = lock::holder(path).filter(|locked| {
+            locked.owner.as_ref().map(|owner| owner.pid) != Some(std::process::id())
+        }) {
+            return Err(anyhow::Error::from(locked).into());
+        }
+        // A reader may have the file open for a moment
+        let storage = retry_while_locked(|| open(path))?;
+        let path = path.to_path_buf();
+        Self::spawn_actor(storage, move || open(&path))
+    }
+
+    /// Like [`open`](Self::open), but returns at once and opens the file on the actor
+    /// thread, which on a large database takes seconds. Commands wait until it is open
+    /// and [`is_ready`](Self::is_ready) turns true; while opening fails, storage shows
+    /// as down and it is retried.
+    pub fn open_lazy(path: &std::path::Path) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let handle = Self::with_sender(sender);
+        handle.ready.store(false, Ordering::Release);
+
+        let ready = Arc::clone(&handle.ready);
+        let path = path.to_path_buf();
+        let actor = thread::spawn(move || {
+            let Some(storage) =
+                open_with_backoff(&receiver, || retry_while_locked(|| Storage::open(&path)))
+            else {
+                return;
+            };
+            ready.store(true, Ordering::Release);
+            supervise(
+                storage,
+                receiver,
+                || Storage::open(&path),
+                run_storage_actor,
+            )
+        });
+
+        handle.with_actor(actor)
+    }
+
+    /// Read the database at `path` without writing to it. While another agenttop records
+    /// into it, queries go to that agenttop's API (see [`connect`](Self::connect));
+    /// otherwise each burst of queries opens the file read-only and releases it when idle.
+    pub fn attach(path: &std::path::Path) -> error::Result<Self> {
+        if let Some((url, token)) = recorder_api(path) {
+            return Ok(Self::connect(&url, token.as_deref())?);
+        }
+        let storage = retry_while_locked(|| Storage::open_read_only(path))?;
+        let (sender, receiver) = mpsc::channel();
+        let path = path.to_path_buf();
+        let reopen = {
+            let path = path.clone();
+            move || retry_while_locked(|| Storage::open_read_only(&path))
+        };
+        let actor = thread::spawn(move || {
+            supervise(storage, receiver, reopen, |storage, receiver| {
+                run_reader_actor(storage, &path, receiver)
+            })
+        });
+        Ok(Self::with_sender(sender).with_actor(actor))
+    }
+
+    /// Open an existing database without write access.
+    /// Fails while another process has the file open for writing (DuckDB allows a
+    /// single writer, or any number of readers).
+    #[allow(dead_code)]
+    pub fn open_read_only(path: &std::path::Path) -> error::Result<Self> {
+        let path = path.to_path_buf();
+        Self::spawn_actor(Storage::open_read_only(&path)?, move || {
+            Storage::open_read_only(&path)
+        })
+    }
+
+    /// Create an in-memory storage handle for testing.
+    /// The database is isolated and won't persist or affect other tests.
+    #[allow(dead_code)]
+    pub fn new_in_memory() -> error::Result<Self> {
+        Self::spawn_actor(Storage::new_in_memory()?, Storage::new_in_memory)
+    }
+
+    /// Queries are sent on `sender` and answered on their own channel, so at most one
+    /// per caller is outstanding; writes go through the bounded [`IngestQueue`]
+    fn with_sender(sender: mpsc::Sender<StorageCommand>) -> Self {
+        Self {
+            sender,
+            ingest: Arc::new(IngestQueue::new(INGEST_QUEUE_CAPACITY)),
+            ready: Arc::new(AtomicBool::new(true)),
+            scope: None,
+            actor: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Remember the thread the actor runs on, for [`close`](Self::close)
+    fn with_actor(self, actor: thread::JoinHandle<()>) -> Self {
+        *self.actor.lock().unwrap() = Some(actor);
+        self
+    }
+
+    /// Stop the actor once it has handled what was sent so far, and wait until it has
+    /// let go of the database. Clones of this handle stop working too.
+    pub fn close(self) {
+        let _ = self.sender.send(StorageCommand::Shutdown);
+        let actor = self.actor.lock().unwrap().take();
+        if let Some(actor) = actor {
+            let _ = actor.join();
+        }
+    }
+
+    /// A handle to the same storage whose queries [`supersede`](Self::supersede) can
+    /// cancel, without affecting the queries of other handles. Clones share the scope.
+    pub fn scoped(&self) -> Self {
+        Self {
+            scope: Some(Scope::default()),
+            ..self.clone()
+        }
+    }
+
+    /// Cancel the queries this scoped handle sent so far: queued ones are skipped and
+    /// a running one is interrupted, failing with [`QueryCancelled`](error::QueryCancelled).
+    /// Call it when their answers are no longer wanted, e.g. the time filter changed.
+    pub fn supersede(&self) {
+        if let Some(scope) = &self.scope {
+            scope.fetch_add(1, Ordering::AcqRel);
+            WATCHDOG.wake();
+        }
+    }
+
+    /// Send a query to the actor, stamped with this handle's scope; returns the
+    /// generation it was sent at
+    fn send(&self, cmd: StorageCommand) -> error::Result<u64> {
+        let Some(scope) = &self.scope else {
+            self.sender.send(cmd)?;
+            return Ok(0);
+        };
+        let generation = scope.load(Ordering::Acquire);
+        self.sender.send(StorageCommand::Scoped {
+            cmd: Box::new(cmd),
+            stamp: Stamp {
+                scope: Arc::clone(scope),
+                generation,
+            },
+        })?;
+        Ok(generation)
+    }
+
+    /// Wait for the answer to a query [`send`](Self::send) sent at `generation`
+    fn reply<T>(&self, generation: u64, rx: mpsc::Receiver<Result<T>>) -> error::Result<T> {
+        let superseded = || {
+            self.scope
+                .as_ref()
+                .is_some_and(|scope| scope.load(Ordering::Acquire) != generation)
+        };
+        let interrupted = || match superseded() {
+            true => AgenttopError::Storage(error::QueryCancelled.into()),
+            false => AgenttopError::Storage(
+                error::QueryTimeout(query_timeout().unwrap_or_default()).into(),
+            ),
+        };
+        match rx.recv() {
+            Ok(Ok(value)) => Ok(value),
+            Ok(Err(e)) if is_interrupt(&e) => Err(interrupted()),
+            Ok(Err(e)) => Err(e.into()),
+            // A superseded query is skipped, closing its reply channel
+            Err(_) if superseded() => Err(interrupted()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Whether the database is open, i.e. queries answer without waiting for it
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Acquire)
+    }
+
+    /// Journal writes to the file at `path` before queuing them, so those not yet
+    /// committed when agenttop stops are committed on the next start; writes left there
+    /// by the last run are queued now. See [`journal_path`] for the usual location.
+    pub fn with_journal(self, path: &std::path::Path) -> error::Result<Self> {
+        let (journal, replay) = Journal::open(path)?;
+        if !replay.is_empty() {
+            tracing::info!("Replaying {} writes from {}", replay.len(), path.display());
+        }
+        if self.ingest.journal.set(journal).is_err() {
+            return Err(AgenttopError::Config(anyhow::anyhow!(
+                "storage already has a journal"
+            )));
+        }
+        for (seq, cmd) in replay {
+            self.queue_write(cmd, Some(seq));
+        }
+        Ok(self)
+    }
+
+    /// Queue a write for the actor, journaling it first if there is a journal
+    fn send_write(&self, cmd: StorageCommand) {
+        let seq = self
+            .ingest
+            .journal
+            .get()
+            .and_then(|journal| journal.append(&cmd));
+        self.queue_write(cmd, seq);
+    }
+
+    /// Queue a write journaled as `seq`, waking the actor if nothing else is queued
+    fn queue_write(&self, cmd: StorageCommand, seq: Option<u64>) {
+        if self.ingest.push(cmd, seq) {
+            let _ = self
+                .sender
+                .send(StorageCommand::Ingest(Arc::clone(&self.ingest)));
+        }
+    }
+
+    /// Run the actor on `storage`; `reopen` gives it a new connection after a panic
+    fn spawn_actor(
+        storage: Storage,
+        reopen: impl Fn() -> Result<Storage> + Send + 'static,
+    ) -> error::Result<Self> {
+        let (sender, receiver) = mpsc::channel();
+
+        // Spawn the storage actor thread
+        let actor = thread::spawn(move || supervise(storage, receiver, reopen, run_storage_actor));
+
+        Ok(Self::with_sender(sender).with_actor(actor))
+    }
+
+    /// Record a tool event to the legacy tool_events table.
+    /// Note: This method is kept for backward compatibility. New code should use
+    /// record_log_events() which stores all OTLP logs without filtering.
+    #[allow(dead_code)]
+    pub fn record_tool_event(&self, event: ToolEvent) {
+        self.send_write(StorageCommand::RecordToolEvent(event));
+    }
+
+    pub fn record_log_events(&self, events: Vec<LogEvent>) {
+        self.send_write(StorageCommand::RecordLogEvents(events));
+    }
+
+    /// Record token usage without a model or provider
+    #[allow(dead_code)]
+    pub fn record_token_usage(&self, token_type: &str, count: u64) {
+        self.record_model_token_usage(token_type, count, None, None);
+    }
+
+    /// Record token usage along with the model and provider it was reported for
+    pub fn record_model_token_usage(
+        &self,
+        token_type: &str,
+        count: u64,
+        model: Option<&str>,
+        provider: Option<&str>,
+    ) {
+        self.send_write(StorageCommand::RecordTokenUsage {
+            token_type: token_type.to_string(),
+            count,
+            model: model.map(str::to_string),
+            provider: provider.map(str::to_string),
+        });
+    }
+
+    /// Record spend reported as an increment, without the session it was reported for
+    #[allow(dead_code)]
+    pub fn record_cost(&self, cost_usd: f64) {
+        self.record_cost_for(None, None, cost_usd, false);
+    }
+
+    /// Record spend reported for `session_id` and `model`. A `cumulative` report is the
+    /// running total of that series, so only its increase over the last one is counted
+    /// and a total re-reported after an agent restart isn't added again.
+    pub fn record_cost_for(
+        &self,
+        session_id: Option<&str>,
+        model: Option<&str>,
+        cost_usd: f64,
+        cumulative: bool,
+    ) {
+        self.send_write(StorageCommand::RecordCost {
+            cost_usd,
+            session_id: session_id.map(str::to_string),
+            model: model.map(str::to_string),
+            cumulative,
+        });
+    }
+
+    /// Record a session metric without the session it was reported for
+    #[allow(dead_code)]
+    pub fn record_session_metric(&self, name: &str, value: i64) {
+        self.record_session_metric_for(None, name, value);
+    }
+
+    /// Record a session metric reported for `session_id`. Cumulative metrics (see
+    /// [`CUMULATIVE_SESSION_METRICS`]) count only their latest value per session.
+    pub fn record_session_metric_for(&self, session_id: Option<&str>, name: &str, value: i64) {
+        self.send_write(StorageCommand::RecordSessionMetric {
+            name: name.to_string(),
+            value,
+            session_id: session_id.map(str::to_string),
+        });
+    }
+
+    /// Record an anomaly found by the detector (see [`crate::anomaly`])
+    pub fn record_anomaly(&self, anomaly: Anomaly) {
+        self.send_write(StorageCommand::RecordAnomaly(anomaly));
+    }
+
+    pub fn get_tool_metrics(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> error::Result<Vec<ToolMetrics>> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::GetToolMetrics { since, until, tx })?;
+        self.reply(sent, rx)
+    }
+
+    /// Get per-tool call counts in `buckets` consecutive buckets of `bucket_secs`
+    /// starting at `since` (oldest bucket first)
+    pub fn get_tool_call_series(
+        &self,
+        since: DateTime<Utc>,
+        bucket_secs: i64,
+        buckets: usize,
+    ) -> error::Result<HashMap<String, Vec<u64>>> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::GetToolCallSeries {
+            since,
+            bucket_secs,
+            buckets,
+            tx,
+        })?;
+        self.reply(sent, rx)
+    }
+
+    /// Get tools whose latest start event since the given time has no later result
+    pub fn get_in_flight_tools(&self, since: DateTime<Utc>) -> error::Result<Vec<InFlightTool>> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::GetInFlightTools { since, tx })?;
+        self.reply(sent, rx)
+    }
+
+    pub fn get_token_metrics(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> error::Result<TokenMetrics> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::GetTokenMetrics { since, until, tx })?;
+        self.reply(sent, rx)
+    }
+
+    /// Input, cache-read and cache-write tokens per hour and model in the range, oldest
+    /// first (see [`crate::efficiency::cache`])
+    pub fn get_cache_usage(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> error::Result<Vec<CacheUsage>> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::GetCacheUsage { since, until, tx })?;
+        self.reply(sent, rx)
+    }
+
+    /// Get the total reported cost (USD) since the given time
+    pub fn get_total_cost(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> error::Result<f64> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::GetTotalCost { since, until, tx })?;
+        self.reply(sent, rx)
+    }
+
+    pub fn get_last_tool_error(&self, tool_name: &str) -> error::Result<Option<String>> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::GetLastToolError {
+            tool_name: tool_name.to_string(),
+            tx,
+        })?;
+        self.reply(sent, rx)
+    }
+
+    /// Get the tool calls, API requests and errors of the most recent session
+    pub fn get_session_timeline(&self) -> error::Result<SessionTimeline> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::GetSessionTimeline { tx })?;
+        self.reply(sent, rx)
+    }
+
+    /// Get tool calls and cost rolled up per hour, oldest first (hours without activity are omitted)
+    pub fn get_hourly_activity(
+        &self,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> error::Result<Vec<HourlyActivity>> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::GetHourlyActivity { since, until, tx })?;
+        self.reply(sent, rx)
+    }
+
+    /// Get per-server health for MCP tools, busiest server first
+    pub fn get_mcp_server_health(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> error::Result<Vec<McpServerHealth>> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::GetMcpServerHealth { since, until, tx })?;
+        self.reply(sent, rx)
+    }
+
+    /// True if nothing has ever been recorded (no events or metrics in any table)
+    pub fn is_empty(&self) -> error::Result<bool> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::IsEmpty { tx })?;
+        self.reply(sent, rx)
+    }
+
+    /// Cheap fingerprint of the stored data, to skip re-running aggregations when it
+    /// has not changed
+    pub fn get_data_version(&self) -> error::Result<DataVersion> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::GetDataVersion { tx })?;
+        self.reply(sent, rx)
+    }
+
+    /// Write the rows of `table` in the time range to `path`, returning the row count
+    pub fn export_table(
+        &self,
+        table: &str,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        format: TableFormat,
+        path: &std::path::Path,
+    ) -> error::Result<u64> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::ExportTable {
+            table: table.to_string(),
+            since,
+            until,
+            format,
+            path: path.to_path_buf(),
+            tx,
+        })?;
+        self.reply(sent, rx)
+    }
+
+    /// Delete rows older than `before` from every table, returning (table, rows) for
+    /// each table. With `dry_run` nothing is deleted and the rows that would be are counted.
+    pub fn prune(&self, before: DateTime<Utc>, dry_run: bool) -> error::Result<Vec<(String, u64)>> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::Prune {
+            before,
+            dry_run,
+            tx,
+        })?;
+        self.reply(sent, rx)
+    }
+
+    /// API calls and cost per model, most expensive first
+    pub fn get_model_costs(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> error::Result<Vec<ModelCost>> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::GetModelCosts { since, until, tx })?;
+        self.reply(sent, rx)
+    }
+
+    /// Most frequent (tool, error message) pairs among failed tool calls
+    pub fn get_top_tool_errors(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        limit: usize,
+    ) -> error::Result<Vec<ToolErrorCount>> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::GetTopToolErrors {
+            since,
+            until,
+            limit,
+            tx,
+        })?;
+        self.reply(sent, rx)
+    }
+
+    /// Run a read-only query (a single SELECT-like statement), returning at most `limit` rows
+    pub fn run_query(&self, sql: &str, limit: usize) -> error::Result<QueryResult> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::RunQuery {
+            sql: sql.to_string(),
+            limit,
+            tx,
+        })?;
+        self.reply(sent, rx)
+    }
+
+    /// Get log events inserted after `after_id` (or the latest ones when None),
+    /// at most `limit`, oldest first
+    pub fn get_log_events_after(
+        &self,
+        after_id: Option<i64>,
+        limit: usize,
+    ) -> error::Result<Vec<LogRecord>> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::GetLogEventsAfter {
+            after_id,
+            limit,
+            tx,
+        })?;
+        self.reply(sent, rx)
+    }
+
+    /// Get the latest log events in the time range, at most `limit`, oldest first
+    pub fn get_log_events(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        limit: usize,
+    ) -> error::Result<Vec<LogRecord>> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::GetLogEvents {
+            since,
+            until,
+            limit,
+            tx,
+        })?;
+        self.reply(sent, rx)
+    }
+
+    /// Most recent user prompts, newest first
+    pub fn get_recent_prompts(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        limit: usize,
+    ) -> error::Result<Vec<PromptEntry>> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::GetRecentPrompts {
+            since,
+            until,
+            limit,
+            tx,
+        })?;
+        self.reply(sent, rx)
+    }
+
+    /// Most recent conversation turns (see [`Turn`]) starting in the range, newest first
+    pub fn get_turns(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        limit: usize,
+    ) -> error::Result<Vec<Turn>> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::GetTurns {
+            since,
+            until,
+            limit,
+            tx,
+        })?;
+        self.reply(sent, rx)
+    }
+
+    /// The most frequent runs of `length` consecutive tool calls within a session in the
+    /// range (see [`ToolSequence`]), most frequent first; runs seen only once are left out
+    pub fn get_tool_sequences(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        length: usize,
+        limit: usize,
+    ) -> error::Result<Vec<ToolSequence>> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::GetToolSequences {
+            since,
+            until,
+            length,
+            limit,
+            tx,
+        })?;
+        self.reply(sent, rx)
+    }
+
+    /// The files (or, with `directories`, the directories) tool calls in the range
+    /// worked on, most edited first
+    pub fn get_file_activity(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        directories: bool,
+        limit: usize,
+    ) -> error::Result<Vec<FileActivity>> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::GetFileActivity {
+            since,
+            until,
+            directories,
+            limit,
+            tx,
+        })?;
+        self.reply(sent, rx)
+    }
+
+    pub fn get_session_metrics(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> error::Result<SessionMetrics> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::GetSessionMetrics { since, until, tx })?;
+        self.reply(sent, rx)
+    }
+
+    pub fn get_api_metrics(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> error::Result<ApiMetrics> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::GetApiMetrics { since, until, tx })?;
+        self.reply(sent, rx)
+    }
+
+    /// Number of API errors caused by rate limiting (HTTP 429 or a "rate limit" message)
+    pub fn count_rate_limit_errors(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> error::Result<u64> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::CountRateLimitErrors { since, until, tx })?;
+        self.reply(sent, rx)
+    }
+
+    /// Number of distinct `session.id` values on log events in the range
+    pub fn count_sessions(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> error::Result<u64> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::CountSessions { since, until, tx })?;
+        self.reply(sent, rx)
+    }
+
+    /// Up to `limit` sessions overlapping the range, most recently started first
+    pub fn get_sessions(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        limit: usize,
+    ) -> error::Result<Vec<Session>> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::GetSessions {
+            since,
+            until,
+            limit,
+            tx,
+        })?;
+        self.reply(sent, rx)
+    }
+
+    /// Score up to `limit` sessions with events newer than their efficiency score (see
+    /// [`crate::efficiency`]); returns how many were scored
+    pub fn score_sessions(&self, limit: usize) -> error::Result<usize> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::ScoreSessions { limit, tx })?;
+        self.reply(sent, rx)
+    }
+
+    /// Up to `limit` anomalies detected in the range, newest first
+    pub fn get_anomalies(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        limit: usize,
+    ) -> error::Result<Vec<Anomaly>> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::GetAnomalies {
+            since,
+            until,
+            limit,
+            tx,
+        })?;
+        self.reply(sent, rx)
+    }
+
+    /// Number of anomalies detected so far, optionally of one kind only
+    pub fn count_anomalies(&self, kind: Option<AnomalyKind>) -> error::Result<u64> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::CountAnomalies { kind, tx })?;
+        self.reply(sent, rx)
+    }
+
+    /// Spend in the range counted by a budget of `scope`
+    pub fn get_budget_spend(
+        &self,
+        scope: &BudgetScope,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> error::Result<f64> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::GetBudgetSpend {
+            scope: scope.clone(),
+            since,
+            until,
+            tx,
+        })?;
+        self.reply(sent, rx)
+    }
+
+    /// Up to `limit` stored budget periods, of one budget or all, latest first; only the
+    /// current (not yet closed) ones if `open_only`
+    pub fn get_budget_usage(
+        &self,
+        budget: Option<&str>,
+        open_only: bool,
+        limit: usize,
+    ) -> error::Result<Vec<BudgetUsage>> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::GetBudgetUsage {
+            budget: budget.map(str::to_string),
+            open_only,
+            limit,
+            tx,
+        })?;
+        self.reply(sent, rx)
+    }
+
+    /// Insert or update a budget period. Unlike the metric writes this waits for the
+    /// actor: the monitor writes it again on every check, so it isn't journaled.
+    pub fn save_budget_usage(&self, usage: BudgetUsage) -> error::Result<()> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::SaveBudgetUsage { usage, tx })?;
+        self.reply(sent, rx)
+    }
+
+    /// API calls and cost per 15 minutes, model and project in the range (oldest first)
+    pub fn get_cost_breakdown(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> error::Result<Vec<CostBreakdown>> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::GetCostBreakdown { since, until, tx })?;
+        self.reply(sent, rx)
+    }
+
+    /// Every api_request in the range with its session, working directory and cost,
+    /// oldest first
+    pub fn get_api_spend(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> error::Result<Vec<ApiSpend>> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::GetApiSpend { since, until, tx })?;
+        self.reply(sent, rx)
+    }
+
+    /// Up to `limit` sessions overlapping the range that reported code metrics, with
+    /// their lines of code, commits, active time and cost; most recently started first
+    pub fn get_session_productivity(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        limit: usize,
+    ) -> error::Result<Vec<SessionProductivity>> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::GetSessionProductivity {
+            since,
+            until,
+            limit,
+            tx,
+        })?;
+        self.reply(sent, rx)
+    }
+
+    /// Totals per provider with events in the range, most spend first
+    pub fn get_provider_comparison(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> error::Result<Vec<ProviderComparison>> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::GetProviderComparison { since, until, tx })?;
+        self.reply(sent, rx)
+    }
+
+    /// Context size of the latest `limit` API requests in the range, oldest first
+    pub fn get_context_samples(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        limit: usize,
+    ) -> error::Result<Vec<ContextSample>> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::GetContextSamples {
+            since,
+            until,
+            limit,
+            tx,
+        })?;
+        self.reply(sent, rx)
+    }
+
+    /// API requests (tokens and cost) and rate-limited API errors since `since`, of one
+    /// provider or all, oldest first
+    pub fn get_usage_points(
+        &self,
+        provider: Option<&str>,
+        since: DateTime<Utc>,
+    ) -> error::Result<Vec<UsagePoint>> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::GetUsagePoints {
+            provider: provider.map(str::to_string),
+            since,
+            tx,
+        })?;
+        self.reply(sent, rx)
+    }
+
+    /// Errors per category in the range, most frequent first
+    pub fn get_error_categories(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> error::Result<Vec<ErrorCategoryCount>> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::GetErrorCategories { since, until, tx })?;
+        self.reply(sent, rx)
+    }
+
+    /// Errors per category and tool or model in the range, most frequent first, with
+    /// the latest message of each
+    pub fn get_error_breakdown(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        limit: usize,
+    ) -> error::Result<Vec<ErrorBreakdown>> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::GetErrorBreakdown {
+            since,
+            until,
+            limit,
+            tx,
+        })?;
+        self.reply(sent, rx)
+    }
+
+    /// Classify the stored errors again with the current rules (see
+    /// [`crate::taxonomy::set_rules`]); returns how many there are
+    pub fn reclassify_errors(&self) -> error::Result<u64> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::ReclassifyErrors { tx })?;
+        self.reply(sent, rx)
+    }
+
+    /// Token usage rows in the range, oldest first, in batches as they are read so that
+    /// the whole table is never in memory. Reading stops when the receiver is dropped;
+    /// the storage answers nothing else until then.
+    pub fn stream_token_usage(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> error::Result<mpsc::Receiver<error::Result<Vec<TokenUsageRow>>>> {
+        let (tx, rx) = mpsc::sync_channel(STREAM_BATCHES_AHEAD);
+        self.send(StorageCommand::StreamTokenUsage { since, until, tx })?;
+        Ok(rx)
+    }
+
+    /// Summarize the session whose id is `session_id` or starts with it (None if no
+    /// session matches; an error if several do)
+    pub fn get_session_summary(&self, session_id: &str) -> error::Result<Option<SessionSummary>> {
+        let (tx, rx) = mpsc::channel();
+        let sent = self.send(StorageCommand::GetSessionSummary {
+            session_id: session_id.to_string(),
+            tx,
+        })?;
+        self.reply(sent, rx)
+    }
+}
+
+fn run_storage_actor(storage: Storage, receiver: &mpsc::Receiver<StorageCommand>) {
+    for cmd in receiver {
+        if !handle_command(&storage, cmd) {
+            break;
+        }
+    }
+}
+
+/// Run `actor` until it returns (shutdown, or every handle dropped). When it panics,
+/// e.g. on a DuckDB failure, storage is reported down in the self-metrics and `reopen`
+/// is retried with a growing pause, failing commands meanwhile; once it succeeds the
+/// actor resumes on the new connection with the same channel.
+fn supervise(
+    storage: Storage,
+    receiver: mpsc::Receiver<StorageCommand>,
+    reopen: impl Fn() -> Result<Storage>,
+    actor: impl Fn(Storage, &mpsc::Receiver<StorageCommand>),
+) {
+    let mut storage = Some(storage);
+    let mut backoff = RESTART_BACKOFF_MIN;
+    loop {
+        let current = match storage.take() {
+            Some(current) => current,
+            None => match reopen() {
+                Ok(reopened) => {
+                    tracing::info!("Storage restarted");
+                    SELF_METRICS.storage.record_restart();
+                    reopened
+                }
+                Err(e) => {
+                    SELF_METRICS.storage.set_down(&format!("{:#}", e));
+                    if !reject_commands(&receiver, backoff) {
+                        return;
+                    }
+                    backoff = (backoff * 2).min(RESTART_BACKOFF_MAX);
+                    continue;
+                }
+            },
+        };
+
+        let started = std::time::Instant::now();
+        let outcome =
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| actor(current, &receiver)));
+        let Err(panic) = outcome else {
+            return;
+        };
+        let message = panic
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "storage actor panicked".to_string());
+        tracing::error!("Storage actor panicked: {}", message);
+        SELF_METRICS.storage.set_down(&message);
+        // A long healthy run starts over at the shortest pause
+        if started.elapsed() > RESTART_BACKOFF_MAX {
+            backoff = RESTART_BACKOFF_MIN;
+        }
+        if !reject_commands(&receiver, backoff) {
+            return;
+        }
+        backoff = (backoff * 2).min(RESTART_BACKOFF_MAX);
+    }
+}
+
+/// Open the database of an actor started before it, retrying with backoff while that
+/// fails (showing storage as down and failing commands meanwhile). None once the actor
+/// should stop.
+fn open_with_backoff(
+    receiver: &mpsc::Receiver<StorageCommand>,
+    open: impl Fn() -> Result<Storage>,
+) -> Option<Storage> {
+    let mut backoff = RESTART_BACKOFF_MIN;
+    let started = std::time::Instant::now();
+    loop {
+        match open() {
+            Ok(storage) => {
+                tracing::info!("Database opened in {:.2?}", started.elapsed());
+                SELF_METRICS.storage.set_up();
+                return Some(storage);
+            }
+            Err(e) => {
+                tracing::error!("Failed to open the database: {:#}", e);
+                SELF_METRICS.storage.set_down(&format!("{:#}", e));
+                if !reject_commands(receiver, backoff) {
+                    return None;
+                }
+                backoff = (backoff * 2).min(RESTART_BACKOFF_MAX);
+            }
+        }
+    }
+}
+
+/// Fail the commands that arrive within `wait` instead of leaving them queued until
+/// storage is back: a dropped query closes its reply channel, and queued writes are
+/// counted as dropped batches. False once the actor should stop.
+fn reject_commands(receiver: &mpsc::Receiver<StorageCommand>, wait: std::time::Duration) -> bool {
+    let deadline = std::time::Instant::now() + wait;
+    loop {
+        let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+        match receiver.recv_timeout(remaining) {
+            Ok(StorageCommand::Shutdown) => return false,
+            Ok(StorageCommand::Ingest(queue)) => queue.discard(),
+            Ok(_) => {}
+            Err(mpsc::RecvTimeoutError::Timeout) => return true,
+            Err(mpsc::RecvTimeoutError::Disconnected) => return false,
+        }
+    }
+}
+
+/// Like [`run_storage_actor`] on a read-only connection, but the connection is dropped
+/// after [`ATTACHED_IDLE`] without a command, so an agenttop can start recording into the
+/// file. Once one does, commands go to its API instead of reopening the file.
+fn run_reader_actor(
+    storage: Storage,
+    path: &std::path::Path,
+    receiver: &mpsc::Receiver<StorageCommand>,
+) {
+    let mut storage = Some(storage);
+    loop {
+        let cmd = if storage.is_some() {
+            match receiver.recv_timeout(ATTACHED_IDLE) {
+                Ok(cmd) => cmd,
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    storage = None;
+                    continue;
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+        } else {
+            match receiver.recv() {
+                Ok(cmd) => cmd,
+                Err(_) => break,
+            }
+        };
+        if matches!(cmd, StorageCommand::Shutdown) {
+            break;
+        }
+        let current = match storage.take() {
+            Some(current) => current,
+            None => {
+                if let Some((url, token)) = recorder_api(path) {
+                    remote::RemoteClient::new(&url, token.as_deref()).run_command(cmd);
+                    continue;
+                }
+                match retry_while_locked(|| Storage::open_read_only(path)) {
+                    Ok(reopened) => reopened,
+                    Err(e) => {
+                        // Dropping a query closes its reply channel, failing it; writes
+                        // are dropped rather than left queued without a wake-up
+                        tracing::error!("{:#}", e);
+                        if let StorageCommand::Ingest(queue) = cmd {
+                            queue.discard();
+                        }
+                        continue;
+                    }
+                }
+            }
+        };
+        handle_command(&current, cmd);
+        storage = Some(current);
+    }
+}
+
+/// Base URL and token of the API of the agenttop recording into the database at `path`,
+/// if one is
+fn recorder_api(path: &std::path::Path) -> Option<(String, Option<String>)> {
+    let owner = lock::holder(path)?.owner?;
+    let receiver = crate::port::connect_addr(owner.receiver?);
+    Some((format!("http://{}", receiver), owner.api_token))
+}
+
+/// Retry `open` for up to [`LOCK_WAIT`] while it fails, e.g. because another
+/// process holds the file
+fn retry_while_locked(mut open: impl FnMut() -> Result<Storage>) -> Result<Storage> {
+    let deadline = std::time::Instant::now() + LOCK_WAIT;
+    loop {
+        match open() {
+            Ok(storage) => return Ok(storage),
+            Err(e) if std::time::Instant::now() >= deadline || !is_lock_error(&e) => return Err(e),
+            Err(_) => thread::sleep(std::time::Duration::from_millis(50)),
+        }
+    }
+}
+
+/// Whether opening failed because another process has the file open
+fn is_lock_error(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .any(|cause| cause.to_string().contains("Could not set lock"))
+}
+
+/// Run one command in a tracing span, recording its latency; false for
+/// [`StorageCommand::Shutdown`]
+fn handle_command(storage: &Storage, cmd: StorageCommand) -> bool {
+    let (cmd, stamp) = cmd.unscoped();
+    if stamp.as_ref().is_some_and(Stamp::is_superseded) {
+        // Nobody waits for the answer any more; dropping the reply channel says so
+        return true;
+    }
+    let name = cmd.name();
+    let _span = tracing::debug_span!("storage", command = name).entered();
+    let started = std::time::Instant::now();
+    let deadline = query_timeout()
+        .filter(|_| cmd.has_timeout())
+        .map(|timeout| started + timeout);
+    let watch = WATCHDOG.watch(&storage.conn, deadline, stamp);
+    let running = run_command(storage, cmd);
+    drop(watch);
+    let elapsed = started.elapsed();
+    tracing::debug!(elapsed_ms = elapsed.as_secs_f64() * 1000.0, "done");
+    if running {
+        SELF_METRICS.storage.record_latency(name, elapsed);
+    }
+    running
+}
+
+fn run_command(storage: &Storage, cmd: StorageCommand) -> bool {
+    match cmd {
+        StorageCommand::Ingest(queue) => {
+            for Queued { cmd, seq } in queue.take() {
+                let failed = storage.failed_writes.get();
+                handle_command(storage, cmd);
+                queue.written(seq, storage.failed_writes.get() > failed);
+            }
+        }
+        StorageCommand::RecordToolEvent(event) => {
+            if let Err(e) = storage.record_tool_event(&event) {
+                storage.write_failed("tool event", e);
+            }
+        }
+        StorageCommand::RecordLogEvents(events) => {
+            if let Err(e) = storage.insert_log_events(&events) {
+                storage.write_failed("log events", e);
+            }
+        }
+        StorageCommand::RecordTokenUsage {
+            token_type,
+            count,
+            model,
+            provider,
+        } => {
+            if let Err(e) = storage.record_token_usage(
+                &token_type,
+                count,
+                model.as_deref(),
+                provider.as_deref(),
+            ) {
+                storage.write_failed("token usage", e);
+            }
+        }
+        StorageCommand::RecordCost {
+            cost_usd,
+            session_id,
+            model,
+            cumulative,
+        } => {
+            if let Err(e) = storage.record_cost(
+                cost_usd,
+                session_id.as_deref(),
+                model.as_deref(),
+                cumulative,
+            ) {
+                storage.write_failed("cost", e);
+            }
+        }
+        StorageCommand::RecordSessionMetric {
+            name,
+            value,
+            session_id,
+        } => {
+            if let Err(e) = storage.record_session_metric(&name, value, session_id.as_deref()) {
+                storage.write_failed("session metric", e);
+            }
+        }
+        StorageCommand::RecordAnomaly(anomaly) => {
+            if let Err(e) = storage.record_anomaly(&anomaly) {
+                storage.write_failed("anomaly", e);
+            }
+        }
+        StorageCommand::GetToolMetrics { since, until, tx } => {
+            let _ = tx.send(storage.get_tool_metrics(since, until));
+        }
+        StorageCommand::GetToolCallSeries {
+            since,
+            bucket_secs,
+            buckets,
+            tx,
+        } => {
+            let _ = tx.send(storage.get_tool_call_series(since, bucket_secs, buckets));
+        }
+        StorageCommand::GetInFlightTools { since, tx } => {
+            let _ = tx.send(storage.get_in_flight_tools(since));
+        }
+        StorageCommand::GetSessionTimeline { tx } => {
+            let _ = tx.send(storage.get_session_timeline());
+        }
+        StorageCommand::GetHourlyActivity { since, until, tx } => {
+            let _ = tx.send(storage.get_hourly_activity(since, until));
+        }
+        StorageCommand::GetMcpServerHealth { since, until, tx } => {
+            let _ = tx.send(storage.get_mcp_server_health(since, until));
+        }
+        StorageCommand::IsEmpty { tx } => {
+            let _ = tx.send(storage.is_empty());
+        }
+        StorageCommand::GetDataVersion { tx } => {
+            let _ = tx.send(storage.get_data_version());
+        }
+        StorageCommand::ExportTable {
+            table,
+            since,
+            until,
+            format,
+            path,
+            tx,
+        } => {
+            let _ = tx.send(storage.export_table(&table, since, until, format, &path));
+        }
+        StorageCommand::Prune {
+            before,
+            dry_run,
+            tx,
+        } => {
+            let _ = tx.send(storage.prune(before, dry_run));
+        }
+        StorageCommand::GetModelCosts { since, until, tx } => {
+            let _ = tx.send(storage.get_model_costs(since, until));
+        }
+        StorageCommand::GetTopToolErrors {
+            since,
+            until,
+            limit,
+            tx,
+        } => {
+            let _ = tx.send(storage.get_top_tool_errors(since, until, limit));
+        }
+        StorageCommand::RunQuery { sql, limit, tx } => {
+            let _ = tx.send(storage.run_query(&sql, limit));
+        }
+        StorageCommand::GetLogEventsAfter {
+            after_id,
+            limit,
+            tx,
+        } => {
+            let _ = tx.send(storage.get_log_events_after(after_id, limit));
+        }
+        StorageCommand::GetLogEvents {
+            since,
+            until,
+            limit,
+            tx,
+        } => {
+            let _ = tx.send(storage.get_log_events(since, until, limit));
+        }
+        StorageCommand::GetRecentPrompts {
+            since,
+            until,
+            limit,
+            tx,
+        } => {
+            let _ = tx.send(storage.get_recent_prompts(since, until, limit));
+        }
+        StorageCommand::GetTurns {
+            since,
+            until,
+            limit,
+            tx,
+        } => {
+            let _ = tx.send(storage.get_turns(since, until, limit));
+        }
+        StorageCommand::GetToolSequences {
+            since,
+            until,
+            length,
+            limit,
+            tx,
+        } => {
+            let _ = tx.send(storage.get_tool_sequences(since, until, length, limit));
+        }
+        StorageCommand::GetFileActivity {
+            since,
+            until,
+            directories,
+            limit,
+            tx,
+        } => {
+            let _ = tx.send(storage.get_file_activity(since, until, directories, limit));
+        }
+        StorageCommand::GetTokenMetrics { since, until, tx } => {
+            let _ = tx.send(storage.get_token_metrics(since, until));
+        }
+        StorageCommand::GetCacheUsage { since, until, tx } => {
+            let _ = tx.send(storage.get_cache_usage(since, until));
+        }
+        StorageCommand::GetTotalCost { since, until, tx } => {
+            let _ = tx.send(storage.get_total_cost(since, until));
+        }
+        StorageCommand::GetLastToolError { tool_name, tx } => {
+            let _ = tx.send(storage.get_last_tool_error(&tool_name));
+        }
+        StorageCommand::GetSessionMetrics { since, until, tx } => {
+            let _ = tx.send(storage.get_session_metrics(since, until));
+        }
+        StorageCommand::GetApiMetrics { since, until, tx } => {
+            let _ = tx.send(storage.get_api_metrics(since, until));
+        }
+        StorageCommand::CountRateLimitErrors { since, until, tx } => {
+            let _ = tx.send(storage.count_rate_limit_errors(since, until));
+        }
+        StorageCommand::CountSessions { since, until, tx } => {
+            let _ = tx.send(storage.count_sessions(since, until));
+        }
+        StorageCommand::GetSessionSummary { session_id, tx } => {
+            let _ = tx.send(storage.get_session_summary(&session_id));
+        }
+        StorageCommand::GetSessions {
+            since,
+            until,
+            limit,
+            tx,
+        } => {
+            let _ = tx.send(storage.get_sessions(since, until, limit));
+        }
+        StorageCommand::ScoreSessions { limit, tx } => {
+            let _ = tx.send(storage.score_sessions(limit));
+        }
+        StorageCommand::GetAnomalies {
+            since,
+            until,
+            limit,
+            tx,
+        } => {
+            let _ = tx.send(storage.get_anomalies(since, until, limit));
+        }
+        StorageCommand::CountAnomalies { kind, tx } => {
+            let _ = tx.send(storage.count_anomalies(kind));
+        }
+        StorageCommand::GetBudgetSpend {
+            scope,
+            since,
+            until,
+            tx,
+        } => {
+            let _ = tx.send(storage.get_budget_spend(&scope, since, until));
+        }
+        StorageCommand::GetBudgetUsage {
+            budget,
+            open_only,
+            limit,
+            tx,
+        } => {
+            let _ = tx.send(storage.get_budget_usage(budget.as_deref(), open_only, limit));
+        }
+        StorageCommand::SaveBudgetUsage { usage, tx } => {
+            let _ = tx.send(storage.save_budget_usage(&usage));
+        }
+        StorageCommand::GetCostBreakdown { since, until, tx } => {
+            let _ = tx.send(storage.get_cost_breakdown(since, until));
+        }
+        StorageCommand::GetApiSpend { since, until, tx } => {
+            let _ = tx.send(storage.get_api_spend(since, until));
+        }
+        StorageCommand::GetSessionProductivity {
+            since,
+            until,
+            limit,
+            tx,
+        } => {
+            let _ = tx.send(storage.get_session_productivity(since, until, limit));
+        }
+        StorageCommand::GetProviderComparison { since, until, tx } => {
+            let _ = tx.send(storage.get_provider_comparison(since, until));
+        }
+        StorageCommand::GetContextSamples {
+            since,
+            until,
+            limit,
+            tx,
+        } => {
+            let _ = tx.send(storage.get_context_samples(since, until, limit));
+        }
+        StorageCommand::GetUsagePoints {
+            provider,
+            since,
+            tx,
+        } => {
+            let _ = tx.send(storage.get_usage_points(provider.as_deref(), since));
+        }
+        StorageCommand::GetErrorCategories { since, until, tx } => {
+            let _ = tx.send(storage.get_error_categories(since, until));
+        }
+        StorageCommand::GetErrorBreakdown {
+            since,
+            until,
+            limit,
+            tx,
+        } => {
+            let _ = tx.send(storage.get_error_breakdown(since, until, limit));
+        }
+        StorageCommand::ReclassifyErrors { tx } => {
+            let _ = tx.send(storage.reclassify_errors());
+        }
+        StorageCommand::StreamTokenUsage { since, until, tx } => {
+            if let Err(e) = storage.stream_token_usage(since, until, &tx) {
+                let _ = tx.send(Err(e.into()));
+            }
+        }
+        // Unwrapped by handle_command
+        StorageCommand::Scoped { cmd, .. } => return run_command(storage, *cmd),
+        StorageCommand::Shutdown => return false,
+    }
+    true
+}
+
+struct Storage {
+    conn: Connection,
+    /// Latest token_usage row per (token type, model, provider), for coalescing
+    recent_tokens: RefCell<LruCache<TokenKey, (i64, DateTime<Utc>)>>,
+    /// token_usage rows records were added to so far, for [`DataVersion`]
+    token_updates: Cell<u64>,
+    /// Writes that failed so far, so the journal keeps the ones that failed
+    failed_writes: Cell<u64>,
+}
+
+type TokenKey = (String, Option<String>, Option<String>);
+
+/// Settings for every connection. Unless `file_access` is set, SQL cannot touch files
+/// other than the database (`read_text`, `glob`, `COPY`, `ATTACH`, ...), which keeps
+/// ad-hoc queries from `/api/query` away from the rest of the disk; the configuration
+/// is locked so a query cannot `SET` it back.
+fn connection_config(file_access: bool) -> Result<duckdb::Config> {
+    Ok(duckdb::Config::default()
+        .enable_external_access(file_access)?
+        .with("lock_configuration", "true")?)
+}
+
+impl Storage {
+    fn with_connection(conn: Connection) -> Self {
+        conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+        Self {
+            conn,
+            recent_tokens: RefCell::new(LruCache::new(
+                "token_rows",
+                crate::cache::capacities().token_rows,
+            )),
+            token_updates: Cell::new(0),
+            failed_writes: Cell::new(0),
+        }
+    }
+
+    fn write_failed(&self, what: &str, error: anyhow::Error) {
+        tracing::error!("Failed to record {}: {}", what, error);
+        self.failed_writes.set(self.failed_writes.get() + 1);
+    }
+
+    fn open(db_path: &std::path::Path) -> Result<Self> {
+        // Ensure parent directory exists
+        if let Some(parent) = db_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let conn = Connection::open_with_flags(db_path, connection_config(false)?)?;
+        let storage = Self::with_connection(conn);
+        storage.check_schema(db_path)?;
+        storage.init_schema()?;
+        Ok(storage)
+    }
+
+    /// Like [`open`](Self::open), but statements may read and write other files, which
+    /// `COPY ... TO` needs
+    fn open_for_export(db_path: &std::path::Path) -> Result<Self> {
+        let conn = Connection::open_with_flags(db_path, connection_config(true)?)?;
+        let storage = Self::with_connection(conn);
+        storage.check_schema(db_path)?;
+        storage.init_schema()?;
+        Ok(storage)
+    }
+
+    /// Open an existing database read-only; the schema is left as it is
+    fn open_read_only(db_path: &std::path::Path) -> Result<Self> {
+        if !db_path.exists() {
+            anyhow::bail!("No database at {}", db_path.display());
+        }
+        let config = connection_config(false)?.access_mode(duckdb::AccessMode::ReadOnly)?;
+        let conn = Connection::open_with_flags(db_path, config).with_context(|| {
+            format!(
+                "Could not open {} read-only (is another agenttop writing to it?)",
+                db_path.display()
+            )
+        })?;
+        let storage = Self::with_connection(conn);
+        storage.check_schema(db_path)?;
+        Ok(storage)
+    }
+
+    /// Refuse a database written with a newer [`SCHEMA_VERSION`] (see
+    /// [`IncompatibleDatabase`](error::IncompatibleDatabase)) before touching it
+    fn check_schema(&self, db_path: &std::path::Path) -> Result<()> {
+        let has_meta: bool = self.conn.query_row(
+            "SELECT count(*) > 0 FROM information_schema.tables WHERE table_name = 'agenttop_meta'",
+            [],
+            |row| row.get(0),
+        )?;
+        // Written before versions were recorded
+        if !has_meta {
+            return Ok(());
+        }
+        let meta = |key: &str| -> Result<Option<String>> {
+            match self.conn.query_row(
+                "SELECT value FROM agenttop_meta WHERE key = ?",
+                params![key],
+                |row| row.get(0),
+            ) {
+                Ok(value) => Ok(Some(value)),
+                Err(duckdb::Error::QueryReturnedNoRows) => Ok(None),
+                Err(e) => Err(e.into()),
+            }
+        };
+        let schema = meta("schema_version")?
+            .and_then(|value| value.parse::<i64>().ok())
+            .unwrap_or(0);
+        if schema <= SCHEMA_VERSION {
+            return Ok(());
+        }
+        let unknown = || "an unknown version".to_string();
+        Err(error::IncompatibleDatabase {
+            path: db_path.display().to_string(),
+            written_by: meta("written_by")?.unwrap_or_else(unknown),
+            schema,
+            min_version: meta("min_version")?.unwrap_or_else(unknown),
+        }
+        .into())
+    }
+
+    /// Create an in-memory storage instance (for testing)
+    #[allow(dead_code)]
+    fn new_in_memory() -> Result<Self> {
+        let conn = Connection::open_in_memory_with_flags(connection_config(false)?)?;
+        let storage = Self::with_connection(conn);
+        storage.init_schema()?;
+        Ok(storage)
+    }
+
+    fn init_schema(&self) -> Result<()> {
+        let tracked = |table: &str| -> Result<bool> {
+            Ok(self.conn.query_row(
+                "SELECT count(*) > 0 FROM information_schema.tables WHERE table_name = ?",
+                params![table],
+                |row| row.get(0),
+            )?)
+        };
+        let files_tracked = tracked("file_events")?;
+        let activity_tracked = tracked("session_activity")?;
+        let errors_tracked = tracked("error_events")?;
+        // Note: Using BIGINT with GENERATED ALWAYS AS IDENTITY for auto-increment in DuckDB
+        self.conn.execute_batch(
+            r#"
+            CREATE SEQUENCE IF NOT EXISTS tool_events_seq;
+            CREATE TABLE IF NOT EXISTS tool_events (
+                id BIGINT DEFAULT nextval('tool_events_seq') PRIMARY KEY,
+                timestamp TIMESTAMP NOT NULL,
+                tool_name VARCHAR NOT NULL,
+                success BOOLEAN NOT NULL,
+                duration_ms BIGINT NOT NULL,
+                error VARCHAR
+            );
+
+            CREATE SEQUENCE IF NOT EXISTS log_events_seq;
+            CREATE TABLE IF NOT EXISTS log_events (
+                id BIGINT DEFAULT nextval('log_events_seq') PRIMARY KEY,
+                timestamp TIMESTAMP NOT NULL,
+                event_name VARCHAR,
+                body TEXT,
+                attributes JSON
+            );
+
+            CREATE SEQUENCE IF NOT EXISTS token_usage_seq;
+            CREATE TABLE IF NOT EXISTS token_usage (
+                id BIGINT DEFAULT nextval('token_usage_seq') PRIMARY KEY,
+                timestamp TIMESTAMP NOT NULL,
+                token_type VARCHAR NOT NULL,
+                count BIGINT NOT NULL,
+                model VARCHAR,
+                provider VARCHAR,
+                estimated_cost_usd DOUBLE
+            );
+            -- Added after the table was first released
+            ALTER TABLE token_usage ADD COLUMN IF NOT EXISTS model VARCHAR;
+            ALTER TABLE token_usage ADD COLUMN IF NOT EXISTS provider VARCHAR;
+            ALTER TABLE token_usage ADD COLUMN IF NOT EXISTS estimated_cost_usd DOUBLE;
+
+            CREATE SEQUENCE IF NOT EXISTS cost_usage_seq;
+            CREATE TABLE IF NOT EXISTS cost_usage (
+                id BIGINT DEFAULT nextval('cost_usage_seq') PRIMARY KEY,
+                timestamp TIMESTAMP NOT NULL,
+                cost_usd DOUBLE NOT NULL,
+                session_id VARCHAR,
+                model VARCHAR,
+                total_usd DOUBLE
+            );
+            -- Added after the table was first released
+            ALTER TABLE cost_usage ADD COLUMN IF NOT EXISTS session_id VARCHAR;
+            ALTER TABLE cost_usage ADD COLUMN IF NOT EXISTS model VARCHAR;
+            ALTER TABLE cost_usage ADD COLUMN IF NOT EXISTS total_usd DOUBLE;
+
+            CREATE SEQUENCE IF NOT EXISTS session_metrics_seq;
+            CREATE TABLE IF NOT EXISTS session_metrics (
+                id BIGINT DEFAULT nextval('session_metrics_seq') PRIMARY KEY,
+                timestamp TIMESTAMP NOT NULL,
+                metric_name VARCHAR NOT NULL,
+                value BIGINT NOT NULL,
+                session_id VARCHAR
+            );
+            -- Added after the table was first released
+            ALTER TABLE session_metrics ADD COLUMN IF NOT EXISTS session_id VARCHAR;
+
+            -- Derived from log_events by the storage actor (see sessions.rs)
+            CREATE TABLE IF NOT EXISTS sessions (
+                session_id VARCHAR PRIMARY KEY,
+                inferred BOOLEAN NOT NULL,
+                provider VARCHAR,
+                started_at TIMESTAMP NOT NULL,
+                ended_at TIMESTAMP NOT NULL,
+                events BIGINT NOT NULL,
+                api_calls BIGINT NOT NULL,
+                api_errors BIGINT NOT NULL,
+                tool_calls BIGINT NOT NULL,
+                cost_usd DOUBLE NOT NULL,
+                input_tokens BIGINT NOT NULL,
+                output_tokens BIGINT NOT NULL
+            );
+
+            -- Written by the anomaly detector (see anomaly/mod.rs)
+            CREATE SEQUENCE IF NOT EXISTS anomalies_seq;
+            CREATE TABLE IF NOT EXISTS anomalies (
+                id BIGINT DEFAULT nextval('anomalies_seq') PRIMARY KEY,
+                timestamp TIMESTAMP NOT NULL,
+                kind VARCHAR NOT NULL,
+                scope VARCHAR NOT NULL,
+                subject VARCHAR NOT NULL,
+                value DOUBLE NOT NULL,
+                baseline DOUBLE NOT NULL
+            );
+
+            -- Kept by the budget monitor (see budget/mod.rs)
+            CREATE TABLE IF NOT EXISTS budget_periods (
+                budget VARCHAR NOT NULL,
+                period_start TIMESTAMP NOT NULL,
+                period_end TIMESTAMP NOT NULL,
+                limit_usd DOUBLE NOT NULL,
+                spent_usd DOUBLE NOT NULL,
+                closed BOOLEAN NOT NULL,
+                updated_at TIMESTAMP NOT NULL,
+                PRIMARY KEY (budget, period_start)
+            );
+
+            -- Kept by the session scorer (see efficiency/mod.rs)
+            CREATE TABLE IF NOT EXISTS session_scores (
+                session_id VARCHAR PRIMARY KEY,
+                scored_through TIMESTAMP NOT NULL,
+                score DOUBLE,
+                success_rate DOUBLE,
+                cache_reuse DOUBLE,
+                retry_rate DOUBLE,
+                cost_per_outcome DOUBLE,
+                outcomes BIGINT NOT NULL
+            );
+
+            -- Derived from tool_result events by the storage actor (see files.rs)
+            CREATE TABLE IF NOT EXISTS file_events (
+                timestamp TIMESTAMP NOT NULL,
+                session_id VARCHAR,
+                tool_name VARCHAR NOT NULL,
+                path VARCHAR NOT NULL,
+                directory VARCHAR NOT NULL,
+                edit BOOLEAN NOT NULL
+            );
+
+            -- Active segments of each session, from event density (see activity.rs)
+            CREATE TABLE IF NOT EXISTS session_activity (
+                session_id VARCHAR NOT NULL,
+                started_at TIMESTAMP NOT NULL,
+                ended_at TIMESTAMP NOT NULL
+            );
+
+            -- Failed tool calls and api_errors with their category (see errors.rs)
+            CREATE TABLE IF NOT EXISTS error_events (
+                timestamp TIMESTAMP NOT NULL,
+                session_id VARCHAR,
+                provider VARCHAR,
+                kind VARCHAR NOT NULL,
+                source VARCHAR,
+                category VARCHAR NOT NULL,
+                message VARCHAR
+            );
+            "#,
+        )?;
+        self.conn.execute_batch(
+            r#"
+            CREATE INDEX IF NOT EXISTS idx_tool_events_timestamp ON tool_events(timestamp);
+            CREATE INDEX IF NOT EXISTS idx_tool_events_tool_name ON tool_events(tool_name);
+            CREATE INDEX IF NOT EXISTS idx_log_events_timestamp ON log_events(timestamp);
+            CREATE INDEX IF NOT EXISTS idx_log_events_event_name ON log_events(event_name);
+            CREATE INDEX IF NOT EXISTS idx_token_usage_timestamp ON token_usage(timestamp);
+            CREATE INDEX IF NOT EXISTS idx_file_events_timestamp ON file_events(timestamp);
+            CREATE INDEX IF NOT EXISTS idx_session_activity_session_id ON session_activity(session_id);
+            CREATE INDEX IF NOT EXISTS idx_error_events_timestamp ON error_events(timestamp);
+
+            CREATE TABLE IF NOT EXISTS agenttop_meta (
+                key VARCHAR PRIMARY KEY,
+                value VARCHAR NOT NULL
+            );
+            "#,
+        )?;
+        self.rebuild_sessions()?;
+        if !files_tracked {
+            self.rebuild_file_events()?;
+        }
+        if !activity_tracked {
+            self.rebuild_session_activity()?;
+        }
+        if !errors_tracked {
+            self.rebuild_error_events()?;
+        }
+        self.estimate_token_costs()?;
+        // Lets an older agenttop tell it can't use the database (see `check_schema`)
+        self.conn.execute(
+            "INSERT OR REPLACE INTO agenttop_meta VALUES \
+             ('schema_version', ?), ('written_by', ?), ('min_version', ?)",
+            params![
+                SCHEMA_VERSION.to_string(),
+                env!("CARGO_PKG_VERSION"),
+                SCHEMA_SINCE
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Price the token_usage rows stored without an estimate, e.g. before estimates were
+    /// stored or before their model's pricing was known
+    fn estimate_token_costs(&self) -> Result<()> {
+        let unpriced = self
+            .conn
+            .prepare(
+                "SELECT DISTINCT token_type, model FROM token_usage \
+                 WHERE estimated_cost_usd IS NULL AND model IS NOT NULL",
+            )?
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<duckdb::Result<Vec<_>>>()?;
+        for (token_type, model) in unpriced {
+            let Some(per_mtok) = estimate_token_cost(&token_type, 1_000_000, Some(&model)) else {
+                continue;
+            };
+            self.conn.execute(
+                "UPDATE token_usage SET estimated_cost_usd = count * ? / 1000000 \
+                 WHERE estimated_cost_usd IS NULL AND token_type = ? AND model = ?",
+                params![per_mtok, token_type, model],
+            )?;
+        }
+        Ok(())
+    }
+
+    fn record_tool_event(&self, event: &ToolEvent) -> Result<()> {
+        self.conn.prepare_cached("INSERT INTO tool_events (timestamp, tool_name, success, duration_ms, error) VALUES (?, ?, ?, ?, ?)")?.execute(
+            params![
+                SqlTimestamp(event.timestamp),
+                event.tool_name,
+                event.success,
+                event.duration_ms as i64,
+                event.error,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn insert_log_events(&self, events: &[LogEvent]) -> Result<()> {
+        for event in events {
+            let attributes_json = serde_json::to_string(&event.attributes)?;
+            self.conn.prepare_cached("INSERT INTO log_events (timestamp, event_name, body, attributes) VALUES (?, ?, ?, ?)")?.execute(
+                params![
+                    SqlTimestamp(event.timestamp),
+                    event.event_name,
+                    event.body,
+                    attributes_json,
+                ],
+            )?;
+        }
+        self.track_sessions(events)?;
+        self.track_files(events)?;
+        self.track_errors(events)
+    }
+
+    fn record_token_usage(
+        &self,
+        token_type: &str,
+        count: u64,
+        model: Option<&str>,
+        provider: Option<&str>,
+    ) -> Result<()> {
+        tracing::debug!(
+            "Token received: type={}, count={}, model={:?}",
+            token_type,
+            count,
+            model
+        );
+        // Agents flush many small data points at once; add them to a recent row of the
+        // same kind rather than growing the table by one row each
+        let now = Utc::now();
+        let key = (
+            token_type.to_string(),
+            model.map(str::to_string),
+            provider.map(str::to_string),
+        );
+        let mut recent = self.recent_tokens.borrow_mut();
+        let window = |time: DateTime<Utc>| {
+            time.timestamp_millis()
+                .div_euclid(TOKEN_COALESCE_WINDOW.as_millis() as i64)
+        };
+        let coalesce_into = recent
+            .get(&key)
+            .and_then(|&(id, first)| (window(first) == window(now)).then_some(id));
+        let estimate = estimate_token_cost(token_type, count, model);
+        if let Some(id) = coalesce_into {
+            let updated = self
+                .conn
+                .prepare_cached(
+                    "UPDATE token_usage SET count = count + ?, \
+                     estimated_cost_usd = estimated_cost_usd + ? WHERE id = ?",
+                )?
+                .execute(params![count as i64, estimate, id])?;
+            // Zero when the row was pruned meanwhile
+            if updated > 0 {
+                self.token_updates.set(self.token_updates.get() + 1);
+                return Ok(());
+            }
+        }
+
+        let id: i64 = self.conn.prepare_cached("INSERT INTO token_usage (timestamp, token_type, count, model, provider, estimated_cost_usd) VALUES (?, ?, ?, ?, ?, ?) RETURNING id")?.query_row(
+            params![
+                SqlTimestamp(now),
+                token_type,
+                count as i64,
+                model,
+                provider,
+                estimate
+            ],
+            |row| row.get(0),
+        )?;
+        recent.put(key, (id, now));
+        Ok(())
+    }
+
+    /// Store spend as the increment it adds, so every query can sum `cost_usd`. A
+    /// cumulative report keeps its running total in `total_usd`; the next report of the
+    /// same session and model counts only what it adds to that total, and one below it
+    /// means the agent's counter started over.
+    fn record_cost(
+        &self,
+        cost_usd: f64,
+        session_id: Option<&str>,
+        model: Option<&str>,
+        cumulative: bool,
+    ) -> Result<()> {
+        let (increment, total) = if cumulative {
+            let previous = match self
+                .conn
+                .prepare_cached(
+                    "SELECT total_usd FROM cost_usage \
+                     WHERE total_usd IS NOT NULL \
+                       AND session_id IS NOT DISTINCT FROM ? \
+                       AND model IS NOT DISTINCT FROM ? \
+                     ORDER BY id DESC LIMIT 1",
+                )?
+                .query_row(params![session_id, model], |row| row.get::<_, f64>(0))
+            {
+                Ok(total) => Some(total),
+                Err(duckdb::Error::QueryReturnedNoRows) => None,
+                Err(e) => return Err(e.into()),
+            };
+            let increment = match previous {
+                Some(previous) if cost_usd >= previous => cost_usd - previous,
+                _ => cost_usd,
+            };
+            (increment, Some(cost_usd))
+        } else {
+            (cost_usd, None)
+        };
+
+        self.conn
+            .prepare_cached(
+                "INSERT INTO cost_usage (timestamp, cost_usd, session_id, model, total_usd) \
+                 VALUES (?, ?, ?, ?, ?)",
+            )?
+            .execute(params![
+                SqlTimestamp(Utc::now()),
+                increment,
+                session_id,
+                model,
+                total
+            ])?;
+        Ok(())
+    }
+
+    fn record_session_metric(
+        &self,
+        metric_name: &str,
+        value: i64,
+        session_id: Option<&str>,
+    ) -> Result<()> {
+        self.conn
+            .prepare_cached(
+                "INSERT INTO session_metrics (timestamp, metric_name, value, session_id) \
+                 VALUES (?, ?, ?, ?)",
+            )?
+            .execute(params![
+                SqlTimestamp(Utc::now()),
+                metric_name,
+                value,
+                session_id
+            ])?;
+        Ok(())
+    }
+
+    fn get_tool_metrics(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<Vec<ToolMetrics>> {
+        // Query that combines both legacy tool_events and new log_events tables
+        // The log_events query filters by event_name at query time (not ingestion)
+        // This matches both "tool_result" and "claude_code.tool_result"
+        let cte = combined_tool_events_cte(&time_clause("AND", since, until));
+
+        let query = format!(
+            r#"
+            {cte}
+            SELECT
+                tool_name,
+                COUNT(*) as call_count,
+                MAX(timestamp) as last_call,
+                AVG(duration_ms) as avg_duration_ms,
+                MIN(duration_ms) as min_duration_ms,
+                MAX(duration_ms) as max_duration_ms,
+                SUM(CASE WHEN success THEN 1 ELSE 0 END) as success_count,
+                SUM(CASE WHEN NOT success THEN 1 ELSE 0 END) as error_count,
+                SUM(CASE WHEN decision IN ('approved', 'auto_approved') THEN 1 ELSE 0 END) as approved_count,
+                SUM(CASE WHEN decision = 'rejected' THEN 1 ELSE 0 END) as rejected_count
+            FROM combined_events
+            GROUP BY tool_name
+            ORDER BY call_count DESC
+            "#
+        );
+
+        let mut stmt = self.conn.prepare_cached(&query)?;
+
+        let rows = stmt.query_map(time_params(since, until), |row| {
+            Ok(ToolMetrics {
+                tool_name: row.get(0)?,
+                call_count: row.get::<_, i64>(1)? as u64,
+                last_call: row.get::<_, Option<SqlTimestamp>>(2)?.map(|t| t.0),
+                avg_duration_ms: row.get(3)?,
+                min_duration_ms: row.get(4)?,
+                max_duration_ms: row.get(5)?,
+                success_count: row.get::<_, i64>(6)? as u64,
+                error_count: row.get::<_, i64>(7)? as u64,
+                approved_count: row.get::<_, i64>(8)? as u64,
+                rejected_count: row.get::<_, i64>(9)? as u64,
+            })
+        })?;
+
+        let mut metrics = Vec::new();
+        for row in rows {
+            metrics.push(row?);
+        }
+        Ok(metrics)
+    }
+
+    fn get_tool_call_series(
+        &self,
+        since: DateTime<Utc>,
+        bucket_secs: i64,
+        buckets: usize,
+    ) -> Result<HashMap<String, Vec<u64>>> {
+        let bucket_secs = bucket_secs.max(1);
+        let until = since + chrono::Duration::seconds(bucket_secs * buckets as i64);
+        let cte = combined_tool_events_cte(&time_clause("AND", Some(since), Some(until)));
+
+        let query = format!(
+            r#"
+            {cte}
+            SELECT
+                tool_name,
+                CAST(FLOOR((epoch(timestamp) - epoch(?1)) / {bucket_secs}) AS BIGINT) as bucket,
+                COUNT(*) as call_count
+            FROM combined_events
+            GROUP BY tool_name, bucket
+            "#
+        );
+
+        let mut stmt = self.conn.prepare_cached(&query)?;
+        let rows = stmt.query_map(time_params(Some(since), Some(until)), |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, i64>(2)? as u64,
+            ))
+        })?;
+
+        let mut series: HashMap<String, Vec<u64>> = HashMap::new();
+        for row in rows {
+            let (tool_name, bucket, count) = row?;
+            if bucket < 0 || bucket as usize >= buckets {
+                continue;
+            }
+            series.entry(tool_name).or_insert_with(|| vec![0; buckets])[bucket as usize] += count;
+        }
+        Ok(series)
+    }
+
+    /// Start events are pre-tool events (tool_start, pre_tool_use) or accepted
+    /// tool_decision events; a tool is in flight while its latest start is newer
+    /// than its latest tool_result.
+    fn get_in_flight_tools(&self, since: DateTime<Utc>) -> Result<Vec<InFlightTool>> {
+        let time_clause = time_clause("AND", Some(since), None);
+
+        let query = format!(
+            r#"
+            WITH starts AS (
+                SELECT
+                    json_extract_string(attributes, '$.tool_name') as tool_name,
+                    MAX(timestamp) as started_at
+                FROM log_events
+                WHERE (
+                    event_name LIKE '%tool_start'
+                    OR event_name LIKE '%pre_tool_use'
+                    OR (
+                        event_name LIKE '%tool_decision'
+                        AND COALESCE(json_extract_string(attributes, '$.decision'), '')
+                            NOT IN ('reject', 'rejected', 'deny', 'denied')
+                    )
+                ) {time_clause}
+                GROUP BY 1
+            ),
+            results AS (
+                SELECT
+                    json_extract_string(attributes, '$.tool_name') as tool_name,
+                    MAX(timestamp) as finished_at
+                FROM log_events
+                WHERE event_name LIKE '%tool_result' {time_clause}
+                GROUP BY 1
+            )
+            SELECT s.tool_name, s.started_at
+            FROM starts s
+            LEFT JOIN results r ON s.tool_name = r.tool_name
+            WHERE s.tool_name IS NOT NULL
+              AND (r.finished_at IS NULL OR r.finished_at < s.started_at)
+            ORDER BY s.started_at
+            "#
+        );
+
+        let mut stmt = self.conn.prepare_cached(&query)?;
+        let rows = stmt.query_map(time_params(Some(since), None), |row| {
+            Ok(InFlightTool {
+                tool_name: row.get(0)?,
+                started_at: row.get::<_, SqlTimestamp>(1)?.0,
+            })
+        })?;
+
+        let mut tools = Vec::new();
+        for row in rows {
+            tools.push(row?);
+        }
+        Ok(tools)
+    }
+
+    fn get_token_metrics(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<TokenMetrics> {
+        let time_clause = time_clause("WHERE", since, until);
+
+        let query = format!(
+            r#"
+            SELECT
+                token_type,
+                SUM(count) as total,
+                COALESCE(SUM(estimated_cost_usd), 0)
+            FROM token_usage
+            {time_clause}
+            GROUP BY token_type
+            "#
+        );
 
-    fn init_schema(&self) -> Result<()> {
-        // Note: Using BIGINT with GENERATED ALWAYS AS IDENTITY for auto-increment in DuckDB
-        self.conn.execute_batch(
+        let mut stmt = self.conn.prepare_cached(&query)?;
+
+        let mut metrics = TokenMetrics::default();
+
+        let rows = stmt.query_map(time_params(since, until), |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, i64>(1)? as u64,
+                row.get::<_, f64>(2)?,
+            ))
+        })?;
+
+        for row in rows {
+            let (token_type, count, estimated_cost_usd) = row?;
+            metrics.estimated_cost_usd += estimated_cost_usd;
+            // Use provider registry to normalize token types
+            match PROVIDER_REGISTRY.normalize_token_type(&token_type) {
+                Some(TOKEN_INPUT) => metrics.input_tokens += count,
+                Some(TOKEN_OUTPUT) => metrics.output_tokens += count,
+                Some(TOKEN_CACHE_READ) => metrics.cache_read_tokens += count,
+                Some(TOKEN_CACHE_WRITE) => metrics.cache_creation_tokens += count,
+                _ => {
+                    tracing::warn!("Unknown token type: {}", token_type);
+                }
+            }
+        }
+
+        // Get total cost
+        metrics.total_cost_usd = self.get_total_cost(since, until)?;
+
+        Ok(metrics)
+    }
+
+    fn get_cache_usage(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<Vec<CacheUsage>> {
+        let time_clause = time_clause("WHERE", since, until);
+        let query = format!(
             r#"
-            CREATE SEQUENCE IF NOT EXISTS tool_events_seq;
-            CREATE TABLE IF NOT EXISTS tool_events (
-                id BIGINT DEFAULT nextval('tool_events_seq') PRIMARY KEY,
-                timestamp TIMESTAMP NOT NULL,
-                tool_name VARCHAR NOT NULL,
-                success BOOLEAN NOT NULL,
-                duration_ms BIGINT NOT NULL,
-                error VARCHAR
-            );
+            SELECT date_trunc('hour', timestamp) as hour, model, token_type, SUM(count)
+            FROM token_usage
+            {time_clause}
+            GROUP BY hour, model, token_type
+            ORDER BY hour, model
+            "#
+        );
 
-            CREATE SEQUENCE IF NOT EXISTS log_events_seq;
-            CREATE TABLE IF NOT EXISTS log_events (
-                id BIGINT DEFAULT nextval('log_events_seq') PRIMARY KEY,
-                timestamp TIMESTAMP NOT NULL,
-                event_name VARCHAR,
-                body TEXT,
-                attributes JSON
-            );
+        let mut stmt = self.conn.prepare_cached(&query)?;
+        let rows = stmt.query_map(time_params(since, until), |row| {
+            Ok((
+                row.get::<_, SqlTimestamp>(0)?.0,
+                row.get::<_, Option<String>>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, i64>(3)?.max(0) as u64,
+            ))
+        })?;
 
-            CREATE SEQUENCE IF NOT EXISTS token_usage_seq;
-            CREATE TABLE IF NOT EXISTS token_usage (
-                id BIGINT DEFAULT nextval('token_usage_seq') PRIMARY KEY,
-                timestamp TIMESTAMP NOT NULL,
-                token_type VARCHAR NOT NULL,
-                count BIGINT NOT NULL
-            );
+        let mut usage: Vec<CacheUsage> = Vec::new();
+        for row in rows {
+            let (hour, model, token_type, count) = row?;
+            let counts = match usage.last_mut() {
+                Some(last) if last.hour == hour && last.model == model => last,
+                _ => {
+                    usage.push(CacheUsage {
+                        hour,
+                        model,
+                        input_tokens: 0,
+                        cache_read_tokens: 0,
+                        cache_creation_tokens: 0,
+                    });
+                    usage.last_mut().expect("just pushed")
+                }
+            };
+            match PROVIDER_REGISTRY.normalize_token_type(&token_type) {
+                Some(TOKEN_INPUT) => counts.input_tokens += count,
+                Some(TOKEN_CACHE_READ) => counts.cache_read_tokens += count,
+                Some(TOKEN_CACHE_WRITE) => counts.cache_creation_tokens += count,
+                _ => {}
+            }
+        }
+        // Hours with only output tokens
+        usage.retain(|u| u.input_tokens + u.cache_read_tokens + u.cache_creation_tokens > 0);
+        Ok(usage)
+    }
 
-            CREATE SEQUENCE IF NOT EXISTS cost_usage_seq;
-            CREATE TABLE IF NOT EXISTS cost_usage (
-                id BIGINT DEFAULT nextval('cost_usage_seq') PRIMARY KEY,
-                timestamp TIMESTAMP NOT NULL,
-                cost_usd DOUBLE NOT NULL
-            );
+    fn get_total_cost(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<f64> {
+        let cost_clause = time_clause("WHERE", since, until);
+        let cost_query = format!("SELECT COALESCE(SUM(cost_usd), 0) FROM cost_usage {cost_clause}");
+        let cost: f64 = self
+            .conn
+            .prepare_cached(&cost_query)?
+            .query_row(time_params(since, until), |row| row.get(0))?;
+        Ok(cost)
+    }
 
-            CREATE SEQUENCE IF NOT EXISTS session_metrics_seq;
-            CREATE TABLE IF NOT EXISTS session_metrics (
-                id BIGINT DEFAULT nextval('session_metrics_seq') PRIMARY KEY,
-                timestamp TIMESTAMP NOT NULL,
-                metric_name VARCHAR NOT NULL,
-                value BIGINT NOT NULL
-            );
+    fn get_session_timeline(&self) -> Result<SessionTimeline> {
+        const SESSION_ID: &str = r#"json_extract_string(attributes, '$."session.id"')"#;
+        const TIMELINE_EVENTS: &str = "(event_name LIKE '%tool_result' OR event_name LIKE '%api_request' OR event_name LIKE '%api_error')";
 
-            CREATE INDEX IF NOT EXISTS idx_tool_events_timestamp ON tool_events(timestamp);
-            CREATE INDEX IF NOT EXISTS idx_tool_events_tool_name ON tool_events(tool_name);
-            CREATE INDEX IF NOT EXISTS idx_log_events_timestamp ON log_events(timestamp);
-            CREATE INDEX IF NOT EXISTS idx_log_events_event_name ON log_events(event_name);
-            CREATE INDEX IF NOT EXISTS idx_token_usage_timestamp ON token_usage(timestamp);
-            "#,
-        )?;
-        Ok(())
+        let session_query = format!(
+            "SELECT {SESSION_ID} FROM log_events WHERE {TIMELINE_EVENTS} AND {SESSION_ID} IS NOT NULL ORDER BY timestamp DESC LIMIT 1"
+        );
+        let session_id: Option<String> = self
+            .conn
+            .prepare_cached(&session_query)?
+            .query_row([], |row| row.get(0))
+            .ok();
+
+        let (filter, param) = match &session_id {
+            Some(id) => (format!("AND {SESSION_ID} = ?1"), Value::Text(id.clone())),
+            None => {
+                let since = Utc::now() - chrono::Duration::hours(1);
+                (
+                    time_clause("AND", Some(since), None),
+                    SqlTimestamp(since).into(),
+                )
+            }
+        };
+
+        let query = format!(
+            r#"
+            SELECT
+                timestamp,
+                event_name,
+                COALESCE(json_extract_string(attributes, '$.tool_name'), json_extract_string(attributes, '$.model'), 'unknown'),
+                COALESCE(CAST(json_extract(attributes, '$.duration_ms') AS BIGINT), 0),
+                CASE
+                    WHEN json_extract_string(attributes, '$.success') IN ('true', '1') THEN true
+                    WHEN json_extract(attributes, '$.success') = true THEN true
+                    ELSE false
+                END as success
+            FROM log_events
+            WHERE {TIMELINE_EVENTS} {filter}
+            ORDER BY timestamp
+            "#
+        );
+
+        let mut stmt = self.conn.prepare_cached(&query)?;
+        let rows = stmt.query_map([param], |row| {
+            Ok((
+                row.get::<_, SqlTimestamp>(0)?.0,
+                row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+                row.get::<_, String>(2)?,
+                row.get::<_, i64>(3)?,
+                row.get::<_, bool>(4)?,
+            ))
+        })?;
+
+        let mut events = Vec::new();
+        for row in rows {
+            let (timestamp, event_name, name, duration_ms, success) = row?;
+            let kind = if event_name.ends_with("api_error") {
+                TimelineKind::Error
+            } else if event_name.ends_with("api_request") {
+                TimelineKind::Api
+            } else if success {
+                TimelineKind::Tool
+            } else {
+                TimelineKind::Error
+            };
+            events.push(TimelineEvent {
+                timestamp,
+                kind,
+                name,
+                duration_ms: duration_ms.max(0) as u64,
+            });
+        }
+
+        Ok(SessionTimeline { session_id, events })
     }
 
-    fn record_tool_event(&self, event: &ToolEvent) -> Result<()> {
-        self.conn.execute(
-            "INSERT INTO tool_events (timestamp, tool_name, success, duration_ms, error) VALUES (?, ?, ?, ?, ?)",
-            params![
-                event.timestamp.to_rfc3339(),
-                event.tool_name,
-                event.success,
-                event.duration_ms as i64,
-                event.error,
-            ],
-        )?;
-        Ok(())
+    fn get_hourly_activity(
+        &self,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> Result<Vec<HourlyActivity>> {
+        let time_filter = time_clause("AND", Some(since), Some(until));
+        let cte = combined_tool_events_cte(&time_filter);
+
+        let query = format!(
+            r#"
+            {cte},
+            calls AS (
+                SELECT date_trunc('hour', timestamp) as hour, COUNT(*) as tool_calls
+                FROM combined_events
+                GROUP BY hour
+            ),
+            costs AS (
+                SELECT date_trunc('hour', timestamp) as hour, SUM(cost_usd) as cost_usd
+                FROM cost_usage
+                WHERE 1=1 {time_filter}
+                GROUP BY hour
+            )
+            SELECT
+                COALESCE(calls.hour, costs.hour) as hour,
+                COALESCE(calls.tool_calls, 0),
+                COALESCE(costs.cost_usd, 0)
+            FROM calls
+            FULL OUTER JOIN costs ON calls.hour = costs.hour
+            ORDER BY 1
+            "#
+        );
+
+        let mut stmt = self.conn.prepare_cached(&query)?;
+        let rows = stmt.query_map(time_params(Some(since), Some(until)), |row| {
+            Ok(HourlyActivity {
+                hour: row.get::<_, SqlTimestamp>(0)?.0,
+                tool_calls: row.get::<_, i64>(1)?.max(0) as u64,
+                cost_usd: row.get(2)?,
+            })
+        })?;
+
+        let mut hours = Vec::new();
+        for row in rows {
+            hours.push(row?);
+        }
+        Ok(hours)
     }
 
-    fn insert_log_events(&self, events: &[LogEvent]) -> Result<()> {
-        for event in events {
-            let attributes_json = serde_json::to_string(&event.attributes)?;
-            self.conn.execute(
-                "INSERT INTO log_events (timestamp, event_name, body, attributes) VALUES (?, ?, ?, ?)",
-                params![
-                    event.timestamp.to_rfc3339(),
-                    event.event_name,
-                    event.body,
-                    attributes_json,
-                ],
-            )?;
+    fn get_mcp_server_health(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<Vec<McpServerHealth>> {
+        let cte = combined_tool_events_cte(&time_clause("AND", since, until));
+        let query = format!(
+            r#"
+            {cte}
+            SELECT tool_name, duration_ms, success, timestamp
+            FROM combined_events
+            "#
+        );
+
+        let mut stmt = self.conn.prepare_cached(&query)?;
+        let rows = stmt.query_map(time_params(since, until), |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, bool>(2)?,
+                row.get::<_, SqlTimestamp>(3)?.0,
+            ))
+        })?;
+
+        // Group calls by server, keeping durations for the percentile
+        struct ServerCalls {
+            tools: std::collections::HashSet<String>,
+            durations: Vec<u64>,
+            errors: u64,
+            last_seen: Option<DateTime<Utc>>,
         }
-        Ok(())
+        let mut servers: HashMap<String, ServerCalls> = HashMap::new();
+        for row in rows {
+            let (tool_name, duration_ms, success, timestamp) = row?;
+            if PROVIDER_REGISTRY.is_any_builtin_tool(&tool_name) {
+                continue;
+            }
+            let server_name = parse_mcp_tool_name(&tool_name)
+                .map(|info| info.server_name)
+                .unwrap_or_else(|| tool_name.clone());
+            let server = servers.entry(server_name).or_insert_with(|| ServerCalls {
+                tools: std::collections::HashSet::new(),
+                durations: Vec::new(),
+                errors: 0,
+                last_seen: None,
+            });
+            server.tools.insert(tool_name);
+            server.durations.push(duration_ms.max(0) as u64);
+            if !success {
+                server.errors += 1;
+            }
+            server.last_seen = server.last_seen.max(Some(timestamp));
+        }
+
+        let mut health: Vec<McpServerHealth> = servers
+            .into_iter()
+            .map(|(server_name, mut calls)| {
+                calls.durations.sort_unstable();
+                let rank = (calls.durations.len() as f64 * 0.95).ceil() as usize;
+                McpServerHealth {
+                    server_name,
+                    tool_count: calls.tools.len(),
+                    call_count: calls.durations.len() as u64,
+                    error_count: calls.errors,
+                    p95_duration_ms: calls.durations[rank.saturating_sub(1)],
+                    last_seen: calls.last_seen,
+                }
+            })
+            .collect();
+        health.sort_by(|a, b| {
+            b.call_count
+                .cmp(&a.call_count)
+                .then_with(|| a.server_name.cmp(&b.server_name))
+        });
+        Ok(health)
     }
 
-    fn record_token_usage(&self, token_type: &str, count: u64) -> Result<()> {
-        tracing::debug!("Token received: type={}, count={}", token_type, count);
-        self.conn.execute(
-            "INSERT INTO token_usage (timestamp, token_type, count) VALUES (?, ?, ?)",
-            params![Utc::now().to_rfc3339(), token_type, count as i64],
-        )?;
-        Ok(())
+    fn export_table(
+        &self,
+        table: &str,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        format: TableFormat,
+        path: &std::path::Path,
+    ) -> Result<u64> {
+        if !TABLES.contains(&table) {
+            anyhow::bail!(
+                "Unknown table \"{}\" (tables: {})",
+                table,
+                TABLES.join(", ")
+            );
+        }
+        let options = match format {
+            TableFormat::Csv => "FORMAT CSV, HEADER",
+            TableFormat::Json => "FORMAT JSON",
+            TableFormat::Parquet => "FORMAT PARQUET",
+        };
+        let query = format!(
+            "COPY (SELECT * FROM {table} {} ORDER BY id) TO '{}' ({options})",
+            time_clause("WHERE", since, until),
+            path.display().to_string().replace('\'', "''")
+        );
+        Ok(self.conn.execute(&query, time_params(since, until))? as u64)
     }
 
-    fn record_cost(&self, cost_usd: f64) -> Result<()> {
-        self.conn.execute(
-            "INSERT INTO cost_usage (timestamp, cost_usd) VALUES (?, ?)",
-            params![Utc::now().to_rfc3339(), cost_usd],
-        )?;
-        Ok(())
+    fn prune(&self, before: DateTime<Utc>, dry_run: bool) -> Result<Vec<(String, u64)>> {
+        let filter = time_clause("WHERE", None, Some(before));
+        let mut counts = Vec::new();
+        if dry_run {
+            for table in TABLES {
+                let rows: i64 = self.conn.query_row(
+                    &format!("SELECT COUNT(*) FROM {table} {filter}"),
+                    time_params(None, Some(before)),
+                    |row| row.get(0),
+                )?;
+                counts.push((table.to_string(), rows as u64));
+            }
+            return Ok(counts);
+        }
+
+        self.conn.execute_batch("BEGIN TRANSACTION")?;
+        for table in TABLES {
+            match self.conn.execute(
+                &format!("DELETE FROM {table} {filter}"),
+                time_params(None, Some(before)),
+            ) {
+                Ok(rows) => counts.push((table.to_string(), rows as u64)),
+                Err(e) => {
+                    let _ = self.conn.execute_batch("ROLLBACK");
+                    return Err(e.into());
+                }
+            }
+        }
+        // Sessions, their activity and file events are derived from log events, so they
+        // go with them (a session with its last event)
+        if let Err(e) = self
+            .conn
+            .execute(
+                "DELETE FROM sessions WHERE ended_at < ?",
+                params![SqlTimestamp(before)],
+            )
+            .and_then(|_| {
+                self.conn.execute(
+                    "DELETE FROM session_scores \
+                     WHERE session_id NOT IN (SELECT session_id FROM sessions)",
+                    [],
+                )
+            })
+            .and_then(|_| {
+                self.conn.execute(
+                    "DELETE FROM session_activity WHERE ended_at < ?",
+                    params![SqlTimestamp(before)],
+                )
+            })
+            .and_then(|_| {
+                self.conn.execute(
+                    "DELETE FROM file_events WHERE timestamp < ?",
+                    params![SqlTimestamp(before)],
+                )
+            })
+            .and_then(|_| {
+                self.conn.execute(
+                    "DELETE FROM error_events WHERE timestamp < ?",
+                    params![SqlTimestamp(before)],
+                )
+            })
+        {
+            let _ = self.conn.execute_batch("ROLLBACK");
+            return Err(e.into());
+        }
+        self.conn.execute_batch("COMMIT")?;
+        // Write the deletions to the database file so the space can be reused
+        self.conn.execute_batch("CHECKPOINT")?;
+        Ok(counts)
     }
 
-    fn record_session_metric(&self, metric_name: &str, value: i64) -> Result<()> {
-        self.conn.execute(
-            "INSERT INTO session_metrics (timestamp, metric_name, value) VALUES (?, ?, ?)",
-            params![Utc::now().to_rfc3339(), metric_name, value],
-        )?;
-        Ok(())
+    fn get_model_costs(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<Vec<ModelCost>> {
+        let time_clause = time_clause("AND", since, until);
+        let query = format!(
+            r#"
+            SELECT
+                COALESCE(json_extract_string(attributes, '$.model'), 'unknown') as model,
+                COUNT(*) as calls,
+                SUM(COALESCE(TRY_CAST(json_extract_string(attributes, '$.cost_usd') AS DOUBLE), 0)) as cost
+            FROM log_events
+            WHERE event_name LIKE '%api_request' {time_clause}
+            GROUP BY model
+            ORDER BY cost DESC, calls DESC, model
+            "#
+        );
+
+        let mut stmt = self.conn.prepare_cached(&query)?;
+        let rows = stmt.query_map(time_params(since, until), |row| {
+            Ok(ModelCost {
+                model: row.get(0)?,
+                calls: row.get::<_, i64>(1)? as u64,
+                cost_usd: row.get(2)?,
+            })
+        })?;
+        Ok(rows.collect::<duckdb::Result<Vec<_>>>()?)
     }
 
-    fn get_tool_metrics(&self, since: Option<DateTime<Utc>>) -> Result<Vec<ToolMetrics>> {
-        // Query that combines both legacy tool_events and new log_events tables
-        // The log_events query filters by event_name at query time (not ingestion)
-        // This matches both "tool_result" and "claude_code.tool_result"
-        let time_clause = since
-            .map(|dt| format!("AND timestamp >= '{}'", dt.to_rfc3339()))
+    fn get_top_tool_errors(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        limit: usize,
+    ) -> Result<Vec<ToolErrorCount>> {
+        let time_clause = time_clause("AND", since, until);
+        let query = format!(
+            r#"
+            WITH errors AS (
+                SELECT tool_name, error as error_msg
+                FROM tool_events
+                WHERE success = false {time_clause}
+
+                UNION ALL
+
+                SELECT
+                    COALESCE(json_extract_string(attributes, '$.tool_name'), 'unknown'),
+                    json_extract_string(attributes, '$.error')
+                FROM log_events
+                WHERE event_name LIKE '%tool_result'
+                  AND COALESCE(json_extract_string(attributes, '$.success'), 'false') NOT IN ('true', '1')
+                  {time_clause}
+            )
+            SELECT tool_name, error_msg, COUNT(*) as n
+            FROM errors
+            GROUP BY tool_name, error_msg
+            ORDER BY n DESC, tool_name
+            LIMIT {limit}
+            "#
+        );
+
+        let mut stmt = self.conn.prepare_cached(&query)?;
+        let rows = stmt.query_map(time_params(since, until), |row| {
+            Ok(ToolErrorCount {
+                tool_name: row.get(0)?,
+                error: row.get(1)?,
+                count: row.get::<_, i64>(2)? as u64,
+            })
+        })?;
+        Ok(rows.collect::<duckdb::Result<Vec<_>>>()?)
+    }
+
+    fn run_query(&self, sql: &str, limit: usize) -> Result<QueryResult> {
+        let sql = sql.trim().trim_end_matches([';', ' ', '\t', '\n', '\r']);
+        if sql.is_empty() {
+            anyhow::bail!("Empty query");
+        }
+
+        // Values are cast to text for display. The parser must see exactly one SELECT
+        // (text after a `;` inside the subquery would otherwise run on its own), and
+        // the read-only transaction stops anything with side effects, like nextval()
+        let query = format!(
+            "SELECT COLUMNS(*)::VARCHAR FROM ({}\n) LIMIT {}",
+            sql,
+            limit + 1
+        );
+        let parsed: String =
+            self.conn
+                .query_row("SELECT json_serialize_sql(?::VARCHAR)", [&query], |row| {
+                    row.get(0)
+                })?;
+        let parsed: serde_json::Value = serde_json::from_str(&parsed)?;
+        if parsed["error"].as_bool() != Some(false) {
+            anyhow::bail!(
+                "Only a single SELECT-like statement can be run: {}",
+                parsed["error_message"].as_str().unwrap_or("not a query")
+            );
+        }
+        if parsed["statements"].as_array().map_or(0, Vec::len) != 1 {
+            anyhow::bail!("Only a single statement can be run");
+        }
+
+        self.conn.execute_batch("BEGIN TRANSACTION READ ONLY")?;
+        let result = self.read_query(&query, limit);
+        self.conn.execute_batch("ROLLBACK")?;
+        result
+    }
+
+    /// Run a validated query, at most `limit` rows
+    fn read_query(&self, query: &str, limit: usize) -> Result<QueryResult> {
+        let mut stmt = self.conn.prepare(query)?;
+        let mut rows = stmt.query([])?;
+        let columns = rows
+            .as_ref()
+            .map(|stmt| stmt.column_names())
             .unwrap_or_default();
 
+        let mut result = QueryResult {
+            columns,
+            ..Default::default()
+        };
+        while let Some(row) = rows.next()? {
+            if result.rows.len() == limit {
+                result.truncated = true;
+                break;
+            }
+            let values = (0..result.columns.len())
+                .map(|i| row.get::<_, Option<String>>(i))
+                .collect::<duckdb::Result<Vec<_>>>()?;
+            result.rows.push(values);
+        }
+        Ok(result)
+    }
+
+    fn get_data_version(&self) -> Result<DataVersion> {
+        let columns = TABLES
+            .iter()
+            .map(|table| {
+                format!(
+                    "(SELECT coalesce(max(id), 0) FROM {0}), (SELECT count(*) FROM {0})",
+                    table
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        let version = self
+            .conn
+            .prepare_cached(&format!("SELECT {}", columns))?
+            .query_row([], |row| {
+                let mut version = DataVersion {
+                    token_updates: self.token_updates.get(),
+                    ..DataVersion::default()
+                };
+                for i in 0..TABLES.len() {
+                    version.max_ids[i] = row.get(2 * i)?;
+                    version.row_counts[i] = row.get::<_, i64>(2 * i + 1)? as u64;
+                }
+                Ok(version)
+            })?;
+        Ok(version)
+    }
+
+    fn is_empty(&self) -> Result<bool> {
+        let has_rows: bool = self
+            .conn
+            .prepare_cached(
+                r#"
+            SELECT EXISTS (SELECT 1 FROM tool_events)
+                OR EXISTS (SELECT 1 FROM log_events)
+                OR EXISTS (SELECT 1 FROM token_usage)
+                OR EXISTS (SELECT 1 FROM cost_usage)
+                OR EXISTS (SELECT 1 FROM session_metrics)
+            "#,
+            )?
+            .query_row([], |row| row.get(0))?;
+        Ok(!has_rows)
+    }
+
+    fn get_log_events_after(&self, after_id: Option<i64>, limit: usize) -> Result<Vec<LogRecord>> {
+        match after_id {
+            Some(id) => self.latest_log_events("WHERE id > ?", params![id], limit),
+            None => self.latest_log_events("", [], limit),
+        }
+    }
+
+    fn get_log_events(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        limit: usize,
+    ) -> Result<Vec<LogRecord>> {
+        self.latest_log_events(
+            &time_clause("WHERE", since, until),
+            time_params(since, until),
+            limit,
+        )
+    }
+
+    /// The newest `limit` log events matching `filter` (a WHERE clause with `params`),
+    /// oldest first
+    fn latest_log_events(
+        &self,
+        filter: &str,
+        params: impl duckdb::Params,
+        limit: usize,
+    ) -> Result<Vec<LogRecord>> {
         let query = format!(
             r#"
-            WITH combined_events AS (
-                -- Legacy tool_events table (no decision tracking)
-                SELECT
-                    tool_name,
-                    timestamp,
-                    duration_ms,
-                    success,
-                    NULL as decision
-                FROM tool_events
-                WHERE 1=1 {time_clause}
-
-                UNION ALL
-
-                -- New log_events table with query-time filtering
-                SELECT
-                    COALESCE(json_extract_string(attributes, '$.tool_name'), 'unknown') as tool_name,
-                    timestamp,
-                    COALESCE(CAST(json_extract(attributes, '$.duration_ms') AS BIGINT), 0) as duration_ms,
-                    CASE
-                        WHEN json_extract_string(attributes, '$.success') IN ('true', '1') THEN true
-                        WHEN json_extract(attributes, '$.success') = true THEN true
-                        ELSE false
-                    END as success,
-                    json_extract_string(attributes, '$.decision') as decision
-                FROM log_events
-                WHERE event_name LIKE '%tool_result' {time_clause}
+            SELECT id, timestamp, event_name, CAST(attributes AS VARCHAR)
+            FROM (
+                SELECT * FROM log_events {filter} ORDER BY id DESC LIMIT {limit}
             )
-            SELECT
-                tool_name,
-                COUNT(*) as call_count,
-                CAST(MAX(timestamp) AS VARCHAR) as last_call,
-                AVG(duration_ms) as avg_duration_ms,
-                MIN(duration_ms) as min_duration_ms,
-                MAX(duration_ms) as max_duration_ms,
-                SUM(CASE WHEN success THEN 1 ELSE 0 END) as success_count,
-                SUM(CASE WHEN NOT success THEN 1 ELSE 0 END) as error_count,
-                SUM(CASE WHEN decision IN ('approved', 'auto_approved') THEN 1 ELSE 0 END) as approved_count,
-                SUM(CASE WHEN decision = 'rejected' THEN 1 ELSE 0 END) as rejected_count
-            FROM combined_events
-            GROUP BY tool_name
-            ORDER BY call_count DESC
+            ORDER BY id
             "#
         );
 
-        let mut stmt = self.conn.prepare(&query)?;
-
-        let rows = stmt.query_map([], |row| {
-            let last_call_str: Option<String> = row.get(2)?;
-            // DuckDB CAST(timestamp AS VARCHAR) produces "2026-01-18 21:03:57.123456"
-            // We need to parse this format, not RFC3339
-            let last_call = last_call_str.and_then(|s| {
-                // Try RFC3339 first (for backwards compatibility with stored RFC3339 strings)
-                DateTime::parse_from_rfc3339(&s)
-                    .map(|dt| dt.with_timezone(&Utc))
-                    .ok()
-                    .or_else(|| {
-                        // Try DuckDB's format: "2026-01-18 21:03:57.123456" or "2026-01-18 21:03:57"
-                        chrono::NaiveDateTime::parse_from_str(&s, "%Y-%m-%d %H:%M:%S%.f")
-                            .or_else(|_| {
-                                chrono::NaiveDateTime::parse_from_str(&s, "%Y-%m-%d %H:%M:%S")
-                            })
-                            .ok()
-                            .map(|naive| naive.and_utc())
-                    })
-            });
-
-            Ok(ToolMetrics {
-                tool_name: row.get(0)?,
-                call_count: row.get::<_, i64>(1)? as u64,
-                last_call,
-                avg_duration_ms: row.get(3)?,
-                min_duration_ms: row.get(4)?,
-                max_duration_ms: row.get(5)?,
-                success_count: row.get::<_, i64>(6)? as u64,
-                error_count: row.get::<_, i64>(7)? as u64,
-                approved_count: row.get::<_, i64>(8)? as u64,
-                rejected_count: row.get::<_, i64>(9)? as u64,
-            })
+        let mut stmt = self.conn.prepare_cached(&query)?;
+        let rows = stmt.query_map(params, |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, SqlTimestamp>(1)?.0,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, Option<String>>(3)?,
+            ))
         })?;
 
-        let mut metrics = Vec::new();
+        let mut records = Vec::new();
         for row in rows {
-            metrics.push(row?);
+            let (id, timestamp, event_name, attributes) = row?;
+            let attributes = attributes
+                .and_then(|json| serde_json::from_str(&json).ok())
+                .unwrap_or_default();
+            records.push(LogRecord {
+                id,
+                timestamp,
+                event_name,
+                attributes,
+            });
         }
-        Ok(metrics)
+        Ok(records)
     }
 
-    fn get_token_metrics(&self, since: Option<DateTime<Utc>>) -> Result<TokenMetrics> {
-        let time_clause = since
-            .map(|dt| format!("WHERE timestamp >= '{}'", dt.to_rfc3339()))
-            .unwrap_or_default();
-
+    fn get_recent_prompts(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        limit: usize,
+    ) -> Result<Vec<PromptEntry>> {
+        let time_filter = time_clause("AND", since, until);
         let query = format!(
             r#"
             SELECT
-                token_type,
-                SUM(count) as total
-            FROM token_usage
-            {time_clause}
-            GROUP BY token_type
+                timestamp,
+                json_extract_string(attributes, '$.prompt'),
+                TRY_CAST(json_extract_string(attributes, '$.prompt_length') AS BIGINT)
+            FROM log_events
+            WHERE event_name LIKE '%user_prompt' {time_filter}
+            ORDER BY timestamp DESC
+            LIMIT {limit}
             "#
         );
 
-        let mut stmt = self.conn.prepare(&query)?;
-
-        let mut metrics = TokenMetrics::default();
-
-        let rows = stmt.query_map([], |row| {
-            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as u64))
+        let mut stmt = self.conn.prepare_cached(&query)?;
+        let rows = stmt.query_map(time_params(since, until), |row| {
+            Ok((
+                row.get::<_, SqlTimestamp>(0)?.0,
+                row.get::<_, Option<String>>(1)?,
+                row.get::<_, Option<i64>>(2)?,
+            ))
         })?;
 
+        let mut prompts = Vec::new();
         for row in rows {
-            let (token_type, count) = row?;
-            // Use provider registry to normalize token types
-            match PROVIDER_REGISTRY.normalize_token_type(&token_type) {
-                Some(TOKEN_INPUT) => metrics.input_tokens += count,
-                Some(TOKEN_OUTPUT) => metrics.output_tokens += count,
-                Some(TOKEN_CACHE_READ) => metrics.cache_read_tokens += count,
-                Some(TOKEN_CACHE_WRITE) => metrics.cache_creation_tokens += count,
-                _ => {
-                    tracing::warn!("Unknown token type: {}", token_type);
-                }
-            }
+            let (timestamp, text, length) = row?;
+            // Fall back to the text itself when the agent reports no length
+            let length = length
+                .map(|l| l.max(0) as u64)
+                .or_else(|| text.as_ref().map(|t| t.chars().count() as u64));
+            prompts.push(PromptEntry {
+                timestamp,
+                text,
+                length,
+            });
         }
+        Ok(prompts)
+    }
 
-        // Get total cost
-        let cost_clause = since
-            .map(|dt| format!("WHERE timestamp >= '{}'", dt.to_rfc3339()))
-            .unwrap_or_default();
-        let cost_query = format!("SELECT COALESCE(SUM(cost_usd), 0) FROM cost_usage {cost_clause}");
-        let cost: f64 = self.conn.query_row(&cost_query, [], |row| row.get(0))?;
-        metrics.total_cost_usd = cost;
+    fn get_turns(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        limit: usize,
+    ) -> Result<Vec<Turn>> {
+        let time_filter = time_clause("AND", since, until);
+        let attr_u64 = |name: &str| {
+            format!(
+                "COALESCE(SUM(TRY_CAST(json_extract_string(attributes, '$.{name}') AS BIGINT)) FILTER (WHERE event_name LIKE '%api_request'), 0)"
+            )
+        };
+        // Each prompt starts a new turn in its session; events before a session's
+        // first prompt in the range belong to no turn
+        let query = format!(
+            r#"
+            WITH numbered AS (
+                SELECT
+                    timestamp,
+                    event_name,
+                    attributes,
+                    json_extract_string(attributes, '$."session.id"') AS session_id,
+                    SUM(CASE WHEN event_name LIKE '%user_prompt' THEN 1 ELSE 0 END) OVER (
+                        PARTITION BY json_extract_string(attributes, '$."session.id"')
+                        ORDER BY timestamp, event_name LIKE '%user_prompt' DESC, id
+                        ROWS UNBOUNDED PRECEDING
+                    ) AS turn
+                FROM log_events
+                WHERE (event_name LIKE '%user_prompt'
+                    OR event_name LIKE '%api_request'
+                    OR event_name LIKE '%tool_result') {time_filter}
+            )
+            SELECT
+                session_id,
+                MIN(timestamp),
+                MAX(timestamp),
+                MAX(TRY_CAST(json_extract_string(attributes, '$.prompt_length') AS BIGINT)) FILTER (WHERE event_name LIKE '%user_prompt'),
+                COUNT(*) FILTER (WHERE event_name LIKE '%api_request'),
+                COUNT(*) FILTER (WHERE event_name LIKE '%tool_result'),
+                {},
+                {},
+                COALESCE(SUM(TRY_CAST(json_extract_string(attributes, '$.cost_usd') AS DOUBLE)) FILTER (WHERE event_name LIKE '%api_request'), 0)
+            FROM numbered
+            WHERE turn > 0
+            GROUP BY session_id, turn
+            ORDER BY MIN(timestamp) DESC
+            LIMIT {limit}
+            "#,
+            attr_u64("input_tokens"),
+            attr_u64("output_tokens"),
+        );
 
-        Ok(metrics)
+        let mut stmt = self.conn.prepare_cached(&query)?;
+        let rows = stmt.query_map(time_params(since, until), |row| {
+            Ok(Turn {
+                session_id: row.get(0)?,
+                started_at: row.get::<_, SqlTimestamp>(1)?.0,
+                ended_at: row.get::<_, SqlTimestamp>(2)?.0,
+                prompt_length: row.get::<_, Option<i64>>(3)?.map(|l| l.max(0) as u64),
+                api_calls: row.get::<_, i64>(4)? as u64,
+                tool_calls: row.get::<_, i64>(5)? as u64,
+                input_tokens: row.get::<_, i64>(6)? as u64,
+                output_tokens: row.get::<_, i64>(7)? as u64,
+                cost_usd: row.get(8)?,
+            })
+        })?;
+        Ok(rows.collect::<duckdb::Result<Vec<_>>>()?)
     }
 
     fn get_last_tool_error(&self, tool_name: &str) -> Result<Option<String>> {
@@ -646,9 +4245,10 @@ impl Storage {
             LIMIT 1
         "#;
 
-        let result: Result<String, _> =
-            self.conn
-                .query_row(query, params![tool_name, tool_name], |row| row.get(0));
+        let result: Result<String, _> = self
+            .conn
+            .prepare_cached(query)?
+            .query_row(params![tool_name, tool_name], |row| row.get(0));
 
         match result {
             Ok(msg) => Ok(Some(msg)),
@@ -657,47 +4257,68 @@ impl Storage {
         }
     }
 
-    fn get_session_metrics(&self, since: Option<DateTime<Utc>>) -> Result<SessionMetrics> {
-        let time_clause = since
-            .map(|dt| format!("WHERE timestamp >= '{}'", dt.to_rfc3339()))
-            .unwrap_or_default();
+    fn get_session_metrics(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<SessionMetrics> {
+        let time_clause = time_clause("WHERE", since, until);
+        let cumulative = CUMULATIVE_SESSION_METRICS
+            .iter()
+            .map(|name| format!("'{}'", name))
+            .collect::<Vec<_>>()
+            .join(", ");
 
+        // Cumulative metrics count their latest value in each session, the others sum
         let query = format!(
             r#"
-            SELECT
-                metric_name,
-                SUM(value) as total
-            FROM session_metrics
-            {time_clause}
+            SELECT metric_name, SUM(total) as total
+            FROM (
+                SELECT
+                    metric_name,
+                    CASE WHEN metric_name IN ({cumulative})
+                        THEN arg_max(value, timestamp)
+                        ELSE SUM(value)
+                    END as total
+                FROM session_metrics
+                {time_clause}
+                GROUP BY metric_name, session_id
+            )
             GROUP BY metric_name
             "#
         );
 
+        // Not prepare_cached: a plan made while session_metrics was empty compresses the
+        // grouped strings to the lengths seen then, and DuckDB aborts on longer ones later
         let mut stmt = self.conn.prepare(&query)?;
         let mut metrics = SessionMetrics::default();
 
-        let rows = stmt.query_map([], |row| {
+        let rows = stmt.query_map(time_params(since, until), |row| {
             Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
         })?;
 
         for row in rows {
             let (metric_name, value) = row?;
             match metric_name.as_str() {
-                "lines_of_code" | "loc" => metrics.lines_of_code = value,
-                "commits" | "commit_count" => metrics.commit_count = value as u64,
+                name if LINES_OF_CODE_METRICS.contains(&name) => metrics.lines_of_code += value,
+                name if COMMIT_METRICS.contains(&name) => metrics.commit_count += value as u64,
                 "active_time" => metrics.active_time_secs = value as u64,
                 _ => {}
             }
         }
+        metrics.detected_active_secs = self.clipped_secs("session_activity", since, until)?;
+        metrics.session_secs = self.clipped_secs("sessions", since, until)?;
 
         Ok(metrics)
     }
 
     /// Get API metrics from api_request and api_error events
-    fn get_api_metrics(&self, since: Option<DateTime<Utc>>) -> Result<ApiMetrics> {
-        let time_clause = since
-            .map(|dt| format!("AND timestamp >= '{}'", dt.to_rfc3339()))
-            .unwrap_or_default();
+    fn get_api_metrics(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<ApiMetrics> {
+        let time_clause = time_clause("AND", since, until);
 
         // Query api_request events for call count, latency, and model breakdown
         let api_query = format!(
@@ -712,10 +4333,10 @@ impl Storage {
             "#
         );
 
-        let mut stmt = self.conn.prepare(&api_query)?;
+        let mut stmt = self.conn.prepare_cached(&api_query)?;
         let mut metrics = ApiMetrics::default();
 
-        let rows = stmt.query_map([], |row| {
+        let rows = stmt.query_map(time_params(since, until), |row| {
             Ok((
                 row.get::<_, i64>(0)? as u64,
                 row.get::<_, f64>(1).unwrap_or(0.0),
@@ -729,6 +4350,7 @@ impl Storage {
             metrics.total_calls += count;
             total_latency_sum += avg_latency * count as f64;
             if let Some(m) = model {
+                metrics.latency_by_model.insert(m.clone(), avg_latency);
                 *metrics.models.entry(m).or_insert(0) += count;
             }
         }
@@ -737,26 +4359,578 @@ impl Storage {
             metrics.avg_latency_ms = total_latency_sum / metrics.total_calls as f64;
         }
 
-        // Query api_error events for error count
+        // Query api_error events for error count and model breakdown
         let error_query = format!(
             r#"
-            SELECT COUNT(*) as error_count
+            SELECT
+                COUNT(*) as error_count,
+                json_extract_string(attributes, '$.model') as model
             FROM log_events
             WHERE event_name LIKE '%api_error' {time_clause}
+            GROUP BY model
             "#
         );
 
-        let error_count: i64 = self.conn.query_row(&error_query, [], |row| row.get(0))?;
-        metrics.total_errors = error_count as u64;
+        let mut stmt = self.conn.prepare_cached(&error_query)?;
+        let rows = stmt.query_map(time_params(since, until), |row| {
+            Ok((
+                row.get::<_, i64>(0)? as u64,
+                row.get::<_, Option<String>>(1)?,
+            ))
+        })?;
+
+        for row in rows {
+            let (count, model) = row?;
+            metrics.total_errors += count;
+            if let Some(m) = model {
+                *metrics.errors_by_model.entry(m).or_insert(0) += count;
+            }
+        }
 
         Ok(metrics)
     }
+
+    fn count_rate_limit_errors(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<u64> {
+        let time_clause = time_clause("AND", since, until);
+        let query = format!(
+            r#"
+            SELECT COUNT(*)
+            FROM log_events
+            WHERE event_name LIKE '%api_error' AND {RATE_LIMITED}
+              {time_clause}
+            "#
+        );
+        let count: i64 = self
+            .conn
+            .prepare_cached(&query)?
+            .query_row(time_params(since, until), |row| row.get(0))?;
+        Ok(count as u64)
+    }
+
+    fn count_sessions(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<u64> {
+        let time_clause = time_clause("WHERE", since, until);
+        let query = format!(
+            r#"
+            SELECT COUNT(DISTINCT json_extract_string(attributes, '$."session.id"'))
+            FROM log_events
+            {time_clause}
+            "#
+        );
+        let count: i64 = self
+            .conn
+            .prepare_cached(&query)?
+            .query_row(time_params(since, until), |row| row.get(0))?;
+        Ok(count as u64)
+    }
+
+    fn get_cost_breakdown(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<Vec<CostBreakdown>> {
+        let time_clause = time_clause("AND", since, until);
+        let query = format!(
+            r#"
+            SELECT
+                time_bucket(INTERVAL 15 MINUTES, timestamp) as bucket,
+                COALESCE(json_extract_string(attributes, '$.model'), 'unknown') as model,
+                json_extract_string(attributes, '$.project') as project,
+                COUNT(*),
+                COALESCE(SUM(TRY_CAST(json_extract_string(attributes, '$.input_tokens') AS BIGINT)), 0),
+                COALESCE(SUM(TRY_CAST(json_extract_string(attributes, '$.output_tokens') AS BIGINT)), 0),
+                SUM(COALESCE(TRY_CAST(json_extract_string(attributes, '$.cost_usd') AS DOUBLE), 0))
+            FROM log_events
+            WHERE event_name LIKE '%api_request' {time_clause}
+            GROUP BY bucket, model, project
+            ORDER BY bucket, model, project
+            "#
+        );
+
+        let mut stmt = self.conn.prepare_cached(&query)?;
+        let rows = stmt.query_map(time_params(since, until), |row| {
+            Ok((
+                row.get::<_, SqlTimestamp>(0)?.0,
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, i64>(3)?,
+                row.get::<_, i64>(4)?,
+                row.get::<_, i64>(5)?,
+                row.get::<_, f64>(6)?,
+            ))
+        })?;
+
+        let mut breakdown = Vec::new();
+        for row in rows {
+            let (bucket, model, project, calls, input_tokens, output_tokens, cost_usd) = row?;
+            breakdown.push(CostBreakdown {
+                bucket,
+                model,
+                project,
+                calls: calls as u64,
+                input_tokens: input_tokens as u64,
+                output_tokens: output_tokens as u64,
+                cost_usd,
+            });
+        }
+        Ok(breakdown)
+    }
+
+    fn get_api_spend(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<Vec<ApiSpend>> {
+        let query = format!(
+            r#"
+            SELECT
+                timestamp,
+                json_extract_string(attributes, '$."session.id"'),
+                json_extract_string(attributes, '$.cwd'),
+                COALESCE(TRY_CAST(json_extract_string(attributes, '$.cost_usd') AS DOUBLE), 0)
+            FROM log_events
+            WHERE event_name LIKE '%api_request' {}
+            ORDER BY timestamp, id
+            "#,
+            time_clause("AND", since, until)
+        );
+        let mut stmt = self.conn.prepare_cached(&query)?;
+        let rows = stmt.query_map(time_params(since, until), |row| {
+            Ok(ApiSpend {
+                timestamp: row.get::<_, SqlTimestamp>(0)?.0,
+                session_id: row.get(1)?,
+                cwd: row.get(2)?,
+                cost_usd: row.get(3)?,
+            })
+        })?;
+        Ok(rows.collect::<duckdb::Result<Vec<_>>>()?)
+    }
+
+    fn stream_token_usage(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        tx: &mpsc::SyncSender<error::Result<Vec<TokenUsageRow>>>,
+    ) -> Result<()> {
+        let time_clause = time_clause("WHERE", since, until);
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT timestamp, token_type, count, model, provider FROM token_usage {time_clause} ORDER BY timestamp, id"
+        ))?;
+        let mut rows = stmt.query(time_params(since, until))?;
+        let mut batch = Vec::with_capacity(STREAM_BATCH_ROWS);
+        while let Some(row) = rows.next()? {
+            batch.push(TokenUsageRow {
+                timestamp: row.get::<_, SqlTimestamp>(0)?.0,
+                token_type: row.get(1)?,
+                count: row.get::<_, i64>(2)? as u64,
+                model: row.get(3)?,
+                provider: row.get(4)?,
+            });
+            if batch.len() == STREAM_BATCH_ROWS {
+                let full = std::mem::replace(&mut batch, Vec::with_capacity(STREAM_BATCH_ROWS));
+                if tx.send(Ok(full)).is_err() {
+                    // The reader is gone (e.g. stdout was closed)
+                    return Ok(());
+                }
+            }
+        }
+        if !batch.is_empty() {
+            let _ = tx.send(Ok(batch));
+        }
+        Ok(())
+    }
+
+    fn get_session_summary(&self, prefix: &str) -> Result<Option<SessionSummary>> {
+        const SESSION_ID: &str = r#"json_extract_string(attributes, '$."session.id"')"#;
+        const FAILED: &str =
+            "COALESCE(json_extract_string(attributes, '$.success'), 'false') NOT IN ('true', '1')";
+
+        // An exact id sorts before the ids it is a prefix of, and wins over them
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT DISTINCT {SESSION_ID} AS id FROM log_events WHERE starts_with({SESSION_ID}, ?) ORDER BY id LIMIT 2"
+        ))?;
+        let ids = stmt
+            .query_map(params![prefix], |row| row.get::<_, String>(0))?
+            .collect::<duckdb::Result<Vec<_>>>()?;
+        let session_id = match ids.as_slice() {
+            [] => return Ok(None),
+            [id] => id.clone(),
+            [id, _] if id == prefix => id.clone(),
+            [a, b] => anyhow::bail!(
+                "Several sessions start with `{}` ({}, {}, ...); give more of the id",
+                prefix,
+                a,
+                b
+            ),
+            _ => unreachable!("at most two ids are selected"),
+        };
+
+        let attr_u64 = |name: &str| {
+            format!(
+                "COALESCE(SUM(TRY_CAST(json_extract_string(attributes, '$.{name}') AS BIGINT)) FILTER (WHERE event_name LIKE '%api_request'), 0)"
+            )
+        };
+        let totals_query = format!(
+            r#"
+            SELECT
+                MIN(timestamp),
+                MAX(timestamp),
+                COUNT(*) FILTER (WHERE event_name LIKE '%api_request'),
+                COUNT(*) FILTER (WHERE event_name LIKE '%api_error'),
+                COALESCE(SUM(TRY_CAST(json_extract_string(attributes, '$.cost_usd') AS DOUBLE)) FILTER (WHERE event_name LIKE '%api_request'), 0),
+                {},
+                {},
+                {},
+                {}
+            FROM log_events
+            WHERE {SESSION_ID} = ?
+            "#,
+            attr_u64("input_tokens"),
+            attr_u64("output_tokens"),
+            attr_u64("cache_read_tokens"),
+            attr_u64("cache_creation_tokens"),
+        );
+        let (started, ended, api_calls, api_errors, cost_usd, tokens) =
+            self.conn
+                .query_row(&totals_query, params![session_id], |row| {
+                    Ok((
+                        row.get::<_, Option<SqlTimestamp>>(0)?,
+                        row.get::<_, Option<SqlTimestamp>>(1)?,
+                        row.get::<_, i64>(2)? as u64,
+                        row.get::<_, i64>(3)? as u64,
+                        row.get::<_, f64>(4)?,
+                        [
+                            row.get::<_, i64>(5)? as u64,
+                            row.get::<_, i64>(6)? as u64,
+                            row.get::<_, i64>(7)? as u64,
+                            row.get::<_, i64>(8)? as u64,
+                        ],
+                    ))
+                })?;
+        let (Some(SqlTimestamp(started_at)), Some(SqlTimestamp(ended_at))) = (started, ended)
+        else {
+            anyhow::bail!("Unreadable timestamps for session {}", session_id);
+        };
+
+        let mut stmt = self.conn.prepare(&format!(
+            r#"
+            SELECT
+                COALESCE(json_extract_string(attributes, '$.model'), 'unknown') as model,
+                COUNT(*) as calls,
+                SUM(COALESCE(TRY_CAST(json_extract_string(attributes, '$.cost_usd') AS DOUBLE), 0)) as cost
+            FROM log_events
+            WHERE event_name LIKE '%api_request' AND {SESSION_ID} = ?
+            GROUP BY model
+            ORDER BY cost DESC, calls DESC, model
+            "#
+        ))?;
+        let models = stmt
+            .query_map(params![session_id], |row| {
+                Ok(ModelCost {
+                    model: row.get(0)?,
+                    calls: row.get::<_, i64>(1)? as u64,
+                    cost_usd: row.get(2)?,
+                })
+            })?
+            .collect::<duckdb::Result<Vec<_>>>()?;
+
+        let mut stmt = self.conn.prepare(&format!(
+            r#"
+            SELECT
+                COALESCE(json_extract_string(attributes, '$.tool_name'), 'unknown') as tool,
+                COUNT(*) as calls,
+                COUNT(*) FILTER (WHERE {FAILED})
+            FROM log_events
+            WHERE event_name LIKE '%tool_result' AND {SESSION_ID} = ?
+            GROUP BY tool
+            ORDER BY calls DESC, tool
+            "#
+        ))?;
+        let tools = stmt
+            .query_map(params![session_id], |row| {
+                Ok(ToolCallCount {
+                    tool_name: row.get(0)?,
+                    calls: row.get::<_, i64>(1)? as u64,
+                    errors: row.get::<_, i64>(2)? as u64,
+                })
+            })?
+            .collect::<duckdb::Result<Vec<_>>>()?;
+
+        let mut stmt = self.conn.prepare(&format!(
+            r#"
+            SELECT
+                COALESCE(json_extract_string(attributes, '$.tool_name'), 'unknown') as tool,
+                json_extract_string(attributes, '$.error') as error_msg,
+                COUNT(*) as n
+            FROM log_events
+            WHERE event_name LIKE '%tool_result' AND {FAILED} AND {SESSION_ID} = ?
+            GROUP BY tool, error_msg
+            ORDER BY n DESC, tool
+            "#
+        ))?;
+        let errors = stmt
+            .query_map(params![session_id], |row| {
+                Ok(ToolErrorCount {
+                    tool_name: row.get(0)?,
+                    error: row.get(1)?,
+                    count: row.get::<_, i64>(2)? as u64,
+                })
+            })?
+            .collect::<duckdb::Result<Vec<_>>>()?;
+
+        // Metrics are exported periodically, so the last ones arrive after the last event
+        let code = self.get_session_metrics(
+            Some(started_at),
+            Some(ended_at + chrono::Duration::minutes(1)),
+        )?;
+        let active_ms: i64 = self.conn.query_row(
+            "SELECT CAST(COALESCE(SUM(date_diff('millisecond', started_at, ended_at)), 0) AS BIGINT) \
+             FROM session_activity WHERE session_id = ?",
+            params![session_id],
+            |row| row.get(0),
+        )?;
+
+        let [
+            input_tokens,
+            output_tokens,
+            cache_read_tokens,
+            cache_creation_tokens,
+        ] = tokens;
+        Ok(Some(SessionSummary {
+            session_id,
+            started_at,
+            ended_at,
+            api_calls,
+            api_errors,
+            cost_usd,
+            input_tokens,
+            output_tokens,
+            cache_read_tokens,
+            cache_creation_tokens,
+            models,
+            tools,
+            errors,
+            lines_of_code: code.lines_of_code,
+            commit_count: code.commit_count,
+            active_secs: active_ms.max(0) as u64 / 1000,
+        }))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_supervise_restarts_after_panic() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let (sender, receiver) = mpsc::channel();
+        let handle = StorageHandle::with_sender(sender);
+        let runs = AtomicUsize::new(0);
+        let restarts = SELF_METRICS.storage.snapshot().restarts;
+        let actor = thread::spawn(move || {
+            supervise(
+                Storage::new_in_memory().unwrap(),
+                receiver,
+                Storage::new_in_memory,
+                |storage, receiver| {
+                    if runs.fetch_add(1, Ordering::SeqCst) == 0 {
+                        panic!("simulated DuckDB failure");
+                    }
+                    run_storage_actor(storage, receiver)
+                },
+            )
+        });
+
+        // Fails fast while the actor is down instead of hanging
+        let error = handle.is_empty().unwrap_err();
+        assert!(error.is_unavailable());
+
+        let deadline = std::time::Instant::now() + RESTART_BACKOFF_MIN * 5;
+        while handle.is_empty().is_err() {
+            assert!(
+                std::time::Instant::now() < deadline,
+                "storage did not restart"
+            );
+            thread::sleep(std::time::Duration::from_millis(50));
+        }
+        assert!(SELF_METRICS.storage.snapshot().restarts > restarts);
+
+        drop(handle);
+        actor.join().unwrap();
+    }
+
+    #[test]
+    fn test_writes_land_after_failed_reopen() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let (sender, receiver) = mpsc::channel();
+        let handle = StorageHandle::with_sender(sender);
+        let runs = AtomicUsize::new(0);
+        let reopens = AtomicUsize::new(0);
+        let dropped = SELF_METRICS.receiver.snapshot().batches_dropped;
+        let actor = thread::spawn(move || {
+            supervise(
+                Storage::new_in_memory().unwrap(),
+                receiver,
+                || {
+                    if reopens.fetch_add(1, Ordering::SeqCst) == 0 {
+                        anyhow::bail!("simulated reopen failure");
+                    }
+                    Storage::new_in_memory()
+                },
+                |storage, receiver| {
+                    if runs.fetch_add(1, Ordering::SeqCst) == 0 {
+                        panic!("simulated DuckDB failure");
+                    }
+                    run_storage_actor(storage, receiver)
+                },
+            )
+        });
+        let prompt = || LogEvent {
+            timestamp: Utc::now(),
+            event_name: Some("user_prompt".to_string()),
+            body: None,
+            attributes: HashMap::new(),
+        };
+
+        // Written while storage is down: dropped and counted, not left queued
+        handle.record_log_events(vec![prompt()]);
+        let deadline = std::time::Instant::now() + RESTART_BACKOFF_MIN * 10;
+        while handle.is_empty().is_err() {
+            assert!(
+                std::time::Instant::now() < deadline,
+                "storage did not restart"
+            );
+            thread::sleep(std::time::Duration::from_millis(50));
+        }
+        assert!(SELF_METRICS.receiver.snapshot().batches_dropped > dropped);
+
+        handle.record_log_events(vec![prompt()]);
+        handle.record_log_events(vec![prompt()]);
+        assert_eq!(handle.get_log_events_after(None, 10).unwrap().len(), 2);
+
+        drop(handle);
+        actor.join().unwrap();
+    }
+
+    #[test]
+    fn test_reader_drops_writes_when_reopen_fails() {
+        let (sender, receiver) = mpsc::channel();
+        let handle = StorageHandle::with_sender(sender);
+        let path = std::env::temp_dir().join(format!("agenttop-missing-{}", std::process::id()));
+        let storage = Storage::new_in_memory().unwrap();
+        let actor = thread::spawn(move || run_reader_actor(storage, &path, &receiver));
+        let cost = || StorageCommand::RecordCost {
+            cost_usd: 1.0,
+            session_id: None,
+            model: None,
+            cumulative: false,
+        };
+
+        // Idle, so the next command reopens the file, which is gone
+        thread::sleep(ATTACHED_IDLE * 2);
+        handle.queue_write(cost(), None);
+        assert!(handle.is_empty().is_err());
+        // The failed batch was taken off the queue, so a new write wakes the actor again
+        assert!(handle.ingest.push(cost(), None));
+
+        drop(handle);
+        actor.join().unwrap();
+    }
+
+    #[test]
+    fn test_superseded_query_is_skipped() {
+        let (sender, receiver) = mpsc::channel();
+        let handle = StorageHandle::with_sender(sender).scoped();
+        let waiting = {
+            let handle = handle.clone();
+            thread::spawn(move || handle.is_empty())
+        };
+
+        // Superseded while still queued, so the actor drops it without running it
+        let cmd = receiver.recv().unwrap();
+        handle.supersede();
+        assert!(handle_command(&Storage::new_in_memory().unwrap(), cmd));
+        let error = waiting.join().unwrap().unwrap_err();
+        assert!(error.is_interrupted());
+        assert_eq!(error.to_string(), "query superseded by a newer one");
+
+        // Later queries of the scope are unaffected
+        let storage = Storage::new_in_memory().unwrap();
+        let waiting = {
+            let handle = handle.clone();
+            thread::spawn(move || handle.is_empty())
+        };
+        assert!(handle_command(&storage, receiver.recv().unwrap()));
+        assert!(waiting.join().unwrap().unwrap());
+    }
+
+    #[test]
+    fn test_ingest_queue_drops_oldest() {
+        let queue = IngestQueue::new(2);
+        let dropped = SELF_METRICS.receiver.snapshot().batches_dropped;
+        let cost = |cost_usd| StorageCommand::RecordCost {
+            cost_usd,
+            session_id: None,
+            model: None,
+            cumulative: false,
+        };
+        assert!(queue.push(cost(1.0), None));
+        assert!(!queue.push(cost(2.0), None));
+        assert!(!queue.push(cost(3.0), None));
+        assert!(SELF_METRICS.receiver.snapshot().batches_dropped > dropped);
+
+        let costs: Vec<f64> = queue
+            .take()
+            .into_iter()
+            .map(|queued| match queued.cmd {
+                StorageCommand::RecordCost { cost_usd, .. } => cost_usd,
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(costs, vec![2.0, 3.0]);
+        // Emptied, so the next write needs a wake-up again
+        assert!(queue.push(cost(4.0), None));
+    }
+
+    #[test]
+    fn test_full_queue_empties_journal() {
+        let path =
+            std::env::temp_dir().join(format!("agenttop-journal-full-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let (sender, receiver) = mpsc::channel();
+        let handle = StorageHandle {
+            ingest: Arc::new(IngestQueue::new(2)),
+            ..StorageHandle::with_sender(sender)
+        }
+        .with_journal(&path)
+        .unwrap();
+        for cost_usd in [1.0, 2.0, 3.0] {
+            handle.send_write(StorageCommand::RecordCost {
+                cost_usd,
+                session_id: None,
+                model: None,
+                cumulative: false,
+            });
+        }
+
+        // The first write was dropped for the queue being full, and counts as done
+        let storage = Storage::new_in_memory().unwrap();
+        assert!(handle_command(&storage, receiver.recv().unwrap()));
+        assert_eq!(storage.get_total_cost(None, None).unwrap(), 5.0);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+        std::fs::remove_file(&path).unwrap();
+    }
+
     #[test]
     fn test_parse_mcp_tool_name_standard() {
         let result = parse_mcp_tool_name("mcp__context7__resolve-library-id");
@@ -0,0 +1,91 @@
+//! Per-session code output: the lines of code, commits and active time each session
+//! reported in session_metrics, next to its spend from the sessions table.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+
+use super::{
+    COMMIT_METRICS, CUMULATIVE_SESSION_METRICS, LINES_OF_CODE_METRICS, SessionProductivity,
+    SqlTimestamp, Storage,
+};
+
+/// `'a', 'b'` for an SQL `IN` list
+fn sql_list(names: &[&str]) -> String {
+    names
+        .iter()
+        .map(|name| format!("'{name}'"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl Storage {
+    pub(super) fn get_session_productivity(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        limit: usize,
+    ) -> Result<Vec<SessionProductivity>> {
+        let mut bounds = Vec::new();
+        if since.is_some() {
+            bounds.push(format!("s.ended_at >= ?{}", bounds.len() + 1));
+        }
+        if until.is_some() {
+            bounds.push(format!("s.started_at < ?{}", bounds.len() + 1));
+        }
+        let filter = if bounds.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", bounds.join(" AND "))
+        };
+        let cumulative = sql_list(CUMULATIVE_SESSION_METRICS);
+        let lines = sql_list(LINES_OF_CODE_METRICS);
+        let commits = sql_list(COMMIT_METRICS);
+
+        // Cumulative metrics count their latest value in the session, the others sum
+        let query = format!(
+            r#"
+            WITH metrics AS (
+                SELECT
+                    session_id,
+                    metric_name,
+                    CASE WHEN metric_name IN ({cumulative})
+                        THEN arg_max(value, timestamp)
+                        ELSE SUM(value)
+                    END AS total
+                FROM session_metrics
+                WHERE session_id IS NOT NULL
+                GROUP BY session_id, metric_name
+            ),
+            code AS (
+                SELECT
+                    session_id,
+                    CAST(COALESCE(SUM(total) FILTER (WHERE metric_name IN ({lines})), 0) AS BIGINT) AS lines,
+                    CAST(COALESCE(SUM(total) FILTER (WHERE metric_name IN ({commits})), 0) AS BIGINT) AS commits,
+                    CAST(COALESCE(SUM(total) FILTER (WHERE metric_name = 'active_time'), 0) AS BIGINT) AS active_time
+                FROM metrics
+                GROUP BY session_id
+            )
+            SELECT s.session_id, s.started_at, s.ended_at, s.cost_usd, c.lines, c.commits, c.active_time
+            FROM sessions s
+            JOIN code c USING (session_id)
+            {filter}
+            ORDER BY s.started_at DESC
+            LIMIT {limit}
+            "#
+        );
+        // Not cached, for the reason given in get_session_metrics
+        let mut stmt = self.conn.prepare(&query)?;
+        let rows = stmt.query_map(super::time_params(since, until), |row| {
+            Ok(SessionProductivity {
+                session_id: row.get(0)?,
+                started_at: row.get::<_, SqlTimestamp>(1)?.0,
+                ended_at: row.get::<_, SqlTimestamp>(2)?.0,
+                cost_usd: row.get(3)?,
+                lines_of_code: row.get(4)?,
+                commits: row.get::<_, i64>(5)?.max(0) as u64,
+                active_time_secs: row.get::<_, i64>(6)?.max(0) as u64,
+            })
+        })?;
+        Ok(rows.collect::<duckdb::Result<Vec<_>>>()?)
+    }
+}
@@ -0,0 +1,53 @@
+//! API usage over time for the usage-window quotas (see [`crate::quota`]): each
+//! api_request's tokens and cost, and each rate-limited api_error, in time order.
+
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use duckdb::params;
+
+use super::{RATE_LIMITED, SqlTimestamp, Storage, UsagePoint};
+use crate::providers::PROVIDER_REGISTRY;
+
+impl Storage {
+    pub(super) fn get_usage_points(
+        &self,
+        provider: Option<&str>,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<UsagePoint>> {
+        let prefix = provider
+            .map(|id| {
+                PROVIDER_REGISTRY
+                    .get(id)
+                    .map(|p| p.metric_prefix())
+                    .ok_or_else(|| anyhow!("Unknown provider \"{}\" in quota", id))
+            })
+            .transpose()?;
+        let tokens = |name: &str| {
+            format!("COALESCE(TRY_CAST(json_extract_string(attributes, '$.{name}') AS BIGINT), 0)")
+        };
+        let query = format!(
+            "SELECT timestamp, \
+                    CASE WHEN event_name LIKE '%api_request' THEN {} + {} ELSE 0 END, \
+                    CASE WHEN event_name LIKE '%api_request' \
+                         THEN COALESCE(TRY_CAST(json_extract_string(attributes, '$.cost_usd') AS DOUBLE), 0) \
+                         ELSE 0 END, \
+                    event_name LIKE '%api_error' \
+             FROM log_events \
+             WHERE timestamp >= ?1 AND (?2 IS NULL OR starts_with(event_name, ?2)) \
+               AND (event_name LIKE '%api_request' OR (event_name LIKE '%api_error' AND {RATE_LIMITED})) \
+             ORDER BY timestamp",
+            tokens("input_tokens"),
+            tokens("output_tokens")
+        );
+        let mut stmt = self.conn.prepare_cached(&query)?;
+        let rows = stmt.query_map(params![SqlTimestamp(since), prefix], |row| {
+            Ok(UsagePoint {
+                timestamp: row.get::<_, SqlTimestamp>(0)?.0,
+                tokens: row.get::<_, i64>(1)?.max(0) as u64,
+                cost_usd: row.get(2)?,
+                rate_limited: row.get(3)?,
+            })
+        })?;
+        Ok(rows.collect::<duckdb::Result<Vec<_>>>()?)
+    }
+}
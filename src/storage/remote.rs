@@ -0,0 +1,692 @@
+//! Storage backed by another agenttop's REST API, for `agenttop view --remote URL` and for
+//! reading a database that a local agenttop is recording into.
+//!
+//! The dashboard's queries go to `POST /api/query` as [`RemoteQuery`] JSON and come back
+//! as `{"data": ...}`. Nothing is written remotely; exports and pruning are not available.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use super::watchdog::Stamp;
+use super::{StorageCommand, StorageHandle};
+use crate::anomaly::AnomalyKind;
+use crate::budget::BudgetScope;
+
+/// How long one remote query may take
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Most rows a remote query returns
+const MAX_LIMIT: usize = 10_000;
+
+/// A read-only storage query, as sent to `/api/query`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "query", rename_all = "snake_case")]
+pub enum RemoteQuery {
+    ToolMetrics {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    },
+    ToolCallSeries {
+        since: DateTime<Utc>,
+        bucket_secs: i64,
+        buckets: usize,
+    },
+    InFlightTools {
+        since: DateTime<Utc>,
+    },
+    SessionTimeline,
+    HourlyActivity {
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+    },
+    McpServerHealth {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    },
+    IsEmpty,
+    DataVersion,
+    ModelCosts {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    },
+    TopToolErrors {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        limit: usize,
+    },
+    LogEventsAfter {
+        after_id: Option<i64>,
+        limit: usize,
+    },
+    LogEvents {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        limit: usize,
+    },
+    RecentPrompts {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        limit: usize,
+    },
+    Turns {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        limit: usize,
+    },
+    ToolSequences {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        length: usize,
+        limit: usize,
+    },
+    FileActivity {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        directories: bool,
+        limit: usize,
+    },
+    TokenMetrics {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    },
+    CacheUsage {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    },
+    TotalCost {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    },
+    LastToolError {
+        tool_name: String,
+    },
+    SessionMetrics {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    },
+    ApiMetrics {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    },
+    RateLimitErrors {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    },
+    Sessions {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    },
+    SessionSummary {
+        session_id: String,
+    },
+    SessionList {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        limit: usize,
+    },
+    Anomalies {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        limit: usize,
+    },
+    AnomalyCount {
+        kind: Option<AnomalyKind>,
+    },
+    BudgetSpend {
+        scope: BudgetScope,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    },
+    BudgetUsage {
+        budget: Option<String>,
+        open_only: bool,
+        limit: usize,
+    },
+    CostBreakdown {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    },
+    ApiSpend {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    },
+    SessionProductivity {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        limit: usize,
+    },
+    ProviderComparison {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    },
+    ContextSamples {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        limit: usize,
+    },
+    UsagePoints {
+        provider: Option<String>,
+        since: DateTime<Utc>,
+    },
+    ErrorCategories {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    },
+    ErrorBreakdown {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        limit: usize,
+    },
+    /// A read-only statement from the SQL console or `agenttop query`
+    Sql {
+        sql: String,
+        limit: usize,
+    },
+}
+
+impl RemoteQuery {
+    /// Run the query against `storage` and return the result as JSON
+    pub fn execute(self, storage: &StorageHandle) -> Result<serde_json::Value> {
+        use RemoteQuery as Q;
+        match self {
+            Q::ToolMetrics { since, until } => json(storage.get_tool_metrics(since, until)?),
+            Q::ToolCallSeries {
+                since,
+                bucket_secs,
+                buckets,
+            } => json(storage.get_tool_call_series(since, bucket_secs, buckets.min(MAX_LIMIT))?),
+            Q::InFlightTools { since } => json(storage.get_in_flight_tools(since)?),
+            Q::SessionTimeline => json(storage.get_session_timeline()?),
+            Q::HourlyActivity { since, until } => json(storage.get_hourly_activity(since, until)?),
+            Q::McpServerHealth { since, until } => {
+                json(storage.get_mcp_server_health(since, until)?)
+            }
+            Q::IsEmpty => json(storage.is_empty()?),
+            Q::DataVersion => json(storage.get_data_version()?),
+            Q::ModelCosts { since, until } => json(storage.get_model_costs(since, until)?),
+            Q::TopToolErrors {
+                since,
+                until,
+                limit,
+            } => json(storage.get_top_tool_errors(since, until, limit.min(MAX_LIMIT))?),
+            Q::LogEventsAfter { after_id, limit } => {
+                json(storage.get_log_events_after(after_id, limit.min(MAX_LIMIT))?)
+            }
+            Q::LogEvents {
+                since,
+                until,
+                limit,
+            } => json(storage.get_log_events(since, until, limit.min(MAX_LIMIT))?),
+            Q::RecentPrompts {
+                since,
+                until,
+                limit,
+            } => json(storage.get_recent_prompts(since, until, limit.min(MAX_LIMIT))?),
+            Q::Turns {
+                since,
+                until,
+                limit,
+            } => json(storage.get_turns(since, until, limit.min(MAX_LIMIT))?),
+            Q::ToolSequences {
+                since,
+                until,
+                length,
+                limit,
+            } => json(storage.get_tool_sequences(since, until, length, limit.min(MAX_LIMIT))?),
+            Q::FileActivity {
+                since,
+                until,
+                directories,
+                limit,
+            } => {
+                json(storage.get_file_activity(since, until, directories, limit.min(MAX_LIMIT))?)
+            }
+            Q::TokenMetrics { since, until } => json(storage.get_token_metrics(since, until)?),
+            Q::CacheUsage { since, until } => json(storage.get_cache_usage(since, until)?),
+            Q::TotalCost { since, until } => json(storage.get_total_cost(since, until)?),
+            Q::LastToolError { tool_name } => json(storage.get_last_tool_error(&tool_name)?),
+            Q::SessionMetrics { since, until } => json(storage.get_session_metrics(since, until)?),
+            Q::ApiMetrics { since, until } => json(storage.get_api_metrics(since, until)?),
+            Q::RateLimitErrors { since, until } => {
+                json(storage.count_rate_limit_errors(since, until)?)
+            }
+            Q::Sessions { since, until } => json(storage.count_sessions(since, until)?),
+            Q::SessionSummary { session_id } => json(storage.get_session_summary(&session_id)?),
+            Q::SessionList {
+                since,
+                until,
+                limit,
+            } => json(storage.get_sessions(since, until, limit.min(MAX_LIMIT))?),
+            Q::Anomalies {
+                since,
+                until,
+                limit,
+            } => json(storage.get_anomalies(since, until, limit.min(MAX_LIMIT))?),
+            Q::AnomalyCount { kind } => json(storage.count_anomalies(kind)?),
+            Q::BudgetSpend {
+                scope,
+                since,
+                until,
+            } => json(storage.get_budget_spend(&scope, since, until)?),
+            Q::BudgetUsage {
+                budget,
+                open_only,
+                limit,
+            } => json(storage.get_budget_usage(
+                budget.as_deref(),
+                open_only,
+                limit.min(MAX_LIMIT),
+            )?),
+            Q::CostBreakdown { since, until } => json(storage.get_cost_breakdown(since, until)?),
+            Q::ApiSpend { since, until } => json(storage.get_api_spend(since, until)?),
+            Q::SessionProductivity {
+                since,
+                until,
+                limit,
+            } => json(storage.get_session_productivity(since, until, limit.min(MAX_LIMIT))?),
+            Q::ProviderComparison { since, until } => {
+                json(storage.get_provider_comparison(since, until)?)
+            }
+            Q::ContextSamples {
+                since,
+                until,
+                limit,
+            } => json(storage.get_context_samples(since, until, limit.min(MAX_LIMIT))?),
+            Q::UsagePoints { provider, since } => {
+                json(storage.get_usage_points(provider.as_deref(), since)?)
+            }
+            Q::ErrorCategories { since, until } => {
+                json(storage.get_error_categories(since, until)?)
+            }
+            Q::ErrorBreakdown {
+                since,
+                until,
+                limit,
+            } => json(storage.get_error_breakdown(since, until, limit.min(MAX_LIMIT))?),
+            Q::Sql { sql, limit } => json(storage.run_query(&sql, limit.min(MAX_LIMIT))?),
+        }
+    }
+}
+
+fn json<T: Serialize>(value: T) -> Result<serde_json::Value> {
+    Ok(serde_json::to_value(value)?)
+}
+
+#[derive(Deserialize)]
+struct Reply<T> {
+    data: T,
+}
+
+pub(super) struct RemoteClient {
+    agent: ureq::Agent,
+    /// `<base URL>/api/query`
+    url: String,
+    /// Sent as `Authorization: Bearer <token>`
+    token: Option<String>,
+}
+
+impl RemoteClient {
+    /// A client of the `/api/query` of the agenttop at `url`
+    pub(super) fn new(url: &str, token: Option<&str>) -> Self {
+        Self {
+            agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build(),
+            url: format!("{}/api/query", url.trim_end_matches('/')),
+            token: token.map(str::to_string),
+        }
+    }
+
+    fn call<T: DeserializeOwned>(&self, query: &RemoteQuery) -> Result<T> {
+        let body = serde_json::to_string(query)?;
+        let mut request = self
+            .agent
+            .post(&self.url)
+            .set("Content-Type", "application/json");
+        if let Some(token) = &self.token {
+            request = request.set("Authorization", &format!("Bearer {}", token));
+        }
+        match request.send_string(&body) {
+            Ok(response) => {
+                let reply: Reply<T> = serde_json::from_str(&response.into_string()?)
+                    .with_context(|| format!("Unexpected answer from {}", self.url))?;
+                Ok(reply.data)
+            }
+            Err(ureq::Error::Status(status, response)) => {
+                let error = response
+                    .into_string()
+                    .ok()
+                    .and_then(|body| serde_json::from_str::<serde_json::Value>(&body).ok())
+                    .and_then(|body| body.get("error")?.as_str().map(str::to_string))
+                    .unwrap_or_default();
+                anyhow::bail!("{} answered HTTP {}: {}", self.url, status, error)
+            }
+            Err(e) => Err(e).with_context(|| format!("Could not reach {}", self.url)),
+        }
+    }
+}
+
+impl StorageHandle {
+    /// Query the agenttop at `url` (running with `--api`) instead of a local database,
+    /// sending `token` if it requires one
+    pub fn connect(url: &str, token: Option<&str>) -> Result<Self> {
+        let client = RemoteClient::new(url, token);
+        client
+            .call::<bool>(&RemoteQuery::IsEmpty)
+            .with_context(|| format!("Could not query {} (is it running with --api?)", url))?;
+
+        let (sender, receiver) = mpsc::channel();
+        let actor = std::thread::spawn(move || run_remote_actor(client, receiver));
+        Ok(Self::with_sender(sender).with_actor(actor))
+    }
+}
+
+fn run_remote_actor(client: RemoteClient, receiver: mpsc::Receiver<StorageCommand>) {
+    for cmd in receiver {
+        if !client.run_command(cmd) {
+            break;
+        }
+    }
+}
+
+impl RemoteClient {
+    /// Answer one command with a query to the remote agenttop; false for
+    /// [`StorageCommand::Shutdown`]
+    pub(super) fn run_command(&self, cmd: StorageCommand) -> bool {
+        use RemoteQuery as Q;
+        let unavailable = |what: &str| anyhow::anyhow!("{} is not available in remote view", what);
+        let client = self;
+        let (cmd, stamp) = cmd.unscoped();
+        if stamp.as_ref().is_some_and(Stamp::is_superseded) {
+            return true;
+        }
+        match cmd {
+            // Viewing only: nothing is recorded in the remote database
+            StorageCommand::RecordToolEvent(_)
+            | StorageCommand::RecordLogEvents(_)
+            | StorageCommand::RecordTokenUsage { .. }
+            | StorageCommand::RecordCost { .. }
+            | StorageCommand::RecordSessionMetric { .. }
+            | StorageCommand::RecordAnomaly(_) => {}
+            StorageCommand::Ingest(queue) => drop(queue.take()),
+            StorageCommand::GetToolMetrics { since, until, tx } => {
+                let _ = tx.send(client.call(&Q::ToolMetrics { since, until }));
+            }
+            StorageCommand::GetToolCallSeries {
+                since,
+                bucket_secs,
+                buckets,
+                tx,
+            } => {
+                let _ = tx.send(client.call(&Q::ToolCallSeries {
+                    since,
+                    bucket_secs,
+                    buckets,
+                }));
+            }
+            StorageCommand::GetInFlightTools { since, tx } => {
+                let _ = tx.send(client.call(&Q::InFlightTools { since }));
+            }
+            StorageCommand::GetSessionTimeline { tx } => {
+                let _ = tx.send(client.call(&Q::SessionTimeline));
+            }
+            StorageCommand::GetHourlyActivity { since, until, tx } => {
+                let _ = tx.send(client.call(&Q::HourlyActivity { since, until }));
+            }
+            StorageCommand::GetMcpServerHealth { since, until, tx } => {
+                let _ = tx.send(client.call(&Q::McpServerHealth { since, until }));
+            }
+            StorageCommand::IsEmpty { tx } => {
+                let _ = tx.send(client.call(&Q::IsEmpty));
+            }
+            StorageCommand::GetDataVersion { tx } => {
+                let _ = tx.send(client.call(&Q::DataVersion));
+            }
+            StorageCommand::ExportTable { tx, .. } => {
+                let _ = tx.send(Err(unavailable("Exporting tables")));
+            }
+            StorageCommand::Prune { tx, .. } => {
+                let _ = tx.send(Err(unavailable("Pruning")));
+            }
+            StorageCommand::GetModelCosts { since, until, tx } => {
+                let _ = tx.send(client.call(&Q::ModelCosts { since, until }));
+            }
+            StorageCommand::GetTopToolErrors {
+                since,
+                until,
+                limit,
+                tx,
+            } => {
+                let _ = tx.send(client.call(&Q::TopToolErrors {
+                    since,
+                    until,
+                    limit,
+                }));
+            }
+            StorageCommand::RunQuery { sql, limit, tx } => {
+                let _ = tx.send(client.call(&Q::Sql { sql, limit }));
+            }
+            StorageCommand::GetLogEventsAfter {
+                after_id,
+                limit,
+                tx,
+            } => {
+                let _ = tx.send(client.call(&Q::LogEventsAfter { after_id, limit }));
+            }
+            StorageCommand::GetLogEvents {
+                since,
+                until,
+                limit,
+                tx,
+            } => {
+                let _ = tx.send(client.call(&Q::LogEvents {
+                    since,
+                    until,
+                    limit,
+                }));
+            }
+            StorageCommand::GetRecentPrompts {
+                since,
+                until,
+                limit,
+                tx,
+            } => {
+                let _ = tx.send(client.call(&Q::RecentPrompts {
+                    since,
+                    until,
+                    limit,
+                }));
+            }
+            StorageCommand::GetTurns {
+                since,
+                until,
+                limit,
+                tx,
+            } => {
+                let _ = tx.send(client.call(&Q::Turns {
+                    since,
+                    until,
+                    limit,
+                }));
+            }
+            StorageCommand::GetToolSequences {
+                since,
+                until,
+                length,
+                limit,
+                tx,
+            } => {
+                let _ = tx.send(client.call(&Q::ToolSequences {
+                    since,
+                    until,
+                    length,
+                    limit,
+                }));
+            }
+            StorageCommand::GetFileActivity {
+                since,
+                until,
+                directories,
+                limit,
+                tx,
+            } => {
+                let _ = tx.send(client.call(&Q::FileActivity {
+                    since,
+                    until,
+                    directories,
+                    limit,
+                }));
+            }
+            StorageCommand::GetTokenMetrics { since, until, tx } => {
+                let _ = tx.send(client.call(&Q::TokenMetrics { since, until }));
+            }
+            StorageCommand::GetCacheUsage { since, until, tx } => {
+                let _ = tx.send(client.call(&Q::CacheUsage { since, until }));
+            }
+            StorageCommand::GetTotalCost { since, until, tx } => {
+                let _ = tx.send(client.call(&Q::TotalCost { since, until }));
+            }
+            StorageCommand::GetLastToolError { tool_name, tx } => {
+                let _ = tx.send(client.call(&Q::LastToolError { tool_name }));
+            }
+            StorageCommand::GetSessionMetrics { since, until, tx } => {
+                let _ = tx.send(client.call(&Q::SessionMetrics { since, until }));
+            }
+            StorageCommand::GetApiMetrics { since, until, tx } => {
+                let _ = tx.send(client.call(&Q::ApiMetrics { since, until }));
+            }
+            StorageCommand::CountRateLimitErrors { since, until, tx } => {
+                let _ = tx.send(client.call(&Q::RateLimitErrors { since, until }));
+            }
+            StorageCommand::CountSessions { since, until, tx } => {
+                let _ = tx.send(client.call(&Q::Sessions { since, until }));
+            }
+            StorageCommand::GetSessionSummary { session_id, tx } => {
+                let _ = tx.send(client.call(&Q::SessionSummary { session_id }));
+            }
+            StorageCommand::GetSessions {
+                since,
+                until,
+                limit,
+                tx,
+            } => {
+                let _ = tx.send(client.call(&Q::SessionList {
+                    since,
+                    until,
+                    limit,
+                }));
+            }
+            StorageCommand::GetAnomalies {
+                since,
+                until,
+                limit,
+                tx,
+            } => {
+                let _ = tx.send(client.call(&Q::Anomalies {
+                    since,
+                    until,
+                    limit,
+                }));
+            }
+            StorageCommand::CountAnomalies { kind, tx } => {
+                let _ = tx.send(client.call(&Q::AnomalyCount { kind }));
+            }
+            StorageCommand::GetBudgetSpend {
+                scope,
+                since,
+                until,
+                tx,
+            } => {
+                let _ = tx.send(client.call(&Q::BudgetSpend {
+                    scope,
+                    since,
+                    until,
+                }));
+            }
+            StorageCommand::GetBudgetUsage {
+                budget,
+                open_only,
+                limit,
+                tx,
+            } => {
+                let _ = tx.send(client.call(&Q::BudgetUsage {
+                    budget,
+                    open_only,
+                    limit,
+                }));
+            }
+            StorageCommand::SaveBudgetUsage { tx, .. } => {
+                let _ = tx.send(Err(unavailable("Recording budget usage")));
+            }
+            StorageCommand::ScoreSessions { tx, .. } => {
+                let _ = tx.send(Err(unavailable("Scoring sessions")));
+            }
+            StorageCommand::GetCostBreakdown { since, until, tx } => {
+                let _ = tx.send(client.call(&Q::CostBreakdown { since, until }));
+            }
+            StorageCommand::GetApiSpend { since, until, tx } => {
+                let _ = tx.send(client.call(&Q::ApiSpend { since, until }));
+            }
+            StorageCommand::GetSessionProductivity {
+                since,
+                until,
+                limit,
+                tx,
+            } => {
+                let _ = tx.send(client.call(&Q::SessionProductivity {
+                    since,
+                    until,
+                    limit,
+                }));
+            }
+            StorageCommand::GetProviderComparison { since, until, tx } => {
+                let _ = tx.send(client.call(&Q::ProviderComparison { since, until }));
+            }
+            StorageCommand::GetContextSamples {
+                since,
+                until,
+                limit,
+                tx,
+            } => {
+                let _ = tx.send(client.call(&Q::ContextSamples {
+                    since,
+                    until,
+                    limit,
+                }));
+            }
+            StorageCommand::GetUsagePoints {
+                provider,
+                since,
+                tx,
+            } => {
+                let _ = tx.send(client.call(&Q::UsagePoints { provider, since }));
+            }
+            StorageCommand::GetErrorCategories { since, until, tx } => {
+                let _ = tx.send(client.call(&Q::ErrorCategories { since, until }));
+            }
+            StorageCommand::GetErrorBreakdown {
+                since,
+                until,
+                limit,
+                tx,
+            } => {
+                let _ = tx.send(client.call(&Q::ErrorBreakdown {
+                    since,
+                    until,
+                    limit,
+                }));
+            }
+            StorageCommand::ReclassifyErrors { tx } => {
+                let _ = tx.send(Err(unavailable("Reclassifying errors")));
+            }
+            StorageCommand::StreamTokenUsage { tx, .. } => {
+                let _ = tx.send(Err(unavailable("Streaming token usage").into()));
+            }
+            // Unwrapped above
+            StorageCommand::Scoped { .. } => {}
+            StorageCommand::Shutdown => return false,
+        }
+        true
+    }
+}
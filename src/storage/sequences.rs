@@ -0,0 +1,123 @@
+//! Tool sequences: runs of consecutive tool calls within a session (n-grams), e.g.
+//! Grep → Read → Edit, counted over all sessions in a range. A run that starts right
+//! where an identical one ended is counted as a repeat, which is how agent loops show
+//! up (Read → Edit → Bash, over and over).
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+
+use super::{SqlTimestamp, Storage, ToolSequence, time_clause, time_params};
+
+/// One tool_result: the tool, when the call started and when it ended
+struct Call {
+    tool: String,
+    started_at: DateTime<Utc>,
+    ended_at: DateTime<Utc>,
+}
+
+#[derive(Default)]
+struct Totals {
+    count: u64,
+    sessions: u64,
+    repeats: u64,
+    cycle_ms: f64,
+    /// Index of the last session counted in `sessions`
+    last_session: Option<usize>,
+}
+
+impl Storage {
+    pub(super) fn get_tool_sequences(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        length: usize,
+        limit: usize,
+    ) -> Result<Vec<ToolSequence>> {
+        let length = length.max(1);
+        let query = format!(
+            r#"
+            SELECT
+                json_extract_string(attributes, '$."session.id"') as session_id,
+                timestamp,
+                COALESCE(json_extract_string(attributes, '$.tool_name'), 'unknown'),
+                COALESCE(TRY_CAST(json_extract(attributes, '$.duration_ms') AS BIGINT), 0)
+            FROM log_events
+            WHERE event_name LIKE '%tool_result' {}
+            ORDER BY session_id, timestamp, id
+            "#,
+            time_clause("AND", since, until)
+        );
+        let mut stmt = self.conn.prepare_cached(&query)?;
+        let mut rows = stmt.query(time_params(since, until))?;
+
+        let mut totals: HashMap<Vec<String>, Totals> = HashMap::new();
+        let mut session: Option<Option<String>> = None;
+        let mut calls: Vec<Call> = Vec::new();
+        let mut sessions = 0;
+        while let Some(row) = rows.next()? {
+            let id: Option<String> = row.get(0)?;
+            if session.as_ref() != Some(&id) {
+                count_sequences(&calls, length, sessions, &mut totals);
+                calls.clear();
+                sessions += 1;
+                session = Some(id);
+            }
+            let ended_at = row.get::<_, SqlTimestamp>(1)?.0;
+            let duration_ms = row.get::<_, i64>(3)?.max(0);
+            calls.push(Call {
+                tool: row.get(2)?,
+                started_at: ended_at - chrono::Duration::milliseconds(duration_ms),
+                ended_at,
+            });
+        }
+        count_sequences(&calls, length, sessions, &mut totals);
+
+        let mut sequences: Vec<ToolSequence> = totals
+            .into_iter()
+            .filter(|(_, totals)| totals.count > 1)
+            .map(|(tools, totals)| ToolSequence {
+                tools,
+                count: totals.count,
+                sessions: totals.sessions,
+                repeats: totals.repeats,
+                avg_cycle_ms: totals.cycle_ms / totals.count as f64,
+            })
+            .collect();
+        sequences.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then_with(|| b.repeats.cmp(&a.repeats))
+                .then_with(|| a.tools.cmp(&b.tools))
+        });
+        sequences.truncate(limit);
+        Ok(sequences)
+    }
+}
+
+/// Add every run of `length` calls in one session's `calls` to `totals`
+fn count_sequences(
+    calls: &[Call],
+    length: usize,
+    session: usize,
+    totals: &mut HashMap<Vec<String>, Totals>,
+) {
+    for (start, window) in calls.windows(length).enumerate() {
+        let tools: Vec<String> = window.iter().map(|call| call.tool.clone()).collect();
+        let repeat = start >= length
+            && calls[start - length..start]
+                .iter()
+                .zip(&tools)
+                .all(|(call, tool)| call.tool == *tool);
+        let cycle = window[length - 1].ended_at - window[0].started_at;
+
+        let entry = totals.entry(tools).or_default();
+        entry.count += 1;
+        entry.repeats += repeat as u64;
+        entry.cycle_ms += cycle.num_milliseconds().max(0) as f64;
+        if entry.last_session != Some(session) {
+            entry.sessions += 1;
+            entry.last_session = Some(session);
+        }
+    }
+}
@@ -0,0 +1,297 @@
+//! Session detection. Log events with a `session.id` attribute belong to that session;
+//! events of agents that don't send one are split per provider into runs without a
+//! [`SESSION_IDLE_GAP`] between their events. The storage actor adds each inserted
+//! batch to the `sessions` table, so per-session views read one row per session instead
+//! of scanning the events.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use duckdb::params;
+use std::collections::HashMap;
+
+use super::{LogEvent, SESSION_IDLE_GAP, Session, SqlTimestamp, Storage};
+use crate::efficiency::Efficiency;
+use crate::providers::PROVIDER_REGISTRY;
+
+/// Log events read at a time when filling the sessions table from existing events
+const REBUILD_BATCH: usize = 10_000;
+
+/// A batch's additions to one session
+#[derive(Default)]
+struct Totals {
+    inferred: bool,
+    provider: Option<&'static str>,
+    started_at: Option<DateTime<Utc>>,
+    ended_at: Option<DateTime<Utc>>,
+    events: u64,
+    api_calls: u64,
+    api_errors: u64,
+    tool_calls: u64,
+    cost_usd: f64,
+    input_tokens: u64,
+    output_tokens: u64,
+}
+
+impl Totals {
+    fn add(&mut self, event: &LogEvent, provider: Option<&'static str>) {
+        self.provider = self.provider.or(provider);
+        self.started_at = Some(
+            self.started_at
+                .map_or(event.timestamp, |t| t.min(event.timestamp)),
+        );
+        self.ended_at = Some(
+            self.ended_at
+                .map_or(event.timestamp, |t| t.max(event.timestamp)),
+        );
+        self.events += 1;
+
+        let name = event.event_name.as_deref().unwrap_or_default();
+        let attr = |key: &str| {
+            event
+                .attributes
+                .get(key)
+                .and_then(|v| v.parse::<f64>().ok())
+        };
+        if name.ends_with("api_request") {
+            self.api_calls += 1;
+            self.cost_usd += attr("cost_usd").unwrap_or(0.0);
+            self.input_tokens += attr("input_tokens").unwrap_or(0.0) as u64;
+            self.output_tokens += attr("output_tokens").unwrap_or(0.0) as u64;
+        } else if name.ends_with("api_error") {
+            self.api_errors += 1;
+        } else if name.ends_with("tool_result") {
+            self.tool_calls += 1;
+        }
+    }
+}
+
+/// Id, first and last event of a session
+type Span = (String, DateTime<Utc>, DateTime<Utc>);
+
+/// Id of a session found by inactivity gaps: the provider and its first event
+fn inferred_id(provider: Option<&str>, started_at: DateTime<Utc>) -> String {
+    format!(
+        "{}@{}",
+        provider.unwrap_or("unknown"),
+        started_at.format("%Y-%m-%dT%H:%M:%S%.3fZ")
+    )
+}
+
+impl Storage {
+    /// Add `events`, just inserted into log_events, to their sessions and the sessions'
+    /// active segments (see activity.rs)
+    pub(super) fn track_sessions(&self, events: &[LogEvent]) -> Result<()> {
+        let gap = chrono::Duration::from_std(SESSION_IDLE_GAP)?;
+        let mut totals: HashMap<String, Totals> = HashMap::new();
+        let mut unnamed: HashMap<Option<&'static str>, Vec<&LogEvent>> = HashMap::new();
+        let mut times: HashMap<String, Vec<DateTime<Utc>>> = HashMap::new();
+        for event in events {
+            let provider = event
+                .event_name
+                .as_deref()
+                .and_then(|name| PROVIDER_REGISTRY.detect_from_metric(name))
+                .map(|p| p.id());
+            match event.attributes.get("session.id") {
+                Some(id) => {
+                    totals.entry(id.clone()).or_default().add(event, provider);
+                    times.entry(id.clone()).or_default().push(event.timestamp);
+                }
+                None => unnamed.entry(provider).or_default().push(event),
+            }
+        }
+
+        for (provider, mut events) in unnamed {
+            events.sort_by_key(|event| event.timestamp);
+            let mut current = self.latest_inferred_session(provider)?;
+            for event in events {
+                let joins = current.as_ref().is_some_and(|(_, started, ended)| {
+                    event.timestamp >= *started - gap && event.timestamp <= *ended + gap
+                });
+                if !joins {
+                    let id = inferred_id(provider, event.timestamp);
+                    current = Some((id, event.timestamp, event.timestamp));
+                }
+                let (id, started, ended) = current.as_mut().expect("set above");
+                *started = (*started).min(event.timestamp);
+                *ended = (*ended).max(event.timestamp);
+                let session = totals.entry(id.clone()).or_default();
+                session.inferred = true;
+                session.add(event, provider);
+                times.entry(id.clone()).or_default().push(event.timestamp);
+            }
+        }
+
+        let mut stmt = self.conn.prepare_cached(
+            r#"
+            INSERT INTO sessions (session_id, inferred, provider, started_at, ended_at, events,
+                                  api_calls, api_errors, tool_calls, cost_usd, input_tokens,
+                                  output_tokens)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT (session_id) DO UPDATE SET
+                provider = coalesce(provider, excluded.provider),
+                started_at = least(started_at, excluded.started_at),
+                ended_at = greatest(ended_at, excluded.ended_at),
+                events = events + excluded.events,
+                api_calls = api_calls + excluded.api_calls,
+                api_errors = api_errors + excluded.api_errors,
+                tool_calls = tool_calls + excluded.tool_calls,
+                cost_usd = cost_usd + excluded.cost_usd,
+                input_tokens = input_tokens + excluded.input_tokens,
+                output_tokens = output_tokens + excluded.output_tokens
+            "#,
+        )?;
+        for (id, session) in totals {
+            let (Some(started_at), Some(ended_at)) = (session.started_at, session.ended_at) else {
+                continue;
+            };
+            stmt.execute(params![
+                id,
+                session.inferred,
+                session.provider,
+                SqlTimestamp(started_at),
+                SqlTimestamp(ended_at),
+                session.events as i64,
+                session.api_calls as i64,
+                session.api_errors as i64,
+                session.tool_calls as i64,
+                session.cost_usd,
+                session.input_tokens as i64,
+                session.output_tokens as i64,
+            ])?;
+        }
+        self.track_activity(&times)
+    }
+
+    /// Id, start and end of the session found by inactivity gaps that ended last
+    fn latest_inferred_session(&self, provider: Option<&str>) -> Result<Option<Span>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT session_id, started_at, ended_at FROM sessions \
+             WHERE inferred AND provider IS NOT DISTINCT FROM ? \
+             ORDER BY ended_at DESC LIMIT 1",
+        )?;
+        let mut rows = stmt.query_map(params![provider], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, SqlTimestamp>(1)?.0,
+                row.get::<_, SqlTimestamp>(2)?.0,
+            ))
+        })?;
+        Ok(rows.next().transpose()?)
+    }
+
+    /// Fill an empty sessions table from the stored log events, e.g. in a database
+    /// written before sessions were tracked
+    pub(super) fn rebuild_sessions(&self) -> Result<()> {
+        let tracked: bool =
+            self.conn
+                .query_row("SELECT EXISTS (SELECT 1 FROM sessions)", [], |row| {
+                    row.get(0)
+                })?;
+        if tracked {
+            return Ok(());
+        }
+
+        let mut after = i64::MIN;
+        loop {
+            let mut stmt = self.conn.prepare(&format!(
+                "SELECT id, timestamp, event_name, CAST(attributes AS VARCHAR) FROM log_events \
+                 WHERE id > ? ORDER BY id LIMIT {REBUILD_BATCH}"
+            ))?;
+            let rows = stmt
+                .query_map(params![after], |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, SqlTimestamp>(1)?.0,
+                        row.get::<_, Option<String>>(2)?,
+                        row.get::<_, Option<String>>(3)?,
+                    ))
+                })?
+                .collect::<duckdb::Result<Vec<_>>>()?;
+            let Some((last, ..)) = rows.last() else {
+                return Ok(());
+            };
+            after = *last;
+
+            let events: Vec<LogEvent> = rows
+                .into_iter()
+                .map(|(_, timestamp, event_name, attributes)| LogEvent {
+                    timestamp,
+                    event_name,
+                    body: None,
+                    attributes: attributes
+                        .and_then(|json| serde_json::from_str(&json).ok())
+                        .unwrap_or_default(),
+                })
+                .collect();
+            self.track_sessions(&events)?;
+        }
+    }
+
+    pub(super) fn get_sessions(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        limit: usize,
+    ) -> Result<Vec<Session>> {
+        let mut bounds = Vec::new();
+        if since.is_some() {
+            bounds.push(format!("ended_at >= ?{}", bounds.len() + 1));
+        }
+        if until.is_some() {
+            bounds.push(format!("started_at < ?{}", bounds.len() + 1));
+        }
+        let filter = if bounds.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", bounds.join(" AND "))
+        };
+        let query = format!(
+            r#"
+            SELECT session_id, inferred, provider, started_at, ended_at, events, api_calls,
+                   api_errors, tool_calls, cost_usd, input_tokens, output_tokens,
+                   score, success_rate, cache_reuse, retry_rate, cost_per_outcome, outcomes,
+                   CAST(COALESCE(active_ms, 0) AS BIGINT)
+            FROM sessions
+            LEFT JOIN session_scores USING (session_id)
+            LEFT JOIN (
+                SELECT session_id, SUM(date_diff('millisecond', started_at, ended_at)) AS active_ms
+                FROM session_activity
+                GROUP BY session_id
+            ) USING (session_id)
+            {filter}
+            ORDER BY started_at DESC
+            LIMIT {limit}
+            "#
+        );
+        let mut stmt = self.conn.prepare_cached(&query)?;
+        let rows = stmt.query_map(super::time_params(since, until), |row| {
+            Ok(Session {
+                session_id: row.get(0)?,
+                inferred: row.get(1)?,
+                provider: row.get(2)?,
+                started_at: row.get::<_, SqlTimestamp>(3)?.0,
+                ended_at: row.get::<_, SqlTimestamp>(4)?.0,
+                events: row.get::<_, i64>(5)? as u64,
+                api_calls: row.get::<_, i64>(6)? as u64,
+                api_errors: row.get::<_, i64>(7)? as u64,
+                tool_calls: row.get::<_, i64>(8)? as u64,
+                cost_usd: row.get(9)?,
+                input_tokens: row.get::<_, i64>(10)? as u64,
+                output_tokens: row.get::<_, i64>(11)? as u64,
+                efficiency: match row.get::<_, Option<f64>>(12)? {
+                    Some(score) => Some(Efficiency {
+                        score,
+                        success_rate: row.get(13)?,
+                        cache_reuse: row.get(14)?,
+                        retry_rate: row.get(15)?,
+                        cost_per_outcome: row.get(16)?,
+                        outcomes: row.get::<_, i64>(17)? as u64,
+                    }),
+                    None => None,
+                },
+                active_secs: row.get::<_, i64>(18)?.max(0) as u64 / 1000,
+            })
+        })?;
+        Ok(rows.collect::<duckdb::Result<Vec<_>>>()?)
+    }
+}
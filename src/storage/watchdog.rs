@@ -0,0 +1,157 @@
+//! Interrupts queries that run too long or that a newer refresh superseded. The storage
+//! actor runs one query at a time, so a slow analytic query (all-time history on a
+//! large database) would otherwise hold up every command queued behind it.
+//!
+//! One background thread watches the running queries of all actors, sleeping until the
+//! next deadline or until [`Watchdog::wake`] reports a superseded scope.
+
+use duckdb::{Connection, InterruptHandle};
+use once_cell::sync::Lazy;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Instant;
+
+/// The watchdog shared by all storage actors
+pub(super) static WATCHDOG: Lazy<Arc<Watchdog>> = Lazy::new(|| {
+    let watchdog = Arc::new(Watchdog::default());
+    let running = Arc::clone(&watchdog);
+    std::thread::spawn(move || running.run());
+    watchdog
+});
+
+/// Generation of a [`StorageHandle::scoped`](super::StorageHandle::scoped) handle's
+/// queries; bumping it supersedes the ones sent before
+pub(super) type Scope = Arc<AtomicU64>;
+
+/// A query sent from `scope` at `generation`
+pub(super) struct Stamp {
+    pub scope: Scope,
+    pub generation: u64,
+}
+
+impl Stamp {
+    pub fn is_superseded(&self) -> bool {
+        self.scope.load(Ordering::Acquire) != self.generation
+    }
+}
+
+struct Watched {
+    id: u64,
+    deadline: Option<Instant>,
+    stamp: Option<Stamp>,
+    interrupt: Arc<InterruptHandle>,
+}
+
+impl Watched {
+    fn is_due(&self, now: Instant) -> bool {
+        self.deadline.is_some_and(|deadline| deadline <= now)
+            || self.stamp.as_ref().is_some_and(Stamp::is_superseded)
+    }
+}
+
+#[derive(Default)]
+pub(super) struct Watchdog {
+    watched: Mutex<Vec<Watched>>,
+    next_id: AtomicU64,
+    changed: Condvar,
+}
+
+/// Stops watching its query when dropped
+pub(super) struct WatchGuard {
+    watchdog: Arc<Watchdog>,
+    id: u64,
+}
+
+impl Drop for WatchGuard {
+    fn drop(&mut self) {
+        let mut watched = self.watchdog.watched.lock().unwrap();
+        watched.retain(|w| w.id != self.id);
+    }
+}
+
+impl Watchdog {
+    /// Interrupt the query about to run on `conn` at `deadline`, or once `stamp` is
+    /// superseded, unless the returned guard is dropped first
+    pub fn watch(
+        self: &Arc<Self>,
+        conn: &Connection,
+        deadline: Option<Instant>,
+        stamp: Option<Stamp>,
+    ) -> Option<WatchGuard> {
+        if deadline.is_none() && stamp.is_none() {
+            return None;
+        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.watched.lock().unwrap().push(Watched {
+            id,
+            deadline,
+            stamp,
+            interrupt: conn.interrupt_handle(),
+        });
+        self.wake();
+        Some(WatchGuard {
+            watchdog: Arc::clone(self),
+            id,
+        })
+    }
+
+    /// Check the watched queries again, e.g. after a scope was superseded
+    pub fn wake(&self) {
+        self.changed.notify_one();
+    }
+
+    fn run(&self) {
+        let mut watched = self.watched.lock().unwrap();
+        loop {
+            let now = Instant::now();
+            // Interrupting under the lock: the query's guard can't be dropped meanwhile,
+            // so the next command on the connection is never hit instead
+            watched.retain(|w| {
+                let due = w.is_due(now);
+                if due {
+                    w.interrupt.interrupt();
+                }
+                !due
+            });
+            watched = match watched.iter().filter_map(|w| w.deadline).min() {
+                Some(next) => {
+                    let wait = next.saturating_duration_since(now);
+                    self.changed.wait_timeout(watched, wait).unwrap().0
+                }
+                None => self.changed.wait(watched).unwrap(),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const SLOW_QUERY: &str = "SELECT count(*) FROM range(100000000) a, range(1000000) b";
+
+    #[test]
+    fn test_interrupts_at_deadline() {
+        let conn = Connection::open_in_memory().unwrap();
+        let started = Instant::now();
+        let guard = WATCHDOG.watch(&conn, Some(started + Duration::from_millis(200)), None);
+        let result = conn.query_row(SLOW_QUERY, [], |row| row.get::<_, i64>(0));
+        drop(guard);
+
+        assert!(result.unwrap_err().to_string().contains("INTERRUPT"));
+        assert!(started.elapsed() < Duration::from_secs(30));
+        // The connection is usable again afterwards
+        assert_eq!(
+            conn.query_row("SELECT 1", [], |row| row.get::<_, i64>(0))
+                .unwrap(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_unwatched_without_deadline_or_scope() {
+        let conn = Connection::open_in_memory().unwrap();
+        assert!(WATCHDOG.watch(&conn, None, None).is_none());
+    }
+}
@@ -0,0 +1,166 @@
+//! `agenttop tail`: print each newly received log event as one JSON line.
+//!
+//! When agenttop is already receiving on the OTLP port, events are read from its
+//! `/events/stream` endpoint (served with `--event-stream`); otherwise tail runs the receiver itself (storing events
+//! as usual) and prints from the in-process event feed.
+
+use anyhow::Result;
+use std::io::Write;
+use std::net::SocketAddr;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::otlp::{self, EVENT_FEED, ReceiverOptions};
+use crate::port::connect_addr;
+use crate::storage::{LogEvent, StorageHandle};
+
+/// Match `text` against a glob where `*` matches any run of characters and `?` one character
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` and the text position it is currently matched up to
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Whether an event passes the `--filter` globs (no globs = everything passes)
+pub fn matches_filters(event_name: Option<&str>, filters: &[String]) -> bool {
+    filters.is_empty()
+        || filters
+            .iter()
+            .any(|glob| glob_match(glob, event_name.unwrap_or("")))
+}
+
+/// Follow the events received on `bind`, by whoever receives them
+pub async fn run(filters: &[String], bind: SocketAddr) -> Result<()> {
+    if tokio::net::TcpListener::bind(bind).await.is_err() {
+        follow_remote(filters, bind).await
+    } else {
+        follow_local(filters, bind).await
+    }
+}
+
+/// Read the JSON lines streamed by the agenttop instance that holds the port
+async fn follow_remote(filters: &[String], bind: SocketAddr) -> Result<()> {
+    let addr = connect_addr(bind);
+    let mut stream = tokio::net::TcpStream::connect(addr).await?;
+    // HTTP/1.0 so the body is not chunked and ends when the connection closes
+    stream
+        .write_all(format!("GET /events/stream HTTP/1.0\r\nHost: {addr}\r\n\r\n").as_bytes())
+        .await?;
+    let mut lines = BufReader::new(stream).lines();
+
+    let status = lines.next_line().await?.unwrap_or_default();
+    match status.split_whitespace().nth(1) {
+        Some("200") => {}
+        Some("404") => anyhow::bail!(
+            "{} does not stream events; restart the agenttop there with --event-stream",
+            bind
+        ),
+        _ => anyhow::bail!(
+            "{} is in use but does not stream events (is it an older agenttop?)",
+            bind
+        ),
+    }
+    // Skip the headers
+    while let Some(line) = lines.next_line().await? {
+        if line.is_empty() {
+            break;
+        }
+    }
+    eprintln!("Following events from the agenttop on {}", bind);
+
+    while let Some(line) = lines.next_line().await? {
+        let Ok(event) = serde_json::from_str::<LogEvent>(&line) else {
+            continue;
+        };
+        if matches_filters(event.event_name.as_deref(), filters) && !emit(&line) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Receive events ourselves and print them from the in-process feed
+async fn follow_local(filters: &[String], bind: SocketAddr) -> Result<()> {
+    let storage = StorageHandle::new()?;
+    let mut events = EVENT_FEED.subscribe();
+    let options = ReceiverOptions {
+        bind: Some(bind),
+        ..Default::default()
+    };
+    let receiver = tokio::spawn(otlp::start_receiver(storage, options));
+    eprintln!(
+        "No agenttop running; receiving on {} and storing events as usual",
+        bind
+    );
+
+    loop {
+        match events.recv().await {
+            Ok(event) => {
+                if !matches_filters(event.event_name.as_deref(), filters) {
+                    continue;
+                }
+                if !emit(&serde_json::to_string(&event)?) {
+                    break;
+                }
+            }
+            Err(RecvError::Lagged(skipped)) => {
+                eprintln!("Skipped {} events (output too slow)", skipped);
+            }
+            Err(RecvError::Closed) => break,
+        }
+    }
+    receiver.abort();
+    Ok(())
+}
+
+/// Print one line; false once stdout is closed (e.g. `| head`)
+fn emit(line: &str) -> bool {
+    let mut stdout = std::io::stdout().lock();
+    writeln!(stdout, "{}", line)
+        .and_then(|_| stdout.flush())
+        .is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*tool_result", "claude_code.tool_result"));
+        assert!(glob_match("claude_code.*", "claude_code.api_request"));
+        assert!(glob_match("api_?equest", "api_request"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*a*b*", "xaxxbx"));
+        assert!(!glob_match("*tool_result", "claude_code.tool_decision"));
+        assert!(!glob_match("api_request", "claude_code.api_request"));
+    }
+
+    #[test]
+    fn test_matches_filters() {
+        let filters = vec!["*api_*".to_string(), "user_prompt".to_string()];
+        assert!(matches_filters(Some("gemini_cli.api_error"), &filters));
+        assert!(matches_filters(Some("user_prompt"), &filters));
+        assert!(!matches_filters(Some("tool_result"), &filters));
+        assert!(!matches_filters(None, &filters));
+        assert!(matches_filters(None, &[]));
+    }
+}
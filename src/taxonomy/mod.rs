@@ -0,0 +1,160 @@
+//! Error taxonomy: tool and API error messages sorted into categories (permission
+//! denied, timeout, not found, rate limit, syntax, ...) by regex rules. Rules from the
+//! config file's `error_categories` are tried before the built-in [`DEFAULT_RULES`]; the
+//! first match wins and a message nothing matches is [`UNCATEGORIZED`].
+//!
+//! ```json
+//! "error_categories": [
+//!   { "category": "network", "pattern": "(?i)connection (refused|reset)|ECONNRESET" },
+//!   { "category": "not_found", "pattern": "(?i)no such branch" }
+//! ]
+//! ```
+//!
+//! The storage actor classifies each failed tool call and api_error as it is inserted
+//! and keeps the category in the `error_events` table; `agenttop errors --reclassify`
+//! applies changed rules to the stored errors.
+
+use anyhow::{Context, Result};
+use once_cell::sync::{Lazy, OnceCell};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// Category of an error no rule matches
+pub const UNCATEGORIZED: &str = "other";
+
+/// Built-in rules, tried after the configured ones: (category, regex)
+pub const DEFAULT_RULES: &[(&str, &str)] = &[
+    (
+        "permission_denied",
+        r"(?i)permission denied|access denied|forbidden|not permitted|unauthori[sz]ed|\bEACCES\b|\bEPERM\b|\b40[13]\b",
+    ),
+    (
+        "rate_limit",
+        r"(?i)rate.?limit|too many requests|quota exceeded|overloaded|\b429\b|\b529\b",
+    ),
+    (
+        "timeout",
+        r"(?i)timed? ?out|timeout|deadline exceeded|\bETIMEDOUT\b|\b408\b|\b504\b",
+    ),
+    (
+        "not_found",
+        r"(?i)not found|no such file|does not exist|doesn't exist|cannot find|could not find|\bENOENT\b|\b404\b",
+    ),
+    (
+        "syntax",
+        r"(?i)syntax ?error|parse error|unexpected token|invalid json|unterminated|malformed",
+    ),
+];
+
+/// A configured rule: messages matching `pattern` are in `category`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErrorRule {
+    pub category: String,
+    /// Regex searched for in the message (and the status code of API errors)
+    pub pattern: String,
+}
+
+/// Compiled rules, configured first
+#[derive(Debug)]
+pub struct Classifier {
+    rules: Vec<(String, Regex)>,
+}
+
+impl Classifier {
+    /// `rules` followed by the [`DEFAULT_RULES`]
+    pub fn new(rules: &[ErrorRule]) -> Result<Self> {
+        let configured = rules.iter().map(|rule| {
+            Regex::new(&rule.pattern)
+                .map(|regex| (rule.category.clone(), regex))
+                .with_context(|| format!("Error category `{}`: invalid regex", rule.category))
+        });
+        let defaults = DEFAULT_RULES.iter().map(|(category, pattern)| {
+            Ok((
+                category.to_string(),
+                Regex::new(pattern).expect("valid default rule"),
+            ))
+        });
+        Ok(Self {
+            rules: configured.chain(defaults).collect::<Result<_>>()?,
+        })
+    }
+
+    /// Category of the first rule matching `message`
+    pub fn classify(&self, message: &str) -> &str {
+        self.rules
+            .iter()
+            .find(|(_, regex)| regex.is_match(message))
+            .map_or(UNCATEGORIZED, |(category, _)| category)
+    }
+}
+
+/// Rules set from the config file, if any
+static CLASSIFIER: OnceCell<Classifier> = OnceCell::new();
+
+/// Classify errors with `rules` (then the defaults) for the rest of the process
+pub fn set_rules(rules: &[ErrorRule]) -> Result<()> {
+    let classifier = Classifier::new(rules)?;
+    let _ = CLASSIFIER.set(classifier);
+    Ok(())
+}
+
+/// Category of `message` under the configured rules, or the defaults
+pub fn classify(message: &str) -> String {
+    static DEFAULT: Lazy<Classifier> =
+        Lazy::new(|| Classifier::new(&[]).expect("valid default rules"));
+    CLASSIFIER
+        .get()
+        .unwrap_or(&DEFAULT)
+        .classify(message)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_rules() {
+        let classifier = Classifier::new(&[]).unwrap();
+        for (message, category) in [
+            ("Permission denied (os error 13)", "permission_denied"),
+            ("EACCES: open '/etc/shadow'", "permission_denied"),
+            ("429 Too Many Requests", "rate_limit"),
+            ("Overloaded", "rate_limit"),
+            ("Command timed out after 120s", "timeout"),
+            ("File does not exist: src/lib.rs", "not_found"),
+            ("ENOENT: no such file or directory", "not_found"),
+            ("SyntaxError: Unexpected token '}'", "syntax"),
+            ("exit code 1", UNCATEGORIZED),
+            ("", UNCATEGORIZED),
+        ] {
+            assert_eq!(classifier.classify(message), category, "{message}");
+        }
+    }
+
+    #[test]
+    fn test_configured_rules_first() {
+        let rules = [
+            ErrorRule {
+                category: "network".to_string(),
+                pattern: "(?i)connection refused".to_string(),
+            },
+            // Overrides the default for some timeouts
+            ErrorRule {
+                category: "build".to_string(),
+                pattern: "cargo build timed out".to_string(),
+            },
+        ];
+        let classifier = Classifier::new(&rules).unwrap();
+        assert_eq!(classifier.classify("Connection refused"), "network");
+        assert_eq!(classifier.classify("cargo build timed out"), "build");
+        assert_eq!(classifier.classify("request timed out"), "timeout");
+
+        let invalid = [ErrorRule {
+            category: "bad".to_string(),
+            pattern: "(".to_string(),
+        }];
+        let err = Classifier::new(&invalid).unwrap_err();
+        assert!(format!("{err:#}").contains("Error category `bad`"));
+    }
+}
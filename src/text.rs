@@ -0,0 +1,96 @@
+//! Cutting text to a terminal width. Names and messages come from agents and can hold
+//! any Unicode, so cuts are made between grapheme clusters (never inside a multibyte
+//! character or an emoji sequence) and measured in display columns.
+
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Columns `text` takes up in a terminal
+pub fn width(text: &str) -> usize {
+    text.width()
+}
+
+/// The longest start of `text` that fits in `width` columns
+pub fn prefix(text: &str, width: usize) -> &str {
+    let mut used = 0;
+    let mut end = 0;
+    for (start, grapheme) in text.grapheme_indices(true) {
+        used += grapheme.width();
+        if used > width {
+            break;
+        }
+        end = start + grapheme.len();
+    }
+    &text[..end]
+}
+
+/// The longest end of `text` that fits in `width` columns
+pub fn suffix(text: &str, width: usize) -> &str {
+    let mut used = 0;
+    let mut start = text.len();
+    for (i, grapheme) in text.grapheme_indices(true).rev() {
+        used += grapheme.width();
+        if used > width {
+            break;
+        }
+        start = i;
+    }
+    &text[start..]
+}
+
+/// `text` cut to at most `width` columns, ending in "…" when it did not fit
+pub fn truncate(text: &str, width: usize) -> String {
+    if self::width(text) <= width {
+        return text.to_string();
+    }
+    if width == 0 {
+        return String::new();
+    }
+    format!("{}…", prefix(text, width.saturating_sub(1)))
+}
+
+/// `text` cut to at most `width` columns from the front, starting with "…" when it did
+/// not fit; for paths, whose end matters most
+pub fn truncate_start(text: &str, width: usize) -> String {
+    if self::width(text) <= width {
+        return text.to_string();
+    }
+    if width == 0 {
+        return String::new();
+    }
+    format!("…{}", suffix(text, width.saturating_sub(1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefix() {
+        assert_eq!(prefix("claude-sonnet", 6), "claude");
+        assert_eq!(prefix("short", 10), "short");
+        // "é" as e + combining accent stays whole
+        assert_eq!(prefix("cafe\u{301}s", 4), "cafe\u{301}");
+        // Wide characters take two columns each
+        assert_eq!(prefix("模型名称", 5), "模型");
+        assert_eq!(prefix("👍🏽ok", 1), "");
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("Read", 10), "Read");
+        assert_eq!(truncate("mcp__github__get_issue", 10), "mcp__gith…");
+        assert_eq!(truncate("ツールの実行に失敗", 7), "ツール…");
+        assert_eq!(truncate("anything", 1), "…");
+        assert_eq!(truncate("anything", 0), "");
+    }
+
+    #[test]
+    fn test_truncate_start() {
+        assert_eq!(suffix("src/main.rs", 7), "main.rs");
+        assert_eq!(suffix("模型名称", 5), "名称");
+        assert_eq!(truncate_start("/repo/src/main.rs", 10), "…c/main.rs");
+        assert_eq!(truncate_start("main.rs", 10), "main.rs");
+        assert_eq!(truncate_start("anything", 0), "");
+    }
+}
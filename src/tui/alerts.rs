@@ -0,0 +1,145 @@
+//! Error-rate alerts shown as a banner at the top of the TUI.
+//!
+//! Alerts are recomputed on every refresh from a short look-back window, so a
+//! banner clears on its own once the offending tool or model recovers. Anomalies
+//! recorded by the detector within the window are shown after them, then sessions
+//! whose requests kept filling most of the context window (see [`crate::context`]), then
+//! usage windows (see [`crate::quota`]) near or at their capacity.
+
+use crate::anomaly::Anomaly;
+use crate::context::SessionContext;
+use crate::quota::QuotaStatus;
+use crate::storage::{ApiMetrics, ToolMetrics, get_tool_display_name};
+
+/// Thresholds that trigger the alert banner
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AlertThresholds {
+    /// Tool error rate (percent) at or above which a tool is flagged
+    pub tool_error_rate_pct: f64,
+    /// Minimum calls in the window before a tool's error rate is considered
+    pub min_tool_calls: u64,
+    /// API errors within the window at or above which a model is flagged
+    pub api_errors: u64,
+    /// Look-back window in minutes
+    pub window_mins: i64,
+}
+
+impl Default for AlertThresholds {
+    fn default() -> Self {
+        Self {
+            tool_error_rate_pct: 25.0,
+            min_tool_calls: 4,
+            api_errors: 3,
+            window_mins: 10,
+        }
+    }
+}
+
+/// A condition currently over its threshold
+#[derive(Debug, Clone, PartialEq)]
+pub enum Alert {
+    ToolErrorRate {
+        tool_name: String,
+        errors: u64,
+        calls: u64,
+    },
+    ApiErrors {
+        /// None when the errors carry no model attribute
+        model: Option<String>,
+        errors: u64,
+    },
+    Anomaly(Anomaly),
+    /// A session consistently near its model's context window
+    ContextCeiling(SessionContext),
+    /// A usage window at its warning level or above
+    Quota(QuotaStatus),
+}
+
+impl Alert {
+    pub fn message(&self) -> String {
+        match self {
+            Alert::ToolErrorRate {
+                tool_name,
+                errors,
+                calls,
+            } => format!(
+                "{}: {}/{} calls failed ({:.0}%)",
+                get_tool_display_name(tool_name),
+                errors,
+                calls,
+                *errors as f64 / *calls as f64 * 100.0
+            ),
+            Alert::ApiErrors {
+                model: Some(model),
+                errors,
+            } => format!("{}: {} API errors", model, errors),
+            Alert::ApiErrors {
+                model: None,
+                errors,
+            } => format!("{} API errors", errors),
+            Alert::Anomaly(anomaly) => anomaly.message(),
+            Alert::ContextCeiling(session) => format!(
+                "Session {}: {}/{} requests near the context limit (avg {:.0}%)",
+                session.session_id.chars().take(8).collect::<String>(),
+                session.utilization.near_ceiling,
+                session.utilization.requests,
+                session.utilization.avg_pct
+            ),
+            Alert::Quota(status) => format!("Usage window {}", status.describe()),
+        }
+    }
+}
+
+/// Evaluate thresholds against metrics from the look-back window.
+/// Tools are reported worst error rate first, followed by API errors per model.
+pub fn evaluate(
+    tools: &[ToolMetrics],
+    api: &ApiMetrics,
+    thresholds: &AlertThresholds,
+) -> Vec<Alert> {
+    let mut tool_alerts: Vec<(f64, Alert)> = tools
+        .iter()
+        .filter(|t| t.call_count >= thresholds.min_tool_calls.max(1))
+        .filter_map(|t| {
+            let rate = t.error_count as f64 / t.call_count as f64 * 100.0;
+            (t.error_count > 0 && rate >= thresholds.tool_error_rate_pct).then(|| {
+                (
+                    rate,
+                    Alert::ToolErrorRate {
+                        tool_name: t.tool_name.clone(),
+                        errors: t.error_count,
+                        calls: t.call_count,
+                    },
+                )
+            })
+        })
+        .collect();
+    tool_alerts.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+
+    let mut alerts: Vec<Alert> = tool_alerts.into_iter().map(|(_, alert)| alert).collect();
+
+    let threshold = thresholds.api_errors.max(1);
+    let mut models: Vec<(&String, &u64)> = api
+        .errors_by_model
+        .iter()
+        .filter(|(_, errors)| **errors >= threshold)
+        .collect();
+    models.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+    alerts.extend(models.into_iter().map(|(model, errors)| Alert::ApiErrors {
+        model: Some(model.clone()),
+        errors: *errors,
+    }));
+
+    // Errors without a model attribute still count toward the total
+    let unattributed = api
+        .total_errors
+        .saturating_sub(api.errors_by_model.values().sum());
+    if unattributed >= threshold {
+        alerts.push(Alert::ApiErrors {
+            model: None,
+            errors: unattributed,
+        });
+    }
+
+    alerts
+}
@@ -1,8 +1,23 @@
 use anyhow::Result;
-use chrono::{DateTime, Utc};
+use chrono::{
+    DateTime, Datelike, Local, Months, NaiveDate, NaiveDateTime, NaiveTime, Timelike, Utc,
+};
 
-use crate::providers::PROVIDER_REGISTRY;
-use crate::storage::{ApiMetrics, SessionMetrics, StorageHandle, TokenMetrics, ToolMetrics};
+use super::alerts::{self, Alert, AlertThresholds};
+use crate::budget::{self, Budget, BudgetLevel, BudgetStatus};
+use crate::context::{self, ContextUsage};
+use crate::efficiency::cache::CacheEfficiency;
+use crate::export::{self, ExportFormat, ToolExportRow};
+use crate::prompt_cost::{self, PromptCost};
+use crate::providers::{DEFAULT_OTLP_ENDPOINT, PROVIDER_REGISTRY};
+use crate::quota::{self, Quota, QuotaStatus};
+use crate::self_metrics::{ReceiverSnapshot, SELF_METRICS, StorageSnapshot};
+use crate::storage::{
+    ApiMetrics, DataVersion, ErrorBreakdown, ErrorCategoryCount, FileActivity, HourlyActivity,
+    InFlightTool, LogRecord, McpServerHealth, PromptEntry, ProviderComparison, QueryResult,
+    Session, SessionMetrics, SessionProductivity, SessionTimeline, StorageHandle, TokenMetrics,
+    ToolMetrics, ToolSequence, Turn,
+};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum TimeFilter {
@@ -11,15 +26,33 @@ pub enum TimeFilter {
     Last7Days,
     #[default]
     AllTime,
+    /// Rolling window ending now (e.g., "last 3h")
+    Last(chrono::Duration),
+    /// Fixed window; either bound may be open (e.g., "since 09:00")
+    Range {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    },
 }
 
 impl TimeFilter {
-    pub fn label(&self) -> &'static str {
+    pub fn label(&self) -> String {
         match self {
-            TimeFilter::LastHour => "Last 1h",
-            TimeFilter::Last24Hours => "Last 24h",
-            TimeFilter::Last7Days => "Last 7d",
-            TimeFilter::AllTime => "All-time",
+            TimeFilter::LastHour => "Last 1h".to_string(),
+            TimeFilter::Last24Hours => "Last 24h".to_string(),
+            TimeFilter::Last7Days => "Last 7d".to_string(),
+            TimeFilter::AllTime => "All-time".to_string(),
+            TimeFilter::Last(duration) => format!("Last {}", format_span(*duration)),
+            TimeFilter::Range { since, until } => match (since, until) {
+                (Some(since), None) => format!("Since {}", format_bound(*since, false)),
+                (None, Some(until)) => format!("Until {}", format_bound(*until, true)),
+                (Some(since), Some(until)) => format!(
+                    "{}..{}",
+                    format_bound(*since, false),
+                    format_bound(*until, true)
+                ),
+                (None, None) => "All-time".to_string(),
+            },
         }
     }
 
@@ -29,10 +62,218 @@ impl TimeFilter {
             TimeFilter::Last24Hours => Some(Utc::now() - chrono::Duration::hours(24)),
             TimeFilter::Last7Days => Some(Utc::now() - chrono::Duration::days(7)),
             TimeFilter::AllTime => None,
+            TimeFilter::Last(duration) => Some(Utc::now() - *duration),
+            TimeFilter::Range { since, .. } => *since,
+        }
+    }
+
+    /// The window of equal length immediately before this one, as `(since, until)`.
+    /// None when the window has no lower bound.
+    pub fn previous_window(&self) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        let since = self.since()?;
+        let until = self.until().unwrap_or_else(Utc::now);
+        Some((since - (until - since), since))
+    }
+
+    /// Exclusive upper bound of the window (None = up to now)
+    pub fn until(&self) -> Option<DateTime<Utc>> {
+        match self {
+            TimeFilter::Range { until, .. } => *until,
+            _ => None,
+        }
+    }
+
+    /// Parse a free-form time range typed by the user.
+    ///
+    /// Supported forms (interpreted in local time):
+    /// - `last 3h`, `90m`, `2d`, `1w` - rolling window ending now
+    /// - `since 09:00`, `since 2025-01-10`, `since 2025-01-10 09:00`
+    /// - `2025-01-10..2025-01-12` - inclusive dates; either side may be omitted
+    /// - `all` - no bounds
+    pub fn parse(input: &str, now: DateTime<Local>) -> Result<Self> {
+        let input = input.trim().to_lowercase();
+        if input.is_empty() {
+            anyhow::bail!("Empty time range");
+        }
+        if input == "all" || input == "all-time" {
+            return Ok(TimeFilter::AllTime);
         }
+
+        if let Some((start, end)) = input.split_once("..") {
+            let since = match start.trim() {
+                "" => None,
+                s => Some(parse_bound(s, now, false)?),
+            };
+            let until = match end.trim() {
+                "" => None,
+                s => Some(parse_bound(s, now, true)?),
+            };
+            if let (Some(since), Some(until)) = (since, until)
+                && since >= until
+            {
+                anyhow::bail!("Range start must be before its end");
+            }
+            return Ok(TimeFilter::Range { since, until });
+        }
+
+        if let Some(rest) = input.strip_prefix("since ") {
+            let since = parse_bound(rest.trim(), now, false)?;
+            return Ok(TimeFilter::Range {
+                since: Some(since),
+                until: None,
+            });
+        }
+
+        let span = input.strip_prefix("last ").unwrap_or(&input).trim();
+        parse_span(span)
+            .map(TimeFilter::Last)
+            .ok_or_else(|| anyhow::anyhow!("Unrecognized time range: {}", input))
+    }
+}
+
+/// Parse a span like "3h", "90m", "2d", "1w" (a bare "h"/"d" means one unit)
+fn parse_span(span: &str) -> Option<chrono::Duration> {
+    let span = span.replace(' ', "");
+    let split = span.find(|c: char| !c.is_ascii_digit())?;
+    let (num, unit) = span.split_at(split);
+    let n: i64 = if num.is_empty() { 1 } else { num.parse().ok()? };
+    if n <= 0 {
+        return None;
+    }
+    match unit {
+        "m" | "min" | "mins" | "minute" | "minutes" => Some(chrono::Duration::minutes(n)),
+        "h" | "hr" | "hrs" | "hour" | "hours" => Some(chrono::Duration::hours(n)),
+        "d" | "day" | "days" => Some(chrono::Duration::days(n)),
+        "w" | "wk" | "week" | "weeks" => Some(chrono::Duration::weeks(n)),
+        _ => None,
     }
 }
 
+/// Parse a range bound: "HH:MM" (today), "YYYY-MM-DD" or "YYYY-MM-DD HH:MM".
+/// A bare date used as an upper bound is inclusive, i.e. it ends at the next midnight.
+fn parse_bound(s: &str, now: DateTime<Local>, is_end: bool) -> Result<DateTime<Utc>> {
+    let naive = if let Ok(time) = NaiveTime::parse_from_str(s, "%H:%M") {
+        now.date_naive().and_time(time)
+    } else if let Ok(dt) = NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M") {
+        dt
+    } else if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        let date = if is_end {
+            date.succ_opt()
+                .ok_or_else(|| anyhow::anyhow!("Date out of range: {}", s))?
+        } else {
+            date
+        };
+        date.and_time(NaiveTime::MIN)
+    } else {
+        anyhow::bail!("Unrecognized time: {}", s);
+    };
+
+    naive
+        .and_local_timezone(Local)
+        .earliest()
+        .map(|dt| dt.with_timezone(&Utc))
+        .ok_or_else(|| anyhow::anyhow!("Invalid local time: {}", s))
+}
+
+/// Format a rolling span compactly (e.g., "3h", "90m", "2d")
+fn format_span(span: chrono::Duration) -> String {
+    let mins = span.num_minutes();
+    if mins % (60 * 24) == 0 {
+        format!("{}d", mins / (60 * 24))
+    } else if mins % 60 == 0 {
+        format!("{}h", mins / 60)
+    } else {
+        format!("{}m", mins)
+    }
+}
+
+/// Format a range bound in local time. Midnight bounds are shown as dates; an
+/// exclusive midnight end bound is shown as the (inclusive) previous day.
+fn format_bound(dt: DateTime<Utc>, is_end: bool) -> String {
+    let local = dt.with_timezone(&Local);
+    if local.time() == NaiveTime::MIN {
+        let date = if is_end {
+            local.date_naive().pred_opt().unwrap_or(local.date_naive())
+        } else {
+            local.date_naive()
+        };
+        date.format("%Y-%m-%d").to_string()
+    } else if local.date_naive() == Local::now().date_naive() {
+        local.format("%H:%M").to_string()
+    } else {
+        local.format("%Y-%m-%d %H:%M").to_string()
+    }
+}
+
+/// Window over which the recent cost burn rate is measured
+const BURN_RATE_WINDOW_HOURS: i64 = 1;
+
+/// Projected spend extrapolated from the recent burn rate
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CostProjection {
+    /// Spend per hour over the burn-rate window
+    pub burn_rate_per_hour: f64,
+    /// Projected total spend at local midnight
+    pub end_of_day_usd: f64,
+    /// Projected total spend at the end of the local calendar month
+    pub end_of_month_usd: f64,
+}
+
+impl CostProjection {
+    /// Extrapolate today's and this month's spend to the end of each period,
+    /// assuming the current hourly burn rate continues.
+    pub fn project(
+        now: DateTime<Local>,
+        today_usd: f64,
+        month_usd: f64,
+        burn_rate_per_hour: f64,
+    ) -> Self {
+        let hours_until = |end: Option<DateTime<Local>>| {
+            end.map(|end| (end - now).num_seconds().max(0) as f64 / 3600.0)
+                .unwrap_or(0.0)
+        };
+
+        let today = now.date_naive();
+        let end_of_day = today
+            .succ_opt()
+            .and_then(|d| d.and_hms_opt(0, 0, 0))
+            .and_then(|dt| dt.and_local_timezone(Local).earliest());
+        let end_of_month = today
+            .with_day(1)
+            .and_then(|d| d.checked_add_months(Months::new(1)))
+            .and_then(|d| d.and_hms_opt(0, 0, 0))
+            .and_then(|dt| dt.and_local_timezone(Local).earliest());
+
+        Self {
+            burn_rate_per_hour,
+            end_of_day_usd: today_usd + burn_rate_per_hour * hours_until(end_of_day),
+            end_of_month_usd: month_usd + burn_rate_per_hour * hours_until(end_of_month),
+        }
+    }
+}
+
+/// Start of the local calendar day containing `now`, in UTC
+pub(crate) fn local_day_start(now: DateTime<Local>) -> Option<DateTime<Utc>> {
+    now.date_naive()
+        .and_hms_opt(0, 0, 0)
+        .and_then(|dt| dt.and_local_timezone(Local).earliest())
+        .map(|dt| dt.with_timezone(&Utc))
+}
+
+/// Start of the local calendar month containing `now`, in UTC
+fn local_month_start(now: DateTime<Local>) -> Option<DateTime<Utc>> {
+    now.date_naive()
+        .with_day(1)
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .and_then(|dt| dt.and_local_timezone(Local).earliest())
+        .map(|dt| dt.with_timezone(&Utc))
+}
+
+/// Format a USD amount for display (e.g., "$4.57")
+pub fn format_usd(usd: f64) -> String {
+    format!("${:.2}", usd)
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SortColumn {
     Calls,
@@ -41,12 +282,240 @@ pub enum SortColumn {
     Name,
 }
 
+/// Window over which the receiver's events/sec rate is averaged
+const RECEIVER_RATE_WINDOW_SECS: i64 = 10;
+
+/// Telemetry setup state of one provider, shown on the onboarding screen
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProviderSetup {
+    pub id: &'static str,
+    pub name: &'static str,
+    /// None if the provider's settings can't be checked
+    pub configured: Option<bool>,
+    /// Whether setup can be run from the TUI (otherwise it is manual)
+    pub supports_setup: bool,
+    pub settings_path: Option<std::path::PathBuf>,
+}
+
+/// OTLP receiver health as shown in the footer
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReceiverStatus {
+    pub snapshot: ReceiverSnapshot,
+    pub events_per_sec: f64,
+}
+
+/// Number of buckets in each tool's TREND sparkline
+const TREND_BUCKETS: usize = 20;
+/// Bucket size for the TREND sparkline (20 x 3 minutes = last hour)
+const TREND_BUCKET_SECS: i64 = 180;
+
+/// Start events older than this without a result are assumed lost, not running
+const IN_FLIGHT_MAX_MINS: i64 = 30;
+
+/// Most recent anomalies within the alert window shown in the banner
+const ANOMALY_ALERT_LIMIT: usize = 5;
+
+/// Longest time the window aggregations are reused while nothing new is stored; rolling
+/// windows and trend buckets still move with the clock
+const IDLE_REFRESH_SECS: i64 = 5;
+
+/// After this long without new data the dashboard counts as idle and refreshes less often
+const IDLE_AFTER_SECS: i64 = 60;
+
+/// How long a tool's row stays highlighted after new calls arrive
+const ROW_FLASH_MILLIS: i64 = 1200;
+
+/// Minimum change in success rate (percentage points) shown as a trend arrow
+const SUCCESS_TREND_MIN_DELTA: f64 = 5.0;
+
+/// Direction of a tool's success rate compared to the previous window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuccessTrend {
+    Improved,
+    Regressed,
+}
+
+/// Totals for the window before the current one, shown side by side in comparison mode
+#[derive(Debug, Clone, Default)]
+pub struct PeriodComparison {
+    pub tool_calls: u64,
+    pub tool_errors: u64,
+    pub cost_usd: f64,
+    /// Previous-window metrics per tool
+    pub tools: std::collections::HashMap<String, ToolMetrics>,
+}
+
+impl PeriodComparison {
+    /// Tool error rate (percent) in the previous window
+    pub fn error_rate(&self) -> f64 {
+        if self.tool_calls == 0 {
+            return 0.0;
+        }
+        self.tool_errors as f64 / self.tool_calls as f64 * 100.0
+    }
+}
+
+/// Main content shown between the metrics bar and the footer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum View {
+    #[default]
+    Dashboard,
+    Timeline,
+    Sessions,
+    Prompts,
+    Turns,
+    Workflows,
+    Files,
+    Cache,
+    Productivity,
+    Providers,
+    Errors,
+    Heatmap,
+    Events,
+    Sql,
+}
+
+/// Maximum rows returned by a SQL console query
+pub const SQL_ROW_LIMIT: usize = 500;
+
+/// Value shown in the activity heatmap
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HeatmapMetric {
+    #[default]
+    Calls,
+    Cost,
+}
+
+impl HeatmapMetric {
+    pub fn label(&self) -> &'static str {
+        match self {
+            HeatmapMetric::Calls => "Tool calls",
+            HeatmapMetric::Cost => "Cost",
+        }
+    }
+}
+
+/// Tool calls or cost per weekday and local hour for one calendar week
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActivityHeatmap {
+    pub metric: HeatmapMetric,
+    /// Monday of the week shown
+    pub week_start: NaiveDate,
+    /// `cells[weekday][hour]`, Monday first, local hours
+    pub cells: [[f64; 24]; 7],
+    pub total: f64,
+    /// Total for the week before, for comparison
+    pub previous_total: f64,
+}
+
+impl ActivityHeatmap {
+    /// Bucket hourly rollups (covering this week and the one before) into local weekday/hour cells
+    pub fn build(hours: &[HourlyActivity], week_start: NaiveDate, metric: HeatmapMetric) -> Self {
+        let previous_start = week_start - chrono::Duration::days(7);
+        let mut cells = [[0.0; 24]; 7];
+        let mut total = 0.0;
+        let mut previous_total = 0.0;
+
+        for activity in hours {
+            let value = match metric {
+                HeatmapMetric::Calls => activity.tool_calls as f64,
+                HeatmapMetric::Cost => activity.cost_usd,
+            };
+            let local = activity.hour.with_timezone(&Local);
+            let days = (local.date_naive() - week_start).num_days();
+            if (0..7).contains(&days) {
+                cells[days as usize][local.hour() as usize] += value;
+                total += value;
+            } else if (0..7).contains(&(local.date_naive() - previous_start).num_days()) {
+                previous_total += value;
+            }
+        }
+
+        Self {
+            metric,
+            week_start,
+            cells,
+            total,
+            previous_total,
+        }
+    }
+
+    /// Largest cell value (used to scale the shading)
+    pub fn max(&self) -> f64 {
+        self.cells.iter().flatten().copied().fold(0.0, f64::max)
+    }
+}
+
+/// Monday of the local week `weeks_back` weeks before the one containing `now`
+fn local_week_start(now: DateTime<Local>, weeks_back: u32) -> NaiveDate {
+    let today = now.date_naive();
+    today
+        - chrono::Duration::days(today.weekday().num_days_from_monday() as i64)
+        - chrono::Duration::weeks(weeks_back as i64)
+}
+
+/// Local midnight of `date`, in UTC
+fn local_date_start(date: NaiveDate) -> Option<DateTime<Utc>> {
+    date.and_time(NaiveTime::MIN)
+        .and_local_timezone(Local)
+        .earliest()
+        .map(|dt| dt.with_timezone(&Utc))
+}
+
+/// Number of sessions loaded for the session list
+pub const SESSION_LIST_LIMIT: usize = 100;
+
+/// Number of api_requests the sessions view's context utilization covers
+pub const CONTEXT_SAMPLE_LIMIT: usize = 20_000;
+
+/// Number of prompts loaded for the prompt history view
+pub const PROMPT_HISTORY_LIMIT: usize = 50;
+
+/// Number of conversation turns loaded for the turns view
+pub const TURN_HISTORY_LIMIT: usize = 100;
+
+/// Number of turns the turns view's prompt size analysis covers
+pub const PROMPT_COST_TURN_LIMIT: usize = 5_000;
+
+/// Consecutive tool calls per sequence in the workflows view
+pub const WORKFLOW_LENGTH: usize = 3;
+
+/// Number of tool sequences loaded for the workflows view
+pub const WORKFLOW_LIMIT: usize = 50;
+
+/// Number of files and of directories loaded for the files view
+pub const FILE_ACTIVITY_LIMIT: usize = 50;
+
+/// Number of sessions loaded for the productivity view
+pub const PRODUCTIVITY_SESSION_LIMIT: usize = 500;
+
+/// Number of category and tool or model rows loaded for the errors view
+pub const ERROR_BREAKDOWN_LIMIT: usize = 50;
+
+/// Terminals narrower than this use the compact layout automatically
+pub const COMPACT_MAX_WIDTH: u16 = 70;
+
+/// How long a toast notification stays visible
+const TOAST_DURATION_SECS: i64 = 5;
+
+/// Short-lived notification shown over the footer
+#[derive(Debug, Clone, PartialEq)]
+pub struct Toast {
+    pub message: String,
+    pub is_error: bool,
+    pub shown_at: DateTime<Utc>,
+}
+
 pub struct App {
     storage: StorageHandle,
     pub tool_metrics: Vec<ToolMetrics>,
     pub token_metrics: TokenMetrics,
+    /// Prompt-cache use per model and per day in the window
+    pub cache_efficiency: CacheEfficiency,
     pub session_metrics: SessionMetrics,
     pub api_metrics: ApiMetrics,
+    /// End-of-day / end-of-month spend projection (independent of time filter)
+    pub cost_projection: CostProjection,
     pub selected_index: usize,
     pub sort_by: SortColumn,
     pub sort_ascending: bool,
@@ -58,16 +527,137 @@ pub struct App {
     pub detected_agents: Vec<String>,
     /// Currently selected agent index (for filtering display)
     pub selected_agent_index: usize,
+    /// Text being typed into the custom time range input (None = input closed)
+    pub time_input: Option<String>,
+    /// Parse error for the last submitted time range input
+    pub time_input_error: Option<String>,
+    /// Last notification (e.g., export result); hidden once expired
+    pub toast: Option<Toast>,
+    /// OTLP receiver health (updated even while paused)
+    pub receiver_status: ReceiverStatus,
+    /// Storage actor health; while it is down the last data stays on screen
+    pub storage_status: StorageSnapshot,
+    /// The database is still being opened; nothing has been loaded yet
+    pub loading: bool,
+    /// Something shown changed since the last frame was drawn
+    pub needs_redraw: bool,
+    /// Last data version seen, and when it was first seen
+    data_version: Option<DataVersion>,
+    last_data_change: DateTime<Utc>,
+    /// Showing a database another agenttop writes; this process does not receive
+    pub view_only: bool,
+    /// Recent (time, events_received) samples used for the events/sec rate
+    receiver_samples: std::collections::VecDeque<(DateTime<Utc>, u64)>,
+    /// Per-tool call counts over the last hour, oldest bucket first
+    pub tool_trends: std::collections::HashMap<String, Vec<u64>>,
+    /// Tools that have started but not yet reported a result
+    pub in_flight: Vec<InFlightTool>,
+    /// Per-server MCP health, busiest server first
+    pub mcp_servers: Vec<McpServerHealth>,
+    /// Show the onboarding screen: nothing has been stored or received yet
+    pub onboarding: bool,
+    /// Set once data has arrived; onboarding is not shown again
+    onboarding_finished: bool,
+    /// Provider setup status (loaded only while onboarding)
+    pub provider_setup: Vec<ProviderSetup>,
+    /// Provider selected on the onboarding screen
+    pub onboarding_index: usize,
+    /// Show deltas against the previous window of equal length
+    pub compare_mode: bool,
+    /// Show the list-price estimate of the token usage instead of the reported cost
+    pub estimated_cost: bool,
+    /// Previous-window totals (loaded only in comparison mode with a bounded filter)
+    pub comparison: Option<PeriodComparison>,
+    /// Data version, filter and comparison mode the aggregations were last loaded for
+    refreshed_for: Option<(DataVersion, TimeFilter, bool)>,
+    /// Call counts from the previous refresh and the filter they were loaded with
+    call_count_baseline: Option<(TimeFilter, std::collections::HashMap<String, u64>)>,
+    /// When each tool last received new calls (for the row flash)
+    flashed_at: std::collections::HashMap<String, DateTime<Utc>>,
+    /// Success rate (%) per tool in the previous window of equal length
+    pub previous_success_rates: std::collections::HashMap<String, f64>,
+    /// Main content view
+    pub view: View,
+    /// Timeline of the current session (loaded only while the timeline view is shown)
+    pub session_timeline: SessionTimeline,
+    /// Recent sessions, newest first (loaded only while the session list is shown)
+    pub sessions: Vec<Session>,
+    /// Context-window utilization over up to [`CONTEXT_SAMPLE_LIMIT`] requests (loaded
+    /// with the sessions)
+    pub context_usage: ContextUsage,
+    /// Recent user prompts, newest first (loaded only while the prompt view is shown)
+    pub prompts: Vec<PromptEntry>,
+    /// Recent conversation turns, newest first (loaded only while the turns view is shown)
+    pub turns: Vec<Turn>,
+    /// Turn cost by prompt size over up to [`PROMPT_COST_TURN_LIMIT`] turns (loaded with
+    /// the turns)
+    pub prompt_cost: PromptCost,
+    /// Most frequent tool-call sequences (loaded only while the workflows view is shown)
+    pub workflows: Vec<ToolSequence>,
+    /// Most edited files and directories (loaded only while the files view is shown)
+    pub files: Vec<FileActivity>,
+    pub directories: Vec<FileActivity>,
+    /// Sessions with code metrics, most recent first (loaded only while the productivity
+    /// view is shown)
+    pub productivity: Vec<SessionProductivity>,
+    /// Totals per provider, most spend first (loaded only while the providers view is
+    /// shown)
+    pub providers: Vec<ProviderComparison>,
+    /// Errors per category, most frequent first (loaded only while the errors view is
+    /// shown)
+    pub error_categories: Vec<ErrorCategoryCount>,
+    /// Errors per category and tool or model, most frequent first
+    pub error_breakdown: Vec<ErrorBreakdown>,
+    /// Value shown in the heatmap view
+    pub heatmap_metric: HeatmapMetric,
+    /// Weeks before the current one shown in the heatmap view
+    pub heatmap_weeks_back: u32,
+    /// Activity heatmap (loaded only while the heatmap view is shown)
+    pub heatmap: Option<ActivityHeatmap>,
+    /// Buffered log events for the event stream view, oldest first
+    pub events: std::collections::VecDeque<LogRecord>,
+    /// When frozen (not following), the id of the last visible event; new events
+    /// keep buffering below it
+    pub event_anchor: Option<i64>,
+    /// Tools pinned to the top of their table (persisted in the config file)
+    pub pinned_tools: Vec<String>,
+    /// Latency targets (max average duration in ms) per tool name
+    pub latency_slos_ms: std::collections::HashMap<String, u64>,
+    /// Spend budgets from the config file
+    pub budgets: Vec<Budget>,
+    /// Spend against each budget, refreshed with the cost projection
+    pub budget_statuses: Vec<BudgetStatus>,
+    /// Usage-window quotas from the config file
+    pub quotas: Vec<Quota>,
+    /// Current window of each quota, refreshed with the cost projection
+    pub quota_statuses: Vec<QuotaStatus>,
+    /// SQL console query being edited (None while the editor is closed)
+    pub sql_input: Option<String>,
+    /// Last query submitted in the SQL console
+    pub sql_query: String,
+    /// Result of the last query, or its error message
+    pub sql_result: Option<std::result::Result<QueryResult, String>>,
+    /// First result row shown in the SQL console
+    pub sql_scroll: usize,
+    /// Compact layout forced on with the 'c' key (otherwise chosen by width)
+    pub force_compact: bool,
+    /// Thresholds for the error-rate alert banner
+    pub alert_thresholds: AlertThresholds,
+    /// Conditions currently over their alert threshold
+    pub alerts: Vec<Alert>,
 }
 
 impl App {
     pub fn new(storage: StorageHandle) -> Self {
         Self {
+            loading: !storage.is_ready(),
             storage,
             tool_metrics: Vec::new(),
             token_metrics: TokenMetrics::default(),
+            cache_efficiency: CacheEfficiency::default(),
             session_metrics: SessionMetrics::default(),
             api_metrics: ApiMetrics::default(),
+            cost_projection: CostProjection::default(),
             selected_index: 0,
             sort_by: SortColumn::Calls,
             sort_ascending: false,
@@ -77,18 +667,201 @@ impl App {
             time_filter: TimeFilter::default(),
             detected_agents: Vec::new(),
             selected_agent_index: 0,
+            time_input: None,
+            time_input_error: None,
+            toast: None,
+            receiver_status: ReceiverStatus::default(),
+            storage_status: StorageSnapshot::default(),
+            needs_redraw: true,
+            data_version: None,
+            last_data_change: Utc::now(),
+            view_only: false,
+            receiver_samples: std::collections::VecDeque::new(),
+            tool_trends: std::collections::HashMap::new(),
+            in_flight: Vec::new(),
+            mcp_servers: Vec::new(),
+            onboarding: false,
+            onboarding_finished: false,
+            provider_setup: Vec::new(),
+            onboarding_index: 0,
+            previous_success_rates: std::collections::HashMap::new(),
+            compare_mode: false,
+            estimated_cost: false,
+            comparison: None,
+            refreshed_for: None,
+            call_count_baseline: None,
+            flashed_at: std::collections::HashMap::new(),
+            view: View::default(),
+            session_timeline: SessionTimeline::default(),
+            sessions: Vec::new(),
+            prompts: Vec::new(),
+            turns: Vec::new(),
+            prompt_cost: PromptCost::default(),
+            context_usage: ContextUsage::default(),
+            workflows: Vec::new(),
+            files: Vec::new(),
+            directories: Vec::new(),
+            productivity: Vec::new(),
+            providers: Vec::new(),
+            error_categories: Vec::new(),
+            error_breakdown: Vec::new(),
+            heatmap_metric: HeatmapMetric::default(),
+            heatmap_weeks_back: 0,
+            heatmap: None,
+            events: std::collections::VecDeque::new(),
+            event_anchor: None,
+            pinned_tools: Vec::new(),
+            latency_slos_ms: std::collections::HashMap::new(),
+            budgets: Vec::new(),
+            budget_statuses: Vec::new(),
+            quotas: Vec::new(),
+            quota_statuses: Vec::new(),
+            sql_input: None,
+            sql_query: String::new(),
+            sql_result: None,
+            sql_scroll: 0,
+            force_compact: false,
+            alert_thresholds: AlertThresholds::default(),
+            alerts: Vec::new(),
         }
     }
 
     pub fn refresh(&mut self) -> Result<()> {
-        if self.paused {
+        // The footer shows these (but not the storage latencies, which every query moves)
+        let shown = |app: &Self| {
+            (
+                app.receiver_status.clone(),
+                app.storage_status.error.clone(),
+                app.loading,
+            )
+        };
+        let before = shown(self);
+        self.update_receiver_status(SELF_METRICS.receiver.snapshot(), Utc::now());
+        self.storage_status = SELF_METRICS.storage.snapshot();
+        self.loading = !self.storage.is_ready();
+        if shown(self) != before {
+            self.needs_redraw = true;
+        }
+
+        if self.paused || self.loading || self.storage_status.error.is_some() {
             return Ok(());
         }
 
-        self.tool_metrics = self.storage.get_tool_metrics(self.time_filter.since())?;
-        self.token_metrics = self.storage.get_token_metrics(self.time_filter.since())?;
-        self.session_metrics = self.storage.get_session_metrics(self.time_filter.since())?;
-        self.api_metrics = self.storage.get_api_metrics(self.time_filter.since())?;
+        // The aggregations are the expensive part; skip them while nothing changed
+        let version = self.storage.get_data_version().ok();
+        if version.is_some() && version != self.data_version {
+            self.data_version = version;
+            self.last_data_change = Utc::now();
+        }
+        let key = version.map(|version| (version, self.time_filter, self.compare_mode));
+        let idle_for = Utc::now() - self.last_refresh;
+        if key.is_none()
+            || key != self.refreshed_for
+            || idle_for >= chrono::Duration::seconds(IDLE_REFRESH_SECS)
+        {
+            self.refresh_aggregates()?;
+            self.refreshed_for = key;
+            self.needs_redraw = true;
+        }
+
+        let (since, until) = (self.time_filter.since(), self.time_filter.until());
+        match self.view {
+            // The cache view shows the aggregates
+            View::Dashboard | View::Cache | View::Sql => return Ok(()),
+            View::Timeline => self.session_timeline = self.storage.get_session_timeline()?,
+            View::Sessions => {
+                self.sessions = self
+                    .storage
+                    .get_sessions(since, until, SESSION_LIST_LIMIT)?;
+                self.context_usage = context::analyze(&self.storage.get_context_samples(
+                    since,
+                    until,
+                    CONTEXT_SAMPLE_LIMIT,
+                )?);
+            }
+            View::Prompts => {
+                self.prompts =
+                    self.storage
+                        .get_recent_prompts(since, until, PROMPT_HISTORY_LIMIT)?
+            }
+            View::Turns => {
+                let mut turns = self
+                    .storage
+                    .get_turns(since, until, PROMPT_COST_TURN_LIMIT)?;
+                self.prompt_cost = prompt_cost::analyze(&turns);
+                turns.truncate(TURN_HISTORY_LIMIT);
+                self.turns = turns;
+            }
+            View::Workflows => {
+                self.workflows = self.storage.get_tool_sequences(
+                    since,
+                    until,
+                    WORKFLOW_LENGTH,
+                    WORKFLOW_LIMIT,
+                )?
+            }
+            View::Files => {
+                self.files =
+                    self.storage
+                        .get_file_activity(since, until, false, FILE_ACTIVITY_LIMIT)?;
+                self.directories =
+                    self.storage
+                        .get_file_activity(since, until, true, FILE_ACTIVITY_LIMIT)?;
+            }
+            View::Productivity => {
+                self.productivity = self.storage.get_session_productivity(
+                    since,
+                    until,
+                    PRODUCTIVITY_SESSION_LIMIT,
+                )?
+            }
+            View::Providers => {
+                self.providers = self.storage.get_provider_comparison(since, until)?
+            }
+            View::Errors => {
+                self.error_categories = self.storage.get_error_categories(since, until)?;
+                self.error_breakdown =
+                    self.storage
+                        .get_error_breakdown(since, until, ERROR_BREAKDOWN_LIMIT)?;
+            }
+            View::Heatmap => self.refresh_heatmap()?,
+            View::Events => self.refresh_events()?,
+        }
+        self.needs_redraw = true;
+        Ok(())
+    }
+
+    /// No new data has been stored for a while, so refreshing can slow down
+    pub fn is_idle(&self, now: DateTime<Utc>) -> bool {
+        now - self.last_data_change >= chrono::Duration::seconds(IDLE_AFTER_SECS)
+    }
+
+    /// Force the next refresh to reload everything, e.g. after a key press changed
+    /// what is shown
+    pub fn invalidate(&mut self) {
+        self.refreshed_for = None;
+        self.storage.supersede();
+    }
+
+    /// Reload the metrics for the time filter and everything derived from them
+    fn refresh_aggregates(&mut self) -> Result<()> {
+        let (since, until) = (self.time_filter.since(), self.time_filter.until());
+        self.tool_metrics = self.storage.get_tool_metrics(since, until)?;
+        self.token_metrics = self.storage.get_token_metrics(since, until)?;
+        self.cache_efficiency =
+            CacheEfficiency::from_usage(&self.storage.get_cache_usage(since, until)?);
+        self.session_metrics = self.storage.get_session_metrics(since, until)?;
+        self.api_metrics = self.storage.get_api_metrics(since, until)?;
+        self.mcp_servers = self.storage.get_mcp_server_health(since, until)?;
+        self.refresh_onboarding()?;
+        self.refresh_cost_projection()?;
+        self.refresh_alerts()?;
+        self.refresh_tool_trends()?;
+        self.refresh_previous_window()?;
+        self.in_flight = self
+            .storage
+            .get_in_flight_tools(Utc::now() - chrono::Duration::minutes(IN_FLIGHT_MAX_MINS))?;
+        self.update_row_flashes(Utc::now());
         self.last_refresh = Utc::now();
 
         // Detect agents from tool usage and model names
@@ -126,6 +899,193 @@ impl App {
         Ok(())
     }
 
+    /// Reload per-tool call series for the TREND column. Buckets are aligned to
+    /// bucket boundaries so the sparkline shifts one bucket at a time.
+    fn refresh_tool_trends(&mut self) -> Result<()> {
+        let now = Utc::now().timestamp();
+        let end = now - now.rem_euclid(TREND_BUCKET_SECS) + TREND_BUCKET_SECS;
+        let start = end - TREND_BUCKET_SECS * TREND_BUCKETS as i64;
+        let since = DateTime::from_timestamp(start, 0).unwrap_or_else(Utc::now);
+        self.tool_trends =
+            self.storage
+                .get_tool_call_series(since, TREND_BUCKET_SECS, TREND_BUCKETS)?;
+        Ok(())
+    }
+
+    /// Load per-tool success rates, and the comparison totals when comparison mode is on,
+    /// for the previous window (cleared for unbounded filters)
+    fn refresh_previous_window(&mut self) -> Result<()> {
+        let Some((since, until)) = self.time_filter.previous_window() else {
+            self.previous_success_rates.clear();
+            self.comparison = None;
+            return Ok(());
+        };
+
+        let tools = self.storage.get_tool_metrics(Some(since), Some(until))?;
+        self.previous_success_rates = tools
+            .iter()
+            .filter(|t| t.call_count > 0)
+            .map(|t| {
+                let rate = t.success_count as f64 / t.call_count as f64 * 100.0;
+                (t.tool_name.clone(), rate)
+            })
+            .collect();
+
+        self.comparison = if self.compare_mode {
+            Some(PeriodComparison {
+                tool_calls: tools.iter().map(|t| t.call_count).sum(),
+                tool_errors: tools.iter().map(|t| t.error_count).sum(),
+                cost_usd: self.storage.get_total_cost(Some(since), Some(until))?,
+                tools: tools
+                    .into_iter()
+                    .map(|t| (t.tool_name.clone(), t))
+                    .collect(),
+            })
+        } else {
+            None
+        };
+        Ok(())
+    }
+
+    pub fn toggle_compare_mode(&mut self) {
+        self.compare_mode = !self.compare_mode;
+        if !self.compare_mode {
+            self.comparison = None;
+        }
+    }
+
+    pub fn toggle_cost_estimate(&mut self) {
+        self.estimated_cost = !self.estimated_cost;
+    }
+
+    /// Cost shown in the metrics bar: reported, or estimated from token usage
+    pub fn shown_cost(&self) -> f64 {
+        if self.estimated_cost {
+            self.token_metrics.estimated_cost_usd
+        } else {
+            self.token_metrics.total_cost_usd
+        }
+    }
+
+    /// Tool error rate (percent) in the current window
+    pub fn tool_error_rate(&self) -> f64 {
+        let calls = self.total_tool_calls();
+        if calls == 0 {
+            return 0.0;
+        }
+        let errors: u64 = self.tool_metrics.iter().map(|t| t.error_count).sum();
+        errors as f64 / calls as f64 * 100.0
+    }
+
+    /// Flash tools whose call count grew since the previous refresh. Counts loaded
+    /// under a different time filter are not compared, so switching filters is quiet.
+    pub fn update_row_flashes(&mut self, now: DateTime<Utc>) {
+        let counts: std::collections::HashMap<String, u64> = self
+            .tool_metrics
+            .iter()
+            .map(|t| (t.tool_name.clone(), t.call_count))
+            .collect();
+
+        if let Some((filter, previous)) = &self.call_count_baseline
+            && *filter == self.time_filter
+        {
+            for (tool_name, count) in &counts {
+                if *count > previous.get(tool_name).copied().unwrap_or(0) {
+                    self.flashed_at.insert(tool_name.clone(), now);
+                }
+            }
+        }
+
+        self.flashed_at
+            .retain(|_, at| now - *at < chrono::Duration::milliseconds(ROW_FLASH_MILLIS));
+        self.call_count_baseline = Some((self.time_filter, counts));
+    }
+
+    /// Whether the tool's row is highlighted for recently arrived calls
+    pub fn is_flashing(&self, tool_name: &str, now: DateTime<Utc>) -> bool {
+        self.flashed_at
+            .get(tool_name)
+            .is_some_and(|at| now - *at < chrono::Duration::milliseconds(ROW_FLASH_MILLIS))
+    }
+
+    /// Whether the tool's success rate moved noticeably since the previous window
+    pub fn success_trend(&self, tool: &ToolMetrics) -> Option<SuccessTrend> {
+        let previous = *self.previous_success_rates.get(&tool.tool_name)?;
+        if tool.call_count == 0 {
+            return None;
+        }
+        let current = tool.success_count as f64 / tool.call_count as f64 * 100.0;
+        if current - previous >= SUCCESS_TREND_MIN_DELTA {
+            Some(SuccessTrend::Improved)
+        } else if previous - current >= SUCCESS_TREND_MIN_DELTA {
+            Some(SuccessTrend::Regressed)
+        } else {
+            None
+        }
+    }
+
+    /// When the tool started, if it is currently executing
+    pub fn in_flight_since(&self, tool_name: &str) -> Option<DateTime<Utc>> {
+        self.in_flight
+            .iter()
+            .find(|t| t.tool_name == tool_name)
+            .map(|t| t.started_at)
+    }
+
+    /// Call counts for the tool's TREND sparkline, if it was called in the last hour
+    pub fn tool_trend(&self, tool_name: &str) -> Option<&[u64]> {
+        self.tool_trends.get(tool_name).map(Vec::as_slice)
+    }
+
+    /// Re-evaluate alert thresholds over the look-back window ending now
+    fn refresh_alerts(&mut self) -> Result<()> {
+        let since = Some(Utc::now() - chrono::Duration::minutes(self.alert_thresholds.window_mins));
+        let tools = self.storage.get_tool_metrics(since, None)?;
+        let api = self.storage.get_api_metrics(since, None)?;
+        self.alerts = alerts::evaluate(&tools, &api, &self.alert_thresholds);
+        self.alerts.extend(
+            self.storage
+                .get_anomalies(since, None, ANOMALY_ALERT_LIMIT)?
+                .into_iter()
+                .map(Alert::Anomaly),
+        );
+        let samples = self
+            .storage
+            .get_context_samples(since, None, CONTEXT_SAMPLE_LIMIT)?;
+        self.alerts.extend(
+            context::analyze(&samples)
+                .sessions_near_ceiling()
+                .cloned()
+                .map(Alert::ContextCeiling),
+        );
+        self.alerts.extend(
+            self.quota_statuses
+                .iter()
+                .filter(|status| status.level >= BudgetLevel::Warn)
+                .cloned()
+                .map(Alert::Quota),
+        );
+        Ok(())
+    }
+
+    /// Recompute the cost projection from today's, this month's and the last hour's spend
+    fn refresh_cost_projection(&mut self) -> Result<()> {
+        let now = Local::now();
+        let today_usd = self.storage.get_total_cost(local_day_start(now), None)?;
+        let month_usd = self.storage.get_total_cost(local_month_start(now), None)?;
+        let window_usd = self.storage.get_total_cost(
+            Some(Utc::now() - chrono::Duration::hours(BURN_RATE_WINDOW_HOURS)),
+            None,
+        )?;
+        let burn_rate_per_hour = window_usd / BURN_RATE_WINDOW_HOURS as f64;
+
+        self.cost_projection =
+            CostProjection::project(now, today_usd, month_usd, burn_rate_per_hour);
+        self.budget_statuses = budget::check(&self.storage, &self.budgets, now)?;
+        self.quota_statuses = quota::check(&self.storage, &self.quotas, Utc::now())?;
+        Ok(())
+    }
+
     fn sort_tools(&mut self) {
         let ascending = self.sort_ascending;
         // All sorts use tool_name as secondary key for stability
@@ -174,6 +1134,52 @@ impl App {
                 });
             }
         }
+        // Keep display order (built-in table above MCP table) so selected_index
+        // matches the row shown as selected; the stable sort keeps the order above
+        // Pinned tools go first within their table
+        self.tool_metrics
+            .sort_by_key(|t| (t.is_mcp(), !self.pinned_tools.contains(&t.tool_name)));
+    }
+
+    /// Whether a tool's average duration is within its latency SLO (None if it has no SLO)
+    pub fn meets_latency_slo(&self, tool: &ToolMetrics) -> Option<bool> {
+        self.latency_slos_ms
+            .get(&tool.tool_name)
+            .map(|target| tool.avg_duration_ms <= *target as f64)
+    }
+
+    /// (met, total) over tools that have an SLO and calls in the current window
+    pub fn latency_slo_summary(&self) -> Option<(usize, usize)> {
+        let results: Vec<bool> = self
+            .tool_metrics
+            .iter()
+            .filter_map(|t| self.meets_latency_slo(t))
+            .collect();
+        if results.is_empty() {
+            return None;
+        }
+        Some((results.iter().filter(|met| **met).count(), results.len()))
+    }
+
+    pub fn is_pinned(&self, tool_name: &str) -> bool {
+        self.pinned_tools.iter().any(|t| t == tool_name)
+    }
+
+    /// Pin or unpin the selected tool, keeping it selected. Returns true if the pins changed.
+    pub fn toggle_pin_selected(&mut self) -> bool {
+        let Some(name) = self.selected_tool().map(|t| t.tool_name.clone()) else {
+            return false;
+        };
+        if self.is_pinned(&name) {
+            self.pinned_tools.retain(|t| *t != name);
+        } else {
+            self.pinned_tools.push(name.clone());
+        }
+        self.sort_tools();
+        if let Some(index) = self.tool_metrics.iter().position(|t| t.tool_name == name) {
+            self.selected_index = index;
+        }
+        true
     }
 
     pub fn toggle_sort(&mut self) {
@@ -190,6 +1196,135 @@ impl App {
         self.paused = !self.paused;
     }
 
+    pub fn cycle_view(&mut self) {
+        self.view = match self.view {
+            View::Dashboard => View::Timeline,
+            View::Timeline => View::Sessions,
+            View::Sessions => View::Prompts,
+            View::Prompts => View::Turns,
+            View::Turns => View::Workflows,
+            View::Workflows => View::Files,
+            View::Files => View::Cache,
+            View::Cache => View::Productivity,
+            View::Productivity => View::Providers,
+            View::Providers => View::Errors,
+            View::Errors => View::Heatmap,
+            View::Heatmap => View::Events,
+            View::Events => View::Sql,
+            View::Sql => View::Dashboard,
+        };
+    }
+
+    /// Load the selected week and the one before it from hourly rollups
+    fn refresh_heatmap(&mut self) -> Result<()> {
+        let week_start = local_week_start(Local::now(), self.heatmap_weeks_back);
+        let (Some(since), Some(until)) = (
+            local_date_start(week_start - chrono::Duration::days(7)),
+            local_date_start(week_start + chrono::Duration::days(7)),
+        ) else {
+            return Ok(());
+        };
+        let hours = self.storage.get_hourly_activity(since, until)?;
+        self.heatmap = Some(ActivityHeatmap::build(
+            &hours,
+            week_start,
+            self.heatmap_metric,
+        ));
+        Ok(())
+    }
+
+    /// Append log events that arrived since the last refresh, dropping the oldest
+    /// beyond `[cache] recent_events`
+    fn refresh_events(&mut self) -> Result<()> {
+        let limit = crate::cache::capacities().recent_events;
+        let after_id = self.events.back().map(|e| e.id);
+        let new_events = self.storage.get_log_events_after(after_id, limit)?;
+        self.events.extend(new_events);
+        let excess = self.events.len().saturating_sub(limit);
+        self.events.drain(..excess);
+        SELF_METRICS
+            .caches
+            .record_evictions("recent_events", excess as u64);
+        SELF_METRICS
+            .caches
+            .set_size("recent_events", self.events.len(), limit);
+        Ok(())
+    }
+
+    /// Whether the event stream sticks to the newest event
+    pub fn is_following_events(&self) -> bool {
+        self.event_anchor.is_none()
+    }
+
+    /// Toggle follow mode (like `less +F`); freezing keeps the current last event in view
+    pub fn toggle_event_follow(&mut self) {
+        self.event_anchor = match self.event_anchor {
+            Some(_) => None,
+            None => self.events.back().map(|e| e.id),
+        };
+    }
+
+    /// Index of the last visible event in the buffer
+    pub fn event_anchor_index(&self) -> Option<usize> {
+        let last = self.events.len().checked_sub(1)?;
+        match self.event_anchor {
+            None => Some(last),
+            // The anchor may have been trimmed from the buffer; fall back to the oldest event
+            Some(anchor) => Some(
+                self.events
+                    .iter()
+                    .rposition(|e| e.id <= anchor)
+                    .unwrap_or(0),
+            ),
+        }
+    }
+
+    /// Events buffered after the frozen position
+    pub fn unseen_events(&self) -> usize {
+        match (self.event_anchor, self.event_anchor_index()) {
+            (Some(_), Some(index)) => self.events.len() - index - 1,
+            _ => 0,
+        }
+    }
+
+    /// Scroll the event stream; scrolling up freezes it, reaching the newest event follows again
+    pub fn scroll_events(&mut self, delta: isize) {
+        let Some(index) = self.event_anchor_index() else {
+            return;
+        };
+        let last = self.events.len() - 1;
+        let target = index.saturating_add_signed(delta).min(last);
+        self.event_anchor = if target == last && delta > 0 {
+            None
+        } else {
+            Some(self.events[target].id)
+        };
+    }
+
+    pub fn toggle_heatmap_metric(&mut self) {
+        self.heatmap_metric = match self.heatmap_metric {
+            HeatmapMetric::Calls => HeatmapMetric::Cost,
+            HeatmapMetric::Cost => HeatmapMetric::Calls,
+        };
+    }
+
+    pub fn heatmap_previous_week(&mut self) {
+        self.heatmap_weeks_back += 1;
+    }
+
+    pub fn heatmap_next_week(&mut self) {
+        self.heatmap_weeks_back = self.heatmap_weeks_back.saturating_sub(1);
+    }
+
+    pub fn toggle_compact(&mut self) {
+        self.force_compact = !self.force_compact;
+    }
+
+    /// Whether to use the compact layout for a terminal of the given width
+    pub fn is_compact(&self, width: u16) -> bool {
+        self.force_compact || width < COMPACT_MAX_WIDTH
+    }
+
     pub fn toggle_detail(&mut self) {
         self.show_detail = !self.show_detail;
     }
@@ -214,6 +1349,56 @@ impl App {
         }
     }
 
+    /// Move the selection by `delta` rows without wrapping
+    pub fn move_selection(&mut self, delta: isize) {
+        if let Some(last) = self.tool_metrics.len().checked_sub(1) {
+            self.selected_index = self.selected_index.saturating_add_signed(delta).min(last);
+        }
+    }
+
+    /// Select a row by index, clamped to the last row
+    pub fn select_row(&mut self, index: usize) {
+        if let Some(last) = self.tool_metrics.len().checked_sub(1) {
+            self.selected_index = index.min(last);
+        }
+    }
+
+    /// Index of the first row of each non-empty section (built-in, then MCP)
+    fn section_starts(&self) -> Vec<usize> {
+        let builtin = self.tool_metrics.iter().filter(|t| t.is_builtin()).count();
+        [0, builtin]
+            .into_iter()
+            .filter(|start| *start < self.tool_metrics.len())
+            .collect::<std::collections::BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    /// Jump to the start of the next section, wrapping around
+    pub fn select_next_section(&mut self) {
+        let starts = self.section_starts();
+        if let Some(next) = starts
+            .iter()
+            .find(|s| **s > self.selected_index)
+            .or(starts.first())
+        {
+            self.selected_index = *next;
+        }
+    }
+
+    /// Jump to the start of the current section, or the previous one if already there
+    pub fn select_previous_section(&mut self) {
+        let starts = self.section_starts();
+        if let Some(previous) = starts
+            .iter()
+            .rev()
+            .find(|s| **s < self.selected_index)
+            .or(starts.last())
+        {
+            self.selected_index = *previous;
+        }
+    }
+
     pub fn reset_stats(&mut self) {
         // Clear old data and reset selection
         self.selected_index = 0;
@@ -236,8 +1421,247 @@ impl App {
             TimeFilter::LastHour => TimeFilter::Last24Hours,
             TimeFilter::Last24Hours => TimeFilter::Last7Days,
             TimeFilter::Last7Days => TimeFilter::AllTime,
-            TimeFilter::AllTime => TimeFilter::LastHour,
+            TimeFilter::AllTime | TimeFilter::Last(_) | TimeFilter::Range { .. } => {
+                TimeFilter::LastHour
+            }
+        };
+    }
+
+    /// Open the custom time range input
+    pub fn open_time_input(&mut self) {
+        self.time_input = Some(String::new());
+        self.time_input_error = None;
+    }
+
+    pub fn cancel_time_input(&mut self) {
+        self.time_input = None;
+        self.time_input_error = None;
+    }
+
+    pub fn time_input_push(&mut self, c: char) {
+        if let Some(input) = self.time_input.as_mut() {
+            input.push(c);
+            self.time_input_error = None;
+        }
+    }
+
+    pub fn time_input_backspace(&mut self) {
+        if let Some(input) = self.time_input.as_mut() {
+            input.pop();
+            self.time_input_error = None;
+        }
+    }
+
+    /// Apply the typed time range. On a parse error the input stays open with the error shown.
+    pub fn submit_time_input(&mut self) {
+        let Some(input) = self.time_input.as_deref() else {
+            return;
+        };
+        match TimeFilter::parse(input, Local::now()) {
+            Ok(filter) => {
+                self.time_filter = filter;
+                self.cancel_time_input();
+            }
+            Err(e) => self.time_input_error = Some(e.to_string()),
+        }
+    }
+
+    /// Open the SQL console editor with the last query
+    pub fn open_sql_input(&mut self) {
+        self.sql_input = Some(self.sql_query.clone());
+    }
+
+    pub fn cancel_sql_input(&mut self) {
+        self.sql_input = None;
+    }
+
+    pub fn sql_input_push(&mut self, c: char) {
+        if let Some(input) = self.sql_input.as_mut() {
+            input.push(c);
+        }
+    }
+
+    pub fn sql_input_backspace(&mut self) {
+        if let Some(input) = self.sql_input.as_mut() {
+            input.pop();
+        }
+    }
+
+    /// Run the typed query. On an error the editor stays open so the query can be fixed.
+    pub fn submit_sql_input(&mut self) {
+        let Some(input) = self.sql_input.clone() else {
+            return;
+        };
+        let result = self
+            .storage
+            .run_query(&input, SQL_ROW_LIMIT)
+            .map_err(|e| e.to_string());
+        if result.is_ok() {
+            self.sql_input = None;
+        }
+        self.sql_query = input;
+        self.sql_result = Some(result);
+        self.sql_scroll = 0;
+    }
+
+    /// Scroll the SQL console results by `delta` rows (clamped)
+    pub fn scroll_sql(&mut self, delta: isize) {
+        let rows = match &self.sql_result {
+            Some(Ok(result)) => result.rows.len(),
+            _ => 0,
+        };
+        self.sql_scroll = self
+            .sql_scroll
+            .saturating_add_signed(delta)
+            .min(rows.saturating_sub(1));
+    }
+
+    /// Record a receiver snapshot and recompute the events/sec rate over the sample window
+    pub fn update_receiver_status(&mut self, snapshot: ReceiverSnapshot, now: DateTime<Utc>) {
+        let window = chrono::Duration::seconds(RECEIVER_RATE_WINDOW_SECS);
+        self.receiver_samples
+            .push_back((now, snapshot.events_received));
+        while self
+            .receiver_samples
+            .front()
+            .is_some_and(|(at, _)| now - *at > window)
+        {
+            self.receiver_samples.pop_front();
+        }
+
+        let events_per_sec = match self.receiver_samples.front() {
+            Some((at, count)) if *at < now => {
+                let elapsed = (now - *at).num_milliseconds() as f64 / 1000.0;
+                snapshot.events_received.saturating_sub(*count) as f64 / elapsed
+            }
+            _ => 0.0,
         };
+
+        self.receiver_status = ReceiverStatus {
+            snapshot,
+            events_per_sec,
+        };
+    }
+
+    /// Show onboarding until the first event arrives, re-checking provider settings meanwhile
+    fn refresh_onboarding(&mut self) -> Result<()> {
+        if self.onboarding_finished {
+            return Ok(());
+        }
+        self.onboarding =
+            self.receiver_status.snapshot.events_received == 0 && self.storage.is_empty()?;
+        if !self.onboarding {
+            self.onboarding_finished = true;
+            self.provider_setup.clear();
+            return Ok(());
+        }
+        self.provider_setup = PROVIDER_REGISTRY
+            .providers()
+            .iter()
+            .map(|provider| ProviderSetup {
+                id: provider.id(),
+                name: provider.name(),
+                configured: provider.is_configured(),
+                supports_setup: provider.supports_setup(),
+                settings_path: provider.settings_path(),
+            })
+            .collect();
+        self.onboarding_index = self
+            .onboarding_index
+            .min(self.provider_setup.len().saturating_sub(1));
+        Ok(())
+    }
+
+    /// Move the onboarding provider selection (wraps around)
+    pub fn move_onboarding_selection(&mut self, delta: isize) {
+        let len = self.provider_setup.len() as isize;
+        if len > 0 {
+            self.onboarding_index =
+                (self.onboarding_index as isize + delta).rem_euclid(len) as usize;
+        }
+    }
+
+    /// Run telemetry setup for the provider selected on the onboarding screen
+    pub fn setup_selected_provider(&mut self) {
+        let Some(setup) = self.provider_setup.get(self.onboarding_index) else {
+            return;
+        };
+        let Some(provider) = PROVIDER_REGISTRY.get(setup.id) else {
+            return;
+        };
+        if !provider.supports_setup() {
+            let path = provider
+                .settings_path()
+                .map(|p| p.display().to_string())
+                .unwrap_or_default();
+            self.show_toast(
+                format!(
+                    "{} needs manual setup in {} (see README)",
+                    provider.name(),
+                    path
+                ),
+                false,
+            );
+            return;
+        }
+        match provider.ensure_configured(DEFAULT_OTLP_ENDPOINT) {
+            Ok(true) => self.show_toast(
+                format!(
+                    "Configured {}; restart it to start sending telemetry",
+                    provider.name()
+                ),
+                false,
+            ),
+            Ok(false) => {
+                self.show_toast(format!("{} is already configured", provider.name()), false)
+            }
+            Err(e) => self.show_toast(format!("Setup failed for {}: {}", provider.name(), e), true),
+        }
+    }
+
+    /// Show a toast notification
+    pub fn show_toast(&mut self, message: impl Into<String>, is_error: bool) {
+        self.toast = Some(Toast {
+            message: message.into(),
+            is_error,
+            shown_at: Utc::now(),
+        });
+        self.needs_redraw = true;
+    }
+
+    /// The toast to display, if one was shown recently
+    pub fn active_toast(&self) -> Option<&Toast> {
+        self.toast.as_ref().filter(|toast| {
+            Utc::now() - toast.shown_at < chrono::Duration::seconds(TOAST_DURATION_SECS)
+        })
+    }
+
+    /// Tool rows as currently displayed: built-in table first, then MCP, each in sort order
+    pub fn export_rows(&self) -> Vec<ToolExportRow> {
+        self.builtin_tools()
+            .into_iter()
+            .chain(self.mcp_tools())
+            .map(ToolExportRow::from)
+            .collect()
+    }
+
+    /// Export the current view into `dir`, returning the written file path
+    pub fn export_view_to(
+        &self,
+        dir: &std::path::Path,
+        format: ExportFormat,
+    ) -> Result<std::path::PathBuf> {
+        export::export_tool_metrics(dir, format, &self.export_rows(), &self.time_filter.label())
+    }
+
+    /// Export the current view into the data directory and report the result in a toast
+    pub fn export_view(&mut self, format: ExportFormat) {
+        let result = crate::config::data_dir()
+            .and_then(|dir| self.export_view_to(&dir.join("exports"), format));
+        match result {
+            Ok(path) => self.show_toast(format!("Exported to {}", path.display()), false),
+            Err(e) => self.show_toast(format!("Export failed: {}", e), true),
+        }
     }
 
     pub fn cache_reuse_rate(&self) -> f64 {
@@ -248,6 +1672,48 @@ impl App {
         (self.token_metrics.cache_read_tokens as f64 / total_input as f64) * 100.0
     }
 
+    /// Estimated dollars saved by cache reads: at their own model's prices where the
+    /// token counts name a priced model, the rest at the mix of models seen in API
+    /// requests. None when neither has known pricing.
+    pub fn cache_savings_usd(&self) -> Option<f64> {
+        let unpriced_reads = self
+            .token_metrics
+            .cache_read_tokens
+            .saturating_sub(self.cache_efficiency.priced_cache_read_tokens());
+        match (
+            self.cache_efficiency.total.saved_usd,
+            self.mixed_cache_savings_usd(unpriced_reads),
+        ) {
+            (None, None) => None,
+            (priced, mixed) => Some(priced.unwrap_or(0.0) + mixed.unwrap_or(0.0)),
+        }
+    }
+
+    /// Dollars saved by `cache_read_tokens` priced at the mix of models seen in API
+    /// requests; None when no requested model has known pricing
+    fn mixed_cache_savings_usd(&self, cache_read_tokens: u64) -> Option<f64> {
+        let priced: Vec<(crate::pricing::ModelPricing, u64)> = self
+            .api_metrics
+            .models
+            .iter()
+            .filter_map(|(model, calls)| crate::pricing::lookup(model).map(|p| (p, *calls)))
+            .collect();
+        let priced_calls: u64 = priced.iter().map(|(_, calls)| calls).sum();
+        if priced_calls == 0 {
+            return None;
+        }
+
+        Some(
+            priced
+                .iter()
+                .map(|(pricing, calls)| {
+                    crate::pricing::cache_savings_usd(cache_read_tokens, pricing) * *calls as f64
+                        / priced_calls as f64
+                })
+                .sum(),
+        )
+    }
+
     pub fn builtin_tools(&self) -> Vec<&ToolMetrics> {
         self.tool_metrics
             .iter()
@@ -275,18 +1741,30 @@ impl App {
             .flatten()
     }
 
-    /// Format active time as human-readable string (e.g., "1h 23m")
+    /// Format active time as human-readable string (e.g., "1h 23m (40%)"): the time
+    /// found from event density with the share of the sessions' time it covers, else the
+    /// agent's reported active time
     pub fn format_active_time(&self) -> String {
-        let secs = self.session_metrics.active_time_secs;
+        let metrics = &self.session_metrics;
+        let secs = match metrics.detected_active_secs {
+            0 => metrics.active_time_secs,
+            detected => detected,
+        };
         if secs == 0 {
             return "-".to_string();
         }
         let hours = secs / 3600;
         let mins = (secs % 3600) / 60;
-        if hours > 0 {
+        let time = if hours > 0 {
             format!("{}h {}m", hours, mins)
         } else {
             format!("{}m", mins)
+        };
+        match metrics.utilization() {
+            Some(utilization) if metrics.detected_active_secs > 0 => {
+                format!("{} ({:.0}%)", time, utilization * 100.0)
+            }
+            _ => time,
         }
     }
 
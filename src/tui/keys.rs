@@ -0,0 +1,351 @@
+//! Key bindings for the TUI.
+//!
+//! Bindings map key sequences to actions. Sequences are written vim-style:
+//! plain characters (`q`, `G`, `{`), multi-key sequences (`gg`), and named keys
+//! in angle brackets (`<C-d>`, `<Up>`, `<Enter>`). Defaults can be overridden
+//! from the `keys` section of the config file.
+
+use anyhow::{Result, bail};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::HashMap;
+
+/// Something the user can trigger from the keyboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Quit,
+    Sort,
+    Pause,
+    Detail,
+    Close,
+    TimeFilter,
+    TimeRange,
+    Reset,
+    Agent,
+    Compact,
+    View,
+    Compare,
+    CostEstimate,
+    HeatmapMetric,
+    HeatmapPreviousWeek,
+    HeatmapNextWeek,
+    ExportCsv,
+    ExportJson,
+    Snapshot,
+    Up,
+    Down,
+    Top,
+    Bottom,
+    HalfPageDown,
+    HalfPageUp,
+    PreviousSection,
+    NextSection,
+    Follow,
+    Setup,
+    Pin,
+}
+
+/// Action names as used in the config file
+const ACTION_NAMES: &[(&str, Action)] = &[
+    ("quit", Action::Quit),
+    ("sort", Action::Sort),
+    ("pause", Action::Pause),
+    ("detail", Action::Detail),
+    ("close", Action::Close),
+    ("time_filter", Action::TimeFilter),
+    ("time_range", Action::TimeRange),
+    ("reset", Action::Reset),
+    ("agent", Action::Agent),
+    ("compact", Action::Compact),
+    ("view", Action::View),
+    ("compare", Action::Compare),
+    ("cost_estimate", Action::CostEstimate),
+    ("heatmap_metric", Action::HeatmapMetric),
+    ("heatmap_previous_week", Action::HeatmapPreviousWeek),
+    ("heatmap_next_week", Action::HeatmapNextWeek),
+    ("export_csv", Action::ExportCsv),
+    ("export_json", Action::ExportJson),
+    ("snapshot", Action::Snapshot),
+    ("up", Action::Up),
+    ("down", Action::Down),
+    ("top", Action::Top),
+    ("bottom", Action::Bottom),
+    ("half_page_down", Action::HalfPageDown),
+    ("half_page_up", Action::HalfPageUp),
+    ("previous_section", Action::PreviousSection),
+    ("next_section", Action::NextSection),
+    ("follow", Action::Follow),
+    ("setup", Action::Setup),
+    ("pin", Action::Pin),
+];
+
+impl Action {
+    pub fn from_name(name: &str) -> Option<Action> {
+        ACTION_NAMES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, action)| *action)
+    }
+}
+
+/// Default bindings
+const DEFAULT_BINDINGS: &[(&str, Action)] = &[
+    ("q", Action::Quit),
+    ("s", Action::Sort),
+    ("p", Action::Pause),
+    ("d", Action::Detail),
+    ("<Enter>", Action::Detail),
+    ("<Esc>", Action::Close),
+    ("t", Action::TimeFilter),
+    ("T", Action::TimeRange),
+    ("r", Action::Reset),
+    ("a", Action::Agent),
+    ("c", Action::Compact),
+    ("v", Action::View),
+    ("w", Action::Compare),
+    ("$", Action::CostEstimate),
+    ("m", Action::HeatmapMetric),
+    ("[", Action::HeatmapPreviousWeek),
+    ("]", Action::HeatmapNextWeek),
+    ("x", Action::ExportCsv),
+    ("X", Action::ExportJson),
+    ("S", Action::Snapshot),
+    ("k", Action::Up),
+    ("<Up>", Action::Up),
+    ("j", Action::Down),
+    ("<Down>", Action::Down),
+    ("gg", Action::Top),
+    ("<Home>", Action::Top),
+    ("G", Action::Bottom),
+    ("<End>", Action::Bottom),
+    ("<C-d>", Action::HalfPageDown),
+    ("<PageDown>", Action::HalfPageDown),
+    ("<C-u>", Action::HalfPageUp),
+    ("<PageUp>", Action::HalfPageUp),
+    ("{", Action::PreviousSection),
+    ("}", Action::NextSection),
+    ("f", Action::Follow),
+    ("i", Action::Setup),
+    ("P", Action::Pin),
+];
+
+/// A single key press; Shift is folded into the character (`G` rather than Shift+g)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyPress {
+    pub code: KeyCode,
+    pub ctrl: bool,
+}
+
+impl From<KeyEvent> for KeyPress {
+    fn from(event: KeyEvent) -> Self {
+        Self {
+            code: event.code,
+            ctrl: event.modifiers.contains(KeyModifiers::CONTROL),
+        }
+    }
+}
+
+impl KeyPress {
+    fn plain(code: KeyCode) -> Self {
+        Self { code, ctrl: false }
+    }
+}
+
+/// Parse a key sequence such as `gg`, `<C-d>` or `<Up>`
+pub fn parse_keys(notation: &str) -> Result<Vec<KeyPress>> {
+    let mut keys = Vec::new();
+    let mut chars = notation.chars();
+    while let Some(c) = chars.next() {
+        if c != '<' {
+            keys.push(KeyPress::plain(KeyCode::Char(c)));
+            continue;
+        }
+        let name: String = chars.by_ref().take_while(|c| *c != '>').collect();
+        keys.push(parse_named_key(&name)?);
+    }
+    if keys.is_empty() {
+        bail!("Empty key sequence");
+    }
+    Ok(keys)
+}
+
+fn parse_named_key(name: &str) -> Result<KeyPress> {
+    if let Some(rest) = name.strip_prefix("C-")
+        && let [c] = rest.chars().collect::<Vec<_>>()[..]
+    {
+        return Ok(KeyPress {
+            code: KeyCode::Char(c.to_ascii_lowercase()),
+            ctrl: true,
+        });
+    }
+    let code = match name.to_lowercase().as_str() {
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "enter" | "cr" => KeyCode::Enter,
+        "esc" => KeyCode::Esc,
+        "tab" => KeyCode::Tab,
+        "space" => KeyCode::Char(' '),
+        "lt" => KeyCode::Char('<'),
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        _ => bail!("Unknown key <{}>", name),
+    };
+    Ok(KeyPress::plain(code))
+}
+
+/// Key sequence to action mapping
+#[derive(Debug, Clone)]
+pub struct KeyMap {
+    bindings: HashMap<Vec<KeyPress>, Action>,
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        let bindings = DEFAULT_BINDINGS
+            .iter()
+            .map(|(keys, action)| {
+                (
+                    parse_keys(keys).expect("default key bindings are valid"),
+                    *action,
+                )
+            })
+            .collect();
+        Self { bindings }
+    }
+}
+
+impl KeyMap {
+    /// Defaults with user overrides applied (sequence -> action name, or "none" to unbind)
+    pub fn with_overrides(overrides: &HashMap<String, String>) -> Result<Self> {
+        let mut map = Self::default();
+        for (keys, action_name) in overrides {
+            let keys = parse_keys(keys)?;
+            if action_name == "none" {
+                map.bindings.remove(&keys);
+                continue;
+            }
+            let Some(action) = Action::from_name(action_name) else {
+                bail!("Unknown action \"{}\"", action_name);
+            };
+            map.bindings.insert(keys, action);
+        }
+        Ok(map)
+    }
+
+    fn is_prefix(&self, keys: &[KeyPress]) -> bool {
+        self.bindings
+            .keys()
+            .any(|binding| binding.len() > keys.len() && binding.starts_with(keys))
+    }
+}
+
+/// Pending input: a numeric count prefix and a partially typed sequence
+#[derive(Debug, Clone, Default)]
+pub struct KeyInput {
+    count: Option<usize>,
+    pending: Vec<KeyPress>,
+}
+
+impl KeyInput {
+    /// Feed a key press; returns the action and its count (None if a count was not typed)
+    /// once a full binding has been entered
+    pub fn push(&mut self, map: &KeyMap, key: KeyPress) -> Option<(Action, Option<usize>)> {
+        // Digits start or extend a count unless they begin a binding themselves
+        if self.pending.is_empty()
+            && !key.ctrl
+            && let KeyCode::Char(c) = key.code
+            && let Some(digit) = c.to_digit(10)
+            && (digit > 0 || self.count.is_some())
+            && !map.bindings.contains_key(&[key][..])
+            && !map.is_prefix(&[key])
+        {
+            let count = self.count.unwrap_or(0).saturating_mul(10) + digit as usize;
+            self.count = Some(count);
+            return None;
+        }
+
+        self.pending.push(key);
+        if let Some(action) = map.bindings.get(&self.pending) {
+            let count = self.count.take();
+            self.pending.clear();
+            return Some((*action, count));
+        }
+        if map.is_prefix(&self.pending) {
+            return None;
+        }
+
+        // Not a binding: drop the sequence, but let its last key start a new one
+        let retry = self.pending.len() > 1;
+        self.pending.clear();
+        self.count = None;
+        if retry { self.push(map, key) } else { None }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(c: char) -> KeyPress {
+        KeyPress::plain(KeyCode::Char(c))
+    }
+
+    #[test]
+    fn test_parse_keys() {
+        assert_eq!(parse_keys("gg").unwrap(), vec![key('g'), key('g')]);
+        assert_eq!(
+            parse_keys("<C-d>").unwrap(),
+            vec![KeyPress {
+                code: KeyCode::Char('d'),
+                ctrl: true
+            }]
+        );
+        assert_eq!(
+            parse_keys("<PageDown>").unwrap(),
+            vec![KeyPress::plain(KeyCode::PageDown)]
+        );
+        assert!(parse_keys("<Nope>").is_err());
+        assert!(parse_keys("").is_err());
+    }
+
+    #[test]
+    fn test_sequences_and_counts() {
+        let map = KeyMap::default();
+        let mut input = KeyInput::default();
+
+        assert_eq!(input.push(&map, key('g')), None);
+        assert_eq!(input.push(&map, key('g')), Some((Action::Top, None)));
+
+        assert_eq!(input.push(&map, key('1')), None);
+        assert_eq!(input.push(&map, key('2')), None);
+        assert_eq!(input.count, Some(12));
+        assert_eq!(input.push(&map, key('j')), Some((Action::Down, Some(12))));
+        assert_eq!(input.count, None);
+
+        // An unknown continuation falls back to the last key on its own
+        assert_eq!(input.push(&map, key('g')), None);
+        assert_eq!(input.push(&map, key('G')), Some((Action::Bottom, None)));
+    }
+
+    #[test]
+    fn test_overrides() {
+        let overrides = HashMap::from([
+            ("<C-n>".to_string(), "down".to_string()),
+            ("q".to_string(), "none".to_string()),
+        ]);
+        let map = KeyMap::with_overrides(&overrides).unwrap();
+        let mut input = KeyInput::default();
+        let ctrl_n = KeyPress {
+            code: KeyCode::Char('n'),
+            ctrl: true,
+        };
+        assert_eq!(input.push(&map, ctrl_n), Some((Action::Down, None)));
+        assert_eq!(input.push(&map, key('q')), None);
+
+        let bad = HashMap::from([("z".to_string(), "fly".to_string())]);
+        assert!(KeyMap::with_overrides(&bad).is_err());
+    }
+}
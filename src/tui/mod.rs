@@ -1,4 +1,7 @@
+pub mod alerts;
 pub mod app;
+pub mod keys;
+pub mod snapshot;
 pub mod ui;
 
 use anyhow::Result;
@@ -9,12 +12,30 @@ use crossterm::{
 };
 use ratatui::prelude::*;
 use std::io;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use crate::storage::StorageHandle;
-use app::App;
+use crate::config::Settings;
+use crate::export::ExportFormat;
+use crate::storage::{self, StorageHandle};
+use alerts::AlertThresholds;
+use app::{App, View};
+use keys::{Action, KeyInput, KeyMap};
+use snapshot::SnapshotFormat;
 
-pub async fn run(storage: StorageHandle) -> Result<()> {
+/// Refresh interval once the dashboard is idle (no new data), unless the configured one
+/// is longer
+const IDLE_REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Longest time a frame stays up unchanged: relative times ("12s ago"), toasts and row
+/// highlights move with the clock
+const MAX_FRAME_AGE: Duration = Duration::from_secs(1);
+
+pub async fn run(
+    storage: StorageHandle,
+    alert_thresholds: AlertThresholds,
+    mut settings: Settings,
+    view_only: bool,
+) -> Result<()> {
     // Setup terminal
     enable_raw_mode()?;
     let mut stdout = io::stdout();
@@ -23,10 +44,22 @@ pub async fn run(storage: StorageHandle) -> Result<()> {
     let mut terminal = Terminal::new(backend)?;
 
     // Create app state
-    let mut app = App::new(storage);
+    let mut app = App::new(storage.scoped());
+    app.alert_thresholds = alert_thresholds;
+    app.view_only = view_only;
+    app.pinned_tools = settings.app.pinned_tools.clone();
+    app.latency_slos_ms = settings.app.latency_slos_ms.clone();
+    app.budgets = settings.app.budgets.clone();
+    app.quotas = settings.app.quotas.clone();
+
+    // Fall back to the default bindings if the configured ones are invalid
+    let keymap = KeyMap::with_overrides(&settings.app.keys).unwrap_or_else(|e| {
+        app.show_toast(format!("Invalid key binding in config: {}", e), true);
+        KeyMap::default()
+    });
 
     // Run the main loop
-    let res = run_app(&mut terminal, &mut app).await;
+    let res = run_app(&mut terminal, &mut app, &keymap, &mut settings).await;
 
     // Restore terminal
     disable_raw_mode()?;
@@ -44,33 +77,240 @@ pub async fn run(storage: StorageHandle) -> Result<()> {
     Ok(())
 }
 
-async fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> Result<()> {
+async fn run_app<B: Backend>(
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+    keymap: &KeyMap,
+    settings: &mut Settings,
+) -> Result<()> {
+    let mut key_input = KeyInput::default();
+    let refresh_interval = settings.tui.refresh_interval();
+    let theme = settings.tui.theme;
+    let mut last_refresh: Option<Instant> = None;
+    let mut last_frame: Option<Instant> = None;
+
     loop {
-        // Refresh data from storage
-        app.refresh()?;
+        // Refresh data from storage every refresh interval (less often while no new data
+        // arrives), and right after a key press
+        let interval = if app.is_idle(chrono::Utc::now()) {
+            refresh_interval.max(IDLE_REFRESH_INTERVAL)
+        } else {
+            refresh_interval
+        };
+        if last_refresh.is_none_or(|at| at.elapsed() >= interval) {
+            if let Err(e) = app.refresh() {
+                if storage::is_interrupted(&e) {
+                    // A slow query timed out; the next refresh tries again
+                    app.show_toast(format!("Refresh failed: {:#}", e), true);
+                } else if !storage::is_unavailable(&e) {
+                    // (Storage failing mid-refresh is shown in the footer while it restarts)
+                    return Err(e);
+                }
+            }
+            last_refresh = Some(Instant::now());
+        }
 
-        // Draw UI
-        terminal.draw(|f| ui::draw(f, app))?;
+        // Draw UI only when something changed, or the frame is getting stale
+        if app.needs_redraw || last_frame.is_none_or(|at| at.elapsed() >= MAX_FRAME_AGE) {
+            terminal.draw(|f| {
+                ui::draw(f, app);
+                ui::apply_theme(f.buffer_mut(), theme);
+            })?;
+            app.needs_redraw = false;
+            last_frame = Some(Instant::now());
+        }
 
         // Handle input with timeout for refresh
-        if event::poll(Duration::from_millis(100))?
-            && let Event::Key(key) = event::read()?
-            && key.kind == KeyEventKind::Press
-        {
-            match key.code {
-                KeyCode::Char('q') => return Ok(()),
-                KeyCode::Char('s') => app.toggle_sort(),
-                KeyCode::Char('p') => app.toggle_pause(),
-                KeyCode::Char('d') => app.toggle_detail(),
-                KeyCode::Char('t') => app.toggle_time_filter(),
-                KeyCode::Char('r') => app.reset_stats(),
-                KeyCode::Char('a') => app.cycle_agent(),
-                KeyCode::Up | KeyCode::Char('k') => app.select_previous(),
-                KeyCode::Down | KeyCode::Char('j') => app.select_next(),
-                KeyCode::Enter => app.toggle_detail(),
-                KeyCode::Esc => app.close_detail(),
-                _ => {}
+        if event::poll(Duration::from_millis(100))? {
+            let key = match event::read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => key,
+                Event::Resize(..) => {
+                    app.needs_redraw = true;
+                    continue;
+                }
+                _ => continue,
+            };
+            last_refresh = None;
+            app.invalidate();
+            app.needs_redraw = true;
+
+            // Route keys to the time range input while it is open
+            if app.time_input.is_some() {
+                match key.code {
+                    KeyCode::Enter => app.submit_time_input(),
+                    KeyCode::Esc => app.cancel_time_input(),
+                    KeyCode::Backspace => app.time_input_backspace(),
+                    KeyCode::Char(c) => app.time_input_push(c),
+                    _ => {}
+                }
+                continue;
+            }
+
+            // Likewise for the SQL console editor
+            if app.sql_input.is_some() {
+                match key.code {
+                    KeyCode::Enter => app.submit_sql_input(),
+                    KeyCode::Esc => app.cancel_sql_input(),
+                    KeyCode::Backspace => app.sql_input_backspace(),
+                    KeyCode::Char(c) => app.sql_input_push(c),
+                    _ => {}
+                }
+                continue;
+            }
+
+            if let Some((action, count)) = key_input.push(keymap, key.into()) {
+                let height = terminal.size()?.height;
+                if !apply_action(app, action, count, height, terminal, settings)? {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Rows moved by half-page scrolling: about half of one tool table
+fn half_page_rows(height: u16) -> isize {
+    // Header, metrics, footer and table chrome take about 10 rows; the tables share the rest
+    (height.saturating_sub(10) / 4).max(1) as isize
+}
+
+/// Run a bound action `count` times where repetition makes sense. Returns false to quit.
+fn apply_action<B: Backend>(
+    app: &mut App,
+    action: Action,
+    count: Option<usize>,
+    height: u16,
+    terminal: &mut Terminal<B>,
+    settings: &mut Settings,
+) -> Result<bool> {
+    let times = count.unwrap_or(1).max(1);
+
+    // Up/down pick a provider on the onboarding screen
+    if app.onboarding && app.view == View::Dashboard {
+        let delta = match action {
+            Action::Up => Some(-(times as isize)),
+            Action::Down => Some(times as isize),
+            _ => None,
+        };
+        if let Some(delta) = delta {
+            app.move_onboarding_selection(delta);
+            return Ok(true);
+        }
+    }
+
+    // Movement scrolls the SQL console results; Enter edits the query
+    if app.view == View::Sql {
+        let half_page = half_page_rows(height) * 2;
+        let delta = match action {
+            Action::Up => Some(-(times as isize)),
+            Action::Down => Some(times as isize),
+            Action::HalfPageUp => Some(-half_page * times as isize),
+            Action::HalfPageDown => Some(half_page * times as isize),
+            Action::Top => Some(isize::MIN),
+            Action::Bottom => Some(isize::MAX),
+            _ => None,
+        };
+        if let Some(delta) = delta {
+            app.scroll_sql(delta);
+            return Ok(true);
+        }
+        if action == Action::Detail {
+            app.open_sql_input();
+            return Ok(true);
+        }
+    }
+
+    // Movement scrolls the event stream while it is shown
+    if app.view == View::Events {
+        // The event stream is one full-height panel, so a half page is twice a table's
+        let half_page = half_page_rows(height) * 2;
+        let delta = match action {
+            Action::Up => Some(-(times as isize)),
+            Action::Down => Some(times as isize),
+            Action::HalfPageUp => Some(-half_page * times as isize),
+            Action::HalfPageDown => Some(half_page * times as isize),
+            Action::Top => Some(isize::MIN),
+            Action::Bottom => Some(isize::MAX),
+            _ => None,
+        };
+        if let Some(delta) = delta {
+            app.scroll_events(delta);
+            return Ok(true);
+        }
+    }
+
+    match action {
+        Action::Quit => return Ok(false),
+        Action::Sort => app.toggle_sort(),
+        Action::Pause => app.toggle_pause(),
+        Action::Detail => app.toggle_detail(),
+        Action::Close => app.close_detail(),
+        Action::TimeFilter => app.toggle_time_filter(),
+        Action::TimeRange => app.open_time_input(),
+        Action::Reset => app.reset_stats(),
+        Action::Agent => app.cycle_agent(),
+        Action::Compact => app.toggle_compact(),
+        Action::View => app.cycle_view(),
+        Action::Compare => app.toggle_compare_mode(),
+        Action::CostEstimate => app.toggle_cost_estimate(),
+        Action::HeatmapMetric if app.view == View::Heatmap => app.toggle_heatmap_metric(),
+        Action::HeatmapPreviousWeek if app.view == View::Heatmap => {
+            (0..times).for_each(|_| app.heatmap_previous_week())
+        }
+        Action::HeatmapNextWeek if app.view == View::Heatmap => {
+            (0..times).for_each(|_| app.heatmap_next_week())
+        }
+        Action::HeatmapMetric | Action::HeatmapPreviousWeek | Action::HeatmapNextWeek => {}
+        Action::ExportCsv => app.export_view(ExportFormat::Csv),
+        Action::ExportJson => app.export_view(ExportFormat::Json),
+        Action::Snapshot => {
+            let size = terminal.size()?;
+            save_snapshot(app, size.width, size.height);
+        }
+        // Up/down keep their wrap-around behaviour for single steps
+        Action::Up if count.is_none() => app.select_previous(),
+        Action::Down if count.is_none() => app.select_next(),
+        Action::Up => app.move_selection(-(times as isize)),
+        Action::Down => app.move_selection(times as isize),
+        // With a count, gg/G jump to that (1-based) row like vim
+        Action::Top => app.select_row(count.map_or(0, |n| n.saturating_sub(1))),
+        Action::Bottom => match count {
+            Some(n) => app.select_row(n.saturating_sub(1)),
+            None => app.select_row(usize::MAX),
+        },
+        Action::HalfPageDown => app.move_selection(half_page_rows(height) * times as isize),
+        Action::HalfPageUp => app.move_selection(-half_page_rows(height) * times as isize),
+        Action::NextSection => (0..times).for_each(|_| app.select_next_section()),
+        Action::PreviousSection => (0..times).for_each(|_| app.select_previous_section()),
+        Action::Follow if app.view == View::Events => app.toggle_event_follow(),
+        Action::Follow => {}
+        Action::Setup if app.onboarding => app.setup_selected_provider(),
+        Action::Setup => {}
+        Action::Pin => {
+            if app.toggle_pin_selected() {
+                settings.app.pinned_tools = app.pinned_tools.clone();
+                if let Err(e) = settings.save_pinned_tools() {
+                    app.show_toast(format!("Could not save pins: {}", e), true);
+                }
             }
         }
     }
+    Ok(true)
+}
+
+/// Save a text snapshot of the dashboard to the data directory and report it in a toast
+fn save_snapshot(app: &mut App, width: u16, height: u16) {
+    let result = crate::config::data_dir().and_then(|dir| {
+        snapshot::save(
+            app,
+            &dir.join("snapshots"),
+            SnapshotFormat::Text,
+            width,
+            height,
+        )
+    });
+    match result {
+        Ok(path) => app.show_toast(format!("Snapshot saved to {}", path.display()), false),
+        Err(e) => app.show_toast(format!("Snapshot failed: {}", e), true),
+    }
 }
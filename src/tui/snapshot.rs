@@ -0,0 +1,175 @@
+//! Render the dashboard to a plain-text or HTML snapshot.
+//!
+//! Uses ratatui's TestBackend so the snapshot matches what the TUI draws.
+
+use anyhow::Result;
+use chrono::Utc;
+use ratatui::{
+    Terminal,
+    backend::TestBackend,
+    buffer::Buffer,
+    style::{Color, Modifier},
+};
+use std::path::{Path, PathBuf};
+
+use super::app::App;
+use super::ui;
+
+/// Output format for dashboard snapshots
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum SnapshotFormat {
+    #[default]
+    Text,
+    Html,
+}
+
+impl SnapshotFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            SnapshotFormat::Text => "txt",
+            SnapshotFormat::Html => "html",
+        }
+    }
+}
+
+/// Draw the dashboard into an off-screen buffer of the given size
+pub fn render_buffer(app: &App, width: u16, height: u16) -> Result<Buffer> {
+    let mut terminal = Terminal::new(TestBackend::new(width, height))?;
+    terminal.draw(|f| ui::draw(f, app))?;
+    Ok(terminal.backend().buffer().clone())
+}
+
+/// Render the dashboard as plain text (one line per terminal row, trailing spaces trimmed)
+pub fn render_text(app: &App, width: u16, height: u16) -> Result<String> {
+    let buffer = render_buffer(app, width, height)?;
+    Ok(buffer_to_text(&buffer))
+}
+
+/// Render the dashboard as a standalone HTML page preserving colors
+pub fn render_html(app: &App, width: u16, height: u16) -> Result<String> {
+    let buffer = render_buffer(app, width, height)?;
+    Ok(buffer_to_html(&buffer))
+}
+
+pub fn render(app: &App, format: SnapshotFormat, width: u16, height: u16) -> Result<String> {
+    match format {
+        SnapshotFormat::Text => render_text(app, width, height),
+        SnapshotFormat::Html => render_html(app, width, height),
+    }
+}
+
+/// Render a snapshot to a timestamped file in `dir`, returning the file path
+pub fn save(
+    app: &App,
+    dir: &Path,
+    format: SnapshotFormat,
+    width: u16,
+    height: u16,
+) -> Result<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(format!(
+        "agenttop-snapshot-{}.{}",
+        Utc::now().format("%Y%m%d-%H%M%S"),
+        format.extension()
+    ));
+    std::fs::write(&path, render(app, format, width, height)?)?;
+    Ok(path)
+}
+
+fn buffer_to_text(buffer: &Buffer) -> String {
+    let area = buffer.area;
+    let mut out = String::new();
+    for y in area.top()..area.bottom() {
+        let mut line = String::new();
+        for x in area.left()..area.right() {
+            line.push_str(buffer[(x, y)].symbol());
+        }
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+fn buffer_to_html(buffer: &Buffer) -> String {
+    let area = buffer.area;
+    let mut body = String::new();
+    for y in area.top()..area.bottom() {
+        // Group consecutive cells with the same style into one span
+        let mut current_style = String::new();
+        let mut run = String::new();
+        for x in area.left()..area.right() {
+            let cell = &buffer[(x, y)];
+            let style = cell_css(cell.fg, cell.bg, cell.modifier);
+            if style != current_style && !run.is_empty() {
+                push_span(&mut body, &current_style, &run);
+                run.clear();
+            }
+            current_style = style;
+            run.push_str(&html_escape(cell.symbol()));
+        }
+        push_span(&mut body, &current_style, &run);
+        body.push('\n');
+    }
+
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>agenttop snapshot {}</title>\n</head>\n<body style=\"background:#000;color:#ccc\">\n<pre style=\"font-family:monospace;line-height:1.2\">\n{}</pre>\n</body>\n</html>\n",
+        Utc::now().format("%Y-%m-%d %H:%M:%S UTC"),
+        body
+    )
+}
+
+fn push_span(out: &mut String, style: &str, text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    if style.is_empty() {
+        out.push_str(text);
+    } else {
+        out.push_str(&format!("<span style=\"{}\">{}</span>", style, text));
+    }
+}
+
+fn cell_css(fg: Color, bg: Color, modifier: Modifier) -> String {
+    let mut css = Vec::new();
+    if let Some(c) = color_css(fg) {
+        css.push(format!("color:{}", c));
+    }
+    if let Some(c) = color_css(bg) {
+        css.push(format!("background:{}", c));
+    }
+    if modifier.contains(Modifier::BOLD) {
+        css.push("font-weight:bold".to_string());
+    }
+    css.join(";")
+}
+
+fn color_css(color: Color) -> Option<String> {
+    let css = match color {
+        Color::Reset => return None,
+        Color::Black => "#000000",
+        Color::Red => "#cd3131",
+        Color::Green => "#0dbc79",
+        Color::Yellow => "#e5e510",
+        Color::Blue => "#2472c8",
+        Color::Magenta => "#bc3fbc",
+        Color::Cyan => "#11a8cd",
+        Color::Gray => "#e5e5e5",
+        Color::DarkGray => "#666666",
+        Color::LightRed => "#f14c4c",
+        Color::LightGreen => "#23d18b",
+        Color::LightYellow => "#f5f543",
+        Color::LightBlue => "#3b8eea",
+        Color::LightMagenta => "#d670d6",
+        Color::LightCyan => "#29b8db",
+        Color::White => "#ffffff",
+        Color::Rgb(r, g, b) => return Some(format!("#{:02x}{:02x}{:02x}", r, g, b)),
+        Color::Indexed(_) => return None,
+    };
+    Some(css.to_string())
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
@@ -1,56 +1,145 @@
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use ratatui::{
     Frame,
+    buffer::Buffer,
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, Cell, Clear, Paragraph, Row, Table, TableState},
+    widgets::{Block, Borders, Cell, Clear, Paragraph, Row, Table, TableState, Wrap},
 };
 
-use super::app::App;
+use super::app::{App, HeatmapMetric, SuccessTrend, Toast, View, WORKFLOW_LENGTH, format_usd};
+use crate::budget::{self, BudgetLevel};
+use crate::config::Theme;
+use crate::context;
+use crate::productivity::{self, Productivity};
+use crate::prompt_cost;
 use crate::providers::PROVIDER_REGISTRY;
+use crate::quota;
+use crate::report::providers::{measures, provider_name};
+use crate::report::{format_count, format_duration};
+use crate::storage::{FileActivity, TimelineKind, ToolMetrics, get_tool_display_name};
+use crate::text;
+
+/// Terminal width at which the tool tables and API panel are placed side by side
+pub const WIDE_LAYOUT_MIN_WIDTH: u16 = 160;
 
 pub fn draw(f: &mut Frame, app: &App) {
     let has_mcp_tools = !app.mcp_tools().is_empty();
 
-    let chunks = if has_mcp_tools {
-        Layout::default()
-            .direction(Direction::Vertical)
-            .constraints([
-                Constraint::Length(3),   // Header with session info
-                Constraint::Length(3),   // Metrics bar (tokens + tools summary)
-                Constraint::Ratio(1, 2), // Built-in tools table (50%)
-                Constraint::Ratio(1, 2), // MCP tools section (50%)
-                Constraint::Length(1),   // Footer (hotkeys only)
-            ])
-            .split(f.area())
-    } else {
-        Layout::default()
-            .direction(Direction::Vertical)
-            .constraints([
-                Constraint::Length(3), // Header with session info
-                Constraint::Length(3), // Metrics bar (tokens + tools summary)
-                Constraint::Min(8),    // Built-in tools table
-                Constraint::Length(1), // Footer (hotkeys only)
-            ])
-            .split(f.area())
-    };
+    // Alert banner takes a line at the top only while alerts are active
+    let banner_height = if app.alerts.is_empty() { 0 } else { 1 };
+    let outer = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(banner_height), Constraint::Min(0)])
+        .split(f.area());
 
-    draw_header(f, app, chunks[0]);
-    draw_metrics_bar(f, app, chunks[1]);
-    draw_builtin_tool_table(f, app, chunks[2]);
+    if banner_height > 0 {
+        draw_alert_banner(f, app, outer[0]);
+    }
 
-    if has_mcp_tools {
-        draw_mcp_table(f, app, chunks[3]);
-        draw_footer(f, chunks[4]);
-    } else {
-        draw_footer(f, chunks[3]);
+    let compact = app.is_compact(f.area().width);
+    let metrics_height = if compact { 1 } else { 3 };
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3),              // Header with session info
+            Constraint::Length(metrics_height), // Metrics bar (tokens + tools summary)
+            Constraint::Min(8),                 // Tool tables (and API panel in wide layout)
+            Constraint::Length(1),              // Footer (hotkeys only)
+        ])
+        .split(outer[1]);
+
+    draw_header(f, app, chunks[0]);
+    draw_metrics_bar(f, app, chunks[1], compact);
+
+    match app.view {
+        _ if app.loading => draw_loading(f, app, chunks[2]),
+        View::Dashboard if app.onboarding => draw_onboarding(f, app, chunks[2]),
+        View::Dashboard => draw_dashboard_body(f, app, chunks[2], compact, has_mcp_tools),
+        View::Timeline => draw_timeline(f, app, chunks[2]),
+        View::Sessions => draw_sessions(f, app, chunks[2]),
+        View::Prompts => draw_prompt_history(f, app, chunks[2]),
+        View::Turns => draw_turns(f, app, chunks[2]),
+        View::Workflows => draw_workflows(f, app, chunks[2]),
+        View::Files => draw_files(f, app, chunks[2]),
+        View::Cache => draw_cache(f, app, chunks[2]),
+        View::Productivity => draw_productivity(f, app, chunks[2]),
+        View::Providers => draw_providers(f, app, chunks[2]),
+        View::Errors => draw_errors(f, app, chunks[2]),
+        View::Heatmap => draw_heatmap(f, app, chunks[2]),
+        View::Events => draw_event_stream(f, app, chunks[2]),
+        View::Sql => draw_sql_console(f, app, chunks[2]),
     }
 
+    draw_footer(f, app, chunks[3], compact);
+
     // Draw detail popup if active
     if app.show_detail {
         draw_detail_popup(f, app);
     }
+
+    // Draw toast notification over the footer
+    if let Some(toast) = app.active_toast() {
+        draw_toast(f, toast);
+    }
+
+    // Draw time range input if open
+    if app.time_input.is_some() {
+        draw_time_input(f, app);
+    }
+}
+
+/// Restyle a drawn frame for the configured theme. `Mono` drops every color and shows
+/// highlighted cells (those with a background) reversed instead.
+pub fn apply_theme(buf: &mut Buffer, theme: Theme) {
+    if theme != Theme::Mono {
+        return;
+    }
+    for cell in buf.content.iter_mut() {
+        if cell.bg != Color::Reset {
+            cell.modifier.insert(Modifier::REVERSED);
+        }
+        cell.fg = Color::Reset;
+        cell.bg = Color::Reset;
+    }
+}
+
+fn draw_dashboard_body(f: &mut Frame, app: &App, area: Rect, compact: bool, has_mcp_tools: bool) {
+    if !compact && area.width >= WIDE_LAYOUT_MIN_WIDTH {
+        draw_wide_body(f, app, area, has_mcp_tools);
+    } else if has_mcp_tools {
+        let body = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Ratio(1, 2), // Built-in tools table (50%)
+                Constraint::Ratio(1, 2), // MCP tools section (50%)
+            ])
+            .split(area);
+        draw_builtin_tool_table(f, app, body[0], compact);
+        draw_mcp_table(f, app, body[1], compact);
+    } else {
+        draw_builtin_tool_table(f, app, area, compact);
+    }
+}
+
+fn draw_alert_banner(f: &mut Frame, app: &App, area: Rect) {
+    let messages: Vec<String> = app.alerts.iter().map(|a| a.message()).collect();
+    let text = format!(
+        " ⚠ {} (last {}m)",
+        messages.join("  │  "),
+        app.alert_thresholds.window_mins
+    );
+
+    let paragraph = Paragraph::new(Line::from(Span::styled(
+        text,
+        Style::default()
+            .fg(Color::White)
+            .bg(Color::Red)
+            .add_modifier(Modifier::BOLD),
+    )))
+    .style(Style::default().bg(Color::Red));
+    f.render_widget(paragraph, area);
 }
 
 fn draw_header(f: &mut Frame, app: &App, area: Rect) {
@@ -106,7 +195,12 @@ fn draw_header(f: &mut Frame, app: &App, area: Rect) {
     f.render_widget(paragraph, area);
 }
 
-fn draw_metrics_bar(f: &mut Frame, app: &App, area: Rect) {
+fn draw_metrics_bar(f: &mut Frame, app: &App, area: Rect, compact: bool) {
+    if compact {
+        draw_compact_metrics_bar(f, app, area);
+        return;
+    }
+
     let cache_reuse = app.cache_reuse_rate();
     let total_calls = app.total_tool_calls();
 
@@ -146,6 +240,81 @@ fn draw_metrics_bar(f: &mut Frame, app: &App, area: Rect) {
         Span::raw(")"),
     ];
 
+    if let Some(saved) = app
+        .cache_savings_usd()
+        .filter(|_| app.token_metrics.cache_read_tokens > 0)
+    {
+        metrics_spans.push(Span::raw("  "));
+        metrics_spans.push(Span::styled(
+            "Cache saved ",
+            Style::default().fg(Color::DarkGray),
+        ));
+        metrics_spans.push(Span::styled(
+            format!("~{}", format_usd(saved)),
+            Style::default().fg(Color::Green),
+        ));
+        // Daily hit rate, once there is more than one day to compare
+        let days = &app.cache_efficiency.days;
+        if days.len() > 1 {
+            let rates: Vec<u64> = days
+                .iter()
+                .map(|d| d.totals.hit_rate().unwrap_or(0.0).round() as u64)
+                .collect();
+            metrics_spans.push(Span::raw(" "));
+            metrics_spans.push(Span::styled(
+                braille_sparkline(&rates),
+                Style::default().fg(Color::Magenta),
+            ));
+        }
+    }
+
+    // Add cost and end-of-day / end-of-month projection if available
+    let cost = app.shown_cost();
+    let projection = app.cost_projection;
+    if cost > 0.0 || projection.burn_rate_per_hour > 0.0 {
+        let label = if app.estimated_cost {
+            "Est. cost: "
+        } else {
+            "Cost: "
+        };
+        metrics_spans.push(Span::raw("  "));
+        metrics_spans.push(Span::styled(label, Style::default().fg(Color::DarkGray)));
+        metrics_spans.push(Span::styled(
+            format_usd(cost),
+            Style::default().fg(Color::Yellow),
+        ));
+    }
+    if projection.burn_rate_per_hour > 0.0 {
+        metrics_spans.push(Span::raw("  "));
+        metrics_spans.push(Span::styled("Proj: ", Style::default().fg(Color::DarkGray)));
+        metrics_spans.push(Span::styled(
+            format!(
+                "~{} EOD / ~{} EOM",
+                format_usd(projection.end_of_day_usd),
+                format_usd(projection.end_of_month_usd)
+            ),
+            Style::default().fg(Color::LightRed),
+        ));
+    }
+
+    if let Some(status) = budget::most_severe(&app.budget_statuses) {
+        metrics_spans.push(Span::raw("  "));
+        metrics_spans.push(Span::styled(
+            "Budget: ",
+            Style::default().fg(Color::DarkGray),
+        ));
+        metrics_spans.push(Span::styled(status.describe(), budget_style(status.level)));
+    }
+
+    if let Some(status) = quota::most_severe(&app.quota_statuses) {
+        metrics_spans.push(Span::raw("  "));
+        metrics_spans.push(Span::styled(
+            "Window: ",
+            Style::default().fg(Color::DarkGray),
+        ));
+        metrics_spans.push(Span::styled(status.describe(), budget_style(status.level)));
+    }
+
     // Add LOC and Commits if available
     let loc = app.session_metrics.lines_of_code;
     let commits = app.session_metrics.commit_count;
@@ -244,27 +413,123 @@ fn draw_metrics_bar(f: &mut Frame, app: &App, area: Rect) {
         Style::default().fg(Color::Cyan),
     ));
 
+    if let Some((met, total)) = app.latency_slo_summary() {
+        api_spans.push(Span::raw("  "));
+        api_spans.push(Span::styled("SLOs: ", Style::default().fg(Color::DarkGray)));
+        api_spans.push(Span::styled(
+            format!("{}/{}", met, total),
+            Style::default().fg(if met == total {
+                Color::Green
+            } else {
+                Color::Red
+            }),
+        ));
+    }
+
     let api_line = Line::from(api_spans);
 
     let block = Block::default().borders(Borders::LEFT | Borders::RIGHT);
 
-    let paragraph = Paragraph::new(vec![metrics_line, api_line]).block(block);
+    let mut lines = vec![metrics_line, api_line];
+    if app.compare_mode {
+        lines.push(comparison_line(app));
+    }
+
+    let paragraph = Paragraph::new(lines).block(block);
     f.render_widget(paragraph, area);
 }
 
-fn draw_builtin_tool_table(f: &mut Frame, app: &App, area: Rect) {
-    let header_cells = [
-        "TOOL", "CALLS", "ERR", "APR%", "AVG", "RANGE", "LAST", "FREQ",
-    ]
-    .iter()
-    .map(|h| {
-        Cell::from(*h).style(
-            Style::default()
-                .fg(Color::Yellow)
-                .add_modifier(Modifier::BOLD),
-        )
-    });
-    let header = Row::new(header_cells).height(1);
+fn budget_style(level: BudgetLevel) -> Style {
+    match level {
+        BudgetLevel::Ok => Style::default().fg(Color::Green),
+        BudgetLevel::Warn => Style::default().fg(Color::Yellow),
+        BudgetLevel::Critical => Style::default().fg(Color::Red),
+        BudgetLevel::Exceeded => Style::default()
+            .fg(Color::Red)
+            .add_modifier(Modifier::BOLD | Modifier::REVERSED),
+    }
+}
+
+/// One-line metrics bar for compact mode
+fn draw_compact_metrics_bar(f: &mut Frame, app: &App, area: Rect) {
+    let total_tokens = app.token_metrics.input_tokens + app.token_metrics.output_tokens;
+    let mut spans = vec![
+        Span::styled(" Tok ", Style::default().fg(Color::DarkGray)),
+        Span::styled(
+            format!("{:.1}K", total_tokens as f64 / 1000.0),
+            Style::default().fg(Color::LightBlue),
+        ),
+        Span::styled(
+            format!(" {:.0}%", app.cache_reuse_rate()),
+            Style::default().fg(Color::Magenta),
+        ),
+    ];
+
+    let cost = app.shown_cost();
+    if cost > 0.0 {
+        let prefix = if app.estimated_cost { "~" } else { "" };
+        spans.push(Span::raw(" "));
+        spans.push(Span::styled(
+            format!("{}{}", prefix, format_usd(cost)),
+            Style::default().fg(Color::Yellow),
+        ));
+    }
+
+    if let Some(status) = budget::most_severe(&app.budget_statuses) {
+        spans.push(Span::styled(
+            format!(" {:.0}%", status.percent()),
+            budget_style(status.level),
+        ));
+    }
+
+    // Capacity left in the usage window
+    if let Some(status) = quota::most_severe(&app.quota_statuses)
+        && let Some(pct) = status.percent()
+    {
+        spans.push(Span::styled(
+            format!(" W{:.0}%", (100.0 - pct).max(0.0)),
+            budget_style(status.level),
+        ));
+    }
+
+    spans.push(Span::styled(
+        " │ API ",
+        Style::default().fg(Color::DarkGray),
+    ));
+    spans.push(Span::styled(
+        app.api_metrics.total_calls.to_string(),
+        Style::default().fg(Color::Cyan),
+    ));
+    if app.api_metrics.total_errors > 0 {
+        spans.push(Span::styled(
+            format!("/{}err", app.api_metrics.total_errors),
+            Style::default().fg(Color::Red),
+        ));
+    }
+    spans.push(Span::styled(
+        " │ Tools ",
+        Style::default().fg(Color::DarkGray),
+    ));
+    spans.push(Span::styled(
+        app.total_tool_calls().to_string(),
+        Style::default().fg(Color::Cyan),
+    ));
+    if let Some((met, total)) = app.latency_slo_summary() {
+        spans.push(Span::styled(
+            format!(" SLO {}/{}", met, total),
+            Style::default().fg(if met == total {
+                Color::Green
+            } else {
+                Color::Red
+            }),
+        ));
+    }
+
+    f.render_widget(Paragraph::new(Line::from(spans)), area);
+}
+
+fn draw_builtin_tool_table(f: &mut Frame, app: &App, area: Rect, compact: bool) {
+    let header = tool_table_header(compact);
 
     let now = Utc::now();
     let builtin_tools = app.builtin_tools();
@@ -327,23 +592,12 @@ fn draw_builtin_tool_table(f: &mut Frame, app: &App, area: Rect) {
             let empty = bar_width - filled;
             let freq_bar = format!("{}{}", "█".repeat(filled), "░".repeat(empty));
 
-            // Currently executing indicator
-            let indicator = if tool
-                .last_call
-                .map(|l| (now - l).num_seconds() < 2)
-                .unwrap_or(false)
-            {
-                "▶ "
-            } else {
-                "  "
-            };
-
             let style = if i == app.selected_index {
                 Style::default()
                     .bg(Color::DarkGray)
                     .add_modifier(Modifier::BOLD)
             } else {
-                Style::default()
+                row_flash_style(app, tool, now)
             };
 
             // Error count style (red if > 0)
@@ -364,76 +618,81 @@ fn draw_builtin_tool_table(f: &mut Frame, app: &App, area: Rect) {
                 Style::default().fg(Color::Red)
             };
 
-            Row::new(vec![
-                Cell::from(format!("{}{}", indicator, tool.tool_name)),
-                Cell::from(tool.call_count.to_string()),
-                Cell::from(tool.error_count.to_string()).style(error_style),
-                Cell::from(apr_str).style(apr_style),
-                Cell::from(avg_str),
-                Cell::from(range_str),
-                Cell::from(last_str),
-                Cell::from(freq_bar).style(Style::default().fg(Color::Cyan)),
-            ])
+            Row::new(visible_columns(
+                vec![
+                    tool_name_cell(app, tool, tool.tool_name.clone(), now),
+                    calls_cell(app, tool),
+                    error_cell(app, tool, error_style),
+                    Cell::from(apr_str).style(apr_style),
+                    Cell::from(avg_str).style(slo_style(app, tool)),
+                    Cell::from(range_str),
+                    Cell::from(last_str),
+                    Cell::from(freq_bar).style(Style::default().fg(Color::Cyan)),
+                    Cell::from(
+                        app.tool_trend(&tool.tool_name)
+                            .map(braille_sparkline)
+                            .unwrap_or_default(),
+                    )
+                    .style(Style::default().fg(Color::LightBlue)),
+                ],
+                compact,
+            ))
             .style(style)
         })
         .collect();
 
-    let table = Table::new(
-        rows,
-        [
-            Constraint::Min(14),    // TOOL
-            Constraint::Length(6),  // CALLS
-            Constraint::Length(4),  // ERR
-            Constraint::Length(5),  // APR%
-            Constraint::Length(7),  // AVG
-            Constraint::Length(12), // RANGE
-            Constraint::Length(5),  // LAST
-            Constraint::Length(10), // FREQ
-        ],
-    )
-    .header(header)
-    .block(
-        Block::default()
-            .borders(Borders::ALL)
-            .title(" Tools ")
-            .border_style(Style::default().fg(Color::Cyan)),
-    )
-    .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let table = Table::new(rows, tool_table_widths(compact, app.comparison.is_some()))
+        .header(header)
+        .footer(tool_totals_row(&builtin_tools, now, compact))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(" Tools ")
+                .border_style(Style::default().fg(Color::Cyan)),
+        )
+        .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED));
 
     let mut state = TableState::default();
-    state.select(Some(app.selected_index));
+    state.select(Some(app.selected_index).filter(|i| *i < builtin_tools.len()));
 
     f.render_stateful_widget(table, area, &mut state);
 }
 
-fn draw_mcp_table(f: &mut Frame, app: &App, area: Rect) {
+fn draw_mcp_table(f: &mut Frame, app: &App, area: Rect, compact: bool) {
     let mcp_tools = app.mcp_tools();
 
     if mcp_tools.is_empty() {
         return;
     }
 
-    let header_cells = [
-        "TOOL", "CALLS", "ERR", "APR%", "AVG", "RANGE", "LAST", "FREQ",
-    ]
-    .iter()
-    .map(|h| {
-        Cell::from(*h).style(
-            Style::default()
-                .fg(Color::Yellow)
-                .add_modifier(Modifier::BOLD),
-        )
-    });
-    let header = Row::new(header_cells).height(1);
+    // Server health strip above the table, unless it would crowd out the rows
+    let area = if !compact && !app.mcp_servers.is_empty() && area.height >= MCP_STRIP_MIN_HEIGHT {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(3), Constraint::Min(0)])
+            .split(area);
+        draw_mcp_server_strip(f, app, chunks[0]);
+        chunks[1]
+    } else {
+        area
+    };
+
+    let header = tool_table_header(compact);
 
     let now = Utc::now();
 
     // Calculate max calls from MCP tools only for the frequency bar
     let max_calls = mcp_tools.iter().map(|t| t.call_count).max().unwrap_or(1);
 
+    // MCP rows follow the built-in rows in the shared selection order
+    let selected = app
+        .selected_index
+        .checked_sub(app.tool_metrics.len() - mcp_tools.len());
+
     let rows: Vec<Row> = mcp_tools
         .iter()
-        .map(|tool| {
+        .enumerate()
+        .map(|(i, tool)| {
             // Calculate time since last call
             let last_str = match tool.last_call {
                 Some(last) => {
@@ -481,17 +740,6 @@ fn draw_mcp_table(f: &mut Frame, app: &App, area: Rect) {
             let empty = bar_width - filled;
             let freq_bar = format!("{}{}", "█".repeat(filled), "░".repeat(empty));
 
-            // Currently executing indicator
-            let indicator = if tool
-                .last_call
-                .map(|l| (now - l).num_seconds() < 2)
-                .unwrap_or(false)
-            {
-                "▶ "
-            } else {
-                "  "
-            };
-
             // Error count style (red if > 0)
             let error_style = if tool.error_count > 0 {
                 Style::default().fg(Color::Red)
@@ -511,96 +759,2275 @@ fn draw_mcp_table(f: &mut Frame, app: &App, area: Rect) {
             };
 
             // Use display_name() for MCP tools to show "server:tool" format
-            Row::new(vec![
-                Cell::from(format!("{}{}", indicator, tool.display_name())),
-                Cell::from(tool.call_count.to_string()),
-                Cell::from(tool.error_count.to_string()).style(error_style),
-                Cell::from(apr_str).style(apr_style),
-                Cell::from(avg_str),
-                Cell::from(range_str),
-                Cell::from(last_str),
-                Cell::from(freq_bar).style(Style::default().fg(Color::Magenta)),
-            ])
+            Row::new(visible_columns(
+                vec![
+                    tool_name_cell(app, tool, tool.display_name(), now),
+                    calls_cell(app, tool),
+                    error_cell(app, tool, error_style),
+                    Cell::from(apr_str).style(apr_style),
+                    Cell::from(avg_str).style(slo_style(app, tool)),
+                    Cell::from(range_str),
+                    Cell::from(last_str),
+                    Cell::from(freq_bar).style(Style::default().fg(Color::Magenta)),
+                    Cell::from(
+                        app.tool_trend(&tool.tool_name)
+                            .map(braille_sparkline)
+                            .unwrap_or_default(),
+                    )
+                    .style(Style::default().fg(Color::LightBlue)),
+                ],
+                compact,
+            ))
+            .style(if Some(i) == selected {
+                Style::default()
+                    .bg(Color::DarkGray)
+                    .add_modifier(Modifier::BOLD)
+            } else {
+                row_flash_style(app, tool, now)
+            })
         })
         .collect();
 
-    let table = Table::new(
-        rows,
-        [
-            Constraint::Min(14),    // TOOL
-            Constraint::Length(6),  // CALLS
-            Constraint::Length(4),  // ERR
-            Constraint::Length(5),  // APR%
-            Constraint::Length(7),  // AVG
-            Constraint::Length(12), // RANGE
-            Constraint::Length(5),  // LAST
-            Constraint::Length(10), // FREQ
+    let table = Table::new(rows, tool_table_widths(compact, app.comparison.is_some()))
+        .header(header)
+        .footer(tool_totals_row(&mcp_tools, now, compact))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(" MCP Tools ")
+                .border_style(Style::default().fg(Color::Magenta)),
+        )
+        .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+
+    let mut state = TableState::default();
+    state.select(selected);
+
+    f.render_stateful_widget(table, area, &mut state);
+}
+
+/// Placeholder while the database is opened in the background
+fn draw_loading(f: &mut Frame, app: &App, area: Rect) {
+    let dim = Style::default().fg(Color::DarkGray);
+    let lines = match &app.storage_status.error {
+        Some(error) => vec![
+            Line::from(Span::styled(
+                " Could not open the database; retrying...",
+                dim,
+            )),
+            Line::from(Span::styled(
+                format!(" {}", error),
+                Style::default().fg(Color::Red),
+            )),
         ],
-    )
-    .header(header)
-    .block(
+        None => vec![Line::from(Span::styled(" Opening the database...", dim))],
+    };
+    let paragraph = Paragraph::new(lines).wrap(Wrap { trim: false }).block(
         Block::default()
             .borders(Borders::ALL)
-            .title(" MCP Tools ")
-            .border_style(Style::default().fg(Color::Magenta)),
+            .border_style(Style::default().fg(Color::Cyan)),
     );
-
-    f.render_widget(table, area);
-}
-
-fn draw_footer(f: &mut Frame, area: Rect) {
-    let footer = Line::from(vec![Span::styled(
-        " [q]uit [s]ort [p]ause [d]etail [t]ime [r]eset [a]gent",
-        Style::default().fg(Color::DarkGray),
-    )]);
-
-    let paragraph = Paragraph::new(footer);
     f.render_widget(paragraph, area);
 }
 
-fn draw_detail_popup(f: &mut Frame, app: &App) {
-    let Some(tool) = app.selected_tool() else {
-        return;
-    };
-
-    let area = centered_rect(60, 60, f.area());
-
-    // Clear the area
-    f.render_widget(Clear, area);
-
-    let success_rate = if tool.call_count > 0 {
-        (tool.success_count as f64 / tool.call_count as f64) * 100.0
-    } else {
-        100.0
-    };
-
-    // Format duration range
-    let format_duration = |ms: f64| -> String {
-        if ms < 1000.0 {
-            format!("{:.0}ms", ms)
-        } else {
-            format!("{:.1}s", ms / 1000.0)
-        }
+/// First-run panel: where telemetry goes, which agents send it, and a live waiting indicator
+fn draw_onboarding(f: &mut Frame, app: &App, area: Rect) {
+    let dim = Style::default().fg(Color::DarkGray);
+    let endpoint = match &app.receiver_status.snapshot.listen_addr {
+        Some(addr) => Span::styled(
+            format!("http://{}", addr),
+            Style::default().fg(Color::Green),
+        ),
+        None if app.view_only => Span::styled(
+            "the other agenttop's receiver (view-only)",
+            Style::default().fg(Color::Cyan),
+        ),
+        None => Span::styled("starting receiver...", Style::default().fg(Color::Yellow)),
     };
 
-    // Use display_name() for MCP tools to show "server:tool" format
-    let display_name = tool.display_name();
-    let mut content = vec![
-        Line::from(vec![
-            Span::styled("Tool: ", Style::default().add_modifier(Modifier::BOLD)),
-            Span::raw(&display_name),
-        ]),
+    let mut lines = vec![
+        Line::from(Span::styled(
+            "Welcome to agenttop",
+            Style::default().add_modifier(Modifier::BOLD),
+        )),
         Line::from(""),
         Line::from(vec![
-            Span::raw("Total Calls: "),
-            Span::styled(
-                tool.call_count.to_string(),
-                Style::default().fg(Color::Cyan),
-            ),
+            Span::raw("Listening for OTLP telemetry on "),
+            endpoint,
         ]),
-        Line::from(vec![
-            Span::raw("Successful: "),
-            Span::styled(
+        Line::from(""),
+        Line::from(Span::styled(
+            "Agent telemetry setup:",
+            Style::default().fg(Color::Yellow),
+        )),
+    ];
+
+    let name_width = app
+        .provider_setup
+        .iter()
+        .map(|p| p.name.len())
+        .max()
+        .unwrap_or(0);
+    for (i, setup) in app.provider_setup.iter().enumerate() {
+        let selected = i == app.onboarding_index;
+        let marker = if selected { "› " } else { "  " };
+        let name_style = if selected {
+            Style::default().add_modifier(Modifier::BOLD)
+        } else {
+            Style::default()
+        };
+        let status = match (setup.configured, setup.supports_setup) {
+            (Some(true), _) => Span::styled("✓ configured", Style::default().fg(Color::Green)),
+            (_, true) => Span::styled(
+                "✗ not configured - press [i] to set up",
+                Style::default().fg(Color::Red),
+            ),
+            (_, false) => Span::styled(
+                format!(
+                    "✗ manual setup: {}",
+                    setup
+                        .settings_path
+                        .as_ref()
+                        .map(|p| p.display().to_string())
+                        .unwrap_or_default()
+                ),
+                Style::default().fg(Color::Yellow),
+            ),
+        };
+        lines.push(Line::from(vec![
+            Span::raw(marker),
+            Span::styled(format!("{:<name_width$}  ", setup.name), name_style),
+            status,
+        ]));
+    }
+
+    let frame = (Utc::now().timestamp_millis() / 100).rem_euclid(SPINNER_FRAMES.len() as i64);
+    lines.push(Line::from(""));
+    lines.push(Line::from(vec![
+        Span::styled(
+            format!("{} ", SPINNER_FRAMES[frame as usize]),
+            Style::default().fg(Color::Cyan),
+        ),
+        Span::raw("Waiting for first event... start an agent session to see data here"),
+    ]));
+    lines.push(Line::from(""));
+    lines.push(Line::from(Span::styled(
+        "[j/k] select agent  [i] set up selected agent (restart it afterwards)",
+        dim,
+    )));
+
+    let panel = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" Getting Started ")
+            .border_style(Style::default().fg(Color::Cyan)),
+    );
+    f.render_widget(panel, area);
+}
+
+/// MCP panel height needed to fit the server strip and a few table rows
+const MCP_STRIP_MIN_HEIGHT: u16 = 10;
+
+/// Server error rate (%) at which the strip highlights a server
+const MCP_SERVER_ERROR_RATE_WARN: f64 = 10.0;
+
+/// One line of per-server health: calls, error rate, p95 latency and last seen
+fn draw_mcp_server_strip(f: &mut Frame, app: &App, area: Rect) {
+    let now = Utc::now();
+    let mut spans = Vec::new();
+    for server in &app.mcp_servers {
+        if !spans.is_empty() {
+            spans.push(Span::styled(" │ ", Style::default().fg(Color::DarkGray)));
+        }
+        let error_rate = server.error_rate();
+        let (name_style, error_style) = if error_rate >= MCP_SERVER_ERROR_RATE_WARN {
+            let red = Style::default().fg(Color::Red);
+            (red.add_modifier(Modifier::BOLD), red)
+        } else if server.error_count > 0 {
+            (Style::default(), Style::default().fg(Color::Yellow))
+        } else {
+            (Style::default(), Style::default().fg(Color::Green))
+        };
+        let p95 = if server.p95_duration_ms < 1000 {
+            format!("{}ms", server.p95_duration_ms)
+        } else {
+            format!("{:.1}s", server.p95_duration_ms as f64 / 1000.0)
+        };
+        let last_seen = match server.last_seen.map(|t| (now - t).num_seconds()) {
+            Some(secs) if secs < 0 => "-".to_string(),
+            Some(secs) if secs < 60 => format!("{}s", secs),
+            Some(secs) if secs < 3600 => format!("{}m", secs / 60),
+            Some(secs) if secs < 86400 => format!("{}h", secs / 3600),
+            Some(secs) => format!("{}d", secs / 86400),
+            None => "-".to_string(),
+        };
+        spans.push(Span::styled(server.server_name.clone(), name_style));
+        spans.push(Span::raw(format!(" {} calls ", server.call_count)));
+        spans.push(Span::styled(format!("{:.0}% err", error_rate), error_style));
+        spans.push(Span::styled(
+            format!(" p95 {} last {}", p95, last_seen),
+            Style::default().fg(Color::DarkGray),
+        ));
+    }
+
+    let strip = Paragraph::new(Line::from(spans)).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" MCP Servers ")
+            .border_style(Style::default().fg(Color::Magenta)),
+    );
+    f.render_widget(strip, area);
+}
+
+/// Totals across a table: summed calls and errors, call-weighted averages, overall range
+fn tool_totals_row(tools: &[&ToolMetrics], now: DateTime<Utc>, compact: bool) -> Row<'static> {
+    let calls: u64 = tools.iter().map(|t| t.call_count).sum();
+    let errors: u64 = tools.iter().map(|t| t.error_count).sum();
+    let approved: u64 = tools.iter().map(|t| t.approved_count).sum();
+    let rejected: u64 = tools.iter().map(|t| t.rejected_count).sum();
+    let weighted_avg = if calls > 0 {
+        tools
+            .iter()
+            .map(|t| t.avg_duration_ms * t.call_count as f64)
+            .sum::<f64>()
+            / calls as f64
+    } else {
+        0.0
+    };
+    let min = tools
+        .iter()
+        .map(|t| t.min_duration_ms)
+        .fold(f64::INFINITY, f64::min);
+    let max = tools.iter().map(|t| t.max_duration_ms).fold(0.0, f64::max);
+    let last_call = tools.iter().filter_map(|t| t.last_call).max();
+
+    let format_duration = |ms: f64| -> String {
+        if ms < 1000.0 {
+            format!("{}ms", ms as u64)
+        } else {
+            format!("{:.1}s", ms / 1000.0)
+        }
+    };
+    let apr_str = if approved + rejected > 0 {
+        format!(
+            "{:.0}%",
+            approved as f64 / (approved + rejected) as f64 * 100.0
+        )
+    } else {
+        "100%".to_string()
+    };
+    let range_str = if tools.is_empty() {
+        "-".to_string()
+    } else {
+        format!("{}-{}", format_duration(min), format_duration(max))
+    };
+    let last_str = match last_call.map(|last| (now - last).num_seconds()) {
+        Some(secs) if secs < 0 => "-".to_string(),
+        Some(secs) if secs < 60 => format!("{}s", secs),
+        Some(secs) if secs < 3600 => format!("{}m", secs / 60),
+        Some(secs) if secs < 86400 => format!("{}h", secs / 3600),
+        Some(secs) => format!("{}d", secs / 86400),
+        None => "-".to_string(),
+    };
+    let error_style = if errors > 0 {
+        Style::default().fg(Color::Red)
+    } else {
+        Style::default().fg(Color::Green)
+    };
+
+    Row::new(visible_columns(
+        vec![
+            Cell::from("  TOTAL"),
+            Cell::from(calls.to_string()),
+            Cell::from(errors.to_string()).style(error_style),
+            Cell::from(apr_str),
+            Cell::from(format_duration(weighted_avg)),
+            Cell::from(range_str),
+            Cell::from(last_str),
+            Cell::from(""),
+            Cell::from(""),
+        ],
+        compact,
+    ))
+    .style(Style::default().add_modifier(Modifier::BOLD))
+}
+
+/// Tool table columns, with the compact header for each (None = hidden in compact mode)
+const TOOL_COLUMNS: [(&str, Option<&str>); 9] = [
+    ("TOOL", Some("TOOL")),
+    ("CALLS", Some("N")),
+    ("ERR", Some("ERR")),
+    ("APR%", Some("AP%")),
+    ("AVG", Some("AVG")),
+    ("RANGE", None),
+    ("LAST", Some("LST")),
+    ("FREQ", None),
+    ("TREND", None),
+];
+
+fn tool_table_header(compact: bool) -> Row<'static> {
+    let header_cells = TOOL_COLUMNS
+        .iter()
+        .filter_map(|(full, short)| if compact { *short } else { Some(*full) })
+        .map(|h| {
+            Cell::from(h).style(
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD),
+            )
+        });
+    Row::new(header_cells).height(1)
+}
+
+/// Column widths; CALLS and ERR widen to fit deltas in comparison mode
+fn tool_table_widths(compact: bool, compare: bool) -> Vec<Constraint> {
+    let widths = [
+        Constraint::Min(14),                              // TOOL
+        Constraint::Length(if compare { 10 } else { 6 }), // CALLS
+        Constraint::Length(if compare { 10 } else { 5 }), // ERR
+        Constraint::Length(5),                            // APR%
+        Constraint::Length(7),                            // AVG
+        Constraint::Length(12),                           // RANGE
+        Constraint::Length(5),                            // LAST
+        Constraint::Length(10),                           // FREQ
+        Constraint::Length(10),                           // TREND
+    ];
+    visible_columns(widths.to_vec(), compact)
+}
+
+/// Drop the columns hidden in compact mode (cells are in TOOL_COLUMNS order)
+fn visible_columns<T>(cells: Vec<T>, compact: bool) -> Vec<T> {
+    cells
+        .into_iter()
+        .zip(TOOL_COLUMNS.iter())
+        .filter(|(_, (_, short))| !compact || short.is_some())
+        .map(|(cell, _)| cell)
+        .collect()
+}
+
+/// AVG column color: green within the tool's latency SLO, red over it, plain without one
+fn slo_style(app: &App, tool: &ToolMetrics) -> Style {
+    match app.meets_latency_slo(tool) {
+        Some(true) => Style::default().fg(Color::Green),
+        Some(false) => Style::default().fg(Color::Red),
+        None => Style::default(),
+    }
+}
+
+/// Error count followed by a ▲/▼ arrow when the success rate moved since the previous window
+fn error_cell(app: &App, tool: &ToolMetrics, error_style: Style) -> Cell<'static> {
+    let mut spans = vec![Span::styled(tool.error_count.to_string(), error_style)];
+    match app.success_trend(tool) {
+        Some(SuccessTrend::Improved) => {
+            spans.push(Span::styled("▲", Style::default().fg(Color::Green)))
+        }
+        Some(SuccessTrend::Regressed) => {
+            spans.push(Span::styled("▼", Style::default().fg(Color::Red)))
+        }
+        None => {}
+    }
+    if let Some(previous) = app
+        .comparison
+        .as_ref()
+        .and_then(|c| c.tools.get(&tool.tool_name))
+        .filter(|t| t.call_count > 0 && tool.call_count > 0)
+    {
+        let rate = |t: &ToolMetrics| t.error_count as f64 / t.call_count as f64 * 100.0;
+        let delta = rate(tool) - rate(previous);
+        spans.push(Span::styled(
+            format!(" {:+.0}pp", delta),
+            Style::default().fg(delta_color(delta)),
+        ));
+    }
+    Cell::from(Line::from(spans))
+}
+
+/// Call count, followed by the change since the previous window in comparison mode
+fn calls_cell(app: &App, tool: &ToolMetrics) -> Cell<'static> {
+    let mut spans = vec![Span::raw(tool.call_count.to_string())];
+    if let Some(comparison) = &app.comparison {
+        let previous = comparison
+            .tools
+            .get(&tool.tool_name)
+            .map_or(0, |t| t.call_count);
+        spans.push(Span::styled(
+            format!(" {:+}", tool.call_count as i64 - previous as i64),
+            Style::default().fg(Color::DarkGray),
+        ));
+    }
+    Cell::from(Line::from(spans))
+}
+
+/// Red for increases and green for decreases of a "lower is better" metric
+fn delta_color(delta: f64) -> Color {
+    if delta > 0.0 {
+        Color::Red
+    } else if delta < 0.0 {
+        Color::Green
+    } else {
+        Color::DarkGray
+    }
+}
+
+/// Third metrics line in comparison mode: current vs previous window
+fn comparison_line(app: &App) -> Line<'static> {
+    let mut spans = vec![Span::raw(" vs prev ")];
+    let Some(comparison) = &app.comparison else {
+        spans.push(Span::styled(
+            format!(
+                "no previous window for {} (press t to pick a bounded filter)",
+                app.time_filter.label()
+            ),
+            Style::default().fg(Color::DarkGray),
+        ));
+        return Line::from(spans);
+    };
+
+    let percent = |current: f64, previous: f64| {
+        if previous > 0.0 {
+            format!(" ({:+.0}%)", (current - previous) / previous * 100.0)
+        } else {
+            String::new()
+        }
+    };
+
+    let calls = app.total_tool_calls();
+    spans.push(Span::styled(
+        "Calls: ",
+        Style::default().fg(Color::DarkGray),
+    ));
+    spans.push(Span::styled(
+        format!(
+            "{} → {}{}",
+            comparison.tool_calls,
+            calls,
+            percent(calls as f64, comparison.tool_calls as f64)
+        ),
+        Style::default().fg(Color::Cyan),
+    ));
+
+    let cost = app.token_metrics.total_cost_usd;
+    spans.push(Span::raw("  "));
+    spans.push(Span::styled("Cost: ", Style::default().fg(Color::DarkGray)));
+    spans.push(Span::styled(
+        format!(
+            "{} → {}{}",
+            format_usd(comparison.cost_usd),
+            format_usd(cost),
+            percent(cost, comparison.cost_usd)
+        ),
+        Style::default().fg(Color::Yellow),
+    ));
+
+    let error_rate = app.tool_error_rate();
+    let delta = error_rate - comparison.error_rate();
+    spans.push(Span::raw("  "));
+    spans.push(Span::styled(
+        "Err rate: ",
+        Style::default().fg(Color::DarkGray),
+    ));
+    spans.push(Span::raw(format!(
+        "{:.1}% → {:.1}% ",
+        comparison.error_rate(),
+        error_rate
+    )));
+    spans.push(Span::styled(
+        format!("({:+.1}pp)", delta),
+        Style::default().fg(delta_color(delta)),
+    ));
+
+    Line::from(spans)
+}
+
+/// Spinner frames for tools that are still executing
+const SPINNER_FRAMES: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+/// Tool name with an activity indicator: a spinner and elapsed time while the tool
+/// is executing, ▶ if it completed within the last 2s
+fn tool_name_cell(
+    app: &App,
+    tool: &ToolMetrics,
+    name: String,
+    now: DateTime<Utc>,
+) -> Cell<'static> {
+    if let Some(started_at) = app.in_flight_since(&tool.tool_name) {
+        let frame = (now.timestamp_millis() / 100).rem_euclid(SPINNER_FRAMES.len() as i64);
+        let secs = (now - started_at).num_seconds().max(0);
+        let elapsed = if secs < 60 {
+            format!("{}s", secs)
+        } else {
+            format!("{}m{:02}s", secs / 60, secs % 60)
+        };
+        return Cell::from(Line::from(vec![
+            Span::styled(
+                format!("{} ", SPINNER_FRAMES[frame as usize]),
+                Style::default().fg(Color::Yellow),
+            ),
+            Span::raw(name),
+            Span::styled(format!(" {}", elapsed), Style::default().fg(Color::Yellow)),
+        ]));
+    }
+
+    let indicator = if app.is_pinned(&tool.tool_name) {
+        "⚑ "
+    } else if tool
+        .last_call
+        .map(|l| (now - l).num_seconds() < 2)
+        .unwrap_or(false)
+    {
+        "▶ "
+    } else {
+        "  "
+    };
+    Cell::from(format!("{}{}", indicator, name))
+}
+
+/// Render values as a braille sparkline, two values per character and four
+/// dot rows of height, scaled to the series maximum (like htop's history graphs)
+pub fn braille_sparkline(values: &[u64]) -> String {
+    // Dot bits for each column, filled bottom-up
+    const LEFT: [u32; 4] = [0x40, 0x04, 0x02, 0x01];
+    const RIGHT: [u32; 4] = [0x80, 0x20, 0x10, 0x08];
+
+    let max = values.iter().copied().max().unwrap_or(0);
+    let height = |v: u64| -> usize {
+        if v == 0 || max == 0 {
+            0
+        } else {
+            ((v * 4).div_ceil(max) as usize).clamp(1, 4)
+        }
+    };
+
+    values
+        .chunks(2)
+        .map(|pair| {
+            let left = height(pair[0]);
+            let right = pair.get(1).map_or(0, |v| height(*v));
+            let bits = LEFT[..left].iter().sum::<u32>() + RIGHT[..right].iter().sum::<u32>();
+            char::from_u32(0x2800 + bits).unwrap_or(' ')
+        })
+        .collect()
+}
+
+/// Wide layout: built-in tools on the left, MCP tools and the API panel stacked on the right
+fn draw_wide_body(f: &mut Frame, app: &App, area: Rect, has_mcp_tools: bool) {
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)])
+        .split(area);
+
+    draw_builtin_tool_table(f, app, columns[0], false);
+
+    if has_mcp_tools {
+        let right = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)])
+            .split(columns[1]);
+        draw_mcp_table(f, app, right[0], false);
+        draw_api_panel(f, app, right[1]);
+    } else {
+        draw_api_panel(f, app, columns[1]);
+    }
+}
+
+/// Per-model API breakdown (wide layout only)
+fn draw_api_panel(f: &mut Frame, app: &App, area: Rect) {
+    let header_cells = ["MODEL", "CALLS", "ERR", "SHARE"].iter().map(|h| {
+        Cell::from(*h).style(
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        )
+    });
+    let header = Row::new(header_cells).height(1);
+
+    let api = &app.api_metrics;
+    let mut models: Vec<_> = api.models.iter().collect();
+    models.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+
+    let rows: Vec<Row> = models
+        .iter()
+        .map(|(name, calls)| {
+            let errors = api.errors_by_model.get(*name).copied().unwrap_or(0);
+            let share = if api.total_calls > 0 {
+                **calls as f64 / api.total_calls as f64 * 100.0
+            } else {
+                0.0
+            };
+            let error_style = if errors > 0 {
+                Style::default().fg(Color::Red)
+            } else {
+                Style::default().fg(Color::Green)
+            };
+            Row::new(vec![
+                Cell::from(PROVIDER_REGISTRY.shorten_model_name(name)),
+                Cell::from(calls.to_string()),
+                Cell::from(errors.to_string()).style(error_style),
+                Cell::from(format!("{:.0}%", share)),
+            ])
+        })
+        .collect();
+
+    let title = format!(
+        " API  {} calls · avg {} ",
+        api.total_calls,
+        app.format_api_latency()
+    );
+
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Min(16),   // MODEL
+            Constraint::Length(6), // CALLS
+            Constraint::Length(4), // ERR
+            Constraint::Length(6), // SHARE
+        ],
+    )
+    .header(header)
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(title)
+            .border_style(Style::default().fg(Color::Blue)),
+    );
+
+    f.render_widget(table, area);
+}
+
+/// Width of the row labels in the timeline view
+const TIMELINE_LABEL_WIDTH: usize = 8;
+/// Number of slowest calls listed under the timeline
+const TIMELINE_SLOWEST_CALLS: usize = 5;
+
+/// Horizontal timeline of the current session: tool calls drawn over their duration,
+/// API requests and errors as marks, followed by the slowest calls
+fn draw_timeline(f: &mut Frame, app: &App, area: Rect) {
+    let timeline = &app.session_timeline;
+    let title = match &timeline.session_id {
+        Some(id) => format!(
+            " Session Timeline ({}) ",
+            id.chars().take(8).collect::<String>()
+        ),
+        None => " Timeline (last hour) ".to_string(),
+    };
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(title)
+        .border_style(Style::default().fg(Color::Cyan));
+
+    let inner_width = area.width.saturating_sub(2) as usize;
+    let track_width = inner_width.saturating_sub(TIMELINE_LABEL_WIDTH);
+
+    let (Some(first), Some(last)) = (timeline.events.first(), timeline.events.last()) else {
+        let paragraph = Paragraph::new(Line::from(Span::styled(
+            " No tool calls or API requests yet",
+            Style::default().fg(Color::DarkGray),
+        )))
+        .block(block);
+        f.render_widget(paragraph, area);
+        return;
+    };
+
+    let call_start = |e: &crate::storage::TimelineEvent| {
+        e.timestamp - chrono::Duration::milliseconds(e.duration_ms as i64)
+    };
+    let start = timeline
+        .events
+        .iter()
+        .map(call_start)
+        .min()
+        .unwrap_or(first.timestamp);
+    let end = last.timestamp;
+    let span_ms = (end - start).num_milliseconds().max(1) as f64;
+    let column = |t: DateTime<Utc>| -> usize {
+        let offset = (t - start).num_milliseconds().max(0) as f64;
+        ((offset / span_ms) * track_width.saturating_sub(1) as f64).round() as usize
+    };
+
+    let mut tools = vec![' '; track_width];
+    let mut api = vec![' '; track_width];
+    let mut errors = vec![' '; track_width];
+    if track_width > 0 {
+        for event in &timeline.events {
+            let end_col = column(event.timestamp).min(track_width - 1);
+            match event.kind {
+                TimelineKind::Tool => {
+                    let start_col = column(call_start(event)).min(end_col);
+                    for cell in &mut tools[start_col..=end_col] {
+                        *cell = '━';
+                    }
+                }
+                TimelineKind::Api => api[end_col] = '│',
+                TimelineKind::Error => errors[end_col] = '×',
+            }
+        }
+    }
+
+    let track_line = |label: &str, cells: Vec<char>, color: Color| {
+        Line::from(vec![
+            Span::styled(
+                format!("{:<width$}", label, width = TIMELINE_LABEL_WIDTH),
+                Style::default().fg(Color::DarkGray),
+            ),
+            Span::styled(
+                cells.into_iter().collect::<String>(),
+                Style::default().fg(color),
+            ),
+        ])
+    };
+
+    // Time axis: start, middle and end labels
+    let fmt = |t: DateTime<Utc>| {
+        t.with_timezone(&chrono::Local)
+            .format("%H:%M:%S")
+            .to_string()
+    };
+    let start_label = fmt(start);
+    let mid_label = fmt(start + (end - start) / 2);
+    let end_label = fmt(end);
+    let mut axis = vec![' '; track_width];
+    for (col, label) in [
+        (0, &start_label),
+        (
+            (track_width / 2).saturating_sub(mid_label.len() / 2),
+            &mid_label,
+        ),
+        (track_width.saturating_sub(end_label.len()), &end_label),
+    ] {
+        for (i, c) in label.chars().enumerate() {
+            if let Some(cell) = axis.get_mut(col + i) {
+                *cell = c;
+            }
+        }
+    }
+
+    let mut lines = vec![
+        track_line("Tools", tools, Color::Cyan),
+        track_line("API", api, Color::Blue),
+        track_line("Errors", errors, Color::Red),
+        Line::from(vec![
+            Span::raw(" ".repeat(TIMELINE_LABEL_WIDTH)),
+            Span::styled(
+                axis.into_iter().collect::<String>(),
+                Style::default().fg(Color::DarkGray),
+            ),
+        ]),
+        Line::from(""),
+        Line::from(Span::styled(
+            "Slowest calls",
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        )),
+    ];
+
+    let mut slowest: Vec<_> = timeline
+        .events
+        .iter()
+        .filter(|e| e.kind != TimelineKind::Api && e.duration_ms > 0)
+        .collect();
+    slowest.sort_by_key(|e| std::cmp::Reverse(e.duration_ms));
+    for event in slowest.into_iter().take(TIMELINE_SLOWEST_CALLS) {
+        let secs = event.duration_ms / 1000;
+        let duration = if secs >= 60 {
+            format!("{}m{:02}s", secs / 60, secs % 60)
+        } else {
+            format!("{:.1}s", event.duration_ms as f64 / 1000.0)
+        };
+        lines.push(Line::from(vec![
+            Span::raw(format!("  {:<24}", get_tool_display_name(&event.name))),
+            Span::styled(
+                format!("{:>8}", duration),
+                Style::default().fg(Color::Yellow),
+            ),
+            Span::styled(
+                format!("  {} → {}", fmt(call_start(event)), fmt(event.timestamp)),
+                Style::default().fg(Color::DarkGray),
+            ),
+        ]));
+    }
+
+    f.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+/// Recent user prompts, newest first. Text is only available when the agent
+/// logs prompt contents (e.g. OTEL_LOG_USER_PROMPTS=1 for Claude Code).
+fn draw_prompt_history(f: &mut Frame, app: &App, area: Rect) {
+    let header_cells = ["TIME", "LEN", "PROMPT"].iter().map(|h| {
+        Cell::from(*h).style(
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        )
+    });
+    let header = Row::new(header_cells).height(1);
+
+    // TIME and LEN columns, spacing and borders
+    let text_width = (area.width as usize).saturating_sub(8 + 7 + 2 + 2);
+    let rows: Vec<Row> = app
+        .prompts
+        .iter()
+        .map(|prompt| {
+            let text = match &prompt.text {
+                Some(text) => Cell::from(truncate_prompt(text, text_width)),
+                None => Cell::from("(prompt text not logged)")
+                    .style(Style::default().fg(Color::DarkGray)),
+            };
+            Row::new(vec![
+                Cell::from(
+                    prompt
+                        .timestamp
+                        .with_timezone(&chrono::Local)
+                        .format("%H:%M:%S")
+                        .to_string(),
+                ),
+                Cell::from(
+                    prompt
+                        .length
+                        .map(|l| l.to_string())
+                        .unwrap_or_else(|| "-".to_string()),
+                ),
+                text,
+            ])
+        })
+        .collect();
+
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(8), // TIME
+            Constraint::Length(7), // LEN
+            Constraint::Min(10),   // PROMPT
+        ],
+    )
+    .header(header)
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!(" Prompts ({}) ", app.prompts.len()))
+            .border_style(Style::default().fg(Color::Cyan)),
+    );
+
+    f.render_widget(table, area);
+}
+
+/// Recent sessions, newest first, with their efficiency scores and the average score
+/// in the title
+fn draw_sessions(f: &mut Frame, app: &App, area: Rect) {
+    let header_cells = [
+        "STARTED", "SESSION", "AGENT", "DURATION", "ACTIVE", "TOOLS", "API", "CTX", "COST", "SCORE",
+    ]
+    .iter()
+    .map(|h| {
+        Cell::from(*h).style(
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        )
+    });
+    let header = Row::new(header_cells).height(1);
+
+    let rows: Vec<Row> = app
+        .sessions
+        .iter()
+        .map(|session| {
+            let agent = session
+                .provider
+                .as_deref()
+                .and_then(|id| PROVIDER_REGISTRY.get(id))
+                .map(|provider| provider.name())
+                .unwrap_or("-");
+            let duration = session.ended_at - session.started_at;
+            let score = match &session.efficiency {
+                Some(efficiency) => Cell::from(format!("{:.0}", efficiency.score)).style(
+                    Style::default().fg(if efficiency.score >= 70.0 {
+                        Color::Green
+                    } else if efficiency.score >= 40.0 {
+                        Color::Yellow
+                    } else {
+                        Color::Red
+                    }),
+                ),
+                None => Cell::from("-").style(Style::default().fg(Color::DarkGray)),
+            };
+            // Share of the session spent active; low means the terminal sat idle
+            let active = match session.utilization() {
+                Some(utilization) => Cell::from(format!("{:.0}%", utilization * 100.0)).style(
+                    Style::default().fg(if utilization >= 0.5 {
+                        Color::Green
+                    } else if utilization >= 0.2 {
+                        Color::Yellow
+                    } else {
+                        Color::DarkGray
+                    }),
+                ),
+                None => Cell::from("-").style(Style::default().fg(Color::DarkGray)),
+            };
+            // Average share of the context window the session's prompts filled
+            let ctx = match app.context_usage.session(&session.session_id) {
+                Some(ctx) => Cell::from(format!("{:.0}%", ctx.utilization.avg_pct)).style(
+                    if ctx.utilization.is_consistently_near() {
+                        Style::default().fg(Color::Red)
+                    } else if ctx.utilization.peak_pct >= context::NEAR_CEILING_PCT {
+                        Style::default().fg(Color::Yellow)
+                    } else {
+                        Style::default()
+                    },
+                ),
+                None => Cell::from("-").style(Style::default().fg(Color::DarkGray)),
+            };
+            Row::new(vec![
+                Cell::from(
+                    session
+                        .started_at
+                        .with_timezone(&chrono::Local)
+                        .format("%m-%d %H:%M")
+                        .to_string(),
+                ),
+                Cell::from(session.session_id.chars().take(8).collect::<String>())
+                    .style(Style::default().fg(Color::DarkGray)),
+                Cell::from(agent),
+                Cell::from(format_session_duration(duration)),
+                active,
+                Cell::from(session.tool_calls.to_string()),
+                Cell::from(session.api_calls.to_string()),
+                ctx,
+                Cell::from(format_usd(session.cost_usd)),
+                score,
+            ])
+        })
+        .collect();
+
+    let scores: Vec<f64> = app
+        .sessions
+        .iter()
+        .filter_map(|session| session.efficiency.map(|e| e.score))
+        .collect();
+    let mut title = if scores.is_empty() {
+        format!(" Sessions ({}) ", app.sessions.len())
+    } else {
+        format!(
+            " Sessions ({}) · avg score {:.0} ",
+            app.sessions.len(),
+            scores.iter().sum::<f64>() / scores.len() as f64
+        )
+    };
+    let near_ceiling = app.context_usage.sessions_near_ceiling().count();
+    if near_ceiling > 0 {
+        title.push_str(&format!("· {} near context limit ", near_ceiling));
+    }
+
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(11), // STARTED
+            Constraint::Length(8),  // SESSION
+            Constraint::Length(12), // AGENT
+            Constraint::Length(9),  // DURATION
+            Constraint::Length(6),  // ACTIVE
+            Constraint::Length(6),  // TOOLS
+            Constraint::Length(5),  // API
+            Constraint::Length(5),  // CTX
+            Constraint::Length(9),  // COST
+            Constraint::Min(5),     // SCORE
+        ],
+    )
+    .header(header)
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(title)
+            .border_style(Style::default().fg(Color::Cyan)),
+    );
+
+    f.render_widget(table, area);
+}
+
+/// Session length in the largest fitting unit, e.g. "45s", "1.5m", "2h 10m"
+fn format_session_duration(duration: chrono::Duration) -> String {
+    let secs = duration.num_seconds().max(0);
+    if secs < 60 {
+        format!("{}s", secs)
+    } else if secs < 3600 {
+        format!("{:.1}m", secs as f64 / 60.0)
+    } else {
+        format!("{}h {}m", secs / 3600, (secs % 3600) / 60)
+    }
+}
+
+/// Recent conversation turns, newest first, with the averages per turn in the title
+fn draw_turns(f: &mut Frame, app: &App, area: Rect) {
+    let header_cells = [
+        "TIME", "SESSION", "PROMPT", "API", "TOOLS", "TOKENS", "LATENCY", "COST",
+    ]
+    .iter()
+    .map(|h| {
+        Cell::from(*h).style(
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        )
+    });
+    let header = Row::new(header_cells).height(1);
+
+    let rows: Vec<Row> = app
+        .turns
+        .iter()
+        .map(|turn| {
+            let session = turn
+                .session_id
+                .as_deref()
+                .map(|id| id.chars().take(8).collect::<String>())
+                .unwrap_or_else(|| "-".to_string());
+            Row::new(vec![
+                Cell::from(
+                    turn.started_at
+                        .with_timezone(&chrono::Local)
+                        .format("%H:%M:%S")
+                        .to_string(),
+                ),
+                Cell::from(session).style(Style::default().fg(Color::DarkGray)),
+                match turn.prompt_length {
+                    Some(chars) => Cell::from(format_count(chars)),
+                    None => Cell::from("-").style(Style::default().fg(Color::DarkGray)),
+                },
+                Cell::from(turn.api_calls.to_string()),
+                Cell::from(turn.tool_calls.to_string()),
+                Cell::from(format_count(turn.tokens())),
+                Cell::from(format_duration(turn.latency_ms() as f64)),
+                Cell::from(format_usd(turn.cost_usd)),
+            ])
+        })
+        .collect();
+
+    let count = app.turns.len();
+    let title = if count == 0 {
+        " Turns (0) ".to_string()
+    } else {
+        let average = |total: u64| total as f64 / count as f64;
+        let sum = |f: fn(&crate::storage::Turn) -> u64| app.turns.iter().map(f).sum::<u64>();
+        format!(
+            " Turns ({}) · avg {:.1} tools, {} tokens, {} per turn ",
+            count,
+            average(sum(|t| t.tool_calls)),
+            format_count(average(sum(|t| t.tokens())) as u64),
+            format_duration(average(sum(|t| t.latency_ms()))),
+        )
+    };
+
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(8), // TIME
+            Constraint::Length(8), // SESSION
+            Constraint::Length(7), // PROMPT
+            Constraint::Length(5), // API
+            Constraint::Length(6), // TOOLS
+            Constraint::Length(8), // TOKENS
+            Constraint::Length(8), // LATENCY
+            Constraint::Min(8),    // COST
+        ],
+    )
+    .header(header)
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(title)
+            .border_style(Style::default().fg(Color::Cyan)),
+    );
+
+    if app.prompt_cost.turns == 0 {
+        f.render_widget(table, area);
+        return;
+    }
+    // Buckets, header and borders
+    let analysis_height = PROMPT_COST_HEIGHT.min(area.height / 2);
+    let sections = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(analysis_height)])
+        .split(area);
+    f.render_widget(table, sections[0]);
+    draw_prompt_cost(f, app, sections[1]);
+}
+
+/// Height of the turns view's prompt size panel: the buckets, a header and borders
+const PROMPT_COST_HEIGHT: u16 = prompt_cost::SIZE_BUCKETS.len() as u16 + 3;
+
+/// Width of the bars of average turn cost per prompt size
+const PROMPT_COST_BAR_WIDTH: usize = 20;
+
+/// Turn cost per prompt size bucket, with the correlation in the title
+fn draw_prompt_cost(f: &mut Frame, app: &App, area: Rect) {
+    let analysis = &app.prompt_cost;
+    let title = match analysis.describe() {
+        described if described.is_empty() => {
+            format!(" Prompt size vs cost ({} turns) ", analysis.turns)
+        }
+        described => format!(
+            " Prompt size vs cost ({} turns) · {} ",
+            analysis.turns, described
+        ),
+    };
+    let max_avg = analysis
+        .buckets
+        .iter()
+        .filter_map(|b| b.avg_cost_usd())
+        .fold(0.0, f64::max);
+    let usd = |value: Option<f64>| match value {
+        Some(v) => Cell::from(format_usd(v)),
+        None => Cell::from("-").style(Style::default().fg(Color::DarkGray)),
+    };
+    let rows: Vec<Row> = analysis
+        .buckets
+        .iter()
+        .map(|bucket| {
+            let filled = match bucket.avg_cost_usd() {
+                Some(avg) if max_avg > 0.0 => {
+                    ((avg / max_avg) * PROMPT_COST_BAR_WIDTH as f64).round() as usize
+                }
+                _ => 0,
+            };
+            Row::new(vec![
+                Cell::from(bucket.label),
+                Cell::from(bucket.turns.to_string()),
+                usd(bucket.avg_cost_usd()),
+                usd((bucket.turns > 0).then_some(bucket.median_cost_usd)),
+                usd(bucket.cost_per_1k_chars()),
+                Cell::from(Line::from(vec![
+                    Span::styled("█".repeat(filled), Style::default().fg(Color::Magenta)),
+                    Span::styled(
+                        "░".repeat(PROMPT_COST_BAR_WIDTH - filled.min(PROMPT_COST_BAR_WIDTH)),
+                        Style::default().fg(Color::DarkGray),
+                    ),
+                ])),
+            ])
+        })
+        .collect();
+    let header = Row::new(
+        ["PROMPT", "TURNS", "AVG", "MEDIAN", "$/1K CHARS", "AVG COST"].map(|h| {
+            Cell::from(h).style(
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD),
+            )
+        }),
+    );
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(8),  // PROMPT
+            Constraint::Length(6),  // TURNS
+            Constraint::Length(8),  // AVG
+            Constraint::Length(8),  // MEDIAN
+            Constraint::Length(10), // $/1K CHARS
+            Constraint::Min(PROMPT_COST_BAR_WIDTH as u16),
+        ],
+    )
+    .header(header)
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(title)
+            .border_style(Style::default().fg(Color::Cyan)),
+    );
+    f.render_widget(table, area);
+}
+
+/// Most frequent tool-call sequences; ones the agent went round back to back are
+/// highlighted as loops
+fn draw_workflows(f: &mut Frame, app: &App, area: Rect) {
+    let header_cells = ["SEQUENCE", "COUNT", "SESSIONS", "LOOPS", "AVG CYCLE"]
+        .iter()
+        .map(|h| {
+            Cell::from(*h).style(
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD),
+            )
+        });
+    let header = Row::new(header_cells).height(1);
+
+    let rows: Vec<Row> = app
+        .workflows
+        .iter()
+        .map(|sequence| {
+            let tools: Vec<String> = sequence
+                .tools
+                .iter()
+                .map(|tool| get_tool_display_name(tool))
+                .collect();
+            let loops = if sequence.repeats > 0 {
+                Cell::from(sequence.repeats.to_string()).style(Style::default().fg(Color::Yellow))
+            } else {
+                Cell::from("-").style(Style::default().fg(Color::DarkGray))
+            };
+            Row::new(vec![
+                Cell::from(tools.join(" → ")),
+                Cell::from(sequence.count.to_string()),
+                Cell::from(sequence.sessions.to_string()),
+                loops,
+                Cell::from(format_duration(sequence.avg_cycle_ms)),
+            ])
+        })
+        .collect();
+
+    let title = format!(
+        " Workflows ({}) · {}-call sequences ",
+        app.workflows.len(),
+        WORKFLOW_LENGTH
+    );
+
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Min(30),    // SEQUENCE
+            Constraint::Length(7),  // COUNT
+            Constraint::Length(9),  // SESSIONS
+            Constraint::Length(6),  // LOOPS
+            Constraint::Length(10), // AVG CYCLE
+        ],
+    )
+    .header(header)
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(title)
+            .border_style(Style::default().fg(Color::Cyan)),
+    );
+
+    f.render_widget(table, area);
+}
+
+/// Most edited files on the left, directories on the right
+fn draw_files(f: &mut Frame, app: &App, area: Rect) {
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)])
+        .split(area);
+    draw_file_table(f, " Files ", &app.files, columns[0]);
+    draw_file_table(f, " Directories ", &app.directories, columns[1]);
+}
+
+/// Time since `at` in its largest unit ("45s", "12m", "3h", "2d")
+fn format_age(now: DateTime<Utc>, at: DateTime<Utc>) -> String {
+    match (now - at).num_seconds() {
+        secs if secs < 0 => "-".to_string(),
+        secs if secs < 60 => format!("{}s", secs),
+        secs if secs < 3600 => format!("{}m", secs / 60),
+        secs if secs < 86400 => format!("{}h", secs / 3600),
+        secs => format!("{}d", secs / 86400),
+    }
+}
+
+fn draw_file_table(f: &mut Frame, title: &str, entries: &[FileActivity], area: Rect) {
+    const WIDTHS: [u16; 4] = [6, 6, 9, 5]; // EDITS, CALLS, SESSIONS, LAST
+    let header_cells = ["PATH", "EDITS", "CALLS", "SESSIONS", "LAST"]
+        .iter()
+        .map(|h| {
+            Cell::from(*h).style(
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD),
+            )
+        });
+    let header = Row::new(header_cells).height(1);
+
+    // Borders and the gaps between the five columns
+    let path_width = (area.width as usize)
+        .saturating_sub(2 + 4 + WIDTHS.iter().sum::<u16>() as usize)
+        .max(8);
+    let home = dirs::home_dir().map(|home| home.display().to_string());
+    let now = Utc::now();
+    let rows: Vec<Row> = entries
+        .iter()
+        .map(|entry| {
+            let path = match home.as_deref() {
+                Some(home) if entry.path.starts_with(home) && home != "/" => {
+                    format!("~{}", &entry.path[home.len()..])
+                }
+                _ => entry.path.clone(),
+            };
+            let edits = if entry.edits > 0 {
+                Cell::from(entry.edits.to_string()).style(Style::default().fg(Color::Green))
+            } else {
+                Cell::from("-").style(Style::default().fg(Color::DarkGray))
+            };
+            Row::new(vec![
+                Cell::from(text::truncate_start(&path, path_width)),
+                edits,
+                Cell::from(entry.calls.to_string()),
+                Cell::from(entry.sessions.to_string()),
+                Cell::from(format_age(now, entry.last_at)),
+            ])
+        })
+        .collect();
+
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Min(8),
+            Constraint::Length(WIDTHS[0]),
+            Constraint::Length(WIDTHS[1]),
+            Constraint::Length(WIDTHS[2]),
+            Constraint::Length(WIDTHS[3]),
+        ],
+    )
+    .header(header)
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!("{}({}) ", title, entries.len()))
+            .border_style(Style::default().fg(Color::Cyan)),
+    );
+
+    f.render_widget(table, area);
+}
+
+/// Cells of the output and ratio columns the productivity tables share
+fn productivity_cells(p: &Productivity) -> Vec<Cell<'static>> {
+    let ratio = |value: Option<f64>, precision: usize| match value {
+        Some(v) => Cell::from(format!("{v:.precision$}")),
+        None => Cell::from("-").style(Style::default().fg(Color::DarkGray)),
+    };
+    vec![
+        Cell::from(format!("{:+}", p.lines_of_code)),
+        Cell::from(p.commits.to_string()),
+        Cell::from(format_session_duration(chrono::Duration::seconds(
+            (p.hours * 3600.0) as i64,
+        ))),
+        Cell::from(format_usd(p.cost_usd)),
+        ratio(p.lines_per_usd(), 0).style(Style::default().fg(Color::Green)),
+        ratio(p.commits_per_hour(), 1),
+        match p.cost_per_commit() {
+            Some(cost) => Cell::from(format_usd(cost)),
+            None => Cell::from("-").style(Style::default().fg(Color::DarkGray)),
+        },
+    ]
+}
+
+/// Lines of code per dollar, commits per hour and cost per commit: per week on top,
+/// per session below
+fn draw_productivity(f: &mut Frame, app: &App, area: Rect) {
+    let total = productivity::total(&app.productivity);
+    let title = match total.describe() {
+        described if described.is_empty() => " Productivity ".to_string(),
+        described => format!(" Productivity · {} ", described),
+    };
+    if app.productivity.is_empty() {
+        let hint = Paragraph::new(
+            "No sessions with code metrics in this range. Claude Code reports lines of code, \
+             commits and active time; other agents don't.",
+        )
+        .style(Style::default().fg(Color::DarkGray))
+        .wrap(Wrap { trim: true })
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(title)
+                .border_style(Style::default().fg(Color::Cyan)),
+        );
+        f.render_widget(hint, area);
+        return;
+    }
+
+    let weeks = productivity::by_week(&app.productivity);
+    // Header, borders and a total row
+    let weeks_height = (weeks.len() as u16 + 4).min(area.height / 2);
+    let sections = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(weeks_height), Constraint::Min(0)])
+        .split(area);
+
+    let header = |first: &'static str, second: &'static str| {
+        Row::new(
+            [
+                first,
+                second,
+                "LINES",
+                "COMMITS",
+                "TIME",
+                "COST",
+                "LINES/$",
+                "COMMITS/H",
+                "$/COMMIT",
+            ]
+            .map(|h| {
+                Cell::from(h).style(
+                    Style::default()
+                        .fg(Color::Yellow)
+                        .add_modifier(Modifier::BOLD),
+                )
+            }),
+        )
+    };
+    let widths = |first: u16, second: u16| {
+        [
+            Constraint::Length(first),
+            Constraint::Length(second),
+            Constraint::Length(7),
+            Constraint::Length(7),
+            Constraint::Length(8),
+            Constraint::Length(9),
+            Constraint::Length(7),
+            Constraint::Length(9),
+            Constraint::Length(8),
+        ]
+    };
+
+    let mut week_rows: Vec<Row> = weeks
+        .iter()
+        .rev()
+        .map(|(week, p)| {
+            let mut cells = vec![
+                Cell::from(week.to_string()),
+                Cell::from(p.sessions.to_string()),
+            ];
+            cells.extend(productivity_cells(p));
+            Row::new(cells)
+        })
+        .collect();
+    let mut total_cells = vec![Cell::from("TOTAL"), Cell::from(total.sessions.to_string())];
+    total_cells.extend(productivity_cells(&total));
+    week_rows.push(Row::new(total_cells).style(Style::default().add_modifier(Modifier::BOLD)));
+    let week_table = Table::new(week_rows, widths(10, 8))
+        .header(header("WEEK OF", "SESSIONS"))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(title)
+                .border_style(Style::default().fg(Color::Cyan)),
+        );
+    f.render_widget(week_table, sections[0]);
+
+    let session_rows: Vec<Row> = app
+        .productivity
+        .iter()
+        .map(|session| {
+            let mut cells = vec![
+                Cell::from(
+                    session
+                        .started_at
+                        .with_timezone(&chrono::Local)
+                        .format("%m-%d %H:%M")
+                        .to_string(),
+                ),
+                Cell::from(text::truncate(&session.session_id, 10)),
+            ];
+            cells.extend(productivity_cells(&productivity::of_session(session)));
+            Row::new(cells)
+        })
+        .collect();
+    let session_table = Table::new(session_rows, widths(11, 10))
+        .header(header("STARTED", "SESSION"))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!(" Sessions ({}) ", app.productivity.len()))
+                .border_style(Style::default().fg(Color::Cyan)),
+        );
+    f.render_widget(session_table, sections[1]);
+}
+
+/// The providers side by side: a column per provider, a row per measure, with the best
+/// value on each measure in green
+fn draw_providers(f: &mut Frame, app: &App, area: Rect) {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!(" Providers ({}) ", app.providers.len()))
+        .border_style(Style::default().fg(Color::Cyan));
+    if app.providers.is_empty() {
+        let hint = Paragraph::new("No provider events in this range.")
+            .style(Style::default().fg(Color::DarkGray))
+            .block(block);
+        f.render_widget(hint, area);
+        return;
+    }
+
+    let header = Row::new(
+        std::iter::once("")
+            .chain(app.providers.iter().map(|p| provider_name(&p.provider)))
+            .map(|h| {
+                Cell::from(h.to_string()).style(
+                    Style::default()
+                        .fg(Color::Yellow)
+                        .add_modifier(Modifier::BOLD),
+                )
+            }),
+    );
+    let rows: Vec<Row> = measures(&app.providers)
+        .into_iter()
+        .map(|measure| {
+            let best = measure.best;
+            Row::new(std::iter::once(Cell::from(measure.label)).chain(
+                measure.cells.into_iter().enumerate().map(|(i, cell)| {
+                    if best == Some(i) {
+                        Cell::from(cell).style(Style::default().fg(Color::Green))
+                    } else {
+                        Cell::from(cell)
+                    }
+                }),
+            ))
+        })
+        .collect();
+    let widths: Vec<Constraint> = std::iter::once(Constraint::Length(14))
+        .chain(app.providers.iter().map(|_| Constraint::Length(14)))
+        .collect();
+    let table = Table::new(rows, widths).header(header).block(block);
+    f.render_widget(table, area);
+}
+
+/// Width of the share bars in the errors view
+const ERROR_SHARE_BAR_WIDTH: usize = 12;
+
+/// Errors by category with each one's share, then by category and tool or model with
+/// the latest message
+fn draw_errors(f: &mut Frame, app: &App, area: Rect) {
+    let header = |titles: &[&'static str]| {
+        Row::new(titles.iter().map(|h| {
+            Cell::from(*h).style(
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD),
+            )
+        }))
+    };
+    let block = |title: String| {
+        Block::default()
+            .borders(Borders::ALL)
+            .title(title)
+            .border_style(Style::default().fg(Color::Cyan))
+    };
+    let total: u64 = app.error_categories.iter().map(|c| c.errors).sum();
+    if total == 0 {
+        let hint = Paragraph::new("No tool or API errors in this range.")
+            .style(Style::default().fg(Color::DarkGray))
+            .block(block(" Errors ".to_string()));
+        f.render_widget(hint, area);
+        return;
+    }
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(app.error_categories.len().min(10) as u16 + 3),
+            Constraint::Min(4),
+        ])
+        .split(area);
+
+    let now = Utc::now();
+    let rows: Vec<Row> = app
+        .error_categories
+        .iter()
+        .map(|category| {
+            let share = category.errors as f64 / total as f64;
+            let filled = (share * ERROR_SHARE_BAR_WIDTH as f64).round() as usize;
+            Row::new(vec![
+                Cell::from(category.category.clone()).style(Style::default().fg(Color::Red)),
+                Cell::from(category.errors.to_string()),
+                Cell::from(Line::from(vec![
+                    Span::styled("█".repeat(filled), Style::default().fg(Color::Red)),
+                    Span::styled(
+                        "░".repeat(ERROR_SHARE_BAR_WIDTH.saturating_sub(filled)),
+                        Style::default().fg(Color::DarkGray),
+                    ),
+                    Span::raw(format!(" {:>3.0}%", share * 100.0)),
+                ])),
+                Cell::from(category.tool_errors.to_string()),
+                Cell::from(category.api_errors.to_string()),
+                Cell::from(category.sessions.to_string()),
+                Cell::from(format_age(now, category.last_at)),
+            ])
+        })
+        .collect();
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(18),
+            Constraint::Length(7),
+            Constraint::Length(ERROR_SHARE_BAR_WIDTH as u16 + 5),
+            Constraint::Length(6),
+            Constraint::Length(6),
+            Constraint::Length(9),
+            Constraint::Length(5),
+        ],
+    )
+    .header(header(&[
+        "CATEGORY", "ERRORS", "SHARE", "TOOL", "API", "SESSIONS", "LAST",
+    ]))
+    .block(block(format!(" Errors by category ({}) ", total)));
+    f.render_widget(table, chunks[0]);
+
+    // Borders and the gaps between the six columns
+    const WIDTHS: [u16; 5] = [18, 4, 16, 7, 5]; // CATEGORY, KIND, SOURCE, ERRORS, LAST
+    let message_width = (chunks[1].width as usize)
+        .saturating_sub(2 + 5 + WIDTHS.iter().sum::<u16>() as usize)
+        .max(8);
+    let rows: Vec<Row> = app
+        .error_breakdown
+        .iter()
+        .map(|entry| {
+            let source = if entry.kind == crate::storage::TOOL_ERROR {
+                get_tool_display_name(&entry.source)
+            } else {
+                entry.source.clone()
+            };
+            Row::new(vec![
+                Cell::from(entry.category.clone()),
+                Cell::from(entry.kind.clone()).style(Style::default().fg(Color::DarkGray)),
+                Cell::from(source),
+                Cell::from(entry.errors.to_string()),
+                Cell::from(format_age(now, entry.last_at)),
+                Cell::from(text::truncate(
+                    entry.last_message.as_deref().unwrap_or("-"),
+                    message_width,
+                ))
+                .style(Style::default().fg(Color::DarkGray)),
+            ])
+        })
+        .collect();
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(WIDTHS[0]),
+            Constraint::Length(WIDTHS[1]),
+            Constraint::Length(WIDTHS[2]),
+            Constraint::Length(WIDTHS[3]),
+            Constraint::Length(WIDTHS[4]),
+            Constraint::Min(8),
+        ],
+    )
+    .header(header(&[
+        "CATEGORY",
+        "KIND",
+        "TOOL/MODEL",
+        "ERRORS",
+        "LAST",
+        "LATEST MESSAGE",
+    ]))
+    .block(block(format!(
+        " By tool and model ({}) ",
+        app.error_breakdown.len()
+    )));
+    f.render_widget(table, chunks[1]);
+}
+
+/// Width of the bars in the cache view's daily trend
+const CACHE_TREND_BAR_WIDTH: usize = 20;
+
+/// Prompt-cache use: the daily hit rate and savings as bars, then per model
+fn draw_cache(f: &mut Frame, app: &App, area: Rect) {
+    let cache = &app.cache_efficiency;
+    let saved = |saved_usd: Option<f64>| match saved_usd {
+        Some(usd) => format!("~{}", format_usd(usd)),
+        None => "-".to_string(),
+    };
+    let hit_rate = |rate: Option<f64>| match rate {
+        Some(rate) => format!("{:.0}%", rate),
+        None => "-".to_string(),
+    };
+
+    // The newest days that fit, leaving room for the model table
+    let model_rows = cache.models.len().clamp(1, 8) as u16 + 3;
+    let trend_rows = area.height.saturating_sub(model_rows + 2).max(1) as usize;
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(cache.days.len().min(trend_rows).max(1) as u16 + 2),
+            Constraint::Min(model_rows),
+        ])
+        .split(area);
+
+    let lines: Vec<Line> = cache.days[cache.days.len().saturating_sub(trend_rows)..]
+        .iter()
+        .map(|day| {
+            let rate = day.totals.hit_rate().unwrap_or(0.0);
+            let filled = ((rate / 100.0) * CACHE_TREND_BAR_WIDTH as f64).round() as usize;
+            Line::from(vec![
+                Span::styled(
+                    format!("{:<11}", day.day.format("%a %m-%d")),
+                    Style::default().fg(Color::DarkGray),
+                ),
+                Span::styled("█".repeat(filled), Style::default().fg(Color::Magenta)),
+                Span::styled(
+                    "░".repeat(CACHE_TREND_BAR_WIDTH - filled.min(CACHE_TREND_BAR_WIDTH)),
+                    Style::default().fg(Color::DarkGray),
+                ),
+                Span::raw(format!(" {:>4}", hit_rate(day.totals.hit_rate()))),
+                Span::styled(
+                    format!("  {}", saved(day.totals.saved_usd)),
+                    Style::default().fg(Color::Green),
+                ),
+            ])
+        })
+        .collect();
+    let title = format!(
+        " Cache · hit rate {} · saved {} ",
+        hit_rate(cache.total.hit_rate()),
+        saved(cache.total.saved_usd)
+    );
+    f.render_widget(
+        Paragraph::new(lines).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(title)
+                .border_style(Style::default().fg(Color::Cyan)),
+        ),
+        chunks[0],
+    );
+
+    let header_cells = [
+        "MODEL",
+        "INPUT",
+        "CACHE READ",
+        "CACHE WRITE",
+        "HIT",
+        "SAVED",
+    ]
+    .iter()
+    .map(|h| {
+        Cell::from(*h).style(
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        )
+    });
+    let header = Row::new(header_cells).height(1);
+    let rows: Vec<Row> = cache
+        .models
+        .iter()
+        .map(|model| {
+            Row::new(vec![
+                Cell::from(PROVIDER_REGISTRY.shorten_model_name(&model.model)),
+                Cell::from(format_count(model.totals.input_tokens)),
+                Cell::from(format_count(model.totals.cache_read_tokens)),
+                Cell::from(format_count(model.totals.cache_creation_tokens)),
+                Cell::from(hit_rate(model.totals.hit_rate())),
+                Cell::from(saved(model.totals.saved_usd)).style(Style::default().fg(Color::Green)),
+            ])
+        })
+        .collect();
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Min(16),    // MODEL
+            Constraint::Length(8),  // INPUT
+            Constraint::Length(11), // CACHE READ
+            Constraint::Length(12), // CACHE WRITE
+            Constraint::Length(5),  // HIT
+            Constraint::Length(10), // SAVED
+        ],
+    )
+    .header(header)
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" By model ")
+            .border_style(Style::default().fg(Color::Blue)),
+    );
+    f.render_widget(table, chunks[1]);
+}
+
+/// Highlight for a row whose tool just received new calls
+fn row_flash_style(app: &App, tool: &ToolMetrics, now: DateTime<Utc>) -> Style {
+    if app.is_flashing(&tool.tool_name, now) {
+        Style::default().bg(Color::Blue)
+    } else {
+        Style::default()
+    }
+}
+
+/// Attributes shown first in the event stream, when present
+const EVENT_KEY_ATTRIBUTES: [&str; 9] = [
+    "tool_name",
+    "model",
+    "success",
+    "duration_ms",
+    "decision",
+    "error",
+    "error_type",
+    "status_code",
+    "prompt_length",
+];
+
+/// One-line summary of an event's attributes: key attributes, or the first few others
+fn event_summary(attributes: &std::collections::HashMap<String, String>) -> String {
+    let mut pairs: Vec<String> = EVENT_KEY_ATTRIBUTES
+        .iter()
+        .filter_map(|key| {
+            attributes
+                .get(*key)
+                .map(|value| format!("{}={}", key, value))
+        })
+        .collect();
+    if pairs.is_empty() {
+        let mut keys: Vec<&String> = attributes.keys().collect();
+        keys.sort();
+        pairs = keys
+            .into_iter()
+            .take(3)
+            .map(|key| format!("{}={}", key, attributes[key]))
+            .collect();
+    }
+    pairs.join(" ")
+}
+
+/// Live stream of raw log events. Follows the newest event by default; when frozen
+/// the view stays put while new events buffer below it.
+fn draw_event_stream(f: &mut Frame, app: &App, area: Rect) {
+    let title = if app.is_following_events() {
+        format!(" Events ({}) · following ", app.events.len())
+    } else {
+        format!(
+            " Events ({}) · frozen · {} new · [f]ollow ",
+            app.events.len(),
+            app.unseen_events()
+        )
+    };
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(title)
+        .border_style(Style::default().fg(if app.is_following_events() {
+            Color::Cyan
+        } else {
+            Color::Yellow
+        }));
+
+    let visible = area.height.saturating_sub(2) as usize;
+    let lines: Vec<Line> = match app.event_anchor_index() {
+        Some(last) => {
+            let first = (last + 1).saturating_sub(visible);
+            app.events
+                .range(first..=last)
+                .map(|event| {
+                    let name = event.event_name.as_deref().unwrap_or("-");
+                    let is_error = name.ends_with("api_error")
+                        || event
+                            .attributes
+                            .get("success")
+                            .is_some_and(|s| s == "false");
+                    Line::from(vec![
+                        Span::styled(
+                            event
+                                .timestamp
+                                .with_timezone(&chrono::Local)
+                                .format("%H:%M:%S%.3f ")
+                                .to_string(),
+                            Style::default().fg(Color::DarkGray),
+                        ),
+                        Span::styled(
+                            format!("{:<28} ", name),
+                            Style::default().fg(if is_error { Color::Red } else { Color::Cyan }),
+                        ),
+                        Span::raw(event_summary(&event.attributes)),
+                    ])
+                })
+                .collect()
+        }
+        None => vec![Line::from(Span::styled(
+            " Waiting for events...",
+            Style::default().fg(Color::DarkGray),
+        ))],
+    };
+
+    f.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+/// Widest a SQL console result column gets before values are cut off
+const SQL_MAX_COLUMN_WIDTH: usize = 40;
+
+/// Query line above a results table (or the query's error)
+fn draw_sql_console(f: &mut Frame, app: &App, area: Rect) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Min(0)])
+        .split(area);
+
+    let query_line = match &app.sql_input {
+        Some(input) => Line::from(vec![
+            Span::raw(input.clone()),
+            Span::styled("▏", Style::default().fg(Color::Yellow)),
+        ]),
+        None if app.sql_query.is_empty() => Line::from(Span::styled(
+            "Press [Enter] to type a read-only query, e.g. SELECT * FROM log_events",
+            Style::default().fg(Color::DarkGray),
+        )),
+        None => Line::from(Span::raw(app.sql_query.clone())),
+    };
+    let editing = app.sql_input.is_some();
+    let query = Paragraph::new(query_line).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(if editing {
+                " SQL · [Enter] run · [Esc] cancel "
+            } else {
+                " SQL · [Enter] edit "
+            })
+            .border_style(Style::default().fg(if editing { Color::Yellow } else { Color::Cyan })),
+    );
+    f.render_widget(query, chunks[0]);
+
+    let result = match &app.sql_result {
+        Some(Ok(result)) => result,
+        Some(Err(e)) => {
+            let error = Paragraph::new(e.clone())
+                .style(Style::default().fg(Color::Red))
+                .wrap(ratatui::widgets::Wrap { trim: false })
+                .block(Block::default().borders(Borders::ALL).title(" Error "));
+            f.render_widget(error, chunks[1]);
+            return;
+        }
+        None => {
+            let tables = Paragraph::new(
+                "Tables: tool_events, log_events, token_usage, cost_usage, session_metrics",
+            )
+            .style(Style::default().fg(Color::DarkGray))
+            .block(Block::default().borders(Borders::ALL).title(" Results "));
+            f.render_widget(tables, chunks[1]);
+            return;
+        }
+    };
+
+    let cell_text = |value: &Option<String>| -> String {
+        let cell = value.as_deref().unwrap_or("NULL").replace('\n', " ");
+        text::truncate(&cell, SQL_MAX_COLUMN_WIDTH)
+    };
+    let widths: Vec<Constraint> = result
+        .columns
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let widest = result
+                .rows
+                .iter()
+                .map(|row| cell_text(&row[i]).chars().count())
+                .chain([name.chars().count()])
+                .max()
+                .unwrap_or(0);
+            Constraint::Length(widest.min(SQL_MAX_COLUMN_WIDTH) as u16)
+        })
+        .collect();
+
+    let header = Row::new(result.columns.iter().map(|name| {
+        Cell::from(name.clone()).style(
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        )
+    }));
+    let rows: Vec<Row> = result
+        .rows
+        .iter()
+        .skip(app.sql_scroll)
+        .map(|row| {
+            Row::new(row.iter().map(|value| {
+                let style = if value.is_none() {
+                    Style::default().fg(Color::DarkGray)
+                } else {
+                    Style::default()
+                };
+                Cell::from(cell_text(value)).style(style)
+            }))
+        })
+        .collect();
+
+    let title = if result.truncated {
+        format!(" Results (first {} rows) ", result.rows.len())
+    } else {
+        format!(" Results ({} rows) ", result.rows.len())
+    };
+    let table = Table::new(rows, widths).header(header).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(title)
+            .border_style(Style::default().fg(Color::Cyan)),
+    );
+    f.render_widget(table, chunks[1]);
+}
+
+/// Shading for heatmap cells, from no activity to the busiest hour
+const HEATMAP_SHADES: [char; 5] = ['·', '░', '▒', '▓', '█'];
+
+/// Weekday × hour-of-day heatmap of tool calls or cost for one week
+fn draw_heatmap(f: &mut Frame, app: &App, area: Rect) {
+    let Some(heatmap) = &app.heatmap else {
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title(" Activity ")
+            .border_style(Style::default().fg(Color::Cyan));
+        f.render_widget(Paragraph::new("").block(block), area);
+        return;
+    };
+
+    let title = format!(
+        " Activity · {} · week of {} ",
+        heatmap.metric.label(),
+        heatmap.week_start.format("%a %b %d")
+    );
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(title)
+        .border_style(Style::default().fg(Color::Cyan));
+
+    // Use three columns per hour when the terminal is wide enough
+    let cell_width = if area.width as usize >= 2 + 4 + 24 * 3 {
+        3
+    } else {
+        2
+    };
+    let max = heatmap.max();
+
+    let mut hours_header = String::from("    ");
+    for hour in 0..24 {
+        let label = if hour % 3 == 0 {
+            format!("{:02}", hour)
+        } else {
+            String::new()
+        };
+        hours_header.push_str(&format!("{:<width$}", label, width = cell_width));
+    }
+    let mut lines = vec![Line::from(Span::styled(
+        hours_header,
+        Style::default().fg(Color::DarkGray),
+    ))];
+
+    for (day, row) in heatmap.cells.iter().enumerate() {
+        let date = heatmap.week_start + chrono::Duration::days(day as i64);
+        let mut spans = vec![Span::styled(
+            format!("{:<4}", date.format("%a")),
+            Style::default().fg(Color::DarkGray),
+        )];
+        for value in row {
+            let shade = if *value <= 0.0 || max <= 0.0 {
+                0
+            } else {
+                // Any activity gets at least the lightest filled shade
+                ((value / max) * (HEATMAP_SHADES.len() - 1) as f64)
+                    .ceil()
+                    .clamp(1.0, (HEATMAP_SHADES.len() - 1) as f64) as usize
+            };
+            let color = if shade == 0 {
+                Color::DarkGray
+            } else {
+                Color::Green
+            };
+            spans.push(Span::styled(
+                HEATMAP_SHADES[shade].to_string().repeat(cell_width - 1) + " ",
+                Style::default().fg(color),
+            ));
+        }
+        lines.push(Line::from(spans));
+    }
+
+    let format_value = |value: f64| match heatmap.metric {
+        HeatmapMetric::Calls => format!("{:.0}", value),
+        HeatmapMetric::Cost => format_usd(value),
+    };
+    let mut summary = vec![
+        Span::raw(format!("Total {}", format_value(heatmap.total))),
+        Span::styled(
+            format!(
+                "  ·  previous week {}",
+                format_value(heatmap.previous_total)
+            ),
+            Style::default().fg(Color::DarkGray),
+        ),
+    ];
+    if heatmap.previous_total > 0.0 {
+        let change = (heatmap.total - heatmap.previous_total) / heatmap.previous_total * 100.0;
+        summary.push(Span::styled(
+            format!(" ({:+.0}%)", change),
+            Style::default().fg(if change > 0.0 {
+                Color::Yellow
+            } else {
+                Color::Green
+            }),
+        ));
+    }
+    lines.push(Line::from(""));
+    lines.push(Line::from(summary));
+    lines.push(Line::from(Span::styled(
+        "[m]etric  [ / ] previous/next week",
+        Style::default().fg(Color::DarkGray),
+    )));
+
+    f.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+/// Collapse a prompt to a single line and cut it to `width` columns
+fn truncate_prompt(prompt: &str, width: usize) -> String {
+    let line = prompt.split_whitespace().collect::<Vec<_>>().join(" ");
+    text::truncate(&line, width)
+}
+
+fn draw_footer(f: &mut Frame, app: &App, area: Rect, compact: bool) {
+    let status = receiver_status_line(app, compact);
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Min(0),
+            Constraint::Length(status.width() as u16),
+        ])
+        .split(area);
+
+    let keys = if compact {
+        " [q]uit [s]ort [p]ause [t]ime [c]ompact"
+    } else {
+        " [q]uit [s]ort [p]ause [d]etail [t]ime [T]range [r]eset [a]gent e[x]port [S]napshot [c]ompact [w]compare [v]iew"
+    };
+    let footer = Line::from(vec![Span::styled(
+        keys,
+        Style::default().fg(Color::DarkGray),
+    )]);
+
+    let paragraph = Paragraph::new(footer);
+    f.render_widget(paragraph, chunks[0]);
+    f.render_widget(Paragraph::new(status), chunks[1]);
+}
+
+/// Receiver health: listen address, time since last event, events/sec, dropped payloads.
+/// Compact mode shows only the health dot and events/sec. Storage being down takes
+/// precedence, as nothing received is stored meanwhile.
+fn receiver_status_line(app: &App, compact: bool) -> Line<'static> {
+    if let Some(error) = &app.storage_status.error {
+        let text = if compact {
+            "● storage down ".to_string()
+        } else {
+            format!("● storage down: {} ", truncate_prompt(error, 48))
+        };
+        return Line::from(Span::styled(text, Style::default().fg(Color::Red)));
+    }
+    let status = &app.receiver_status;
+    if app.view_only {
+        let text = if compact {
+            "● view "
+        } else {
+            "● view-only "
+        };
+        return Line::from(Span::styled(text, Style::default().fg(Color::Cyan)));
+    }
+    let Some(addr) = status.snapshot.listen_addr.clone() else {
+        let text = if compact {
+            "● down "
+        } else {
+            "● receiver down "
+        };
+        return Line::from(Span::styled(text, Style::default().fg(Color::Red)));
+    };
+
+    let since_last = status
+        .snapshot
+        .last_event_at
+        .map(|at| (Utc::now() - at).num_seconds().max(0));
+    let (dot_color, last_str) = match since_last {
+        Some(secs) if secs < 60 => (Color::Green, format!("{}s ago", secs)),
+        Some(secs) if secs < 3600 => (Color::Yellow, format!("{}m ago", secs / 60)),
+        Some(secs) => (Color::Yellow, format!("{}h ago", secs / 3600)),
+        None => (Color::Yellow, "no events".to_string()),
+    };
+
+    if compact {
+        return Line::from(vec![
+            Span::styled("● ", Style::default().fg(dot_color)),
+            Span::styled(
+                format!("{:.1}/s ", status.events_per_sec),
+                Style::default().fg(Color::DarkGray),
+            ),
+        ]);
+    }
+
+    // Unparseable payloads and batches storage could not keep up with
+    let dropped = status.snapshot.payloads_dropped + status.snapshot.batches_dropped;
+    let dropped_style = if dropped > 0 {
+        Style::default().fg(Color::Red)
+    } else {
+        Style::default().fg(Color::DarkGray)
+    };
+
+    Line::from(vec![
+        Span::styled("● ", Style::default().fg(dot_color)),
+        Span::styled(
+            format!(
+                "{} │ {} │ {:.1} ev/s │ ",
+                addr, last_str, status.events_per_sec
+            ),
+            Style::default().fg(Color::DarkGray),
+        ),
+        Span::styled(format!("{} dropped ", dropped), dropped_style),
+    ])
+}
+
+fn draw_toast(f: &mut Frame, toast: &Toast) {
+    let full = f.area();
+    let width = (toast.message.chars().count() as u16 + 4).min(full.width);
+    let height = 3.min(full.height);
+    let area = Rect {
+        x: full.x + full.width - width,
+        y: full.y + full.height - height,
+        width,
+        height,
+    };
+
+    let color = if toast.is_error {
+        Color::Red
+    } else {
+        Color::Green
+    };
+
+    f.render_widget(Clear, area);
+    let paragraph = Paragraph::new(Line::from(Span::styled(
+        format!(" {}", toast.message),
+        Style::default().fg(color),
+    )))
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(color)),
+    );
+    f.render_widget(paragraph, area);
+}
+
+fn draw_detail_popup(f: &mut Frame, app: &App) {
+    let Some(tool) = app.selected_tool() else {
+        return;
+    };
+
+    let area = centered_rect(60, 60, f.area());
+
+    // Clear the area
+    f.render_widget(Clear, area);
+
+    let success_rate = if tool.call_count > 0 {
+        (tool.success_count as f64 / tool.call_count as f64) * 100.0
+    } else {
+        100.0
+    };
+
+    // Format duration range
+    let format_duration = |ms: f64| -> String {
+        if ms < 1000.0 {
+            format!("{:.0}ms", ms)
+        } else {
+            format!("{:.1}s", ms / 1000.0)
+        }
+    };
+
+    // Use display_name() for MCP tools to show "server:tool" format
+    let display_name = tool.display_name();
+    let mut content = vec![
+        Line::from(vec![
+            Span::styled("Tool: ", Style::default().add_modifier(Modifier::BOLD)),
+            Span::raw(&display_name),
+        ]),
+        Line::from(""),
+        Line::from(vec![
+            Span::raw("Total Calls: "),
+            Span::styled(
+                tool.call_count.to_string(),
+                Style::default().fg(Color::Cyan),
+            ),
+        ]),
+        Line::from(vec![
+            Span::raw("Successful: "),
+            Span::styled(
                 tool.success_count.to_string(),
                 Style::default().fg(Color::Green),
             ),
@@ -661,8 +3088,8 @@ fn draw_detail_popup(f: &mut Frame, app: &App) {
             Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
         )]));
         // Truncate error message if too long (max ~60 chars per line, 2 lines)
-        let error_display = if last_error.len() > 120 {
-            format!("{}...", &last_error[..117])
+        let error_display = if text::width(&last_error) > 120 {
+            format!("{}...", text::prefix(&last_error, 117))
         } else {
             last_error
         };
@@ -688,6 +3115,50 @@ fn draw_detail_popup(f: &mut Frame, app: &App) {
     f.render_widget(paragraph, area);
 }
 
+fn draw_time_input(f: &mut Frame, app: &App) {
+    let Some(input) = app.time_input.as_deref() else {
+        return;
+    };
+
+    let outer = centered_rect(60, 40, f.area());
+    let height = outer.height.min(6);
+    let area = Rect {
+        y: outer.y + (outer.height - height) / 2,
+        height,
+        ..outer
+    };
+
+    f.render_widget(Clear, area);
+
+    let mut content = vec![
+        Line::from(vec![
+            Span::styled("> ", Style::default().fg(Color::Yellow)),
+            Span::raw(input),
+            Span::styled("_", Style::default().add_modifier(Modifier::SLOW_BLINK)),
+        ]),
+        Line::from(Span::styled(
+            "e.g. last 3h · since 09:00 · 2025-01-10..2025-01-12",
+            Style::default().fg(Color::DarkGray),
+        )),
+    ];
+
+    if let Some(error) = &app.time_input_error {
+        content.push(Line::from(Span::styled(
+            error.as_str(),
+            Style::default().fg(Color::Red),
+        )));
+    }
+
+    let paragraph = Paragraph::new(content).block(
+        Block::default()
+            .title(" Time Range (Enter to apply, Esc to cancel) ")
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Yellow)),
+    );
+
+    f.render_widget(paragraph, area);
+}
+
 fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
     let popup_layout = Layout::default()
         .direction(Direction::Vertical)
@@ -0,0 +1,205 @@
+//! `agenttop watch`: a table of the activity received since it started, printed every
+//! interval without the TUI.
+//!
+//! By default each table replaces the previous one on screen; with `--plain` tables are
+//! appended as plain text (no alternate screen, no ANSI codes) for CI job logs and dumb
+//! terminals.
+
+use anyhow::Result;
+use chrono::{DateTime, Local, Utc};
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use crate::otlp::{self, ReceiverOptions};
+use crate::report::{format_count, format_duration, percent};
+use crate::storage::{StorageHandle, TokenMetrics, ToolMetrics};
+use crate::tui::app::format_usd;
+
+/// Clear the screen and move the cursor home
+const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+
+/// Parse an interval like "30s", "5m", "1h" or plain seconds ("30")
+pub fn parse_interval(input: &str) -> Result<Duration, String> {
+    let input = input.trim();
+    let split = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    let (num, unit) = input.split_at(split);
+    let n: u64 = num
+        .parse()
+        .map_err(|_| format!("Invalid interval: {}", input))?;
+    let secs = match unit.trim() {
+        "" | "s" | "sec" | "secs" => n,
+        "m" | "min" | "mins" => n * 60,
+        "h" | "hr" | "hrs" => n * 3600,
+        _ => return Err(format!("Invalid interval unit: {}", unit)),
+    };
+    if secs == 0 {
+        return Err("Interval must be at least 1s".to_string());
+    }
+    Ok(Duration::from_secs(secs))
+}
+
+/// One table: a header line, totals, and calls per tool (most called first)
+pub fn render_frame(
+    tools: &[ToolMetrics],
+    tokens: &TokenMetrics,
+    since: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> String {
+    let mut tools: Vec<&ToolMetrics> = tools.iter().collect();
+    tools.sort_by(|a, b| {
+        b.call_count
+            .cmp(&a.call_count)
+            .then_with(|| a.tool_name.cmp(&b.tool_name))
+    });
+    let calls: u64 = tools.iter().map(|t| t.call_count).sum();
+    let errors: u64 = tools.iter().map(|t| t.error_count).sum();
+
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "agenttop · {} · since {}",
+        now.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S"),
+        since.with_timezone(&Local).format("%H:%M:%S")
+    );
+    let _ = writeln!(
+        out,
+        "tokens in {} / out {} · cost {} · {} tool calls ({} errors)",
+        format_count(tokens.input_tokens),
+        format_count(tokens.output_tokens),
+        format_usd(tokens.total_cost_usd),
+        calls,
+        errors
+    );
+    if tools.is_empty() {
+        let _ = writeln!(out, "(no tool calls yet)");
+        return out;
+    }
+
+    let name_width = tools
+        .iter()
+        .map(|t| t.display_name().chars().count())
+        .max()
+        .unwrap_or(0)
+        .max("TOOL".len());
+    let _ = writeln!(
+        out,
+        "{:<name_width$}  {:>6}  {:>5}  {:>6}  {:>7}",
+        "TOOL", "CALLS", "ERR", "ERR%", "AVG"
+    );
+    for tool in tools {
+        let _ = writeln!(
+            out,
+            "{:<name_width$}  {:>6}  {:>5}  {:>5.1}%  {:>7}",
+            tool.display_name(),
+            tool.call_count,
+            tool.error_count,
+            percent(tool.error_count, tool.call_count),
+            format_duration(tool.avg_duration_ms)
+        );
+    }
+    out
+}
+
+/// Receive telemetry on `bind` and print a table every `interval` until interrupted
+pub async fn run(interval: Duration, plain: bool, bind: SocketAddr) -> Result<()> {
+    if tokio::net::TcpListener::bind(bind).await.is_err() {
+        anyhow::bail!(
+            "{} is already in use (is another agenttop running?); watch needs to receive the telemetry itself",
+            bind
+        );
+    }
+    let storage = StorageHandle::new()?;
+    let options = ReceiverOptions {
+        bind: Some(bind),
+        ..Default::default()
+    };
+    let receiver = tokio::spawn(otlp::start_receiver(storage.clone(), options));
+    eprintln!(
+        "Receiving on {}; printing activity every {}s",
+        bind,
+        interval.as_secs()
+    );
+
+    let since = Utc::now();
+    let mut ticker = tokio::time::interval(interval);
+    // The first tick completes immediately; wait a full interval before the first table
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        if receiver.is_finished() {
+            break;
+        }
+        let query_storage = storage.clone();
+        let metrics = tokio::task::spawn_blocking(move || -> Result<_> {
+            Ok((
+                query_storage.get_tool_metrics(Some(since), None)?,
+                query_storage.get_token_metrics(Some(since), None)?,
+            ))
+        })
+        .await?;
+        match metrics {
+            Ok((tools, tokens)) => {
+                let frame = render_frame(&tools, &tokens, since, Utc::now());
+                if plain {
+                    println!("{}", frame);
+                } else {
+                    print!("{}{}", CLEAR_SCREEN, frame);
+                }
+            }
+            Err(e) => eprintln!("Could not query metrics: {:#}", e),
+        }
+    }
+    Ok(receiver.await??)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_interval() {
+        assert_eq!(parse_interval("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_interval("45"), Ok(Duration::from_secs(45)));
+        assert_eq!(parse_interval("2m"), Ok(Duration::from_secs(120)));
+        assert_eq!(parse_interval("1h"), Ok(Duration::from_secs(3600)));
+        assert!(parse_interval("0s").is_err());
+        assert!(parse_interval("5 days").is_err());
+        assert!(parse_interval("s").is_err());
+    }
+
+    #[test]
+    fn test_render_frame_is_plain_text() {
+        let tools = vec![ToolMetrics {
+            tool_name: "mcp__github__create_issue".to_string(),
+            call_count: 2,
+            last_call: None,
+            avg_duration_ms: 1500.0,
+            min_duration_ms: 0.0,
+            max_duration_ms: 0.0,
+            success_count: 1,
+            error_count: 1,
+            approved_count: 2,
+            rejected_count: 0,
+        }];
+        let tokens = TokenMetrics {
+            input_tokens: 1500,
+            total_cost_usd: 0.25,
+            ..Default::default()
+        };
+        let now = Utc::now();
+        let frame = render_frame(&tools, &tokens, now, now);
+        assert!(frame.contains("tokens in 1.5K / out 0 · cost $0.25 · 2 tool calls (1 errors)"));
+        assert!(
+            frame
+                .lines()
+                .any(|l| l.starts_with("github:create_issue") && l.contains("50.0%"))
+        );
+        assert!(!frame.contains('\x1b'));
+
+        let empty = render_frame(&[], &TokenMetrics::default(), now, now);
+        assert!(empty.ends_with("(no tool calls yet)\n"));
+    }
+}
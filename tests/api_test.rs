@@ -0,0 +1,478 @@
+//! REST API tests
+//!
+//! These tests serve the /api routes on an ephemeral port and query them over HTTP
+//! (and WebSocket for /ws).
+
+use agenttop::api;
+use agenttop::otlp::{self, EVENT_FEED, ReceiverOptions};
+use agenttop::storage::{LogEvent, StorageHandle};
+use chrono::Utc;
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_stream::StreamExt;
+use tokio_tungstenite::tungstenite::Message;
+
+/// Serve the API for `storage` on an ephemeral port, returning its address
+async fn serve(storage: StorageHandle) -> String {
+    serve_with(storage, Default::default()).await
+}
+
+/// Serve the API with `access` on a free port, returning its address
+async fn serve_with(storage: StorageHandle, access: api::Access) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    tokio::spawn(async move {
+        axum::serve(listener, api::router(access).with_state(storage))
+            .await
+            .unwrap();
+    });
+    addr
+}
+
+/// Run the whole receiver with `options` on a free port, returning its address
+async fn start_receiver(storage: StorageHandle, options: ReceiverOptions) -> SocketAddr {
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let options = ReceiverOptions {
+        bind: Some(addr),
+        ..options
+    };
+    tokio::spawn(otlp::start_receiver(storage, options));
+    while tokio::net::TcpStream::connect(addr).await.is_err() {
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    addr
+}
+
+/// Send a raw HTTP/1.0 request, returning the lowercased head and the body
+async fn exchange(addr: impl tokio::net::ToSocketAddrs, request: &str) -> (String, String) {
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    (head.to_lowercase(), body.to_string())
+}
+
+/// GET `path`, returning the status code and the parsed JSON body
+async fn get(addr: &str, path: &str) -> (u16, serde_json::Value) {
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(format!("GET {path} HTTP/1.0\r\nHost: {addr}\r\n\r\n").as_bytes())
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let status = head.split_whitespace().nth(1).unwrap().parse().unwrap();
+    (status, serde_json::from_str(body).unwrap())
+}
+
+fn tool_result(tool: &str, minutes_ago: i64) -> LogEvent {
+    LogEvent {
+        timestamp: Utc::now() - chrono::Duration::minutes(minutes_ago),
+        event_name: Some("tool_result".to_string()),
+        body: None,
+        attributes: [
+            ("tool_name".to_string(), tool.to_string()),
+            ("success".to_string(), "true".to_string()),
+        ]
+        .into(),
+    }
+}
+
+/// Test the tools and events endpoints with and without a time range
+#[tokio::test]
+async fn test_api_tools_and_events() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    storage.record_log_events(vec![
+        tool_result("Read", 180),
+        tool_result("Read", 5),
+        tool_result("Bash", 1),
+    ]);
+    storage.record_token_usage("input", 42);
+    let addr = serve(storage).await;
+
+    let (status, all) = get(&addr, "/api/tools").await;
+    assert_eq!(status, 200);
+    assert_eq!(all["range"], "All-time");
+    assert_eq!(all["data"][0]["tool_name"], "Read");
+    assert_eq!(all["data"][0]["call_count"], 2);
+
+    let (_, recent) = get(&addr, "/api/tools?range=1h").await;
+    assert_eq!(recent["range"], "Last 1h");
+    assert!(recent["since"].is_string());
+    assert_eq!(recent["data"].as_array().unwrap().len(), 2);
+    assert_eq!(recent["data"][0]["call_count"], 1);
+
+    let (_, events) = get(&addr, "/api/events?limit=2").await;
+    let events = events["data"].as_array().unwrap();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[1]["attributes"]["tool_name"], "Bash");
+
+    let (_, tokens) = get(&addr, "/api/tokens").await;
+    assert_eq!(tokens["data"]["input_tokens"], 42);
+
+    let (_, sessions) = get(&addr, "/api/sessions").await;
+    assert_eq!(sessions["data"]["commit_count"], 0);
+}
+
+/// Test that the dashboard page is served at the root
+#[tokio::test]
+async fn test_dashboard() {
+    let addr = serve(StorageHandle::new_in_memory().unwrap()).await;
+    let mut stream = tokio::net::TcpStream::connect(&addr).await.unwrap();
+    stream
+        .write_all(format!("GET / HTTP/1.0\r\nHost: {addr}\r\n\r\n").as_bytes())
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.0 200"));
+    assert!(response.contains("content-type: text/html"));
+    assert!(response.contains("/ws?"));
+}
+
+/// Test that an invalid range is rejected with a JSON error
+#[tokio::test]
+async fn test_api_bad_range() {
+    let addr = serve(StorageHandle::new_in_memory().unwrap()).await;
+    let (status, body) = get(&addr, "/api/tools?range=yesterday-ish").await;
+    assert_eq!(status, 400);
+    assert!(body["error"].as_str().unwrap().contains("Unrecognized"));
+}
+
+/// Test the month-end forecast counts this month's spend so far
+#[tokio::test]
+async fn test_api_forecast() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    storage.record_cost(3.0);
+    // The cost is timestamped when stored, which has to be before the forecast's now
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    let addr = serve(storage).await;
+    let (status, body) = get(&addr, "/api/forecast").await;
+    assert_eq!(status, 200);
+    assert_eq!(body["month_to_date_usd"], 3.0);
+    assert!(body["projected_usd"].as_f64().unwrap() >= 3.0);
+    assert_eq!(body["days_fitted"], 0);
+}
+
+/// Test a storage handle for `view --remote` answering from another agenttop's /api/query
+#[tokio::test]
+async fn test_remote_storage() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    storage.record_log_events(vec![tool_result("Read", 5), tool_result("Read", 3)]);
+    storage.record_token_usage("input", 42);
+    let access = api::Access {
+        sql_token: Some("s3cret".to_string()),
+        ..Default::default()
+    };
+    let addr = serve_with(storage, access).await;
+
+    let remote = tokio::task::spawn_blocking(move || {
+        let url = format!("http://{addr}/");
+        let remote = StorageHandle::connect(&url, None).unwrap();
+        let tools = remote.get_tool_metrics(None, None).unwrap();
+        assert_eq!(tools[0].tool_name, "Read");
+        assert_eq!(tools[0].call_count, 2);
+        assert_eq!(
+            remote.get_token_metrics(None, None).unwrap().input_tokens,
+            42
+        );
+        assert!(!remote.is_empty().unwrap());
+
+        // Raw SQL needs the token even though the rest of the API is open
+        let err = remote
+            .run_query("SELECT count(*) FROM log_events", 10)
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("401"));
+        let remote = StorageHandle::connect(&url, Some("s3cret")).unwrap();
+        let result = remote
+            .run_query("SELECT count(*) FROM log_events", 10)
+            .unwrap();
+        assert_eq!(result.rows, vec![vec![Some("2".to_string())]]);
+        assert!(remote.run_query("DELETE FROM log_events", 10).is_err());
+        assert!(
+            remote
+                .run_query("SELECT * FROM read_text('/etc/passwd')", 10)
+                .is_err()
+        );
+        let err = remote.prune(Utc::now(), true).unwrap_err();
+        assert!(err.to_string().contains("not available in remote view"));
+    });
+    remote.await.unwrap();
+
+    let unreachable =
+        tokio::task::spawn_blocking(|| StorageHandle::connect("http://127.0.0.1:9", None));
+    assert!(unreachable.await.unwrap().is_err());
+}
+
+/// Test /api/query answers repeated queries from the cache until the data changes
+#[tokio::test]
+async fn test_remote_query_cache() {
+    use agenttop::self_metrics::SELF_METRICS;
+
+    let storage = StorageHandle::new_in_memory().unwrap();
+    storage.record_token_usage("output", 7);
+    let addr = serve(storage.clone()).await;
+
+    let remote = tokio::task::spawn_blocking(move || {
+        let remote = StorageHandle::connect(&format!("http://{addr}/"), None).unwrap();
+        let hits = || SELF_METRICS.caches.snapshot()["query_results"].hits;
+        let until = Some(Utc::now() + chrono::Duration::hours(1));
+        let output = || remote.get_token_metrics(None, until).unwrap().output_tokens;
+
+        assert_eq!(output(), 7);
+        let before = hits();
+        assert_eq!(output(), 7);
+        assert!(hits() > before);
+
+        storage.record_model_token_usage("output", 3, Some("other-model"), None);
+        assert_eq!(output(), 10);
+    });
+    remote.await.unwrap();
+}
+
+/// Test that /ws sends aggregates on connect and then pushes new events
+#[tokio::test]
+async fn test_ws_updates() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    storage.record_log_events(vec![tool_result("Read", 5)]);
+    let addr = serve(storage).await;
+
+    let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{addr}/ws?range=1h"))
+        .await
+        .unwrap();
+    let mut next_json = async || loop {
+        match socket.next().await.unwrap().unwrap() {
+            Message::Text(text) => break serde_json::from_str::<serde_json::Value>(&text).unwrap(),
+            _ => continue,
+        }
+    };
+
+    let aggregates = next_json().await;
+    assert_eq!(aggregates["type"], "aggregates");
+    assert_eq!(aggregates["range"], "Last 1h");
+    assert_eq!(aggregates["tools"][0]["tool_name"], "Read");
+    assert!(aggregates["tokens"].is_object());
+
+    EVENT_FEED.send(tool_result("Bash", 0)).unwrap();
+    let event = next_json().await;
+    assert_eq!(event["type"], "event");
+    assert_eq!(event["event"]["attributes"]["tool_name"], "Bash");
+}
+
+/// Test that the event stream is only served when asked for, and that only the OTLP
+/// endpoints answer cross-origin requests
+#[tokio::test]
+async fn test_receiver_routes() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let addr = start_receiver(
+        storage.clone(),
+        ReceiverOptions {
+            api: true,
+            ..Default::default()
+        },
+    )
+    .await;
+    assert!(agenttop::port::probe(addr).await.unwrap().agenttop);
+
+    let stream = format!("GET /events/stream HTTP/1.0\r\nHost: {addr}\r\n\r\n");
+    let (head, _) = exchange(addr, &stream).await;
+    assert!(head.starts_with("http/1.0 404"));
+
+    let preflight = |path: &str| {
+        format!(
+            "OPTIONS {path} HTTP/1.0\r\nHost: {addr}\r\nOrigin: https://example.com\r\n\
+             Access-Control-Request-Method: POST\r\n\r\n"
+        )
+    };
+    let (head, _) = exchange(addr, &preflight("/v1/logs")).await;
+    assert!(head.contains("access-control-allow-origin: *"));
+    let (head, _) = exchange(addr, &preflight("/api/query")).await;
+    assert!(!head.contains("access-control-allow-origin"));
+    let tools =
+        format!("GET /api/tools HTTP/1.0\r\nHost: {addr}\r\nOrigin: https://example.com\r\n\r\n");
+    let (head, _) = exchange(addr, &tools).await;
+    assert!(head.starts_with("http/1.0 200"));
+    assert!(!head.contains("access-control-allow-origin"));
+
+    let addr = start_receiver(
+        storage,
+        ReceiverOptions {
+            event_stream: true,
+            ..Default::default()
+        },
+    )
+    .await;
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(format!("GET /events/stream HTTP/1.0\r\nHost: {addr}\r\n\r\n").as_bytes())
+        .await
+        .unwrap();
+    let mut status = [0; 12];
+    stream.read_exact(&mut status).await.unwrap();
+    assert_eq!(&status, b"HTTP/1.0 200");
+}
+
+/// Test that while an agenttop records into a database, other processes read it through
+/// its /api/query, finding the address and token in the lock file
+#[tokio::test(flavor = "multi_thread")]
+async fn test_attach_reads_through_the_recorder() {
+    use agenttop::storage::lock::{self, InstanceLock};
+
+    let dir = std::env::temp_dir().join(format!("agenttop-recorder-{}", std::process::id()));
+    let path = dir.join("metrics.duckdb");
+    let mut lock = InstanceLock::acquire(&path, true).unwrap();
+    let storage = StorageHandle::open(&path).unwrap();
+    storage.record_log_events(vec![tool_result("Read", 1)]);
+    let token = api::generate_token().unwrap();
+    let addr = start_receiver(
+        storage.clone(),
+        ReceiverOptions {
+            access: api::Access {
+                sql_token: Some(token.clone()),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )
+    .await;
+    lock.set_receiver(addr, Some(&token)).unwrap();
+    assert_eq!(
+        lock::holder(&path).unwrap().owner.unwrap().receiver,
+        Some(addr)
+    );
+
+    // Only /api/query, and only with the token
+    let (head, _) = exchange(
+        addr,
+        &format!("GET /api/tools HTTP/1.0\r\nHost: {addr}\r\n\r\n"),
+    )
+    .await;
+    assert!(head.starts_with("http/1.0 404"));
+    let body = r#"{"query":"is_empty"}"#;
+    let query = format!(
+        "POST /api/query HTTP/1.0\r\nContent-Type: application/json\r\n\
+         Content-Length: {}\r\n\r\n{body}",
+        body.len()
+    );
+    let (head, _) = exchange(addr, &query).await;
+    assert!(head.starts_with("http/1.0 401"));
+
+    let reader_path = path.clone();
+    let reader = tokio::task::spawn_blocking(move || {
+        let reader = StorageHandle::attach(&reader_path).unwrap();
+        assert_eq!(
+            reader.get_tool_metrics(None, None).unwrap()[0].call_count,
+            1
+        );
+        reader
+    })
+    .await
+    .unwrap();
+
+    // The recorder keeps writing, and the reader sees it at once
+    storage.record_log_events(vec![tool_result("Read", 0)]);
+    let reader = tokio::task::spawn_blocking(move || {
+        let result = reader
+            .run_query("SELECT count(*) FROM log_events", 10)
+            .unwrap();
+        assert_eq!(result.rows, vec![vec![Some("2".to_string())]]);
+        assert!(reader.run_query("DELETE FROM log_events", 10).is_err());
+    });
+    reader.await.unwrap();
+
+    drop(lock);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+/// Test that /api/query asks for the configured token and other origins only get the
+/// API when allowed
+#[tokio::test]
+async fn test_api_access() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let access = api::Access {
+        allowed_origins: vec!["https://grafana.example.com".to_string()],
+        token: Some("s3cret".to_string()),
+        ..Default::default()
+    };
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    tokio::spawn(async move {
+        axum::serve(listener, api::router(access).with_state(storage))
+            .await
+            .unwrap();
+    });
+
+    let url = format!("http://{addr}/");
+    let remote = tokio::task::spawn_blocking(move || {
+        let err = StorageHandle::connect(&url, None).err().unwrap();
+        assert!(format!("{:#}", err).contains("401"));
+        assert!(StorageHandle::connect(&url, Some("wrong")).is_err());
+        let remote = StorageHandle::connect(&url, Some("s3cret")).unwrap();
+        assert!(remote.is_empty().unwrap());
+    });
+    remote.await.unwrap();
+
+    let tools = |origin: &str| {
+        format!("GET /api/tools HTTP/1.0\r\nHost: {addr}\r\nOrigin: {origin}\r\n\r\n")
+    };
+    let (head, _) = exchange(&addr, &tools("https://grafana.example.com")).await;
+    assert!(head.contains("access-control-allow-origin: https://grafana.example.com"));
+    let (head, _) = exchange(&addr, &tools("https://evil.example.com")).await;
+    assert!(!head.contains("access-control-allow-origin"));
+}
+
+/// Test that /ws refuses pages from other origins unless they are configured
+#[tokio::test]
+async fn test_ws_origin() {
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+    let access = api::Access {
+        allowed_origins: vec!["https://grafana.example.com".to_string()],
+        ..Default::default()
+    };
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let storage = StorageHandle::new_in_memory().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, api::router(access).with_state(storage))
+            .await
+            .unwrap();
+    });
+
+    let connect = |origin: Option<String>| {
+        let mut request = format!("ws://{addr}/ws").into_client_request().unwrap();
+        if let Some(origin) = origin {
+            request
+                .headers_mut()
+                .insert("Origin", origin.parse().unwrap());
+        }
+        tokio_tungstenite::connect_async(request)
+    };
+    assert!(connect(None).await.is_ok());
+    assert!(connect(Some(format!("http://{addr}"))).await.is_ok());
+    assert!(
+        connect(Some("http://localhost:3000".to_string()))
+            .await
+            .is_ok()
+    );
+    assert!(
+        connect(Some("https://grafana.example.com".to_string()))
+            .await
+            .is_ok()
+    );
+    assert!(
+        connect(Some("https://evil.example.com".to_string()))
+            .await
+            .is_err()
+    );
+    assert!(
+        connect(Some("http://localhost.evil.example.com".to_string()))
+            .await
+            .is_err()
+    );
+}
@@ -142,3 +142,327 @@ fn test_missing_telemetry_config() {
     let telemetry = existing.get("telemetry");
     assert!(telemetry.is_none());
 }
+
+/// Test the agenttop config file loads key overrides and defaults when missing
+#[test]
+fn test_app_config_load_from() {
+    use agenttop::config::AppConfig;
+
+    let dir = std::env::temp_dir().join(format!("agenttop-config-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("config.json");
+
+    assert_eq!(AppConfig::load_from(&path).unwrap(), AppConfig::default());
+
+    std::fs::write(&path, r#"{"keys": {"<C-n>": "down", "q": "none"}}"#).unwrap();
+    let config = AppConfig::load_from(&path).unwrap();
+    assert_eq!(config.keys.get("<C-n>").map(String::as_str), Some("down"));
+    assert_eq!(config.keys.get("q").map(String::as_str), Some("none"));
+
+    std::fs::write(&path, r#"{"latency_slos_ms": {"Read": 100, "Bash": 5000}}"#).unwrap();
+    let config = AppConfig::load_from(&path).unwrap();
+    assert_eq!(config.latency_slos_ms.get("Bash"), Some(&5000));
+
+    std::fs::write(
+        &path,
+        r#"{"otlp_export": {"endpoint": "https://otel.example.com", "headers": {"x-api-key": "k"}}}"#,
+    )
+    .unwrap();
+    let export = AppConfig::load_from(&path).unwrap().otlp_export.unwrap();
+    assert_eq!(export.endpoint, "https://otel.example.com");
+    assert_eq!(export.interval_secs, 60);
+    assert_eq!(
+        export.headers.get("x-api-key").map(String::as_str),
+        Some("k")
+    );
+
+    std::fs::write(
+        &path,
+        r#"{"influx_export": {"url": "http://nas:8086/write?db=agenttop", "tags": {"team": "infra"}}}"#,
+    )
+    .unwrap();
+    let influx = AppConfig::load_from(&path).unwrap().influx_export.unwrap();
+    assert_eq!(influx.url, "http://nas:8086/write?db=agenttop");
+    assert_eq!(influx.token, None);
+    assert_eq!(influx.interval_secs, 60);
+    assert_eq!(influx.tags.get("team").map(String::as_str), Some("infra"));
+
+    std::fs::write(
+        &path,
+        r#"{"graphite_export": {"address": "graphite:2003"}}"#,
+    )
+    .unwrap();
+    let graphite = AppConfig::load_from(&path)
+        .unwrap()
+        .graphite_export
+        .unwrap();
+    assert_eq!(graphite.address, "graphite:2003");
+    assert_eq!(graphite.prefix, "agenttop");
+
+    std::fs::write(
+        &path,
+        r#"{"mqtt": {"broker": "broker:1883", "cost_topic": ""}}"#,
+    )
+    .unwrap();
+    let mqtt = AppConfig::load_from(&path).unwrap().mqtt.unwrap();
+    assert_eq!(mqtt.broker, "broker:1883");
+    assert_eq!(mqtt.tool_result_topic, "agenttop/tool_result");
+    assert_eq!(mqtt.cost_topic, "");
+    assert_eq!(mqtt.cost_interval_secs, 30);
+
+    std::fs::write(
+        &path,
+        r#"{"budgets": [{"period": "weekly", "limit_usd": 50, "hook": "revoke-key.sh"}]}"#,
+    )
+    .unwrap();
+    let budget = &AppConfig::load_from(&path).unwrap().budgets[0];
+    assert_eq!(budget.period, agenttop::budget::BudgetPeriod::Weekly);
+    assert_eq!(budget.warn_pct, 75.0);
+    assert_eq!(budget.hook.as_deref(), Some("revoke-key.sh"));
+
+    std::fs::write(
+        &path,
+        r#"{"quotas": [{"provider": "claude_code", "limit_tokens": 4000000}]}"#,
+    )
+    .unwrap();
+    let quota = &AppConfig::load_from(&path).unwrap().quotas[0];
+    assert_eq!(quota.provider.as_deref(), Some("claude_code"));
+    assert_eq!(quota.window_hours, 5.0);
+    assert_eq!(quota.window(), chrono::Duration::hours(5));
+    assert_eq!(quota.limit_usd, None);
+
+    std::fs::write(
+        &path,
+        r#"{"context_windows": {"sonnet-4-5": 1000000, "my-model": 0}}"#,
+    )
+    .unwrap();
+    let windows = AppConfig::load_from(&path).unwrap().context_windows;
+    assert_eq!(windows["sonnet-4-5"], 1_000_000);
+    let err = agenttop::context::set_windows(&windows).unwrap_err();
+    assert!(err.to_string().contains("\"my-model\""), "{}", err);
+
+    std::fs::write(
+        &path,
+        r#"{"digest": {"from": "a@example.com", "to": ["b@example.com"], "smtp": {"host": "smtp.example.com"}}}"#,
+    )
+    .unwrap();
+    let digest = AppConfig::load_from(&path).unwrap().digest.unwrap();
+    assert_eq!(digest.period, agenttop::budget::BudgetPeriod::Daily);
+    assert_eq!(digest.hour, 8);
+    assert_eq!(
+        digest.smtp.security,
+        agenttop::digest::smtp::SmtpSecurity::Starttls
+    );
+
+    std::fs::write(
+        &path,
+        r#"{"hooks": [{"name": "push", "tool": "Bash", "attributes": {"tool_parameters": "git push"}, "command": "say pushed"}]}"#,
+    )
+    .unwrap();
+    let hook = &AppConfig::load_from(&path).unwrap().hooks[0];
+    assert_eq!(hook.event, None);
+    assert_eq!(hook.tool.as_deref(), Some("Bash"));
+    assert_eq!(hook.attributes["tool_parameters"], "git push");
+
+    std::fs::write(&path, "{not json").unwrap();
+    assert!(AppConfig::load_from(&path).is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Test that pinned tools survive a save/load round trip alongside key overrides
+#[test]
+fn test_app_config_save_pins() {
+    use agenttop::config::AppConfig;
+
+    let dir = std::env::temp_dir().join(format!("agenttop-pins-{}", std::process::id()));
+    let path = dir.join("nested").join("config.json");
+
+    let mut config = AppConfig::default();
+    config.keys.insert("<C-n>".to_string(), "down".to_string());
+    config.pinned_tools = vec!["Bash".to_string(), "mcp__github__search".to_string()];
+    config.save_to(&path).unwrap();
+
+    assert_eq!(AppConfig::load_from(&path).unwrap(), config);
+
+    // Empty sections are left out of the file
+    AppConfig::default().save_to(&path).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap().trim(), "{}");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Test loading config.toml sections next to the top-level AppConfig keys
+#[test]
+fn test_settings_load_toml() {
+    use agenttop::config::{Settings, Theme};
+
+    let dir = std::env::temp_dir().join(format!("agenttop-settings-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("config.toml");
+
+    std::fs::write(
+        &path,
+        r#"
+pinned_tools = ["Bash"]
+
+[receiver]
+bind = "0.0.0.0:14318"
+max_attribute_bytes = 1024
+
+[storage]
+path = "/var/lib/agenttop/metrics.duckdb"
+retention_days = 30
+query_timeout_secs = 0
+journal = true
+
+[cache]
+query_results = 0
+recent_events = 200
+
+[tui]
+refresh_ms = 500
+theme = "mono"
+
+[keys]
+"<C-n>" = "down"
+
+[[budgets]]
+period = "daily"
+limit_usd = 10
+
+[[alert_rules]]
+name = "spend"
+condition = { type = "daily_cost", above_usd = 20 }
+webhook = { url = "https://hooks.example.com/x" }
+"#,
+    )
+    .unwrap();
+    let settings = Settings::load(Some(&path)).unwrap();
+    assert_eq!(settings.receiver.bind.to_string(), "0.0.0.0:14318");
+    assert_eq!(settings.receiver.limits().max_attribute_bytes, 1024);
+    assert_eq!(settings.receiver.limits().max_body_bytes, 65536);
+    assert_eq!(
+        settings.db_path().unwrap(),
+        PathBuf::from("/var/lib/agenttop/metrics.duckdb")
+    );
+    assert_eq!(settings.storage.retention_days, Some(30));
+    assert_eq!(settings.storage.query_timeout(), None);
+    assert!(settings.storage.journal);
+    assert_eq!(settings.cache.query_results, 0);
+    assert_eq!(settings.cache.recent_events, 200);
+    assert_eq!(
+        settings.cache.token_rows,
+        agenttop::cache::DEFAULT_TOKEN_ROWS
+    );
+    assert_eq!(
+        settings.tui.refresh_interval(),
+        std::time::Duration::from_millis(500)
+    );
+    assert_eq!(settings.tui.theme, Theme::Mono);
+    assert_eq!(settings.app.pinned_tools, vec!["Bash".to_string()]);
+    assert_eq!(settings.app.keys["<C-n>"], "down");
+    assert_eq!(settings.app.budgets[0].limit_usd, 10.0);
+    assert_eq!(settings.app.alert_rules[0].name, "spend");
+    assert_eq!(settings.path.as_deref(), Some(path.as_path()));
+
+    // Missing sections fall back to the defaults
+    std::fs::write(&path, "").unwrap();
+    let settings = Settings::load_from(&path).unwrap();
+    assert_eq!(settings.receiver.bind.to_string(), "127.0.0.1:4318");
+    assert_eq!(settings.receiver.max_attribute_bytes, 65536);
+    assert_eq!(settings.storage.retention_days, None);
+    assert_eq!(
+        settings.storage.query_timeout(),
+        Some(std::time::Duration::from_secs(30))
+    );
+    assert_eq!(settings.tui.refresh_ms, 1000);
+
+    // The older JSON format is still read
+    let json = dir.join("config.json");
+    std::fs::write(&json, r#"{"pinned_tools": ["Read"]}"#).unwrap();
+    let settings = Settings::load(Some(&json)).unwrap();
+    assert_eq!(settings.app.pinned_tools, vec!["Read".to_string()]);
+    assert_eq!(settings.tui.theme, Theme::Default);
+
+    std::fs::write(&path, "[receiver]\nbind = \"not an address\"").unwrap();
+    assert!(Settings::load_from(&path).is_err());
+    assert!(Settings::load(Some(&dir.join("missing.toml"))).is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Test that saving pins rewrites only `pinned_tools` in config.toml
+#[test]
+fn test_settings_save_pins_keeps_comments() {
+    use agenttop::config::Settings;
+
+    let dir = std::env::temp_dir().join(format!("agenttop-settings-pins-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("config.toml");
+    std::fs::write(&path, "# my settings\n[tui]\nrefresh_ms = 250 # fast\n").unwrap();
+
+    let mut settings = Settings::load_from(&path).unwrap();
+    settings.app.pinned_tools = vec!["Bash".to_string(), "Read".to_string()];
+    settings.save_pinned_tools().unwrap();
+
+    let content = std::fs::read_to_string(&path).unwrap();
+    assert!(content.contains("# my settings"));
+    assert!(content.contains("refresh_ms = 250 # fast"));
+    let reloaded = Settings::load_from(&path).unwrap();
+    assert_eq!(reloaded.app.pinned_tools, settings.app.pinned_tools);
+    assert_eq!(reloaded.tui.refresh_ms, 250);
+
+    settings.app.pinned_tools.clear();
+    settings.save_pinned_tools().unwrap();
+    assert!(
+        !std::fs::read_to_string(&path)
+            .unwrap()
+            .contains("pinned_tools")
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Test that AGENTTOP_* variables override the config file
+#[test]
+fn test_settings_env_overrides() {
+    use agenttop::config::{Settings, Theme};
+    use std::collections::HashMap;
+
+    let env = HashMap::from([
+        ("AGENTTOP_BIND", "0.0.0.0:4318"),
+        ("AGENTTOP_API_TOKEN", "s3cret"),
+        ("AGENTTOP_DB_PATH", "/data/metrics.duckdb"),
+        ("AGENTTOP_RETENTION_DAYS", "14"),
+        ("AGENTTOP_REFRESH_MS", "2000"),
+        ("AGENTTOP_THEME", "Mono"),
+    ]);
+    let mut settings = Settings::default();
+    settings.storage.retention_days = Some(30);
+    settings
+        .apply_env(|name| env.get(name).map(|value| value.to_string()))
+        .unwrap();
+    assert_eq!(settings.receiver.bind.to_string(), "0.0.0.0:4318");
+    assert_eq!(settings.receiver.api_token.as_deref(), Some("s3cret"));
+    assert_eq!(
+        settings.db_path().unwrap(),
+        PathBuf::from("/data/metrics.duckdb")
+    );
+    assert_eq!(settings.storage.retention_days, Some(14));
+    assert_eq!(settings.tui.refresh_ms, 2000);
+    assert_eq!(settings.tui.theme, Theme::Mono);
+
+    // Unset and empty variables leave the file's values alone
+    let mut settings = Settings::default();
+    settings.tui.refresh_ms = 250;
+    settings
+        .apply_env(|name| (name == "AGENTTOP_REFRESH_MS").then(String::new))
+        .unwrap();
+    assert_eq!(settings.tui.refresh_ms, 250);
+
+    let error = Settings::default()
+        .apply_env(|name| (name == "AGENTTOP_BIND").then(|| "localhost".to_string()))
+        .unwrap_err();
+    assert!(error.to_string().contains("AGENTTOP_BIND"));
+}
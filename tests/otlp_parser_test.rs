@@ -315,7 +315,10 @@ fn test_parse_token_metrics_string_int() {
                     "sum": {
                         "dataPoints": [{
                             "asInt": "5000",
-                            "attributes": [{"key": "type", "value": {"stringValue": "input"}}]
+                            "attributes": [
+                                {"key": "type", "value": {"stringValue": "input"}},
+                                {"key": "model", "value": {"stringValue": "claude-sonnet-4-5"}}
+                            ]
                         }]
                     }
                 }]
@@ -326,9 +329,16 @@ fn test_parse_token_metrics_string_int() {
     let metrics = parse_metrics(json.as_bytes()).unwrap();
     assert_eq!(metrics.len(), 1);
     match &metrics[0] {
-        ParsedMetric::TokenUsage { token_type, count } => {
+        ParsedMetric::TokenUsage {
+            token_type,
+            count,
+            model,
+            provider,
+        } => {
             assert_eq!(token_type, "input");
             assert_eq!(*count, 5000);
+            assert_eq!(model.as_deref(), Some("claude-sonnet-4-5"));
+            assert_eq!(provider.as_deref(), Some("claude_code"));
         }
         _ => panic!("Expected TokenUsage metric"),
     }
@@ -353,8 +363,53 @@ fn test_parse_cost_metrics() {
     let metrics = parse_metrics(json.as_bytes()).unwrap();
     assert_eq!(metrics.len(), 1);
     match &metrics[0] {
-        ParsedMetric::CostUsage { cost_usd } => {
+        ParsedMetric::CostUsage {
+            cost_usd,
+            cumulative,
+            ..
+        } => {
             assert!((*cost_usd - 0.0523).abs() < 0.0001);
+            assert!(!cumulative);
+        }
+        _ => panic!("Expected CostUsage metric"),
+    }
+}
+
+/// Test parsing a cumulative cost metric keeps its session, model and temporality
+#[test]
+fn test_parse_cumulative_cost_metric() {
+    let json = r#"{
+        "resourceMetrics": [{
+            "scopeMetrics": [{
+                "metrics": [{
+                    "name": "claude_code.cost.usage",
+                    "sum": {
+                        "aggregationTemporality": 2,
+                        "dataPoints": [{
+                            "asDouble": 1.25,
+                            "attributes": [
+                                {"key": "session.id", "value": {"stringValue": "sess-1"}},
+                                {"key": "model", "value": {"stringValue": "claude-sonnet-4-5"}}
+                            ]
+                        }]
+                    }
+                }]
+            }]
+        }]
+    }"#;
+
+    let metrics = parse_metrics(json.as_bytes()).unwrap();
+    match &metrics[0] {
+        ParsedMetric::CostUsage {
+            cost_usd,
+            session_id,
+            model,
+            cumulative,
+        } => {
+            assert!((*cost_usd - 1.25).abs() < 0.0001);
+            assert_eq!(session_id.as_deref(), Some("sess-1"));
+            assert_eq!(model.as_deref(), Some("claude-sonnet-4-5"));
+            assert!(cumulative);
         }
         _ => panic!("Expected CostUsage metric"),
     }
@@ -378,6 +433,31 @@ fn test_parse_session_metrics_json() {
     assert_eq!(metrics.len(), 2);
 }
 
+/// Test session metrics keep the session they were reported for
+#[test]
+fn test_parse_session_metric_session_id() {
+    let json = r#"{
+        "resourceMetrics": [{
+            "scopeMetrics": [{
+                "metrics": [{
+                    "name": "claude_code.active_time.total",
+                    "sum": {"dataPoints": [{
+                        "asInt": 42,
+                        "attributes": [{"key": "session.id", "value": {"stringValue": "s1"}}]
+                    }]}
+                }]
+            }]
+        }]
+    }"#;
+
+    let metrics = parse_metrics(json.as_bytes()).unwrap();
+    assert!(matches!(
+        &metrics[0],
+        ParsedMetric::SessionMetric { name, value: 42, session_id: Some(id) }
+            if name == "active_time" && id == "s1"
+    ));
+}
+
 // =============================================================================
 // Error Handling Tests
 // =============================================================================
@@ -0,0 +1,836 @@
+//! Report tests
+//!
+//! These tests render the `agenttop report` text from in-memory storage.
+
+use agenttop::report;
+use agenttop::report::check::{self, Policy};
+use agenttop::report::stats;
+use agenttop::storage::{LogEvent, StorageHandle};
+use agenttop::tui::app::TimeFilter;
+use chrono::Utc;
+
+fn make_event(name: &str, attrs: &[(&str, &str)]) -> LogEvent {
+    LogEvent {
+        timestamp: Utc::now(),
+        event_name: Some(name.to_string()),
+        body: None,
+        attributes: attrs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+    }
+}
+
+/// Test that the report includes totals, tools, model costs and top errors
+#[test]
+fn test_report_sections() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    storage.record_log_events(vec![
+        make_event(
+            "tool_result",
+            &[
+                ("tool_name", "Read"),
+                ("success", "true"),
+                ("duration_ms", "20"),
+            ],
+        ),
+        make_event(
+            "tool_result",
+            &[
+                ("tool_name", "Bash"),
+                ("success", "false"),
+                ("duration_ms", "1500"),
+                ("error", "command not found"),
+            ],
+        ),
+        make_event(
+            "claude_code.api_request",
+            &[("model", "claude-sonnet-4-5"), ("cost_usd", "1.5")],
+        ),
+    ]);
+    storage.record_cost(1.5);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let text = report::render(&storage, &TimeFilter::Last24Hours).unwrap();
+    assert!(text.starts_with("agenttop report · Last 24h"));
+    assert!(text.contains("Tool calls  2 (1 errors, 50.0%)"));
+    assert!(text.contains("Cost        $1.50"));
+    assert!(
+        text.lines()
+            .any(|l| l.contains("Bash") && l.contains("1.5s"))
+    );
+    assert!(text.contains("Cost by model"));
+    assert!(
+        text.lines()
+            .any(|l| l.contains("claude-sonnet-4-5") && l.contains("$1.50"))
+    );
+    assert!(text.contains("1× Bash: command not found"));
+}
+
+/// Test that an empty database still produces the totals section only
+#[test]
+fn test_report_empty() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let text = report::render(&storage, &TimeFilter::AllTime).unwrap();
+    assert!(text.contains("beginning"));
+    assert!(text.contains("Tool calls  0 (0 errors, 0.0%)"));
+    assert!(!text.contains("Tools\n"));
+    assert!(!text.contains("Top errors"));
+}
+
+/// Test the one-line headless summary
+#[test]
+fn test_summary_line() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let since = Utc::now() - chrono::Duration::minutes(1);
+    assert_eq!(
+        report::summary_line(&storage, since, 0).unwrap(),
+        "0 events · tokens in 0 / out 0 · cost $0.00 · top tool none"
+    );
+
+    storage.record_log_events(vec![
+        make_event("tool_result", &[("tool_name", "Read"), ("success", "true")]),
+        make_event("tool_result", &[("tool_name", "Bash"), ("success", "true")]),
+        make_event("tool_result", &[("tool_name", "Read"), ("success", "true")]),
+    ]);
+    storage.record_token_usage("input", 1500);
+    storage.record_cost(0.25);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    assert_eq!(
+        report::summary_line(&storage, since, 5).unwrap(),
+        "5 events · tokens in 1.5K / out 0 · cost $0.25 · top tool Read (2 calls)"
+    );
+}
+
+/// Test the --once policy checks
+#[test]
+fn test_check_policy() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    storage.record_log_events(vec![
+        make_event("tool_result", &[("tool_name", "Read"), ("success", "true")]),
+        make_event(
+            "tool_result",
+            &[("tool_name", "Bash"), ("success", "false")],
+        ),
+    ]);
+    storage.record_cost(2.0);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let unchecked = check::run(&storage, &TimeFilter::AllTime, &Policy::default()).unwrap();
+    assert!(unchecked.passed);
+    assert!(unchecked.render_text().ends_with("PASS\n"));
+
+    let policy = Policy {
+        max_error_rate_pct: Some(25.0),
+        max_api_errors: Some(0),
+        max_cost_usd: Some(1.0),
+    };
+    let result = check::run(&storage, &TimeFilter::AllTime, &policy).unwrap();
+    assert!(!result.passed);
+    let checks: Vec<_> = result.violations.iter().map(|v| v.check).collect();
+    assert_eq!(checks, vec!["error_rate", "cost"]);
+    assert!(
+        result
+            .render_text()
+            .contains("FAIL Tool error rate 50.0% is above 25.0% (1 of 2 calls failed)")
+    );
+
+    let json = serde_json::to_value(&result).unwrap();
+    assert_eq!(json["passed"], false);
+    assert_eq!(json["cost_usd"], 2.0);
+}
+
+/// Test the `agenttop stats` totals and JSON field names
+#[test]
+fn test_stats() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    storage.record_log_events(vec![
+        make_event(
+            "tool_result",
+            &[
+                ("tool_name", "Read"),
+                ("success", "true"),
+                ("session.id", "s1"),
+            ],
+        ),
+        make_event(
+            "tool_result",
+            &[
+                ("tool_name", "Bash"),
+                ("success", "false"),
+                ("session.id", "s2"),
+            ],
+        ),
+        make_event(
+            "tool_result",
+            &[
+                ("tool_name", "Bash"),
+                ("success", "true"),
+                ("session.id", "s2"),
+            ],
+        ),
+        make_event("claude_code.api_error", &[("status_code", "429")]),
+    ]);
+    storage.record_token_usage("input", 100);
+    storage.record_token_usage("output", 20);
+    storage.record_cost(0.5);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let stats = stats::collect(&storage, None, None).unwrap();
+    let json = serde_json::to_value(&stats).unwrap();
+    assert_eq!(json["schema_version"], stats::SCHEMA_VERSION);
+    assert_eq!(json["since"], serde_json::Value::Null);
+    assert_eq!(json["tokens"]["total"], 120);
+    assert_eq!(json["cost_usd"], 0.5);
+    assert_eq!(json["tools"]["calls"], 3);
+    assert_eq!(json["tools"]["errors"], 1);
+    assert_eq!(json["tools"]["by_tool"][0]["name"], "Bash");
+    assert_eq!(json["api"]["errors"], 1);
+    assert_eq!(json["api"]["rate_limited"], 1);
+    assert_eq!(json["sessions"], 2);
+
+    assert!(stats.render_text().contains("tools.errors           1\n"));
+}
+
+/// Test the per-session report as markdown, looked up by an id prefix
+#[test]
+fn test_session_report_markdown() {
+    use report::session::{ReportFormat, render};
+
+    let storage = StorageHandle::new_in_memory().unwrap();
+    storage.record_log_events(vec![
+        make_event(
+            "tool_result",
+            &[
+                ("tool_name", "Edit"),
+                ("success", "true"),
+                ("session.id", "abc-123"),
+            ],
+        ),
+        make_event(
+            "tool_result",
+            &[
+                ("tool_name", "Bash"),
+                ("success", "false"),
+                ("error", "exit 1 | grep"),
+                ("session.id", "abc-123"),
+            ],
+        ),
+        make_event(
+            "claude_code.api_request",
+            &[
+                ("model", "claude-sonnet-4-5"),
+                ("cost_usd", "0.25"),
+                ("input_tokens", "1200"),
+                ("output_tokens", "300"),
+                ("session.id", "abc-123"),
+            ],
+        ),
+        // Another session, not in the report
+        make_event(
+            "tool_result",
+            &[
+                ("tool_name", "Read"),
+                ("success", "true"),
+                ("session.id", "abd-456"),
+            ],
+        ),
+    ]);
+    storage.record_session_metric("lines_of_code", 42);
+    storage.record_session_metric("commits", 1);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let md = render(&storage, "abc", ReportFormat::Md).unwrap();
+    assert!(md.starts_with("### AI assistance\n"));
+    assert!(md.contains("Agent session `abc-123`"));
+    assert!(md.contains("| Cost | $0.25 |"));
+    assert!(md.contains("| Tool calls | 2 (1 errors) |"));
+    assert!(md.contains("| Tokens | in 1.2K · out 300 · cache read 0 |"));
+    assert!(md.contains("| Code | +42 lines · 1 commits |"));
+    assert!(md.contains("| Bash | 1 | 1 |"));
+    assert!(!md.contains("Read"));
+    assert!(md.contains("- 1× Bash: `exit 1 | grep`"));
+
+    let text = render(&storage, "abc-123", ReportFormat::Text).unwrap();
+    assert!(text.starts_with("agenttop session abc-123\n"));
+    assert!(text.contains("Tool calls  2 (1 errors, 50.0%)"));
+
+    assert!(render(&storage, "ab", ReportFormat::Md).is_err());
+    assert!(render(&storage, "zzz", ReportFormat::Md).is_err());
+}
+
+/// Test spend grouped by model, project and day
+#[test]
+fn test_cost_group_by() {
+    use report::cost::{CostGroup, collect};
+
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let request = |model: &str, cost: &str, project: Option<&str>| {
+        let mut attrs = vec![
+            ("model", model),
+            ("cost_usd", cost),
+            ("input_tokens", "100"),
+        ];
+        if let Some(project) = project {
+            attrs.push(("project", project));
+        }
+        make_event("claude_code.api_request", &attrs)
+    };
+    storage.record_log_events(vec![
+        request("claude-haiku-4-5", "0.25", Some("web")),
+        request("claude-sonnet-4-5", "1.0", Some("api")),
+        request("claude-sonnet-4-5", "0.5", None),
+        make_event("tool_result", &[("tool_name", "Read")]),
+    ]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let by_model = collect(&storage, CostGroup::Model, None, None).unwrap();
+    assert_eq!(by_model.rows.len(), 2);
+    assert_eq!(by_model.rows[0].key, "claude-sonnet-4-5");
+    assert_eq!(by_model.rows[0].calls, 2);
+    assert_eq!(by_model.rows[0].cost_usd, 1.5);
+    assert_eq!(by_model.total.cost_usd, 1.75);
+    assert_eq!(by_model.total.input_tokens, 300);
+
+    let by_project = collect(&storage, CostGroup::Project, None, None).unwrap();
+    let keys: Vec<&str> = by_project.rows.iter().map(|r| r.key.as_str()).collect();
+    assert_eq!(keys, ["api", "(none)", "web"]);
+
+    let by_day = collect(&storage, CostGroup::Day, None, None).unwrap();
+    let today = chrono::Local::now().date_naive().to_string();
+    assert_eq!(by_day.rows.len(), 1);
+    assert_eq!(by_day.rows[0].key, today);
+    let text = by_day.render_text();
+    assert!(text.starts_with("DAY"));
+    assert!(
+        text.lines()
+            .any(|l| l.starts_with("TOTAL") && l.ends_with("$1.75"))
+    );
+
+    let json = serde_json::to_value(&by_day).unwrap();
+    assert_eq!(json["group_by"], "day");
+    assert_eq!(json["rows"][0]["calls"], 3);
+    // Up to now, so with this month's forecast
+    assert!(json["forecast"]["projected_usd"].is_number());
+}
+
+/// Test productivity is totalled per week or per session, and the summary report shows
+/// the range's ratios
+#[test]
+fn test_productivity_report() {
+    use report::productivity::{ProductivityGroup, collect};
+
+    let storage = StorageHandle::new_in_memory().unwrap();
+    storage.record_log_events(vec![
+        make_event(
+            "claude_code.api_request",
+            &[("session.id", "a"), ("cost_usd", "2.0")],
+        ),
+        make_event(
+            "claude_code.api_request",
+            &[("session.id", "b"), ("cost_usd", "0.5")],
+        ),
+    ]);
+    storage.record_session_metric_for(Some("a"), "lines_of_code", 300);
+    storage.record_session_metric_for(Some("a"), "commit", 2);
+    storage.record_session_metric_for(Some("a"), "active_time", 1800);
+    storage.record_session_metric_for(Some("b"), "lines_of_code", 50);
+    storage.record_session_metric_for(Some("b"), "active_time", 1800);
+    // The summary's cost comes from the cost metric
+    storage.record_cost(2.5);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let by_week = collect(&storage, ProductivityGroup::Week, None, None).unwrap();
+    assert_eq!(by_week.rows.len(), 1);
+    let total = &by_week.total;
+    assert_eq!(total.productivity.sessions, 2);
+    assert_eq!(total.productivity.lines_of_code, 350);
+    assert_eq!(total.lines_per_usd, Some(140.0));
+    assert_eq!(total.commits_per_hour, Some(2.0));
+    assert_eq!(total.cost_per_commit, Some(1.25));
+    let text = by_week.render_text();
+    assert!(text.starts_with("WEEK OF"), "{}", text);
+    assert!(
+        text.lines()
+            .any(|l| l.starts_with("TOTAL") && l.ends_with("$1.25"))
+    );
+
+    let by_session = collect(&storage, ProductivityGroup::Session, None, None).unwrap();
+    assert_eq!(by_session.rows.len(), 2);
+    let b = by_session.rows.iter().find(|r| r.key == "b").unwrap();
+    assert_eq!(b.cost_per_commit, None);
+    let json = serde_json::to_value(&by_session).unwrap();
+    assert_eq!(json["group_by"], "session");
+    assert_eq!(json["total"]["commits"], 2);
+    assert!(json["rows"][0]["lines_per_usd"].is_number());
+
+    let summary = report::render(&storage, &TimeFilter::AllTime).unwrap();
+    assert!(
+        summary.contains("Output      140 lines/$ · 2.0 commits/h · $1.25/commit"),
+        "{}",
+        summary
+    );
+}
+
+/// Test commits made during a session in the repository are attributed to it, with the
+/// session's spend since its previous commit
+#[test]
+fn test_commit_report() {
+    use std::process::Command;
+
+    let repo = std::env::temp_dir().join(format!("agenttop-commits-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&repo);
+    std::fs::create_dir_all(repo.join("src")).unwrap();
+    let git = |args: &[&str], at: chrono::DateTime<Utc>| {
+        let date = format!("{} +0000", at.timestamp());
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(&repo)
+            .args(args)
+            .env("GIT_AUTHOR_DATE", &date)
+            .env("GIT_COMMITTER_DATE", &date)
+            .env("GIT_AUTHOR_NAME", "Dev")
+            .env("GIT_AUTHOR_EMAIL", "dev@example.com")
+            .env("GIT_COMMITTER_NAME", "Dev")
+            .env("GIT_COMMITTER_EMAIL", "dev@example.com")
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {:?}", args);
+    };
+    let now = Utc::now();
+    let minutes_ago = |m: i64| now - chrono::Duration::minutes(m);
+    let commit = |file: &str, lines: usize, message: &str, at| {
+        std::fs::write(repo.join(file), "line\n".repeat(lines)).unwrap();
+        git(&["add", "-A"], at);
+        git(&["commit", "-q", "-m", message], at);
+    };
+
+    git(&["init", "-q"], minutes_ago(120));
+    commit("README.md", 1, "Initial commit", minutes_ago(120));
+    commit("src/a.rs", 10, "Add a", minutes_ago(50));
+    commit("src/b.rs", 4, "Add b", minutes_ago(30));
+    commit("notes.txt", 2, "Notes by hand", minutes_ago(5));
+
+    let cwd = repo.join("src").display().to_string();
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let request = |m: i64, cost: &str| LogEvent {
+        timestamp: minutes_ago(m),
+        ..make_event(
+            "claude_code.api_request",
+            &[("cost_usd", cost), ("session.id", "s1"), ("cwd", &cwd)],
+        )
+    };
+    storage.record_log_events(vec![
+        request(60, "1.0"),
+        request(55, "0.5"),
+        request(40, "2.0"),
+        request(32, "0.25"),
+    ]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let reports = report::commits::collect(&storage, Some(&repo), None, None).unwrap();
+    assert_eq!(reports.len(), 1);
+    let report = &reports[0];
+    // The initial commit predates the first API call
+    let summaries: Vec<&str> = report
+        .commits
+        .iter()
+        .map(|c| c.commit.summary.as_str())
+        .collect();
+    assert_eq!(summaries, ["Add a", "Add b", "Notes by hand"]);
+    assert_eq!(report.commits[0].session_id.as_deref(), Some("s1"));
+    assert_eq!(report.commits[0].cost_usd, Some(1.5));
+    assert_eq!(report.commits[0].commit.insertions, 10);
+    assert_eq!(report.commits[1].cost_usd, Some(2.25));
+    assert!(!report.commits[2].assisted());
+    assert_eq!(report.assisted_commits, 2);
+    assert_eq!(report.assisted_lines, 14);
+    assert_eq!(report.cost_per_commit(), Some(1.875));
+
+    let text = report.render_text();
+    assert!(
+        text.contains("2 of 3 commits AI-assisted (67%), 14 lines, $3.75"),
+        "{}",
+        text
+    );
+    assert!(text.contains("$1.88/commit"));
+
+    // Without --repo, found from the sessions' working directory
+    let found = report::commits::collect(&storage, None, None, None).unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].commits.len(), 3);
+
+    std::fs::remove_dir_all(&repo).unwrap();
+}
+
+/// Test budgets count their scope's spend, and recording usage closes earlier periods
+#[test]
+fn test_budget_report() {
+    use agenttop::budget::{self, Budget, BudgetLevel, BudgetPeriod, BudgetScope};
+    use agenttop::storage::BudgetUsage;
+    use chrono::Local;
+
+    let storage = StorageHandle::new_in_memory().unwrap();
+    storage.record_log_events(vec![
+        make_event(
+            "claude_code.api_request",
+            &[("cost_usd", "1.0"), ("project", "web")],
+        ),
+        make_event(
+            "codex.api_request",
+            &[("cost_usd", "0.5"), ("project", "api")],
+        ),
+    ]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let scoped = |period, limit_usd, scope| Budget {
+        period,
+        limit_usd,
+        scope,
+        warn_pct: 75.0,
+        critical_pct: 90.0,
+        hook: None,
+    };
+    let budgets = vec![
+        scoped(
+            BudgetPeriod::Daily,
+            2.0,
+            BudgetScope::Provider("claude_code".to_string()),
+        ),
+        scoped(
+            BudgetPeriod::Weekly,
+            0.4,
+            BudgetScope::Project("api".to_string()),
+        ),
+    ];
+    let now = Local::now();
+
+    // A period left open by an earlier run
+    let today = BudgetPeriod::Daily.start(now).unwrap();
+    let yesterday = today - chrono::Duration::days(1);
+    storage
+        .save_budget_usage(BudgetUsage {
+            budget: budgets[0].key(),
+            period_start: yesterday,
+            period_end: today,
+            limit_usd: 2.0,
+            spent_usd: 0.7,
+            closed: false,
+            updated_at: yesterday,
+        })
+        .unwrap();
+    for (budget, status) in budgets
+        .iter()
+        .zip(budget::check(&storage, &budgets, now).unwrap())
+    {
+        budget::record_usage(&storage, budget, &status, now).unwrap();
+    }
+
+    let report = report::budget::collect(&storage, &budgets, 10, now).unwrap();
+    assert_eq!(report.current[0].budget, "daily/provider:claude_code");
+    assert_eq!(report.current[0].spent_usd, 1.0);
+    assert_eq!(report.current[0].level, BudgetLevel::Ok);
+    assert_eq!(report.current[1].spent_usd, 0.5);
+    assert_eq!(report.current[1].level, BudgetLevel::Exceeded);
+
+    let history: Vec<(&str, bool, f64)> = report
+        .history
+        .iter()
+        .filter(|usage| usage.budget == budgets[0].key())
+        .map(|usage| (usage.budget.as_str(), usage.closed, usage.spent_usd))
+        .collect();
+    assert_eq!(
+        history,
+        [
+            ("daily/provider:claude_code", false, 1.0),
+            // Closed with the spend recomputed over its own day
+            ("daily/provider:claude_code", true, 0.0),
+        ]
+    );
+    assert!(
+        storage
+            .get_budget_usage(None, true, 10)
+            .unwrap()
+            .iter()
+            .all(|usage| usage.period_start >= today - chrono::Duration::days(6))
+    );
+
+    let text = report.render_text();
+    assert!(text.contains("weekly/project:api"), "{}", text);
+    assert!(text.contains("Exceeded"), "{}", text);
+    assert!(text.contains("current"), "{}", text);
+    assert!(text.contains("within"), "{}", text);
+}
+
+/// Test the providers are compared side by side, with the best on each measure marked
+#[test]
+fn test_provider_report() {
+    use report::providers::collect;
+
+    let storage = StorageHandle::new_in_memory().unwrap();
+    storage.record_log_events(vec![
+        make_event(
+            "claude_code.api_request",
+            &[
+                ("session.id", "a"),
+                ("cost_usd", "2.0"),
+                ("duration_ms", "4000"),
+                ("input_tokens", "1000"),
+            ],
+        ),
+        make_event(
+            "gemini_cli.api_request",
+            &[
+                ("session.id", "b"),
+                ("cost_usd", "0.5"),
+                ("duration_ms", "1500"),
+                ("input_tokens", "1000"),
+            ],
+        ),
+        make_event("gemini_cli.api_error", &[("session.id", "b")]),
+    ]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let comparison = collect(&storage, None, None).unwrap();
+    assert_eq!(comparison.providers.len(), 2);
+    assert_eq!(comparison.providers[0].name, "Claude Code");
+    assert_eq!(comparison.providers[1].api_success_rate, Some(0.5));
+
+    let text = comparison.render_text();
+    let line = |label: &str| {
+        text.lines()
+            .find(|l| l.starts_with(label))
+            .unwrap_or_else(|| panic!("no {label} line in\n{text}"))
+            .to_string()
+    };
+    assert!(line("").contains("Claude Code") && line("").contains("Gemini CLI"));
+    assert!(line("Cost/session").ends_with("$0.50*"), "{}", text);
+    assert!(line("Avg latency").ends_with("1.5s*"), "{}", text);
+    assert!(line("API success").contains("100.0%*"), "{}", text);
+    assert!(!line("Cost  ").contains('*'), "{}", text);
+
+    let json = serde_json::to_value(&comparison).unwrap();
+    assert_eq!(json["providers"][0]["provider"], "claude_code");
+    assert_eq!(
+        json["providers"][1]["tool_error_rate"],
+        serde_json::Value::Null
+    );
+
+    let empty = collect(&StorageHandle::new_in_memory().unwrap(), None, None).unwrap();
+    assert!(empty.render_text().starts_with("No provider events"));
+}
+
+/// Test turns are bucketed by prompt size with the prompt length and cost correlated
+#[test]
+fn test_prompt_cost_report() {
+    use chrono::Duration;
+    use report::prompt_cost::{collect, render_text};
+
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let start = Utc::now() - Duration::minutes(5);
+    let mut events = Vec::new();
+    for (i, (chars, cost)) in [("100", "0.05"), ("800", "0.10"), ("5000", "0.40")]
+        .into_iter()
+        .enumerate()
+    {
+        let mut prompt = make_event(
+            "claude_code.user_prompt",
+            &[("session.id", "a"), ("prompt_length", chars)],
+        );
+        prompt.timestamp = start + Duration::seconds(i as i64 * 10);
+        let mut request = make_event(
+            "claude_code.api_request",
+            &[("session.id", "a"), ("cost_usd", cost)],
+        );
+        request.timestamp = prompt.timestamp + Duration::seconds(1);
+        events.extend([prompt, request]);
+    }
+    storage.record_log_events(events);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let analysis = collect(&storage, None, None).unwrap();
+    assert_eq!(analysis.turns, 3);
+    assert!(analysis.correlation.unwrap() > 0.99);
+    let text = render_text(&analysis);
+    assert!(text.starts_with("PROMPT"), "{}", text);
+    assert!(
+        text.lines()
+            .any(|l| l.starts_with("2K-10K") && l.ends_with("$0.08")),
+        "{}",
+        text
+    );
+    assert!(text.contains("Correlation        r = 1.00"), "{}", text);
+
+    let json = serde_json::to_value(&analysis).unwrap();
+    assert_eq!(json["buckets"].as_array().unwrap().len(), 5);
+    assert_eq!(json["buckets"][0]["label"], "<500");
+
+    let empty = collect(&StorageHandle::new_in_memory().unwrap(), None, None).unwrap();
+    assert!(render_text(&empty).starts_with("No turns"));
+}
+
+/// Test errors are listed by category with their share, then by tool or model
+#[test]
+fn test_error_report() {
+    use report::errors::collect;
+
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let empty = collect(&storage, None, None).unwrap();
+    assert!(empty.render_text().contains("No tool or API errors"));
+
+    storage.record_log_events(vec![
+        make_event(
+            "claude_code.tool_result",
+            &[
+                ("session.id", "a"),
+                ("tool_name", "Bash"),
+                ("success", "false"),
+                ("error", "Command timed out after 120s"),
+            ],
+        ),
+        make_event(
+            "claude_code.tool_result",
+            &[
+                ("session.id", "a"),
+                ("tool_name", "Bash"),
+                ("success", "false"),
+                ("error", "Command timed out after 600s"),
+            ],
+        ),
+        make_event(
+            "claude_code.api_error",
+            &[
+                ("session.id", "a"),
+                ("model", "claude-sonnet-4-5"),
+                ("status_code", "529"),
+                ("error", "Overloaded"),
+            ],
+        ),
+        make_event(
+            "claude_code.tool_result",
+            &[("tool_name", "Read"), ("success", "true")],
+        ),
+    ]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let errors = collect(&storage, None, None).unwrap();
+    assert_eq!(errors.errors, 3);
+    assert_eq!(errors.categories[0].category, "timeout");
+    assert_eq!(errors.breakdown[1].source, "claude-sonnet-4-5");
+
+    let text = errors.render_text();
+    let line = |prefix: &str| {
+        text.lines()
+            .find(|l| l.starts_with(prefix))
+            .unwrap_or_else(|| panic!("no {prefix} line in\n{text}"))
+            .to_string()
+    };
+    assert!(line("timeout ").contains("67%"), "{}", text);
+    assert!(line("rate_limit ").contains("33%"), "{}", text);
+    let bash = text
+        .lines()
+        .find(|l| l.contains("Bash"))
+        .unwrap_or_else(|| panic!("no Bash line in\n{text}"));
+    assert!(bash.contains("tool"), "{}", text);
+    assert!(bash.contains("Command timed out"), "{}", text);
+
+    let json = serde_json::to_value(&errors).unwrap();
+    assert_eq!(json["categories"][0]["tool_errors"], 2);
+    assert_eq!(json["breakdown"][1]["kind"], "api");
+}
+
+/// Test each quota's current window is reported with the capacity left
+#[test]
+fn test_quota_report() {
+    use agenttop::quota::Quota;
+
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let empty = report::quota::collect(&storage, &[], Utc::now()).unwrap();
+    assert!(empty.render_text().contains("No quotas configured"));
+
+    storage.record_log_events(vec![
+        make_event(
+            "claude_code.api_request",
+            &[
+                ("input_tokens", "1500"),
+                ("output_tokens", "100"),
+                ("cost_usd", "0.2"),
+            ],
+        ),
+        make_event("gemini_cli.api_request", &[("input_tokens", "900")]),
+    ]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let quotas: Vec<Quota> = serde_json::from_str(
+        r#"[{"provider": "claude_code", "limit_tokens": 2000}, {"provider": "gemini_cli"}]"#,
+    )
+    .unwrap();
+    let report = report::quota::collect(&storage, &quotas, Utc::now()).unwrap();
+    let claude = &report.quotas[0];
+    assert_eq!(claude.status.percent(), Some(80.0));
+    assert_eq!(claude.windows.len(), 1);
+    assert_eq!(claude.windows[0].tokens, 1600);
+    assert_eq!(report.quotas[1].status.percent(), None);
+
+    let text = report.render_text();
+    assert!(text.contains("claude_code: 20% left, resets "), "{}", text);
+    assert!(text.contains("gemini_cli: 900 tokens, "), "{}", text);
+    assert!(text.contains("REQUESTS"), "{}", text);
+
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["quotas"][0]["name"], "claude_code");
+    assert_eq!(json["quotas"][0]["level"], "warn");
+    assert_eq!(json["quotas"][0]["windows"][0]["requests"], 1);
+}
+
+#[test]
+fn test_context_report() {
+    use report::context::{collect, render_text};
+
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let empty = collect(&storage, None, None).unwrap();
+    assert!(render_text(&empty).starts_with("No api_requests"));
+
+    let request = |session: &str, model: &str, tokens: &str| {
+        make_event(
+            "claude_code.api_request",
+            &[
+                ("session.id", session),
+                ("model", model),
+                ("input_tokens", tokens),
+            ],
+        )
+    };
+    let mut events: Vec<LogEvent> = (0..6)
+        .map(|_| request("3f2a9c1b-full", "claude-opus-4-1", "180000"))
+        .collect();
+    events.push(request("light", "claude-haiku-4-5", "20000"));
+    events.push(request("light", "mystery-model", "20000"));
+    storage.record_log_events(events);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let usage = collect(&storage, None, None).unwrap();
+    assert_eq!(usage.totals.requests, 7);
+    assert_eq!(usage.unknown_window, 1);
+    let text = render_text(&usage);
+    assert!(text.starts_with("DAY"), "{}", text);
+    assert!(
+        text.lines()
+            .any(|l| l.starts_with("Total") && l.ends_with("7      6     79%     90%")),
+        "{}",
+        text
+    );
+    assert!(text.contains("1 requests left out"), "{}", text);
+    assert!(
+        text.lines()
+            .any(|l| l.starts_with("3f2a9c1b  claude-opus-4-1") && l.ends_with("90%")),
+        "{}",
+        text
+    );
+    assert!(!text.contains("light"), "{}", text);
+
+    let json = serde_json::to_value(&usage).unwrap();
+    assert_eq!(json["near_ceiling"], 6);
+    assert_eq!(json["sessions"][0]["session_id"], "3f2a9c1b-full");
+}
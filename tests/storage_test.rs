@@ -4,7 +4,7 @@
 //! query-time filtering behavior for tool_result events.
 #![allow(dead_code)]
 
-use chrono::{DateTime, Timelike, Utc};
+use chrono::{DateTime, TimeZone, Timelike, Utc};
 use std::collections::HashMap;
 
 // =============================================================================
@@ -436,7 +436,7 @@ fn test_record_and_retrieve_log_events() {
     std::thread::sleep(std::time::Duration::from_millis(100));
 
     // Query tool metrics
-    let metrics = storage.get_tool_metrics(None).unwrap();
+    let metrics = storage.get_tool_metrics(None, None).unwrap();
     assert_eq!(metrics.len(), 1);
     assert_eq!(metrics[0].tool_name, "Read");
     assert_eq!(metrics[0].call_count, 1);
@@ -476,7 +476,7 @@ fn test_multiple_tool_events() {
     storage.record_log_events(events);
     std::thread::sleep(std::time::Duration::from_millis(100));
 
-    let metrics = storage.get_tool_metrics(None).unwrap();
+    let metrics = storage.get_tool_metrics(None, None).unwrap();
     assert_eq!(metrics.len(), 3); // Read, Write, Bash
 
     // Find Read metrics
@@ -504,13 +504,151 @@ fn test_token_usage_recording() {
 
     std::thread::sleep(std::time::Duration::from_millis(100));
 
-    let metrics = storage.get_token_metrics(None).unwrap();
+    let metrics = storage.get_token_metrics(None, None).unwrap();
     assert_eq!(metrics.input_tokens, 1000);
     assert_eq!(metrics.output_tokens, 500);
     assert_eq!(metrics.cache_read_tokens, 2000);
     assert_eq!(metrics.cache_creation_tokens, 100);
 }
 
+/// Test streaming token usage as CSV in batches, and abandoning a stream early
+#[test]
+fn test_stream_token_usage_csv() {
+    use agenttop::export::write_token_usage_csv;
+    use agenttop::storage::StorageHandle;
+
+    let storage = StorageHandle::new_in_memory().unwrap();
+
+    storage.record_model_token_usage(
+        "input",
+        1000,
+        Some("claude-sonnet-4-5"),
+        Some("claude_code"),
+    );
+    // A model each, so the records aren't coalesced into one row
+    for i in 0..2500 {
+        storage.record_model_token_usage("output", 1, Some(&format!("m{i}")), None);
+    }
+
+    let mut csv = Vec::new();
+    let rows =
+        write_token_usage_csv(&mut csv, storage.stream_token_usage(None, None).unwrap()).unwrap();
+    assert_eq!(rows, 2501);
+    let csv = String::from_utf8(csv).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines.len(), 2502);
+    assert_eq!(lines[0], "timestamp,token_type,count,model,provider");
+    assert!(lines[1].ends_with(",input,1000,claude-sonnet-4-5,claude_code"));
+    assert!(lines[2].ends_with(",output,1,m0,"));
+
+    // Dropping the receiver after the first batch frees the storage for other queries
+    let stream = storage.stream_token_usage(None, None).unwrap();
+    assert_eq!(stream.recv().unwrap().unwrap().len(), 1000);
+    drop(stream);
+    let metrics = storage.get_token_metrics(None, None).unwrap();
+    assert_eq!(metrics.output_tokens, 2500);
+}
+
+/// Test cache usage is summed per hour and model, with token types normalized and
+/// output tokens left out
+#[test]
+fn test_get_cache_usage() {
+    use agenttop::storage::StorageHandle;
+
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let sonnet = Some("claude-sonnet-4-5");
+    storage.record_model_token_usage("input", 100, sonnet, Some("claude_code"));
+    storage.record_model_token_usage("cacheRead", 300, sonnet, Some("claude_code"));
+    storage.record_model_token_usage("cacheCreation", 50, sonnet, Some("claude_code"));
+    storage.record_model_token_usage("output", 1000, Some("claude-opus-4-1"), None);
+    storage.record_token_usage("cacheRead", 40);
+
+    let usage = storage.get_cache_usage(None, None).unwrap();
+    assert_eq!(usage.len(), 2, "{:?}", usage);
+    let sonnet_usage = usage.iter().find(|u| u.model.as_deref() == sonnet).unwrap();
+    assert_eq!(sonnet_usage.input_tokens, 100);
+    assert_eq!(sonnet_usage.cache_read_tokens, 300);
+    assert_eq!(sonnet_usage.cache_creation_tokens, 50);
+    assert_eq!(sonnet_usage.hour.minute(), 0);
+    let unknown = usage.iter().find(|u| u.model.is_none()).unwrap();
+    assert_eq!(unknown.cache_read_tokens, 40);
+
+    let later = Utc::now() + chrono::Duration::hours(1);
+    assert!(
+        storage
+            .get_cache_usage(Some(later), None)
+            .unwrap()
+            .is_empty()
+    );
+}
+
+/// Test token records of the same kind arriving together are stored as one row
+#[test]
+fn test_token_usage_coalesced() {
+    use agenttop::storage::StorageHandle;
+
+    // Rows cover 5s windows counted from the epoch; start at one so the records share it
+    let next_window = || {
+        let now = Utc::now().timestamp_millis();
+        let start = chrono::DateTime::from_timestamp_millis(now - now % 5000 + 5000).unwrap();
+        std::thread::sleep((start - Utc::now()).to_std().unwrap_or_default());
+        start
+    };
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let model = Some("claude-sonnet-4-5");
+    next_window();
+    for _ in 0..100 {
+        storage.record_model_token_usage("input", 10, model, Some("claude_code"));
+        storage.record_model_token_usage("output", 5, model, Some("claude_code"));
+    }
+    storage.record_model_token_usage("input", 7, Some("claude-haiku-4-5"), Some("claude_code"));
+
+    let metrics = storage.get_token_metrics(None, None).unwrap();
+    assert_eq!(metrics.input_tokens, 1007);
+    assert_eq!(metrics.output_tokens, 500);
+
+    let rows = storage
+        .run_query("SELECT count(*) FROM token_usage", 10)
+        .unwrap();
+    assert_eq!(rows.rows[0][0].as_deref(), Some("3"));
+
+    // Rows don't reach across the start of a window, so a query from there counts only
+    // what arrived since
+    let before = next_window();
+    storage.record_model_token_usage("input", 3, Some("claude-haiku-4-5"), Some("claude_code"));
+    storage.record_model_token_usage("input", 2, Some("claude-haiku-4-5"), Some("claude_code"));
+    let recent = storage.get_token_metrics(Some(before), None).unwrap();
+    assert_eq!(recent.input_tokens, 5);
+    let earlier = storage.get_token_metrics(None, Some(before)).unwrap();
+    assert_eq!(earlier.input_tokens, 1007);
+}
+
+/// Test token usage is priced at list prices, including rows added to a coalesced row,
+/// and that rows of unknown models or without one count nothing
+#[test]
+fn test_estimated_token_cost() {
+    use agenttop::storage::StorageHandle;
+
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let sonnet = Some("claude-sonnet-4-5");
+    storage.record_model_token_usage("input", 500_000, sonnet, Some("claude_code"));
+    storage.record_model_token_usage("input", 500_000, sonnet, Some("claude_code"));
+    storage.record_model_token_usage("output", 100_000, sonnet, Some("claude_code"));
+    storage.record_model_token_usage("cacheRead", 1_000_000, sonnet, Some("claude_code"));
+    storage.record_model_token_usage("input", 1_000_000, Some("mystery-1"), None);
+    storage.record_token_usage("input", 1_000_000);
+
+    let metrics = storage.get_token_metrics(None, None).unwrap();
+    // $3.00 input + $1.50 output + $0.30 cache reads
+    assert!(
+        (metrics.estimated_cost_usd - 4.80).abs() < 1e-9,
+        "{}",
+        metrics.estimated_cost_usd
+    );
+    // Nothing reported
+    assert_eq!(metrics.total_cost_usd, 0.0);
+}
+
 /// Test recording cost
 #[test]
 fn test_cost_recording() {
@@ -523,10 +661,256 @@ fn test_cost_recording() {
 
     std::thread::sleep(std::time::Duration::from_millis(100));
 
-    let metrics = storage.get_token_metrics(None).unwrap();
+    let metrics = storage.get_token_metrics(None, None).unwrap();
     assert!((metrics.total_cost_usd - 0.08).abs() < 0.001);
 }
 
+/// Test queries respect an exclusive upper time bound
+#[test]
+fn test_tool_metrics_until_bound() {
+    use agenttop::storage::{LogEvent, StorageHandle};
+
+    let storage = StorageHandle::new_in_memory().unwrap();
+
+    let now = Utc::now();
+    let events: Vec<LogEvent> = vec![("Read", now - chrono::Duration::hours(2)), ("Write", now)]
+        .into_iter()
+        .map(|(tool, timestamp)| {
+            let mut attrs = HashMap::new();
+            attrs.insert("tool_name".to_string(), tool.to_string());
+            attrs.insert("success".to_string(), "true".to_string());
+            LogEvent {
+                timestamp,
+                event_name: Some("tool_result".to_string()),
+                body: None,
+                attributes: attrs,
+            }
+        })
+        .collect();
+
+    storage.record_log_events(events);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let cutoff = now - chrono::Duration::hours(1);
+    let before = storage.get_tool_metrics(None, Some(cutoff)).unwrap();
+    assert_eq!(before.len(), 1);
+    assert_eq!(before[0].tool_name, "Read");
+
+    let after = storage.get_tool_metrics(Some(cutoff), None).unwrap();
+    assert_eq!(after.len(), 1);
+    assert_eq!(after[0].tool_name, "Write");
+}
+
+/// Test a cached windowed query follows the window as it moves
+#[test]
+fn test_tool_metrics_moving_window() {
+    use agenttop::storage::{LogEvent, StorageHandle};
+
+    let storage = StorageHandle::new_in_memory().unwrap();
+
+    let now = Utc::now();
+    let events: Vec<LogEvent> = (0..3)
+        .map(|hours| {
+            let mut attrs = HashMap::new();
+            attrs.insert("tool_name".to_string(), format!("Tool{}", hours));
+            attrs.insert("success".to_string(), "true".to_string());
+            LogEvent {
+                timestamp: now - chrono::Duration::hours(hours),
+                event_name: Some("tool_result".to_string()),
+                body: None,
+                attributes: attrs,
+            }
+        })
+        .collect();
+
+    storage.record_log_events(events);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    for hours in 0..3 {
+        let since = now - chrono::Duration::hours(hours) - chrono::Duration::minutes(30);
+        let until = since + chrono::Duration::hours(1);
+        let tools = storage.get_tool_metrics(Some(since), Some(until)).unwrap();
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0].tool_name, format!("Tool{}", hours));
+    }
+}
+
+/// Test the recorder keeps its connection, and with it the cached statements, across
+/// idle gaps: after the file is moved away its queries still answer from the open file
+#[cfg(unix)]
+#[test]
+fn test_connection_kept_while_idle() {
+    use agenttop::storage::{LogEvent, StorageHandle};
+
+    let dir = std::env::temp_dir().join(format!("agenttop-kept-{}", std::process::id()));
+    let path = dir.join("metrics.duckdb");
+    let storage = StorageHandle::open(&path).unwrap();
+    let mut attrs = HashMap::new();
+    attrs.insert("tool_name".to_string(), "Read".to_string());
+    storage.record_log_events(vec![LogEvent {
+        timestamp: Utc::now(),
+        event_name: Some("tool_result".to_string()),
+        body: None,
+        attributes: attrs,
+    }]);
+    assert_eq!(storage.get_tool_metrics(None, None).unwrap().len(), 1);
+
+    std::thread::sleep(std::time::Duration::from_millis(500));
+    std::fs::rename(&path, dir.join("moved.duckdb")).unwrap();
+    assert_eq!(storage.get_tool_metrics(None, None).unwrap().len(), 1);
+    assert!(!path.exists());
+
+    drop(storage);
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Test per-tool call series are bucketed by time
+#[test]
+fn test_tool_call_series_buckets() {
+    use agenttop::storage::{LogEvent, StorageHandle};
+
+    let storage = StorageHandle::new_in_memory().unwrap();
+
+    let since = Utc::now() - chrono::Duration::minutes(30);
+    let events: Vec<LogEvent> = vec![
+        ("Read", 1),
+        ("Read", 2),
+        ("Read", 25),
+        ("Bash", 11),
+        ("Bash", 45), // outside the 3 x 10 minute window
+    ]
+    .into_iter()
+    .map(|(tool, mins)| {
+        let mut attrs = HashMap::new();
+        attrs.insert("tool_name".to_string(), tool.to_string());
+        attrs.insert("success".to_string(), "true".to_string());
+        LogEvent {
+            timestamp: since + chrono::Duration::minutes(mins),
+            event_name: Some("tool_result".to_string()),
+            body: None,
+            attributes: attrs,
+        }
+    })
+    .collect();
+
+    storage.record_log_events(events);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let series = storage.get_tool_call_series(since, 600, 3).unwrap();
+    assert_eq!(series.get("Read"), Some(&vec![2, 0, 1]));
+    assert_eq!(series.get("Bash"), Some(&vec![0, 1, 0]));
+}
+
+/// Test tools with a start event but no later result are reported in flight
+#[test]
+fn test_in_flight_tools() {
+    use agenttop::storage::{LogEvent, StorageHandle};
+
+    let storage = StorageHandle::new_in_memory().unwrap();
+
+    let now = Utc::now();
+    let event = |name: &str, tool: &str, decision: Option<&str>, secs_ago: i64| {
+        let mut attrs = HashMap::new();
+        attrs.insert("tool_name".to_string(), tool.to_string());
+        if let Some(decision) = decision {
+            attrs.insert("decision".to_string(), decision.to_string());
+        }
+        LogEvent {
+            timestamp: now - chrono::Duration::seconds(secs_ago),
+            event_name: Some(name.to_string()),
+            body: None,
+            attributes: attrs,
+        }
+    };
+
+    storage.record_log_events(vec![
+        // Finished: result after start
+        event("claude_code.tool_decision", "Read", Some("accept"), 10),
+        event("claude_code.tool_result", "Read", None, 9),
+        // Still running
+        event("claude_code.tool_decision", "Bash", Some("accept"), 30),
+        // Rejected tools never run
+        event("claude_code.tool_decision", "Write", Some("reject"), 5),
+        // Explicit start event after an older result
+        event("tool_result", "Grep", None, 20),
+        event("tool_start", "Grep", None, 3),
+    ]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let in_flight = storage
+        .get_in_flight_tools(now - chrono::Duration::minutes(5))
+        .unwrap();
+    let names: Vec<&str> = in_flight.iter().map(|t| t.tool_name.as_str()).collect();
+    assert_eq!(names, vec!["Bash", "Grep"]);
+    assert!(
+        (in_flight[0].started_at - (now - chrono::Duration::seconds(30)))
+            .num_seconds()
+            .abs()
+            <= 1
+    );
+}
+
+/// Test API errors are broken down by model
+#[test]
+fn test_api_errors_by_model() {
+    use agenttop::storage::{LogEvent, StorageHandle};
+
+    let storage = StorageHandle::new_in_memory().unwrap();
+
+    let events: Vec<LogEvent> = vec![Some("opus"), Some("opus"), Some("haiku"), None]
+        .into_iter()
+        .map(|model| {
+            let mut attrs = HashMap::new();
+            if let Some(model) = model {
+                attrs.insert("model".to_string(), model.to_string());
+            }
+            LogEvent {
+                timestamp: Utc::now(),
+                event_name: Some("claude_code.api_error".to_string()),
+                body: None,
+                attributes: attrs,
+            }
+        })
+        .collect();
+
+    storage.record_log_events(events);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let metrics = storage.get_api_metrics(None, None).unwrap();
+    assert_eq!(metrics.total_errors, 4);
+    assert_eq!(metrics.errors_by_model.get("opus"), Some(&2));
+    assert_eq!(metrics.errors_by_model.get("haiku"), Some(&1));
+}
+
+/// Test rate-limit errors are recognized by status code or message
+#[test]
+fn test_count_rate_limit_errors() {
+    use agenttop::storage::{LogEvent, StorageHandle};
+
+    let storage = StorageHandle::new_in_memory().unwrap();
+
+    let events: Vec<LogEvent> = [
+        ("status_code", "429"),
+        ("error", "Rate limit exceeded"),
+        ("error", "rate_limit_error"),
+        ("error", "Overloaded"),
+    ]
+    .into_iter()
+    .map(|(key, value)| LogEvent {
+        timestamp: Utc::now(),
+        event_name: Some("claude_code.api_error".to_string()),
+        body: None,
+        attributes: HashMap::from([(key.to_string(), value.to_string())]),
+    })
+    .collect();
+
+    storage.record_log_events(events);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    assert_eq!(storage.count_rate_limit_errors(None, None).unwrap(), 3);
+}
+
 /// Test prefixed event names are properly aggregated
 #[test]
 fn test_prefixed_event_names_aggregation() {
@@ -554,7 +938,7 @@ fn test_prefixed_event_names_aggregation() {
     storage.record_log_events(events);
     std::thread::sleep(std::time::Duration::from_millis(100));
 
-    let metrics = storage.get_tool_metrics(None).unwrap();
+    let metrics = storage.get_tool_metrics(None, None).unwrap();
     let read_metrics = metrics.iter().find(|m| m.tool_name == "Read").unwrap();
     // Both events should be counted for Read tool
     assert_eq!(read_metrics.call_count, 2);
@@ -586,7 +970,7 @@ fn test_non_tool_result_events_stored() {
     std::thread::sleep(std::time::Duration::from_millis(100));
 
     // Tool metrics should be empty (no tool_result events)
-    let metrics = storage.get_tool_metrics(None).unwrap();
+    let metrics = storage.get_tool_metrics(None, None).unwrap();
     assert!(metrics.is_empty());
 }
 
@@ -597,10 +981,10 @@ fn test_empty_database_metrics() {
 
     let storage = StorageHandle::new_in_memory().unwrap();
 
-    let tool_metrics = storage.get_tool_metrics(None).unwrap();
+    let tool_metrics = storage.get_tool_metrics(None, None).unwrap();
     assert!(tool_metrics.is_empty());
 
-    let token_metrics = storage.get_token_metrics(None).unwrap();
+    let token_metrics = storage.get_token_metrics(None, None).unwrap();
     assert_eq!(token_metrics.input_tokens, 0);
     assert_eq!(token_metrics.output_tokens, 0);
     assert_eq!(token_metrics.total_cost_usd, 0.0);
@@ -615,7 +999,7 @@ fn test_database_isolation_1() {
     storage.record_token_usage("input", 999);
     std::thread::sleep(std::time::Duration::from_millis(50));
 
-    let metrics = storage.get_token_metrics(None).unwrap();
+    let metrics = storage.get_token_metrics(None, None).unwrap();
     assert_eq!(metrics.input_tokens, 999);
 }
 
@@ -626,7 +1010,7 @@ fn test_database_isolation_2() {
 
     let storage = StorageHandle::new_in_memory().unwrap();
     // This should NOT see data from test_database_isolation_1
-    let metrics = storage.get_token_metrics(None).unwrap();
+    let metrics = storage.get_token_metrics(None, None).unwrap();
     assert_eq!(metrics.input_tokens, 0);
 }
 
@@ -729,12 +1113,111 @@ fn test_get_session_metrics() {
     storage.record_session_metric("lines_of_code", -30);
     storage.record_session_metric("commits", 2);
     storage.record_session_metric("commits", 1);
+    // Claude Code's commit.count is stored as "commit"
+    storage.record_session_metric("commit", 4);
 
     std::thread::sleep(std::time::Duration::from_millis(100));
 
-    let metrics = storage.get_session_metrics(None).unwrap();
+    let metrics = storage.get_session_metrics(None, None).unwrap();
     assert_eq!(metrics.lines_of_code, 120); // 150 + (-30) = 120
-    assert_eq!(metrics.commit_count, 3); // 2 + 1 = 3
+    assert_eq!(metrics.commit_count, 7); // 2 + 1 + 4 = 7
+}
+
+/// Test sessions with code metrics are listed with their lines, commits, latest active
+/// time and cost; sessions without any are left out
+#[test]
+fn test_get_session_productivity() {
+    use agenttop::storage::{LogEvent, StorageHandle};
+
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let start = Utc::now() - chrono::Duration::hours(2);
+    let request = |minutes: i64, session: &str, cost: &str| LogEvent {
+        timestamp: start + chrono::Duration::minutes(minutes),
+        event_name: Some("claude_code.api_request".to_string()),
+        body: None,
+        attributes: [("session.id", session), ("cost_usd", cost)]
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+    };
+    storage.record_log_events(vec![
+        request(0, "a", "1.0"),
+        request(30, "a", "0.5"),
+        request(60, "b", "2.0"),
+        request(70, "quiet", "3.0"),
+    ]);
+    storage.record_session_metric_for(Some("a"), "lines_of_code", 120);
+    storage.record_session_metric_for(Some("a"), "lines_of_code", -20);
+    storage.record_session_metric_for(Some("a"), "commit", 2);
+    storage.record_session_metric_for(Some("a"), "active_time", 600);
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    storage.record_session_metric_for(Some("a"), "active_time", 900);
+    storage.record_session_metric_for(Some("b"), "lines_of_code", 40);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let sessions = storage.get_session_productivity(None, None, 10).unwrap();
+    let ids: Vec<&str> = sessions.iter().map(|s| s.session_id.as_str()).collect();
+    assert_eq!(ids, ["b", "a"]);
+    let a = &sessions[1];
+    assert_eq!(a.lines_of_code, 100);
+    assert_eq!(a.commits, 2);
+    assert_eq!(a.active_time_secs, 900);
+    assert_eq!(a.cost_usd, 1.5);
+    assert_eq!(a.ended_at - a.started_at, chrono::Duration::minutes(30));
+    assert_eq!(sessions[0].commits, 0);
+
+    // Only sessions still active after `since`
+    let since = Some(start + chrono::Duration::minutes(45));
+    let recent = storage.get_session_productivity(since, None, 10).unwrap();
+    assert_eq!(recent.len(), 1);
+    assert_eq!(
+        storage
+            .get_session_productivity(None, None, 1)
+            .unwrap()
+            .len(),
+        1
+    );
+}
+
+/// Test active time counts each session's latest running total instead of every report
+#[test]
+fn test_get_session_metrics_active_time() {
+    use agenttop::storage::StorageHandle;
+
+    let storage = StorageHandle::new_in_memory().unwrap();
+    for (session, total) in [("a", 30), ("a", 90), ("b", 20), ("a", 120), ("b", 45)] {
+        storage.record_session_metric_for(Some(session), "active_time", total);
+        std::thread::sleep(std::time::Duration::from_millis(5));
+    }
+    storage.record_session_metric_for(Some("a"), "lines_of_code", 10);
+    storage.record_session_metric_for(Some("a"), "lines_of_code", 5);
+
+    let metrics = storage.get_session_metrics(None, None).unwrap();
+    assert_eq!(metrics.active_time_secs, 165); // 120 + 45
+    assert_eq!(metrics.lines_of_code, 15);
+}
+
+/// Test cumulative cost reports count each series' increase once, across restarts
+#[test]
+fn test_cumulative_cost_not_double_counted() {
+    use agenttop::storage::StorageHandle;
+
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let model = Some("claude-sonnet-4-5");
+    storage.record_cost_for(Some("a"), model, 1.0, true);
+    storage.record_cost_for(Some("a"), model, 2.5, true);
+    // Re-reported after the agent restarted
+    storage.record_cost_for(Some("a"), model, 2.5, true);
+    storage.record_cost_for(Some("a"), Some("claude-haiku-4-5"), 0.5, true);
+    storage.record_cost_for(Some("b"), model, 3.0, true);
+    // A new process counting from zero again
+    storage.record_cost_for(Some("b"), model, 0.25, true);
+    // Delta reports still add up
+    storage.record_cost_for(Some("c"), model, 0.1, false);
+    storage.record_cost_for(Some("c"), model, 0.1, false);
+
+    let total = storage.get_total_cost(None, None).unwrap();
+    assert!((total - 6.45).abs() < 1e-9, "total was {total}"); // 2.5 + 0.5 + 3.25 + 0.2
 }
 
 /// Test get_session_metrics returns defaults when empty
@@ -744,7 +1227,1684 @@ fn test_get_session_metrics_empty() {
 
     let storage = StorageHandle::new_in_memory().unwrap();
 
-    let metrics = storage.get_session_metrics(None).unwrap();
+    let metrics = storage.get_session_metrics(None, None).unwrap();
     assert_eq!(metrics.lines_of_code, 0);
     assert_eq!(metrics.commit_count, 0);
 }
+
+/// Test the session timeline is limited to the latest session and classifies events
+#[test]
+fn test_get_session_timeline() {
+    use agenttop::storage::{LogEvent, StorageHandle, TimelineKind};
+
+    let storage = StorageHandle::new_in_memory().unwrap();
+
+    let now = Utc::now();
+    let event = |name: &str, session: &str, attrs: &[(&str, &str)], secs_ago: i64| {
+        let mut attributes: HashMap<String, String> = attrs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        attributes.insert("session.id".to_string(), session.to_string());
+        LogEvent {
+            timestamp: now - chrono::Duration::seconds(secs_ago),
+            event_name: Some(name.to_string()),
+            body: None,
+            attributes,
+        }
+    };
+
+    storage.record_log_events(vec![
+        // Older session is excluded
+        event(
+            "claude_code.tool_result",
+            "old",
+            &[("tool_name", "Grep"), ("success", "true")],
+            600,
+        ),
+        event(
+            "claude_code.tool_result",
+            "current",
+            &[
+                ("tool_name", "Read"),
+                ("success", "true"),
+                ("duration_ms", "1500"),
+            ],
+            30,
+        ),
+        event(
+            "claude_code.api_request",
+            "current",
+            &[("model", "claude-sonnet"), ("duration_ms", "800")],
+            20,
+        ),
+        event(
+            "claude_code.tool_result",
+            "current",
+            &[
+                ("tool_name", "Bash"),
+                ("success", "false"),
+                ("duration_ms", "200"),
+            ],
+            10,
+        ),
+    ]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let timeline = storage.get_session_timeline().unwrap();
+    assert_eq!(timeline.session_id.as_deref(), Some("current"));
+    let kinds: Vec<(TimelineKind, &str)> = timeline
+        .events
+        .iter()
+        .map(|e| (e.kind, e.name.as_str()))
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            (TimelineKind::Tool, "Read"),
+            (TimelineKind::Api, "claude-sonnet"),
+            (TimelineKind::Error, "Bash"),
+        ]
+    );
+    assert_eq!(timeline.events[0].duration_ms, 1500);
+}
+
+/// Test recent prompts are returned newest first with text and length
+#[test]
+fn test_get_recent_prompts() {
+    use agenttop::storage::{LogEvent, StorageHandle};
+
+    let storage = StorageHandle::new_in_memory().unwrap();
+
+    let now = Utc::now();
+    let prompt = |text: Option<&str>, length: Option<&str>, secs_ago: i64| {
+        let mut attributes = HashMap::new();
+        if let Some(text) = text {
+            attributes.insert("prompt".to_string(), text.to_string());
+        }
+        if let Some(length) = length {
+            attributes.insert("prompt_length".to_string(), length.to_string());
+        }
+        LogEvent {
+            timestamp: now - chrono::Duration::seconds(secs_ago),
+            event_name: Some("claude_code.user_prompt".to_string()),
+            body: None,
+            attributes,
+        }
+    };
+
+    storage.record_log_events(vec![
+        prompt(None, Some("120"), 30),
+        prompt(Some("fix the tests"), None, 20),
+        prompt(Some("add a timeline"), Some("14"), 10),
+        // Other events are ignored
+        LogEvent {
+            timestamp: now,
+            event_name: Some("claude_code.tool_result".to_string()),
+            body: None,
+            attributes: HashMap::new(),
+        },
+    ]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let prompts = storage.get_recent_prompts(None, None, 10).unwrap();
+    assert_eq!(prompts.len(), 3);
+    assert_eq!(prompts[0].text.as_deref(), Some("add a timeline"));
+    assert_eq!(prompts[0].length, Some(14));
+    // Length falls back to the text when not reported
+    assert_eq!(prompts[1].length, Some(13));
+    assert_eq!(prompts[2].text, None);
+    assert_eq!(prompts[2].length, Some(120));
+
+    let limited = storage.get_recent_prompts(None, None, 1).unwrap();
+    assert_eq!(limited.len(), 1);
+}
+
+/// Test tool calls and cost are rolled up per hour
+#[test]
+fn test_get_hourly_activity() {
+    use agenttop::storage::{LogEvent, StorageHandle};
+
+    let storage = StorageHandle::new_in_memory().unwrap();
+
+    let hour = Utc::now()
+        .with_minute(0)
+        .and_then(|t| t.with_second(0))
+        .and_then(|t| t.with_nanosecond(0))
+        .unwrap()
+        - chrono::Duration::hours(3);
+    let tool_call = |offset_mins: i64| LogEvent {
+        timestamp: hour + chrono::Duration::minutes(offset_mins),
+        event_name: Some("claude_code.tool_result".to_string()),
+        body: None,
+        attributes: HashMap::from([("tool_name".to_string(), "Read".to_string())]),
+    };
+
+    storage.record_log_events(vec![tool_call(5), tool_call(50), tool_call(70)]);
+    storage.record_cost(1.25);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let hours = storage
+        .get_hourly_activity(
+            hour - chrono::Duration::hours(1),
+            Utc::now() + chrono::Duration::minutes(1),
+        )
+        .unwrap();
+    assert_eq!(hours.len(), 3);
+    assert_eq!(hours[0].hour, hour);
+    assert_eq!(hours[0].tool_calls, 2);
+    assert_eq!(hours[1].tool_calls, 1);
+    // Cost is recorded at the current time, in an hour without tool calls
+    assert_eq!(hours[2].tool_calls, 0);
+    assert!((hours[2].cost_usd - 1.25).abs() < 1e-9);
+}
+
+/// Test log events are paged by row id for the event stream
+#[test]
+fn test_get_log_events_after() {
+    use agenttop::storage::{LogEvent, StorageHandle};
+
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let event = |tool: &str| LogEvent {
+        timestamp: Utc::now(),
+        event_name: Some("claude_code.tool_result".to_string()),
+        body: None,
+        attributes: HashMap::from([("tool_name".to_string(), tool.to_string())]),
+    };
+
+    storage.record_log_events(vec![event("Read"), event("Edit"), event("Bash")]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    // Without a cursor the newest events are returned, oldest first
+    let latest = storage.get_log_events_after(None, 2).unwrap();
+    let tools: Vec<&str> = latest
+        .iter()
+        .map(|e| e.attributes["tool_name"].as_str())
+        .collect();
+    assert_eq!(tools, vec!["Edit", "Bash"]);
+    assert!(latest[0].id < latest[1].id);
+
+    storage.record_log_events(vec![event("Grep")]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let newer = storage
+        .get_log_events_after(Some(latest[1].id), 10)
+        .unwrap();
+    assert_eq!(newer.len(), 1);
+    assert_eq!(newer[0].attributes["tool_name"], "Grep");
+    assert_eq!(
+        newer[0].event_name.as_deref(),
+        Some("claude_code.tool_result")
+    );
+}
+
+#[test]
+fn test_get_mcp_server_health() {
+    use agenttop::storage::{LogEvent, StorageHandle};
+
+    let storage = StorageHandle::new_in_memory().unwrap();
+
+    let call = |tool_name: &str, success: bool, duration_ms: u64| {
+        let mut attributes = HashMap::new();
+        attributes.insert("tool_name".to_string(), tool_name.to_string());
+        attributes.insert("success".to_string(), success.to_string());
+        attributes.insert("duration_ms".to_string(), duration_ms.to_string());
+        LogEvent {
+            timestamp: Utc::now(),
+            event_name: Some("tool_result".to_string()),
+            body: None,
+            attributes,
+        }
+    };
+
+    let mut events: Vec<LogEvent> = (1..=20)
+        .map(|i| call("mcp__github__search", true, i * 10))
+        .collect();
+    events.push(call("mcp__github__get_issue", false, 500));
+    events.push(call("mcp__plugin_foo_slack__post", false, 40));
+    // Built-in tools are not servers
+    events.push(call("Bash", false, 10));
+    storage.record_log_events(events);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let servers = storage.get_mcp_server_health(None, None).unwrap();
+    assert_eq!(servers.len(), 2);
+
+    let github = &servers[0];
+    assert_eq!(github.server_name, "github");
+    assert_eq!(github.tool_count, 2);
+    assert_eq!(github.call_count, 21);
+    assert_eq!(github.error_count, 1);
+    // 20th of 21 sorted durations (nearest rank)
+    assert_eq!(github.p95_duration_ms, 200);
+    assert!(github.last_seen.is_some());
+
+    let slack = &servers[1];
+    assert_eq!(slack.server_name, "slack");
+    assert_eq!(slack.call_count, 1);
+    assert_eq!(slack.error_rate(), 100.0);
+}
+
+#[test]
+fn test_is_empty() {
+    use agenttop::storage::{LogEvent, StorageHandle};
+
+    let storage = StorageHandle::new_in_memory().unwrap();
+    assert!(storage.is_empty().unwrap());
+
+    storage.record_log_events(vec![LogEvent {
+        timestamp: Utc::now(),
+        event_name: Some("claude_code.user_prompt".to_string()),
+        body: None,
+        attributes: HashMap::new(),
+    }]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    assert!(!storage.is_empty().unwrap());
+}
+
+/// Test the data version changes when rows are added or pruned, and only then
+#[test]
+fn test_data_version() {
+    use agenttop::storage::{LogEvent, StorageHandle};
+
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let empty = storage.get_data_version().unwrap();
+    assert_eq!(empty.max_ids, [0; agenttop::storage::TABLES.len()]);
+
+    let event = |days_ago: i64| LogEvent {
+        timestamp: Utc::now() - chrono::Duration::days(days_ago),
+        event_name: Some("tool_result".to_string()),
+        body: None,
+        attributes: HashMap::new(),
+    };
+    storage.record_log_events(vec![event(60), event(1)]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    let written = storage.get_data_version().unwrap();
+    assert_ne!(written, empty);
+    assert_eq!(storage.get_data_version().unwrap(), written);
+
+    storage
+        .prune(Utc::now() - chrono::Duration::days(30), false)
+        .unwrap();
+    let pruned = storage.get_data_version().unwrap();
+    assert_eq!(pruned.max_ids, written.max_ids);
+    assert_ne!(pruned, written);
+
+    // A token record added to the row before it still changes the version
+    storage.record_token_usage("input", 10);
+    let first = storage.get_data_version().unwrap();
+    storage.record_token_usage("input", 10);
+    let coalesced = storage.get_data_version().unwrap();
+    assert_eq!(coalesced.max_ids, first.max_ids);
+    assert_ne!(coalesced, first);
+}
+
+#[test]
+fn test_run_query() {
+    use agenttop::storage::{LogEvent, StorageHandle};
+
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let call = |tool_name: &str| {
+        let mut attributes = HashMap::new();
+        attributes.insert("tool_name".to_string(), tool_name.to_string());
+        attributes.insert("success".to_string(), "true".to_string());
+        attributes.insert("duration_ms".to_string(), "10".to_string());
+        LogEvent {
+            timestamp: Utc::now(),
+            event_name: Some("tool_result".to_string()),
+            body: None,
+            attributes,
+        }
+    };
+    storage.record_log_events(vec![call("Read"), call("Read"), call("Bash")]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let result = storage
+        .run_query(
+            "SELECT attributes->>'tool_name' AS tool, count(*) AS n, NULL AS note \
+             FROM log_events GROUP BY tool ORDER BY n DESC;",
+            10,
+        )
+        .unwrap();
+    assert_eq!(result.columns, vec!["tool", "n", "note"]);
+    assert_eq!(
+        result.rows[0],
+        vec![Some("Read".to_string()), Some("2".to_string()), None]
+    );
+    assert_eq!(result.rows.len(), 2);
+    assert!(!result.truncated);
+
+    let limited = storage.run_query("SELECT * FROM log_events", 2).unwrap();
+    assert_eq!(limited.rows.len(), 2);
+    assert!(limited.truncated);
+
+    let described = storage.run_query("DESCRIBE log_events", 100).unwrap();
+    assert!(
+        described
+            .rows
+            .iter()
+            .any(|r| r[0].as_deref() == Some("attributes"))
+    );
+
+    // Anything that would modify the database is rejected
+    assert!(matches!(
+        storage.run_query("DELETE FROM log_events", 10),
+        Err(agenttop::error::AgenttopError::Storage(_))
+    ));
+    assert!(
+        storage
+            .run_query("SELECT 1; DROP TABLE log_events", 10)
+            .is_err()
+    );
+    assert!(
+        storage
+            .run_query("SELECT 1); DELETE FROM log_events; SELECT (1", 10)
+            .is_err()
+    );
+    assert!(storage.run_query("SELECT 1; SELECT 2", 10).is_err());
+    assert!(storage.run_query("  ", 10).is_err());
+
+    // Files other than the database are out of reach
+    for sql in [
+        "SELECT * FROM read_text('/etc/passwd')",
+        "SELECT * FROM read_csv('/etc/passwd')",
+        "SELECT * FROM glob('/*')",
+    ] {
+        assert!(storage.run_query(sql, 10).is_err(), "{sql}");
+    }
+
+    // A `;` inside a literal is part of the statement
+    let literal = storage.run_query("SELECT 'a;b' AS s;", 10).unwrap();
+    assert_eq!(literal.rows, vec![vec![Some("a;b".to_string())]]);
+
+    // Reads with side effects don't get through either
+    let next_id = || {
+        storage
+            .run_query("SELECT max(id) FROM log_events", 10)
+            .unwrap()
+            .rows[0][0]
+            .clone()
+    };
+    let before = next_id();
+    assert!(
+        storage
+            .run_query("SELECT nextval('log_events_seq')", 10)
+            .is_err()
+    );
+    storage.record_log_events(vec![call("Grep")]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    assert_eq!(
+        next_id().map(|id| id.parse::<i64>().unwrap()),
+        before.map(|id| id.parse::<i64>().unwrap() + 1)
+    );
+    assert_eq!(
+        storage
+            .run_query("SELECT count(*) FROM log_events", 10)
+            .unwrap()
+            .rows[0][0]
+            .as_deref(),
+        Some("4")
+    );
+}
+
+#[test]
+fn test_get_model_costs_and_top_errors() {
+    use agenttop::storage::{LogEvent, StorageHandle};
+
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let event = |name: &str, attrs: &[(&str, &str)]| LogEvent {
+        timestamp: Utc::now(),
+        event_name: Some(name.to_string()),
+        body: None,
+        attributes: attrs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+    };
+    storage.record_log_events(vec![
+        event(
+            "claude_code.api_request",
+            &[("model", "claude-sonnet-4-5"), ("cost_usd", "0.25")],
+        ),
+        event(
+            "claude_code.api_request",
+            &[("model", "claude-sonnet-4-5"), ("cost_usd", "0.5")],
+        ),
+        event(
+            "claude_code.api_request",
+            &[("model", "claude-haiku-4-5"), ("cost_usd", "0.01")],
+        ),
+        // No cost reported
+        event("gemini_cli.api_request", &[("model", "gemini-2.5-pro")]),
+        event(
+            "tool_result",
+            &[
+                ("tool_name", "Bash"),
+                ("success", "false"),
+                ("error", "exit 1"),
+            ],
+        ),
+        event(
+            "tool_result",
+            &[
+                ("tool_name", "Bash"),
+                ("success", "false"),
+                ("error", "exit 1"),
+            ],
+        ),
+        event(
+            "tool_result",
+            &[("tool_name", "Read"), ("success", "false")],
+        ),
+        event("tool_result", &[("tool_name", "Read"), ("success", "true")]),
+    ]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let models = storage.get_model_costs(None, None).unwrap();
+    assert_eq!(models.len(), 3);
+    assert_eq!(models[0].model, "claude-sonnet-4-5");
+    assert_eq!(models[0].calls, 2);
+    assert!((models[0].cost_usd - 0.75).abs() < 1e-9);
+    assert_eq!(models[2].model, "gemini-2.5-pro");
+    assert_eq!(models[2].cost_usd, 0.0);
+
+    let errors = storage.get_top_tool_errors(None, None, 10).unwrap();
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0].tool_name, "Bash");
+    assert_eq!(errors[0].error.as_deref(), Some("exit 1"));
+    assert_eq!(errors[0].count, 2);
+    assert_eq!(errors[1].tool_name, "Read");
+    assert_eq!(errors[1].error, None);
+
+    assert_eq!(storage.get_top_tool_errors(None, None, 1).unwrap().len(), 1);
+}
+
+#[test]
+fn test_export_table() {
+    use agenttop::export::TableFormat;
+    use agenttop::storage::{LogEvent, StorageHandle};
+
+    let dir = std::env::temp_dir().join(format!("agenttop-export-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    // Other handles can't write files
+    let csv = dir.join("log_events.csv");
+    let storage = StorageHandle::new_in_memory().unwrap();
+    assert!(
+        storage
+            .export_table("log_events", None, None, TableFormat::Csv, &csv)
+            .is_err()
+    );
+    assert!(!csv.exists());
+
+    let storage = StorageHandle::open_for_export(&dir.join("test.db")).unwrap();
+    let now = Utc::now();
+    let event = |secs_ago: i64| LogEvent {
+        timestamp: now - chrono::Duration::seconds(secs_ago),
+        event_name: Some("tool_result".to_string()),
+        body: None,
+        attributes: HashMap::from([("tool_name".to_string(), "Read, \"quoted\"".to_string())]),
+    };
+    storage.record_log_events(vec![event(7200), event(60), event(30)]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let since = Some(now - chrono::Duration::hours(1));
+    let rows = storage
+        .export_table("log_events", since, None, TableFormat::Csv, &csv)
+        .unwrap();
+    assert_eq!(rows, 2);
+    let content = std::fs::read_to_string(&csv).unwrap();
+    assert!(content.starts_with("id,timestamp,event_name,body,attributes"));
+    assert_eq!(content.lines().count(), 3);
+
+    let json = dir.join("log_events.ndjson");
+    let rows = storage
+        .export_table("log_events", None, None, TableFormat::Json, &json)
+        .unwrap();
+    assert_eq!(rows, 3);
+    let first: serde_json::Value = serde_json::from_str(
+        std::fs::read_to_string(&json)
+            .unwrap()
+            .lines()
+            .next()
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(first["event_name"], "tool_result");
+
+    let parquet = dir.join("log_events.parquet");
+    storage
+        .export_table("log_events", None, None, TableFormat::Parquet, &parquet)
+        .unwrap();
+    let read_back = storage
+        .run_query(
+            &format!("SELECT count(*) FROM read_parquet('{}')", parquet.display()),
+            1,
+        )
+        .unwrap();
+    assert_eq!(read_back.rows[0][0].as_deref(), Some("3"));
+
+    // Only known tables can be exported
+    assert!(
+        storage
+            .export_table(
+                "log_events; DROP TABLE log_events",
+                None,
+                None,
+                TableFormat::Csv,
+                &csv
+            )
+            .is_err()
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_open_read_only() {
+    use agenttop::storage::{LogEvent, StorageHandle};
+
+    let dir = std::env::temp_dir().join(format!("agenttop-readonly-{}", std::process::id()));
+    let path = dir.join("metrics.duckdb");
+    assert!(StorageHandle::open_read_only(&path).is_err());
+
+    {
+        let storage = StorageHandle::open(&path).unwrap();
+        storage.record_log_events(vec![LogEvent {
+            timestamp: Utc::now(),
+            event_name: Some("user_prompt".to_string()),
+            body: None,
+            attributes: HashMap::new(),
+        }]);
+        // Queries are handled in order, so this waits for the insert
+        storage.run_query("SELECT 1", 1).unwrap();
+    }
+    // Give the actor thread time to close the writable connection
+    std::thread::sleep(std::time::Duration::from_millis(200));
+
+    let reader = StorageHandle::open_read_only(&path).unwrap();
+    let result = reader
+        .run_query("SELECT event_name FROM log_events", 10)
+        .unwrap();
+    assert_eq!(result.rows, vec![vec![Some("user_prompt".to_string())]]);
+    assert!(reader.run_query("DELETE FROM log_events", 10).is_err());
+    drop(reader);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Test a reader releases the file when idle, so an agenttop can record in between
+#[test]
+fn test_attach_releases_the_file_when_idle() {
+    use agenttop::storage::{LogEvent, StorageHandle};
+
+    let dir = std::env::temp_dir().join(format!("agenttop-attach-{}", std::process::id()));
+    let path = dir.join("metrics.duckdb");
+    let event = |name: &str| LogEvent {
+        timestamp: Utc::now(),
+        event_name: Some(name.to_string()),
+        body: None,
+        attributes: HashMap::new(),
+    };
+    let count = |storage: &StorageHandle| {
+        storage
+            .run_query("SELECT count(*) FROM log_events", 1)
+            .unwrap()
+            .rows[0][0]
+            .clone()
+    };
+
+    let writer = StorageHandle::open(&path).unwrap();
+    writer.record_log_events(vec![event("user_prompt")]);
+    assert_eq!(count(&writer), Some("1".to_string()));
+    writer.close();
+
+    let viewer = StorageHandle::attach(&path).unwrap();
+    assert_eq!(count(&viewer), Some("1".to_string()));
+    assert!(viewer.run_query("DELETE FROM log_events", 10).is_err());
+
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    let writer = StorageHandle::open(&path).unwrap();
+    writer.record_log_events(vec![event("tool_result")]);
+    assert_eq!(count(&writer), Some("2".to_string()));
+    writer.close();
+    assert_eq!(count(&viewer), Some("2".to_string()));
+
+    viewer.close();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Test a database opened in the background takes writes and queries before it is ready
+#[test]
+fn test_open_lazy() {
+    use agenttop::storage::{LogEvent, StorageHandle};
+
+    let dir = std::env::temp_dir().join(format!("agenttop-lazy-{}", std::process::id()));
+    let path = dir.join("metrics.duckdb");
+
+    let storage = StorageHandle::open_lazy(&path);
+    storage.record_log_events(vec![LogEvent {
+        timestamp: Utc::now(),
+        event_name: Some("user_prompt".to_string()),
+        body: None,
+        attributes: HashMap::new(),
+    }]);
+    // Queries wait for the database to open, and behind the writes queued before them
+    assert!(!storage.is_empty().unwrap());
+    assert!(storage.is_ready());
+
+    drop(storage);
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_prune() {
+    use agenttop::storage::{LogEvent, StorageHandle};
+
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let now = Utc::now();
+    let event = |days_ago: i64| LogEvent {
+        timestamp: now - chrono::Duration::days(days_ago),
+        event_name: Some("tool_result".to_string()),
+        body: None,
+        attributes: HashMap::new(),
+    };
+    storage.record_log_events(vec![event(60), event(45), event(1)]);
+    storage.record_token_usage("input", 100);
+
+    let before = now - chrono::Duration::days(30);
+    let count = |counts: &[(String, u64)], table: &str| {
+        counts
+            .iter()
+            .find(|(t, _)| t == table)
+            .map(|(_, rows)| *rows)
+            .unwrap()
+    };
+
+    let dry_run = storage.prune(before, true).unwrap();
+    assert_eq!(count(&dry_run, "log_events"), 2);
+    assert_eq!(count(&dry_run, "token_usage"), 0);
+    assert_eq!(
+        storage.get_tool_metrics(None, None).unwrap()[0].call_count,
+        3
+    );
+
+    let deleted = storage.prune(before, false).unwrap();
+    assert_eq!(deleted, dry_run);
+    assert_eq!(
+        storage.get_tool_metrics(None, None).unwrap()[0].call_count,
+        1
+    );
+    assert_eq!(
+        storage.get_token_metrics(None, None).unwrap().input_tokens,
+        100
+    );
+    assert_eq!(
+        count(&storage.prune(before, true).unwrap(), "log_events"),
+        0
+    );
+}
+
+/// Test writes left in the journal by a crashed run are stored on the next start, and
+/// the journal is emptied once they are
+#[test]
+fn test_journal_replay() {
+    use agenttop::storage::{StorageHandle, journal_path};
+
+    let dir = std::env::temp_dir().join(format!("agenttop-journal-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = journal_path(&dir.join("metrics.duckdb"));
+    assert_eq!(path, dir.join("metrics.journal.jsonl"));
+    // Three writes: the second was committed, the third cut short by the crash
+    std::fs::write(
+        &path,
+        concat!(
+            r#"{"seq":0,"kind":"token_usage","token_type":"input","count":42,"model":null,"provider":null}"#,
+            "\n",
+            r#"{"seq":1,"kind":"cost","cost_usd":2.5,"session_id":null,"model":null,"cumulative":false}"#,
+            "\n",
+            r#"{"done":1}"#,
+            "\n",
+            r#"{"seq":2,"kind":"cost","cost_usd":1.5,"#,
+        ),
+    )
+    .unwrap();
+
+    let storage = StorageHandle::new_in_memory()
+        .unwrap()
+        .with_journal(&path)
+        .unwrap();
+    assert_eq!(
+        storage.get_token_metrics(None, None).unwrap().input_tokens,
+        42
+    );
+    assert_eq!(storage.get_total_cost(None, None).unwrap(), 0.0);
+    assert_eq!(std::fs::read(&path).unwrap().len(), 0);
+
+    // New writes are journaled until committed
+    storage.record_cost(0.5);
+    assert!((storage.get_total_cost(None, None).unwrap() - 0.5).abs() < 1e-9);
+    assert_eq!(std::fs::read(&path).unwrap().len(), 0);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Test a database written by a newer agenttop is refused with a clear error and left as is
+#[test]
+fn test_newer_schema_refused() {
+    use agenttop::storage::StorageHandle;
+
+    let dir = std::env::temp_dir().join(format!("agenttop-schema-{}", std::process::id()));
+    let path = dir.join("metrics.duckdb");
+    {
+        let storage = StorageHandle::open(&path).unwrap();
+        let result = storage
+            .run_query(
+                "SELECT value FROM agenttop_meta WHERE key = 'written_by'",
+                1,
+            )
+            .unwrap();
+        assert_eq!(
+            result.rows,
+            vec![vec![Some(env!("CARGO_PKG_VERSION").to_string())]]
+        );
+    }
+    std::thread::sleep(std::time::Duration::from_millis(200));
+
+    {
+        let conn = duckdb::Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "UPDATE agenttop_meta SET value = '99' WHERE key = 'schema_version';
+             UPDATE agenttop_meta SET value = '9.0.0' WHERE key = 'written_by';
+             UPDATE agenttop_meta SET value = '8.0.0' WHERE key = 'min_version';",
+        )
+        .unwrap();
+    }
+
+    for result in [
+        StorageHandle::open(&path),
+        StorageHandle::open_read_only(&path),
+    ] {
+        let message = result.err().unwrap().to_string();
+        assert!(message.contains("agenttop 9.0.0"), "{}", message);
+        assert!(message.contains("agenttop >= 8.0.0"), "{}", message);
+    }
+    std::thread::sleep(std::time::Duration::from_millis(200));
+
+    // The refused open didn't overwrite the recorded version
+    let conn = duckdb::Connection::open(&path).unwrap();
+    let schema: String = conn
+        .query_row(
+            "SELECT value FROM agenttop_meta WHERE key = 'schema_version'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(schema, "99");
+    drop(conn);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Test log events are grouped into sessions by session.id, or by inactivity gaps
+/// without one, and that a database without the sessions filled is backfilled on open
+#[test]
+fn test_sessions_tracked() {
+    use agenttop::storage::{LogEvent, SESSION_IDLE_GAP, StorageHandle};
+
+    let event = |name: &str, minute: i64, attrs: &[(&str, &str)]| LogEvent {
+        timestamp: Utc.with_ymd_and_hms(2025, 6, 1, 12, 0, 0).unwrap()
+            + chrono::Duration::minutes(minute),
+        event_name: Some(name.to_string()),
+        body: None,
+        attributes: attrs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+    };
+    let gap = SESSION_IDLE_GAP.as_secs() as i64 / 60;
+
+    let dir = std::env::temp_dir().join(format!("agenttop-sessions-{}", std::process::id()));
+    let path = dir.join("metrics.duckdb");
+    {
+        let storage = StorageHandle::open(&path).unwrap();
+        storage.record_log_events(vec![
+            event(
+                "claude_code.api_request",
+                0,
+                &[
+                    ("session.id", "abc"),
+                    ("cost_usd", "0.5"),
+                    ("input_tokens", "100"),
+                    ("output_tokens", "20"),
+                ],
+            ),
+            event("claude_code.tool_result", 1, &[("session.id", "abc")]),
+            // No session.id: split where the events are more than the gap apart
+            event("codex.api_request", 0, &[]),
+            event("codex.tool_result", gap + 5, &[]),
+        ]);
+        // A later batch adds to the same sessions
+        storage.record_log_events(vec![
+            event("claude_code.api_error", 3, &[("session.id", "abc")]),
+            event("codex.tool_result", gap + 10, &[]),
+        ]);
+
+        let sessions = storage.get_sessions(None, None, 10).unwrap();
+        assert_eq!(sessions.len(), 3);
+        let abc = sessions.iter().find(|s| s.session_id == "abc").unwrap();
+        assert!(!abc.inferred);
+        assert_eq!(abc.provider.as_deref(), Some("claude_code"));
+        assert_eq!((abc.events, abc.api_calls, abc.api_errors), (3, 1, 1));
+        assert_eq!(abc.tool_calls, 1);
+        assert!((abc.cost_usd - 0.5).abs() < 1e-9);
+        assert_eq!((abc.input_tokens, abc.output_tokens), (100, 20));
+        assert_eq!((abc.ended_at - abc.started_at).num_minutes(), 3);
+
+        // Most recently started first
+        let inferred: Vec<_> = sessions.iter().filter(|s| s.inferred).collect();
+        assert_eq!(inferred.len(), 2);
+        assert_eq!(inferred[0].provider.as_deref(), Some("openai_codex"));
+        assert_eq!((inferred[0].events, inferred[0].tool_calls), (2, 2));
+        assert_eq!((inferred[1].events, inferred[1].api_calls), (1, 1));
+
+        // Range filters keep sessions overlapping it
+        let until = Utc.with_ymd_and_hms(2025, 6, 1, 12, 2, 0).unwrap();
+        assert_eq!(
+            storage.get_sessions(None, Some(until), 10).unwrap().len(),
+            2
+        );
+        assert_eq!(storage.get_sessions(None, None, 1).unwrap().len(), 1);
+    }
+    std::thread::sleep(std::time::Duration::from_millis(200));
+
+    {
+        let conn = duckdb::Connection::open(&path).unwrap();
+        conn.execute_batch("DELETE FROM sessions").unwrap();
+    }
+    let storage = StorageHandle::open(&path).unwrap();
+    let sessions = storage.get_sessions(None, None, 10).unwrap();
+    assert_eq!(sessions.len(), 3);
+    assert_eq!(
+        sessions.iter().map(|s| s.events).sum::<u64>(),
+        6,
+        "{:?}",
+        sessions
+    );
+    drop(storage);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Test events less than ACTIVE_GAP apart make up a session's active time, the idle gaps
+/// between them don't count, and the segments are rebuilt on open and pruned
+#[test]
+fn test_session_active_time() {
+    use agenttop::storage::{ACTIVE_GAP, LogEvent, StorageHandle};
+
+    let start = Utc.with_ymd_and_hms(2025, 6, 1, 12, 0, 0).unwrap();
+    let event = |minute: i64| LogEvent {
+        timestamp: start + chrono::Duration::minutes(minute),
+        event_name: Some("claude_code.tool_result".to_string()),
+        body: None,
+        attributes: [("session.id".to_string(), "abc".to_string())].into(),
+    };
+    let gap = ACTIVE_GAP.as_secs() as i64 / 60;
+
+    let dir = std::env::temp_dir().join(format!("agenttop-activity-{}", std::process::id()));
+    let path = dir.join("metrics.duckdb");
+    {
+        let storage = StorageHandle::open(&path).unwrap();
+        // Active 0-10, idle, active 30-35, idle, active 60-61
+        storage.record_log_events((0..=10).step_by(2).map(event).collect());
+        storage.record_log_events(vec![event(30), event(60)]);
+        // A later batch joins the segments it is within the gap of
+        storage.record_log_events(vec![event(33), event(35), event(61)]);
+
+        let sessions = storage.get_sessions(None, None, 10).unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].active_secs, (10 + 5 + 1) * 60);
+        let utilization = sessions[0].utilization().unwrap();
+        assert!((utilization - 16.0 / 61.0).abs() < 1e-9);
+
+        let summary = storage.get_session_summary("abc").unwrap().unwrap();
+        assert_eq!(summary.active_secs, 16 * 60);
+
+        // Clipped to the range: 5-10 and 30-35
+        let metrics = storage
+            .get_session_metrics(
+                Some(start + chrono::Duration::minutes(5)),
+                Some(start + chrono::Duration::minutes(40)),
+            )
+            .unwrap();
+        assert_eq!(metrics.detected_active_secs, 10 * 60);
+        assert_eq!(metrics.session_secs, 35 * 60);
+
+        // Events within the gap of a segment extend it
+        storage.record_log_events(vec![event(61 + gap - 1)]);
+        let sessions = storage.get_sessions(None, None, 10).unwrap();
+        assert_eq!(sessions[0].active_secs, (10 + 5 + gap) as u64 * 60);
+    }
+    std::thread::sleep(std::time::Duration::from_millis(200));
+
+    {
+        let conn = duckdb::Connection::open(&path).unwrap();
+        conn.execute_batch("DROP TABLE session_activity").unwrap();
+    }
+    let storage = StorageHandle::open(&path).unwrap();
+    let sessions = storage.get_sessions(None, None, 10).unwrap();
+    assert_eq!(sessions[0].active_secs, (10 + 5 + gap) as u64 * 60);
+
+    storage
+        .prune(start + chrono::Duration::minutes(20), false)
+        .unwrap();
+    let metrics = storage.get_session_metrics(None, None).unwrap();
+    assert_eq!(metrics.detected_active_secs, (5 + gap) as u64 * 60);
+    drop(storage);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Test each provider's spend, tokens, latency and error counts are totalled apart
+#[test]
+fn test_get_provider_comparison() {
+    use agenttop::storage::{LogEvent, StorageHandle};
+
+    let start = Utc.with_ymd_and_hms(2025, 6, 1, 12, 0, 0).unwrap();
+    let event = |name: &str, secs: i64, attrs: &[(&str, &str)]| LogEvent {
+        timestamp: start + chrono::Duration::seconds(secs),
+        event_name: Some(name.to_string()),
+        body: None,
+        attributes: attrs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+    };
+    let request = |name: &str, secs: i64, session: &str, cost: &str, latency: &str| {
+        event(
+            name,
+            secs,
+            &[
+                ("session.id", session),
+                ("cost_usd", cost),
+                ("duration_ms", latency),
+                ("input_tokens", "1000"),
+                ("output_tokens", "100"),
+            ],
+        )
+    };
+
+    let storage = StorageHandle::new_in_memory().unwrap();
+    storage.record_log_events(vec![
+        request("claude_code.api_request", 0, "c1", "1.0", "1000"),
+        request("claude_code.api_request", 10, "c1", "2.0", "3000"),
+        request("claude_code.api_request", 20, "c2", "1.0", "2000"),
+        event("claude_code.api_error", 30, &[("session.id", "c2")]),
+        event(
+            "claude_code.tool_result",
+            40,
+            &[("session.id", "c2"), ("success", "true")],
+        ),
+        request("gemini_cli.api_request", 0, "g1", "0.5", "500"),
+        event(
+            "gemini_cli.tool_result",
+            5,
+            &[("session.id", "g1"), ("success", "false")],
+        ),
+        event(
+            "gemini_cli.tool_result",
+            6,
+            &[("session.id", "g1"), ("success", "true")],
+        ),
+        // No provider prefix: left out
+        event("tool_result", 7, &[("success", "false")]),
+    ]);
+
+    let providers = storage.get_provider_comparison(None, None).unwrap();
+    assert_eq!(providers.len(), 2);
+    // Most spend first
+    let claude = &providers[0];
+    assert_eq!(claude.provider, "claude_code");
+    assert_eq!(claude.sessions, 2);
+    assert_eq!((claude.api_calls, claude.api_errors), (3, 1));
+    assert!((claude.cost_usd - 4.0).abs() < 1e-9);
+    assert_eq!((claude.input_tokens, claude.output_tokens), (3000, 300));
+    assert!((claude.avg_latency_ms - 2000.0).abs() < 1e-9);
+    assert_eq!(claude.p95_latency_ms, 3000);
+    assert_eq!((claude.tool_calls, claude.tool_errors), (1, 0));
+    assert_eq!(claude.api_success_rate(), Some(0.75));
+    assert_eq!(claude.cost_per_session(), Some(2.0));
+
+    let gemini = &providers[1];
+    assert_eq!(gemini.provider, "gemini_cli");
+    assert_eq!(gemini.sessions, 1);
+    assert_eq!(gemini.tool_error_rate(), Some(0.5));
+    assert_eq!(gemini.api_success_rate(), Some(1.0));
+
+    // Only the events in the range
+    let since = start + chrono::Duration::seconds(15);
+    let providers = storage.get_provider_comparison(Some(since), None).unwrap();
+    assert_eq!(providers.len(), 1);
+    assert_eq!(providers[0].api_calls, 1);
+}
+
+/// Test turns run from a prompt to the next one in the same session, with their totals
+#[test]
+fn test_get_turns() {
+    use agenttop::storage::{LogEvent, StorageHandle};
+
+    let start = Utc.with_ymd_and_hms(2025, 6, 1, 12, 0, 0).unwrap();
+    let event = |name: &str, secs: i64, session: &str, attrs: &[(&str, &str)]| LogEvent {
+        timestamp: start + chrono::Duration::seconds(secs),
+        event_name: Some(format!("claude_code.{name}")),
+        body: None,
+        attributes: attrs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .chain([("session.id".to_string(), session.to_string())])
+            .collect(),
+    };
+
+    let storage = StorageHandle::new_in_memory().unwrap();
+    storage.record_log_events(vec![
+        // Before the first prompt: no turn
+        event("tool_result", 0, "a", &[]),
+        event("user_prompt", 10, "a", &[("prompt_length", "40")]),
+        event("tool_result", 12, "a", &[]),
+        event("tool_result", 13, "a", &[]),
+        event(
+            "api_request",
+            15,
+            "a",
+            &[
+                ("input_tokens", "1000"),
+                ("output_tokens", "200"),
+                ("cost_usd", "0.25"),
+            ],
+        ),
+        // Another session's prompt doesn't end session a's turn
+        event("user_prompt", 14, "b", &[]),
+        event("api_request", 16, "b", &[("input_tokens", "10")]),
+        event("user_prompt", 30, "a", &[]),
+    ]);
+
+    let turns = storage.get_turns(None, None, 10).unwrap();
+    assert_eq!(turns.len(), 3);
+    // Newest first
+    assert_eq!(turns[0].session_id.as_deref(), Some("a"));
+    assert_eq!((turns[0].api_calls, turns[0].tool_calls), (0, 0));
+    assert_eq!(turns[0].latency_ms(), 0);
+    assert_eq!(turns[1].session_id.as_deref(), Some("b"));
+    assert_eq!(turns[1].input_tokens, 10);
+
+    let first = &turns[2];
+    assert_eq!(first.started_at, start + chrono::Duration::seconds(10));
+    assert_eq!(first.prompt_length, Some(40));
+    assert_eq!((first.api_calls, first.tool_calls), (1, 2));
+    assert_eq!(first.tokens(), 1200);
+    assert!((first.cost_usd - 0.25).abs() < 1e-9);
+    assert_eq!(first.latency_ms(), 5000);
+
+    assert_eq!(storage.get_turns(None, None, 1).unwrap().len(), 1);
+}
+
+/// Test sessions are scored once, again after new events, and listed with their score
+#[test]
+fn test_session_efficiency_scores() {
+    use agenttop::storage::{LogEvent, StorageHandle};
+
+    let start = Utc.with_ymd_and_hms(2025, 6, 1, 12, 0, 0).unwrap();
+    let event = |name: &str, secs: i64, attrs: &[(&str, &str)]| LogEvent {
+        timestamp: start + chrono::Duration::seconds(secs),
+        event_name: Some(format!("claude_code.{name}")),
+        body: None,
+        attributes: attrs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .chain([("session.id".to_string(), "a".to_string())])
+            .collect(),
+    };
+
+    let storage = StorageHandle::new_in_memory().unwrap();
+    storage.record_log_events(vec![
+        event(
+            "tool_result",
+            0,
+            &[("tool_name", "Edit"), ("success", "true")],
+        ),
+        event(
+            "tool_result",
+            1,
+            &[("tool_name", "Read"), ("success", "true")],
+        ),
+        event(
+            "tool_result",
+            2,
+            &[("tool_name", "Bash"), ("success", "false")],
+        ),
+        event(
+            "tool_result",
+            3,
+            &[
+                ("tool_name", "Write"),
+                ("success", "true"),
+                ("decision", "reject"),
+            ],
+        ),
+        event(
+            "api_request",
+            4,
+            &[
+                ("input_tokens", "100"),
+                ("cache_read_tokens", "300"),
+                ("cost_usd", "1.0"),
+            ],
+        ),
+        event("api_error", 5, &[]),
+    ]);
+    // Sessions found by inactivity gaps aren't scored
+    storage.record_log_events(vec![LogEvent {
+        timestamp: start,
+        event_name: Some("gemini_cli.tool_call".to_string()),
+        body: None,
+        attributes: Default::default(),
+    }]);
+
+    assert_eq!(storage.score_sessions(10).unwrap(), 1);
+    assert_eq!(storage.score_sessions(10).unwrap(), 0);
+    let sessions = storage.get_sessions(None, None, 10).unwrap();
+    let scored = sessions.iter().find(|s| s.session_id == "a").unwrap();
+    let efficiency = scored.efficiency.unwrap();
+    assert_eq!(efficiency.success_rate, Some(75.0));
+    assert_eq!(efficiency.cache_reuse, Some(75.0));
+    assert_eq!(efficiency.retry_rate, Some(50.0));
+    // Only the accepted Edit counts
+    assert_eq!(efficiency.outcomes, 1);
+    assert_eq!(efficiency.cost_per_outcome, Some(1.0));
+    assert!(
+        sessions
+            .iter()
+            .any(|s| s.inferred && s.efficiency.is_none())
+    );
+
+    storage.record_log_events(vec![event("api_request", 60, &[("cost_usd", "1.0")])]);
+    assert_eq!(storage.score_sessions(10).unwrap(), 1);
+    let sessions = storage.get_sessions(None, None, 10).unwrap();
+    let scored = sessions.iter().find(|s| s.session_id == "a").unwrap();
+    assert_eq!(scored.efficiency.unwrap().cost_per_outcome, Some(2.0));
+}
+
+/// Test tool sequences are counted within sessions, with their loops and cycle times
+#[test]
+fn test_get_tool_sequences() {
+    use agenttop::storage::{LogEvent, StorageHandle};
+
+    let start = Utc.with_ymd_and_hms(2025, 6, 1, 12, 0, 0).unwrap();
+    let call = |tool: &str, secs: i64, session: &str| LogEvent {
+        timestamp: start + chrono::Duration::seconds(secs),
+        event_name: Some("claude_code.tool_result".to_string()),
+        body: None,
+        attributes: [
+            ("tool_name", tool),
+            ("session.id", session),
+            ("duration_ms", "100"),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect(),
+    };
+
+    let storage = StorageHandle::new_in_memory().unwrap();
+    storage.record_log_events(vec![
+        call("Grep", 0, "a"),
+        call("Read", 1, "a"),
+        call("Edit", 2, "a"),
+        call("Grep", 3, "a"),
+        call("Read", 4, "a"),
+        call("Edit", 5, "a"),
+        // Interleaved with session a, but a sequence of its own
+        call("Grep", 2, "b"),
+        call("Read", 3, "b"),
+        call("Edit", 4, "b"),
+    ]);
+
+    let sequences = storage.get_tool_sequences(None, None, 3, 10).unwrap();
+    // Read → Edit → Grep and Edit → Grep → Read occur once and are left out
+    assert_eq!(sequences.len(), 1, "{:?}", sequences);
+    let loop_ = &sequences[0];
+    assert_eq!(loop_.tools, ["Grep", "Read", "Edit"]);
+    assert_eq!((loop_.count, loop_.sessions, loop_.repeats), (3, 2, 1));
+    // From the start of the Grep call to the end of the Edit call
+    assert_eq!(loop_.avg_cycle_ms, 2100.0);
+
+    let pairs = storage.get_tool_sequences(None, None, 2, 10).unwrap();
+    assert_eq!(pairs.len(), 2);
+    assert_eq!(pairs[0].tools, ["Grep", "Read"]);
+    assert_eq!(pairs[0].count, 3);
+    assert_eq!(pairs[0].repeats, 0);
+    assert_eq!(
+        storage.get_tool_sequences(None, None, 2, 1).unwrap().len(),
+        1
+    );
+
+    let since = Some(start + chrono::Duration::seconds(4));
+    assert!(
+        storage
+            .get_tool_sequences(since, None, 3, 10)
+            .unwrap()
+            .is_empty()
+    );
+}
+
+/// Test tool calls are attributed to the normalized file they name, counted per file and
+/// per directory, and filled in for databases written before files were tracked
+#[test]
+fn test_get_file_activity() {
+    use agenttop::storage::{LogEvent, StorageHandle};
+
+    let start = Utc.with_ymd_and_hms(2025, 6, 1, 12, 0, 0).unwrap();
+    let call = |tool: &str, secs: i64, session: &str, attrs: &[(&str, &str)]| LogEvent {
+        timestamp: start + chrono::Duration::seconds(secs),
+        event_name: Some("claude_code.tool_result".to_string()),
+        body: None,
+        attributes: attrs
+            .iter()
+            .chain(&[("tool_name", tool), ("session.id", session)])
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+    };
+
+    let dir = std::env::temp_dir().join(format!("agenttop-files-{}", std::process::id()));
+    let path = dir.join("metrics.duckdb");
+    {
+        let storage = StorageHandle::open(&path).unwrap();
+        storage.record_log_events(vec![
+            call("Read", 0, "a", &[("file_path", "/repo/src/lib.rs")]),
+            call("Edit", 1, "a", &[("file_path", "/repo/src/./lib.rs")]),
+            call(
+                "Write",
+                2,
+                "b",
+                &[("tool_parameters", r#"{"file_path": "/repo/src/new.rs"}"#)],
+            ),
+            call("Read", 3, "b", &[("file_path", "/repo/README.md")]),
+            // No path
+            call(
+                "Bash",
+                4,
+                "b",
+                &[("tool_parameters", r#"{"bash_command": "ls"}"#)],
+            ),
+        ]);
+
+        let files = storage.get_file_activity(None, None, false, 10).unwrap();
+        assert_eq!(files.len(), 3, "{:?}", files);
+        assert_eq!(files[0].path, "/repo/src/lib.rs");
+        assert_eq!(
+            (files[0].edits, files[0].calls, files[0].sessions),
+            (1, 2, 1)
+        );
+        assert_eq!(files[1].path, "/repo/src/new.rs");
+        assert_eq!(files[2].path, "/repo/README.md");
+        assert_eq!(files[2].edits, 0);
+
+        let directories = storage.get_file_activity(None, None, true, 10).unwrap();
+        assert_eq!(directories.len(), 2);
+        assert_eq!(directories[0].path, "/repo/src");
+        assert_eq!((directories[0].edits, directories[0].calls), (2, 3));
+        assert_eq!(directories[0].sessions, 2);
+        assert_eq!(directories[0].last_at, start + chrono::Duration::seconds(2));
+
+        let since = Some(start + chrono::Duration::seconds(2));
+        assert_eq!(
+            storage
+                .get_file_activity(since, None, false, 10)
+                .unwrap()
+                .len(),
+            2
+        );
+        assert_eq!(
+            storage
+                .get_file_activity(None, None, false, 1)
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+    std::thread::sleep(std::time::Duration::from_millis(200));
+
+    {
+        let conn = duckdb::Connection::open(&path).unwrap();
+        conn.execute_batch("DROP TABLE file_events").unwrap();
+    }
+    let storage = StorageHandle::open(&path).unwrap();
+    let files = storage.get_file_activity(None, None, false, 10).unwrap();
+    assert_eq!(files.iter().map(|f| f.calls).sum::<u64>(), 4);
+    drop(storage);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Test anomalies are stored, listed newest first within the range and counted by kind
+#[test]
+fn test_anomalies_recorded() {
+    use agenttop::anomaly::{Anomaly, AnomalyKind, AnomalyScope};
+    use agenttop::storage::StorageHandle;
+
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let now = Utc::now();
+    let anomaly = |minutes_ago: i64, kind: AnomalyKind, subject: &str| Anomaly {
+        detected_at: now - chrono::Duration::minutes(minutes_ago),
+        kind,
+        scope: AnomalyScope::Tool,
+        subject: subject.to_string(),
+        value: 80.0,
+        baseline: 5.0,
+    };
+    storage.record_anomaly(anomaly(30, AnomalyKind::ErrorRate, "Bash"));
+    storage.record_anomaly(anomaly(2, AnomalyKind::Latency, "Read"));
+    storage.record_anomaly(anomaly(1, AnomalyKind::ErrorRate, "Edit"));
+
+    let recent = storage
+        .get_anomalies(Some(now - chrono::Duration::minutes(10)), None, 10)
+        .unwrap();
+    let subjects: Vec<&str> = recent.iter().map(|a| a.subject.as_str()).collect();
+    assert_eq!(subjects, vec!["Edit", "Read"]);
+    assert_eq!(recent[1].kind, AnomalyKind::Latency);
+    assert_eq!(recent[1].baseline, 5.0);
+    assert_eq!(storage.get_anomalies(None, None, 1).unwrap().len(), 1);
+
+    assert_eq!(storage.count_anomalies(None).unwrap(), 3);
+    assert_eq!(
+        storage
+            .count_anomalies(Some(AnomalyKind::ErrorRate))
+            .unwrap(),
+        2
+    );
+}
+
+/// Test errors are classified as they are stored, grouped by category and by tool or
+/// model, reclassified with new rules, backfilled and pruned
+#[test]
+fn test_error_taxonomy() {
+    use agenttop::storage::{LogEvent, StorageHandle};
+    use agenttop::taxonomy::{self, ErrorRule};
+
+    let start = Utc.with_ymd_and_hms(2025, 6, 1, 12, 0, 0).unwrap();
+    let event = |name: &str, minute: i64, attrs: &[(&str, &str)]| LogEvent {
+        timestamp: start + chrono::Duration::minutes(minute),
+        event_name: Some(name.to_string()),
+        body: None,
+        attributes: attrs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+    };
+    let failed = |minute: i64, session: &str, tool: &str, error: &str| {
+        event(
+            "claude_code.tool_result",
+            minute,
+            &[
+                ("session.id", session),
+                ("tool_name", tool),
+                ("success", "false"),
+                ("error", error),
+            ],
+        )
+    };
+
+    let dir = std::env::temp_dir().join(format!("agenttop-errors-{}", std::process::id()));
+    let path = dir.join("metrics.duckdb");
+    {
+        let storage = StorageHandle::open(&path).unwrap();
+        storage.record_log_events(vec![
+            failed(0, "s1", "Bash", "Permission denied (os error 13)"),
+            failed(10, "s2", "Bash", "bash: ./deploy.sh: Permission denied"),
+            failed(20, "s2", "Read", "ENOENT: no such file or directory"),
+            event(
+                "claude_code.api_error",
+                30,
+                &[
+                    ("session.id", "s2"),
+                    ("model", "claude-sonnet-4-5"),
+                    ("status_code", "429"),
+                    ("error", "Too many requests"),
+                ],
+            ),
+            event(
+                "claude_code.api_error",
+                40,
+                &[("model", "claude-sonnet-4-5"), ("error", "flaky widget")],
+            ),
+            // Not errors
+            event(
+                "claude_code.tool_result",
+                50,
+                &[("tool_name", "Bash"), ("success", "true")],
+            ),
+            event(
+                "claude_code.api_request",
+                50,
+                &[("model", "claude-sonnet-4-5")],
+            ),
+        ]);
+
+        let categories = storage.get_error_categories(None, None).unwrap();
+        let counts: Vec<(&str, u64)> = categories
+            .iter()
+            .map(|c| (c.category.as_str(), c.errors))
+            .collect();
+        assert_eq!(
+            counts,
+            [
+                ("permission_denied", 2),
+                ("not_found", 1),
+                (taxonomy::UNCATEGORIZED, 1),
+                ("rate_limit", 1)
+            ]
+        );
+        let denied = &categories[0];
+        assert_eq!((denied.tool_errors, denied.api_errors), (2, 0));
+        assert_eq!(denied.sessions, 2);
+        assert_eq!(denied.last_at, start + chrono::Duration::minutes(10));
+        assert_eq!(categories[3].api_errors, 1);
+
+        let breakdown = storage.get_error_breakdown(None, None, 10).unwrap();
+        assert_eq!(breakdown.len(), 4);
+        assert_eq!(
+            (
+                breakdown[0].category.as_str(),
+                breakdown[0].kind.as_str(),
+                breakdown[0].source.as_str(),
+                breakdown[0].errors
+            ),
+            ("permission_denied", "tool", "Bash", 2)
+        );
+        assert_eq!(
+            breakdown[0].last_message.as_deref(),
+            Some("bash: ./deploy.sh: Permission denied")
+        );
+        assert_eq!(storage.get_error_breakdown(None, None, 1).unwrap().len(), 1);
+
+        // Range
+        let categories = storage
+            .get_error_categories(Some(start + chrono::Duration::minutes(15)), None)
+            .unwrap();
+        assert_eq!(categories.iter().map(|c| c.errors).sum::<u64>(), 3);
+
+        // Configured rules apply to stored errors once reclassified
+        taxonomy::set_rules(&[ErrorRule {
+            category: "widget".to_string(),
+            pattern: "widget".to_string(),
+        }])
+        .unwrap();
+        assert_eq!(storage.reclassify_errors().unwrap(), 5);
+        let categories = storage.get_error_categories(None, None).unwrap();
+        assert!(categories.iter().any(|c| c.category == "widget"));
+        assert!(
+            !categories
+                .iter()
+                .any(|c| c.category == taxonomy::UNCATEGORIZED)
+        );
+    }
+    std::thread::sleep(std::time::Duration::from_millis(200));
+
+    {
+        let conn = duckdb::Connection::open(&path).unwrap();
+        conn.execute_batch("DROP TABLE error_events").unwrap();
+    }
+    let storage = StorageHandle::open(&path).unwrap();
+    let categories = storage.get_error_categories(None, None).unwrap();
+    assert_eq!(categories.iter().map(|c| c.errors).sum::<u64>(), 5);
+    assert_eq!(categories[0].category, "permission_denied");
+
+    storage
+        .prune(start + chrono::Duration::minutes(15), false)
+        .unwrap();
+    let categories = storage.get_error_categories(None, None).unwrap();
+    assert_eq!(categories.iter().map(|c| c.errors).sum::<u64>(), 3);
+    drop(storage);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Test usage points list each provider's requests and rate-limited errors in time order
+#[test]
+fn test_get_usage_points() {
+    use agenttop::storage::{LogEvent, StorageHandle};
+
+    let start = Utc.with_ymd_and_hms(2025, 6, 1, 12, 0, 0).unwrap();
+    let event = |name: &str, minute: i64, attrs: &[(&str, &str)]| LogEvent {
+        timestamp: start + chrono::Duration::minutes(minute),
+        event_name: Some(name.to_string()),
+        body: None,
+        attributes: attrs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+    };
+    let storage = StorageHandle::new_in_memory().unwrap();
+    storage.record_log_events(vec![
+        event(
+            "claude_code.api_request",
+            0,
+            &[
+                ("input_tokens", "1000"),
+                ("output_tokens", "200"),
+                ("cost_usd", "0.5"),
+            ],
+        ),
+        event("claude_code.api_error", 10, &[("status_code", "429")]),
+        // Not rate limited
+        event("claude_code.api_error", 15, &[("status_code", "500")]),
+        event(
+            "claude_code.api_request",
+            20,
+            &[("input_tokens", "300"), ("cost_usd", "0.1")],
+        ),
+        event(
+            "gemini_cli.api_request",
+            5,
+            &[("input_tokens", "50"), ("output_tokens", "5")],
+        ),
+        event(
+            "gemini_cli.api_error",
+            6,
+            &[("error", "Rate limit exceeded")],
+        ),
+    ]);
+
+    let points = storage
+        .get_usage_points(Some("claude_code"), start)
+        .unwrap();
+    let summary: Vec<(i64, u64, bool)> = points
+        .iter()
+        .map(|p| {
+            (
+                (p.timestamp - start).num_minutes(),
+                p.tokens,
+                p.rate_limited,
+            )
+        })
+        .collect();
+    assert_eq!(summary, [(0, 1200, false), (10, 0, true), (20, 300, false)]);
+    assert!((points[0].cost_usd - 0.5).abs() < 1e-9);
+
+    let all = storage.get_usage_points(None, start).unwrap();
+    assert_eq!(all.len(), 5);
+    assert_eq!(all.iter().filter(|p| p.rate_limited).count(), 2);
+    let later = storage
+        .get_usage_points(None, start + chrono::Duration::minutes(6))
+        .unwrap();
+    assert_eq!(later.len(), 3);
+
+    let err = storage.get_usage_points(Some("nope"), start).unwrap_err();
+    assert!(err.to_string().contains("Unknown provider"), "{}", err);
+}
+
+#[test]
+fn test_get_context_samples() {
+    use agenttop::storage::{LogEvent, StorageHandle};
+
+    let start = Utc.with_ymd_and_hms(2025, 6, 1, 12, 0, 0).unwrap();
+    let event = |name: &str, minute: i64, attrs: &[(&str, &str)]| LogEvent {
+        timestamp: start + chrono::Duration::minutes(minute),
+        event_name: Some(name.to_string()),
+        body: None,
+        attributes: attrs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+    };
+    let storage = StorageHandle::new_in_memory().unwrap();
+    storage.record_log_events(vec![
+        event(
+            "claude_code.api_request",
+            10,
+            &[
+                ("session.id", "a"),
+                ("model", "claude-sonnet-4-5"),
+                ("input_tokens", "100"),
+                ("cache_read_tokens", "150000"),
+                ("cache_creation_tokens", "2000"),
+                ("output_tokens", "900"),
+            ],
+        ),
+        event(
+            "codex.api_request",
+            0,
+            &[("input_tokens", "30000"), ("context_window", "272000")],
+        ),
+        event("claude_code.tool_result", 5, &[("session.id", "a")]),
+    ]);
+
+    let samples = storage.get_context_samples(None, None, 10).unwrap();
+    let summary: Vec<(i64, Option<&str>, u64, Option<u64>)> = samples
+        .iter()
+        .map(|s| {
+            (
+                (s.timestamp - start).num_minutes(),
+                s.session_id.as_deref(),
+                s.context_tokens,
+                s.context_window,
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            (0, None, 30_000, Some(272_000)),
+            (10, Some("a"), 152_100, None)
+        ]
+    );
+    assert_eq!(samples[1].model.as_deref(), Some("claude-sonnet-4-5"));
+
+    // The limit keeps the latest
+    let latest = storage.get_context_samples(None, None, 1).unwrap();
+    assert_eq!(latest[0].session_id.as_deref(), Some("a"));
+    let since = storage
+        .get_context_samples(Some(start + chrono::Duration::minutes(1)), None, 10)
+        .unwrap();
+    assert_eq!(since.len(), 1);
+}
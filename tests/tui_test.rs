@@ -3,8 +3,16 @@
 //! These tests verify that the TUI App correctly interacts with the storage
 //! layer and can render data properly.
 
-use agenttop::storage::{LogEvent, StorageHandle};
-use agenttop::tui::app::{App, SortColumn, TimeFilter};
+use agenttop::export::ExportFormat;
+use agenttop::self_metrics::ReceiverSnapshot;
+use agenttop::storage::{ApiMetrics, InFlightTool, LogEvent, StorageHandle, ToolMetrics};
+use agenttop::tui::alerts::{self, Alert, AlertThresholds};
+use agenttop::tui::app::{
+    ActivityHeatmap, App, COMPACT_MAX_WIDTH, CostProjection, HeatmapMetric, SortColumn,
+    SuccessTrend, TimeFilter, View,
+};
+use agenttop::tui::snapshot::{self, SnapshotFormat};
+use agenttop::tui::ui::{WIDE_LAYOUT_MIN_WIDTH, braille_sparkline};
 use chrono::Utc;
 use ratatui::{Terminal, backend::TestBackend};
 use std::collections::HashMap;
@@ -100,6 +108,61 @@ fn test_app_cache_reuse_rate() {
     assert!((app.cache_reuse_rate() - 80.0).abs() < 0.1);
 }
 
+/// Test cache savings are priced at the mix of requested models
+#[test]
+fn test_app_cache_savings() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let mut app = App::new(storage);
+
+    app.token_metrics.cache_read_tokens = 2_000_000;
+    assert_eq!(app.cache_savings_usd(), None);
+
+    // Unpriced models are ignored
+    app.api_metrics.models = HashMap::from([
+        ("claude-sonnet-4-5".to_string(), 3),
+        ("claude-opus-4-1".to_string(), 1),
+        ("local-llama".to_string(), 10),
+    ]);
+    // Sonnet saves $2.70/M and Opus $13.50/M: (3 * 5.40 + 1 * 27.00) / 4 = 10.80
+    let saved = app.cache_savings_usd().unwrap();
+    assert!((saved - 10.80).abs() < 1e-6, "saved {}", saved);
+
+    let text = snapshot::render(&app, SnapshotFormat::Text, 140, 24).unwrap();
+    assert!(text.contains("Cache saved ~$10.80"));
+}
+
+/// Test cache reads with a priced model are saved at that model's prices, and the
+/// cache view shows them per day and per model
+#[test]
+fn test_ui_cache_view() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let sonnet = Some("claude-sonnet-4-5");
+    storage.record_model_token_usage("input", 1_000_000, sonnet, Some("claude_code"));
+    storage.record_model_token_usage("cacheRead", 1_000_000, sonnet, Some("claude_code"));
+    storage.record_token_usage("cacheRead", 1_000_000);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let mut app = App::new(storage);
+    app.refresh().unwrap();
+    // Without requested models the reads lacking a model aren't priced
+    let saved = app.cache_savings_usd().unwrap();
+    assert!((saved - 2.70).abs() < 1e-9, "saved {}", saved);
+    app.api_metrics.models = HashMap::from([("claude-opus-4-1".to_string(), 1)]);
+    let saved = app.cache_savings_usd().unwrap();
+    assert!((saved - 16.20).abs() < 1e-9, "saved {}", saved);
+
+    app.view = View::Cache;
+    let text = snapshot::render(&app, SnapshotFormat::Text, 100, 24).unwrap();
+    assert!(
+        text.contains("Cache · hit rate 67% · saved ~$2.70"),
+        "{}",
+        text
+    );
+    assert!(text.contains("67%  ~$2.70"), "{}", text);
+    assert!(text.contains("By model"));
+    assert!(text.contains("unknown"));
+}
+
 /// Test that App sorting works correctly
 #[test]
 fn test_app_sorting() {
@@ -134,6 +197,28 @@ fn test_app_sorting() {
     assert_eq!(app.tool_metrics[2].tool_name, "AAA");
 }
 
+/// Test a refresh asks for a redraw only when something shown changed
+#[test]
+fn test_app_redraws_only_on_change() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let mut app = App::new(storage.clone());
+    assert!(app.needs_redraw);
+    app.refresh().unwrap();
+    assert!(!app.is_idle(Utc::now()));
+
+    app.needs_redraw = false;
+    app.refresh().unwrap();
+    assert!(!app.needs_redraw);
+
+    storage.record_log_events(vec![make_tool_event("Read", true, 50)]);
+    app.refresh().unwrap();
+    assert!(app.needs_redraw);
+    assert_eq!(app.tool_metrics.len(), 1);
+
+    // Slows down once nothing new has arrived for a while
+    assert!(app.is_idle(Utc::now() + chrono::Duration::minutes(5)));
+}
+
 /// Test navigation works correctly
 #[test]
 fn test_app_navigation() {
@@ -167,6 +252,57 @@ fn test_app_navigation() {
     assert_eq!(app.selected_index, 2);
 }
 
+/// Test vim-style jumps: rows, clamped moves, and built-in/MCP section jumps
+#[test]
+fn test_app_jump_navigation() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+
+    let mut events = vec![
+        make_tool_event("mcp__github__search", true, 10),
+        make_tool_event("mcp__github__search", true, 10),
+        make_tool_event("mcp__slack__post", true, 10),
+        make_tool_event("Read", true, 10),
+        make_tool_event("Bash", true, 10),
+    ];
+    // Make the MCP tool the busiest so it sorts first overall
+    events.push(make_tool_event("mcp__github__search", true, 10));
+    storage.record_log_events(events);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let mut app = App::new(storage);
+    app.refresh().unwrap();
+
+    // Built-in tools come first so the selection matches the table layout
+    let names: Vec<&str> = app
+        .tool_metrics
+        .iter()
+        .map(|t| t.tool_name.as_str())
+        .collect();
+    assert_eq!(
+        names,
+        vec!["Bash", "Read", "mcp__github__search", "mcp__slack__post"]
+    );
+
+    app.select_next_section();
+    assert_eq!(app.selected_index, 2);
+    app.select_next_section();
+    assert_eq!(app.selected_index, 0);
+    app.select_previous_section();
+    assert_eq!(app.selected_index, 2);
+    app.move_selection(1);
+    app.select_previous_section();
+    assert_eq!(app.selected_index, 2);
+
+    app.select_row(usize::MAX);
+    assert_eq!(app.selected_index, 3);
+    app.move_selection(10);
+    assert_eq!(app.selected_index, 3);
+    app.move_selection(-10);
+    assert_eq!(app.selected_index, 0);
+    app.select_row(1);
+    assert_eq!(app.selected_tool().unwrap().tool_name, "Read");
+}
+
 /// Test pause functionality
 #[test]
 fn test_app_pause() {
@@ -266,329 +402,1959 @@ fn test_app_cost() {
     assert!((app.token_metrics.total_cost_usd - 0.08).abs() < 0.001);
 }
 
-// =============================================================================
-// UI Rendering Tests
-// =============================================================================
-
-/// Test that UI can render with empty data without crashing
+/// Test cost projection extrapolates the burn rate to end of day and month
 #[test]
-fn test_ui_renders_empty_state() {
-    let storage = StorageHandle::new_in_memory().unwrap();
-    let app = App::new(storage);
+fn test_cost_projection_extrapolates_burn_rate() {
+    use chrono::{Local, TimeZone};
 
-    // Create a test terminal
-    let backend = TestBackend::new(80, 24);
-    let mut terminal = Terminal::new(backend).unwrap();
+    // 18:00 on Jan 30th: 6 hours left in the day, 30 hours left in the month
+    let now = Local.with_ymd_and_hms(2025, 1, 30, 18, 0, 0).unwrap();
+    let projection = CostProjection::project(now, 2.0, 40.0, 0.5);
 
-    // Render the UI - this should not panic
-    terminal.draw(|f| agenttop::tui::ui::draw(f, &app)).unwrap();
+    assert!((projection.end_of_day_usd - 5.0).abs() < 0.01);
+    assert!((projection.end_of_month_usd - 55.0).abs() < 0.01);
+}
 
-    // Verify something was rendered
-    let buffer = terminal.backend().buffer();
-    assert!(buffer.area.width > 0);
-    assert!(buffer.area.height > 0);
+/// Test cost projection with no recent spend is just the current totals
+#[test]
+fn test_cost_projection_idle() {
+    use chrono::{Local, TimeZone};
+
+    let now = Local.with_ymd_and_hms(2025, 12, 15, 9, 30, 0).unwrap();
+    let projection = CostProjection::project(now, 1.25, 10.0, 0.0);
+
+    assert!((projection.end_of_day_usd - 1.25).abs() < 0.001);
+    assert!((projection.end_of_month_usd - 10.0).abs() < 0.001);
 }
 
-/// Test that UI can render with real data
+/// Test App computes a projection from recent spend
 #[test]
-fn test_ui_renders_with_data() {
+fn test_app_cost_projection() {
     let storage = StorageHandle::new_in_memory().unwrap();
 
-    // Insert various test data
-    storage.record_log_events(vec![
-        make_tool_event("Read", true, 50),
-        make_tool_event("Read", true, 75),
-        make_tool_event("Write", true, 100),
-        make_tool_event("Write", false, 200),
-        make_tool_event("Bash", true, 500),
-        make_tool_event("Bash", true, 750),
-        make_tool_event("Bash", false, 1000),
-    ]);
-    storage.record_token_usage("input", 5000);
-    storage.record_token_usage("output", 2500);
-    storage.record_token_usage("cacheRead", 10000);
-    storage.record_cost(0.15);
-    storage.record_session_metric("lines_of_code", 250);
-    storage.record_session_metric("commit", 3);
-
+    storage.record_cost(0.50);
     std::thread::sleep(std::time::Duration::from_millis(100));
 
     let mut app = App::new(storage);
     app.refresh().unwrap();
 
-    // Create a test terminal
-    let backend = TestBackend::new(100, 30);
-    let mut terminal = Terminal::new(backend).unwrap();
-
-    // Render the UI
-    terminal.draw(|f| agenttop::tui::ui::draw(f, &app)).unwrap();
-
-    // Convert buffer to string for inspection
-    let buffer = terminal.backend().buffer();
-    let content: String = buffer.content.iter().map(|cell| cell.symbol()).collect();
-
-    // Verify key elements are rendered
-    assert!(content.contains("agenttop"), "Should show app name");
-    assert!(content.contains("Tokens"), "Should show token gauge");
-    assert!(content.contains("Tools"), "Should show tools section");
-    assert!(content.contains("Bash"), "Should show Bash tool");
-    assert!(content.contains("Read"), "Should show Read tool");
-    assert!(content.contains("Write"), "Should show Write tool");
+    assert!((app.cost_projection.burn_rate_per_hour - 0.50).abs() < 0.001);
+    assert!(app.cost_projection.end_of_day_usd >= 0.50);
+    assert!(app.cost_projection.end_of_month_usd >= app.cost_projection.end_of_day_usd);
 }
 
-/// Test that UI renders detail popup correctly
+/// Test exporting the current view writes displayed rows in display order
 #[test]
-fn test_ui_renders_detail_popup() {
+fn test_app_export_view_csv() {
     let storage = StorageHandle::new_in_memory().unwrap();
-
     storage.record_log_events(vec![
-        make_tool_event("Bash", true, 100),
-        make_tool_event("Bash", true, 150),
-        make_tool_event("Bash", false, 200),
+        make_tool_event("Read", true, 10),
+        make_tool_event("Read", true, 10),
+        make_tool_event("Bash", false, 50),
     ]);
     std::thread::sleep(std::time::Duration::from_millis(100));
 
     let mut app = App::new(storage);
     app.refresh().unwrap();
-    app.toggle_detail(); // Show detail popup
-
-    let backend = TestBackend::new(100, 30);
-    let mut terminal = Terminal::new(backend).unwrap();
-
-    terminal.draw(|f| agenttop::tui::ui::draw(f, &app)).unwrap();
 
-    let buffer = terminal.backend().buffer();
-    let content: String = buffer.content.iter().map(|cell| cell.symbol()).collect();
+    let dir = std::env::temp_dir().join(format!("agenttop-export-test-{}", std::process::id()));
+    let path = app.export_view_to(&dir, ExportFormat::Csv).unwrap();
+    let content = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
 
-    // Verify detail popup content
-    assert!(content.contains("Details"), "Should show details title");
-    assert!(content.contains("Total Calls"), "Should show total calls");
-    assert!(
-        content.contains("Successful"),
-        "Should show successful count"
-    );
-    assert!(content.contains("Errors"), "Should show error count");
+    let lines: Vec<&str> = content.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("tool_name,"));
+    assert!(lines[1].starts_with("Read,Read,builtin,2,0,2,"));
+    assert!(lines[2].starts_with("Bash,Bash,builtin,1,1,0,"));
 }
 
-/// Test UI with large terminal size
+/// Test JSON export includes the time range and tool rows
 #[test]
-fn test_ui_renders_large_terminal() {
+fn test_app_export_view_json() {
     let storage = StorageHandle::new_in_memory().unwrap();
-
-    // Add many tools to test scrolling/layout
-    for i in 0..20 {
-        storage.record_log_events(vec![make_tool_event(
-            &format!("Tool{:02}", i),
-            true,
-            50 + i * 10,
-        )]);
-    }
+    storage.record_log_events(vec![make_tool_event("Read", true, 10)]);
     std::thread::sleep(std::time::Duration::from_millis(100));
 
     let mut app = App::new(storage);
+    app.time_filter = TimeFilter::Last24Hours;
     app.refresh().unwrap();
 
-    // Large terminal
-    let backend = TestBackend::new(200, 50);
-    let mut terminal = Terminal::new(backend).unwrap();
+    let dir = std::env::temp_dir().join(format!("agenttop-export-json-{}", std::process::id()));
+    let path = app.export_view_to(&dir, ExportFormat::Json).unwrap();
+    let content = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
 
-    // Should not panic
-    terminal.draw(|f| agenttop::tui::ui::draw(f, &app)).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&content).unwrap();
+    assert_eq!(json["time_range"], "Last 24h");
+    assert_eq!(json["tools"][0]["tool_name"], "Read");
+    assert_eq!(json["tools"][0]["call_count"], 1);
 }
 
-/// Test UI with small terminal size
+/// Test toasts expire after being shown
 #[test]
-fn test_ui_renders_small_terminal() {
+fn test_app_toast_expires() {
     let storage = StorageHandle::new_in_memory().unwrap();
+    let mut app = App::new(storage);
+    assert!(app.active_toast().is_none());
 
-    storage.record_log_events(vec![
-        make_tool_event("Read", true, 50),
-        make_tool_event("Write", true, 100),
-    ]);
+    app.show_toast("Exported", false);
+    assert_eq!(app.active_toast().unwrap().message, "Exported");
+
+    app.toast.as_mut().unwrap().shown_at = Utc::now() - chrono::Duration::seconds(60);
+    assert!(app.active_toast().is_none());
+}
+
+/// Test text snapshots contain the rendered dashboard
+#[test]
+fn test_snapshot_text_render() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    storage.record_log_events(vec![make_tool_event("Read", true, 10)]);
     std::thread::sleep(std::time::Duration::from_millis(100));
 
     let mut app = App::new(storage);
     app.refresh().unwrap();
 
-    // Small terminal (minimum usable size)
-    let backend = TestBackend::new(60, 15);
-    let mut terminal = Terminal::new(backend).unwrap();
-
-    // Should not panic even on small terminal
-    terminal.draw(|f| agenttop::tui::ui::draw(f, &app)).unwrap();
+    let text = snapshot::render(&app, SnapshotFormat::Text, 100, 30).unwrap();
+    assert_eq!(text.lines().count(), 30);
+    assert!(text.contains("agenttop"));
+    assert!(text.contains("Read"));
+    assert!(text.lines().all(|line| !line.ends_with(' ')));
 }
 
-// =============================================================================
-// Time Filter Tests
-// =============================================================================
-
-/// Test time filter cycling
+/// Test HTML snapshots are escaped, styled documents
 #[test]
-fn test_app_time_filter_cycling() {
+fn test_snapshot_html_render() {
     let storage = StorageHandle::new_in_memory().unwrap();
-    let mut app = App::new(storage);
+    storage.record_log_events(vec![make_tool_event("mcp__srv__<b>", true, 10)]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
 
-    // Default should be AllTime
-    assert_eq!(app.time_filter, TimeFilter::AllTime);
+    let mut app = App::new(storage);
+    app.refresh().unwrap();
 
-    // Cycle through filters
-    app.toggle_time_filter();
-    assert_eq!(app.time_filter, TimeFilter::LastHour);
+    let html = snapshot::render(&app, SnapshotFormat::Html, 100, 30).unwrap();
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains("<span style=\"color:"));
+    assert!(html.contains("&lt;b&gt;"));
+    assert!(!html.contains("<b>"));
+}
 
-    app.toggle_time_filter();
-    assert_eq!(app.time_filter, TimeFilter::Last24Hours);
+/// Test saving a snapshot writes a timestamped file
+#[test]
+fn test_snapshot_save() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let app = App::new(storage);
 
-    app.toggle_time_filter();
-    assert_eq!(app.time_filter, TimeFilter::Last7Days);
+    let dir = std::env::temp_dir().join(format!("agenttop-snapshot-test-{}", std::process::id()));
+    let path = snapshot::save(&app, &dir, SnapshotFormat::Text, 80, 24).unwrap();
+    let content = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
 
-    app.toggle_time_filter();
-    assert_eq!(app.time_filter, TimeFilter::AllTime);
+    assert!(path.extension().is_some_and(|ext| ext == "txt"));
+    assert_eq!(content.lines().count(), 24);
 }
 
-/// Test time filter labels
+/// Test the receiver events/sec rate is computed over the sample window
 #[test]
-fn test_time_filter_labels() {
-    assert_eq!(TimeFilter::LastHour.label(), "Last 1h");
-    assert_eq!(TimeFilter::Last24Hours.label(), "Last 24h");
-    assert_eq!(TimeFilter::Last7Days.label(), "Last 7d");
-    assert_eq!(TimeFilter::AllTime.label(), "All-time");
+fn test_app_receiver_events_per_sec() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let mut app = App::new(storage);
+    let start = Utc::now();
+    let snapshot = |events_received| ReceiverSnapshot {
+        listen_addr: Some("127.0.0.1:4318".to_string()),
+        events_received,
+        payloads_dropped: 0,
+        batches_dropped: 0,
+        values_truncated: 0,
+        hook_runs_dropped: 0,
+        last_event_at: Some(start),
+    };
+
+    app.update_receiver_status(snapshot(100), start);
+    assert_eq!(app.receiver_status.events_per_sec, 0.0);
+
+    app.update_receiver_status(snapshot(120), start + chrono::Duration::seconds(5));
+    assert!((app.receiver_status.events_per_sec - 4.0).abs() < 0.001);
+
+    // Samples older than the window are dropped
+    app.update_receiver_status(snapshot(120), start + chrono::Duration::seconds(30));
+    assert_eq!(app.receiver_status.events_per_sec, 0.0);
 }
 
-/// Test time filter since values
+/// Helper to create tool metrics for alert evaluation
+fn make_tool_metrics(tool_name: &str, call_count: u64, error_count: u64) -> ToolMetrics {
+    ToolMetrics {
+        tool_name: tool_name.to_string(),
+        call_count,
+        last_call: None,
+        avg_duration_ms: 0.0,
+        min_duration_ms: 0.0,
+        max_duration_ms: 0.0,
+        success_count: call_count - error_count,
+        error_count,
+        approved_count: 0,
+        rejected_count: 0,
+    }
+}
+
+/// Test tool alerts respect the rate threshold and minimum call count
 #[test]
-fn test_time_filter_since() {
-    // AllTime should return None
-    assert!(TimeFilter::AllTime.since().is_none());
+fn test_alerts_tool_error_rate() {
+    let thresholds = AlertThresholds::default();
+    let tools = vec![
+        make_tool_metrics("Read", 20, 1), // 5%: below threshold
+        make_tool_metrics("Edit", 8, 4),  // 50%: alert
+        make_tool_metrics("Bash", 10, 3), // 30%: alert
+        make_tool_metrics("Write", 2, 2), // too few calls
+    ];
+
+    let alerts = alerts::evaluate(&tools, &ApiMetrics::default(), &thresholds);
+    assert_eq!(alerts.len(), 2);
+    assert_eq!(alerts[0].message(), "Edit: 4/8 calls failed (50%)");
+    assert_eq!(alerts[1].message(), "Bash: 3/10 calls failed (30%)");
+}
 
-    // Other filters should return Some datetime in the past
-    let now = Utc::now();
+/// Test API error alerts name the offending model
+#[test]
+fn test_alerts_api_errors() {
+    let thresholds = AlertThresholds::default();
+    let mut api = ApiMetrics {
+        total_errors: 6,
+        ..Default::default()
+    };
+    api.errors_by_model.insert("claude-opus-4".to_string(), 4);
+    api.errors_by_model.insert("claude-haiku".to_string(), 1);
+
+    let alerts = alerts::evaluate(&[], &api, &thresholds);
+    assert_eq!(
+        alerts,
+        vec![Alert::ApiErrors {
+            model: Some("claude-opus-4".to_string()),
+            errors: 4,
+        }]
+    );
+}
 
-    let last_hour = TimeFilter::LastHour.since().unwrap();
-    assert!(last_hour < now);
-    assert!((now - last_hour).num_minutes() >= 59);
+/// Test App raises alerts from recent events and clears them on recovery
+#[test]
+fn test_app_alerts_raise_and_clear() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    storage.record_log_events(
+        (0..4)
+            .map(|i| make_tool_event("Edit", i == 0, 10))
+            .collect(),
+    );
+    std::thread::sleep(std::time::Duration::from_millis(100));
 
-    let last_24h = TimeFilter::Last24Hours.since().unwrap();
-    assert!(last_24h < now);
-    assert!((now - last_24h).num_hours() >= 23);
+    let mut app = App::new(storage.clone());
+    app.refresh().unwrap();
+    assert_eq!(app.alerts.len(), 1);
 
-    let last_7d = TimeFilter::Last7Days.since().unwrap();
-    assert!(last_7d < now);
-    assert!((now - last_7d).num_days() >= 6);
+    // Successful calls bring the error rate back under the threshold
+    storage.record_log_events((0..20).map(|_| make_tool_event("Edit", true, 10)).collect());
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    app.refresh().unwrap();
+    assert!(app.alerts.is_empty());
 }
 
-// =============================================================================
-// Tool Classification Filter Tests
-// =============================================================================
-
-/// Test that App correctly filters built-in tools
+/// Test recent anomalies from the detector join the alert banner
 #[test]
-fn test_app_builtin_tools() {
-    let storage = StorageHandle::new_in_memory().unwrap();
+fn test_app_alerts_show_anomalies() {
+    use agenttop::anomaly::{Anomaly, AnomalyKind, AnomalyScope};
 
-    // Mix of built-in and MCP tools (any non-builtin is MCP)
-    storage.record_log_events(vec![
-        make_tool_event("Read", true, 50),
-        make_tool_event("Write", true, 100),
-        make_tool_event("Bash", true, 200),
-        make_tool_event("context7", true, 150), // MCP (old style)
-        make_tool_event("playwright", true, 300), // MCP (old style)
-    ]);
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let anomaly = |minutes_ago: i64| Anomaly {
+        detected_at: Utc::now() - chrono::Duration::minutes(minutes_ago),
+        kind: AnomalyKind::ErrorRate,
+        scope: AnomalyScope::Tool,
+        subject: "Bash".to_string(),
+        value: 75.0,
+        baseline: 4.0,
+    };
+    // Outside the look-back window
+    storage.record_anomaly(anomaly(60));
+    storage.record_anomaly(anomaly(1));
     std::thread::sleep(std::time::Duration::from_millis(100));
 
     let mut app = App::new(storage);
     app.refresh().unwrap();
+    assert_eq!(app.alerts.len(), 1);
+    assert_eq!(
+        app.alerts[0].message(),
+        "Bash error rate spiked to 75% (usually 4%)"
+    );
 
-    let builtin = app.builtin_tools();
-    assert_eq!(builtin.len(), 3);
-
-    let builtin_names: Vec<&str> = builtin.iter().map(|t| t.tool_name.as_str()).collect();
-    assert!(builtin_names.contains(&"Read"));
-    assert!(builtin_names.contains(&"Write"));
-    assert!(builtin_names.contains(&"Bash"));
-    assert!(!builtin_names.contains(&"context7"));
-    assert!(!builtin_names.contains(&"playwright"));
+    let text = snapshot::render(&app, SnapshotFormat::Text, 120, 24).unwrap();
+    assert!(text.contains("Bash error rate spiked to 75%"), "{}", text);
 }
 
-/// Test that App correctly filters MCP tools
-/// Any tool not in the built-in list is considered MCP
+/// Test the usage window's capacity left shows in the header, and near the capacity
+/// joins the alert banner
 #[test]
-fn test_app_mcp_tools() {
+fn test_app_quota_window() {
     let storage = StorageHandle::new_in_memory().unwrap();
-
-    // Mix of built-in and MCP tools
+    let request = |tokens: &str| LogEvent {
+        timestamp: Utc::now(),
+        event_name: Some("claude_code.api_request".to_string()),
+        body: None,
+        attributes: [("input_tokens", tokens), ("cost_usd", "0.1")]
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+    };
+    storage.record_log_events(vec![request("4000")]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let mut app = App::new(storage.clone());
+    app.quotas =
+        serde_json::from_str(r#"[{"provider": "claude_code", "limit_tokens": 10000}]"#).unwrap();
+    app.refresh().unwrap();
+    assert_eq!(app.quota_statuses[0].percent(), Some(40.0));
+    assert!(app.alerts.is_empty());
+    let text = snapshot::render(&app, SnapshotFormat::Text, 200, 24).unwrap();
+    assert!(
+        text.contains("Window: claude_code: 60% left, resets "),
+        "{}",
+        text
+    );
+
+    storage.record_log_events(vec![request("5000")]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    app.invalidate();
+    app.refresh().unwrap();
+    assert_eq!(app.alerts.len(), 1);
+    assert!(
+        app.alerts[0]
+            .message()
+            .starts_with("Usage window claude_code: 10% left"),
+        "{}",
+        app.alerts[0].message()
+    );
+}
+
+/// Test the previous window has equal length and ends where the current one starts
+#[test]
+fn test_time_filter_previous_window() {
+    assert!(TimeFilter::AllTime.previous_window().is_none());
+
+    let since = Utc::now() - chrono::Duration::hours(5);
+    let until = since + chrono::Duration::hours(3);
+    let filter = TimeFilter::Range {
+        since: Some(since),
+        until: Some(until),
+    };
+    assert_eq!(
+        filter.previous_window(),
+        Some((since - chrono::Duration::hours(3), since))
+    );
+}
+
+/// Test success-rate trend arrows compare against the previous window
+#[test]
+fn test_app_success_trend() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let earlier = |tool: &str, success: bool| {
+        let mut event = make_tool_event(tool, success, 10);
+        event.timestamp = Utc::now() - chrono::Duration::minutes(90);
+        event
+    };
     storage.record_log_events(vec![
-        make_tool_event("Read", true, 50),
-        make_tool_event("Write", true, 100),
-        make_tool_event("context7", true, 150),      // MCP
-        make_tool_event("playwright", true, 300),    // MCP
-        make_tool_event("my_custom_tool", true, 75), // MCP
+        // Previous hour: Edit always succeeded, Bash always failed, Read mostly fine
+        earlier("Edit", true),
+        earlier("Edit", true),
+        earlier("Bash", false),
+        earlier("Read", true),
+        // Current hour
+        make_tool_event("Edit", true, 10),
+        make_tool_event("Edit", false, 10),
+        make_tool_event("Bash", true, 10),
+        make_tool_event("Read", true, 10),
+        make_tool_event("Grep", true, 10),
     ]);
     std::thread::sleep(std::time::Duration::from_millis(100));
 
     let mut app = App::new(storage);
+    app.time_filter = TimeFilter::LastHour;
     app.refresh().unwrap();
 
-    let mcp = app.mcp_tools();
-    assert_eq!(mcp.len(), 3);
+    let trend = |name: &str| {
+        let tool = app
+            .tool_metrics
+            .iter()
+            .find(|t| t.tool_name == name)
+            .unwrap();
+        app.success_trend(tool)
+    };
+    assert_eq!(trend("Edit"), Some(SuccessTrend::Regressed));
+    assert_eq!(trend("Bash"), Some(SuccessTrend::Improved));
+    assert_eq!(trend("Read"), None);
+    assert_eq!(trend("Grep"), None); // no previous data
+
+    // Unbounded windows have nothing to compare against
+    app.time_filter = TimeFilter::AllTime;
+    app.refresh().unwrap();
+    assert!(app.previous_success_rates.is_empty());
+}
 
-    let mcp_names: Vec<&str> = mcp.iter().map(|t| t.tool_name.as_str()).collect();
-    assert!(mcp_names.contains(&"context7"));
-    assert!(mcp_names.contains(&"playwright"));
-    assert!(mcp_names.contains(&"my_custom_tool"));
-    assert!(!mcp_names.contains(&"Read"));
-    assert!(!mcp_names.contains(&"Write"));
+// =============================================================================
+// UI Rendering Tests
+// =============================================================================
+
+/// Test the footer reports storage as down while the actor restarts
+#[test]
+fn test_ui_footer_shows_storage_down() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let mut app = App::new(storage);
+    app.storage_status.error = Some("simulated DuckDB failure".to_string());
+
+    let text = snapshot::render(&app, SnapshotFormat::Text, 160, 24).unwrap();
+    assert!(text.contains("storage down: simulated DuckDB failure"));
 }
 
-/// Test with only built-in tools (no MCP)
+/// Test the dashboard shows a placeholder until the database is open
 #[test]
-fn test_app_only_builtin_tools() {
+fn test_ui_shows_loading_until_ready() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let mut app = App::new(storage);
+    assert!(!app.loading);
+    app.loading = true;
+
+    let text = snapshot::render(&app, SnapshotFormat::Text, 160, 24).unwrap();
+    assert!(text.contains("Opening the database..."));
+}
+
+/// Test the footer reports the receiver as down when it is not listening
+#[test]
+fn test_ui_footer_shows_receiver_status() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let mut app = App::new(storage);
+
+    let text = snapshot::render(&app, SnapshotFormat::Text, 160, 24).unwrap();
+    assert!(text.contains("receiver down"));
+
+    app.update_receiver_status(
+        ReceiverSnapshot {
+            listen_addr: Some("127.0.0.1:4318".to_string()),
+            events_received: 0,
+            payloads_dropped: 2,
+            batches_dropped: 0,
+            values_truncated: 0,
+            hook_runs_dropped: 0,
+            last_event_at: None,
+        },
+        Utc::now(),
+    );
+    let text = snapshot::render(&app, SnapshotFormat::Text, 160, 24).unwrap();
+    assert!(text.contains("127.0.0.1:4318"));
+    assert!(text.contains("no events"));
+    assert!(text.contains("2 dropped"));
+}
+
+/// Test the alert banner is rendered above the header
+#[test]
+fn test_ui_renders_alert_banner() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let mut app = App::new(storage);
+    app.alerts = vec![Alert::ToolErrorRate {
+        tool_name: "Edit".to_string(),
+        errors: 4,
+        calls: 8,
+    }];
+
+    let text = snapshot::render(&app, SnapshotFormat::Text, 100, 24).unwrap();
+    let first_line = text.lines().next().unwrap();
+    assert!(first_line.contains("Edit: 4/8 calls failed (50%)"));
+    assert!(first_line.contains("last 10m"));
+}
+
+/// Test wide terminals place the tool table and API panel side by side
+#[test]
+fn test_ui_wide_layout_side_by_side() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    storage.record_log_events(vec![make_tool_event("Read", true, 10)]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let mut app = App::new(storage);
+    app.refresh().unwrap();
+
+    let wide = snapshot::render(&app, SnapshotFormat::Text, WIDE_LAYOUT_MIN_WIDTH, 30).unwrap();
+    assert!(
+        wide.lines()
+            .any(|line| line.contains(" Tools ") && line.contains("calls · avg"))
+    );
+
+    let narrow =
+        snapshot::render(&app, SnapshotFormat::Text, WIDE_LAYOUT_MIN_WIDTH - 1, 30).unwrap();
+    assert!(narrow.contains(" Tools "));
+    assert!(!narrow.contains("calls · avg"));
+}
+
+/// Test braille sparklines scale to the series maximum
+#[test]
+fn test_braille_sparkline() {
+    assert_eq!(braille_sparkline(&[]), "");
+    assert_eq!(braille_sparkline(&[0, 0, 0, 0]), "\u{2800}\u{2800}");
+    // Full left column, empty right column
+    assert_eq!(braille_sparkline(&[4, 0]), "\u{2847}");
+    // Full-height pair
+    assert_eq!(braille_sparkline(&[4, 4]), "\u{28FF}");
+    // Odd length: last char only uses the left column; small values still show a dot
+    assert_eq!(braille_sparkline(&[8, 1, 1]), "\u{28C7}\u{2840}");
+}
+
+/// Test the app loads per-tool trends for the TREND column
+#[test]
+fn test_app_tool_trends() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    storage.record_log_events(vec![
+        make_tool_event("Read", true, 10),
+        make_tool_event("Read", true, 10),
+    ]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let mut app = App::new(storage);
+    app.refresh().unwrap();
+
+    let trend = app.tool_trend("Read").unwrap();
+    assert_eq!(trend.len(), 20);
+    assert_eq!(*trend.last().unwrap(), 2);
+    assert!(app.tool_trend("Write").is_none());
+}
+
+/// Test executing tools show a spinner with elapsed time
+#[test]
+fn test_ui_shows_in_flight_spinner() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    storage.record_log_events(vec![make_tool_event("Bash", true, 10)]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let mut app = App::new(storage);
+    app.refresh().unwrap();
+    assert!(app.in_flight_since("Bash").is_none());
+
+    app.in_flight = vec![InFlightTool {
+        tool_name: "Bash".to_string(),
+        started_at: Utc::now() - chrono::Duration::seconds(75),
+    }];
+    let text = snapshot::render(&app, SnapshotFormat::Text, 120, 24).unwrap();
+    assert!(text.contains("Bash 1m15s"));
+}
+
+/// Test narrow terminals switch to the compact layout automatically
+#[test]
+fn test_ui_compact_layout_narrow_terminal() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    storage.record_log_events(vec![make_tool_event("Read", true, 10)]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let mut app = App::new(storage);
+    app.refresh().unwrap();
+
+    let compact = snapshot::render(&app, SnapshotFormat::Text, COMPACT_MAX_WIDTH - 1, 24).unwrap();
+    assert!(compact.contains("AP%"));
+    assert!(!compact.contains("RANGE"));
+    assert!(!compact.contains("FREQ"));
+    assert!(compact.contains("│ Tools 1"));
+    assert!(!compact.contains(" API     Calls"));
+
+    let normal = snapshot::render(&app, SnapshotFormat::Text, 120, 24).unwrap();
+    assert!(normal.contains("RANGE"));
+    assert!(normal.contains(" API     Calls"));
+}
+
+/// Test the compact layout can be toggled on for any width
+#[test]
+fn test_app_toggle_compact() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let mut app = App::new(storage);
+
+    assert!(!app.is_compact(120));
+    assert!(app.is_compact(COMPACT_MAX_WIDTH - 1));
+
+    app.toggle_compact();
+    assert!(app.is_compact(200));
+    let text = snapshot::render(&app, SnapshotFormat::Text, 200, 24).unwrap();
+    assert!(!text.contains("RANGE"));
+
+    app.toggle_compact();
+    assert!(!app.is_compact(200));
+}
+
+/// Test that UI can render with empty data without crashing
+#[test]
+fn test_ui_renders_empty_state() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let app = App::new(storage);
+
+    // Create a test terminal
+    let backend = TestBackend::new(80, 24);
+    let mut terminal = Terminal::new(backend).unwrap();
+
+    // Render the UI - this should not panic
+    terminal.draw(|f| agenttop::tui::ui::draw(f, &app)).unwrap();
+
+    // Verify something was rendered
+    let buffer = terminal.backend().buffer();
+    assert!(buffer.area.width > 0);
+    assert!(buffer.area.height > 0);
+}
+
+/// Test that UI can render with real data
+#[test]
+fn test_ui_renders_with_data() {
     let storage = StorageHandle::new_in_memory().unwrap();
 
+    // Insert various test data
     storage.record_log_events(vec![
         make_tool_event("Read", true, 50),
-        make_tool_event("Glob", true, 100),
-        make_tool_event("Grep", true, 150),
+        make_tool_event("Read", true, 75),
+        make_tool_event("Write", true, 100),
+        make_tool_event("Write", false, 200),
+        make_tool_event("Bash", true, 500),
+        make_tool_event("Bash", true, 750),
+        make_tool_event("Bash", false, 1000),
     ]);
+    storage.record_token_usage("input", 5000);
+    storage.record_token_usage("output", 2500);
+    storage.record_token_usage("cacheRead", 10000);
+    storage.record_cost(0.15);
+    storage.record_session_metric("lines_of_code", 250);
+    storage.record_session_metric("commit", 3);
+
     std::thread::sleep(std::time::Duration::from_millis(100));
 
     let mut app = App::new(storage);
     app.refresh().unwrap();
 
-    assert_eq!(app.builtin_tools().len(), 3);
-    assert_eq!(app.mcp_tools().len(), 0);
+    // Create a test terminal
+    let backend = TestBackend::new(100, 30);
+    let mut terminal = Terminal::new(backend).unwrap();
+
+    // Render the UI
+    terminal.draw(|f| agenttop::tui::ui::draw(f, &app)).unwrap();
+
+    // Convert buffer to string for inspection
+    let buffer = terminal.backend().buffer();
+    let content: String = buffer.content.iter().map(|cell| cell.symbol()).collect();
+
+    // Verify key elements are rendered
+    assert!(content.contains("agenttop"), "Should show app name");
+    assert!(content.contains("Tokens"), "Should show token gauge");
+    assert!(content.contains("Tools"), "Should show tools section");
+    assert!(content.contains("Bash"), "Should show Bash tool");
+    assert!(content.contains("Read"), "Should show Read tool");
+    assert!(content.contains("Write"), "Should show Write tool");
 }
 
-/// Test with only MCP tools (no built-in)
+/// Test that UI renders detail popup correctly
 #[test]
-fn test_app_only_mcp_tools() {
+fn test_ui_renders_detail_popup() {
     let storage = StorageHandle::new_in_memory().unwrap();
 
     storage.record_log_events(vec![
-        make_tool_event("context7_search", true, 50),
-        make_tool_event("sqlite_query", true, 100),
+        make_tool_event("Bash", true, 100),
+        make_tool_event("Bash", true, 150),
+        make_tool_event("Bash", false, 200),
     ]);
     std::thread::sleep(std::time::Duration::from_millis(100));
 
     let mut app = App::new(storage);
     app.refresh().unwrap();
+    app.toggle_detail(); // Show detail popup
 
-    assert_eq!(app.builtin_tools().len(), 0);
-    assert_eq!(app.mcp_tools().len(), 2);
+    let backend = TestBackend::new(100, 30);
+    let mut terminal = Terminal::new(backend).unwrap();
+
+    terminal.draw(|f| agenttop::tui::ui::draw(f, &app)).unwrap();
+
+    let buffer = terminal.backend().buffer();
+    let content: String = buffer.content.iter().map(|cell| cell.symbol()).collect();
+
+    // Verify detail popup content
+    assert!(content.contains("Details"), "Should show details title");
+    assert!(content.contains("Total Calls"), "Should show total calls");
+    assert!(
+        content.contains("Successful"),
+        "Should show successful count"
+    );
+    assert!(content.contains("Errors"), "Should show error count");
 }
 
-/// Test session metrics are loaded correctly
+/// Test UI with large terminal size
 #[test]
-fn test_app_session_metrics() {
+fn test_ui_renders_large_terminal() {
     let storage = StorageHandle::new_in_memory().unwrap();
 
-    storage.record_session_metric("lines_of_code", 100);
-    storage.record_session_metric("lines_of_code", 50);
-    storage.record_session_metric("commits", 2);
+    // Add many tools to test scrolling/layout
+    for i in 0..20 {
+        storage.record_log_events(vec![make_tool_event(
+            &format!("Tool{:02}", i),
+            true,
+            50 + i * 10,
+        )]);
+    }
     std::thread::sleep(std::time::Duration::from_millis(100));
 
     let mut app = App::new(storage);
     app.refresh().unwrap();
 
-    assert_eq!(app.session_metrics.lines_of_code, 150);
-    assert_eq!(app.session_metrics.commit_count, 2);
+    // Large terminal
+    let backend = TestBackend::new(200, 50);
+    let mut terminal = Terminal::new(backend).unwrap();
+
+    // Should not panic
+    terminal.draw(|f| agenttop::tui::ui::draw(f, &app)).unwrap();
+}
+
+/// Test UI with small terminal size
+#[test]
+fn test_ui_renders_small_terminal() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+
+    storage.record_log_events(vec![
+        make_tool_event("Read", true, 50),
+        make_tool_event("Write", true, 100),
+    ]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let mut app = App::new(storage);
+    app.refresh().unwrap();
+
+    // Small terminal (minimum usable size)
+    let backend = TestBackend::new(60, 15);
+    let mut terminal = Terminal::new(backend).unwrap();
+
+    // Should not panic even on small terminal
+    terminal.draw(|f| agenttop::tui::ui::draw(f, &app)).unwrap();
+}
+
+// =============================================================================
+// Time Filter Tests
+// =============================================================================
+
+/// Test time filter cycling
+#[test]
+fn test_app_time_filter_cycling() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let mut app = App::new(storage);
+
+    // Default should be AllTime
+    assert_eq!(app.time_filter, TimeFilter::AllTime);
+
+    // Cycle through filters
+    app.toggle_time_filter();
+    assert_eq!(app.time_filter, TimeFilter::LastHour);
+
+    app.toggle_time_filter();
+    assert_eq!(app.time_filter, TimeFilter::Last24Hours);
+
+    app.toggle_time_filter();
+    assert_eq!(app.time_filter, TimeFilter::Last7Days);
+
+    app.toggle_time_filter();
+    assert_eq!(app.time_filter, TimeFilter::AllTime);
+}
+
+/// Test time filter labels
+#[test]
+fn test_time_filter_labels() {
+    assert_eq!(TimeFilter::LastHour.label(), "Last 1h");
+    assert_eq!(TimeFilter::Last24Hours.label(), "Last 24h");
+    assert_eq!(TimeFilter::Last7Days.label(), "Last 7d");
+    assert_eq!(TimeFilter::AllTime.label(), "All-time");
+}
+
+/// Test time filter since values
+#[test]
+fn test_time_filter_since() {
+    // AllTime should return None
+    assert!(TimeFilter::AllTime.since().is_none());
+
+    // Other filters should return Some datetime in the past
+    let now = Utc::now();
+
+    let last_hour = TimeFilter::LastHour.since().unwrap();
+    assert!(last_hour < now);
+    assert!((now - last_hour).num_minutes() >= 59);
+
+    let last_24h = TimeFilter::Last24Hours.since().unwrap();
+    assert!(last_24h < now);
+    assert!((now - last_24h).num_hours() >= 23);
+
+    let last_7d = TimeFilter::Last7Days.since().unwrap();
+    assert!(last_7d < now);
+    assert!((now - last_7d).num_days() >= 6);
+}
+
+/// Test parsing rolling "last N" time ranges
+#[test]
+fn test_time_filter_parse_relative() {
+    let now = chrono::Local::now();
+
+    let filter = TimeFilter::parse("last 3h", now).unwrap();
+    assert_eq!(filter, TimeFilter::Last(chrono::Duration::hours(3)));
+    assert_eq!(filter.label(), "Last 3h");
+    assert!(filter.until().is_none());
+
+    let filter = TimeFilter::parse("90m", now).unwrap();
+    assert_eq!(filter, TimeFilter::Last(chrono::Duration::minutes(90)));
+}
+
+/// Test parsing "since HH:MM" resolves to today in local time
+#[test]
+fn test_time_filter_parse_since_time() {
+    use chrono::{Local, TimeZone};
+
+    let now = Local.with_ymd_and_hms(2025, 1, 15, 14, 30, 0).unwrap();
+    let filter = TimeFilter::parse("since 09:00", now).unwrap();
+
+    let expected = Local.with_ymd_and_hms(2025, 1, 15, 9, 0, 0).unwrap();
+    assert_eq!(filter.since(), Some(expected.with_timezone(&Utc)));
+    assert!(filter.until().is_none());
+}
+
+/// Test parsing an inclusive date range
+#[test]
+fn test_time_filter_parse_date_range() {
+    use chrono::{Local, TimeZone};
+
+    let now = Local::now();
+    let filter = TimeFilter::parse("2025-01-10..2025-01-12", now).unwrap();
+
+    let since = Local.with_ymd_and_hms(2025, 1, 10, 0, 0, 0).unwrap();
+    let until = Local.with_ymd_and_hms(2025, 1, 13, 0, 0, 0).unwrap();
+    assert_eq!(filter.since(), Some(since.with_timezone(&Utc)));
+    assert_eq!(filter.until(), Some(until.with_timezone(&Utc)));
+    assert_eq!(filter.label(), "2025-01-10..2025-01-12");
+}
+
+/// Test invalid time ranges are rejected
+#[test]
+fn test_time_filter_parse_invalid() {
+    let now = chrono::Local::now();
+
+    assert!(TimeFilter::parse("", now).is_err());
+    assert!(TimeFilter::parse("yesterday-ish", now).is_err());
+    assert!(TimeFilter::parse("last 0h", now).is_err());
+    assert!(TimeFilter::parse("2025-01-12..2025-01-10", now).is_err());
+}
+
+/// Test the time range input flow on App
+#[test]
+fn test_app_time_input() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let mut app = App::new(storage);
+
+    app.open_time_input();
+    for c in "bogus".chars() {
+        app.time_input_push(c);
+    }
+    app.submit_time_input();
+    assert!(app.time_input.is_some(), "Input stays open on error");
+    assert!(app.time_input_error.is_some());
+
+    app.cancel_time_input();
+    app.open_time_input();
+    for c in "last 2d".chars() {
+        app.time_input_push(c);
+    }
+    app.submit_time_input();
+    assert!(app.time_input.is_none());
+    assert_eq!(app.time_filter, TimeFilter::Last(chrono::Duration::days(2)));
+
+    // Cycling presets leaves the custom range
+    app.toggle_time_filter();
+    assert_eq!(app.time_filter, TimeFilter::LastHour);
+}
+
+// =============================================================================
+// Tool Classification Filter Tests
+// =============================================================================
+
+/// Test that App correctly filters built-in tools
+#[test]
+fn test_app_builtin_tools() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+
+    // Mix of built-in and MCP tools (any non-builtin is MCP)
+    storage.record_log_events(vec![
+        make_tool_event("Read", true, 50),
+        make_tool_event("Write", true, 100),
+        make_tool_event("Bash", true, 200),
+        make_tool_event("context7", true, 150), // MCP (old style)
+        make_tool_event("playwright", true, 300), // MCP (old style)
+    ]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let mut app = App::new(storage);
+    app.refresh().unwrap();
+
+    let builtin = app.builtin_tools();
+    assert_eq!(builtin.len(), 3);
+
+    let builtin_names: Vec<&str> = builtin.iter().map(|t| t.tool_name.as_str()).collect();
+    assert!(builtin_names.contains(&"Read"));
+    assert!(builtin_names.contains(&"Write"));
+    assert!(builtin_names.contains(&"Bash"));
+    assert!(!builtin_names.contains(&"context7"));
+    assert!(!builtin_names.contains(&"playwright"));
+}
+
+/// Test that App correctly filters MCP tools
+/// Any tool not in the built-in list is considered MCP
+#[test]
+fn test_app_mcp_tools() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+
+    // Mix of built-in and MCP tools
+    storage.record_log_events(vec![
+        make_tool_event("Read", true, 50),
+        make_tool_event("Write", true, 100),
+        make_tool_event("context7", true, 150),      // MCP
+        make_tool_event("playwright", true, 300),    // MCP
+        make_tool_event("my_custom_tool", true, 75), // MCP
+    ]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let mut app = App::new(storage);
+    app.refresh().unwrap();
+
+    let mcp = app.mcp_tools();
+    assert_eq!(mcp.len(), 3);
+
+    let mcp_names: Vec<&str> = mcp.iter().map(|t| t.tool_name.as_str()).collect();
+    assert!(mcp_names.contains(&"context7"));
+    assert!(mcp_names.contains(&"playwright"));
+    assert!(mcp_names.contains(&"my_custom_tool"));
+    assert!(!mcp_names.contains(&"Read"));
+    assert!(!mcp_names.contains(&"Write"));
+}
+
+/// Test with only built-in tools (no MCP)
+#[test]
+fn test_app_only_builtin_tools() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+
+    storage.record_log_events(vec![
+        make_tool_event("Read", true, 50),
+        make_tool_event("Glob", true, 100),
+        make_tool_event("Grep", true, 150),
+    ]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let mut app = App::new(storage);
+    app.refresh().unwrap();
+
+    assert_eq!(app.builtin_tools().len(), 3);
+    assert_eq!(app.mcp_tools().len(), 0);
+}
+
+/// Test with only MCP tools (no built-in)
+#[test]
+fn test_app_only_mcp_tools() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+
+    storage.record_log_events(vec![
+        make_tool_event("context7_search", true, 50),
+        make_tool_event("sqlite_query", true, 100),
+    ]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let mut app = App::new(storage);
+    app.refresh().unwrap();
+
+    assert_eq!(app.builtin_tools().len(), 0);
+    assert_eq!(app.mcp_tools().len(), 2);
+}
+
+/// Test session metrics are loaded correctly
+#[test]
+fn test_app_session_metrics() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+
+    storage.record_session_metric("lines_of_code", 100);
+    storage.record_session_metric("lines_of_code", 50);
+    storage.record_session_metric("commits", 2);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let mut app = App::new(storage);
+    app.refresh().unwrap();
+
+    assert_eq!(app.session_metrics.lines_of_code, 150);
+    assert_eq!(app.session_metrics.commit_count, 2);
+}
+
+/// Test the timeline view loads the session and lists the slowest calls
+#[test]
+fn test_ui_timeline_view() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    storage.record_log_events(vec![
+        make_tool_event("Read", true, 40),
+        make_tool_event("Bash", true, 2500),
+    ]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let mut app = App::new(storage);
+    app.refresh().unwrap();
+    assert!(app.session_timeline.events.is_empty());
+
+    app.cycle_view();
+    assert_eq!(app.view, View::Timeline);
+    app.refresh().unwrap();
+    assert_eq!(app.session_timeline.events.len(), 2);
+
+    let text = snapshot::render(&app, SnapshotFormat::Text, 120, 30).unwrap();
+    assert!(text.contains("Timeline (last hour)"));
+    assert!(text.contains("Slowest calls"));
+    assert!(text.contains("━"));
+    let bash = text.find("Bash").unwrap();
+    let read = text.find("Read").unwrap();
+    assert!(bash < read, "slowest call should be listed first");
+
+    app.cycle_view();
+    assert_eq!(app.view, View::Sessions);
+    app.cycle_view();
+    assert_eq!(app.view, View::Prompts);
+    app.cycle_view();
+    assert_eq!(app.view, View::Turns);
+    app.cycle_view();
+    assert_eq!(app.view, View::Workflows);
+    app.cycle_view();
+    assert_eq!(app.view, View::Files);
+    app.cycle_view();
+    assert_eq!(app.view, View::Cache);
+    app.cycle_view();
+    assert_eq!(app.view, View::Productivity);
+    app.cycle_view();
+    assert_eq!(app.view, View::Providers);
+    app.cycle_view();
+    assert_eq!(app.view, View::Errors);
+    app.cycle_view();
+    assert_eq!(app.view, View::Heatmap);
+    app.cycle_view();
+    assert_eq!(app.view, View::Events);
+    app.cycle_view();
+    assert_eq!(app.view, View::Sql);
+    app.cycle_view();
+    assert_eq!(app.view, View::Dashboard);
+}
+
+/// Test the prompt history view lists prompts and notes missing text
+#[test]
+fn test_ui_prompt_history_view() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let prompt = |text: Option<&str>, length: &str| {
+        let mut attrs = HashMap::new();
+        attrs.insert("prompt_length".to_string(), length.to_string());
+        if let Some(text) = text {
+            attrs.insert("prompt".to_string(), text.to_string());
+        }
+        LogEvent {
+            timestamp: Utc::now(),
+            event_name: Some("user_prompt".to_string()),
+            body: None,
+            attributes: attrs,
+        }
+    };
+    storage.record_log_events(vec![
+        prompt(None, "42"),
+        prompt(
+            Some("refactor\nthe storage actor ".repeat(20).as_str()),
+            "600",
+        ),
+    ]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let mut app = App::new(storage);
+    app.view = View::Prompts;
+    app.refresh().unwrap();
+    assert_eq!(app.prompts.len(), 2);
+
+    let text = snapshot::render(&app, SnapshotFormat::Text, 100, 24).unwrap();
+    assert!(text.contains("Prompts (2)"));
+    assert!(text.contains("refactor the storage actor"));
+    assert!(text.contains("…"));
+    assert!(text.contains("(prompt text not logged)"));
+    assert!(text.contains("600"));
+}
+
+/// Test the session list shows each session's efficiency score once it is scored
+#[test]
+fn test_ui_sessions_view() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let start = Utc::now() - chrono::Duration::minutes(5);
+    let event = |name: &str, secs: i64, attrs: &[(&str, &str)]| LogEvent {
+        timestamp: start + chrono::Duration::seconds(secs),
+        event_name: Some(format!("claude_code.{name}")),
+        body: None,
+        attributes: attrs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .chain([("session.id".to_string(), "session-1".to_string())])
+            .collect(),
+    };
+    storage.record_log_events(vec![
+        event(
+            "tool_result",
+            0,
+            &[("tool_name", "Read"), ("success", "true")],
+        ),
+        event(
+            "tool_result",
+            90,
+            &[("tool_name", "Bash"), ("success", "false")],
+        ),
+    ]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let mut app = App::new(storage.clone());
+    app.view = View::Sessions;
+    app.refresh().unwrap();
+    assert_eq!(app.sessions.len(), 1);
+    let text = snapshot::render(&app, SnapshotFormat::Text, 100, 24).unwrap();
+    assert!(text.contains("Sessions (1) "), "{}", text);
+    assert!(text.contains("Claude Code"));
+    assert!(text.contains("1.5m"));
+
+    assert_eq!(storage.score_sessions(10).unwrap(), 1);
+    app.refresh().unwrap();
+    let text = snapshot::render(&app, SnapshotFormat::Text, 100, 24).unwrap();
+    assert!(text.contains("Sessions (1) · avg score 50"), "{}", text);
+}
+
+/// Test idle gaps between events count against a session's utilization, in the sessions
+/// list and the dashboard header
+#[test]
+fn test_ui_session_utilization() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let start = Utc::now() - chrono::Duration::minutes(45);
+    let event = |minute: i64| LogEvent {
+        timestamp: start + chrono::Duration::minutes(minute),
+        event_name: Some("claude_code.tool_result".to_string()),
+        body: None,
+        attributes: [("session.id".to_string(), "session-1".to_string())].into(),
+    };
+    // 10 minutes active, 30 idle
+    storage.record_log_events(vec![event(0), event(4), event(8), event(40)]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let mut app = App::new(storage.clone());
+    app.view = View::Sessions;
+    app.refresh().unwrap();
+    assert_eq!(app.sessions[0].active_secs, 8 * 60);
+    let text = snapshot::render(&app, SnapshotFormat::Text, 100, 24).unwrap();
+    assert!(text.contains("ACTIVE"), "{}", text);
+    assert!(text.contains("20%"), "{}", text);
+
+    app.view = View::Dashboard;
+    app.refresh().unwrap();
+    assert_eq!(app.format_active_time(), "8m (20%)");
+}
+
+/// Test a session whose prompts keep filling the context window shows its utilization
+/// in red in the sessions list and raises an alert
+#[test]
+fn test_ui_context_ceiling() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let request = |session: &str, tokens: u64| LogEvent {
+        timestamp: Utc::now() - chrono::Duration::minutes(1),
+        event_name: Some("claude_code.api_request".to_string()),
+        body: None,
+        attributes: [
+            ("session.id", session.to_string()),
+            ("model", "claude-sonnet-4-5".to_string()),
+            ("input_tokens", "500".to_string()),
+            ("cache_read_tokens", tokens.to_string()),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect(),
+    };
+    let mut events: Vec<LogEvent> = [150_000, 170_000, 180_000, 190_000, 120_000]
+        .into_iter()
+        .map(|tokens| request("full-session", tokens))
+        .collect();
+    events.extend((0..5).map(|_| request("light-session", 20_000)));
+    storage.record_log_events(events);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let mut app = App::new(storage.clone());
+    app.view = View::Sessions;
+    app.refresh().unwrap();
+    let full = app.context_usage.session("full-session").unwrap();
+    assert_eq!(full.utilization.near_ceiling, 3);
+    assert!(full.utilization.is_consistently_near());
+    let text = snapshot::render(&app, SnapshotFormat::Text, 100, 24).unwrap();
+    assert!(text.contains("CTX"), "{}", text);
+    assert!(text.contains("81%"), "{}", text);
+    assert!(text.contains("· 1 near context limit"), "{}", text);
+
+    assert_eq!(app.alerts.len(), 1);
+    assert_eq!(
+        app.alerts[0].message(),
+        "Session full-ses: 3/5 requests near the context limit (avg 81%)"
+    );
+}
+
+/// Test the turns view lists turns with their per-turn averages
+#[test]
+fn test_ui_turns_view() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let start = Utc::now() - chrono::Duration::minutes(5);
+    let event = |name: &str, secs: i64, attrs: &[(&str, &str)]| LogEvent {
+        timestamp: start + chrono::Duration::seconds(secs),
+        event_name: Some(name.to_string()),
+        body: None,
+        attributes: attrs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .chain([("session.id".to_string(), "session-1".to_string())])
+            .collect(),
+    };
+    storage.record_log_events(vec![
+        event("user_prompt", 0, &[("prompt_length", "12")]),
+        event("tool_result", 2, &[("tool_name", "Read")]),
+        event("tool_result", 3, &[("tool_name", "Edit")]),
+        event(
+            "api_request",
+            4,
+            &[("input_tokens", "1500"), ("output_tokens", "500")],
+        ),
+        event("user_prompt", 10, &[]),
+        event("api_request", 12, &[("input_tokens", "100")]),
+    ]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let mut app = App::new(storage);
+    app.view = View::Turns;
+    app.refresh().unwrap();
+    assert_eq!(app.turns.len(), 2);
+    assert_eq!(app.turns[1].tool_calls, 2);
+
+    let text = snapshot::render(&app, SnapshotFormat::Text, 100, 24).unwrap();
+    assert!(
+        text.contains("Turns (2) · avg 1.0 tools, 1.1K tokens, 3.0s per turn"),
+        "{}",
+        text
+    );
+    assert!(text.contains("session-"));
+    assert!(text.contains("2.0K"));
+}
+
+/// Test the turns view shows each turn's prompt size and the cost per prompt size below
+#[test]
+fn test_ui_turns_prompt_cost() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let start = Utc::now() - chrono::Duration::minutes(5);
+    let event = |name: &str, secs: i64, attrs: &[(&str, &str)]| LogEvent {
+        timestamp: start + chrono::Duration::seconds(secs),
+        event_name: Some(format!("claude_code.{name}")),
+        body: None,
+        attributes: attrs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .chain([("session.id".to_string(), "session-1".to_string())])
+            .collect(),
+    };
+    let mut events = Vec::new();
+    for (i, (chars, cost)) in [("300", "0.10"), ("1500", "0.20"), ("12000", "0.60")]
+        .into_iter()
+        .enumerate()
+    {
+        let secs = i as i64 * 10;
+        events.push(event("user_prompt", secs, &[("prompt_length", chars)]));
+        events.push(event("api_request", secs + 2, &[("cost_usd", cost)]));
+    }
+    storage.record_log_events(events);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let mut app = App::new(storage);
+    app.view = View::Turns;
+    app.refresh().unwrap();
+    assert_eq!(app.prompt_cost.turns, 3);
+    let text = snapshot::render(&app, SnapshotFormat::Text, 100, 30).unwrap();
+    assert!(text.contains("PROMPT"), "{}", text);
+    assert!(text.contains("12.0K"), "{}", text);
+    assert!(
+        text.contains("Prompt size vs cost (3 turns) · r = 1.00"),
+        "{}",
+        text
+    );
+    let bucket = text.lines().find(|l| l.contains("10K-50K")).unwrap();
+    assert!(
+        bucket.contains("$0.60") && bucket.contains("$0.05"),
+        "{}",
+        text
+    );
+}
+
+/// Test the workflows view lists repeated tool sequences and flags loops
+#[test]
+fn test_ui_workflows_view() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let start = Utc::now() - chrono::Duration::minutes(5);
+    let tools = ["Read", "Edit", "Bash", "Read", "Edit", "Bash", "Read"];
+    storage.record_log_events(
+        tools
+            .iter()
+            .enumerate()
+            .map(|(i, tool)| LogEvent {
+                timestamp: start + chrono::Duration::seconds(i as i64),
+                event_name: Some("tool_result".to_string()),
+                body: None,
+                attributes: [
+                    ("tool_name".to_string(), tool.to_string()),
+                    ("session.id".to_string(), "session-1".to_string()),
+                ]
+                .into_iter()
+                .collect(),
+            })
+            .collect(),
+    );
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let mut app = App::new(storage);
+    app.view = View::Workflows;
+    app.refresh().unwrap();
+    // Read → Edit → Bash and Edit → Bash → Read twice each; Bash → Read → Edit once
+    assert_eq!(app.workflows.len(), 2);
+    assert_eq!(app.workflows[0].tools, ["Edit", "Bash", "Read"]);
+    assert!(app.workflows.iter().all(|w| w.count == 2 && w.repeats == 1));
+
+    let text = snapshot::render(&app, SnapshotFormat::Text, 100, 24).unwrap();
+    assert!(
+        text.contains("Workflows (2) · 3-call sequences"),
+        "{}",
+        text
+    );
+    assert!(text.contains("Read → Edit → Bash"));
+    assert!(text.contains("2.0s"));
+}
+
+/// Test the files view lists the most edited files and directories side by side
+#[test]
+fn test_ui_files_view() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let now = Utc::now();
+    let call = |tool: &str, path: &str, secs: i64| LogEvent {
+        timestamp: now - chrono::Duration::seconds(secs),
+        event_name: Some("claude_code.tool_result".to_string()),
+        body: None,
+        attributes: [
+            ("tool_name".to_string(), tool.to_string()),
+            ("file_path".to_string(), path.to_string()),
+            ("session.id".to_string(), "session-1".to_string()),
+        ]
+        .into_iter()
+        .collect(),
+    };
+    storage.record_log_events(vec![
+        call("Read", "/repo/src/lib.rs", 30),
+        call("Edit", "/repo/src/lib.rs", 20),
+        call("Edit", "/repo/src/lib.rs", 10),
+        call("Read", "/repo/README.md", 5),
+    ]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let mut app = App::new(storage);
+    app.view = View::Files;
+    app.refresh().unwrap();
+    assert_eq!(app.files.len(), 2);
+    assert_eq!(app.files[0].path, "/repo/src/lib.rs");
+    assert_eq!(app.directories.len(), 2);
+
+    let text = snapshot::render(&app, SnapshotFormat::Text, 120, 24).unwrap();
+    assert!(text.contains("Files (2)"), "{}", text);
+    assert!(text.contains("Directories (2)"));
+    assert!(text.contains("/repo/src/lib.rs"));
+    let lib = text.find("/repo/src/lib.rs").unwrap();
+    let readme = text.find("/repo/README.md").unwrap();
+    assert!(lib < readme, "most edited file should be listed first");
+}
+
+/// Test the productivity view totals the sessions' code output per week and lists each
+/// session with its ratios
+#[test]
+fn test_ui_productivity_view() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let mut app = App::new(storage.clone());
+    app.view = View::Productivity;
+
+    let request = |session: &str, cost: &str| LogEvent {
+        timestamp: Utc::now(),
+        event_name: Some("claude_code.api_request".to_string()),
+        body: None,
+        attributes: [("session.id", session), ("cost_usd", cost)]
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+    };
+    storage.record_log_events(vec![
+        request("session-a", "1.0"),
+        request("session-b", "1.0"),
+    ]);
+    storage.record_session_metric_for(Some("session-a"), "lines_of_code", 250);
+    storage.record_session_metric_for(Some("session-a"), "commit", 1);
+    storage.record_session_metric_for(Some("session-a"), "active_time", 3600);
+    storage.record_session_metric_for(Some("session-b"), "lines_of_code", 50);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    app.invalidate();
+    app.refresh().unwrap();
+    assert_eq!(app.productivity.len(), 2);
+    let text = snapshot::render(&app, SnapshotFormat::Text, 120, 24).unwrap();
+    assert!(
+        text.contains("Productivity · 150 lines/$ · 1.0 commits/h · $2.00/commit"),
+        "{}",
+        text
+    );
+    assert!(text.contains("WEEK OF"));
+    assert!(text.contains("TOTAL"));
+    assert!(text.contains("Sessions (2)"));
+    assert!(text.contains("session-a"));
+    assert!(text.contains("+250"));
+}
+
+/// Test the providers view puts the providers side by side with the best value marked
+#[test]
+fn test_ui_providers_view() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let mut app = App::new(storage.clone());
+    app.view = View::Providers;
+    app.refresh().unwrap();
+    let text = snapshot::render(&app, SnapshotFormat::Text, 100, 24).unwrap();
+    assert!(text.contains("No provider events"), "{}", text);
+
+    let request = |name: &str, session: &str, cost: &str| LogEvent {
+        timestamp: Utc::now(),
+        event_name: Some(name.to_string()),
+        body: None,
+        attributes: [
+            ("session.id", session),
+            ("cost_usd", cost),
+            ("duration_ms", "1200"),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect(),
+    };
+    storage.record_log_events(vec![
+        request("claude_code.api_request", "a", "3.0"),
+        request("gemini_cli.api_request", "b", "1.0"),
+    ]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    app.invalidate();
+    app.refresh().unwrap();
+    assert_eq!(app.providers.len(), 2);
+    let text = snapshot::render(&app, SnapshotFormat::Text, 100, 24).unwrap();
+    assert!(text.contains("Providers (2)"), "{}", text);
+    let header = text.lines().find(|l| l.contains("Claude Code")).unwrap();
+    assert!(
+        header.find("Claude Code") < header.find("Gemini CLI"),
+        "{}",
+        text
+    );
+    assert!(text.contains("Cost/session"));
+    assert!(text.contains("$3.00"));
+    assert!(text.contains("1.2s"));
+}
+
+/// Test the errors view groups errors by category with their share, then by tool or model
+#[test]
+fn test_ui_errors_view() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let mut app = App::new(storage.clone());
+    app.view = View::Errors;
+    app.refresh().unwrap();
+    let text = snapshot::render(&app, SnapshotFormat::Text, 120, 30).unwrap();
+    assert!(text.contains("No tool or API errors"), "{}", text);
+
+    let failed = |tool: &str, error: &str| LogEvent {
+        timestamp: Utc::now(),
+        event_name: Some("claude_code.tool_result".to_string()),
+        body: None,
+        attributes: [
+            ("session.id", "a"),
+            ("tool_name", tool),
+            ("success", "false"),
+            ("error", error),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect(),
+    };
+    storage.record_log_events(vec![
+        failed("Bash", "Permission denied"),
+        failed("Bash", "Permission denied"),
+        failed("Edit", "File does not exist"),
+        failed("Bash", "exit code 2"),
+    ]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    app.invalidate();
+    app.refresh().unwrap();
+    assert_eq!(app.error_categories.len(), 3);
+    assert_eq!(app.error_breakdown.len(), 3);
+    let text = snapshot::render(&app, SnapshotFormat::Text, 120, 30).unwrap();
+    assert!(text.contains("Errors by category (4)"), "{}", text);
+    let denied = text
+        .lines()
+        .find(|l| l.contains("permission_denied"))
+        .unwrap();
+    assert!(denied.contains(" 50%"), "{}", text);
+    assert!(text.contains("By tool and model (3)"), "{}", text);
+    assert!(text.contains("File does not exist"), "{}", text);
+}
+
+/// Test hourly rollups are bucketed by local weekday and hour, with the previous week totalled
+#[test]
+fn test_activity_heatmap_build() {
+    use agenttop::storage::HourlyActivity;
+    use chrono::{Local, NaiveDate, TimeZone};
+
+    let week_start = NaiveDate::from_ymd_opt(2025, 1, 6).unwrap(); // Monday
+    let at = |day: u32, hour: u32, tool_calls: u64, cost_usd: f64| HourlyActivity {
+        hour: Local
+            .with_ymd_and_hms(2025, 1, day, hour, 0, 0)
+            .unwrap()
+            .with_timezone(&Utc),
+        tool_calls,
+        cost_usd,
+    };
+    let hours = vec![
+        at(1, 9, 4, 0.5),   // Previous week (Wednesday)
+        at(6, 9, 10, 1.0),  // Monday 09:00
+        at(8, 14, 5, 2.0),  // Wednesday 14:00
+        at(12, 23, 1, 0.0), // Sunday 23:00
+        at(13, 0, 7, 0.0),  // Following Monday, ignored
+    ];
+
+    let calls = ActivityHeatmap::build(&hours, week_start, HeatmapMetric::Calls);
+    assert_eq!(calls.cells[0][9], 10.0);
+    assert_eq!(calls.cells[2][14], 5.0);
+    assert_eq!(calls.cells[6][23], 1.0);
+    assert_eq!(calls.total, 16.0);
+    assert_eq!(calls.previous_total, 4.0);
+    assert_eq!(calls.max(), 10.0);
+
+    let cost = ActivityHeatmap::build(&hours, week_start, HeatmapMetric::Cost);
+    assert_eq!(cost.cells[2][14], 2.0);
+    assert_eq!(cost.total, 3.0);
+    assert_eq!(cost.previous_total, 0.5);
+}
+
+/// Test the heatmap view renders the current week and its controls
+#[test]
+fn test_ui_heatmap_view() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    storage.record_log_events(vec![make_tool_event("Read", true, 10)]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let mut app = App::new(storage);
+    app.view = View::Heatmap;
+    app.refresh().unwrap();
+    let heatmap = app.heatmap.clone().unwrap();
+    assert_eq!(heatmap.total, 1.0);
+
+    let text = snapshot::render(&app, SnapshotFormat::Text, 100, 24).unwrap();
+    assert!(text.contains("Activity · Tool calls"));
+    assert!(text.contains("Mon"));
+    assert!(text.contains("Sun"));
+    assert!(text.contains("█"));
+    assert!(text.contains("Total 1"));
+
+    app.toggle_heatmap_metric();
+    app.heatmap_previous_week();
+    app.refresh().unwrap();
+    let previous = app.heatmap.clone().unwrap();
+    assert_eq!(previous.metric, HeatmapMetric::Cost);
+    assert_eq!(
+        previous.week_start,
+        heatmap.week_start - chrono::Duration::days(7)
+    );
+    assert_eq!(previous.total, 0.0);
+
+    app.heatmap_next_week();
+    app.heatmap_next_week();
+    assert_eq!(app.heatmap_weeks_back, 0);
+}
+
+/// Test a tool's row flashes when new calls arrive between refreshes
+#[test]
+fn test_app_row_flash_on_new_calls() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let mut old_bash = make_tool_event("Bash", true, 10);
+    old_bash.timestamp = Utc::now() - chrono::Duration::hours(2);
+    storage.record_log_events(vec![
+        make_tool_event("Read", true, 10),
+        make_tool_event("Bash", true, 10),
+        old_bash,
+    ]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let mut app = App::new(storage.clone());
+    app.time_filter = TimeFilter::LastHour;
+    app.refresh().unwrap();
+    // Nothing flashes on the first load
+    assert!(!app.is_flashing("Read", Utc::now()));
+    assert!(!app.is_flashing("Bash", Utc::now()));
+
+    storage.record_log_events(vec![make_tool_event("Read", true, 10)]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    app.refresh().unwrap();
+    let now = Utc::now();
+    assert!(app.is_flashing("Read", now));
+    assert!(!app.is_flashing("Bash", now));
+    // The flash fades after a moment
+    assert!(!app.is_flashing("Read", now + chrono::Duration::seconds(5)));
+
+    // Widening the time filter raises Bash's count without flashing it
+    app.toggle_time_filter();
+    app.refresh().unwrap();
+    assert_eq!(
+        app.tool_metrics
+            .iter()
+            .find(|t| t.tool_name == "Bash")
+            .unwrap()
+            .call_count,
+        2
+    );
+    assert!(!app.is_flashing("Bash", Utc::now()));
+}
+
+/// Test comparison mode loads the previous window and shows deltas
+#[test]
+fn test_app_compare_mode() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let earlier = |tool: &str, success: bool| {
+        let mut event = make_tool_event(tool, success, 10);
+        event.timestamp = Utc::now() - chrono::Duration::minutes(90);
+        event
+    };
+    storage.record_log_events(vec![
+        earlier("Read", true),
+        earlier("Read", false),
+        make_tool_event("Read", true, 10),
+        make_tool_event("Read", true, 10),
+        make_tool_event("Read", true, 10),
+    ]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let mut app = App::new(storage);
+    app.time_filter = TimeFilter::LastHour;
+    app.refresh().unwrap();
+    assert!(app.comparison.is_none());
+
+    app.toggle_compare_mode();
+    app.refresh().unwrap();
+    let comparison = app.comparison.clone().unwrap();
+    assert_eq!(comparison.tool_calls, 2);
+    assert_eq!(comparison.tool_errors, 1);
+    assert!((comparison.error_rate() - 50.0).abs() < 1e-9);
+    assert_eq!(comparison.tools["Read"].call_count, 2);
+
+    let text = snapshot::render(&app, SnapshotFormat::Text, 140, 24).unwrap();
+    assert!(text.contains("vs prev"));
+    assert!(text.contains("Calls: 2 → 3 (+50%)"));
+    assert!(text.contains("Err rate: 50.0% → 0.0% (-50.0pp)"));
+    assert!(text.contains("3 +1"));
+    assert!(text.contains("-50pp"));
+
+    // Unbounded filters have no previous window
+    app.time_filter = TimeFilter::AllTime;
+    app.refresh().unwrap();
+    assert!(app.comparison.is_none());
+    let text = snapshot::render(&app, SnapshotFormat::Text, 140, 24).unwrap();
+    assert!(text.contains("no previous window"));
+
+    app.toggle_compare_mode();
+    assert!(!app.compare_mode);
+}
+
+/// Test the event stream follows new events, and freezes while scrolled back
+#[test]
+fn test_app_event_stream_follow_and_freeze() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    storage.record_log_events(vec![
+        make_tool_event("Read", true, 10),
+        make_tool_event("Edit", true, 10),
+        make_tool_event("Bash", false, 10),
+    ]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let mut app = App::new(storage.clone());
+    app.view = View::Events;
+    app.refresh().unwrap();
+    assert_eq!(app.events.len(), 3);
+    assert!(app.is_following_events());
+    assert_eq!(app.event_anchor_index(), Some(2));
+
+    // Scrolling up freezes the stream
+    app.scroll_events(-1);
+    assert!(!app.is_following_events());
+    assert_eq!(app.event_anchor_index(), Some(1));
+
+    // New events buffer below the frozen position
+    storage.record_log_events(vec![
+        make_tool_event("Grep", true, 10),
+        make_tool_event("Glob", true, 10),
+    ]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    app.refresh().unwrap();
+    assert_eq!(app.events.len(), 5);
+    assert_eq!(app.event_anchor_index(), Some(1));
+    assert_eq!(app.unseen_events(), 3);
+
+    let text = snapshot::render(&app, SnapshotFormat::Text, 120, 24).unwrap();
+    assert!(text.contains("frozen · 3 new"));
+    assert!(text.contains("tool_name=Edit"));
+    assert!(!text.contains("tool_name=Glob"));
+
+    // Scrolling to the newest event follows again
+    app.scroll_events(isize::MAX);
+    assert!(app.is_following_events());
+    let text = snapshot::render(&app, SnapshotFormat::Text, 120, 24).unwrap();
+    assert!(text.contains("following"));
+    assert!(text.contains("tool_name=Glob"));
+
+    app.toggle_event_follow();
+    assert!(!app.is_following_events());
+    assert_eq!(app.event_anchor_index(), Some(4));
+    app.toggle_event_follow();
+    assert!(app.is_following_events());
+}
+
+/// Test the MCP server health strip above the MCP table
+#[test]
+fn test_ui_mcp_server_strip() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    storage.record_log_events(vec![
+        make_tool_event("mcp__github__search", true, 10),
+        make_tool_event("mcp__github__search", true, 10),
+        make_tool_event("mcp__slack__post", false, 2500),
+        make_tool_event("Read", true, 10),
+    ]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let mut app = App::new(storage);
+    app.refresh().unwrap();
+    assert_eq!(app.mcp_servers.len(), 2);
+    assert_eq!(app.mcp_servers[0].server_name, "github");
+
+    let text = snapshot::render(&app, SnapshotFormat::Text, 140, 50).unwrap();
+    assert!(text.contains("MCP Servers"));
+    assert!(text.contains("github 2 calls 0% err p95 10ms"));
+    assert!(text.contains("slack 1 calls 100% err p95 2.5s"));
+
+    // Compact mode keeps the rows for the table
+    app.force_compact = true;
+    let text = snapshot::render(&app, SnapshotFormat::Text, 140, 50).unwrap();
+    assert!(!text.contains("MCP Servers"));
+}
+
+/// Test the onboarding screen shown until the first event arrives
+#[test]
+fn test_ui_onboarding_until_first_event() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    let mut app = App::new(storage.clone());
+    app.refresh().unwrap();
+    assert!(app.onboarding);
+    assert_eq!(app.provider_setup.len(), 4);
+
+    let text = snapshot::render(&app, SnapshotFormat::Text, 120, 30).unwrap();
+    assert!(text.contains("Getting Started"));
+    assert!(text.contains("Claude Code"));
+    assert!(text.contains("Waiting for first event"));
+
+    app.move_onboarding_selection(-1);
+    assert_eq!(app.onboarding_index, 3);
+    app.move_onboarding_selection(1);
+    assert_eq!(app.onboarding_index, 0);
+
+    storage.record_log_events(vec![make_tool_event("Read", true, 10)]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    app.refresh().unwrap();
+    assert!(!app.onboarding);
+    let text = snapshot::render(&app, SnapshotFormat::Text, 120, 30).unwrap();
+    assert!(!text.contains("Getting Started"));
+}
+
+/// Test that pinned tools stay at the top of their table regardless of sort
+#[test]
+fn test_app_pinned_tools() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    storage.record_log_events(vec![
+        make_tool_event("Read", true, 10),
+        make_tool_event("Read", true, 10),
+        make_tool_event("Edit", true, 10),
+        make_tool_event("Bash", true, 10),
+        make_tool_event("mcp__github__search", true, 10),
+        make_tool_event("mcp__github__search", true, 10),
+        make_tool_event("mcp__slack__post", true, 10),
+    ]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let mut app = App::new(storage);
+    app.pinned_tools = vec!["mcp__slack__post".to_string()];
+    app.refresh().unwrap();
+    let names = |app: &App| -> Vec<String> {
+        app.tool_metrics
+            .iter()
+            .map(|t| t.tool_name.clone())
+            .collect()
+    };
+    assert_eq!(
+        names(&app),
+        vec![
+            "Read",
+            "Bash",
+            "Edit",
+            "mcp__slack__post",
+            "mcp__github__search"
+        ]
+    );
+
+    // Pinning keeps the pinned tool selected at its new position
+    app.select_row(1);
+    assert!(app.toggle_pin_selected());
+    assert_eq!(app.selected_tool().unwrap().tool_name, "Bash");
+    assert_eq!(app.selected_index, 0);
+    assert_eq!(names(&app)[..3], ["Bash", "Read", "Edit"]);
+
+    app.toggle_sort();
+    assert_eq!(app.tool_metrics[0].tool_name, "Bash");
+
+    let text = snapshot::render(&app, SnapshotFormat::Text, 120, 40).unwrap();
+    assert!(text.contains("⚑ Bash"));
+
+    assert!(app.toggle_pin_selected());
+    assert!(!app.is_pinned("Bash"));
+}
+
+/// Test the totals row at the bottom of each tool table
+#[test]
+fn test_ui_tool_table_totals() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    storage.record_log_events(vec![
+        make_tool_event("Read", true, 10),
+        make_tool_event("Read", true, 20),
+        make_tool_event("Bash", false, 90),
+        make_tool_event("mcp__github__search", true, 1500),
+    ]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let mut app = App::new(storage);
+    app.refresh().unwrap();
+
+    let text = snapshot::render(&app, SnapshotFormat::Text, 120, 40).unwrap();
+    let totals: Vec<&str> = text.lines().filter(|l| l.contains("TOTAL")).collect();
+    assert_eq!(totals.len(), 2);
+    // Built-in: 3 calls, 1 error, weighted average (10 + 20 + 90) / 3 = 40ms
+    let builtin: Vec<&str> = totals[0].split_whitespace().collect();
+    assert_eq!(builtin[1..6], ["TOTAL", "3", "1", "100%", "40ms"]);
+    assert!(totals[0].contains("10ms-90ms"));
+    // MCP: a single 1.5s call
+    let mcp: Vec<&str> = totals[1].split_whitespace().collect();
+    assert_eq!(mcp[1..6], ["TOTAL", "1", "0", "100%", "1.5s"]);
+}
+
+/// Test running a query from the SQL console and showing errors
+#[test]
+fn test_ui_sql_console() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    storage.record_log_events(vec![
+        make_tool_event("Read", true, 10),
+        make_tool_event("Read", true, 10),
+        make_tool_event("Bash", true, 10),
+    ]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let mut app = App::new(storage);
+    app.view = View::Sql;
+    let text = snapshot::render(&app, SnapshotFormat::Text, 120, 30).unwrap();
+    assert!(text.contains("Press [Enter] to type a read-only query"));
+
+    app.open_sql_input();
+    for c in "SELECT attributes->>'tool_name' AS tool, count(*) AS calls FROM log_events GROUP BY tool ORDER BY calls DESC".chars() {
+        app.sql_input_push(c);
+    }
+    app.submit_sql_input();
+    assert!(app.sql_input.is_none());
+    let text = snapshot::render(&app, SnapshotFormat::Text, 120, 30).unwrap();
+    assert!(text.contains("Results (2 rows)"));
+    assert!(text.contains("tool"));
+    assert!(text.lines().any(|l| l.contains("Read") && l.contains('2')));
+
+    app.scroll_sql(5);
+    assert_eq!(app.sql_scroll, 1);
+
+    // Errors keep the editor open with the query for fixing
+    app.open_sql_input();
+    assert_eq!(app.sql_input.as_deref(), Some(app.sql_query.as_str()));
+    app.sql_input = Some("DROP TABLE log_events".to_string());
+    app.submit_sql_input();
+    assert!(app.sql_input.is_some());
+    assert!(matches!(app.sql_result, Some(Err(_))));
+    let text = snapshot::render(&app, SnapshotFormat::Text, 120, 30).unwrap();
+    assert!(text.contains("Error"));
+}
+
+/// Test per-tool latency SLOs and the summary chip
+#[test]
+fn test_app_latency_slos() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    storage.record_log_events(vec![
+        make_tool_event("Read", true, 50),
+        make_tool_event("Bash", true, 8000),
+        make_tool_event("Edit", true, 10),
+    ]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let mut app = App::new(storage);
+    app.refresh().unwrap();
+    assert_eq!(app.latency_slo_summary(), None);
+
+    app.latency_slos_ms = HashMap::from([
+        ("Read".to_string(), 100),
+        ("Bash".to_string(), 5000),
+        // Tools without calls in the window are not counted
+        ("Grep".to_string(), 100),
+    ]);
+    let read = app
+        .tool_metrics
+        .iter()
+        .find(|t| t.tool_name == "Read")
+        .unwrap();
+    assert_eq!(app.meets_latency_slo(read), Some(true));
+    let edit = app
+        .tool_metrics
+        .iter()
+        .find(|t| t.tool_name == "Edit")
+        .unwrap();
+    assert_eq!(app.meets_latency_slo(edit), None);
+    assert_eq!(app.latency_slo_summary(), Some((1, 2)));
+
+    let text = snapshot::render(&app, SnapshotFormat::Text, 140, 40).unwrap();
+    assert!(text.contains("SLOs: 1/2"));
+}
+
+/// Test the cost toggle switches the metrics bar to the estimate from token usage
+#[test]
+fn test_ui_estimated_cost_toggle() {
+    let storage = StorageHandle::new_in_memory().unwrap();
+    storage.record_model_token_usage("input", 1_000_000, Some("claude-sonnet-4-5"), None);
+    storage.record_cost(0.5);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let mut app = App::new(storage);
+    app.refresh().unwrap();
+    assert_eq!(app.shown_cost(), 0.5);
+    let text = snapshot::render(&app, SnapshotFormat::Text, 160, 30).unwrap();
+    assert!(text.contains("Cost: $0.50"), "{}", text);
+
+    app.toggle_cost_estimate();
+    assert!((app.shown_cost() - 3.0).abs() < 1e-9);
+    let text = snapshot::render(&app, SnapshotFormat::Text, 160, 30).unwrap();
+    assert!(text.contains("Est. cost: $3.00"), "{}", text);
 }